@@ -0,0 +1,120 @@
+//! Allocations and heap use of the candidates of many regions.
+//!
+//! Matches every region against transcript-rich genes at exon level and keeps
+//! all associations, as a run writing Parquet or JSON does. Before timing, it
+//! prints the allocations made and the peak heap in use while matching.
+//!
+//! Sharing gene and transcript IDs and exon numbers as [`rgmatch::Name`]
+//! took the 640,000 candidates from 13.3 to 5.4 million allocations and the
+//! peak heap from 279 to 245 MB.
+//!
+//! Run with `cargo bench --bench candidate_memory`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rgmatch::matcher::{RegionMatcher, RegionMatches};
+use rgmatch::parser::parse_gtf_from_reader;
+use rgmatch::{Config, GtfData, Region, ReportLevel};
+
+/// Counts allocations and tracks the bytes in use.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        let in_use = IN_USE.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+        PEAK.fetch_max(in_use, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        IN_USE.fetch_sub(layout.size(), Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+const GENES: i64 = 5_000;
+const TRANSCRIPTS_PER_GENE: i64 = 4;
+const GENE_SPACING: i64 = 20_000;
+const REGIONS: i64 = 200_000;
+
+fn transcript_rich_gtf() -> GtfData {
+    let mut gtf = String::new();
+    for g in 0..GENES {
+        let start = 1 + g * GENE_SPACING;
+        for t in 0..TRANSCRIPTS_PER_GENE {
+            for e in 0..4 {
+                let exon_start = start + e * 3_000 + t * 100;
+                gtf.push_str(&format!(
+                    "chr1\tBENCH\texon\t{}\t{}\t.\t+\t.\tgene_id \"GENE{:06}\"; transcript_id \"GENE{:06}-T{}\";\n",
+                    exon_start,
+                    exon_start + 1_500,
+                    g,
+                    g,
+                    t
+                ));
+            }
+        }
+    }
+    parse_gtf_from_reader(gtf.as_bytes(), "gene_id", "transcript_id").unwrap()
+}
+
+fn regions() -> Vec<Region> {
+    let step = GENES * GENE_SPACING / REGIONS;
+    (0..REGIONS)
+        .map(|i| Region::new("chr1".to_string(), i * step + 1, i * step + 300, vec![]))
+        .collect()
+}
+
+fn match_all(gtf: &GtfData, regions: &[Region], config: &Config) -> Vec<RegionMatches> {
+    let mut matcher = RegionMatcher::new(gtf, config);
+    regions
+        .iter()
+        .map(|region| matcher.match_next(region))
+        .collect()
+}
+
+fn bench_candidate_memory(c: &mut Criterion) {
+    let gtf = transcript_rich_gtf();
+    let regions = regions();
+    let config = Config {
+        level: ReportLevel::Exon,
+        ..Config::default()
+    };
+
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let in_use_before = IN_USE.load(Ordering::Relaxed);
+    PEAK.store(in_use_before, Ordering::Relaxed);
+    let all = match_all(&gtf, &regions, &config);
+    let candidates: usize = all.iter().map(|m| m.candidates.len()).sum();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    let peak = PEAK.load(Ordering::Relaxed) - in_use_before;
+    drop(all);
+    println!(
+        "{} regions, {} candidates: {} allocations, peak heap {:.1} MB",
+        regions.len(),
+        candidates,
+        allocations,
+        peak as f64 / 1e6
+    );
+
+    let mut group = c.benchmark_group("candidate_memory");
+    group.sample_size(10);
+    group.bench_function("exon_level", |b| {
+        b.iter(|| black_box(match_all(&gtf, &regions, &config)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_candidate_memory);
+criterion_main!(benches);
@@ -0,0 +1,66 @@
+//! Gene lookup with and without the interval index.
+//!
+//! The fixture is the worst case for the lookback scan: one 2 Mb gene among
+//! 10,000 small ones, so every region looks back over the genes it spans.
+//!
+//! Run with `cargo bench --bench interval_index`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+use rgmatch::matcher::RegionMatcher;
+use rgmatch::parser::parse_gtf_from_reader;
+use rgmatch::{Config, GtfData, Region};
+
+const SMALL_GENES: i64 = 10_000;
+const GENE_SPACING: i64 = 1_000;
+const REGIONS: i64 = 2_000;
+
+fn pathological_gtf() -> GtfData {
+    let mut gtf = String::from(
+        "chr1\tBENCH\texon\t5000\t2005000\t.\t+\t.\tgene_id \"LONG\"; transcript_id \"LONG_T\";\n",
+    );
+    for i in 0..SMALL_GENES {
+        let start = 1 + i * GENE_SPACING;
+        gtf.push_str(&format!(
+            "chr1\tBENCH\texon\t{}\t{}\t.\t+\t.\tgene_id \"G{}\"; transcript_id \"T{}\";\n",
+            start,
+            start + 300,
+            i,
+            i
+        ));
+    }
+    parse_gtf_from_reader(gtf.as_bytes(), "gene_id", "transcript_id").unwrap()
+}
+
+fn regions() -> Vec<Region> {
+    let step = SMALL_GENES * GENE_SPACING / REGIONS;
+    (0..REGIONS)
+        .map(|i| Region::new("chr1".to_string(), i * step + 400, i * step + 600, vec![]))
+        .collect()
+}
+
+fn bench_gene_lookup(c: &mut Criterion) {
+    let gtf = pathological_gtf();
+    let regions = regions();
+    let mut group = c.benchmark_group("one_2mb_gene_among_10k");
+
+    for (name, use_interval_index) in [("lookback_scan", false), ("interval_index", true)] {
+        let config = Config {
+            use_interval_index,
+            ..Config::default()
+        };
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut matcher = RegionMatcher::new(&gtf, &config);
+                for region in &regions {
+                    black_box(matcher.match_next(region));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_gene_lookup);
+criterion_main!(benches);
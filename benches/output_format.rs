@@ -0,0 +1,91 @@
+//! Formatting of output rows.
+//!
+//! Compares a fresh string per row with a reused line buffer, and times the
+//! TSV writer on the candidates of many regions.
+//!
+//! Run with `cargo bench --bench output_format`.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::hint::black_box;
+
+use rgmatch::matcher::RegionMatches;
+use rgmatch::output::{format_output_line, format_output_line_into, write_associations};
+use rgmatch::types::Association;
+use rgmatch::{Area, Candidate, Config, Region, Strand};
+
+const REGIONS: usize = 20_000;
+const CANDIDATES_PER_REGION: usize = 5;
+
+fn associations() -> Vec<Association> {
+    (0..REGIONS)
+        .map(|i| {
+            let start = 1_000 + i as i64 * 500;
+            let region = Region::new(
+                "chr1".to_string(),
+                start,
+                start + 300,
+                vec![format!("peak{}", i), "812".to_string()],
+            );
+            let candidates = (0..CANDIDATES_PER_REGION)
+                .map(|c| {
+                    let mut candidate = Candidate::new(
+                        start + 100,
+                        start + 900,
+                        Strand::Positive,
+                        c as u32 + 1,
+                        Area::FirstExon,
+                        format!("ENST{:011}.{}", i, c),
+                        format!("ENSG{:011}", i),
+                        0,
+                        100.0 / 3.0,
+                        66.666,
+                        -150 + c as i64,
+                    );
+                    candidate.chrom = "chr1".to_string();
+                    candidate
+                })
+                .collect();
+            RegionMatches::new(candidates).into_association(region)
+        })
+        .collect()
+}
+
+fn bench_output_format(c: &mut Criterion) {
+    let associations = associations();
+    let rows = (REGIONS * CANDIDATES_PER_REGION) as u64;
+    let config = Config::default();
+
+    let mut group = c.benchmark_group("output_format");
+    group.throughput(Throughput::Elements(rows));
+    group.bench_function("format_output_line", |b| {
+        b.iter(|| {
+            for association in &associations {
+                for candidate in &association.candidates {
+                    black_box(format_output_line(&association.region, candidate));
+                }
+            }
+        })
+    });
+    group.bench_function("format_output_line_into", |b| {
+        let mut line = String::new();
+        b.iter(|| {
+            for association in &associations {
+                for candidate in &association.candidates {
+                    line.clear();
+                    format_output_line_into(&mut line, &association.region, candidate);
+                    black_box(&line);
+                }
+            }
+        })
+    });
+    group.bench_function("write_associations_tsv", |b| {
+        b.iter(|| {
+            let mut out = std::io::sink();
+            write_associations(&mut out, &associations, 2, &config).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_output_format);
+criterion_main!(benches);
@@ -0,0 +1,179 @@
+//! Each stage of an annotation run: parsing, matching, rules, report-level
+//! selection and writing.
+//!
+//! The GTF is the bundled `tests/data/subset_genome.gtf`; everything else is
+//! generated by `tests/support`. Matching runs on a dense layout of
+//! overlapping genes, a sparse one with most regions far from any gene, and
+//! one with a megabase gene spanning thousands of small ones, and on two
+//! genes of 363 exons.
+//!
+//! The fixtures take a while to build, so this benchmark needs the `bench`
+//! feature: `cargo bench --features bench --bench pipeline_stages`.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use std::hint::black_box;
+use std::path::Path;
+
+use rgmatch::matcher::overlap::{associate_regions, process_candidates_for_output};
+use rgmatch::matcher::rules::{apply_rules, select_transcript};
+use rgmatch::output::write_associations;
+use rgmatch::parser::{parse_bed_from_reader, parse_gtf, parse_gtf_from_reader};
+use rgmatch::source::AnnotationSource;
+use rgmatch::{Config, GtfData, ReportLevel};
+
+#[path = "../tests/support/mod.rs"]
+mod support;
+
+use support::{
+    bed_text, candidate_group, many_exon_gtf, megabase_gene_gtf, sorted_regions, tiled_gtf, Lcg,
+};
+
+const BED_REGIONS: usize = 200_000;
+const MATCH_REGIONS: usize = 50_000;
+const GENES: usize = 10_000;
+
+fn parse(gtf: &str) -> GtfData {
+    parse_gtf_from_reader(gtf.as_bytes(), "gene_id", "transcript_id").unwrap()
+}
+
+fn bench_parsing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+
+    let gtf_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/subset_genome.gtf");
+    group.bench_function("parse_gtf_subset", |b| {
+        b.iter(|| black_box(parse_gtf(&gtf_path, "gene_id", "transcript_id").unwrap()))
+    });
+
+    let bed = bed_text(&mut Lcg(0xbed), &["chr1", "chr2", "chr3"], BED_REGIONS);
+    group.throughput(Throughput::Elements(BED_REGIONS as u64));
+    group.bench_function("parse_bed_200k", |b| {
+        b.iter(|| black_box(parse_bed_from_reader(bed.as_bytes()).unwrap()))
+    });
+    group.finish();
+}
+
+fn bench_matching(c: &mut Criterion) {
+    // Genes of 30 kb every 10 kb overlap three deep; genes of 3 kb every
+    // 100 kb leave most regions to the upstream and downstream areas
+    let layouts = [
+        (
+            "dense",
+            parse(&tiled_gtf("chr1", GENES, 10_000, 30_000)),
+            100_000_000,
+        ),
+        (
+            "sparse",
+            parse(&tiled_gtf("chr1", GENES, 100_000, 3_000)),
+            1_000_000_000,
+        ),
+        (
+            "megabase_gene",
+            parse(&megabase_gene_gtf("chr1", GENES, 1_000, 2)),
+            10_000_000,
+        ),
+    ];
+    let config = Config::default();
+
+    let mut group = c.benchmark_group("associate_regions");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(MATCH_REGIONS as u64));
+    for (name, gtf, span) in &layouts {
+        let regions = sorted_regions(&mut Lcg(0x5eed), "chr1", MATCH_REGIONS, *span);
+        let genes = gtf.genes("chr1");
+        group.bench_function(*name, |b| {
+            b.iter(|| black_box(associate_regions(&regions, genes, &config, 0)))
+        });
+    }
+    group.finish();
+}
+
+fn bench_many_exons(c: &mut Criterion) {
+    // Two genes of 363 exons, 10 Mb apart, with 8 transcripts each
+    let mut rng = Lcg(0x77a);
+    let gtf = parse(&many_exon_gtf(&mut rng, "chr1", 363, 8));
+    let regions = sorted_regions(&mut rng, "chr1", MATCH_REGIONS / 10, 12_000_000);
+    let genes = gtf.genes("chr1");
+    let config = Config::default();
+
+    let mut group = c.benchmark_group("associate_regions");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(regions.len() as u64));
+    group.bench_function("many_exons", |b| {
+        b.iter(|| black_box(associate_regions(&regions, genes, &config, 0)))
+    });
+    group.finish();
+}
+
+fn bench_rules(c: &mut Criterion) {
+    let rules = Config::default().rules;
+    let (candidates, by_transcript) = candidate_group(&mut Lcg(0x0123), 4_000, 200, 10);
+    let mut by_gene = ahash::AHashMap::<String, Vec<usize>>::new();
+    for (i, candidate) in candidates.iter().enumerate() {
+        by_gene
+            .entry(candidate.gene.to_string())
+            .or_default()
+            .push(i);
+    }
+
+    let mut group = c.benchmark_group("rules");
+    group.throughput(Throughput::Elements(candidates.len() as u64));
+    group.bench_function("apply_rules", |b| {
+        b.iter(|| black_box(apply_rules(&candidates, &by_transcript, 50.0, 90.0, &rules)))
+    });
+    group.bench_function("select_transcript", |b| {
+        b.iter(|| black_box(select_transcript(&candidates, &by_gene, &rules)))
+    });
+    group.finish();
+}
+
+fn bench_report_level(c: &mut Criterion) {
+    // One region with 500 candidates of 60 transcripts in 12 genes
+    let (candidates, _) = candidate_group(&mut Lcg(0x500), 500, 12, 5);
+
+    let mut group = c.benchmark_group("report_level");
+    group.throughput(Throughput::Elements(candidates.len() as u64));
+    for level in [ReportLevel::Transcript, ReportLevel::Gene] {
+        let config = Config {
+            level,
+            ..Config::default()
+        };
+        group.bench_function(level.as_str(), |b| {
+            b.iter_batched(
+                || candidates.clone(),
+                |candidates| black_box(process_candidates_for_output(candidates, &config)),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_writing(c: &mut Criterion) {
+    let gtf = parse(&tiled_gtf("chr1", GENES, 10_000, 30_000));
+    let regions = sorted_regions(&mut Lcg(0x5eed), "chr1", MATCH_REGIONS, 100_000_000);
+    let config = Config::default();
+    let associations = associate_regions(&regions, gtf.genes("chr1"), &config, 0);
+    let rows: usize = associations.iter().map(|a| a.candidates.len()).sum();
+
+    let mut group = c.benchmark_group("write");
+    group.sample_size(10);
+    group.throughput(Throughput::Elements(rows as u64));
+    group.bench_function("write_associations_tsv", |b| {
+        b.iter(|| {
+            write_associations(&mut std::io::sink(), &associations, 0, &config).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parsing,
+    bench_matching,
+    bench_many_exons,
+    bench_rules,
+    bench_report_level,
+    bench_writing
+);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+//! Matching one chromosome sequentially and in parallel chunks.
+//!
+//! Every region falls on a single chromosome, the skewed input that
+//! parallelism across chromosomes cannot speed up. The chunked matcher runs
+//! on thread pools of increasing size to show how it scales.
+//!
+//! The associations of 5 million regions need several GB of memory; set
+//! `RGMATCH_BENCH_REGIONS` to run on fewer regions.
+//!
+//! Run with `cargo bench --bench single_chromosome`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+use rgmatch::matcher::overlap::{associate_regions, associate_regions_par};
+use rgmatch::parser::parse_gtf_from_reader;
+use rgmatch::source::AnnotationSource;
+use rgmatch::{Config, GtfData, Region};
+
+const GENES: i64 = 20_000;
+const GENE_SPACING: i64 = 10_000;
+const DEFAULT_REGIONS: i64 = 5_000_000;
+const CHUNK_SIZE: usize = 16_384;
+
+fn two_exon_gtf() -> GtfData {
+    let mut gtf = String::new();
+    for i in 0..GENES {
+        let start = 1 + i * GENE_SPACING;
+        let strand = if i % 2 == 0 { '+' } else { '-' };
+        for (exon_start, exon_end) in [(start, start + 800), (start + 3000, start + 4000)] {
+            gtf.push_str(&format!(
+                "chr1\tBENCH\texon\t{}\t{}\t.\t{}\t.\tgene_id \"G{}\"; transcript_id \"T{}\";\n",
+                exon_start, exon_end, strand, i, i
+            ));
+        }
+    }
+    parse_gtf_from_reader(gtf.as_bytes(), "gene_id", "transcript_id").unwrap()
+}
+
+fn regions() -> Vec<Region> {
+    let count = std::env::var("RGMATCH_BENCH_REGIONS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_REGIONS);
+    let step = (GENES * GENE_SPACING / count).max(1);
+    (0..count)
+        .map(|i| Region::new("chr1".to_string(), i * step + 1, i * step + 200, vec![]))
+        .collect()
+}
+
+fn bench_single_chromosome(c: &mut Criterion) {
+    let gtf = two_exon_gtf();
+    let genes = gtf.genes("chr1");
+    let regions = regions();
+    let config = Config::default();
+
+    let mut group = c.benchmark_group("regions_on_one_chromosome");
+    group.sample_size(10);
+
+    group.bench_function("sequential", |b| {
+        b.iter(|| black_box(associate_regions(&regions, genes, &config, 0)))
+    });
+
+    let max_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let mut threads = 1;
+    while threads <= max_threads {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap();
+        group.bench_function(format!("chunked_{}_threads", threads), |b| {
+            b.iter(|| {
+                pool.install(|| {
+                    black_box(associate_regions_par(&regions, genes, &config, CHUNK_SIZE))
+                })
+            })
+        });
+        threads *= 2;
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_single_chromosome);
+criterion_main!(benches);
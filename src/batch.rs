@@ -0,0 +1,322 @@
+//! Sample manifests and combined summaries of batch runs.
+//!
+//! A manifest lists the samples of a batch run, one per line: the BED file,
+//! the output file and optionally a label, separated by tabs. The annotation
+//! is loaded once for the whole batch, and the outcome of every sample is
+//! collected in a [`BatchSummary`].
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs::File;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::output::summary::Summary;
+use crate::parser::util::create_buffered_reader;
+use crate::types::Area;
+
+/// First field of an optional manifest header line.
+const HEADER_FIELD: &str = "bed_path";
+
+/// One sample of a manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Name of the sample in the combined summary; the BED path as written
+    /// in the manifest when no label is given.
+    pub label: String,
+    /// Region BED file.
+    pub bed: PathBuf,
+    /// Output file of the associations.
+    pub output: PathBuf,
+}
+
+/// Read the samples of a manifest file.
+///
+/// See [`parse_manifest`] for the format.
+pub fn read_manifest(path: &Path) -> Result<Vec<Sample>> {
+    let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+    parse_manifest(create_buffered_reader(file, path)).map_err(|e| e.in_file(path))
+}
+
+/// Parse a manifest: `bed_path`, `output_path` and an optional `label` per
+/// line, tab-separated.
+///
+/// Empty lines, `#` comments and a header line starting with `bed_path` are
+/// skipped. Paths are used as written. Fails with [`Error::Manifest`] on a
+/// line without both paths, or when two samples share a label or an output.
+pub fn parse_manifest<R: BufRead>(reader: R) -> Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    let mut labels = HashSet::new();
+    let mut outputs = HashSet::new();
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line_number = i + 1;
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        if samples.is_empty() && fields[0] == HEADER_FIELD {
+            continue;
+        }
+        let error = |reason: String| Error::Manifest {
+            line: line_number,
+            reason,
+        };
+
+        let (bed, output, label) = match fields.as_slice() {
+            [bed, output] => (*bed, *output, ""),
+            [bed, output, label] => (*bed, *output, *label),
+            _ => {
+                return Err(error(format!(
+                    "expected 2 or 3 tab-separated fields, found {}",
+                    fields.len()
+                )))
+            }
+        };
+        if bed.is_empty() || output.is_empty() {
+            return Err(error("missing BED or output path".to_string()));
+        }
+        let label = if label.is_empty() { bed } else { label };
+        if !labels.insert(label.to_string()) {
+            return Err(error(format!("duplicate sample label '{}'", label)));
+        }
+        if !outputs.insert(output.to_string()) {
+            return Err(error(format!("duplicate output path '{}'", output)));
+        }
+
+        samples.push(Sample {
+            label: label.to_string(),
+            bed: PathBuf::from(bed),
+            output: PathBuf::from(output),
+        });
+    }
+    Ok(samples)
+}
+
+/// Outcome of one sample of a batch run.
+#[derive(Debug, Clone)]
+pub enum SampleStatus {
+    /// The sample was annotated, with its run summary.
+    Done(Box<Summary>),
+    /// Annotating the sample failed with this message.
+    Failed(String),
+    /// The sample was not run because an earlier sample failed.
+    Skipped,
+}
+
+impl SampleStatus {
+    /// Status as written in the combined summary.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SampleStatus::Done(_) => "ok",
+            SampleStatus::Failed(_) => "failed",
+            SampleStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// Outcomes of the samples of a batch run, in manifest order.
+#[derive(Debug, Clone, Default)]
+pub struct BatchSummary {
+    samples: Vec<(Sample, SampleStatus)>,
+}
+
+impl BatchSummary {
+    /// Create an empty batch summary.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the outcome of the next sample.
+    pub fn add(&mut self, sample: Sample, status: SampleStatus) {
+        self.samples.push((sample, status));
+    }
+
+    /// Samples and their outcomes, in the order they were added.
+    pub fn samples(&self) -> &[(Sample, SampleStatus)] {
+        &self.samples
+    }
+
+    /// Number of samples that failed.
+    pub fn num_failed(&self) -> usize {
+        self.samples
+            .iter()
+            .filter(|(_, status)| matches!(status, SampleStatus::Failed(_)))
+            .count()
+    }
+
+    /// Write one row per sample: its files, status, region and association
+    /// counts, then one association count column per area found in any
+    /// sample, and the error message of failed samples. Counts of samples
+    /// that were not annotated are NA.
+    pub fn write_tsv<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let areas: BTreeSet<Area> = self
+            .samples
+            .iter()
+            .filter_map(|(_, status)| match status {
+                SampleStatus::Done(summary) => Some(summary.areas.keys().copied()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+
+        write!(
+            writer,
+            "sample\tbed\toutput\tstatus\ttotal_regions\tassociated_regions\tunassigned_regions\ttotal_associations"
+        )?;
+        for area in &areas {
+            write!(writer, "\t{}", area.as_str())?;
+        }
+        writeln!(writer, "\terror")?;
+
+        for (sample, status) in &self.samples {
+            write!(
+                writer,
+                "{}\t{}\t{}\t{}",
+                sample.label,
+                sample.bed.display(),
+                sample.output.display(),
+                status.as_str()
+            )?;
+            match status {
+                SampleStatus::Done(summary) => {
+                    write!(
+                        writer,
+                        "\t{}\t{}\t{}\t{}",
+                        summary.total_regions,
+                        summary.associated_regions,
+                        summary.unassigned_regions,
+                        summary.total_associations()
+                    )?;
+                    for area in &areas {
+                        let count = summary.areas.get(area).map_or(0, |stats| stats.count);
+                        write!(writer, "\t{}", count)?;
+                    }
+                    writeln!(writer, "\tNA")?;
+                }
+                SampleStatus::Failed(_) | SampleStatus::Skipped => {
+                    for _ in 0..4 + areas.len() {
+                        write!(writer, "\tNA")?;
+                    }
+                    let message = match status {
+                        SampleStatus::Failed(message) => message.replace(['\t', '\n'], " "),
+                        _ => "NA".to_string(),
+                    };
+                    writeln!(writer, "\t{}", message)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Candidate, ReportLevel, Strand};
+
+    fn sample(label: &str) -> Sample {
+        Sample {
+            label: label.to_string(),
+            bed: PathBuf::from(format!("{}.bed", label)),
+            output: PathBuf::from(format!("{}.txt", label)),
+        }
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = "bed_path\toutput_path\tlabel\n\
+                        # treated samples\n\
+                        a.bed\ta.txt\tA\n\
+                        \n\
+                        b.bed\tb.txt\r\n";
+        let samples = parse_manifest(manifest.as_bytes()).unwrap();
+        assert_eq!(
+            samples,
+            vec![
+                Sample {
+                    label: "A".to_string(),
+                    bed: PathBuf::from("a.bed"),
+                    output: PathBuf::from("a.txt"),
+                },
+                Sample {
+                    label: "b.bed".to_string(),
+                    bed: PathBuf::from("b.bed"),
+                    output: PathBuf::from("b.txt"),
+                },
+            ]
+        );
+
+        for (manifest, line, reason) in [
+            (
+                "a.bed\n",
+                1,
+                "expected 2 or 3 tab-separated fields, found 1",
+            ),
+            ("a.bed\ta.txt\tA\tx\n", 1, "expected 2 or 3"),
+            ("a.bed\t\n", 1, "missing BED or output path"),
+            (
+                "a.bed\ta.txt\tA\nb.bed\tb.txt\tA\n",
+                2,
+                "duplicate sample label 'A'",
+            ),
+            (
+                "a.bed\tout.txt\nb.bed\tout.txt\n",
+                2,
+                "duplicate output path 'out.txt'",
+            ),
+        ] {
+            match parse_manifest(manifest.as_bytes()) {
+                Err(Error::Manifest {
+                    line: error_line,
+                    reason: error_reason,
+                }) => {
+                    assert_eq!(error_line, line, "{:?}", manifest);
+                    assert!(error_reason.starts_with(reason), "{}", error_reason);
+                }
+                other => panic!("expected a manifest error, got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let mut done = Summary::new(ReportLevel::Gene);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            100.0,
+            -50,
+        );
+        done.add_region(&[candidate]);
+        done.add_region(&[]);
+
+        let mut batch = BatchSummary::new();
+        batch.add(sample("a"), SampleStatus::Done(Box::new(done)));
+        batch.add(
+            sample("b"),
+            SampleStatus::Failed("Invalid BED line 3:\tbad".to_string()),
+        );
+        batch.add(sample("c"), SampleStatus::Skipped);
+        assert_eq!(batch.num_failed(), 1);
+
+        let mut tsv = Vec::new();
+        batch.write_tsv(&mut tsv).unwrap();
+        assert_eq!(
+            String::from_utf8(tsv).unwrap(),
+            "sample\tbed\toutput\tstatus\ttotal_regions\tassociated_regions\tunassigned_regions\ttotal_associations\tTSS\terror\n\
+             a\ta.bed\ta.txt\tok\t2\t1\t1\t1\t1\tNA\n\
+             b\tb.bed\tb.txt\tfailed\tNA\tNA\tNA\tNA\tNA\tInvalid BED line 3: bad\n\
+             c\tc.bed\tc.txt\tskipped\tNA\tNA\tNA\tNA\tNA\tNA\n"
+        );
+    }
+}
@@ -3,7 +3,24 @@
 //! This module contains the configuration structure and default values
 //! that control the region-to-gene matching behavior.
 
-use crate::types::{Area, ReportLevel};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+use std::path::Path;
+
+#[cfg(feature = "serde")]
+use crate::error::{Error, Result};
+use crate::output::columns::OutputColumn;
+use crate::output::provenance::Provenance;
+use crate::parser::MAX_COORDINATE;
+use crate::types::{
+    Area, AssignmentMode, ClosestBy, ContainedGeneBehavior, CoordinateConvention, DistanceAnchor,
+    ExtraColumn, GeneBodyDefinition, GeneLevelStyle, GeneTssMode, InvalidRegions, OutputFormat,
+    ReportLevel, ScoreCombination, SelectionMode, SpanMultipleGenes, Strandedness, TieBreak,
+    Transcript, TssDistanceSign, UnstrandedGenes,
+};
 
 /// Default rules priority order.
 pub const DEFAULT_RULES: [Area; 8] = [
@@ -17,8 +34,74 @@ pub const DEFAULT_RULES: [Area; 8] = [
     Area::Downstream,
 ];
 
+/// Default number of decimals for percentage columns.
+pub const DEFAULT_OUTPUT_PRECISION: usize = 2;
+
+/// `Config::distance` of a run without distance cutoff, set by a negative
+/// distance in kb (`-q -1`).
+pub const UNLIMITED_DISTANCE: i64 = i64::MAX;
+
+/// Lookback of an unlimited distance: twice the largest coordinate, so it
+/// reaches every gene while coordinate arithmetic cannot overflow.
+const MAX_LOOKBACK_DISTANCE: i64 = 2 * MAX_COORDINATE;
+
+/// Default text for missing values in TSV output.
+pub const DEFAULT_NA_STRING: &str = "NA";
+
+/// Default separator of merged transcript IDs and exon/intron numbers.
+pub const DEFAULT_MERGE_SEPARATOR: &str = ",";
+
+/// Default number of worker threads.
+pub const DEFAULT_THREADS: usize = 8;
+
+/// Default bin edges (bp) of the TSS distance histogram in the summary.
+pub const DEFAULT_TSS_BINS: [i64; 4] = [1000, 5000, 10000, 50000];
+
+/// Transcripts kept from the annotation; the others never reach the matcher.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct TranscriptFilters {
+    /// Keep only transcripts with at least one of these GTF `tag` values;
+    /// empty for no tag filter.
+    pub only_tagged: Vec<String>,
+    /// Keep only transcripts with a `transcript_support_level` of at most
+    /// this; transcripts without one are removed too.
+    pub max_tsl: Option<u8>,
+}
+
+impl TranscriptFilters {
+    /// Whether no transcript is filtered.
+    pub fn is_empty(&self) -> bool {
+        self.only_tagged.is_empty() && self.max_tsl.is_none()
+    }
+
+    /// Whether `transcript` passes the tag filter.
+    pub fn keeps_tags(&self, transcript: &Transcript) -> bool {
+        self.only_tagged.is_empty()
+            || transcript
+                .tags
+                .iter()
+                .any(|tag| self.only_tagged.contains(tag))
+    }
+
+    /// Whether `transcript` passes the support level filter.
+    pub fn keeps_support_level(&self, transcript: &Transcript) -> bool {
+        match self.max_tsl {
+            Some(max) => transcript.support_level.is_some_and(|level| level <= max),
+            None => true,
+        }
+    }
+}
+
 /// Configuration for the region-to-gene matching process.
+///
+/// With the `serde` feature, serializes to the TOML configuration file
+/// format (see `Config::from_toml`). Distances are in bp; enum values use
+/// their command-line names.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct Config {
     /// Priority rules for resolving ties.
     pub rules: Vec<Area>,
@@ -26,20 +109,166 @@ pub struct Config {
     pub perc_area: f64,
     /// Percentage of the region overlapped threshold.
     pub perc_region: f64,
+    /// Minimum overlap in bp. When above 0, the rules keep candidates
+    /// overlapping at least this many bases of the region instead of
+    /// `perc_region` percent of it.
+    pub min_overlap_bp: i64,
+    /// Choose the reported candidates by the rules or by score.
+    pub selection: SelectionMode,
+    /// Weight of each area in `SelectionMode::Score`; areas not listed
+    /// weigh 1.
+    pub area_weights: BTreeMap<Area, f64>,
+    /// How the score of a candidate combines its weight, overlap and
+    /// distance.
+    pub score_combination: ScoreCombination,
+    /// How the rules settle candidates tied on area and region percentage.
+    pub tie_break: TieBreak,
+    /// At transcript and gene level, drop the candidates of a transcript
+    /// when none reaches `perc_region` (or `min_overlap_bp`), instead of
+    /// reporting the best of them anyway.
+    pub thresholds_are_hard_filters: bool,
     /// TSS region distance in bp.
     pub tss: f64,
+    /// Extension of the TSS region into the first exon, in bp: its first
+    /// `tss_downstream` bases are reported as `TSS` instead of `1st_EXON`.
+    pub tss_downstream: f64,
     /// TTS region distance in bp.
     pub tts: f64,
     /// Promoter region distance in bp.
     pub promoter: f64,
-    /// Maximum distance to report associations in bp.
+    /// Whether a region exactly `tss`, `tss + promoter` or `tts` bp away
+    /// from the transcript still falls in the area inside that boundary
+    /// (`<=`), or in the next one out (`<`).
+    pub boundary_inclusive: bool,
+    /// Maximum distance to report associations in bp, or
+    /// [`UNLIMITED_DISTANCE`] for no cutoff.
     pub distance: i64,
+    /// Match the whole region or only its midpoint base.
+    pub assignment_mode: AssignmentMode,
+    /// Measure reported distances from the region midpoint or nearest edge.
+    pub distance_anchor: DistanceAnchor,
+    /// Sign convention of the TSS distance.
+    pub tss_distance_sign: TssDistanceSign,
+    /// Which overlaps are GENE_BODY candidates, and the length their
+    /// PercArea is computed over.
+    pub gene_body_definition: GeneBodyDefinition,
+    /// Maximum distance in bp of the candidates of each listed area, within
+    /// `distance`. Candidates further away are dropped before the rules pick
+    /// the reported ones; areas not listed are only limited by `distance`.
+    pub area_max_distance: BTreeMap<Area, i64>,
     /// Report level (exon, transcript, or gene).
     pub level: ReportLevel,
     /// GTF tag for gene ID.
     pub gene_id_tag: String,
     /// GTF tag for transcript ID.
     pub transcript_id_tag: String,
+    /// Merge the overlapping, abutting or duplicated exons of each
+    /// transcript when the annotation is loaded.
+    pub merge_overlapping_exons: bool,
+    /// Transcripts removed from the annotation when it is loaded.
+    pub transcript_filters: TranscriptFilters,
+    /// Accept negative BED coordinates instead of rejecting the file.
+    pub allow_negative_coordinates: bool,
+    /// What to do with BED regions whose start is greater than their end.
+    pub invalid_regions: InvalidRegions,
+    /// Fail on GTF transcript IDs found under more than one gene, instead of
+    /// keeping a separate transcript per gene.
+    pub strict_transcript_ids: bool,
+    /// Output file format.
+    pub output_format: OutputFormat,
+    /// Write only the best association per region.
+    pub best_only: bool,
+    /// Keep regions without associations in the output, with NA columns.
+    pub keep_unassigned: bool,
+    /// Optional columns added after the standard TSV columns.
+    pub extra_columns: Vec<ExtraColumn>,
+    /// Number of decimals for PercRegion and PercArea.
+    pub output_precision: usize,
+    /// Explicit TSV column layout; `None` uses the default layout.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub columns: Option<Vec<OutputColumn>>,
+    /// One row per region: the best association plus an `OtherGenes` column.
+    /// Regions without associations are kept with `NA` columns.
+    pub wide: bool,
+    /// Run description written as `#` lines before the header, if set.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub provenance: Option<Provenance>,
+    /// Text written for missing values in TSV output.
+    pub na_string: String,
+    /// Write the `-1` percentage sentinel of UPSTREAM/DOWNSTREAM hits as-is,
+    /// like the Python implementation, instead of a missing value.
+    pub legacy_sentinel: bool,
+    /// Separator of merged transcript IDs and exon/intron numbers.
+    pub merge_separator: String,
+    /// Merge tied transcripts in input order without removing repeated exon
+    /// numbers, like the Python implementation.
+    pub legacy_merge: bool,
+    /// Report tied transcripts merged into one gene-level row, or a single
+    /// representative transcript. Other levels are not affected.
+    pub gene_level_style: GeneLevelStyle,
+    /// Convention of reported start positions (Region, Midpoint and the
+    /// coordinate extra columns).
+    pub output_coordinate_convention: CoordinateConvention,
+    /// Increasing bin edges (bp) of the summary's |TSS distance| histogram.
+    pub tss_bins: Vec<i64>,
+    /// Find the genes near a region with the annotation's interval index
+    /// instead of scanning back by the longest gene. Results are the same.
+    pub use_interval_index: bool,
+    /// Strands of the genes a stranded region is matched to.
+    pub strandedness: Strandedness,
+    /// Leave unstranded regions unassigned unless `strandedness` is
+    /// `Ignore`, instead of matching them to genes on both strands.
+    pub strict_strandedness: bool,
+    /// Match regions without any candidate within `distance` to their
+    /// nearest gene, however far.
+    pub always_closest: bool,
+    /// Distance that ranks genes for `always_closest`.
+    pub closest_by: ClosestBy,
+    /// At gene level, also report the `n_closest` genes with the nearest TSS
+    /// to each region, ranked, whatever their distance; 0 for none.
+    pub n_closest: usize,
+    /// Report only the `n_closest` ranked genes, not the associations
+    /// selected by the rules.
+    pub closest_only: bool,
+    /// Report every candidate instead of those selected for the report
+    /// level, flagging the selected ones in a `Selected` column.
+    pub report_all_candidates: bool,
+    /// At gene level, report every gene whose span a region overlaps, with
+    /// the share of the region within it, instead of only those the rules
+    /// and thresholds keep.
+    pub span_multiple_genes: SpanMultipleGenes,
+    /// At gene level, the transcripts whose TSS, PROMOTER and UPSTREAM
+    /// candidates a gene reports.
+    pub gene_tss_mode: GeneTssMode,
+    /// Maximum distance in bp between the TSSs of two genes on opposite
+    /// strands for a region with TSS or PROMOTER candidates of both to be
+    /// at their bidirectional promoter. Used only when the output has a
+    /// `Bidirectional` column.
+    pub bidirectional_window: i64,
+    /// Give regions without any candidate an `INTERGENIC` pseudo-candidate
+    /// with the distance to their nearest gene, instead of no association.
+    pub report_intergenic: bool,
+    /// Report overlaps of the first intron as `1st_INTRON` instead of
+    /// `INTRON`. Takes effect only if `rules` list `1st_INTRON`; the builder
+    /// adds it after `INTRON` to the default rules.
+    pub distinguish_first_intron: bool,
+    /// Report genes within a region as GENE_CONTAINED candidates. Takes
+    /// effect only if `rules` list `GENE_CONTAINED`; the builder adds it
+    /// first to the default rules.
+    pub contained_gene_behavior: ContainedGeneBehavior,
+    /// How genes annotated without a strand ('.') are matched.
+    pub unstranded_genes: UnstrandedGenes,
+    /// Write the intron numbers of intron overlaps as `intron_N` in the
+    /// Exon/Intron column, instead of the bare number like exon overlaps.
+    pub label_introns: bool,
+    /// Report exon overlaps outside the CDS of coding transcripts as `UTR5`
+    /// or `UTR3` instead of `1st_EXON` or `GENE_BODY`. Each area takes effect
+    /// only if `rules` list it; the builder adds `UTR5` after `1st_EXON` and
+    /// `UTR3` after `GENE_BODY` to the default rules.
+    pub report_utr: bool,
+    /// Threads of the pool that runs the parallel work; 0 uses one per CPU.
+    /// Results do not depend on it.
+    pub threads: usize,
 }
 
 impl Default for Config {
@@ -48,13 +277,64 @@ impl Default for Config {
             rules: DEFAULT_RULES.to_vec(),
             perc_area: 90.0,
             perc_region: 50.0,
+            min_overlap_bp: 0,
+            selection: SelectionMode::Rules,
+            area_weights: BTreeMap::new(),
+            score_combination: ScoreCombination::Product,
+            tie_break: TieBreak::ReportAll,
+            thresholds_are_hard_filters: false,
             tss: 200.0,
+            tss_downstream: 0.0,
             tts: 0.0,
             promoter: 1300.0,
+            boundary_inclusive: true,
             distance: 10000, // 10kb default (stored in bp)
+            assignment_mode: AssignmentMode::Overlap,
+            distance_anchor: DistanceAnchor::Midpoint,
+            gene_body_definition: GeneBodyDefinition::ExonOnly,
+            tss_distance_sign: TssDistanceSign::UpstreamNegative,
+            area_max_distance: BTreeMap::new(),
             level: ReportLevel::Exon,
             gene_id_tag: "gene_id".to_string(),
             transcript_id_tag: "transcript_id".to_string(),
+            merge_overlapping_exons: true,
+            transcript_filters: TranscriptFilters::default(),
+            allow_negative_coordinates: false,
+            invalid_regions: InvalidRegions::default(),
+            strict_transcript_ids: false,
+            output_format: OutputFormat::Tsv,
+            best_only: false,
+            keep_unassigned: false,
+            extra_columns: Vec::new(),
+            output_precision: DEFAULT_OUTPUT_PRECISION,
+            columns: None,
+            wide: false,
+            provenance: None,
+            na_string: DEFAULT_NA_STRING.to_string(),
+            legacy_sentinel: false,
+            merge_separator: DEFAULT_MERGE_SEPARATOR.to_string(),
+            legacy_merge: false,
+            gene_level_style: GeneLevelStyle::Merge,
+            output_coordinate_convention: CoordinateConvention::Bed0,
+            tss_bins: DEFAULT_TSS_BINS.to_vec(),
+            use_interval_index: true,
+            strandedness: Strandedness::Ignore,
+            strict_strandedness: false,
+            always_closest: false,
+            closest_by: ClosestBy::Edge,
+            n_closest: 0,
+            closest_only: false,
+            report_all_candidates: false,
+            span_multiple_genes: SpanMultipleGenes::RulesOnly,
+            gene_tss_mode: GeneTssMode::AnyTranscript,
+            bidirectional_window: 1000,
+            report_intergenic: false,
+            distinguish_first_intron: false,
+            contained_gene_behavior: ContainedGeneBehavior::Split,
+            unstranded_genes: UnstrandedGenes::Skip,
+            label_introns: false,
+            report_utr: false,
+            threads: DEFAULT_THREADS,
         }
     }
 }
@@ -65,9 +345,35 @@ impl Config {
         Self::default()
     }
 
+    /// Parse a configuration from TOML. Missing keys keep their default
+    /// values; unknown keys are an error.
+    #[cfg(feature = "serde")]
+    pub fn from_toml(text: &str) -> Result<Self> {
+        toml::from_str(text).map_err(|source| Error::ConfigParse { path: None, source })
+    }
+
+    /// Read a TOML configuration file.
+    #[cfg(all(feature = "serde", not(target_arch = "wasm32")))]
+    pub fn from_toml_file(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+        Self::from_toml(&text).map_err(|e| e.in_file(path))
+    }
+
+    /// Write the configuration as TOML.
+    #[cfg(feature = "serde")]
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("every configuration value is representable in TOML")
+    }
+
     /// Parse and validate priority rules from a comma-separated string.
     ///
     /// Returns true if all 8 valid tags were provided, false otherwise.
+    /// `1st_INTRON` may also be listed, to rank first-intron overlaps (see
+    /// `distinguish_first_intron`), and `UTR5` and `UTR3` to rank UTR overlaps
+    /// (see `report_utr`), and `GENE_CONTAINED` to rank genes within the
+    /// region (see `contained_gene_behavior`). `INTERGENIC` may be listed too; it always
+    /// goes to the bottom, as an intergenic pseudo-candidate is the only
+    /// candidate of its region.
     pub fn parse_rules(&mut self, rules_str: &str) -> bool {
         let valid_tags = [
             "TSS",
@@ -75,9 +381,14 @@ impl Config {
             "PROMOTER",
             "TTS",
             "INTRON",
+            "1st_INTRON",
             "GENE_BODY",
+            "GENE_CONTAINED",
+            "UTR5",
+            "UTR3",
             "UPSTREAM",
             "DOWNSTREAM",
+            "INTERGENIC",
         ];
 
         let mut new_rules = Vec::new();
@@ -94,7 +405,12 @@ impl Config {
             }
         }
 
-        if new_rules.len() == 8 {
+        if let Some(pos) = new_rules.iter().position(|&a| a == Area::Intergenic) {
+            let intergenic = new_rules.remove(pos);
+            new_rules.push(intergenic);
+        }
+
+        if is_complete_rules(&new_rules) {
             self.rules = new_rules;
             true
         } else {
@@ -102,20 +418,870 @@ impl Config {
         }
     }
 
-    /// Set distance in kb (converts to bp internally).
+    /// Parse extra output columns from a comma-separated string.
+    ///
+    /// Returns false if any name is unknown. Repeated names are ignored.
+    pub fn parse_extra_columns(&mut self, columns_str: &str) -> bool {
+        let mut columns = Vec::new();
+        for name in columns_str.split(',') {
+            match name.parse::<ExtraColumn>() {
+                Ok(column) => {
+                    if !columns.contains(&column) {
+                        columns.push(column);
+                    }
+                }
+                Err(_) => return false,
+            }
+        }
+
+        self.extra_columns = columns;
+        true
+    }
+
+    /// Parse TSS histogram bin edges from a comma-separated list of bp values.
+    ///
+    /// Returns false unless the edges are positive and strictly increasing.
+    pub fn parse_tss_bins(&mut self, bins_str: &str) -> bool {
+        let edges: Option<Vec<i64>> = bins_str.split(',').map(|s| s.trim().parse().ok()).collect();
+        match edges {
+            Some(edges) if Self::valid_tss_bins(&edges) => {
+                self.tss_bins = edges;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `edges` are usable TSS histogram bin edges: non-empty,
+    /// positive and strictly increasing.
+    pub fn valid_tss_bins(edges: &[i64]) -> bool {
+        edges.first().is_some_and(|&e| e > 0) && edges.windows(2).all(|w| w[0] < w[1])
+    }
+
+    /// Set distance in kb (converts to bp internally). A negative distance
+    /// removes the cutoff, as in the Python rgmatch.
     pub fn set_distance_kb(&mut self, kb: i64) {
-        if kb >= 0 {
-            self.distance = kb * 1000;
+        self.distance = distance_from_kb(kb);
+    }
+
+    /// Number of worker threads, with 0 resolved to the number of CPUs.
+    pub fn num_threads(&self) -> usize {
+        if self.threads == 0 {
+            num_cpus::get()
+        } else {
+            self.threads
         }
     }
 
+    /// Whether associations are reported at any distance.
+    pub fn has_unlimited_distance(&self) -> bool {
+        self.distance == UNLIMITED_DISTANCE
+    }
+
+    /// List every parameter as a `(name, value)` pair.
+    ///
+    /// Values use the same syntax as the command-line options, so the rules
+    /// string can be passed back to `parse_rules`.
+    pub fn parameters(&self) -> Vec<(&'static str, String)> {
+        let join = |names: Vec<&str>| names.join(",");
+        vec![
+            (
+                "distance",
+                if self.has_unlimited_distance() {
+                    "unlimited".to_string()
+                } else {
+                    self.distance.to_string()
+                },
+            ),
+            ("assignment_mode", self.assignment_mode.as_str().to_string()),
+            ("distance_anchor", self.distance_anchor.as_str().to_string()),
+            (
+                "gene_body_definition",
+                self.gene_body_definition.as_str().to_string(),
+            ),
+            (
+                "tss_distance_sign",
+                self.tss_distance_sign.as_str().to_string(),
+            ),
+            (
+                "area_max_distance",
+                if self.area_max_distance.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.area_max_distance
+                        .iter()
+                        .map(|(area, bp)| format!("{}={}", area, bp))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                },
+            ),
+            ("tss", self.tss.to_string()),
+            ("tss_downstream", self.tss_downstream.to_string()),
+            ("tts", self.tts.to_string()),
+            ("promoter", self.promoter.to_string()),
+            ("boundary_inclusive", self.boundary_inclusive.to_string()),
+            ("perc_area", self.perc_area.to_string()),
+            ("perc_region", self.perc_region.to_string()),
+            ("min_overlap_bp", self.min_overlap_bp.to_string()),
+            ("selection", self.selection.as_str().to_string()),
+            (
+                "area_weights",
+                if self.area_weights.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.area_weights
+                        .iter()
+                        .map(|(area, weight)| format!("{}={}", area, weight))
+                        .collect::<Vec<_>>()
+                        .join(",")
+                },
+            ),
+            (
+                "score_combination",
+                self.score_combination.as_str().to_string(),
+            ),
+            ("tie_break", self.tie_break.as_str().to_string()),
+            (
+                "thresholds_are_hard_filters",
+                self.thresholds_are_hard_filters.to_string(),
+            ),
+            (
+                "rules",
+                join(self.rules.iter().map(|a| a.as_str()).collect()),
+            ),
+            ("report_level", self.level.as_str().to_string()),
+            ("gene_id_tag", self.gene_id_tag.clone()),
+            ("transcript_id_tag", self.transcript_id_tag.clone()),
+            (
+                "merge_overlapping_exons",
+                self.merge_overlapping_exons.to_string(),
+            ),
+            (
+                "allow_negative_coordinates",
+                self.allow_negative_coordinates.to_string(),
+            ),
+            ("invalid_regions", self.invalid_regions.as_str().to_string()),
+            (
+                "strict_transcript_ids",
+                self.strict_transcript_ids.to_string(),
+            ),
+            (
+                "only_tagged",
+                if self.transcript_filters.only_tagged.is_empty() {
+                    "none".to_string()
+                } else {
+                    self.transcript_filters.only_tagged.join(",")
+                },
+            ),
+            (
+                "max_tsl",
+                self.transcript_filters
+                    .max_tsl
+                    .map_or_else(|| "none".to_string(), |max| max.to_string()),
+            ),
+            ("strandedness", self.strandedness.as_str().to_string()),
+            ("strict_strandedness", self.strict_strandedness.to_string()),
+            ("always_closest", self.always_closest.to_string()),
+            ("closest_by", self.closest_by.as_str().to_string()),
+            ("n_closest", self.n_closest.to_string()),
+            ("closest_only", self.closest_only.to_string()),
+            (
+                "report_all_candidates",
+                self.report_all_candidates.to_string(),
+            ),
+            (
+                "span_multiple_genes",
+                self.span_multiple_genes.as_str().to_string(),
+            ),
+            ("gene_tss_mode", self.gene_tss_mode.as_str().to_string()),
+            (
+                "bidirectional_window",
+                self.bidirectional_window.to_string(),
+            ),
+            ("report_intergenic", self.report_intergenic.to_string()),
+            (
+                "distinguish_first_intron",
+                self.distinguish_first_intron.to_string(),
+            ),
+            (
+                "contained_gene_behavior",
+                self.contained_gene_behavior.as_str().to_string(),
+            ),
+            (
+                "unstranded_genes",
+                self.unstranded_genes.as_str().to_string(),
+            ),
+            ("label_introns", self.label_introns.to_string()),
+            ("report_utr", self.report_utr.to_string()),
+            ("output_format", self.output_format.as_str().to_string()),
+            ("best_only", self.best_only.to_string()),
+            ("keep_unassigned", self.keep_unassigned.to_string()),
+            ("wide", self.wide.to_string()),
+            (
+                "extra_columns",
+                if self.extra_columns.is_empty() {
+                    "none".to_string()
+                } else {
+                    join(self.extra_columns.iter().map(|c| c.as_str()).collect())
+                },
+            ),
+            ("output_precision", self.output_precision.to_string()),
+            ("na_string", self.na_string.clone()),
+            ("legacy_sentinel", self.legacy_sentinel.to_string()),
+            ("merge_separator", self.merge_separator.clone()),
+            ("legacy_merge", self.legacy_merge.to_string()),
+            (
+                "gene_level_style",
+                self.gene_level_style.as_str().to_string(),
+            ),
+            (
+                "report_coords",
+                self.output_coordinate_convention.as_str().to_string(),
+            ),
+            (
+                "tss_bins",
+                self.tss_bins
+                    .iter()
+                    .map(|e| e.to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "columns",
+                self.columns.as_ref().map_or_else(
+                    || "default".to_string(),
+                    |columns| join(columns.iter().map(|c| c.name()).collect()),
+                ),
+            ),
+        ]
+    }
+
+    /// Whether first-intron overlaps are reported as `1st_INTRON`: the
+    /// option is set and the rules rank the area. Otherwise they stay
+    /// `INTRON`, so rule strings without the tag keep their results.
+    pub fn reports_first_intron(&self) -> bool {
+        self.distinguish_first_intron && self.rules.contains(&Area::FirstIntron)
+    }
+
+    /// Whether every gene a region overlaps is reported
+    /// (`SpanMultipleGenes::ReportAllOverlapped`), which applies at gene
+    /// level only.
+    pub fn reports_all_overlapped_genes(&self) -> bool {
+        self.span_multiple_genes == SpanMultipleGenes::ReportAllOverlapped
+            && self.level == ReportLevel::Gene
+    }
+
+    /// Whether regions at bidirectional promoters are flagged: the output
+    /// has a `Bidirectional` column. Both genes of the pair are then
+    /// reported, even if one is farther than the nearest upstream gene.
+    pub fn flags_bidirectional(&self) -> bool {
+        match &self.columns {
+            Some(columns) => columns.contains(&OutputColumn::Bidirectional),
+            None => self.extra_columns.contains(&ExtraColumn::Bidirectional),
+        }
+    }
+
+    /// Whether exon, gene body and intron candidates get their distance to
+    /// the nearest exon-intron junction: the output has a
+    /// `JunctionDistance` column.
+    pub fn reports_junction_distance(&self) -> bool {
+        match &self.columns {
+            Some(columns) => columns.contains(&OutputColumn::JunctionDistance),
+            None => self.extra_columns.contains(&ExtraColumn::JunctionDistance),
+        }
+    }
+
+    /// Whether genes within a region are reported as `GENE_CONTAINED`: the
+    /// option asks for it and the rules rank the area. Otherwise they are
+    /// split like any other overlap.
+    pub fn consolidates_contained_genes(&self) -> bool {
+        self.contained_gene_behavior == ContainedGeneBehavior::Consolidate
+            && self.rules.contains(&Area::GeneContained)
+    }
+
+    /// Whether exon overlaps outside the CDS are reported as `area` (`UTR5`
+    /// or `UTR3`): the option is set and the rules rank the area. Otherwise
+    /// they keep their exon area.
+    pub fn reports_utr(&self, area: Area) -> bool {
+        self.report_utr && self.rules.contains(&area)
+    }
+
     /// Get the maximum distance to consider for lookback
+    ///
+    /// With an unlimited distance, it spans any chromosome without
+    /// overflowing when added to or subtracted from a coordinate.
     pub fn max_lookback_distance(&self) -> i64 {
         let max_float = self.tss.max(self.tts).max(self.promoter);
-        self.distance.max(max_float as i64)
+        self.distance
+            .max(max_float as i64)
+            .min(MAX_LOOKBACK_DISTANCE)
+    }
+}
+
+/// Distance in bp of `kb`, or [`UNLIMITED_DISTANCE`] if negative.
+fn distance_from_kb(kb: i64) -> i64 {
+    if kb < 0 {
+        UNLIMITED_DISTANCE
+    } else {
+        kb.saturating_mul(1000)
     }
 }
 
+/// A constraint violated by the values given to a [`ConfigBuilder`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigViolation {
+    /// Maximum distance (bp) below 0.
+    NegativeDistance(i64),
+    /// Maximum distance (bp) of an area below 0.
+    NegativeAreaDistance(Area, i64),
+    /// TSS region size (bp) below 0.
+    NegativeTss(f64),
+    /// TSS extension into the first exon (bp) below 0.
+    NegativeTssDownstream(f64),
+    /// TTS region size (bp) below 0.
+    NegativeTts(f64),
+    /// Minimum overlap (bp) below 0.
+    NegativeMinOverlap(i64),
+    /// Bidirectional promoter window (bp) below 0.
+    NegativeBidirectionalWindow(i64),
+    /// Area weight below 0 or not a number.
+    NegativeAreaWeight(Area, f64),
+    /// Promoter region size (bp) below 0.
+    NegativePromoter(f64),
+    /// Area percentage threshold outside 0-100.
+    PercAreaOutOfRange(f64),
+    /// Region percentage threshold outside 0-100.
+    PercRegionOutOfRange(f64),
+    /// Rules that are not the 8 areas, each listed once.
+    InvalidRules(String),
+    /// Empty GTF gene ID tag.
+    EmptyGeneIdTag,
+    /// Empty GTF transcript ID tag.
+    EmptyTranscriptIdTag,
+    /// Maximum transcript support level outside 1-5.
+    MaxTslOutOfRange(u8),
+}
+
+impl fmt::Display for ConfigViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigViolation::NegativeDistance(bp) => {
+                write!(f, "The distance cannot be lower than 0 (got {} bps).", bp)
+            }
+            ConfigViolation::NegativeAreaDistance(area, bp) => write!(
+                f,
+                "The {} distance cannot be lower than 0 (got {} bps).",
+                area, bp
+            ),
+            ConfigViolation::NegativeTss(bp) => {
+                write!(f, "The TSS distance cannot be lower than 0 bps (got {}).", bp)
+            }
+            ConfigViolation::NegativeTssDownstream(bp) => write!(
+                f,
+                "The TSS downstream extension cannot be lower than 0 bps (got {}).",
+                bp
+            ),
+            ConfigViolation::NegativeTts(bp) => {
+                write!(f, "The TTS distance cannot be lower than 0 bps (got {}).", bp)
+            }
+            ConfigViolation::NegativeMinOverlap(bp) => write!(
+                f,
+                "The minimum overlap cannot be lower than 0 bps (got {}).",
+                bp
+            ),
+            ConfigViolation::NegativeBidirectionalWindow(bp) => write!(
+                f,
+                "The bidirectional promoter window cannot be lower than 0 bps (got {}).",
+                bp
+            ),
+            ConfigViolation::NegativeAreaWeight(area, weight) => write!(
+                f,
+                "The {} weight cannot be lower than 0 (got {}).",
+                area, weight
+            ),
+            ConfigViolation::NegativePromoter(bp) => write!(
+                f,
+                "The promoter distance cannot be lower than 0 bps (got {}).",
+                bp
+            ),
+            ConfigViolation::PercAreaOutOfRange(perc) => write!(
+                f,
+                "The percentage of area defined was wrong (got {}). It should range between 0 and 100.",
+                perc
+            ),
+            ConfigViolation::PercRegionOutOfRange(perc) => write!(
+                f,
+                "The percentage of region defined was wrong (got {}). It should range between 0 and 100.",
+                perc
+            ),
+            ConfigViolation::InvalidRules(rules) => write!(
+                f,
+                "Rules not properly passed (got '{}'). All 8 areas must be listed once.",
+                rules
+            ),
+            ConfigViolation::EmptyGeneIdTag => write!(f, "The gene ID tag cannot be empty."),
+            ConfigViolation::EmptyTranscriptIdTag => {
+                write!(f, "The transcript ID tag cannot be empty.")
+            }
+            ConfigViolation::MaxTslOutOfRange(max) => write!(
+                f,
+                "The maximum transcript support level must be between 1 and 5 (got {}).",
+                max
+            ),
+        }
+    }
+}
+
+/// Error returned by [`ConfigBuilder::build`], listing every violated constraint.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub violations: Vec<ConfigViolation>,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self.violations.iter().map(|v| v.to_string()).collect();
+        write!(f, "{}", messages.join("\n"))
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Builder for the matching parameters of a [`Config`], validated together
+/// by [`build`](ConfigBuilder::build).
+///
+/// Parameters that are not set keep the values of the starting
+/// configuration. Output options are plain fields of the built `Config`.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigBuilder {
+    config: Config,
+    /// Rules string rejected by `rules_str`, if any.
+    invalid_rules: Option<String>,
+}
+
+impl ConfigBuilder {
+    /// Create a builder starting from the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder starting from `config`, e.g. one read from a file.
+    pub fn from_config(config: Config) -> Self {
+        ConfigBuilder {
+            config,
+            invalid_rules: None,
+        }
+    }
+
+    /// Maximum distance (kb) to report upstream/downstream associations;
+    /// negative for no cutoff.
+    pub fn distance_kb(mut self, kb: i64) -> Self {
+        self.config.distance = distance_from_kb(kb);
+        self
+    }
+
+    /// Match the whole region or only its midpoint base.
+    pub fn assignment_mode(mut self, mode: AssignmentMode) -> Self {
+        self.config.assignment_mode = mode;
+        self
+    }
+
+    /// Measure reported distances from the region midpoint or nearest edge.
+    pub fn distance_anchor(mut self, anchor: DistanceAnchor) -> Self {
+        self.config.distance_anchor = anchor;
+        self
+    }
+
+    /// Which overlaps are GENE_BODY candidates, and the length their
+    /// PercArea is computed over.
+    pub fn gene_body_definition(mut self, definition: GeneBodyDefinition) -> Self {
+        self.config.gene_body_definition = definition;
+        self
+    }
+
+    /// Sign convention of the TSS distance.
+    pub fn tss_distance_sign(mut self, sign: TssDistanceSign) -> Self {
+        self.config.tss_distance_sign = sign;
+        self
+    }
+
+    /// Maximum distance in bp of the candidates of `area`.
+    pub fn area_max_distance(mut self, area: Area, bp: i64) -> Self {
+        self.config.area_max_distance.insert(area, bp);
+        self
+    }
+
+    /// TSS region size in bp.
+    pub fn tss_bp(mut self, bp: i64) -> Self {
+        self.config.tss = bp as f64;
+        self
+    }
+
+    /// Extension of the TSS region into the first exon, in bp.
+    pub fn tss_downstream_bp(mut self, bp: i64) -> Self {
+        self.config.tss_downstream = bp as f64;
+        self
+    }
+
+    /// TTS region size in bp.
+    pub fn tts_bp(mut self, bp: i64) -> Self {
+        self.config.tts = bp as f64;
+        self
+    }
+
+    /// Promoter region size in bp.
+    pub fn promoter_bp(mut self, bp: i64) -> Self {
+        self.config.promoter = bp as f64;
+        self
+    }
+
+    /// Whether the base exactly on an area boundary belongs to the inner area.
+    pub fn boundary_inclusive(mut self, inclusive: bool) -> Self {
+        self.config.boundary_inclusive = inclusive;
+        self
+    }
+
+    /// Minimum percentage of the feature covered by the region.
+    pub fn perc_area(mut self, perc: f64) -> Self {
+        self.config.perc_area = perc;
+        self
+    }
+
+    /// Minimum percentage of the region covered by the feature.
+    pub fn perc_region(mut self, perc: f64) -> Self {
+        self.config.perc_region = perc;
+        self
+    }
+
+    /// Minimum overlap of the region with the feature in bp, replacing
+    /// `perc_region` in the rules. 0 disables it.
+    pub fn min_overlap_bp(mut self, bp: i64) -> Self {
+        self.config.min_overlap_bp = bp;
+        self
+    }
+
+    /// Choose the reported candidates by the rules or by score.
+    pub fn selection(mut self, selection: SelectionMode) -> Self {
+        self.config.selection = selection;
+        self
+    }
+
+    /// Weight of `area` in score-based selection.
+    pub fn area_weight(mut self, area: Area, weight: f64) -> Self {
+        self.config.area_weights.insert(area, weight);
+        self
+    }
+
+    /// How candidate scores are combined.
+    pub fn score_combination(mut self, combination: ScoreCombination) -> Self {
+        self.config.score_combination = combination;
+        self
+    }
+
+    /// How the rules settle tied candidates.
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Self {
+        self.config.tie_break = tie_break;
+        self
+    }
+
+    /// Drop transcripts whose candidates all miss the region threshold.
+    pub fn thresholds_are_hard_filters(mut self, hard: bool) -> Self {
+        self.config.thresholds_are_hard_filters = hard;
+        self
+    }
+
+    /// Priority order of all 8 areas.
+    pub fn rules(mut self, rules: &[Area]) -> Self {
+        self.config.rules = rules.to_vec();
+        self.invalid_rules = None;
+        self
+    }
+
+    /// Priority order from a comma-separated string, as accepted by
+    /// [`Config::parse_rules`].
+    pub fn rules_str(mut self, rules: &str) -> Self {
+        self.invalid_rules = if self.config.parse_rules(rules) {
+            None
+        } else {
+            Some(rules.to_string())
+        };
+        self
+    }
+
+    /// Report level.
+    pub fn level(mut self, level: ReportLevel) -> Self {
+        self.config.level = level;
+        self
+    }
+
+    /// GTF attribute holding the gene ID.
+    pub fn gene_id_tag(mut self, tag: &str) -> Self {
+        self.config.gene_id_tag = tag.to_string();
+        self
+    }
+
+    /// GTF attribute holding the transcript ID.
+    pub fn transcript_id_tag(mut self, tag: &str) -> Self {
+        self.config.transcript_id_tag = tag.to_string();
+        self
+    }
+
+    /// Keep only transcripts with at least one of `tags`.
+    pub fn only_tagged(mut self, tags: &[&str]) -> Self {
+        self.config.transcript_filters.only_tagged = tags.iter().map(|t| t.to_string()).collect();
+        self
+    }
+
+    /// Keep only transcripts with a support level of at most `max`.
+    pub fn max_tsl(mut self, max: u8) -> Self {
+        self.config.transcript_filters.max_tsl = Some(max);
+        self
+    }
+
+    /// Merge overlapping exons within a transcript (on by default).
+    pub fn merge_overlapping_exons(mut self, merge: bool) -> Self {
+        self.config.merge_overlapping_exons = merge;
+        self
+    }
+
+    /// Accept negative BED coordinates.
+    pub fn allow_negative_coordinates(mut self, allow: bool) -> Self {
+        self.config.allow_negative_coordinates = allow;
+        self
+    }
+
+    /// What to do with BED regions whose start is greater than their end.
+    pub fn invalid_regions(mut self, policy: InvalidRegions) -> Self {
+        self.config.invalid_regions = policy;
+        self
+    }
+
+    /// Fail on GTF transcript IDs found under more than one gene.
+    pub fn strict_transcript_ids(mut self, strict: bool) -> Self {
+        self.config.strict_transcript_ids = strict;
+        self
+    }
+
+    /// Strands of the genes a stranded region is matched to.
+    pub fn strandedness(mut self, strandedness: Strandedness) -> Self {
+        self.config.strandedness = strandedness;
+        self
+    }
+
+    /// Leave unstranded regions unassigned under `Same` or `Opposite`.
+    pub fn strict_strandedness(mut self, strict: bool) -> Self {
+        self.config.strict_strandedness = strict;
+        self
+    }
+
+    /// Match regions without candidates to their nearest gene.
+    pub fn always_closest(mut self, always_closest: bool) -> Self {
+        self.config.always_closest = always_closest;
+        self
+    }
+
+    /// Distance that ranks genes for `always_closest`.
+    pub fn closest_by(mut self, closest_by: ClosestBy) -> Self {
+        self.config.closest_by = closest_by;
+        self
+    }
+
+    /// Number of nearest genes ranked per region at gene level.
+    pub fn n_closest(mut self, n_closest: usize) -> Self {
+        self.config.n_closest = n_closest;
+        self
+    }
+
+    /// Report only the ranked nearest genes.
+    pub fn closest_only(mut self, closest_only: bool) -> Self {
+        self.config.closest_only = closest_only;
+        self
+    }
+
+    /// Report every candidate, flagging those selected for the report level.
+    pub fn report_all_candidates(mut self, report_all_candidates: bool) -> Self {
+        self.config.report_all_candidates = report_all_candidates;
+        self
+    }
+
+    /// Label regions without candidates as intergenic.
+    pub fn report_intergenic(mut self, report_intergenic: bool) -> Self {
+        self.config.report_intergenic = report_intergenic;
+        self
+    }
+
+    /// Set which genes are reported when a region spans several.
+    pub fn span_multiple_genes(mut self, policy: SpanMultipleGenes) -> Self {
+        self.config.span_multiple_genes = policy;
+        self
+    }
+
+    /// Set the transcripts whose TSS a gene reports at gene level.
+    pub fn gene_tss_mode(mut self, mode: GeneTssMode) -> Self {
+        self.config.gene_tss_mode = mode;
+        self
+    }
+
+    /// Maximum distance between the TSSs of a head-to-head gene pair for a
+    /// region to be at their bidirectional promoter.
+    pub fn bidirectional_window(mut self, bp: i64) -> Self {
+        self.config.bidirectional_window = bp;
+        self
+    }
+
+    /// Report first-intron overlaps as `1st_INTRON`. With the default rules,
+    /// the area ranks right after `INTRON`.
+    pub fn distinguish_first_intron(mut self, distinguish: bool) -> Self {
+        self.config.distinguish_first_intron = distinguish;
+        self
+    }
+
+    /// Report genes within a region as one `GENE_CONTAINED` candidate per
+    /// transcript. With the default rules, the area ranks first.
+    pub fn contained_gene_behavior(mut self, behavior: ContainedGeneBehavior) -> Self {
+        self.config.contained_gene_behavior = behavior;
+        self
+    }
+
+    /// Set how genes without a strand are matched.
+    pub fn unstranded_genes(mut self, policy: UnstrandedGenes) -> Self {
+        self.config.unstranded_genes = policy;
+        self
+    }
+
+    /// Write intron numbers as `intron_N`.
+    pub fn label_introns(mut self, label_introns: bool) -> Self {
+        self.config.label_introns = label_introns;
+        self
+    }
+
+    /// Report exon overlaps outside the CDS as `UTR5` or `UTR3`. With the
+    /// default rules, the areas rank after `1st_EXON` and `GENE_BODY`.
+    pub fn report_utr(mut self, report_utr: bool) -> Self {
+        self.config.report_utr = report_utr;
+        self
+    }
+
+    /// Set the number of worker threads; 0 uses one per CPU.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.config.threads = threads;
+        self
+    }
+
+    /// Validate the parameters and build the configuration.
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let mut config = self.config;
+        let mut violations = Vec::new();
+
+        if config.distance < 0 {
+            violations.push(ConfigViolation::NegativeDistance(config.distance));
+        }
+        for (&area, &bp) in &config.area_max_distance {
+            if bp < 0 {
+                violations.push(ConfigViolation::NegativeAreaDistance(area, bp));
+            }
+        }
+        if config.tss.is_nan() || config.tss < 0.0 {
+            violations.push(ConfigViolation::NegativeTss(config.tss));
+        }
+        if config.tss_downstream.is_nan() || config.tss_downstream < 0.0 {
+            violations.push(ConfigViolation::NegativeTssDownstream(
+                config.tss_downstream,
+            ));
+        }
+        if config.tts.is_nan() || config.tts < 0.0 {
+            violations.push(ConfigViolation::NegativeTts(config.tts));
+        }
+        if config.promoter.is_nan() || config.promoter < 0.0 {
+            violations.push(ConfigViolation::NegativePromoter(config.promoter));
+        }
+        if !(0.0..=100.0).contains(&config.perc_area) {
+            violations.push(ConfigViolation::PercAreaOutOfRange(config.perc_area));
+        }
+        if !(0.0..=100.0).contains(&config.perc_region) {
+            violations.push(ConfigViolation::PercRegionOutOfRange(config.perc_region));
+        }
+        if config.min_overlap_bp < 0 {
+            violations.push(ConfigViolation::NegativeMinOverlap(config.min_overlap_bp));
+        }
+        if config.bidirectional_window < 0 {
+            violations.push(ConfigViolation::NegativeBidirectionalWindow(
+                config.bidirectional_window,
+            ));
+        }
+        for (&area, &weight) in &config.area_weights {
+            if weight.is_nan() || weight < 0.0 {
+                violations.push(ConfigViolation::NegativeAreaWeight(area, weight));
+            }
+        }
+        match self.invalid_rules {
+            Some(rules) => violations.push(ConfigViolation::InvalidRules(rules)),
+            None if !is_complete_rules(&config.rules) => {
+                let names: Vec<&str> = config.rules.iter().map(|a| a.as_str()).collect();
+                violations.push(ConfigViolation::InvalidRules(names.join(",")));
+            }
+            None => {}
+        }
+        if config.gene_id_tag.is_empty() {
+            violations.push(ConfigViolation::EmptyGeneIdTag);
+        }
+        if config.transcript_id_tag.is_empty() {
+            violations.push(ConfigViolation::EmptyTranscriptIdTag);
+        }
+        if let Some(max) = config.transcript_filters.max_tsl {
+            if !(1..=5).contains(&max) {
+                violations.push(ConfigViolation::MaxTslOutOfRange(max));
+            }
+        }
+
+        let default_rules = config.rules == DEFAULT_RULES;
+        if default_rules && config.contained_gene_behavior == ContainedGeneBehavior::Consolidate {
+            config.rules.insert(0, Area::GeneContained);
+        }
+        let mut insert_after = |before: Area, area: Area| {
+            if let Some(pos) = config.rules.iter().position(|&a| a == before) {
+                config.rules.insert(pos + 1, area);
+            }
+        };
+        if default_rules && config.distinguish_first_intron {
+            insert_after(Area::Intron, Area::FirstIntron);
+        }
+        if default_rules && config.report_utr {
+            insert_after(Area::FirstExon, Area::Utr5);
+            insert_after(Area::GeneBody, Area::Utr3);
+        }
+
+        if violations.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { violations })
+        }
+    }
+}
+
+/// Whether `rules` lists each of the 8 areas exactly once, with at most one
+/// each of the optional `GENE_CONTAINED`, `1st_INTRON`, `UTR5` and `UTR3`, and optionally
+/// followed by `INTERGENIC`.
+fn is_complete_rules(rules: &[Area]) -> bool {
+    const OPTIONAL: [Area; 4] = [
+        Area::GeneContained,
+        Area::FirstIntron,
+        Area::Utr5,
+        Area::Utr3,
+    ];
+    let rules = rules.strip_suffix(&[Area::Intergenic]).unwrap_or(rules);
+    let areas: Vec<Area> = rules
+        .iter()
+        .copied()
+        .filter(|a| !OPTIONAL.contains(a))
+        .collect();
+    OPTIONAL
+        .iter()
+        .all(|optional| rules.iter().filter(|&a| a == optional).count() <= 1)
+        && areas.len() == DEFAULT_RULES.len()
+        && DEFAULT_RULES.iter().all(|area| areas.contains(area))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -127,12 +1293,114 @@ mod tests {
         assert_eq!(config.perc_area, 90.0);
         assert_eq!(config.perc_region, 50.0);
         assert_eq!(config.tss, 200.0);
+        assert_eq!(config.tss_downstream, 0.0);
+        assert_eq!(config.min_overlap_bp, 0);
+        assert_eq!(config.selection, SelectionMode::Rules);
+        assert!(config.area_weights.is_empty());
+        assert_eq!(config.score_combination, ScoreCombination::Product);
+        assert_eq!(config.tie_break, TieBreak::ReportAll);
+        assert!(!config.thresholds_are_hard_filters);
         assert_eq!(config.tts, 0.0);
         assert_eq!(config.promoter, 1300.0);
+        assert!(config.boundary_inclusive);
         assert_eq!(config.distance, 10000);
+        assert_eq!(config.assignment_mode, AssignmentMode::Overlap);
+        assert_eq!(config.distance_anchor, DistanceAnchor::Midpoint);
+        assert_eq!(config.gene_body_definition, GeneBodyDefinition::ExonOnly);
+        assert_eq!(config.tss_distance_sign, TssDistanceSign::UpstreamNegative);
+        assert!(config.area_max_distance.is_empty());
         assert_eq!(config.level, ReportLevel::Exon);
         assert_eq!(config.gene_id_tag, "gene_id");
         assert_eq!(config.transcript_id_tag, "transcript_id");
+        assert!(config.merge_overlapping_exons);
+        assert!(config.transcript_filters.is_empty());
+        assert!(!config.allow_negative_coordinates);
+        assert_eq!(config.invalid_regions, InvalidRegions::Swap);
+        assert!(!config.strict_transcript_ids);
+        assert_eq!(config.output_format, OutputFormat::Tsv);
+        assert!(!config.best_only);
+        assert!(!config.keep_unassigned);
+        assert!(config.extra_columns.is_empty());
+        assert_eq!(config.output_precision, 2);
+        assert!(config.columns.is_none());
+        assert!(!config.wide);
+        assert!(config.provenance.is_none());
+        assert_eq!(config.na_string, "NA");
+        assert!(!config.legacy_sentinel);
+        assert_eq!(config.merge_separator, ",");
+        assert!(!config.legacy_merge);
+        assert_eq!(config.gene_level_style, GeneLevelStyle::Merge);
+        assert_eq!(
+            config.output_coordinate_convention,
+            CoordinateConvention::Bed0
+        );
+        assert_eq!(config.tss_bins, vec![1000, 5000, 10000, 50000]);
+        assert!(config.use_interval_index);
+        assert_eq!(config.strandedness, Strandedness::Ignore);
+        assert!(!config.strict_strandedness);
+        assert!(!config.always_closest);
+        assert_eq!(config.closest_by, ClosestBy::Edge);
+        assert_eq!(config.n_closest, 0);
+        assert!(!config.closest_only);
+        assert!(!config.report_all_candidates);
+        assert_eq!(config.span_multiple_genes, SpanMultipleGenes::RulesOnly);
+        assert_eq!(config.gene_tss_mode, GeneTssMode::AnyTranscript);
+        assert_eq!(config.bidirectional_window, 1000);
+        assert!(!config.report_intergenic);
+        assert_eq!(config.contained_gene_behavior, ContainedGeneBehavior::Split);
+        assert_eq!(config.unstranded_genes, UnstrandedGenes::Skip);
+        assert!(!config.label_introns);
+        assert!(!config.report_utr);
+    }
+
+    #[test]
+    fn test_report_utr_default_rules() {
+        let config = ConfigBuilder::new()
+            .distinguish_first_intron(true)
+            .report_utr(true)
+            .build()
+            .unwrap();
+        let names: Vec<&str> = config.rules.iter().map(|a| a.as_str()).collect();
+        assert_eq!(
+            names.join(","),
+            "TSS,1st_EXON,UTR5,PROMOTER,TTS,INTRON,1st_INTRON,GENE_BODY,UTR3,UPSTREAM,DOWNSTREAM"
+        );
+        assert!(config.reports_utr(Area::Utr5));
+
+        // Rule strings without the tags keep the exon areas
+        let mut config = Config::new();
+        assert!(
+            config.parse_rules("TSS,PROMOTER,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM")
+        );
+        config.report_utr = true;
+        assert!(!config.reports_utr(Area::Utr3));
+        assert!(config
+            .parse_rules("UTR3,TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"));
+        assert!(config.reports_utr(Area::Utr3));
+    }
+
+    #[test]
+    fn test_contained_gene_rules() {
+        let config = ConfigBuilder::new()
+            .contained_gene_behavior(ContainedGeneBehavior::Consolidate)
+            .build()
+            .unwrap();
+        assert_eq!(config.rules[0], Area::GeneContained);
+        assert!(config.consolidates_contained_genes());
+
+        // Rule strings without the tag keep splitting contained genes
+        let config = ConfigBuilder::new()
+            .rules_str("PROMOTER,TSS,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM")
+            .contained_gene_behavior(ContainedGeneBehavior::Consolidate)
+            .build()
+            .unwrap();
+        assert!(!config.consolidates_contained_genes());
+
+        let mut config = Config::new();
+        assert!(config.parse_rules(
+            "TSS,1st_EXON,PROMOTER,GENE_CONTAINED,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"
+        ));
+        assert_eq!(config.rules[3], Area::GeneContained);
     }
 
     #[test]
@@ -200,13 +1468,246 @@ mod tests {
         assert!(!result); // Spaces make tags invalid
     }
 
+    #[test]
+    fn test_parse_extra_columns() {
+        let mut config = Config::new();
+        assert!(config.parse_extra_columns("coords,coords"));
+        assert_eq!(config.extra_columns, vec![ExtraColumn::Coords]);
+
+        assert!(!config.parse_extra_columns("coords,bogus"));
+        assert_eq!(config.extra_columns, vec![ExtraColumn::Coords]);
+    }
+
+    #[test]
+    fn test_parse_tss_bins() {
+        let mut config = Config::new();
+        assert!(config.parse_tss_bins("500, 2000"));
+        assert_eq!(config.tss_bins, vec![500, 2000]);
+
+        for invalid in ["", "0,100", "100,100", "200,100", "1kb"] {
+            assert!(!config.parse_tss_bins(invalid), "{}", invalid);
+        }
+        assert_eq!(config.tss_bins, vec![500, 2000]);
+    }
+
+    #[test]
+    fn test_builder_defaults_and_setters() {
+        let config = ConfigBuilder::new().build().unwrap();
+        assert_eq!(config.distance, 10000);
+        assert_eq!(config.rules, DEFAULT_RULES.to_vec());
+
+        let config = ConfigBuilder::new()
+            .distance_kb(20)
+            .tss_bp(100)
+            .tts_bp(50)
+            .promoter_bp(2000)
+            .perc_area(0.0)
+            .perc_region(100.0)
+            .rules_str("UPSTREAM,DOWNSTREAM,TTS,TSS,PROMOTER,INTRON,GENE_BODY,1st_EXON")
+            .level(ReportLevel::Gene)
+            .gene_id_tag("gene_name")
+            .transcript_id_tag("transcript_name")
+            .build()
+            .unwrap();
+        assert_eq!(config.distance, 20000);
+        assert_eq!(config.tss, 100.0);
+        assert_eq!(config.tts, 50.0);
+        assert_eq!(config.promoter, 2000.0);
+        assert_eq!(config.perc_area, 0.0);
+        assert_eq!(config.perc_region, 100.0);
+        assert_eq!(config.rules[0], Area::Upstream);
+        assert_eq!(config.level, ReportLevel::Gene);
+        assert_eq!(config.gene_id_tag, "gene_name");
+        assert_eq!(config.transcript_id_tag, "transcript_name");
+
+        let mut reversed = DEFAULT_RULES.to_vec();
+        reversed.reverse();
+        let config = ConfigBuilder::new().rules(&reversed).build().unwrap();
+        assert_eq!(config.rules, reversed);
+    }
+
+    #[test]
+    fn test_threads() {
+        assert_eq!(Config::default().num_threads(), DEFAULT_THREADS);
+        let config = ConfigBuilder::new().threads(3).build().unwrap();
+        assert_eq!(config.num_threads(), 3);
+        let config = ConfigBuilder::new().threads(0).build().unwrap();
+        assert_eq!(config.num_threads(), num_cpus::get());
+    }
+
+    #[test]
+    fn test_builder_single_violations() {
+        let cases = [
+            (
+                ConfigBuilder::from_config(Config {
+                    distance: -1000,
+                    ..Config::default()
+                }),
+                ConfigViolation::NegativeDistance(-1000),
+            ),
+            (
+                ConfigBuilder::new().area_max_distance(Area::Upstream, -5),
+                ConfigViolation::NegativeAreaDistance(Area::Upstream, -5),
+            ),
+            (
+                ConfigBuilder::new().tss_bp(-1),
+                ConfigViolation::NegativeTss(-1.0),
+            ),
+            (
+                ConfigBuilder::new().tss_downstream_bp(-10),
+                ConfigViolation::NegativeTssDownstream(-10.0),
+            ),
+            (
+                ConfigBuilder::new().min_overlap_bp(-1),
+                ConfigViolation::NegativeMinOverlap(-1),
+            ),
+            (
+                ConfigBuilder::new().bidirectional_window(-1),
+                ConfigViolation::NegativeBidirectionalWindow(-1),
+            ),
+            (
+                ConfigBuilder::new().area_weight(Area::Tss, -2.0),
+                ConfigViolation::NegativeAreaWeight(Area::Tss, -2.0),
+            ),
+            (
+                ConfigBuilder::new().tts_bp(-5),
+                ConfigViolation::NegativeTts(-5.0),
+            ),
+            (
+                ConfigBuilder::new().promoter_bp(-1),
+                ConfigViolation::NegativePromoter(-1.0),
+            ),
+            (
+                ConfigBuilder::new().perc_area(100.5),
+                ConfigViolation::PercAreaOutOfRange(100.5),
+            ),
+            (
+                ConfigBuilder::new().perc_region(-0.1),
+                ConfigViolation::PercRegionOutOfRange(-0.1),
+            ),
+            (
+                ConfigBuilder::new().rules_str("TSS,1st_EXON"),
+                ConfigViolation::InvalidRules("TSS,1st_EXON".to_string()),
+            ),
+            (
+                ConfigBuilder::new().rules(&[Area::Tss; 8]),
+                ConfigViolation::InvalidRules("TSS,TSS,TSS,TSS,TSS,TSS,TSS,TSS".to_string()),
+            ),
+            (
+                ConfigBuilder::new().gene_id_tag(""),
+                ConfigViolation::EmptyGeneIdTag,
+            ),
+            (
+                ConfigBuilder::new().transcript_id_tag(""),
+                ConfigViolation::EmptyTranscriptIdTag,
+            ),
+            (
+                ConfigBuilder::new().max_tsl(0),
+                ConfigViolation::MaxTslOutOfRange(0),
+            ),
+        ];
+
+        for (builder, expected) in cases {
+            let err = builder.build().unwrap_err();
+            assert_eq!(err.violations, vec![expected]);
+        }
+
+        // NaN is not a valid percentage
+        let err = ConfigBuilder::new()
+            .perc_area(f64::NAN)
+            .build()
+            .unwrap_err();
+        assert!(matches!(
+            err.violations[..],
+            [ConfigViolation::PercAreaOutOfRange(_)]
+        ));
+    }
+
+    #[test]
+    fn test_builder_reports_all_violations() {
+        let err = ConfigBuilder::new()
+            .tss_bp(-1)
+            .perc_area(150.0)
+            .perc_region(-3.0)
+            .rules_str("TSS")
+            .build()
+            .unwrap_err();
+
+        assert_eq!(
+            err.violations,
+            vec![
+                ConfigViolation::NegativeTss(-1.0),
+                ConfigViolation::PercAreaOutOfRange(150.0),
+                ConfigViolation::PercRegionOutOfRange(-3.0),
+                ConfigViolation::InvalidRules("TSS".to_string()),
+            ]
+        );
+        let message = err.to_string();
+        assert_eq!(message.lines().count(), 4);
+        assert!(message.starts_with("The TSS distance cannot be lower than 0 bps"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_toml_round_trip() {
+        let mut config = Config::new();
+        assert!(
+            config.parse_rules("UPSTREAM,DOWNSTREAM,TTS,TSS,PROMOTER,INTRON,GENE_BODY,1st_EXON")
+        );
+        config.level = ReportLevel::Gene;
+        config.tss = 350.0;
+        config.extra_columns = vec![ExtraColumn::GeneSpan, ExtraColumn::Coords];
+        config.columns = Some(vec![OutputColumn::Region, OutputColumn::Metadata(0)]);
+        config.output_coordinate_convention = CoordinateConvention::OneBased;
+        config.area_max_distance.insert(Area::Upstream, 5000);
+        config.area_weights.insert(Area::Tss, 2.5);
+
+        let text = config.to_toml();
+        assert!(text.contains("level = \"gene\""));
+        assert!(text.contains("UPSTREAM = 5000"));
+        assert!(text.contains("TSS = 2.5"));
+        assert!(text.contains("\"UPSTREAM\""));
+        assert!(text.contains("columns = [\"Region\", \"name\"]"));
+
+        let parsed = Config::from_toml(&text).unwrap();
+        assert_eq!(parsed.to_toml(), text);
+        assert_eq!(parsed.parameters(), config.parameters());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_toml_partial_and_unknown_keys() {
+        let config = Config::from_toml("level = \"transcript\"\ndistance = 20000\n").unwrap();
+        assert_eq!(config.level, ReportLevel::Transcript);
+        assert_eq!(config.distance, 20000);
+        assert_eq!(config.tss, 200.0);
+
+        // Unknown keys and bad values are errors
+        assert!(Config::from_toml("distanse = 20000\n").is_err());
+        match Config::from_toml("level = \"chromosome\"\n") {
+            Err(Error::ConfigParse { path: None, source }) => {
+                assert!(source.to_string().contains("chromosome"))
+            }
+            other => panic!("expected a ConfigParse error, got {:?}", other),
+        }
+        assert!(Config::from_toml("rules = [\"TSS\", \"EXON\"]\n").is_err());
+    }
+
     #[test]
     fn test_set_distance_kb() {
         let mut config = Config::new();
         config.set_distance_kb(20);
         assert_eq!(config.distance, 20000);
 
+        // Negative values remove the cutoff
         config.set_distance_kb(-1);
-        assert_eq!(config.distance, 20000); // Should not change for negative values
+        assert_eq!(config.distance, UNLIMITED_DISTANCE);
+        assert!(config.has_unlimited_distance());
+        assert_eq!(config.max_lookback_distance(), 2 * MAX_COORDINATE);
+        assert!(ConfigBuilder::new()
+            .distance_kb(-5)
+            .build()
+            .unwrap()
+            .has_unlimited_distance());
     }
 }
@@ -0,0 +1,113 @@
+//! Error type of the library.
+//!
+//! Every fallible public function returns [`Result`], so callers can tell
+//! apart a missing file, a malformed input line and an invalid configuration
+//! by matching on [`Error`].
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::ConfigError;
+
+/// Result type of the library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Errors returned by the library.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Reading or writing failed. `path` is the file involved, when known.
+    #[error("I/O error{}", in_file(path))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+
+    /// A GTF line could not be parsed. `line` is 1-based.
+    #[error("Invalid GTF line {line}: {reason}")]
+    GtfParse { line: usize, reason: String },
+
+    /// A BED line could not be parsed. `line` is 1-based.
+    #[error("Invalid BED line {line}: {reason}")]
+    BedParse { line: usize, reason: String },
+
+    /// A line of an association table could not be read back. `line` is
+    /// 1-based.
+    #[error("Invalid results line {line}: {reason}")]
+    ResultsParse { line: usize, reason: String },
+
+    /// A line of a batch manifest could not be read. `line` is 1-based.
+    #[error("Invalid manifest line {line}: {reason}")]
+    Manifest { line: usize, reason: String },
+
+    /// The GTF annotation has no gene with a valid strand and ID.
+    #[error("No genes found in {}", path.display())]
+    NoGenes { path: PathBuf },
+
+    /// Every BED region is on a chromosome without genes, which means the BED
+    /// and GTF name their chromosomes differently.
+    #[error(
+        "None of the {regions} BED regions is on a chromosome of the annotation \
+         (BED chromosomes: {examples}); check that both files use the same chromosome names"
+    )]
+    NoAnnotatedRegions { regions: u64, examples: String },
+
+    /// The configuration violates one or more constraints.
+    #[error(transparent)]
+    InvalidConfig(#[from] ConfigError),
+
+    /// A TOML configuration could not be parsed.
+    #[cfg(feature = "serde")]
+    #[error("Invalid configuration{}", in_file(path))]
+    ConfigParse {
+        path: Option<PathBuf>,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    /// A JSON configuration could not be parsed.
+    #[cfg(feature = "wasm")]
+    #[error("Invalid JSON configuration")]
+    ConfigJson(#[source] serde_json::Error),
+
+    /// The output options cannot be written, or an output sink was misused.
+    #[error("{0}")]
+    Output(String),
+
+    /// Writing Parquet output failed.
+    #[cfg(feature = "parquet")]
+    #[error("Failed to write Parquet output")]
+    Parquet(#[from] parquet::errors::ParquetError),
+}
+
+impl Error {
+    /// Attach `path` to an I/O or configuration error that has no file yet.
+    pub(crate) fn in_file(self, path: &Path) -> Self {
+        match self {
+            Error::Io { path: None, source } => Error::Io {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            #[cfg(feature = "serde")]
+            Error::ConfigParse { path: None, source } => Error::ConfigParse {
+                path: Some(path.to_path_buf()),
+                source,
+            },
+            other => other,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+/// Format an optional file for an error message.
+fn in_file(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!(" in {}", path.display()),
+        None => String::new(),
+    }
+}
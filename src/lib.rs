@@ -13,31 +13,56 @@
 //!
 //! # Example
 //!
-//! ```ignore
-//! use rgmatch::config::Config;
-//! use rgmatch::parser::{parse_gtf, parse_bed};
-//! use rgmatch::matcher::match_regions_to_genes;
-//! use rgmatch::output::write_results;
+//! ```no_run
+//! use rgmatch::{annotate, Config};
 //! use std::path::Path;
 //!
+//! # fn main() -> rgmatch::Result<()> {
 //! let config = Config::default();
-//! let gtf_data = parse_gtf(Path::new("annotations.gtf"), "gene_id", "transcript_id")?;
-//! let bed_data = parse_bed(Path::new("regions.bed"))?;
-//!
-//! for (chrom, regions) in &bed_data.regions_by_chrom {
-//!     if let Some(mut genes) = gtf_data.genes_by_chrom.get(chrom).cloned() {
-//!         let results = match_regions_to_genes(&regions, &mut genes, &config);
-//!         // Process results...
-//!     }
-//! }
+//! let result = annotate(Path::new("annotations.gtf"), Path::new("regions.bed"), &config)?;
+//!
+//! println!("{} regions without genes", result.num_unassigned());
+//! result.write_tsv(Path::new("regions.rgmatch.tsv"))?;
+//! # Ok(())
+//! # }
 //! ```
+//!
+//! The steps behind [`annotate`] are public as well: [`pipeline::load_annotation`],
+//! [`matcher::RegionMatcher`] and [`output::OutputSink`]. Gene models that do not
+//! come from a GTF file can be matched through an [`AnnotationSource`], such as a
+//! [`VecSource`].
+//!
+//! On `wasm32` targets the functions taking file paths are not compiled; with the
+//! `wasm` feature, `wasm::annotate_strings` annotates GTF and BED text instead.
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod batch;
 pub mod config;
+pub mod error;
 pub mod matcher;
 pub mod output;
 pub mod parser;
+pub mod pipeline;
+pub mod source;
 pub mod types;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 pub use config::Config;
+pub use error::{Error, Result};
+pub use matcher::classify_position;
 pub use parser::{BedReader, GtfData};
-pub use types::{Area, Candidate, Gene, Region, ReportLevel, Strand, Transcript};
+#[cfg(not(target_arch = "wasm32"))]
+pub use pipeline::{annotate, annotate_source, annotate_with_progress};
+pub use pipeline::{
+    AnnotationResult, MissingChromosomes, Phase, Progress, RegionAnnotator, Timings,
+};
+pub use source::{AnnotationSource, VecSource};
+pub use types::{
+    Area, Association, Candidate, ExonNumber, ExonNumbers, Gene, Name, Region, ReportLevel, Strand,
+    Transcript,
+};
+#[cfg(not(target_arch = "wasm32"))]
+pub use validate::{validate, ValidationReport};
@@ -1,184 +1,383 @@
 //! CLI entry point for rgmatch.
 //!
 //! This provides a command-line interface matching the Python implementation.
+//! It reads files, so it is not built for wasm32; see the `wasm` feature instead.
+#![cfg_attr(target_arch = "wasm32", no_main)]
+#![cfg(not(target_arch = "wasm32"))]
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
-use crossbeam_channel::{bounded, Receiver, Sender};
-use std::collections::VecDeque;
-use std::fs::File;
-use std::io::{BufWriter, Write};
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
-use std::thread;
-use std::time::Instant;
-
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, error, info, warn, Level, LevelFilter};
 use rayon::prelude::*;
-use rgmatch::config::Config;
-use rgmatch::matcher::overlap::find_search_start_index;
-use rgmatch::matcher::{match_region_to_genes, process_candidates_for_output};
-use rgmatch::output::{format_output_line, write_header};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use rgmatch::batch::{read_manifest, BatchSummary, Sample, SampleStatus};
+use rgmatch::config::{Config, ConfigBuilder, DEFAULT_THREADS};
+use rgmatch::output::columns::OutputColumn;
+use rgmatch::output::diff::{diff_files, DiffOptions};
+use rgmatch::output::provenance::{format_command_line, Provenance};
+use rgmatch::output::reader::ResultsReader;
+use rgmatch::output::summary::{Summary, Timings};
+use rgmatch::output::{
+    candidates_to_write, format_region_bed_line, OutputSink, OUTPUT_BUFFER_SIZE,
+};
 use rgmatch::parser::gtf::GtfData;
-use rgmatch::parser::{parse_gtf, BedReader};
-use rgmatch::types::{Candidate, Region, ReportLevel};
-
-/// Performance metrics for profiling bottlenecks.
-/// All times are in nanoseconds.
-#[derive(Default)]
-struct PerfMetrics {
-    /// Total time workers spend on matching (match_region_to_genes + process_candidates)
-    worker_matching_ns: AtomicU64,
-    /// Total time workers spend waiting to send results on the channel
-    worker_channel_wait_ns: AtomicU64,
-    /// Total time the writer spends formatting output lines
-    writer_format_ns: AtomicU64,
-    /// Total time the writer spends on I/O (writeln!)
-    writer_io_ns: AtomicU64,
-    /// Number of regions processed by workers
-    regions_processed: AtomicU64,
-    /// Number of output lines written
-    lines_written: AtomicU64,
-    /// Maximum size of the pending buffer in the writer
-    max_pending_size: AtomicU64,
+use rgmatch::parser::util::create_buffered_reader;
+use rgmatch::parser::{parse_tss_bed, BedReader};
+use rgmatch::pipeline::{annotate_chunks, init_thread_pool, load_annotation, Phase, Progress};
+use rgmatch::types::{
+    Area, AssignmentMode, Association, Candidate, ClosestBy, ContainedGeneBehavior, DistanceAnchor,
+    ExtraColumn, GeneBodyDefinition, GeneLevelStyle, GeneTssMode, InvalidRegions, OutputFormat,
+    Region, ReportLevel, ScoreCombination, SelectionMode, SpanMultipleGenes, Strandedness,
+    TieBreak, TssDistanceSign, UnstrandedGenes,
+};
+use rgmatch::validate::validate;
+
+/// Progress bar on stderr.
+///
+/// The bar is only drawn when stderr is a terminal and `--quiet` is not given.
+/// Log records are printed above it by [`ProgressReporter::log_writer`].
+#[derive(Clone)]
+struct ProgressReporter {
+    bar: Option<ProgressBar>,
 }
 
-impl PerfMetrics {
-    fn new() -> Self {
-        Self::default()
+impl ProgressReporter {
+    fn new(quiet: bool) -> Self {
+        let bar = (!quiet && io::stderr().is_terminal()).then(|| {
+            let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+            bar.set_style(
+                ProgressStyle::with_template("{spinner} [{elapsed_precise}] {msg}")
+                    .expect("valid progress template"),
+            );
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        });
+        ProgressReporter { bar }
     }
 
-    fn add_worker_matching(&self, ns: u64) {
-        self.worker_matching_ns.fetch_add(ns, Ordering::Relaxed);
+    /// Show a progress update on the bar.
+    fn update(&self, progress: &Progress) {
+        if let Some(bar) = &self.bar {
+            let unit = match progress.phase {
+                Phase::ParsingGtf => "genes",
+                _ => "regions",
+            };
+            let message = match (progress.done, progress.total) {
+                (0, None) => progress.phase.to_string(),
+                (done, None) => format!("{}: {} {}", progress.phase, done, unit),
+                (done, Some(total)) => format!("{}: {}/{} {}", progress.phase, done, total, unit),
+            };
+            bar.set_message(message);
+        }
     }
 
-    fn add_worker_channel_wait(&self, ns: u64) {
-        self.worker_channel_wait_ns.fetch_add(ns, Ordering::Relaxed);
+    /// Writer to stderr that hides the bar while writing.
+    fn log_writer(&self) -> BarWriter {
+        BarWriter(self.bar.clone())
     }
 
-    fn add_writer_format(&self, ns: u64) {
-        self.writer_format_ns.fetch_add(ns, Ordering::Relaxed);
+    /// Remove the bar from the terminal.
+    fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
     }
+}
 
-    fn add_writer_io(&self, ns: u64) {
-        self.writer_io_ns.fetch_add(ns, Ordering::Relaxed);
+/// Stderr, written above the progress bar if it is shown.
+struct BarWriter(Option<ProgressBar>);
+
+impl Write for BarWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.0 {
+            Some(bar) => bar.suspend(|| io::stderr().write(buf)),
+            None => io::stderr().write(buf),
+        }
     }
 
-    fn add_regions_processed(&self, count: u64) {
-        self.regions_processed.fetch_add(count, Ordering::Relaxed);
+    fn flush(&mut self) -> io::Result<()> {
+        io::stderr().flush()
     }
+}
 
-    fn add_lines_written(&self, count: u64) {
-        self.lines_written.fetch_add(count, Ordering::Relaxed);
+/// Log output: stderr, and the `--log-file` if one is given.
+struct LogWriter {
+    stderr: BarWriter,
+    file: Option<File>,
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(file) = &mut self.file {
+            file.write_all(buf)?;
+        }
+        self.stderr.write_all(buf)?;
+        Ok(buf.len())
     }
 
-    fn update_max_pending(&self, size: usize) {
-        let size = size as u64;
-        let mut current = self.max_pending_size.load(Ordering::Relaxed);
-        while size > current {
-            match self.max_pending_size.compare_exchange_weak(
-                current,
-                size,
-                Ordering::Relaxed,
-                Ordering::Relaxed,
-            ) {
-                Ok(_) => break,
-                Err(c) => current = c,
-            }
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(file) = &mut self.file {
+            file.flush()?;
         }
+        self.stderr.flush()
     }
+}
 
-    fn print_summary(&self) {
-        let worker_matching_ms =
-            self.worker_matching_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let worker_channel_wait_ms =
-            self.worker_channel_wait_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let writer_format_ms = self.writer_format_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let writer_io_ms = self.writer_io_ns.load(Ordering::Relaxed) as f64 / 1_000_000.0;
-        let regions = self.regions_processed.load(Ordering::Relaxed);
-        let lines = self.lines_written.load(Ordering::Relaxed);
-        let max_pending = self.max_pending_size.load(Ordering::Relaxed);
-
-        eprintln!("\n=== Performance Metrics ===");
-        eprintln!("Regions processed: {}", regions);
-        eprintln!("Lines written: {}", lines);
-        eprintln!();
-        eprintln!("Worker time (cumulative across all workers):");
-        eprintln!("  Matching:      {:>10.2} ms", worker_matching_ms);
-        eprintln!("  Channel wait:  {:>10.2} ms", worker_channel_wait_ms);
-        eprintln!();
-        eprintln!("Writer time:");
-        eprintln!("  Formatting:    {:>10.2} ms", writer_format_ms);
-        eprintln!("  I/O:           {:>10.2} ms", writer_io_ms);
-        eprintln!();
-        eprintln!("Channel congestion:");
-        eprintln!(
-            "  Max pending results: {} (channel bound: 2000)",
-            max_pending
-        );
-        if max_pending >= 1900 {
-            eprintln!("  ⚠️  Channel nearly full - WRITER IS BOTTLENECK");
-        } else if max_pending < 100 {
-            eprintln!("  ✓  Channel uncongested - Workers are bottleneck");
-        } else {
-            eprintln!("  ~  Moderate congestion - Mixed bottleneck");
+/// Send log records to stderr and the `--log-file`: status messages (info)
+/// by default, per-chromosome statistics and other debug records with
+/// `--verbose`, per-line parser diagnostics (trace) with `--verbose
+/// --verbose` and only errors with `--quiet`. `RUST_LOG` overrides the level.
+fn init_logger(args: &LogArgs, progress: &ProgressReporter) -> Result<()> {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Info,
+        (false, 1) => LevelFilter::Debug,
+        (false, _) => LevelFilter::Trace,
+    };
+    let file = match &args.log_file {
+        Some(path) => Some(
+            File::create(path)
+                .with_context(|| format!("Failed to create log file: {}", path.display()))?,
+        ),
+        None => None,
+    };
+    let writer = LogWriter {
+        stderr: progress.log_writer(),
+        file,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "[{}] {}", level, record.args()),
+        })
+        .target(env_logger::Target::Pipe(Box::new(writer)))
+        .init();
+    Ok(())
+}
+
+/// Counts the regions written and logs them per chromosome.
+struct RegionProgress<'a> {
+    reporter: &'a ProgressReporter,
+    chrom: String,
+    done: u64,
+    /// Regions and candidates of the current chromosome, and when its first
+    /// region was written.
+    chrom_regions: u64,
+    chrom_candidates: u64,
+    chrom_start: Instant,
+}
+
+impl<'a> RegionProgress<'a> {
+    fn new(reporter: &'a ProgressReporter) -> Self {
+        RegionProgress {
+            reporter,
+            chrom: String::new(),
+            done: 0,
+            chrom_regions: 0,
+            chrom_candidates: 0,
+            chrom_start: Instant::now(),
         }
-        eprintln!();
-
-        // Calculate ratios
-        let total_worker = worker_matching_ms + worker_channel_wait_ms;
-        let total_writer = writer_format_ms + writer_io_ms;
-        if total_worker > 0.0 {
-            eprintln!("Worker breakdown:");
-            eprintln!(
-                "  Matching: {:.1}%",
-                100.0 * worker_matching_ms / total_worker
-            );
-            eprintln!(
-                "  Waiting:  {:.1}%",
-                100.0 * worker_channel_wait_ms / total_worker
-            );
+    }
+
+    /// Count a region and its candidates, logging the previous chromosome
+    /// when it changes.
+    fn add(&mut self, region: &Region, num_candidates: usize) {
+        self.done += 1;
+        if region.chrom != self.chrom {
+            self.log_chrom();
+            self.chrom.clone_from(&region.chrom);
+            self.chrom_regions = 0;
+            self.chrom_candidates = 0;
+            self.chrom_start = Instant::now();
         }
-        if total_writer > 0.0 {
-            eprintln!("Writer breakdown:");
-            eprintln!("  Format: {:.1}%", 100.0 * writer_format_ms / total_writer);
-            eprintln!("  I/O:    {:.1}%", 100.0 * writer_io_ms / total_writer);
+        self.chrom_regions += 1;
+        self.chrom_candidates += num_candidates as u64;
+    }
+
+    /// Log the statistics of the current chromosome at debug level.
+    fn log_chrom(&self) {
+        if self.chrom_regions > 0 {
+            debug!(
+                "{}: {} regions, {} candidates in {:.3}s",
+                self.chrom,
+                self.chrom_regions,
+                self.chrom_candidates,
+                self.chrom_start.elapsed().as_secs_f64()
+            );
         }
-        eprintln!("=== End Performance Metrics ===\n");
+    }
+
+    /// Report the start of writing, before the output is flushed.
+    fn writing(&self) {
+        self.log_chrom();
+        self.reporter
+            .update(&Progress::new(Phase::Writing, self.done, Some(self.done)));
     }
 }
 
 /// Genomic region-to-gene matching tool.
 ///
 /// Maps genomic regions from a BED file to gene annotations from a GTF file.
+/// Without a subcommand, the options are those of `rgmatch annotate`.
 #[derive(Parser, Debug)]
 #[command(name = "rgmatch")]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(flatten)]
+    annotate: Args,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Match regions to genes (the default when no subcommand is given)
+    Annotate(Box<Args>),
+    /// Check that a GTF and a BED file can be annotated together: record
+    /// counts, shared chromosomes and BED layout, without matching
+    Validate(ValidateArgs),
+    /// Summarize an association table written by an earlier run: area
+    /// counts, TSS distance histogram and associations per gene
+    Summarize(SummarizeArgs),
+    /// Annotate every BED file of a manifest against one GTF, parsed once,
+    /// running up to --threads samples at a time
+    Batch(Box<BatchArgs>),
+    /// Compare two association tables row by row, matching rows on Region,
+    /// Gene, Transcript and Area and numbers within a tolerance
+    Diff(DiffArgs),
+}
+
+/// Options of `rgmatch annotate`, also accepted without the subcommand.
+#[derive(clap::Args, Debug)]
 struct Args {
     /// GTF annotation file (required)
-    #[arg(short = 'g', long = "gtf")]
-    gtf: PathBuf,
+    #[arg(short = 'g', long = "gtf", required_unless_present = "dump_config")]
+    gtf: Option<PathBuf>,
 
     /// Region BED file (required)
-    #[arg(short = 'b', long = "bed")]
-    bed: PathBuf,
+    #[arg(short = 'b', long = "bed", required_unless_present = "dump_config")]
+    bed: Option<PathBuf>,
+
+    /// Output file (required, use "-" for stdout)
+    #[arg(short = 'o', long = "output", required_unless_present = "dump_config")]
+    output: Option<PathBuf>,
+
+    #[command(flatten)]
+    options: AnnotateOptions,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long = "dump-config")]
+    dump_config: bool,
 
-    /// Output file (required)
-    #[arg(short = 'o', long = "output")]
-    output: PathBuf,
+    /// Write regions without any association to FILE as BED
+    #[arg(long = "unassigned", value_name = "FILE")]
+    unassigned: Option<PathBuf>,
 
-    /// Report level: exon, transcript, or gene
-    #[arg(short = 'r', long = "report", default_value = "exon")]
-    report: String,
+    /// Write per-run summary statistics to FILE (JSON if it ends in .json, TSV otherwise)
+    #[arg(long = "summary", value_name = "FILE")]
+    summary: Option<PathBuf>,
 
-    /// Maximum distance in kb to report associations
-    #[arg(short = 'q', long = "distance", default_value = "10")]
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Matching and output options, shared by `rgmatch annotate` and
+/// `rgmatch batch`.
+#[derive(clap::Args, Debug)]
+struct AnnotateOptions {
+    /// Read the configuration from a TOML file; options given on the command
+    /// line override its values
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// BED file of refined TSS positions (e.g. CAGE peaks), keyed by
+    /// transcript or gene ID in column 4, that replace the annotation TSS
+    #[arg(long = "tss-bed", value_name = "FILE")]
+    tss_bed: Option<PathBuf>,
+
+    /// Report level
+    #[arg(
+        short = 'r',
+        long = "report",
+        value_enum,
+        ignore_case = true,
+        default_value_t = ReportLevel::Exon
+    )]
+    report: ReportLevel,
+
+    /// Maximum distance in kb to report associations; a negative value
+    /// (e.g. -1) reports them at any distance
+    #[arg(
+        short = 'q',
+        long = "distance",
+        default_value = "10",
+        allow_negative_numbers = true
+    )]
     distance: i64,
 
+    /// Match only the base at each region's midpoint, ignoring the rest of
+    /// the region
+    #[arg(long = "midpoint")]
+    midpoint: bool,
+
+    /// Measure the Distance and TSSDistance columns from the region midpoint
+    /// or from its edge nearest to the feature (0 when the region reaches it)
+    #[arg(
+        long = "distance-from",
+        value_enum,
+        ignore_case = true,
+        default_value = "midpoint"
+    )]
+    distance_from: DistanceAnchor,
+
+    /// What counts as gene body: exon-only (exon overlaps, PercArea over the
+    /// overlapped exons), full-span (introns too, PercArea over the
+    /// transcript span) or exon-union (exon overlaps, PercArea over all exons)
+    #[arg(
+        long = "gene-body-definition",
+        value_enum,
+        ignore_case = true,
+        default_value = "exon-only"
+    )]
+    gene_body_definition: GeneBodyDefinition,
+
+    /// Sign of TSSDistance: upstream-negative (negative 5' of the TSS on
+    /// either strand) or genomic-left-negative (negative left of the TSS in
+    /// genome coordinates)
+    #[arg(
+        long = "tss-sign",
+        value_enum,
+        ignore_case = true,
+        default_value = "upstream-negative"
+    )]
+    tss_sign: TssDistanceSign,
+
+    /// Maximum distance in bp of the associations of one area, e.g.
+    /// UPSTREAM=5000; repeat for several areas
+    #[arg(long = "area-distance", value_name = "AREA=BP", value_parser = parse_area_distance)]
+    area_distance: Vec<(Area, i64)>,
+
     /// TSS region distance in bp
     #[arg(short = 't', long = "tss", default_value = "200")]
     tss: i64,
 
+    /// Extend the TSS region N bp into the first exon, reporting those
+    /// bases as TSS instead of 1st_EXON
+    #[arg(long = "tss-down", default_value = "0")]
+    tss_down: i64,
+
     /// TTS region distance in bp
     #[arg(short = 's', long = "tts", default_value = "0")]
     tts: i64,
@@ -187,6 +386,11 @@ struct Args {
     #[arg(short = 'p', long = "promoter", default_value = "1300")]
     promoter: i64,
 
+    /// Put a region exactly --tss, --tss + --promoter or --tts bp from the
+    /// transcript in the next area out, instead of the area inside
+    #[arg(long = "exclusive-boundaries")]
+    exclusive_boundaries: bool,
+
     /// Percentage of the area overlap threshold (0-100)
     #[arg(short = 'v', long = "perc_area", default_value = "90")]
     perc_area: f64,
@@ -195,6 +399,53 @@ struct Args {
     #[arg(short = 'w', long = "perc_region", default_value = "50")]
     perc_region: f64,
 
+    /// Minimum overlap in bp, used by the rules instead of the region
+    /// percentage (0 = off)
+    #[arg(long = "min-overlap", default_value = "0")]
+    min_overlap: i64,
+
+    /// How to choose the reported candidates: rules (thresholds, then the
+    /// priority order) or score (highest weighted overlap and proximity)
+    #[arg(
+        long = "selection",
+        value_enum,
+        ignore_case = true,
+        default_value = "rules"
+    )]
+    selection: SelectionMode,
+
+    /// Weight of an area for --selection score, e.g. TSS=10; repeatable.
+    /// Areas not given weigh 1
+    #[arg(long = "area-weight", value_name = "AREA=W", value_parser = parse_area_weight)]
+    area_weight: Vec<(Area, f64)>,
+
+    /// How --selection score combines the area weight with the region and
+    /// area percentages and the distance: product or sum
+    #[arg(
+        long = "score-combination",
+        value_enum,
+        ignore_case = true,
+        default_value = "product"
+    )]
+    score_combination: ScoreCombination,
+
+    /// How the rules settle candidates tied on area and region percentage:
+    /// report-all, tss-distance (smallest |TSS distance|, then gene ID) or
+    /// gene-id
+    #[arg(
+        long = "tie-break",
+        value_enum,
+        ignore_case = true,
+        default_value = "report-all"
+    )]
+    tie_break: TieBreak,
+
+    /// Drop the associations of a transcript when none reaches
+    /// --perc_region (or --min-overlap), instead of keeping the best of them;
+    /// regions left without any become unassigned
+    #[arg(long = "hard-thresholds")]
+    hard_thresholds: bool,
+
     /// Priority rules (comma-separated)
     #[arg(
         short = 'R',
@@ -211,503 +462,1181 @@ struct Args {
     #[arg(short = 'T', long = "transcript", default_value = "transcript_id")]
     transcript_tag: String,
 
+    /// Keep overlapping or duplicated exons of a transcript as separate
+    /// exons instead of merging them
+    #[arg(long = "keep-overlapping-exons")]
+    keep_overlapping_exons: bool,
+
+    /// Accept negative BED coordinates instead of rejecting the file
+    #[arg(long = "allow-negative-coordinates")]
+    allow_negative_coordinates: bool,
+
+    /// Fail on BED regions whose start is greater than their end, instead of
+    /// swapping the two, and on GTF transcript IDs used by more than one gene
+    #[arg(long = "strict", conflicts_with = "drop_invalid")]
+    strict: bool,
+
+    /// Skip BED regions whose start is greater than their end, instead of
+    /// swapping the two
+    #[arg(long = "drop-invalid")]
+    drop_invalid: bool,
+
+    /// Keep only transcripts with at least one of these GTF `tag` values
+    /// (comma-separated, e.g. basic,MANE_Select)
+    #[arg(long = "only-tagged", value_name = "TAGS", value_delimiter = ',')]
+    only_tagged: Vec<String>,
+
+    /// Keep only transcripts with a transcript_support_level of at most N
+    /// (1-5); transcripts without one are removed too
+    #[arg(long = "max-tsl", value_name = "N")]
+    max_tsl: Option<u8>,
+
+    /// Match stranded regions (BED column 6) only to genes on the same or the
+    /// opposite strand
+    #[arg(
+        long = "strandedness",
+        value_enum,
+        ignore_case = true,
+        default_value = "ignore"
+    )]
+    strandedness: Strandedness,
+
+    /// With --strandedness same or opposite, leave unstranded regions
+    /// unassigned instead of matching them to genes on both strands
+    #[arg(long = "strict-strandedness")]
+    strict_strandedness: bool,
+
+    /// Assign regions without any gene within --distance to their nearest gene,
+    /// however far, as an UPSTREAM or DOWNSTREAM association
+    #[arg(long = "closest")]
+    closest: bool,
+
+    /// Distance ranking genes for --closest: edge (gap to the nearest gene end)
+    /// or tss (from the region midpoint to the TSS)
+    #[arg(
+        long = "closest-by",
+        value_enum,
+        ignore_case = true,
+        default_value = "edge"
+    )]
+    closest_by: ClosestBy,
+
+    /// With -r gene, also report the N genes with the TSS nearest to each
+    /// region, whatever their distance, ranked in a Rank column (0 = off)
+    #[arg(long = "n-closest", value_name = "N", default_value = "0")]
+    n_closest: usize,
+
+    /// With --n-closest, report only the ranked nearest genes instead of the
+    /// associations selected by the rules
+    #[arg(long = "closest-only")]
+    closest_only: bool,
+
+    /// Report every candidate of each region, not only those selected by the
+    /// rules for -r, with a Selected column (1 or 0) flagging the selected ones
+    #[arg(long = "no-rules")]
+    no_rules: bool,
+
+    /// With -r gene, which genes a region spanning several is reported with:
+    /// those the rules keep, or every gene it overlaps, with PercRegion the
+    /// share of the region within the gene and a Selected column (1 or 0)
+    /// flagging the rules' choice
+    #[arg(
+        long = "span-multiple-genes",
+        value_enum,
+        ignore_case = true,
+        default_value = "rules-only"
+    )]
+    span_multiple_genes: SpanMultipleGenes,
+
+    /// With -r gene, the transcripts whose TSS, PROMOTER and UPSTREAM hits a
+    /// gene reports: any transcript, only the 5'-most one, or the canonical
+    /// one (Ensembl_canonical or MANE_Select tag, else the 5'-most)
+    #[arg(
+        long = "gene-tss",
+        value_enum,
+        ignore_case = true,
+        default_value = "any-transcript"
+    )]
+    gene_tss: GeneTssMode,
+
+    /// Maximum distance (bp) between the TSSs of two genes on opposite strands
+    /// for a region at both to be flagged in the Bidirectional column
+    #[arg(long = "bidirectional-window", default_value = "1000")]
+    bidirectional_window: i64,
+
+    /// Report regions without any gene within --distance as INTERGENIC, with
+    /// the distance to their nearest gene (NA on chromosomes without genes)
+    #[arg(long = "report-intergenic")]
+    report_intergenic: bool,
+
+    /// Report regions in the first intron as 1st_INTRON instead of INTRON.
+    /// Ranked right after INTRON unless --rules lists 1st_INTRON; custom
+    /// rules without it keep INTRON
+    #[arg(long = "distinguish-first-intron")]
+    distinguish_first_intron: bool,
+
+    /// Genes entirely within a region: split them into TSS, 1st_EXON,
+    /// GENE_BODY, INTRON and TTS hits, or consolidate each transcript into
+    /// one GENE_CONTAINED hit. Ranked first unless --rules lists it
+    #[arg(
+        long = "contained-genes",
+        value_enum,
+        ignore_case = true,
+        default_value = "split"
+    )]
+    contained_genes: ContainedGeneBehavior,
+
+    /// Genes annotated without a strand ('.'): skip them, treat them as +
+    /// strand genes, or match both orientations and report the better one
+    #[arg(
+        long = "unstranded-genes",
+        value_enum,
+        ignore_case = true,
+        default_value = "skip"
+    )]
+    unstranded_genes: UnstrandedGenes,
+
+    /// Write the Exon/Intron column of intron overlaps as intron_N, N being
+    /// the intron number in transcription order
+    #[arg(long = "label-introns")]
+    label_introns: bool,
+
+    /// Report exon overlaps outside the CDS of coding transcripts as UTR5 or
+    /// UTR3, from the CDS (or UTR) features of the GTF. Ranked after 1st_EXON
+    /// and GENE_BODY unless --rules lists them
+    #[arg(long = "report-utr")]
+    report_utr: bool,
+
     /// Number of worker threads (0 = auto-detect, 1 = sequential)
-    #[arg(long = "threads", short = 'j', default_value = "8")]
+    #[arg(long = "threads", short = 'j', default_value_t = DEFAULT_THREADS)]
     threads: usize,
 
     /// Batch size for streaming BED regions
     #[arg(long = "batch-size", default_value = "5000")]
     batch_size: usize,
+
+    /// Output format: tsv, bed (BED6 track named GENE|AREA|DISTANCE), parquet
+    /// (requires the `parquet` feature), or closest (bedtools closest -D b layout,
+    /// requires -r gene)
+    #[arg(long = "output-format", default_value = "tsv")]
+    output_format: String,
+
+    /// Write only the best association per region
+    #[arg(long = "best-only")]
+    best_only: bool,
+
+    /// One row per region: the best association plus an OtherGenes column
+    /// listing the other genes as gene:area:distance
+    #[arg(long = "wide")]
+    wide: bool,
+
+    /// Number of decimals for PercRegion and PercArea
+    #[arg(long = "precision", default_value = "2")]
+    precision: usize,
+
+    /// Text written for missing values, including the percentages of UPSTREAM
+    /// and DOWNSTREAM hits
+    #[arg(long = "na-string", default_value = "NA")]
+    na_string: String,
+
+    /// Write inapplicable percentages as -1.00, like the Python RGmatch
+    #[arg(long = "legacy-sentinel")]
+    legacy_sentinel: bool,
+
+    /// Separator of merged transcript IDs and exon/intron numbers
+    #[arg(long = "merge-sep", default_value = ",")]
+    merge_sep: String,
+
+    /// Merge tied transcripts in annotation order, keeping one exon entry per
+    /// transcript, like the Python RGmatch
+    #[arg(long = "legacy-merge")]
+    legacy_merge: bool,
+
+    /// Gene-level rows of tied transcripts: merge (one row listing them all)
+    /// or best-transcript (the transcript with the highest region
+    /// percentage, then the nearest TSS, then the lowest ID)
+    #[arg(
+        long = "gene-style",
+        value_enum,
+        ignore_case = true,
+        default_value = "merge"
+    )]
+    gene_style: GeneLevelStyle,
+
+    /// Coordinates of reported start positions (Region, Midpoint, FeatureStart,
+    /// GeneStart): bed0 (0-based, as in the BED input) or one-based (as in the GTF)
+    #[arg(long = "report-coords", default_value = "bed0")]
+    report_coords: String,
+
+    /// Extra output columns (comma-separated): coords, relative-strand, tts-distance, gene-span,
+    /// num-candidate-genes, sense, perc-gene, bidirectional, junction-distance, bed-line
+    #[arg(long = "extra-columns")]
+    extra_columns: Option<String>,
+
+    /// Output columns to write, in order (comma-separated column names, e.g.
+    /// Region,Gene,Area,Distance,PercRegion). Overrides --extra-columns
+    #[arg(long = "columns")]
+    columns: Option<String>,
+
+    /// Keep regions without any association in the main output, with NA columns
+    #[arg(long = "keep-unassigned")]
+    keep_unassigned: bool,
+
+    /// Bin edges (bp, comma-separated) of the TSS distance histogram in the summary
+    #[arg(long = "tss-bins", default_value = "1000,5000,10000,50000")]
+    tss_bins: String,
+
+    /// Do not write the run parameters as '#' comment lines before the header
+    #[arg(long = "no-provenance")]
+    no_provenance: bool,
 }
 
-fn main() -> Result<()> {
-    let args = Args::parse();
+/// Options of the messages on stderr, shared by the subcommands.
+#[derive(clap::Args, Debug)]
+struct LogArgs {
+    /// Do not print the progress bar or status messages to stderr, only errors
+    #[arg(long = "quiet", conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print per-chromosome timing and candidate counts and performance
+    /// metrics; give twice for a diagnostic per skipped input line. There is
+    /// no short form, as -v is --perc_area and -V is --version
+    #[arg(long = "verbose", action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Also write the log messages to FILE
+    #[arg(long = "log-file", value_name = "FILE")]
+    log_file: Option<PathBuf>,
+}
 
-    // Validate inputs
-    if !args.gtf.exists() {
-        bail!("GTF file not found: {}", args.gtf.display());
-    }
-    if !args.bed.exists() {
-        bail!("BED file not found: {}", args.bed.display());
-    }
+/// Options of `rgmatch batch`.
+#[derive(clap::Args, Debug)]
+struct BatchArgs {
+    /// Tab-separated manifest with bed_path, output_path and an optional
+    /// label per line
+    #[arg(long = "manifest", value_name = "FILE")]
+    manifest: PathBuf,
 
-    // Parse report level
-    let level: ReportLevel = args
-        .report
-        .parse()
-        .context("Report can only be one of the following: exon, transcript or gene")?;
+    /// GTF annotation file, parsed once for all samples
+    #[arg(short = 'g', long = "gtf")]
+    gtf: PathBuf,
+
+    /// Write the region and association counts of every sample to FILE (TSV)
+    #[arg(long = "summary", value_name = "FILE")]
+    summary: PathBuf,
+
+    /// Start no more samples once one has failed
+    #[arg(long = "fail-fast")]
+    fail_fast: bool,
+
+    #[command(flatten)]
+    options: AnnotateOptions,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Options of `rgmatch diff`.
+#[derive(clap::Args, Debug)]
+struct DiffArgs {
+    /// First association table (may be gzipped)
+    #[arg(value_name = "A")]
+    a: PathBuf,
+
+    /// Second association table (may be gzipped)
+    #[arg(value_name = "B")]
+    b: PathBuf,
+
+    /// Largest difference between two numeric values reported as equal
+    #[arg(long = "tolerance", default_value = "0")]
+    tolerance: f64,
 
-    // Build configuration
-    let mut config = Config::new();
-    config.level = level;
+    /// Compare the rows regardless of their order
+    #[arg(long = "ignore-order")]
+    ignore_order: bool,
 
-    // Set distance (convert from kb to bp)
-    if args.distance >= 0 {
-        config.set_distance_kb(args.distance);
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Options of `rgmatch summarize`.
+#[derive(clap::Args, Debug)]
+struct SummarizeArgs {
+    /// Association table written by rgmatch (TSV output, may be gzipped)
+    #[arg(value_name = "RESULTS")]
+    results: PathBuf,
+
+    /// Print the summary as JSON instead of TSV
+    #[arg(long = "json")]
+    json: bool,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+/// Options of `rgmatch validate`.
+#[derive(clap::Args, Debug)]
+struct ValidateArgs {
+    /// GTF annotation file
+    #[arg(short = 'g', long = "gtf")]
+    gtf: PathBuf,
+
+    /// Region BED file
+    #[arg(short = 'b', long = "bed")]
+    bed: PathBuf,
+
+    /// Read only about the first MB megabytes of each file
+    #[arg(long = "sample", value_name = "MB")]
+    sample: Option<u64>,
+
+    /// Read the ID tags and BED options from a TOML configuration file
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// GTF tag for gene ID
+    #[arg(short = 'G', long = "gene")]
+    gene_tag: Option<String>,
+
+    /// GTF tag for transcript ID
+    #[arg(short = 'T', long = "transcript")]
+    transcript_tag: Option<String>,
+
+    #[command(flatten)]
+    log: LogArgs,
+}
+
+impl Args {
+    /// GTF file path; required unless `--dump-config` is given.
+    fn gtf(&self) -> &Path {
+        self.gtf.as_deref().expect("--gtf is required")
     }
 
-    // Set TSS distance
-    if args.tss >= 0 {
-        config.tss = args.tss as f64;
-    } else {
-        bail!("The TSS distance cannot be lower than 0 bps.");
+    /// BED file path; required unless `--dump-config` is given.
+    fn bed(&self) -> &Path {
+        self.bed.as_deref().expect("--bed is required")
     }
 
-    // Set TTS distance
-    if args.tts >= 0 {
-        config.tts = args.tts as f64;
-    } else {
-        bail!("The TTS distance cannot be lower than 0 bps.");
+    /// Output path; required unless `--dump-config` is given.
+    fn output(&self) -> &Path {
+        self.output.as_deref().expect("--output is required")
     }
+}
 
-    // Set promoter distance
-    if args.promoter >= 0 {
-        config.promoter = args.promoter as f64;
-    } else {
-        bail!("The promoter distance cannot be lower than 0 bps.");
+fn main() -> Result<()> {
+    match run() {
+        Err(e) if is_broken_pipe(&e) => Ok(()),
+        result => result,
     }
+}
+
+/// Whether an error was caused by the output pipe being closed (e.g. `| head`).
+fn is_broken_pipe(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        cause
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
 
-    // Set percentage thresholds
-    if args.perc_area >= 0.0 && args.perc_area <= 100.0 {
-        config.perc_area = args.perc_area;
+/// Whether the output argument refers to stdout.
+fn is_stdout(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Human-readable name of the output destination for status messages.
+fn output_display(path: &Path) -> String {
+    if is_stdout(path) {
+        "stdout".to_string()
     } else {
-        bail!("The percentage of area defined was wrong. It should range between 0 and 100.");
+        path.display().to_string()
     }
+}
 
-    if args.perc_region >= 0.0 && args.perc_region <= 100.0 {
-        config.perc_region = args.perc_region;
+/// Open the output destination: a file, or stdout when the path is "-".
+fn create_output_writer(path: &Path) -> Result<BufWriter<Box<dyn Write + Send>>> {
+    let inner: Box<dyn Write + Send> = if is_stdout(path) {
+        Box::new(io::stdout())
     } else {
-        bail!("The percentage of region defined was wrong. It should range between 0 and 100.");
-    }
+        Box::new(File::create(path).context("Failed to create output file")?)
+    };
+    Ok(BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, inner))
+}
 
-    // Parse rules
-    if !config.parse_rules(&args.rules) {
-        bail!("Rules not properly passed.");
+fn run() -> Result<()> {
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches)?;
+
+    match &cli.command {
+        None => run_annotate(&cli.annotate, &matches),
+        Some(Command::Annotate(args)) => {
+            let matches = matches
+                .subcommand_matches("annotate")
+                .expect("annotate subcommand matched");
+            run_annotate(args, matches)
+        }
+        Some(Command::Validate(args)) => run_validate(args),
+        Some(Command::Summarize(args)) => run_summarize(args),
+        Some(Command::Diff(args)) => run_diff(args),
+        Some(Command::Batch(args)) => {
+            let matches = matches
+                .subcommand_matches("batch")
+                .expect("batch subcommand matched");
+            run_batch(args, matches)
+        }
     }
+}
 
-    // Set GTF tags
-    config.gene_id_tag = args.gene_tag.clone();
-    config.transcript_id_tag = args.transcript_tag.clone();
-
-    // Parse GTF file
-    eprintln!("Parsing GTF file: {}", args.gtf.display());
-    let mut gtf_data = parse_gtf(&args.gtf, &config.gene_id_tag, &config.transcript_id_tag)?;
+/// Compare two association tables and print their differences.
+fn run_diff(args: &DiffArgs) -> Result<()> {
+    let progress = ProgressReporter::new(true);
+    init_logger(&args.log, &progress)?;
 
-    // Pre-sort genes for deterministic matching and performance
-    gtf_data
-        .genes_by_chrom
-        .values_mut()
-        .collect::<Vec<_>>()
-        .par_iter_mut()
-        .for_each(|genes| {
-            genes.sort_by(|a, b| a.start.cmp(&b.start).then(a.gene_id.cmp(&b.gene_id)));
-        });
-
-    // Validate batch_size
-    if args.batch_size == 0 {
-        bail!("Batch size must be greater than 0");
+    if args.tolerance.is_nan() || args.tolerance < 0.0 {
+        bail!("Tolerance must be a non-negative number");
     }
-
-    // Determine thread count
-    let num_threads = if args.threads == 0 {
-        num_cpus::get()
-    } else {
-        args.threads
+    let options = DiffOptions {
+        tolerance: args.tolerance,
+        ignore_order: args.ignore_order,
     };
+    let diff = diff_files(&args.a, &args.b, &options).with_context(|| {
+        format!(
+            "Failed to compare {} and {}",
+            args.a.display(),
+            args.b.display()
+        )
+    })?;
+    print!("{}", diff);
+
+    if !diff.is_identical() {
+        bail!("The tables differ");
+    }
+    Ok(())
+}
 
-    if num_threads == 1 {
-        // Use original sequential implementation
-        run_sequential(&args, &gtf_data, &config)?;
+/// Summarize an existing association table on stdout.
+fn run_summarize(args: &SummarizeArgs) -> Result<()> {
+    let progress = ProgressReporter::new(true);
+    init_logger(&args.log, &progress)?;
+
+    let file = File::open(&args.results)
+        .with_context(|| format!("Failed to open {}", args.results.display()))?;
+    let results = ResultsReader::new(create_buffered_reader(file, &args.results))
+        .with_context(|| format!("Failed to read {}", args.results.display()))?;
+    let summary = Summary::from_results(results)
+        .with_context(|| format!("Failed to read {}", args.results.display()))?;
+
+    let mut writer = BufWriter::new(io::stdout().lock());
+    if args.json {
+        summary.write_json(&mut writer)?;
     } else {
-        // Use parallel pipeline
-        run_parallel(&args, gtf_data, &config, num_threads)?;
+        summary.write_tsv(&mut writer)?;
     }
-
-    eprintln!("Done!");
+    writer.flush()?;
     Ok(())
 }
 
-/// Sequential implementation with streaming.
-fn run_sequential(args: &Args, gtf_data: &GtfData, config: &Config) -> Result<()> {
-    eprintln!("Processing BED file: {}", args.bed.display());
+/// Check the input files of a run and print what they hold.
+fn run_validate(args: &ValidateArgs) -> Result<()> {
+    let progress = ProgressReporter::new(true);
+    init_logger(&args.log, &progress)?;
 
-    // Initialize streaming reader
-    let mut bed_reader = BedReader::new(&args.bed)?;
+    let mut builder = ConfigBuilder::from_config(match &args.config {
+        Some(path) => Config::from_toml_file(path)?,
+        None => Config::default(),
+    });
+    if let Some(tag) = &args.gene_tag {
+        builder = builder.gene_id_tag(tag);
+    }
+    if let Some(tag) = &args.transcript_tag {
+        builder = builder.transcript_id_tag(tag);
+    }
+    let config = builder.build()?;
 
-    // Output writer
-    eprintln!("Writing output to: {}", args.output.display());
-    let file = File::create(&args.output).context("Failed to create output file")?;
-    let mut writer = BufWriter::new(file);
+    let sample_bytes = args.sample.map(|mb| mb.saturating_mul(1 << 20));
+    let report = validate(&args.gtf, &args.bed, &config, sample_bytes)?;
+    print!("{}", report);
 
-    let mut header_written = false;
+    if !report.is_valid() {
+        bail!("Validation failed");
+    }
+    Ok(())
+}
 
-    // Optimization state
-    let mut last_chrom = String::new();
-    let mut last_start = -1;
-    let mut last_index = 0;
-
-    // Process in chunks
-    while let Some(chunk) = bed_reader.read_chunk(args.batch_size)? {
-        if !header_written {
-            let num_meta = bed_reader.num_meta_columns();
-            write_header(&mut writer, num_meta)?;
-            header_written = true;
-        }
+/// Annotate the samples of a manifest against one parsed annotation, then
+/// write their combined summary.
+fn run_batch(args: &BatchArgs, matches: &ArgMatches) -> Result<()> {
+    let run_start = Instant::now();
+    let progress = ProgressReporter::new(args.log.quiet);
+    init_logger(&args.log, &progress)?;
+
+    let config = build_config(&args.options, matches)?;
+    if args.options.batch_size == 0 {
+        bail!("Batch size must be greater than 0");
+    }
+    let samples = read_manifest(&args.manifest)?;
+    if samples.is_empty() {
+        bail!("The manifest lists no samples: {}", args.manifest.display());
+    }
+    if !args.gtf.exists() {
+        bail!("GTF file not found: {}", args.gtf.display());
+    }
+
+    let num_threads = init_thread_pool(&config);
+    let gtf_data = load_gtf(&args.gtf, &args.options, &config, &progress)?;
+    progress.finish();
+    info!(
+        "Annotating {} samples, {} at a time",
+        samples.len(),
+        num_threads.min(samples.len())
+    );
 
-        for region in chunk {
-            // Find genes for chrom
-            if let Some(genes) = gtf_data.genes_by_chrom.get(&region.chrom) {
-                let max_len = *gtf_data.max_lengths.get(&region.chrom).unwrap_or(&0);
-
-                // Calculate safe search start (region start - max_len - distance)
-                // Note: we must match the logic in match_regions_to_genes regarding max_lookback
-                let max_lookback = max_len + config.max_lookback_distance();
-                let search_start = region.start.saturating_sub(max_lookback);
-
-                let start_index = if region.chrom == last_chrom && region.start >= last_start {
-                    // Optimistic: advance from last_index
-                    let mut idx = last_index;
-                    // Skip genes that end before search_start
-                    while idx < genes.len() && genes[idx].end < search_start {
-                        idx += 1;
-                    }
-                    idx
-                } else {
-                    // Reset / Binary search
-                    find_search_start_index(genes, search_start)
-                };
-
-                // Update cache
-                last_chrom = region.chrom.clone();
-                last_start = region.start;
-                last_index = start_index;
-
-                // Match
-                let candidates = match_region_to_genes(&region, genes, config, start_index);
-                let processed = process_candidates_for_output(candidates, config);
-
-                // Write line
-                for candidate in processed {
-                    let line = format_output_line(&region, &candidate);
-                    writeln!(writer, "{}", line)?;
+    // Samples run in parallel, each matched sequentially; statuses keep the
+    // manifest order
+    let failed = AtomicBool::new(false);
+    let statuses: Vec<SampleStatus> = samples
+        .par_iter()
+        .map(|sample| {
+            if args.fail_fast && failed.load(Ordering::Relaxed) {
+                return SampleStatus::Skipped;
+            }
+            match annotate_sample(sample, &gtf_data, &config, args) {
+                Ok(summary) => {
+                    info!(
+                        "Sample {}: {} regions, {} associated",
+                        sample.label, summary.total_regions, summary.associated_regions
+                    );
+                    SampleStatus::Done(Box::new(summary))
+                }
+                Err(e) => {
+                    failed.store(true, Ordering::Relaxed);
+                    error!("Sample {} failed: {:#}", sample.label, e);
+                    SampleStatus::Failed(format!("{:#}", e))
                 }
-            } else {
-                // If chromosome not in GTF, verify if we should reset cache?
-                // Probably yes to be safe, though chrom changed so next valid chrom will trigger binary search.
-                last_chrom = region.chrom.clone();
             }
-        }
-    }
+        })
+        .collect();
 
-    if !header_written {
-        // File was empty
-        write_header(&mut writer, 0)?;
+    let mut batch = BatchSummary::new();
+    for (sample, status) in samples.into_iter().zip(statuses) {
+        batch.add(sample, status);
     }
-
+    let file = File::create(&args.summary).context("Failed to create summary file")?;
+    let mut writer = BufWriter::new(file);
+    batch.write_tsv(&mut writer)?;
     writer.flush()?;
+    info!("Summary written to: {}", args.summary.display());
+
+    let num_samples = batch.samples().len();
+    info!(
+        "Samples: {} total, {} failed in {:.1}s",
+        num_samples,
+        batch.num_failed(),
+        run_start.elapsed().as_secs_f64()
+    );
+    if batch.num_failed() > 0 {
+        bail!("{} of {} samples failed", batch.num_failed(), num_samples);
+    }
+    info!("Done!");
     Ok(())
 }
 
-/// Work item for the parallel pipeline.
-struct WorkItem {
-    /// Sequence number for ordering (file order).
-    seq_id: u64,
-    /// Regions to process (all from same chromosome, in file order).
-    regions: Vec<Region>,
-}
+/// Annotate the regions of one sample of a batch.
+fn annotate_sample(
+    sample: &Sample,
+    gtf_data: &GtfData,
+    config: &Config,
+    args: &BatchArgs,
+) -> Result<Summary> {
+    let mut config = config.clone();
+    if !args.options.no_provenance {
+        config.provenance = Some(run_provenance(&args.gtf, &sample.bed, &args.options));
+    }
+    if !sample.bed.exists() {
+        bail!("BED file not found: {}", sample.bed.display());
+    }
 
-/// Result from processing a work item.
-struct WorkResult {
-    /// Sequence number matching the input WorkItem.
-    seq_id: u64,
-    /// Processing results in the same order as input regions.
-    results: Vec<(Region, Vec<Candidate>)>,
+    // Samples share the terminal, so none of them draws a progress bar
+    let progress = ProgressReporter::new(true);
+    let files = RunFiles {
+        bed: &sample.bed,
+        output: &sample.output,
+        unassigned: None,
+        batch_size: args.options.batch_size,
+    };
+    let mut summary = run_files(&files, gtf_data, &config, 1, &progress)?;
+    summary.transcript_filtering = gtf_data.transcript_filtering;
+    if let Some(report) = summary.missing_chromosomes.report() {
+        warn!("Sample {}: {}", sample.label, report);
+    }
+    summary.warn_clamped_percentages();
+    summary.missing_chromosomes.check(summary.total_regions)?;
+    Ok(summary)
 }
 
-/// Parallel implementation using per-chromosome work distribution.
-///
-/// To ensure byte-for-byte compatibility with sequential mode, we:
-/// 1. Parse the entire BED file and group regions by chromosome
-/// 2. Distribute chromosomes to workers (each chromosome is one work item)
-/// 3. Write results in sorted chromosome order
-fn run_parallel(args: &Args, gtf_data: GtfData, config: &Config, num_threads: usize) -> Result<()> {
-    eprintln!("Using parallel mode with {} threads", num_threads);
-
-    // Create performance metrics
-    let metrics = Arc::new(PerfMetrics::new());
-
-    // Create channels
-    let (work_tx, work_rx): (Sender<WorkItem>, Receiver<WorkItem>) = bounded(100);
-    // Increased buffer for results to avoid blocking workers
-    let (result_tx, result_rx): (Sender<WorkResult>, Receiver<WorkResult>) = bounded(2000);
-
-    // Shared GTF data for workers
-    let gtf_arc = Arc::new(gtf_data);
-    let config_arc = Arc::new(config.clone());
-
-    // Spawn writer thread
-    let output_path = args.output.clone();
-
-    let (header_tx, header_rx) = bounded(1);
-
-    let writer_handle = thread::spawn({
-        let result_rx = result_rx.clone();
-        let metrics = Arc::clone(&metrics);
-        move || -> Result<usize> {
-            write_results_ordered(&output_path, result_rx, header_rx, &metrics)
-        }
-    });
+/// Match the regions of the BED file to the genes of the GTF file.
+fn run_annotate(args: &Args, matches: &ArgMatches) -> Result<()> {
+    let run_start = Instant::now();
+    let progress = ProgressReporter::new(args.log.quiet);
+    init_logger(&args.log, &progress)?;
 
-    // Spawn worker threads using rayon's thread pool
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(num_threads)
-        .build()
-        .context("Failed to create thread pool")?;
-
-    // Clone references for the worker scope
-    let gtf_for_workers = Arc::clone(&gtf_arc);
-    let config_for_workers = Arc::clone(&config_arc);
-    let work_rx_for_workers = work_rx.clone();
-    let result_tx_for_workers = result_tx.clone();
-    let metrics_for_workers = Arc::clone(&metrics);
-
-    // Spawn workers in a separate thread to avoid blocking
-    let workers_handle = thread::spawn(move || {
-        pool.scope(|s| {
-            for _ in 0..num_threads {
-                let work_rx = work_rx_for_workers.clone();
-                let result_tx = result_tx_for_workers.clone();
-                let gtf = Arc::clone(&gtf_for_workers);
-                let cfg = Arc::clone(&config_for_workers);
-                let metrics = Arc::clone(&metrics_for_workers);
-
-                s.spawn(move |_| {
-                    worker_loop(work_rx, result_tx, gtf, cfg, &metrics);
-                });
-            }
-        });
-    });
+    let mut config = build_config(&args.options, matches)?;
+    if args.dump_config {
+        print!("{}", config.to_toml());
+        return Ok(());
+    }
 
-    // Producer: Read BED in chunks
-    eprintln!("Processing BED file: {}", args.bed.display());
-    let mut bed_reader = BedReader::new(&args.bed)?;
+    // Validate inputs
+    if !args.gtf().exists() {
+        bail!("GTF file not found: {}", args.gtf().display());
+    }
+    if !args.bed().exists() {
+        bail!("BED file not found: {}", args.bed().display());
+    }
 
-    let mut global_seq_id = 0;
+    // Record run parameters for the output header
+    if !args.options.no_provenance {
+        config.provenance = Some(run_provenance(args.gtf(), args.bed(), &args.options));
+    }
 
-    // Send header info immediately if possible? No, header depends on first line read usually.
-    // BedReader logic: read_chunk updates num_meta_columns.
-    // So we need to read first chunk.
+    // Size the thread pool before the GTF parser first uses it
+    let num_threads = init_thread_pool(&config);
 
-    while let Some(chunk) = bed_reader.read_chunk(args.batch_size)? {
-        if global_seq_id == 0 {
-            // Send header info
-            let _ = header_tx.send(bed_reader.num_meta_columns());
-        }
+    // Parse GTF file and sort its genes for matching
+    let gtf_start = Instant::now();
+    let gtf_data = load_gtf(args.gtf(), &args.options, &config, &progress)?;
+    let gtf_parse_secs = gtf_start.elapsed().as_secs_f64();
 
-        let work_item = WorkItem {
-            seq_id: global_seq_id,
-            regions: chunk,
-        };
+    // Validate batch_size
+    if args.options.batch_size == 0 {
+        bail!("Batch size must be greater than 0");
+    }
 
-        if work_tx.send(work_item).is_err() {
-            break;
+    let files = RunFiles {
+        bed: args.bed(),
+        output: args.output(),
+        unassigned: args.unassigned.as_deref(),
+        batch_size: args.options.batch_size,
+    };
+    let transcript_filtering = gtf_data.transcript_filtering;
+    let mut summary = run_files(&files, &gtf_data, &config, num_threads, &progress)?;
+    progress.finish();
+    summary.transcript_filtering = transcript_filtering;
+    summary.missing_chromosomes.warn();
+    summary.warn_clamped_percentages();
+    let timings = summary.timings.get_or_insert_with(Timings::new);
+    timings.gtf_parse_secs = gtf_parse_secs;
+    timings.total_secs = run_start.elapsed().as_secs_f64();
+
+    info!("Threads: {}", num_threads);
+    info!(
+        "Regions: {} total, {} associated, {} unassigned",
+        summary.total_regions, summary.associated_regions, summary.unassigned_regions
+    );
+    if config.always_closest {
+        info!(
+            "Regions assigned to their closest gene beyond the distance cutoff: {}",
+            summary.closest_fallback_regions
+        );
+    }
+    if !config.area_max_distance.is_empty() {
+        info!(
+            "Candidates beyond their area's distance limit: {}",
+            summary.distance_filtered_candidates
+        );
+    }
+
+    if let Some(unassigned_path) = &args.unassigned {
+        info!(
+            "Unassigned regions written to: {}",
+            unassigned_path.display()
+        );
+    }
+    if let Some(timings) = &summary.timings {
+        info!("Timings:");
+        for line in timings.to_string().lines() {
+            info!("  {}", line);
         }
-        global_seq_id += 1;
     }
 
-    // If loop finished and global_seq_id is 0, file was empty.
-    if global_seq_id == 0 {
-        let _ = header_tx.send(0);
+    if let Some(summary_path) = &args.summary {
+        write_summary_file(summary_path, &summary)?;
+        info!("Summary written to: {}", summary_path.display());
     }
 
-    // Close work channel to signal workers to exit
-    drop(work_tx);
-    drop(header_tx); // Close header channel too
+    // Every region on a missing chromosome is a naming mismatch, not a result
+    summary.missing_chromosomes.check(summary.total_regions)?;
 
-    // Wait for workers to finish
-    workers_handle
-        .join()
-        .map_err(|_| anyhow::anyhow!("Worker thread panicked"))?;
+    info!("Done!");
+    Ok(())
+}
 
-    // Close result channel to signal writer to finish
-    drop(result_tx);
+/// Run parameters of the output header, for a run on `gtf` and `bed`.
+fn run_provenance(gtf: &Path, bed: &Path, options: &AnnotateOptions) -> Provenance {
+    let command_line =
+        format_command_line(std::env::args_os().map(|arg| arg.to_string_lossy().into_owned()));
+    let mut provenance = Provenance::new(command_line);
+    provenance.add_input("gtf", gtf);
+    provenance.add_input("bed", bed);
+    if let Some(tss_bed) = &options.tss_bed {
+        provenance.add_input("tss_bed", tss_bed);
+    }
+    provenance
+}
 
-    // Wait for writer and get the results
-    let lines_written = writer_handle
-        .join()
-        .map_err(|_| anyhow::anyhow!("Writer thread panicked"))??;
+/// Parse the GTF file, refining its TSSs with `--tss-bed` when given.
+fn load_gtf(
+    gtf: &Path,
+    options: &AnnotateOptions,
+    config: &Config,
+    progress: &ProgressReporter,
+) -> Result<GtfData> {
+    progress.update(&Progress::new(Phase::ParsingGtf, 0, None));
+    let mut gtf_data = load_annotation(gtf, config)?;
+    if let Some(tss_bed) = &options.tss_bed {
+        let tss = parse_tss_bed(tss_bed)?;
+        let num_refined = gtf_data.refine_tss(&tss);
+        info!(
+            "Transcripts with a refined TSS: {} ({} positions read)",
+            num_refined,
+            tss.len()
+        );
+    }
+    let num_genes = gtf_data.num_genes() as u64;
+    progress.update(&Progress::new(
+        Phase::ParsingGtf,
+        num_genes,
+        Some(num_genes),
+    ));
+    Ok(gtf_data)
+}
 
-    eprintln!(
-        "Writing output to: {} ({} lines)",
-        args.output.display(),
-        lines_written
-    );
+/// Build the configuration: the `--config` file (or the defaults), overridden
+/// by the options given on the command line, then validated as a whole.
+fn build_config(options: &AnnotateOptions, matches: &ArgMatches) -> Result<Config> {
+    // Options left at their default value do not override the file
+    let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
 
-    // Print performance metrics
-    metrics.print_summary();
+    let base = match &options.config {
+        Some(path) => Config::from_toml_file(path)?,
+        None => Config::default(),
+    };
 
-    Ok(())
-}
+    // Matching parameters
+    let mut builder = ConfigBuilder::from_config(base);
+    if given("report") {
+        builder = builder.level(options.report);
+    }
+    if given("distance") {
+        builder = builder.distance_kb(options.distance);
+    }
+    if given("midpoint") {
+        builder = builder.assignment_mode(if options.midpoint {
+            AssignmentMode::Midpoint
+        } else {
+            AssignmentMode::Overlap
+        });
+    }
+    if given("distance_from") {
+        builder = builder.distance_anchor(options.distance_from);
+    }
+    if given("gene_body_definition") {
+        builder = builder.gene_body_definition(options.gene_body_definition);
+    }
+    if given("tss_sign") {
+        builder = builder.tss_distance_sign(options.tss_sign);
+    }
+    for &(area, bp) in &options.area_distance {
+        builder = builder.area_max_distance(area, bp);
+    }
+    if given("tss") {
+        builder = builder.tss_bp(options.tss);
+    }
+    if given("tss_down") {
+        builder = builder.tss_downstream_bp(options.tss_down);
+    }
+    if given("tts") {
+        builder = builder.tts_bp(options.tts);
+    }
+    if given("promoter") {
+        builder = builder.promoter_bp(options.promoter);
+    }
+    if given("exclusive_boundaries") {
+        builder = builder.boundary_inclusive(!options.exclusive_boundaries);
+    }
+    if given("perc_area") {
+        builder = builder.perc_area(options.perc_area);
+    }
+    if given("perc_region") {
+        builder = builder.perc_region(options.perc_region);
+    }
+    if given("min_overlap") {
+        builder = builder.min_overlap_bp(options.min_overlap);
+    }
+    if given("selection") {
+        builder = builder.selection(options.selection);
+    }
+    for &(area, weight) in &options.area_weight {
+        builder = builder.area_weight(area, weight);
+    }
+    if given("score_combination") {
+        builder = builder.score_combination(options.score_combination);
+    }
+    if given("tie_break") {
+        builder = builder.tie_break(options.tie_break);
+    }
+    if given("hard_thresholds") {
+        builder = builder.thresholds_are_hard_filters(options.hard_thresholds);
+    }
+    if given("rules") {
+        builder = builder.rules_str(&options.rules);
+    }
+    if given("gene_tag") {
+        builder = builder.gene_id_tag(&options.gene_tag);
+    }
+    if given("transcript_tag") {
+        builder = builder.transcript_id_tag(&options.transcript_tag);
+    }
+    if given("only_tagged") {
+        let tags: Vec<&str> = options.only_tagged.iter().map(String::as_str).collect();
+        builder = builder.only_tagged(&tags);
+    }
+    if let Some(max) = options.max_tsl {
+        builder = builder.max_tsl(max);
+    }
+    if given("allow_negative_coordinates") {
+        builder = builder.allow_negative_coordinates(options.allow_negative_coordinates);
+    }
+    if options.strict {
+        builder = builder
+            .invalid_regions(InvalidRegions::Error)
+            .strict_transcript_ids(true);
+    } else if options.drop_invalid {
+        builder = builder.invalid_regions(InvalidRegions::Drop);
+    }
+    if given("keep_overlapping_exons") {
+        builder = builder.merge_overlapping_exons(!options.keep_overlapping_exons);
+    }
+    if given("strandedness") {
+        builder = builder.strandedness(options.strandedness);
+    }
+    if given("strict_strandedness") {
+        builder = builder.strict_strandedness(options.strict_strandedness);
+    }
+    if given("closest") {
+        builder = builder.always_closest(options.closest);
+    }
+    if given("closest_by") {
+        builder = builder.closest_by(options.closest_by);
+    }
+    if given("n_closest") {
+        builder = builder.n_closest(options.n_closest);
+    }
+    if given("closest_only") {
+        builder = builder.closest_only(options.closest_only);
+    }
+    if given("no_rules") {
+        builder = builder.report_all_candidates(options.no_rules);
+    }
+    if given("span_multiple_genes") {
+        builder = builder.span_multiple_genes(options.span_multiple_genes);
+    }
+    if given("gene_tss") {
+        builder = builder.gene_tss_mode(options.gene_tss);
+    }
+    if given("bidirectional_window") {
+        builder = builder.bidirectional_window(options.bidirectional_window);
+    }
+    if given("report_intergenic") {
+        builder = builder.report_intergenic(options.report_intergenic);
+    }
+    if given("distinguish_first_intron") {
+        builder = builder.distinguish_first_intron(options.distinguish_first_intron);
+    }
+    if given("contained_genes") {
+        builder = builder.contained_gene_behavior(options.contained_genes);
+    }
+    if given("unstranded_genes") {
+        builder = builder.unstranded_genes(options.unstranded_genes);
+    }
+    if given("label_introns") {
+        builder = builder.label_introns(options.label_introns);
+    }
+    if given("report_utr") {
+        builder = builder.report_utr(options.report_utr);
+    }
+    if given("threads") {
+        builder = builder.threads(options.threads);
+    }
+    let mut config = builder.build()?;
 
-/// Worker loop: receives work items and sends results.
-fn worker_loop(
-    work_rx: Receiver<WorkItem>,
-    result_tx: Sender<WorkResult>,
-    gtf: Arc<GtfData>,
-    config: Arc<Config>,
-    metrics: &PerfMetrics,
-) {
-    // Optimization state per worker
-    let mut last_chrom = String::new();
-    let mut last_start = -1;
-    let mut last_index = 0;
-
-    while let Ok(work_item) = work_rx.recv() {
-        let num_regions = work_item.regions.len() as u64;
-
-        // Time the matching work
-        let match_start = Instant::now();
-        let results = process_work_item(
-            &work_item,
-            &gtf,
-            &config,
-            &mut last_chrom,
-            &mut last_start,
-            &mut last_index,
-        );
-        let match_elapsed = match_start.elapsed();
-        metrics.add_worker_matching(match_elapsed.as_nanos() as u64);
-        metrics.add_regions_processed(num_regions);
-
-        let work_result = WorkResult {
-            seq_id: work_item.seq_id,
-            results,
-        };
-
-        // Time the channel send (how long we wait if channel is full)
-        let send_start = Instant::now();
-        let send_result = result_tx.send(work_result);
-        let send_elapsed = send_start.elapsed();
-        metrics.add_worker_channel_wait(send_elapsed.as_nanos() as u64);
-
-        if send_result.is_err() {
-            break;
+    // Output options
+    if given("output_format") {
+        config.output_format = options.output_format.parse().context(
+            "Output format can only be one of the following: tsv, bed, parquet or closest",
+        )?;
+    }
+    if given("best_only") {
+        config.best_only = options.best_only;
+    }
+    if given("keep_unassigned") {
+        config.keep_unassigned = options.keep_unassigned;
+    }
+    if given("wide") {
+        config.wide = options.wide;
+    }
+    if given("precision") {
+        config.output_precision = options.precision;
+    }
+    if given("na_string") {
+        config.na_string = options.na_string.clone();
+    }
+    if given("legacy_sentinel") {
+        config.legacy_sentinel = options.legacy_sentinel;
+    }
+    if given("merge_sep") {
+        config.merge_separator = options.merge_sep.clone();
+    }
+    if given("legacy_merge") {
+        config.legacy_merge = options.legacy_merge;
+    }
+    if given("gene_style") {
+        config.gene_level_style = options.gene_style;
+    }
+    if given("report_coords") {
+        config.output_coordinate_convention = options
+            .report_coords
+            .parse()
+            .context("Report coordinates can only be one of the following: bed0 or one-based")?;
+    }
+    if let Some(extra_columns) = &options.extra_columns {
+        if !config.parse_extra_columns(extra_columns) {
+            let names: Vec<&str> = ExtraColumn::ALL.iter().map(|c| c.as_str()).collect();
+            bail!(
+                "Extra columns can only be the following: {}",
+                names.join(", ")
+            );
+        }
+    }
+    if given("tss_bins") && !config.parse_tss_bins(&options.tss_bins) {
+        bail!("--tss-bins must be positive, strictly increasing bp values");
+    }
+    if let Some(columns) = &options.columns {
+        let columns = columns
+            .split(',')
+            .map(str::parse)
+            .collect::<Result<Vec<OutputColumn>, _>>()
+            .context("Invalid --columns")?;
+        config.columns = Some(columns);
+    }
+
+    // Checks on the combined output options
+    if config.output_format == OutputFormat::Parquet && !cfg!(feature = "parquet") {
+        bail!("Parquet output requires rgmatch built with the `parquet` feature");
+    }
+    if config.output_format == OutputFormat::Closest && config.level != ReportLevel::Gene {
+        bail!("--output-format closest requires -r gene");
+    }
+    if config.n_closest > 0 && config.level != ReportLevel::Gene {
+        bail!("--n-closest requires -r gene");
+    }
+    if config.closest_only && config.n_closest == 0 {
+        bail!("--closest-only requires --n-closest");
+    }
+    if config.span_multiple_genes == SpanMultipleGenes::ReportAllOverlapped {
+        if config.level != ReportLevel::Gene {
+            bail!("--span-multiple-genes report-all-overlapped requires -r gene");
+        }
+        if config.report_all_candidates {
+            bail!("--span-multiple-genes report-all-overlapped cannot be combined with --no-rules");
         }
     }
+    if config.report_all_candidates && config.best_only {
+        bail!("--no-rules cannot be combined with --best-only");
+    }
+    if config.wide && config.output_format == OutputFormat::Bed {
+        bail!("--wide is not supported with BED output");
+    }
+    if config.wide && config.output_format == OutputFormat::Closest {
+        bail!("--wide is not supported with closest output");
+    }
+    if config.columns.is_some() && config.output_format != OutputFormat::Tsv {
+        bail!("--columns is only supported with TSV output");
+    }
+    if config.merge_separator.is_empty() {
+        bail!("--merge-sep must not be empty");
+    }
+    if !Config::valid_tss_bins(&config.tss_bins) {
+        bail!("--tss-bins must be positive, strictly increasing bp values");
+    }
+
+    Ok(config)
 }
 
-/// Process a single work item (a chunk of regions).
-fn process_work_item(
-    work_item: &WorkItem,
-    gtf: &GtfData,
-    config: &Config,
-    last_chrom: &mut String,
-    last_start: &mut i64,
-    last_index: &mut usize,
-) -> Vec<(Region, Vec<Candidate>)> {
-    let mut results = Vec::with_capacity(work_item.regions.len());
-
-    for region in &work_item.regions {
-        if let Some(genes) = gtf.genes_by_chrom.get(&region.chrom) {
-            let max_len = *gtf.max_lengths.get(&region.chrom).unwrap_or(&0);
-
-            let max_lookback = max_len + config.max_lookback_distance();
-            let search_start = region.start.saturating_sub(max_lookback);
-
-            let start_index = if *last_chrom == region.chrom && region.start >= *last_start {
-                let mut idx = *last_index;
-                while idx < genes.len() && genes[idx].end < search_start {
-                    idx += 1;
-                }
-                idx
-            } else {
-                find_search_start_index(genes, search_start)
-            };
+/// Parse an `--area-distance` value: an area and its maximum distance in bp.
+fn parse_area_distance(value: &str) -> std::result::Result<(Area, i64), String> {
+    let (area, bp) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected AREA=BP, got '{}'", value))?;
+    let area = area.parse::<Area>().map_err(|e| e.to_string())?;
+    let bp = bp
+        .parse()
+        .map_err(|_| format!("distance '{}' is not an integer", bp))?;
+    Ok((area, bp))
+}
+
+/// Parse an `--area-weight` value, `AREA=W`.
+fn parse_area_weight(value: &str) -> std::result::Result<(Area, f64), String> {
+    let (area, weight) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected AREA=W, got '{}'", value))?;
+    let area = area.parse::<Area>().map_err(|e| e.to_string())?;
+    let weight = weight
+        .parse()
+        .map_err(|_| format!("weight '{}' is not a number", weight))?;
+    Ok((area, weight))
+}
 
-            *last_chrom = region.chrom.clone();
-            *last_start = region.start;
-            *last_index = start_index;
+/// Write summary statistics as JSON (for `.json` paths) or TSV.
+fn write_summary_file(path: &Path, summary: &Summary) -> Result<()> {
+    let file = File::create(path).context("Failed to create summary file")?;
+    let mut writer = BufWriter::new(file);
 
-            let candidates = match_region_to_genes(region, genes, config, start_index);
-            let processed = process_candidates_for_output(candidates, config);
-            results.push((region.clone(), processed));
-        } else {
-            // Chromosome not found, but we must record it in output as processed (with empty candidates)
-            // wait, match_region_to_genes returns Vec<Candidate>.
-            // If no genes, results is empty.
-            // But original code: "If the current gene also covers the region..."
-            // If no genes for chrom, we skip?
-            // run_sequential (original) printd "Warning: {} not found" and skipped.
-            // We should maintain parity.
-            // If skipping, we don't push to results?
-            // But we need to maintain order?
-            // Actually, if a region has no matches, it produces no output lines.
-            // So skipping here is fine.
-            *last_chrom = region.chrom.clone();
+    if path.extension().is_some_and(|ext| ext == "json") {
+        summary.write_json(&mut writer)?;
+    } else {
+        summary.write_tsv(&mut writer)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Open the optional BED file receiving unassigned regions.
+fn create_unassigned_writer(path: Option<&Path>) -> Result<Option<BufWriter<File>>> {
+    path.map(|p| {
+        File::create(p)
+            .map(BufWriter::new)
+            .context("Failed to create unassigned regions file")
+    })
+    .transpose()
+}
+
+/// Write the output rows of one region and, if it has no associations or
+/// only an intergenic one, record it in the unassigned file.
+fn write_region<W: Write + Send>(
+    sink: &mut OutputSink<W>,
+    unassigned_writer: Option<&mut BufWriter<File>>,
+    association: &Association,
+    to_write: &[Candidate],
+    config: &Config,
+) -> rgmatch::Result<usize> {
+    if to_write.iter().all(Candidate::is_intergenic) {
+        if let Some(unassigned_writer) = unassigned_writer {
+            writeln!(
+                unassigned_writer,
+                "{}",
+                format_region_bed_line(&association.region)
+            )?;
         }
     }
 
-    results
+    sink.write_region(association, to_write, config)
 }
 
-/// Write results in order, buffering out-of-order results.
-fn write_results_ordered(
-    output_path: &PathBuf,
-    result_rx: Receiver<WorkResult>,
-    header_rx: Receiver<usize>,
-    metrics: &PerfMetrics,
-) -> Result<usize> {
-    let file = File::create(output_path).context("Failed to create output file")?;
-    let mut writer = BufWriter::new(file);
+/// Files read and written by one annotation run.
+struct RunFiles<'a> {
+    bed: &'a Path,
+    /// Output file, or "-" for stdout.
+    output: &'a Path,
+    /// File of the regions without any association, as BED.
+    unassigned: Option<&'a Path>,
+    /// Regions read from the BED file at a time.
+    batch_size: usize,
+}
 
-    // Get header info (blocking until first chunk read or empty file)
-    let num_meta_columns = header_rx.recv().unwrap_or(0);
-    write_header(&mut writer, num_meta_columns)?;
-
-    // Buffer for out-of-order results using VecDeque for O(1) operations
-    // Since seq_id is dense sequential integers starting from 0, we use
-    // index = seq_id - next_expected to map into the deque
-    let mut pending: VecDeque<Option<WorkResult>> = VecDeque::new();
-    let mut next_expected: u64 = 0;
-    let mut lines_written: usize = 0;
-
-    for result in result_rx {
-        // Insert at the correct position based on seq_id offset
-        let index = (result.seq_id - next_expected) as usize;
-        // Ensure VecDeque is large enough
-        while pending.len() <= index {
-            pending.push_back(None);
+/// Match the regions of the BED file and write their rows.
+///
+/// Regions go through [`rgmatch::pipeline::annotate_chunks`], the loop of
+/// [`rgmatch::annotate`], on `num_threads` workers. Unlike `annotate`, which
+/// keeps every association in memory, the rows of each chunk are written as
+/// soon as it is matched.
+fn run_files(
+    files: &RunFiles,
+    gtf_data: &GtfData,
+    config: &Config,
+    num_threads: usize,
+    progress: &ProgressReporter,
+) -> Result<Summary> {
+    if num_threads > 1 {
+        info!("Using parallel mode with {} threads", num_threads);
+    }
+    info!("Processing BED file: {}", files.bed.display());
+    let mut bed_reader = BedReader::new(files.bed)?.with_config(config);
+
+    info!("Writing output to: {}", output_display(files.output));
+    let mut sink = OutputSink::new(create_output_writer(files.output)?, config)?;
+    let mut unassigned_writer = create_unassigned_writer(files.unassigned)?;
+
+    // The header has the metadata columns of the first chunk
+    let num_meta = Cell::new(None);
+    let mut bed_parse_secs = 0.0;
+    let chunks = std::iter::from_fn(|| {
+        let read_start = Instant::now();
+        let chunk = bed_reader.read_chunk(files.batch_size).transpose();
+        bed_parse_secs += read_start.elapsed().as_secs_f64();
+        if chunk.is_some() && num_meta.get().is_none() {
+            num_meta.set(Some(bed_reader.header_meta_columns()));
         }
-        pending[index] = Some(result);
-
-        // Track max pending size for congestion analysis
-        metrics.update_max_pending(pending.len());
-
-        // Write all ready consecutive results from the front
-        while matches!(pending.front(), Some(Some(_))) {
-            let r = pending.pop_front().unwrap().unwrap();
-            for (region, candidates) in &r.results {
-                for candidate in candidates {
-                    // Time formatting
-                    let format_start = Instant::now();
-                    let line = format_output_line(region, candidate);
-                    let format_elapsed = format_start.elapsed();
-                    metrics.add_writer_format(format_elapsed.as_nanos() as u64);
-
-                    // Time I/O
-                    let io_start = Instant::now();
-                    writeln!(writer, "{}", line)?;
-                    let io_elapsed = io_start.elapsed();
-                    metrics.add_writer_io(io_elapsed.as_nanos() as u64);
-
-                    lines_written += 1;
-                }
+        chunk
+    });
+
+    let mut header_written = false;
+    let mut write_secs = 0.0;
+    let mut region_progress = RegionProgress::new(progress);
+    let mut summary = annotate_chunks(
+        gtf_data,
+        chunks,
+        config,
+        Some(num_threads),
+        None,
+        &mut |associations| {
+            let write_start = Instant::now();
+            if !header_written {
+                sink.write_header(num_meta.get().unwrap_or(0), config)?;
+                header_written = true;
             }
-            next_expected += 1;
-        }
+            for association in &associations {
+                let to_write = candidates_to_write(&association.candidates, config);
+                write_region(
+                    &mut sink,
+                    unassigned_writer.as_mut(),
+                    association,
+                    &to_write,
+                    config,
+                )?;
+                region_progress.add(&association.region, association.candidates.len());
+            }
+            write_secs += write_start.elapsed().as_secs_f64();
+            Ok(())
+        },
+        &mut |update| progress.update(&update),
+    )?;
+    bed_reader.warn_irregular_lines();
+
+    if !header_written {
+        // File was empty
+        sink.write_header(0, config)?;
     }
 
-    metrics.add_lines_written(lines_written as u64);
-    writer.flush()?;
-    Ok(lines_written)
+    region_progress.writing();
+    let write_start = Instant::now();
+    sink.finish()?;
+    if let Some(unassigned_writer) = unassigned_writer.as_mut() {
+        unassigned_writer.flush()?;
+    }
+    write_secs += write_start.elapsed().as_secs_f64();
+    let timings = summary.timings.get_or_insert_with(Timings::new);
+    timings.bed_parse_secs = bed_parse_secs;
+    timings.write_secs = write_secs;
+    Ok(summary)
 }
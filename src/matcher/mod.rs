@@ -5,7 +5,78 @@ pub mod rules;
 pub mod tss;
 pub mod tts;
 
-pub use overlap::{match_region_to_genes, match_regions_to_genes, process_candidates_for_output};
-pub use rules::{apply_rules, select_transcript};
-pub use tss::check_tss;
-pub use tts::check_tts;
+#[allow(deprecated)]
+pub use overlap::match_regions_to_genes;
+pub use overlap::{
+    associate_regions, associate_regions_par, classify_position, match_region,
+    match_region_indexed, match_region_to_genes, process_candidates_for_output, RegionMatcher,
+    RegionMatches,
+};
+pub use rules::{
+    apply_rules, apply_rules_grouped, apply_rules_with, apply_scores, apply_scores_grouped,
+    group_positions, select_best_candidate, select_best_transcript, select_best_transcript_grouped,
+    select_transcript, select_transcript_by_score, select_transcript_grouped,
+    select_transcript_with, GroupKey,
+};
+pub use tss::{check_tss, check_tss_window, check_tss_with_boundary};
+pub use tts::{check_tts, check_tts_with_boundary};
+
+use crate::types::Area;
+
+/// One area overlapped by a region, as reported by [`check_tss`] and
+/// [`check_tts`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AreaOverlap {
+    pub area: Area,
+    /// Percentage of the region inside the area.
+    pub pctg_region: f64,
+    /// Percentage of the area covered by the region, `None` for the
+    /// unbounded UPSTREAM and DOWNSTREAM areas.
+    pub pctg_area: Option<f64>,
+}
+
+impl AreaOverlap {
+    /// Create a new area overlap.
+    pub fn new(area: Area, pctg_region: f64, pctg_area: Option<f64>) -> Self {
+        AreaOverlap {
+            area,
+            pctg_region,
+            pctg_area,
+        }
+    }
+}
+
+/// Reflection of `pos` around `axis`, saturating instead of overflowing.
+fn mirror(pos: i64, axis: i64) -> i64 {
+    axis.saturating_mul(2).saturating_sub(pos)
+}
+
+/// Percentage of an area `width` bp long covered by `overlap` bp, or `None`
+/// (reported as NA) for a zero-width area.
+///
+/// A region placed in an area by its midpoint distance counts whole even
+/// when it is longer than the area; it then covers all of it, not more.
+fn area_percentage(overlap: f64, width: f64) -> Option<f64> {
+    (width > 0.0).then(|| overlap.min(width) / width * 100.0)
+}
+
+/// Whether a region `distance` bp from the anchor of an area `width` bp wide
+/// lies inside it. The base exactly `width` bp away is inside when
+/// `inclusive`, and belongs to the next area out otherwise.
+fn in_window(distance: i64, width: f64, inclusive: bool) -> bool {
+    if inclusive {
+        distance as f64 <= width
+    } else {
+        (distance as f64) < width
+    }
+}
+
+/// Number of bases [`in_window`] counts in an area `width` bp wide: the
+/// distances run from 1 to `width`, or to `width - 1` when exclusive.
+fn window_bases(width: f64, inclusive: bool) -> f64 {
+    if inclusive {
+        width
+    } else {
+        (width - 1.0).max(0.0)
+    }
+}
@@ -3,14 +3,26 @@
 //! This module implements the main matching loop that associates genomic regions
 //! with gene annotations based on positional overlap and proximity.
 
-use ahash::AHashMap;
+use std::borrow::Cow;
+use std::cell::OnceCell;
+
+use ahash::{AHashMap, AHashSet};
 use indexmap::IndexMap;
 
 use crate::config::Config;
-use crate::matcher::rules::{apply_rules, select_transcript};
-use crate::matcher::tss::{check_tss, TssExonInfo};
-use crate::matcher::tts::{check_tts, TtsExonInfo};
-use crate::types::{Area, Candidate, Gene, Region, ReportLevel, Strand};
+use crate::matcher::rules::{
+    apply_rules_grouped, apply_scores_grouped, group_positions, select_best_candidate,
+    select_best_transcript_grouped, select_transcript_grouped,
+};
+use crate::matcher::tss::{check_tss_with_boundary, TssExonInfo};
+use crate::matcher::tts::{check_tts_with_boundary, TtsExonInfo};
+use crate::matcher::{window_bases, AreaOverlap};
+use crate::source::{first_gene_ending_after, running_max_ends, AnnotationSource};
+use crate::types::{
+    Area, AssignmentMode, Association, Candidate, ClosestBy, DistanceAnchor, ExonIndex, ExonNumber,
+    ExonNumbers, Gene, GeneBodyDefinition, GeneLevelStyle, GeneTssMode, Name, Region, ReportLevel,
+    SelectionMode, SenseClass, Strand, Strandedness, Transcript, TssDistanceSign, UnstrandedGenes,
+};
 
 /// Calculate the intron number based on exon index and strand.
 ///
@@ -23,55 +35,395 @@ fn calculate_intron_number(index: usize, total_exons: usize, strand: Strand) ->
     }
 }
 
+/// Exon/Intron value of an overlap of intron `number`.
+fn intron_label(number: usize, config: &Config) -> ExonNumber {
+    if config.label_introns {
+        ExonNumber::Intron(number as u32)
+    } else {
+        ExonNumber::Number(number as u32)
+    }
+}
+
+/// The `GENE_CONTAINED` candidate of a transcript within `region`.
+///
+/// PercRegion is the share of the region covered by the transcript span,
+/// and the Exon/Intron column lists all its exons.
+fn contained_candidate(region: &Region, gene: &Gene, transcript: &Transcript) -> Candidate {
+    let pm = region.midpoint();
+    let exons = &transcript.exons;
+    let (tss_distance, tts_distance) = match gene.strand {
+        Strand::Positive => (pm - exons[0].start, pm - exons.last().unwrap().end),
+        Strand::Negative => (exons.last().unwrap().end - pm, exons[0].start - pm),
+    };
+    let mut numbers: Vec<ExonNumber> = exons
+        .iter()
+        .filter_map(|exon| exon.exon_number.map(ExonNumber::Number))
+        .collect();
+    if gene.strand == Strand::Negative {
+        numbers.reverse();
+    }
+    let span = transcript.end - transcript.start + 1;
+
+    Candidate::new(
+        transcript.start,
+        transcript.end,
+        gene.strand,
+        numbers,
+        Area::GeneContained,
+        transcript.transcript_id.clone(),
+        gene.gene_id.clone(),
+        0,
+        (span as f64 / region.length() as f64) * 100.0,
+        100.0,
+        tss_distance,
+    )
+    .with_tts_distance(tts_distance)
+    .with_gene_span(gene.start, gene.end)
+}
+
+/// Copy of `candidate` for one area reported by `check_tss` or `check_tts`.
+fn overlap_candidate(candidate: &Candidate, overlap: AreaOverlap) -> Candidate {
+    Candidate {
+        area: overlap.area,
+        pctg_region: overlap.pctg_region,
+        pctg_area: overlap.pctg_area.unwrap_or(-1.0),
+        ..candidate.clone()
+    }
+}
+
+/// Split the overlap of `candidate`, a `1st_EXON` candidate, at the end of
+/// the TSS region extended `config.tss_downstream` bp into the exon.
+///
+/// The bases within the extension become a `TSS` candidate, unless the
+/// region also reaches upstream of the exon, in which case
+/// `check_tss_window` has already counted them. The rest stays `1st_EXON`,
+/// with the exon outside the extension as its feature.
+fn split_tss_window(candidate: &Candidate, region: &Region, config: &Config) -> Vec<Candidate> {
+    let downstream = (config.tss_downstream as i64).clamp(0, candidate.end - candidate.start + 1);
+    // The TSS is at the exon start on the + strand and at its end on the - strand
+    let (window, rest, upstream) = match candidate.strand {
+        Strand::Positive => (
+            (candidate.start, candidate.start + downstream - 1),
+            (candidate.start + downstream, candidate.end),
+            region.start < candidate.start,
+        ),
+        Strand::Negative => (
+            (candidate.end - downstream + 1, candidate.end),
+            (candidate.start, candidate.end - downstream),
+            region.end > candidate.end,
+        ),
+    };
+    let overlap = |(from, to): (i64, i64)| region.end.min(to) - region.start.max(from) + 1;
+
+    let region_length = region.length() as f64;
+    let mut pieces = Vec::with_capacity(2);
+    let in_window = overlap(window);
+    if in_window > 0 && !upstream {
+        let tss_window = window_bases(config.tss, config.boundary_inclusive) + downstream as f64;
+        pieces.push(Candidate {
+            area: Area::Tss,
+            pctg_region: (in_window as f64 / region_length) * 100.0,
+            pctg_area: (in_window as f64 / tss_window) * 100.0,
+            ..candidate.clone()
+        });
+    }
+    let in_rest = overlap(rest);
+    if in_rest > 0 {
+        pieces.push(Candidate {
+            start: rest.0,
+            end: rest.1,
+            pctg_region: (in_rest as f64 / region_length) * 100.0,
+            pctg_area: (in_rest as f64 / (rest.1 - rest.0 + 1) as f64) * 100.0,
+            ..candidate.clone()
+        });
+    }
+    pieces
+}
+
+/// Split the exon overlap of `candidate`, a `1st_EXON` or `GENE_BODY`
+/// candidate of a transcript coding from `cds.0` to `cds.1`, into its 5' UTR,
+/// coding and 3' UTR parts.
+///
+/// Each part becomes a `(candidate, area_length, overlapped_area)` entry
+/// whose feature is the part of the exon in that area: `UTR5` or `UTR3` for
+/// the UTRs, the area of `candidate` for the coding part. A UTR area left
+/// out of the rules counts as coding. `None` if the overlap lies within one
+/// area, so the candidate stays as it is.
+fn split_at_cds(
+    candidate: &Candidate,
+    region: &Region,
+    cds: (i64, i64),
+    config: &Config,
+) -> Option<Vec<(Candidate, i64, i64)>> {
+    // The 5' UTR lies left of the CDS on the + strand and right of it on the - strand
+    let (left_area, right_area) = match candidate.strand {
+        Strand::Positive => (Area::Utr5, Area::Utr3),
+        Strand::Negative => (Area::Utr3, Area::Utr5),
+    };
+    let coding_start = if config.reports_utr(left_area) {
+        cds.0
+    } else {
+        i64::MIN
+    };
+    let coding_end = if config.reports_utr(right_area) {
+        cds.1
+    } else {
+        i64::MAX
+    };
+
+    let overlap_start = region.start.max(candidate.start);
+    let overlap_end = region.end.min(candidate.end);
+    if coding_start <= overlap_start && overlap_end <= coding_end {
+        return None;
+    }
+
+    let parts = [
+        (left_area, i64::MIN, coding_start.saturating_sub(1)),
+        (candidate.area, coding_start, coding_end),
+        (right_area, coding_end.saturating_add(1), i64::MAX),
+    ];
+    let region_length = region.length();
+    let pieces = parts
+        .into_iter()
+        .filter_map(|(area, from, to)| {
+            let part_start = candidate.start.max(from);
+            let part_end = candidate.end.min(to);
+            let piece_start = overlap_start.max(part_start);
+            let piece_end = overlap_end.min(part_end);
+            if piece_start > piece_end {
+                return None;
+            }
+            let piece = piece_end - piece_start + 1;
+            let part_length = part_end - part_start + 1;
+            let split = Candidate {
+                start: part_start,
+                end: part_end,
+                area,
+                pctg_region: (piece as f64 / region_length as f64) * 100.0,
+                pctg_area: (piece as f64 / part_length as f64) * 100.0,
+                ..candidate.clone()
+            };
+            Some((split, part_length, piece))
+        })
+        .collect();
+    Some(pieces)
+}
+
 /// Aggregate overlapping entries (gene body or intron) into a single candidate per transcript.
 ///
 /// Takes a map of entries grouped by transcript key and combines overlapping regions
-/// into single candidates with aggregated statistics.
+/// into single candidates with aggregated statistics, listing the numbers of
+/// all of them. The percentage of area is over the summed area lengths, or
+/// over the length in `area_lengths` for the key.
 fn aggregate_entries(
     entries_map: IndexMap<String, Vec<(Candidate, i64, i64)>>,
     region_length: i64,
+    area_lengths: &AHashMap<String, i64>,
 ) -> Vec<Candidate> {
     let mut results = Vec::new();
 
-    for (_, entries) in entries_map {
-        if entries.len() == 1 {
+    for (key, entries) in entries_map {
+        let area_length = area_lengths.get(&key).copied();
+        if entries.len() == 1 && area_length.is_none() {
             results.push(entries[0].0.clone());
         } else {
             let mut total_area = 0i64;
             let mut total_overlap = 0i64;
-            let mut combined_numbers = String::new();
+            let mut numbers: Vec<ExonNumber> = Vec::with_capacity(entries.len());
 
             for (candidate, area_len, overlap) in &entries {
                 total_area += area_len;
                 total_overlap += overlap;
-                combined_numbers.push_str(&candidate.exon_number);
-                combined_numbers.push(',');
+                numbers.extend(&candidate.exon_number);
             }
-            combined_numbers.pop(); // Remove trailing comma
+            let total_area = area_length.unwrap_or(total_area);
 
             let ref_candidate = &entries[0].0;
             let pctg_region = (total_overlap as f64 / region_length as f64) * 100.0;
             let pctg_area = (total_overlap as f64 / total_area as f64) * 100.0;
 
-            results.push(Candidate::new(
-                ref_candidate.start,
-                ref_candidate.end,
-                ref_candidate.strand,
-                combined_numbers,
-                ref_candidate.area,
-                ref_candidate.transcript.clone(),
-                ref_candidate.gene.clone(),
-                ref_candidate.distance,
+            results.push(Candidate {
+                exon_number: numbers.into(),
                 pctg_region,
                 pctg_area,
-                ref_candidate.tss_distance,
-            ));
+                ..ref_candidate.clone()
+            });
         }
     }
 
     results
 }
 
+/// Whether `candidate` is farther than the `config.area_max_distance` limit
+/// of its area.
+fn beyond_area_distance(candidate: &Candidate, config: &Config) -> bool {
+    config
+        .area_max_distance
+        .get(&candidate.area)
+        .is_some_and(|&max| candidate.distance.abs() > max)
+}
+
+/// Whether `gene` passes the strand test of `config.strandedness` for `region`.
+fn strand_accepts(region: &Region, gene: &Gene, config: &Config) -> bool {
+    if gene.unstranded && config.unstranded_genes == UnstrandedGenes::Skip {
+        return false;
+    }
+    match (config.strandedness, region.strand) {
+        (Strandedness::Ignore, _) => true,
+        (_, None) => !config.strict_strandedness,
+        (strandedness, Some(strand)) => strandedness.accepts(strand, gene.strand),
+    }
+}
+
+/// Where the nearest-gene search may stop on the left: the index of the
+/// first gene of the chromosome that may end at or after a position, such as
+/// [`AnnotationSource::first_gene_reaching`]. No gene before it does.
+type FirstReaching<'f> = &'f dyn Fn(i64) -> usize;
+
+/// The `n` genes of the chromosome's `genes` nearest to `region` by `by`,
+/// with their distances, nearest first; ties go to the lowest gene ID.
+///
+/// Genes on a strand rejected by `config.strandedness` are skipped. The
+/// search starts at the genes around the region, found by binary search on
+/// their starts, and walks outward until no farther gene can be nearer: on
+/// the right, genes start farther and farther away; on the left, it stops at
+/// `first_reaching` of the farthest distance kept.
+fn nearest_genes<'g>(
+    region: &Region,
+    genes: &'g [Gene],
+    first_reaching: FirstReaching,
+    by: ClosestBy,
+    n: usize,
+    config: &Config,
+) -> Vec<(i64, &'g Gene)> {
+    if n == 0 {
+        return Vec::new();
+    }
+    // A gene ending before `left` is at least `left` minus its end away, one
+    // starting after `right` at least its start minus `right`
+    let (left, right) = match by {
+        ClosestBy::Edge => (region.start, region.end),
+        ClosestBy::Tss => (region.midpoint(), region.midpoint()),
+    };
+    let split = genes.partition_point(|g| g.start <= right);
+
+    let mut nearest: Vec<(i64, &Gene)> = Vec::with_capacity(n + 1);
+    let keep = |gene: &'g Gene, nearest: &mut Vec<(i64, &'g Gene)>| {
+        if !strand_accepts(region, gene, config) {
+            return false;
+        }
+        let distance = by.distance(region, gene);
+        let index = nearest.partition_point(|&(d, g)| (d, &g.gene_id) < (distance, &gene.gene_id));
+        if index == n {
+            return false;
+        }
+        nearest.insert(index, (distance, gene));
+        nearest.truncate(n);
+        true
+    };
+
+    let mut floor = 0;
+    let mut index = split;
+    while index > floor {
+        index -= 1;
+        if keep(&genes[index], &mut nearest) && nearest.len() == n {
+            floor = first_reaching(left.saturating_sub(nearest[n - 1].0));
+        }
+    }
+    for gene in &genes[split..] {
+        if nearest.len() == n && gene.start - right > nearest[n - 1].0 {
+            break;
+        }
+        keep(gene, &mut nearest);
+    }
+    nearest
+}
+
+/// The gene of the chromosome's `genes` nearest to `region` by
+/// `config.closest_by`, with its distance; ties go to the lowest gene ID.
+///
+/// Genes on a strand rejected by `config.strandedness` are skipped.
+fn closest_gene<'g>(
+    region: &Region,
+    genes: &'g [Gene],
+    first_reaching: FirstReaching,
+    config: &Config,
+) -> Option<(i64, &'g Gene)> {
+    nearest_genes(region, genes, first_reaching, config.closest_by, 1, config).pop()
+}
+
+/// Candidates of the closest-gene fallback (`config.always_closest`).
+///
+/// Matches the region to its [`closest_gene`] without distance cutoff. This
+/// gives the UPSTREAM or DOWNSTREAM candidate of the gene, with its true
+/// distance.
+fn closest_fallback(
+    region: &Region,
+    genes: &[Gene],
+    first_reaching: FirstReaching,
+    config: &Config,
+) -> Vec<Candidate> {
+    let Some((_, gene)) = closest_gene(region, genes, first_reaching, config) else {
+        return Vec::new();
+    };
+
+    let mut candidates = match_region_to_gene_iter(region, std::iter::once(gene), config, i64::MAX);
+    for candidate in &mut candidates {
+        candidate.closest_fallback = true;
+    }
+    candidates
+}
+
+/// Add the genes nearest to `region` to its gene-level `candidates`
+/// (`config.n_closest`).
+///
+/// The `n_closest` genes of the chromosome's `genes` with the TSS nearest to
+/// the region midpoint are ranked from 1, ties going to the lowest gene ID.
+/// All of `genes` are searched, so genes beyond `config.distance` are ranked
+/// when fewer lie within it. A ranked gene that already has candidates gets
+/// its rank on them; any other is matched without distance cutoff and its
+/// rows are appended in rank order. With `config.closest_only`, only the
+/// ranked rows are kept, in rank order.
+fn add_nearest_genes(
+    region: &Region,
+    mut candidates: Vec<Candidate>,
+    genes: &[Gene],
+    first_reaching: FirstReaching,
+    config: &Config,
+) -> Vec<Candidate> {
+    let nearest = nearest_genes(
+        region,
+        genes,
+        first_reaching,
+        ClosestBy::Tss,
+        config.n_closest,
+        config,
+    );
+    for (index, (_, gene)) in nearest.into_iter().enumerate() {
+        let rank = Some(index + 1);
+        let mut found = false;
+        for candidate in candidates.iter_mut().filter(|c| c.gene == gene.gene_id) {
+            candidate.rank = rank;
+            found = true;
+        }
+        if !found {
+            let matched =
+                match_region_to_gene_iter(region, std::iter::once(gene), config, i64::MAX);
+            let mut rows = process_candidates_for_output(matched, config);
+            for row in &mut rows {
+                row.rank = rank;
+            }
+            candidates.extend(rows);
+        }
+    }
+
+    if config.closest_only {
+        candidates.retain(|c| c.rank.is_some());
+        candidates.sort_by_key(|c| c.rank);
+    }
+    candidates
+}
+
 /// Match a single region to genes and return all candidates.
 ///
 /// This implements the main matching logic from the Python code.
@@ -81,6 +433,57 @@ pub fn match_region_to_genes(
     config: &Config,
     last_index: usize,
 ) -> Vec<Candidate> {
+    match_region_to_gene_iter(
+        region,
+        genes.iter().skip(last_index),
+        config,
+        config.distance,
+    )
+}
+
+/// Match a single region to the genes of `genes`, which must come in start
+/// order and include every gene within reach of the region.
+///
+/// Genes on a strand rejected by `config.strandedness` are skipped, so they
+/// give no overlap nor proximity candidates.
+///
+/// In `AssignmentMode::Midpoint`, only the base at the region midpoint is
+/// matched, like a position of [`classify_position`]; the candidates keep
+/// the ID of the whole region.
+///
+/// UPSTREAM and DOWNSTREAM candidates farther than `max_distance` are
+/// dropped; it is `config.distance` except for the searches without cutoff.
+fn match_region_to_gene_iter<'g>(
+    region: &Region,
+    genes: impl Iterator<Item = &'g Gene>,
+    config: &Config,
+    max_distance: i64,
+) -> Vec<Candidate> {
+    debug_assert!(
+        region.start <= region.end,
+        "region {} has start > end",
+        region.id()
+    );
+    if config.assignment_mode == AssignmentMode::Midpoint && region.length() > 1 {
+        let pm = region.midpoint();
+        let point = Region::new(region.chrom.clone(), pm, pm, vec![]).with_strand(region.strand);
+        let mut candidates = match_region_to_gene_iter(&point, genes, config, max_distance);
+        let region_id = region.id();
+        for candidate in &mut candidates {
+            candidate.region_id.clone_from(&region_id);
+        }
+        return candidates;
+    }
+
+    // Unstranded genes are matched in both orientations when asked to
+    let both = config.unstranded_genes == UnstrandedGenes::Both;
+    let genes = genes
+        .flat_map(|gene| {
+            let mirror = (both && gene.unstranded).then(|| Cow::Owned(gene.mirrored()));
+            std::iter::once(Cow::Borrowed(gene)).chain(mirror)
+        })
+        .filter(|gene| strand_accepts(region, gene, config));
+
     let start = region.start;
     let end = region.end;
     let pm = region.midpoint();
@@ -92,6 +495,13 @@ pub fn match_region_to_genes(
 
     let mut upst: i64 = i64::MAX; // Distance to TSS
     let mut exon_up: Option<Candidate> = None;
+    // Nearest upstream candidate on each strand, for bidirectional promoters
+    let mut nearest_up_plus: Option<Candidate> = None;
+    let mut nearest_up_minus: Option<Candidate> = None;
+    // Farther genes may still pair with the nearest upstream one
+    let bidirectional_window = config
+        .flags_bidirectional()
+        .then_some(config.bidirectional_window);
 
     // When flag_gene_body is false, we will report downstream or upstream exons
     // Otherwise, we will only report the overlapped exons
@@ -105,8 +515,17 @@ pub fn match_region_to_genes(
     // This is because there will be regions that will overlap different introns or exons
     let mut my_introns: IndexMap<String, Vec<(Candidate, i64, i64)>> = IndexMap::new();
     let mut my_gene_bodys: IndexMap<String, Vec<(Candidate, i64, i64)>> = IndexMap::new();
-
-    for (_i, gene) in genes.iter().enumerate().skip(last_index) {
+    // UTR parts of the gene body overlaps, keyed by [geneID_transcriptID_area]
+    let mut my_utrs: IndexMap<String, Vec<(Candidate, i64, i64)>> = IndexMap::new();
+    // Gene body lengths, keyed by [geneID_transcriptID], when PercArea of
+    // GENE_BODY is not over the overlapped exons
+    let mut gene_body_lengths: AHashMap<String, i64> = AHashMap::new();
+    // Exon-intron junctions of each transcript, for the JunctionDistance column
+    let reports_junctions = config.reports_junction_distance();
+    let mut junctions: AHashMap<(Name, Name), Vec<i64>> = AHashMap::new();
+
+    for gene in genes {
+        let gene: &Gene = &gene;
         let distance_to_start_gene = (gene.start - pm).abs();
 
         // Check if we should stop processing genes
@@ -119,29 +538,65 @@ pub fn match_region_to_genes(
             // But 'down' is initialized to MAX.
             // The python logic seems to be: if we found something closer than current distance, stop.
             // Simplified check matching Python structure:
-            if flag_gene_body || down < distance_to_start_gene || upst < distance_to_start_gene {
+            // A nearest distance beyond the per-area limit does not stop the
+            // search, as a farther gene may have to replace it
+            let reach = |distance: i64, area: Area| match config.area_max_distance.get(&area) {
+                Some(&max) if distance > max => i64::MAX,
+                _ => distance,
+            };
+            if flag_gene_body
+                || reach(down, Area::Downstream) < distance_to_start_gene
+                || reach(upst, Area::Upstream).saturating_add(bidirectional_window.unwrap_or(0))
+                    < distance_to_start_gene
+            {
                 break;
             }
             // Additional safety check for performance: if gene starts WAY after, we can definitely stop?
             // Existing logic relies on `down` and `upst` being updated.
         }
 
+        // A gene within the region is one candidate per transcript
+        if start <= gene.start && gene.end <= end && config.consolidates_contained_genes() {
+            flag_gene_body = true;
+            for transcript in &gene.transcripts {
+                final_output.push(contained_candidate(region, gene, transcript));
+            }
+            continue;
+        }
+
         // Check associations
-        for transcript in &gene.transcripts {
+        let tss_transcripts = tss_transcripts(gene, config);
+        for (transcript_index, transcript) in gene.transcripts.iter().enumerate() {
             let exons = &transcript.exons;
-
-            // Calculate TSSdist using the first exon "start" position
-            let tss_distance = if exons[0].exon_number.as_deref() == Some("1") {
-                pm - exons[0].start
-            } else {
-                exons.last().unwrap().end - pm
+            let built;
+            let index = match transcript.exon_index(gene.strand) {
+                Some(index) => index,
+                None => {
+                    built = ExonIndex::new(exons, gene.strand);
+                    &built
+                }
             };
+            let outputs_before = final_output.len();
+            let reports_tss = tss_transcripts
+                .as_ref()
+                .map_or(true, |allowed| allowed[transcript_index]);
+            if reports_junctions {
+                junctions
+                    .entry((gene.gene_id.clone(), transcript.transcript_id.clone()))
+                    .or_insert_with(|| index.junctions());
+            }
 
-            for (j, exon) in exons.iter().enumerate() {
+            // TSSdist and TTSdist; positive values are downstream
+            let tss_distance = index.tss_distance(pm);
+            let tts_distance = index.tts_distance(pm);
+
+            // Exons away from the region give no candidate
+            for j in index.exons_near(start, end) {
+                let exon = &exons[j];
                 let is_first_exon = j == 0;
                 let is_last_exon = j == exons.len() - 1;
                 let exon_length = exon.length();
-                let exon_number = exon.exon_number.clone().unwrap_or_default();
+                let exon_number: ExonNumbers = exon.exon_number.map(Into::into).unwrap_or_default();
 
                 // Case 1: Exon before the region
                 // <--------->
@@ -155,22 +610,30 @@ pub fn match_region_to_genes(
                     if is_last_exon {
                         if gene.strand == Strand::Positive && dist_tmp < down {
                             down = dist_tmp;
-                            exon_down = Some(Candidate::new(
-                                exon.start,
-                                exon.end,
-                                gene.strand,
-                                exon_number.clone(),
-                                Area::Downstream,
-                                transcript.transcript_id.clone(),
-                                gene.gene_id.clone(),
-                                down,
-                                100.0,
-                                -1.0,
-                                tss_distance,
-                            ));
-                        } else if gene.strand == Strand::Negative && dist_tmp < upst {
-                            upst = dist_tmp;
-                            exon_up = Some(Candidate::new(
+                            exon_down = Some(
+                                Candidate::new(
+                                    exon.start,
+                                    exon.end,
+                                    gene.strand,
+                                    exon_number.clone(),
+                                    Area::Downstream,
+                                    transcript.transcript_id.clone(),
+                                    gene.gene_id.clone(),
+                                    down,
+                                    100.0,
+                                    -1.0,
+                                    tss_distance,
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end),
+                            );
+                        } else if gene.strand == Strand::Negative
+                            && reports_tss
+                            && nearest_up_minus
+                                .as_ref()
+                                .map_or(true, |c| dist_tmp < c.distance)
+                        {
+                            let candidate = Candidate::new(
                                 exon.start,
                                 exon.end,
                                 gene.strand,
@@ -178,35 +641,42 @@ pub fn match_region_to_genes(
                                 Area::Upstream,
                                 transcript.transcript_id.clone(),
                                 gene.gene_id.clone(),
-                                upst,
+                                dist_tmp,
                                 100.0,
                                 -1.0,
                                 tss_distance,
-                            ));
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end);
+                            if dist_tmp < upst {
+                                upst = dist_tmp;
+                                exon_up = Some(candidate.clone());
+                            }
+                            nearest_up_minus = Some(candidate);
                         }
                     } else {
                         // Check if the next exon is closer to the region
-                        let next_exon = &exons[j + 1];
+                        let next_start = index.starts[j + 1];
 
-                        if next_exon.start > start {
+                        if next_start > start {
                             flag_gene_body = true;
-                            let intron_length = next_exon.start - exon.end - 1;
+                            let (intron_start, intron_end) = index.introns[j];
+                            let intron_length = intron_end - intron_start + 1;
                             let intron_number =
                                 calculate_intron_number(j, exons.len(), gene.strand);
 
-                            if next_exon.start > end {
+                            if next_start > end {
                                 // Region is completely inside intron
                                 let pctg_region = 100.0;
                                 let pctg_area =
                                     (region_length as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -214,7 +684,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
                                     intron_length,
@@ -223,19 +695,18 @@ pub fn match_region_to_genes(
                                 break;
                             } else {
                                 // Region overlaps with next exon
-                                let region_overlap = next_exon.start - start;
+                                let region_overlap = next_start - start;
                                 let pctg_region =
                                     (region_overlap as f64 / region_length as f64) * 100.0;
                                 let pctg_area =
                                     (region_overlap as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -243,7 +714,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
                                     intron_length,
@@ -262,24 +735,26 @@ pub fn match_region_to_genes(
                     let pctg_region = (body_overlap as f64 / region_length as f64) * 100.0;
                     let pctg_area = (body_overlap as f64 / exon_length as f64) * 100.0;
 
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
+                    if j == index.first_exon {
+                        final_output.push(
+                            Candidate::new(
+                                exon.start,
+                                exon.end,
+                                gene.strand,
+                                exon_number.clone(),
+                                Area::FirstExon,
+                                transcript.transcript_id.clone(),
+                                gene.gene_id.clone(),
+                                0,
+                                pctg_region,
+                                pctg_area,
+                                tss_distance,
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end),
+                        );
                     } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                        let my_id = entry_key(gene, transcript);
                         let gb_candidate = Candidate::new(
                             exon.start,
                             exon.end,
@@ -292,7 +767,9 @@ pub fn match_region_to_genes(
                             pctg_region,
                             pctg_area,
                             tss_distance,
-                        );
+                        )
+                        .with_tts_distance(tts_distance)
+                        .with_gene_span(gene.start, gene.end);
                         my_gene_bodys.entry(my_id).or_default().push((
                             gb_candidate,
                             exon_length,
@@ -320,7 +797,9 @@ pub fn match_region_to_genes(
                                     pctg_region_r,
                                     -1.0,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 if config.tts > 0.0 {
                                     let exon_info = TtsExonInfo {
                                         start: candidate.start,
@@ -328,22 +807,14 @@ pub fn match_region_to_genes(
                                         strand: candidate.strand,
                                         distance: candidate.distance,
                                     };
-                                    for (tag, pctg_dhs, pctg_a) in
-                                        check_tts(start, end, &exon_info, config.tts)
-                                    {
-                                        final_output.push(Candidate::new(
-                                            candidate.start,
-                                            candidate.end,
-                                            candidate.strand,
-                                            candidate.exon_number.clone(),
-                                            tag.parse().unwrap_or(Area::Downstream),
-                                            candidate.transcript.clone(),
-                                            candidate.gene.clone(),
-                                            candidate.distance,
-                                            pctg_dhs,
-                                            pctg_a,
-                                            tss_distance,
-                                        ));
+                                    for overlap in check_tts_with_boundary(
+                                        start,
+                                        end,
+                                        &exon_info,
+                                        config.tts,
+                                        config.boundary_inclusive,
+                                    ) {
+                                        final_output.push(overlap_candidate(&candidate, overlap));
                                     }
                                 } else {
                                     final_output.push(candidate);
@@ -361,52 +832,48 @@ pub fn match_region_to_genes(
                                     pctg_region_r,
                                     -1.0,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 let exon_info = TssExonInfo {
                                     start: candidate.start,
                                     end: candidate.end,
                                     strand: candidate.strand,
                                     distance: candidate.distance,
                                 };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tss(start, end, &exon_info, config.tss, config.promoter)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Upstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
+                                for overlap in check_tss_with_boundary(
+                                    start,
+                                    end,
+                                    &exon_info,
+                                    config.tss,
+                                    config.promoter,
+                                    config.tss_downstream,
+                                    config.boundary_inclusive,
+                                ) {
+                                    final_output.push(overlap_candidate(&candidate, overlap));
                                 }
                             }
                         } else {
                             // Check intron after exon
-                            let next_exon = &exons[j + 1];
-                            let intron_length = next_exon.start - exon.end - 1;
+                            let next_start = index.starts[j + 1];
+                            let (intron_start, intron_end) = index.introns[j];
+                            let intron_length = intron_end - intron_start + 1;
                             let intron_number =
                                 calculate_intron_number(j, exons.len(), gene.strand);
 
-                            if next_exon.start > end {
+                            if next_start > end {
                                 let region_overlap = end - exon.end;
                                 let pctg_region =
                                     (region_overlap as f64 / region_length as f64) * 100.0;
                                 let pctg_area =
                                     (region_overlap as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -414,7 +881,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
                                     intron_length,
@@ -422,19 +891,18 @@ pub fn match_region_to_genes(
                                 ));
                                 break;
                             } else {
-                                let region_overlap = next_exon.start - exon.end - 1;
+                                let region_overlap = next_start - exon.end - 1;
                                 let pctg_region =
                                     (region_overlap as f64 / region_length as f64) * 100.0;
                                 let pctg_area =
                                     (region_overlap as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -442,7 +910,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
 
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
@@ -477,7 +947,9 @@ pub fn match_region_to_genes(
                                 pctg_region_r,
                                 -1.0,
                                 tss_distance,
-                            );
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end);
                             if config.tts > 0.0 {
                                 let exon_info = TtsExonInfo {
                                     start: candidate.start,
@@ -485,22 +957,14 @@ pub fn match_region_to_genes(
                                     strand: candidate.strand,
                                     distance: candidate.distance,
                                 };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tts(start, end, &exon_info, config.tts)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Downstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
+                                for overlap in check_tts_with_boundary(
+                                    start,
+                                    end,
+                                    &exon_info,
+                                    config.tts,
+                                    config.boundary_inclusive,
+                                ) {
+                                    final_output.push(overlap_candidate(&candidate, overlap));
                                 }
                             } else {
                                 final_output.push(candidate);
@@ -518,29 +982,25 @@ pub fn match_region_to_genes(
                                 pctg_region_r,
                                 -1.0,
                                 tss_distance,
-                            );
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end);
                             let exon_info = TssExonInfo {
                                 start: candidate.start,
                                 end: candidate.end,
                                 strand: candidate.strand,
                                 distance: candidate.distance,
                             };
-                            for (tag, pctg_dhs, pctg_a) in
-                                check_tss(start, end, &exon_info, config.tss, config.promoter)
-                            {
-                                final_output.push(Candidate::new(
-                                    candidate.start,
-                                    candidate.end,
-                                    candidate.strand,
-                                    candidate.exon_number.clone(),
-                                    tag.parse().unwrap_or(Area::Upstream),
-                                    candidate.transcript.clone(),
-                                    candidate.gene.clone(),
-                                    candidate.distance,
-                                    pctg_dhs,
-                                    pctg_a,
-                                    tss_distance,
-                                ));
+                            for overlap in check_tss_with_boundary(
+                                start,
+                                end,
+                                &exon_info,
+                                config.tss,
+                                config.promoter,
+                                config.tss_downstream,
+                                config.boundary_inclusive,
+                            ) {
+                                final_output.push(overlap_candidate(&candidate, overlap));
                             }
                         }
                     }
@@ -550,24 +1010,26 @@ pub fn match_region_to_genes(
                     let pctg_region = (region_overlap as f64 / region_length as f64) * 100.0;
                     let pctg_area = 100.0;
 
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
+                    if j == index.first_exon {
+                        final_output.push(
+                            Candidate::new(
+                                exon.start,
+                                exon.end,
+                                gene.strand,
+                                exon_number.clone(),
+                                Area::FirstExon,
+                                transcript.transcript_id.clone(),
+                                gene.gene_id.clone(),
+                                0,
+                                pctg_region,
+                                pctg_area,
+                                tss_distance,
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end),
+                        );
                     } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                        let my_id = entry_key(gene, transcript);
 
                         let gb_candidate = Candidate::new(
                             exon.start,
@@ -581,7 +1043,9 @@ pub fn match_region_to_genes(
                             pctg_region,
                             pctg_area,
                             tss_distance,
-                        );
+                        )
+                        .with_tts_distance(tts_distance)
+                        .with_gene_span(gene.start, gene.end);
                         my_gene_bodys.entry(my_id).or_default().push((
                             gb_candidate,
                             exon_length,
@@ -609,7 +1073,9 @@ pub fn match_region_to_genes(
                                     pctg_region_r,
                                     -1.0,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 if config.tts > 0.0 {
                                     let exon_info = TtsExonInfo {
                                         start: candidate.start,
@@ -617,22 +1083,14 @@ pub fn match_region_to_genes(
                                         strand: candidate.strand,
                                         distance: candidate.distance,
                                     };
-                                    for (tag, pctg_dhs, pctg_a) in
-                                        check_tts(start, end, &exon_info, config.tts)
-                                    {
-                                        final_output.push(Candidate::new(
-                                            candidate.start,
-                                            candidate.end,
-                                            candidate.strand,
-                                            candidate.exon_number.clone(),
-                                            tag.parse().unwrap_or(Area::Downstream),
-                                            candidate.transcript.clone(),
-                                            candidate.gene.clone(),
-                                            candidate.distance,
-                                            pctg_dhs,
-                                            pctg_a,
-                                            tss_distance,
-                                        ));
+                                    for overlap in check_tts_with_boundary(
+                                        start,
+                                        end,
+                                        &exon_info,
+                                        config.tts,
+                                        config.boundary_inclusive,
+                                    ) {
+                                        final_output.push(overlap_candidate(&candidate, overlap));
                                     }
                                 } else {
                                     final_output.push(candidate);
@@ -650,52 +1108,48 @@ pub fn match_region_to_genes(
                                     pctg_region_r,
                                     -1.0,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 let exon_info = TssExonInfo {
                                     start: candidate.start,
                                     end: candidate.end,
                                     strand: candidate.strand,
                                     distance: candidate.distance,
                                 };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tss(start, end, &exon_info, config.tss, config.promoter)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Upstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
+                                for overlap in check_tss_with_boundary(
+                                    start,
+                                    end,
+                                    &exon_info,
+                                    config.tss,
+                                    config.promoter,
+                                    config.tss_downstream,
+                                    config.boundary_inclusive,
+                                ) {
+                                    final_output.push(overlap_candidate(&candidate, overlap));
                                 }
                             }
                         } else {
                             // Check intron after exon
-                            let next_exon = &exons[j + 1];
-                            let intron_length = next_exon.start - exon.end - 1;
+                            let next_start = index.starts[j + 1];
+                            let (intron_start, intron_end) = index.introns[j];
+                            let intron_length = intron_end - intron_start + 1;
                             let intron_number =
                                 calculate_intron_number(j, exons.len(), gene.strand);
 
-                            if next_exon.start > end {
+                            if next_start > end {
                                 let region_overlap = end - exon.end;
                                 let pctg_region =
                                     (region_overlap as f64 / region_length as f64) * 100.0;
                                 let pctg_area =
                                     (region_overlap as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -703,7 +1157,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
                                     intron_length,
@@ -711,19 +1167,18 @@ pub fn match_region_to_genes(
                                 ));
                                 break;
                             } else {
-                                let region_overlap = next_exon.start - exon.end - 1;
+                                let region_overlap = next_start - exon.end - 1;
                                 let pctg_region =
                                     (region_overlap as f64 / region_length as f64) * 100.0;
                                 let pctg_area =
                                     (region_overlap as f64 / intron_length as f64) * 100.0;
 
-                                let my_id =
-                                    format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                                let my_id = entry_key(gene, transcript);
                                 let intron_candidate = Candidate::new(
                                     exon.start,
                                     exon.end,
                                     gene.strand,
-                                    intron_number.to_string(),
+                                    intron_label(intron_number, config),
                                     Area::Intron,
                                     transcript.transcript_id.clone(),
                                     gene.gene_id.clone(),
@@ -731,7 +1186,9 @@ pub fn match_region_to_genes(
                                     pctg_region,
                                     pctg_area,
                                     tss_distance,
-                                );
+                                )
+                                .with_tts_distance(tts_distance)
+                                .with_gene_span(gene.start, gene.end);
                                 my_introns.entry(my_id).or_default().push((
                                     intron_candidate,
                                     intron_length,
@@ -765,7 +1222,9 @@ pub fn match_region_to_genes(
                                 pctg_region_r,
                                 -1.0,
                                 tss_distance,
-                            );
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end);
                             if config.tts > 0.0 {
                                 let exon_info = TtsExonInfo {
                                     start: candidate.start,
@@ -773,22 +1232,14 @@ pub fn match_region_to_genes(
                                     strand: candidate.strand,
                                     distance: candidate.distance,
                                 };
-                                for (tag, pctg_dhs, pctg_a) in
-                                    check_tts(start, end, &exon_info, config.tts)
-                                {
-                                    final_output.push(Candidate::new(
-                                        candidate.start,
-                                        candidate.end,
-                                        candidate.strand,
-                                        candidate.exon_number.clone(),
-                                        tag.parse().unwrap_or(Area::Downstream),
-                                        candidate.transcript.clone(),
-                                        candidate.gene.clone(),
-                                        candidate.distance,
-                                        pctg_dhs,
-                                        pctg_a,
-                                        tss_distance,
-                                    ));
+                                for overlap in check_tts_with_boundary(
+                                    start,
+                                    end,
+                                    &exon_info,
+                                    config.tts,
+                                    config.boundary_inclusive,
+                                ) {
+                                    final_output.push(overlap_candidate(&candidate, overlap));
                                 }
                             } else {
                                 final_output.push(candidate);
@@ -806,29 +1257,25 @@ pub fn match_region_to_genes(
                                 pctg_region_r,
                                 -1.0,
                                 tss_distance,
-                            );
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end);
                             let exon_info = TssExonInfo {
                                 start: candidate.start,
                                 end: candidate.end,
                                 strand: candidate.strand,
                                 distance: candidate.distance,
                             };
-                            for (tag, pctg_dhs, pctg_a) in
-                                check_tss(start, end, &exon_info, config.tss, config.promoter)
-                            {
-                                final_output.push(Candidate::new(
-                                    candidate.start,
-                                    candidate.end,
-                                    candidate.strand,
-                                    candidate.exon_number.clone(),
-                                    tag.parse().unwrap_or(Area::Upstream),
-                                    candidate.transcript.clone(),
-                                    candidate.gene.clone(),
-                                    candidate.distance,
-                                    pctg_dhs,
-                                    pctg_a,
-                                    tss_distance,
-                                ));
+                            for overlap in check_tss_with_boundary(
+                                start,
+                                end,
+                                &exon_info,
+                                config.tss,
+                                config.promoter,
+                                config.tss_downstream,
+                                config.boundary_inclusive,
+                            ) {
+                                final_output.push(overlap_candidate(&candidate, overlap));
                             }
                         }
                     }
@@ -837,24 +1284,26 @@ pub fn match_region_to_genes(
                     let pctg_region = (region_overlap as f64 / region_length as f64) * 100.0;
                     let pctg_area = (region_overlap as f64 / exon_length as f64) * 100.0;
 
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
+                    if j == index.first_exon {
+                        final_output.push(
+                            Candidate::new(
+                                exon.start,
+                                exon.end,
+                                gene.strand,
+                                exon_number.clone(),
+                                Area::FirstExon,
+                                transcript.transcript_id.clone(),
+                                gene.gene_id.clone(),
+                                0,
+                                pctg_region,
+                                pctg_area,
+                                tss_distance,
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end),
+                        );
                     } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                        let my_id = entry_key(gene, transcript);
 
                         let gb_candidate = Candidate::new(
                             exon.start,
@@ -868,7 +1317,9 @@ pub fn match_region_to_genes(
                             pctg_region,
                             pctg_area,
                             tss_distance,
-                        );
+                        )
+                        .with_tts_distance(tts_distance)
+                        .with_gene_span(gene.start, gene.end);
                         my_gene_bodys.entry(my_id).or_default().push((
                             gb_candidate,
                             exon_length,
@@ -884,24 +1335,26 @@ pub fn match_region_to_genes(
                     let pctg_region = 100.0;
                     let pctg_area = (region_length as f64 / exon_length as f64) * 100.0;
 
-                    if (is_first_exon && gene.strand == Strand::Positive)
-                        || (is_last_exon && gene.strand == Strand::Negative)
-                    {
-                        final_output.push(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::FirstExon,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            0,
-                            pctg_region,
-                            pctg_area,
-                            tss_distance,
-                        ));
+                    if j == index.first_exon {
+                        final_output.push(
+                            Candidate::new(
+                                exon.start,
+                                exon.end,
+                                gene.strand,
+                                exon_number.clone(),
+                                Area::FirstExon,
+                                transcript.transcript_id.clone(),
+                                gene.gene_id.clone(),
+                                0,
+                                pctg_region,
+                                pctg_area,
+                                tss_distance,
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end),
+                        );
                     } else {
-                        let my_id = format!("{}_{}", gene.gene_id, transcript.transcript_id);
+                        let my_id = entry_key(gene, transcript);
 
                         let gb_candidate = Candidate::new(
                             exon.start,
@@ -915,7 +1368,9 @@ pub fn match_region_to_genes(
                             pctg_region,
                             pctg_area,
                             tss_distance,
-                        );
+                        )
+                        .with_tts_distance(tts_distance)
+                        .with_gene_span(gene.start, gene.end);
                         my_gene_bodys.entry(my_id).or_default().push((
                             gb_candidate,
                             exon_length,
@@ -931,22 +1386,30 @@ pub fn match_region_to_genes(
 
                     if gene.strand == Strand::Negative && dist_tmp < down {
                         down = dist_tmp;
-                        exon_down = Some(Candidate::new(
-                            exon.start,
-                            exon.end,
-                            gene.strand,
-                            exon_number.clone(),
-                            Area::Downstream,
-                            transcript.transcript_id.clone(),
-                            gene.gene_id.clone(),
-                            down,
-                            100.0,
-                            -1.0,
-                            tss_distance,
-                        ));
-                    } else if gene.strand == Strand::Positive && dist_tmp < upst {
-                        upst = dist_tmp;
-                        exon_up = Some(Candidate::new(
+                        exon_down = Some(
+                            Candidate::new(
+                                exon.start,
+                                exon.end,
+                                gene.strand,
+                                exon_number.clone(),
+                                Area::Downstream,
+                                transcript.transcript_id.clone(),
+                                gene.gene_id.clone(),
+                                down,
+                                100.0,
+                                -1.0,
+                                tss_distance,
+                            )
+                            .with_tts_distance(tts_distance)
+                            .with_gene_span(gene.start, gene.end),
+                        );
+                    } else if gene.strand == Strand::Positive
+                        && reports_tss
+                        && nearest_up_plus
+                            .as_ref()
+                            .map_or(true, |c| dist_tmp < c.distance)
+                    {
+                        let candidate = Candidate::new(
                             exon.start,
                             exon.end,
                             gene.strand,
@@ -954,11 +1417,18 @@ pub fn match_region_to_genes(
                             Area::Upstream,
                             transcript.transcript_id.clone(),
                             gene.gene_id.clone(),
-                            upst,
+                            dist_tmp,
                             100.0,
                             -1.0,
                             tss_distance,
-                        ));
+                        )
+                        .with_tts_distance(tts_distance)
+                        .with_gene_span(gene.start, gene.end);
+                        if dist_tmp < upst {
+                            upst = dist_tmp;
+                            exon_up = Some(candidate.clone());
+                        }
+                        nearest_up_plus = Some(candidate);
                     }
 
                     if down <= dist_tmp && upst <= dist_tmp {
@@ -966,80 +1436,646 @@ pub fn match_region_to_genes(
                     }
                 }
             }
-        }
-    }
 
-    // Report closest downstream/upstream if applicable
-    if let Some(exon_down_val) = exon_down {
-        if down <= upst && exon_down_val.distance <= config.distance {
-            if config.tts > 0.0 {
-                let exon_info = TtsExonInfo {
-                    start: exon_down_val.start,
-                    end: exon_down_val.end,
-                    strand: exon_down_val.strand,
-                    distance: exon_down_val.distance,
-                };
-                for (tag, pctg_dhs, pctg_a) in check_tts(start, end, &exon_info, config.tts) {
-                    final_output.push(Candidate::new(
-                        exon_down_val.start,
-                        exon_down_val.end,
-                        exon_down_val.strand,
-                        exon_down_val.exon_number.clone(),
-                        tag.parse().unwrap_or(Area::Downstream),
-                        exon_down_val.transcript.clone(),
-                        exon_down_val.gene.clone(),
-                        exon_down_val.distance,
-                        pctg_dhs,
-                        pctg_a,
-                        exon_down_val.tss_distance,
-                    ));
+            // Report the first exon bases within the TSS region as TSS
+            if config.tss_downstream > 0.0 {
+                for candidate in final_output.split_off(outputs_before) {
+                    match candidate.area {
+                        Area::FirstExon => {
+                            final_output.extend(split_tss_window(&candidate, region, config))
+                        }
+                        _ => final_output.push(candidate),
+                    }
+                }
+            }
+
+            // Only the gene's TSS transcripts report the areas around their TSS
+            if !reports_tss {
+                let mut outputs = final_output.split_off(outputs_before);
+                outputs.retain(|c| !matches!(c.area, Area::Tss | Area::Promoter | Area::Upstream));
+                final_output.extend(outputs);
+            }
+
+            // Split the exon overlaps of a coding transcript at its CDS
+            if let Some(cds) = transcript.cds.filter(|_| config.report_utr) {
+                let split = |candidate: &Candidate| split_at_cds(candidate, region, cds, config);
+
+                for candidate in final_output.split_off(outputs_before) {
+                    let pieces = match candidate.area {
+                        Area::FirstExon => split(&candidate),
+                        _ => None,
+                    };
+                    match pieces {
+                        Some(pieces) => final_output.extend(pieces.into_iter().map(|p| p.0)),
+                        None => final_output.push(candidate),
+                    }
+                }
+
+                let my_id = entry_key(gene, transcript);
+                if let Some(entries) = my_gene_bodys.get_mut(&my_id) {
+                    for entry in std::mem::take(entries) {
+                        for piece in split(&entry.0).unwrap_or_else(|| vec![entry]) {
+                            if piece.0.area == Area::GeneBody {
+                                entries.push(piece);
+                            } else {
+                                let key = format!("{}_{}", my_id, piece.0.area);
+                                my_utrs.entry(key).or_default().push(piece);
+                            }
+                        }
+                    }
+                    if entries.is_empty() {
+                        my_gene_bodys.shift_remove(&my_id);
+                    }
                 }
-            } else {
-                final_output.push(exon_down_val);
+            }
+
+            let gene_body_length = match config.gene_body_definition {
+                GeneBodyDefinition::ExonOnly => None,
+                GeneBodyDefinition::FullSpan => Some(transcript.end - transcript.start + 1),
+                GeneBodyDefinition::ExonUnion => Some(index.exonic_length),
+            };
+            if let Some(length) = gene_body_length {
+                gene_body_lengths.insert(entry_key(gene, transcript), length);
             }
         }
     }
 
-    if let Some(exon_up_val) = exon_up {
-        if upst <= down && exon_up_val.distance <= config.distance {
-            let exon_info = TssExonInfo {
-                start: exon_up_val.start,
-                end: exon_up_val.end,
-                strand: exon_up_val.strand,
-                distance: exon_up_val.distance,
+    // Closest downstream/upstream candidates within the distance cutoff
+    let mut down_candidates = Vec::new();
+    if let Some(exon_down_val) = exon_down.filter(|c| c.distance <= max_distance) {
+        if config.tts > 0.0 {
+            let exon_info = TtsExonInfo {
+                start: exon_down_val.start,
+                end: exon_down_val.end,
+                strand: exon_down_val.strand,
+                distance: exon_down_val.distance,
             };
-            for (tag, pctg_dhs, pctg_a) in
-                check_tss(start, end, &exon_info, config.tss, config.promoter)
-            {
-                final_output.push(Candidate::new(
-                    exon_up_val.start,
-                    exon_up_val.end,
-                    exon_up_val.strand,
-                    exon_up_val.exon_number.clone(),
-                    tag.parse().unwrap_or(Area::Upstream),
-                    exon_up_val.transcript.clone(),
-                    exon_up_val.gene.clone(),
-                    exon_up_val.distance,
-                    pctg_dhs,
-                    pctg_a,
-                    exon_up_val.tss_distance,
-                ));
+            for overlap in check_tts_with_boundary(
+                start,
+                end,
+                &exon_info,
+                config.tts,
+                config.boundary_inclusive,
+            ) {
+                down_candidates.push(overlap_candidate(&exon_down_val, overlap));
             }
+        } else {
+            down_candidates.push(exon_down_val);
+        }
+    }
+
+    // The head-to-head neighbor of the nearest upstream gene shares its
+    // promoter, however much farther it is
+    let exon_up = exon_up.filter(|c| c.distance <= max_distance);
+    let partner = exon_up.as_ref().and_then(|up| {
+        let nearest_other = match up.strand {
+            Strand::Positive => nearest_up_minus,
+            Strand::Negative => nearest_up_plus,
+        };
+        nearest_other.filter(|other| {
+            other.gene != up.gene
+                && other.distance <= max_distance
+                && bidirectional_window.is_some_and(|window| {
+                    (first_exon_tss(up) - first_exon_tss(other)).abs() <= window
+                })
+        })
+    });
+
+    let mut up_candidates = Vec::new();
+    for exon_up_val in exon_up.into_iter().chain(partner) {
+        let exon_info = TssExonInfo {
+            start: exon_up_val.start,
+            end: exon_up_val.end,
+            strand: exon_up_val.strand,
+            distance: exon_up_val.distance,
+        };
+        for overlap in check_tss_with_boundary(
+            start,
+            end,
+            &exon_info,
+            config.tss,
+            config.promoter,
+            config.tss_downstream,
+            config.boundary_inclusive,
+        ) {
+            up_candidates.push(overlap_candidate(&exon_up_val, overlap));
         }
     }
 
+    // Report the closest of the two, or both when tied. The farther one is
+    // reported too if the closest is beyond its per-area limit; finish_matches
+    // then drops the closest.
+    let all_beyond = |candidates: &[Candidate]| {
+        !candidates.is_empty() && candidates.iter().all(|c| beyond_area_distance(c, config))
+    };
+    let down_beyond = all_beyond(&down_candidates);
+    if down <= upst || all_beyond(&up_candidates) {
+        final_output.extend(down_candidates);
+    }
+    if upst <= down || down_beyond {
+        final_output.extend(up_candidates);
+    }
+
     // Sum up gene body and intron overlaps
     if flag_gene_body {
+        // With the full span as gene body, intron overlaps join the exon
+        // ones; unless introns are labelled, the Exon column keeps the
+        // overlapped exons, or the introns when no exon is overlapped
+        if config.gene_body_definition == GeneBodyDefinition::FullSpan {
+            for (my_id, entries) in my_introns.drain(..) {
+                let gene_body = my_gene_bodys.entry(my_id).or_default();
+                let has_exons = !gene_body.is_empty() && !config.label_introns;
+                for (mut candidate, intron_length, overlap) in entries {
+                    candidate.area = Area::GeneBody;
+                    if has_exons {
+                        candidate.exon_number = ExonNumbers::default();
+                    }
+                    gene_body.push((candidate, intron_length, overlap));
+                }
+            }
+        }
+
         // Gene body
-        final_output.extend(aggregate_entries(my_gene_bodys, region_length));
+        final_output.extend(aggregate_entries(
+            my_gene_bodys,
+            region_length,
+            &gene_body_lengths,
+        ));
+        final_output.extend(aggregate_entries(my_utrs, region_length, &AHashMap::new()));
 
         // Introns
-        final_output.extend(aggregate_entries(my_introns, region_length));
+        let mut introns = aggregate_entries(my_introns, region_length, &AHashMap::new());
+        if config.reports_first_intron() {
+            for intron in &mut introns {
+                // Only a region within the first intron alone, not one also
+                // spanning later introns
+                if intron.exon_number == ExonNumbers::from(intron_label(1, config)) {
+                    intron.area = Area::FirstIntron;
+                }
+            }
+        }
+        final_output.extend(introns);
+    }
+
+    if both {
+        final_output = keep_better_orientation(final_output, config);
     }
 
+    let region_id = region.id();
+    for candidate in &mut final_output {
+        candidate.chrom.clone_from(&region.chrom);
+        candidate.region_id.clone_from(&region_id);
+        candidate.sense = SenseClass::of(region.strand, candidate.strand);
+        candidate.overlap_bp =
+            (candidate.pctg_region * region_length as f64 / 100.0).round() as i64;
+        if reports_junctions
+            && matches!(
+                candidate.area,
+                Area::FirstExon
+                    | Area::GeneBody
+                    | Area::Utr5
+                    | Area::Utr3
+                    | Area::Intron
+                    | Area::FirstIntron
+            )
+        {
+            candidate.junction_distance = junctions
+                .get(&(candidate.gene.clone(), candidate.transcript.clone()))
+                .and_then(|junctions| {
+                    junction_distance(region, junctions, candidate.strand, config.distance_anchor)
+                });
+        }
+        if config.distance_anchor == DistanceAnchor::Edge {
+            anchor_at_edge(candidate, region);
+        }
+        if config.tss_distance_sign == TssDistanceSign::GenomicLeftNegative
+            && candidate.strand == Strand::Negative
+        {
+            candidate.tss_distance = -candidate.tss_distance;
+        }
+    }
     final_output
 }
 
+/// Signed distance from `region` to the nearest of `junctions`, in
+/// transcription orientation: positive when the region is 3' of it.
+///
+/// Measured from the region midpoint, or with `DistanceAnchor::Edge` from
+/// the region edge nearest to the junction, 0 if the region spans it. A
+/// base next to a junction is 1 bp from it.
+fn junction_distance(
+    region: &Region,
+    junctions: &[i64],
+    strand: Strand,
+    anchor: DistanceAnchor,
+) -> Option<i64> {
+    junctions
+        .iter()
+        .map(|&junction| {
+            // The junction lies between `junction` and `junction + 1`
+            let offset = |pos: i64| {
+                if pos > junction {
+                    pos - junction
+                } else {
+                    pos - junction - 1
+                }
+            };
+            let genomic = match anchor {
+                DistanceAnchor::Midpoint => offset(region.midpoint()),
+                DistanceAnchor::Edge if region.end <= junction => offset(region.end),
+                DistanceAnchor::Edge if region.start > junction => offset(region.start),
+                DistanceAnchor::Edge => 0,
+            };
+            match strand {
+                Strand::Positive => genomic,
+                Strand::Negative => -genomic,
+            }
+        })
+        .min_by_key(|distance| distance.abs())
+}
+
+/// Which transcripts of `gene` report TSS, PROMOTER and UPSTREAM
+/// candidates, by index, under `config.gene_tss_mode`; `None` for all of
+/// them, as below gene level.
+fn tss_transcripts(gene: &Gene, config: &Config) -> Option<Vec<bool>> {
+    if config.level != ReportLevel::Gene {
+        return None;
+    }
+    let five_prime_most = || {
+        let tss = gene.transcripts.iter().map(|t| t.tss(gene.strand));
+        let first = match gene.strand {
+            Strand::Positive => tss.clone().min(),
+            Strand::Negative => tss.clone().max(),
+        };
+        tss.map(|pos| Some(pos) == first).collect()
+    };
+    match config.gene_tss_mode {
+        GeneTssMode::AnyTranscript => None,
+        GeneTssMode::Canonical if gene.transcripts.iter().any(Transcript::is_canonical) => Some(
+            gene.transcripts
+                .iter()
+                .map(Transcript::is_canonical)
+                .collect(),
+        ),
+        GeneTssMode::FivePrimeMost | GeneTssMode::Canonical => Some(five_prime_most()),
+    }
+}
+
+/// Key of a transcript's gene body and intron entries; the two orientations
+/// of an unstranded gene are kept apart.
+fn entry_key(gene: &Gene, transcript: &Transcript) -> String {
+    if gene.unstranded {
+        format!(
+            "{}_{}_{}",
+            gene.gene_id, transcript.transcript_id, gene.strand
+        )
+    } else {
+        format!("{}_{}", gene.gene_id, transcript.transcript_id)
+    }
+}
+
+/// Keep one orientation of each transcript matched on both strands, as
+/// unstranded genes are with `UnstrandedGenes::Both`: the one whose best
+/// area ranks higher in `config.rules`, the + strand on ties.
+fn keep_better_orientation(candidates: Vec<Candidate>, config: &Config) -> Vec<Candidate> {
+    let rank = |area: Area| {
+        config
+            .rules
+            .iter()
+            .position(|&rule| rule == area)
+            .unwrap_or(config.rules.len())
+    };
+    // Best rank on the + and - strands, by gene and transcript
+    let mut best: AHashMap<(&Name, &Name), [Option<usize>; 2]> = AHashMap::new();
+    for candidate in &candidates {
+        let ranks = best
+            .entry((&candidate.gene, &candidate.transcript))
+            .or_default();
+        let slot = &mut ranks[usize::from(candidate.strand == Strand::Negative)];
+        let area_rank = rank(candidate.area);
+        *slot = Some(slot.map_or(area_rank, |r| r.min(area_rank)));
+    }
+    let kept: AHashMap<(Name, Name), Strand> = best
+        .into_iter()
+        .filter_map(|((gene, transcript), ranks)| match ranks {
+            [Some(plus), Some(minus)] => {
+                let strand = if minus < plus {
+                    Strand::Negative
+                } else {
+                    Strand::Positive
+                };
+                Some(((gene.clone(), transcript.clone()), strand))
+            }
+            _ => None,
+        })
+        .collect();
+    if kept.is_empty() {
+        return candidates;
+    }
+    candidates
+        .into_iter()
+        .filter(|c| {
+            kept.get(&(c.gene.clone(), c.transcript.clone()))
+                .map_or(true, |&strand| strand == c.strand)
+        })
+        .collect()
+}
+
+/// Measure the distances of `candidate` from the region edge nearest to
+/// the feature instead of the region midpoint.
+fn anchor_at_edge(candidate: &mut Candidate, region: &Region) {
+    let pm = region.midpoint();
+    if candidate.distance > 0 {
+        candidate.distance = if candidate.end < pm {
+            region.start - candidate.end
+        } else {
+            candidate.start - region.end
+        }
+        .max(0);
+    }
+
+    // Gap to the TSS, positive when the region is downstream of it
+    let tss_distance = match candidate.strand {
+        Strand::Positive => {
+            let tss = pm - candidate.tss_distance;
+            (region.start - tss).max(0) + (region.end - tss).min(0)
+        }
+        Strand::Negative => {
+            let tss = pm + candidate.tss_distance;
+            (tss - region.end).max(0) + (tss - region.start).min(0)
+        }
+    };
+    candidate.tss_distance = tss_distance;
+}
+
+/// Drop candidates that would produce the same output row as an earlier one.
+///
+/// Rows are compared on gene, transcript, exon numbers, area, distances and
+/// percentages; the first occurrence is kept.
+fn dedup_identical_rows(candidates: Vec<Candidate>) -> Vec<Candidate> {
+    if candidates.len() < 2 {
+        return candidates;
+    }
+
+    let mut seen = AHashSet::with_capacity(candidates.len());
+    candidates
+        .into_iter()
+        .filter(|c| {
+            seen.insert((
+                c.gene.clone(),
+                c.transcript.clone(),
+                c.exon_number.clone(),
+                c.area,
+                c.distance,
+                c.tss_distance,
+                c.pctg_region.to_bits(),
+                c.pctg_area.to_bits(),
+            ))
+        })
+        .collect()
+}
+
+/// Candidates of a region together with statistics gathered before filtering.
+#[derive(Debug, Clone, Default)]
+pub struct RegionMatches {
+    pub candidates: Vec<Candidate>,
+    /// Number of distinct genes with any candidate before report-level filtering.
+    pub num_candidate_genes: usize,
+    /// Number of candidates dropped by `Config::area_max_distance`.
+    pub num_distance_filtered: usize,
+    /// Number of candidates with a percentage clamped into [0, 100].
+    pub num_clamped_percentages: usize,
+}
+
+impl RegionMatches {
+    /// Wrap unfiltered candidates, counting their distinct genes.
+    pub fn new(candidates: Vec<Candidate>) -> Self {
+        let num_candidate_genes = candidates
+            .iter()
+            .map(|c| c.gene.as_str())
+            .collect::<AHashSet<_>>()
+            .len();
+        RegionMatches {
+            candidates,
+            num_candidate_genes,
+            num_distance_filtered: 0,
+            num_clamped_percentages: 0,
+        }
+    }
+
+    /// Attach the matches to their region.
+    pub fn into_association(self, region: Region) -> Association {
+        Association {
+            region,
+            candidates: self.candidates,
+            num_candidate_genes: self.num_candidate_genes,
+            num_distance_filtered: self.num_distance_filtered,
+            num_clamped_percentages: self.num_clamped_percentages,
+        }
+    }
+}
+
+/// Filter the candidates of `region` for the report level, with the steps
+/// that look at all of its chromosome's `genes`.
+///
+/// Candidates beyond the `config.area_max_distance` limit of their area are
+/// dropped first, so the rules pick among the rest. With
+/// `config.always_closest`, a region without candidates gets those of its
+/// nearest gene; with `config.n_closest` at gene level, its nearest genes are
+/// ranked; with `config.report_intergenic`, a region still without candidates
+/// gets an intergenic pseudo-candidate. Last, percentages are clamped into
+/// [0, 100] (see [`Candidate::clamp_percentages`]).
+fn finish_matches(
+    region: &Region,
+    mut candidates: Vec<Candidate>,
+    genes: &[Gene],
+    first_reaching: FirstReaching,
+    config: &Config,
+) -> RegionMatches {
+    let num_candidates = candidates.len();
+    candidates.retain(|c| !beyond_area_distance(c, config));
+    let num_distance_filtered = num_candidates - candidates.len();
+
+    if candidates.is_empty() && config.always_closest {
+        candidates = closest_fallback(region, genes, first_reaching, config);
+    }
+    let mut matches = RegionMatches::new(candidates);
+    matches.num_distance_filtered = num_distance_filtered;
+    matches.candidates = process_candidates_for_output(matches.candidates, config);
+    if config.reports_all_overlapped_genes() {
+        matches.candidates = report_overlapped_genes(region, matches.candidates, config);
+    }
+    if config.n_closest > 0 && config.level == ReportLevel::Gene {
+        matches.candidates =
+            add_nearest_genes(region, matches.candidates, genes, first_reaching, config);
+    }
+    if matches.candidates.is_empty() && config.report_intergenic {
+        let distance =
+            closest_gene(region, genes, first_reaching, config).map(|(distance, _)| distance);
+        matches
+            .candidates
+            .push(Candidate::intergenic(region, distance));
+    }
+    matches.num_clamped_percentages = matches
+        .candidates
+        .iter_mut()
+        .map(Candidate::clamp_percentages)
+        .filter(|&clamped| clamped)
+        .count();
+    matches
+}
+
+/// Gene-level rows of a region with every gene whose span it overlaps
+/// (`SpanMultipleGenes::ReportAllOverlapped`).
+///
+/// Each overlapped gene keeps one row, with PercRegion the share of the
+/// region within the gene span. `selected` flags the gene of the candidate
+/// `best_only` would pick; with `best_only`, the rows of other genes the
+/// region does not overlap are dropped.
+fn report_overlapped_genes(
+    region: &Region,
+    candidates: Vec<Candidate>,
+    config: &Config,
+) -> Vec<Candidate> {
+    let Some(best) = select_best_candidate(
+        &candidates,
+        config.perc_region,
+        config.perc_area,
+        &config.rules,
+    ) else {
+        return candidates;
+    };
+
+    let region_length = region.length();
+    let mut seen: AHashSet<Name> = AHashSet::new();
+    let mut rows = Vec::with_capacity(candidates.len());
+    for mut candidate in candidates {
+        let chosen = candidate.gene == best.gene;
+        let overlap =
+            region.end.min(candidate.gene_end) - region.start.max(candidate.gene_start) + 1;
+        if overlap > 0 && !candidate.is_intergenic() {
+            if !seen.insert(candidate.gene.clone()) {
+                continue;
+            }
+            candidate.pctg_region = overlap as f64 / region_length as f64 * 100.0;
+            candidate.overlap_bp = overlap;
+        } else if config.best_only && !chosen {
+            continue;
+        }
+        candidate.selected = Some(chosen);
+        rows.push(candidate);
+    }
+    rows
+}
+
+/// Match a single region to genes and filter the candidates for the report level.
+///
+/// Combines [`match_region_to_genes`] and [`process_candidates_for_output`],
+/// keeping the number of candidate genes seen before filtering. `genes` are
+/// all genes of the region's chromosome, for the options that search beyond
+/// the distance cutoff (see `Config::always_closest`, `Config::n_closest`
+/// and `Config::report_intergenic`).
+pub fn match_region(
+    region: &Region,
+    genes: &[Gene],
+    config: &Config,
+    last_index: usize,
+) -> RegionMatches {
+    // The gene ends are only needed by the searches without cutoff
+    let max_ends = OnceCell::new();
+    let first_reaching =
+        |pos| first_gene_ending_after(max_ends.get_or_init(|| running_max_ends(genes)), pos);
+    match_region_from(region, genes, &first_reaching, config, last_index)
+}
+
+/// [`match_region`] with the chromosome's [`FirstReaching`] lookup.
+fn match_region_from(
+    region: &Region,
+    genes: &[Gene],
+    first_reaching: FirstReaching,
+    config: &Config,
+    last_index: usize,
+) -> RegionMatches {
+    let candidates = match_region_to_genes(region, genes, config, last_index);
+    finish_matches(region, candidates, genes, first_reaching, config)
+}
+
+/// Match a single region using [`AnnotationSource::genes_in_range`] (the
+/// interval index of a GTF annotation) to find the genes within reach, then
+/// filter the candidates for the report level.
+///
+/// Gives the same result as [`match_region`] over the region's chromosome.
+pub fn match_region_indexed(
+    region: &Region,
+    source: &dyn AnnotationSource,
+    config: &Config,
+) -> RegionMatches {
+    // Every gene the scan could associate lies within the lookback distance
+    let reach = config.max_lookback_distance() + 1;
+    let genes = source.genes_in_range(
+        &region.chrom,
+        region.start.saturating_sub(reach),
+        region.end.saturating_add(reach),
+    );
+
+    let candidates = match_region_to_gene_iter(region, genes.into_iter(), config, config.distance);
+    let first_reaching = |pos| source.first_gene_reaching(&region.chrom, pos);
+    finish_matches(
+        region,
+        candidates,
+        source.genes(&region.chrom),
+        &first_reaching,
+        config,
+    )
+}
+
+/// Classify a single position on `chrom` against the genes of `source`.
+///
+/// `pos` is read in `config.output_coordinate_convention`: with the default
+/// `bed0` convention, position `p` is the base of the BED interval `p..p + 1`,
+/// so a position is never a zero-length region. The base is matched as a 1 bp
+/// region and its candidates are filtered for the report level, as for a BED
+/// region. A chromosome without genes gives no candidates.
+pub fn classify_position(
+    chrom: &str,
+    pos: i64,
+    source: &dyn AnnotationSource,
+    config: &Config,
+) -> Vec<Candidate> {
+    match_region_indexed(&position_region(chrom, pos, config), source, config).candidates
+}
+
+/// The 1 bp region, in matching coordinates, of a position given in the
+/// configured output convention.
+pub(crate) fn position_region(chrom: &str, pos: i64, config: &Config) -> Region {
+    let base = config.output_coordinate_convention.to_one_based_start(pos);
+    Region::new(chrom.to_string(), base, base, vec![])
+}
+
+/// Best candidates per transcript, by the rules or by score as configured.
+fn select_per_transcript(candidates: &[Candidate], config: &Config) -> Vec<Candidate> {
+    let by_transcript = group_positions(candidates, |c| c.transcript.as_str());
+    match config.selection {
+        SelectionMode::Rules => apply_rules_grouped(
+            candidates,
+            &by_transcript,
+            config.perc_region,
+            config.perc_area,
+            config.min_overlap_bp,
+            &config.rules,
+            config.tie_break,
+            config.thresholds_are_hard_filters,
+        ),
+        SelectionMode::Score => apply_scores_grouped(
+            candidates,
+            &by_transcript,
+            &config.area_weights,
+            config.score_combination,
+            &config.rules,
+        ),
+    }
+}
+
 pub fn process_candidates_for_output(
     candidates: Vec<Candidate>,
     config: &Config,
@@ -1047,7 +2083,81 @@ pub fn process_candidates_for_output(
     if candidates.is_empty() {
         return candidates;
     }
+    let bidirectional = if config.flags_bidirectional() {
+        bidirectional_genes(&candidates, config.bidirectional_window)
+    } else {
+        AHashSet::new()
+    };
+    let mut candidates = if config.report_all_candidates {
+        flag_selected(candidates, config)
+    } else {
+        select_for_level(candidates, config)
+    };
+    if !bidirectional.is_empty() {
+        for candidate in &mut candidates {
+            candidate.bidirectional = bidirectional.contains(candidate.gene.as_str());
+        }
+    }
+    candidates
+}
+
+/// TSS of a candidate whose feature is a first exon: its 5' end.
+fn first_exon_tss(candidate: &Candidate) -> i64 {
+    match candidate.strand {
+        Strand::Positive => candidate.start,
+        Strand::Negative => candidate.end,
+    }
+}
+
+/// Genes whose TSS or PROMOTER candidates pair with those of a gene on the
+/// opposite strand with a TSS at most `window` bp away.
+fn bidirectional_genes(candidates: &[Candidate], window: i64) -> AHashSet<String> {
+    let tss: Vec<(&str, Strand, i64)> = candidates
+        .iter()
+        .filter(|c| matches!(c.area, Area::Tss | Area::Promoter))
+        .map(|c| (c.gene.as_str(), c.strand, first_exon_tss(c)))
+        .collect();
+
+    let mut genes = AHashSet::new();
+    for (i, &(gene, strand, pos)) in tss.iter().enumerate() {
+        for &(other_gene, other_strand, other_pos) in &tss[i + 1..] {
+            if strand != other_strand && gene != other_gene && (pos - other_pos).abs() <= window {
+                genes.insert(gene.to_string());
+                genes.insert(other_gene.to_string());
+            }
+        }
+    }
+    genes
+}
+
+/// Every candidate, with `selected` set on those the report level keeps.
+///
+/// A candidate is selected when a kept row has its gene and area and lists
+/// its transcript, so the transcripts merged into one gene-level row are
+/// all selected.
+fn flag_selected(mut candidates: Vec<Candidate>, config: &Config) -> Vec<Candidate> {
+    let kept = select_for_level(candidates.clone(), config);
+    let kept_keys: AHashSet<(&str, &str, Area)> = kept
+        .iter()
+        .flat_map(|c| {
+            c.transcript
+                .split(config.merge_separator.as_str())
+                .map(move |transcript| (c.gene.as_str(), transcript, c.area))
+        })
+        .collect();
+    for candidate in &mut candidates {
+        let key = (
+            candidate.gene.as_str(),
+            candidate.transcript.as_str(),
+            candidate.area,
+        );
+        candidate.selected = Some(kept_keys.contains(&key));
+    }
+    candidates
+}
 
+/// The candidates reported at the configured level.
+fn select_for_level(candidates: Vec<Candidate>, config: &Config) -> Vec<Candidate> {
     // filter_by_transcript helper removed (unused logic)
 
     match config.level {
@@ -1061,72 +2171,117 @@ pub fn process_candidates_for_output(
         }
         ReportLevel::Transcript => {
             // Transcript Level Logic: Best candidate per transcript.
-
-            // Group by transcript for apply_rules
-            let mut by_transcript: AHashMap<String, Vec<usize>> = AHashMap::new();
-            for (i, c) in candidates.iter().enumerate() {
-                by_transcript
-                    .entry(c.transcript.clone())
-                    .or_default()
-                    .push(i);
-            }
-
-            apply_rules(
-                &candidates,
-                &by_transcript,
-                config.perc_region,
-                config.perc_area,
-                &config.rules,
-            )
+            dedup_identical_rows(select_per_transcript(&candidates, config))
         }
         ReportLevel::Gene => {
             // Gene Level Logic: Best transcript per gene.
 
-            // 1. Filter per transcript (Best candidate per transcript)
-            let mut by_transcript: AHashMap<String, Vec<usize>> = AHashMap::new();
-            for (i, c) in candidates.iter().enumerate() {
-                by_transcript
-                    .entry(c.transcript.clone())
-                    .or_default()
-                    .push(i);
-            }
-
-            let transcript_results = apply_rules(
-                &candidates,
-                &by_transcript,
-                config.perc_region,
-                config.perc_area,
-                &config.rules,
-            );
+            // 1. Filter per transcript (Best candidate per transcript).
+            // Identical ties would otherwise be listed twice in the merged row
+            let transcript_results =
+                dedup_identical_rows(select_per_transcript(&candidates, config));
 
             // 2. Select best transcript per gene
-            let mut by_gene: AHashMap<String, Vec<usize>> = AHashMap::new();
-            for (i, c) in transcript_results.iter().enumerate() {
-                by_gene.entry(c.gene.clone()).or_default().push(i);
-            }
+            let by_gene = group_positions(&transcript_results, |c| c.gene.as_str());
 
-            select_transcript(&transcript_results, &by_gene, &config.rules)
+            match config.selection {
+                SelectionMode::Rules
+                    if config.gene_level_style == GeneLevelStyle::BestTranscript =>
+                {
+                    select_best_transcript_grouped(&transcript_results, &by_gene, &config.rules)
+                }
+                SelectionMode::Rules => select_transcript_grouped(
+                    &transcript_results,
+                    &by_gene,
+                    &config.rules,
+                    &config.merge_separator,
+                    config.legacy_merge,
+                    config.tie_break,
+                ),
+                SelectionMode::Score => apply_scores_grouped(
+                    &transcript_results,
+                    &by_gene,
+                    &config.area_weights,
+                    config.score_combination,
+                    &config.rules,
+                ),
+            }
         }
     }
 }
 
 /// Main entry point for matching regions to genes.
+#[deprecated(note = "use `associate_regions`, which returns `Association` values")]
 pub fn match_regions_to_genes(
     regions: &[Region],
     genes: &[Gene],
     config: &Config,
     max_gene_length: i64,
 ) -> Vec<(Region, Vec<Candidate>)> {
-    // Genes must be pre-sorted by start position
+    associate_regions(regions, genes, config, max_gene_length)
+        .into_iter()
+        .map(|association| (association.region, association.candidates))
+        .collect()
+}
 
-    let mut results = Vec::new();
+/// Match sorted regions of one chromosome to its genes.
+///
+/// Genes must be sorted by start. `max_gene_length` is no longer used: the
+/// scan skips the genes ending before each region's lookback window, whatever
+/// the length of the longest gene.
+pub fn associate_regions(
+    regions: &[Region],
+    genes: &[Gene],
+    config: &Config,
+    _max_gene_length: i64,
+) -> Vec<Association> {
+    associate_from(regions, genes, &running_max_ends(genes), config, 0)
+}
+
+/// Match sorted regions of one chromosome to its genes on several threads.
+///
+/// The regions are split into chunks of `chunk_size` that are matched in
+/// parallel; each chunk finds its first gene with a binary search over the
+/// running maximum of the gene ends, so no chunk depends on the scan position
+/// of the previous one. The associations are returned in region order and
+/// equal those of [`associate_regions`].
+pub fn associate_regions_par(
+    regions: &[Region],
+    genes: &[Gene],
+    config: &Config,
+    chunk_size: usize,
+) -> Vec<Association> {
+    use rayon::prelude::*;
+
+    let max_lookback = config.max_lookback_distance();
+    let max_ends = running_max_ends(genes);
+
+    regions
+        .par_chunks(chunk_size.max(1))
+        .flat_map_iter(|chunk| {
+            let search_start = chunk[0].start.saturating_sub(max_lookback);
+            let start_index = first_gene_ending_after(&max_ends, search_start);
+            associate_from(chunk, genes, &max_ends, config, start_index)
+        })
+        .collect()
+}
 
-    let max_lookback = max_gene_length + config.max_lookback_distance();
-    let mut last_index = 0;
+/// Match sorted regions, scanning the genes from `start_index`. `max_ends`
+/// are the [`running_max_ends`] of `genes`.
+fn associate_from(
+    regions: &[Region],
+    genes: &[Gene],
+    max_ends: &[i64],
+    config: &Config,
+    start_index: usize,
+) -> Vec<Association> {
+    let mut results = Vec::with_capacity(regions.len());
+
+    let max_lookback = config.max_lookback_distance();
+    let mut last_index = start_index;
 
     for region in regions {
-        // Calculate safe search start for this region
-        // We need to look back enough to find genes that started earlier but extend into this region
+        // Genes ending before the lookback window cannot be associated
         let search_start = region.start.saturating_sub(max_lookback);
 
         // Advance last_index safe: skip genes that end before the search start
@@ -1137,14 +2292,96 @@ pub fn match_regions_to_genes(
         }
 
         // Pass the calculated start index by value (no mutation allowed inside)
-        let candidates = match_region_to_genes(region, genes, config, last_index);
-        let processed = process_candidates_for_output(candidates, config);
-        results.push((region.clone(), processed));
+        let first_reaching = |pos| first_gene_ending_after(max_ends, pos);
+        let matches = match_region_from(region, genes, &first_reaching, config, last_index);
+        results.push(matches.into_association(region.clone()));
     }
 
     results
 }
 
+/// Matches a stream of regions against all chromosomes of an annotation.
+///
+/// Remembers where the previous region's gene search started, so regions
+/// sorted by position on a chromosome only scan forward; other regions fall
+/// back to a binary search. Any [`AnnotationSource`] can be matched, such as
+/// a parsed GTF file.
+pub struct RegionMatcher<'a> {
+    source: &'a dyn AnnotationSource,
+    config: &'a Config,
+    cursor: SearchCursor,
+}
+
+impl<'a> RegionMatcher<'a> {
+    /// Create a matcher over the genes of `source` using `config`.
+    pub fn new(source: &'a dyn AnnotationSource, config: &'a Config) -> Self {
+        RegionMatcher {
+            source,
+            config,
+            cursor: SearchCursor::default(),
+        }
+    }
+
+    /// Match the next region. Regions on chromosomes without genes get no
+    /// candidates, or only an intergenic one with `report_intergenic`.
+    pub fn match_next(&mut self, region: &Region) -> RegionMatches {
+        self.cursor.match_region(region, self.source, self.config)
+    }
+}
+
+/// Position of the previous gene search, shared by the streaming matchers.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SearchCursor {
+    chrom: String,
+    start: i64,
+    index: usize,
+}
+
+impl SearchCursor {
+    /// Match `region`, starting the gene scan from the previous position when
+    /// the region follows the previous one on the same chromosome.
+    pub(crate) fn match_region(
+        &mut self,
+        region: &Region,
+        source: &dyn AnnotationSource,
+        config: &Config,
+    ) -> RegionMatches {
+        if config.use_interval_index {
+            return match_region_indexed(region, source, config);
+        }
+
+        let genes = source.genes(&region.chrom);
+        if genes.is_empty() {
+            self.chrom.clone_from(&region.chrom);
+            self.index = 0;
+            return finish_matches(region, Vec::new(), genes, &|_| 0, config);
+        }
+
+        // Skip the genes ending before the lookback window; a long gene that
+        // starts earlier but reaches the window is kept
+        let search_start = region.start.saturating_sub(config.max_lookback_distance());
+
+        let start_index = if region.chrom == self.chrom && region.start >= self.start {
+            // Sorted input: advance from the previous position, before which
+            // no gene reaches an earlier window
+            let mut idx = self.index;
+            while idx < genes.len() && genes[idx].end < search_start {
+                idx += 1;
+            }
+            idx
+        } else {
+            source.first_gene_reaching(&region.chrom, search_start)
+        };
+
+        self.chrom.clone_from(&region.chrom);
+        self.start = region.start;
+        self.index = start_index;
+
+        let first_reaching = |pos| source.first_gene_reaching(&region.chrom, pos);
+        match_region_from(region, genes, &first_reaching, config, start_index)
+    }
+}
+
 /// Find the index of the first gene that could potentially overlap with a region.
 ///
 /// Uses binary search to find the first gene with `start >= search_start`.
@@ -3,41 +3,119 @@
 //! This module implements the applyRules and selectTranscript functions
 //! for filtering and selecting the best candidates based on priority rules.
 
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
 use ahash::{AHashMap, AHashSet};
+use indexmap::IndexMap;
+
+use crate::types::{Area, Candidate, ExonNumber, ScoreCombination, TieBreak};
+
+/// Order of tied candidates: by transcript ID, then exon number (both
+/// compared as text).
+fn tie_order(a: &Candidate, b: &Candidate) -> Ordering {
+    a.transcript
+        .cmp(&b.transcript)
+        .then_with(|| a.exon_number.cmp(&b.exon_number))
+}
+
+/// Order of tied candidates under `tie_break`, best first. `ReportAll`
+/// keeps [`tie_order`].
+fn tie_break_order(tie_break: TieBreak, a: &Candidate, b: &Candidate) -> Ordering {
+    match tie_break {
+        TieBreak::ReportAll => Ordering::Equal,
+        TieBreak::TssDistance => a
+            .tss_distance
+            .unsigned_abs()
+            .cmp(&b.tss_distance.unsigned_abs())
+            .then_with(|| a.gene.cmp(&b.gene)),
+        TieBreak::GeneId => a.gene.cmp(&b.gene),
+    }
+    .then_with(|| tie_order(a, b))
+}
 
-use crate::types::{Area, Candidate};
+/// Key of the candidate groups passed to the selection functions: a gene or
+/// transcript ID, as a [`Name`](crate::types::Name) or a `String`.
+pub trait GroupKey: Borrow<str> + Eq + Hash + Ord {}
 
-/// Order keys by their first appearance in the candidates list.
+impl<K: Borrow<str> + Eq + Hash + Ord> GroupKey for K {}
+
+/// Candidate positions of each group, with the groups ordered by their first
+/// appearance in the candidates list.
 ///
 /// This preserves "insertion order" (file order) to match Python behavior.
-/// Keys not found in candidates are sorted and appended at the end.
-fn order_keys_by_occurrence<'a, F>(
+/// Groups whose key is not found in candidates are appended at the end,
+/// sorted by key.
+fn groups_by_occurrence<'a, K, F>(
     candidates: &'a [Candidate],
-    grouped_by: &'a AHashMap<String, Vec<usize>>,
+    grouped_by: &'a AHashMap<K, Vec<usize>>,
     key_fn: F,
-) -> Vec<&'a String>
+) -> Vec<&'a [usize]>
 where
-    F: Fn(&Candidate) -> &String,
+    K: GroupKey,
+    F: Fn(&Candidate) -> &str,
 {
-    let mut key_order = Vec::new();
-    let mut seen = AHashSet::new();
+    let mut groups = Vec::new();
+    let mut seen: AHashSet<&str> = AHashSet::new();
 
     // Iterate candidates to find unique keys in order of first appearance
     for c in candidates {
-        let key = key_fn(c);
-        if grouped_by.contains_key(key) && !seen.contains(key) {
-            seen.insert(key);
-            key_order.push(key);
+        if let Some((key, positions)) = grouped_by.get_key_value(key_fn(c)) {
+            if seen.insert(key.borrow()) {
+                groups.push(positions.as_slice());
+            }
         }
     }
 
     // Add any keys from grouped_by that weren't in candidates (unlikely but safe)
-    let mut remaining_keys: Vec<&String> =
-        grouped_by.keys().filter(|k| !seen.contains(*k)).collect();
-    remaining_keys.sort();
-    key_order.extend(remaining_keys);
+    let mut remaining: Vec<(&K, &Vec<usize>)> = grouped_by
+        .iter()
+        .filter(|(k, _)| !seen.contains((*k).borrow()))
+        .collect();
+    remaining.sort_by(|a, b| a.0.cmp(b.0));
+    groups.extend(
+        remaining
+            .into_iter()
+            .map(|(_, positions)| positions.as_slice()),
+    );
+
+    groups
+}
 
-    key_order
+/// Split `positions` into one group per gene, in order of first appearance.
+fn split_by_gene(candidates: &[Candidate], positions: &[usize]) -> Vec<Vec<usize>> {
+    let mut groups: IndexMap<&str, Vec<usize>> = IndexMap::new();
+    for &pos in positions {
+        groups
+            .entry(candidates[pos].gene.as_str())
+            .or_default()
+            .push(pos);
+    }
+    groups.into_values().collect()
+}
+
+/// Positions of the candidates sharing a `key`, one group per key in order
+/// of its first candidate.
+///
+/// The keys are borrowed from the candidates, so grouping allocates no
+/// string. This is the grouping the `*_grouped` selection functions take.
+pub fn group_positions<'c>(
+    candidates: &'c [Candidate],
+    key: impl Fn(&'c Candidate) -> &'c str,
+) -> Vec<Vec<usize>> {
+    let mut group_of: AHashMap<&str, usize> = AHashMap::with_capacity(candidates.len());
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (pos, candidate) in candidates.iter().enumerate() {
+        let next = groups.len();
+        let group = *group_of.entry(key(candidate)).or_insert(next);
+        if group == next {
+            groups.push(Vec::new());
+        }
+        groups[group].push(pos);
+    }
+    groups
 }
 
 /// Apply priority rules to select the best candidate per group.
@@ -45,6 +123,12 @@ where
 /// Filters candidates by percentage thresholds and applies rule-based
 /// priority ordering to resolve ties.
 ///
+/// # Ordering
+/// The output does not depend on the iteration order of `grouped_by`: groups
+/// are reported in order of their first candidate in `candidates`, and the
+/// candidates of a group that tie on the winning rule are reported by
+/// transcript ID, then exon number.
+///
 /// # Arguments
 /// * `candidates` - List of Candidate objects to filter
 /// * `grouped_by` - Map from group ID to list of candidate indices
@@ -54,30 +138,100 @@ where
 ///
 /// # Returns
 /// Filtered list of Candidate objects to report.
-pub fn apply_rules(
+pub fn apply_rules<K: GroupKey>(
     candidates: &[Candidate],
-    grouped_by: &AHashMap<String, Vec<usize>>,
+    grouped_by: &AHashMap<K, Vec<usize>>,
     perc_region: f64,
     perc_area: f64,
     rules: &[Area],
 ) -> Vec<Candidate> {
-    let mut to_report = Vec::new();
+    apply_rules_with(
+        candidates,
+        grouped_by,
+        perc_region,
+        perc_area,
+        0,
+        rules,
+        TieBreak::ReportAll,
+        false,
+    )
+}
 
-    let key_order = order_keys_by_occurrence(candidates, grouped_by, |c| &c.transcript);
+/// Apply priority rules with an absolute overlap threshold.
+///
+/// When `min_overlap_bp` is above 0, the first filter keeps the candidates
+/// whose [`Candidate::overlap_bp`] reaches it, instead of those covering
+/// `perc_region` percent of the region. As with the percentage, the
+/// remaining steps use every candidate when none passes.
+///
+/// Candidates still tied after the rules are all reported with
+/// `TieBreak::ReportAll`; otherwise only the first by `tie_break` is.
+///
+/// With `hard_thresholds`, a group none of whose candidates passes the first
+/// filter reports nothing, instead of falling back to all of them; this also
+/// drops a lone candidate that fails it. `min_overlap_bp` of 0 with
+/// `TieBreak::ReportAll` and without `hard_thresholds` is [`apply_rules`].
+#[allow(clippy::too_many_arguments)]
+pub fn apply_rules_with<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+    perc_region: f64,
+    perc_area: f64,
+    min_overlap_bp: i64,
+    rules: &[Area],
+    tie_break: TieBreak,
+    hard_thresholds: bool,
+) -> Vec<Candidate> {
+    apply_rules_grouped(
+        candidates,
+        &groups_by_occurrence(candidates, grouped_by, |c| c.transcript.as_str()),
+        perc_region,
+        perc_area,
+        min_overlap_bp,
+        rules,
+        tie_break,
+        hard_thresholds,
+    )
+}
 
-    for key in key_order {
-        let positions = &grouped_by[key];
+/// [`apply_rules_with`] on groups of candidate positions, reported in the
+/// order of `groups`, such as those of [`group_positions`].
+#[allow(clippy::too_many_arguments)]
+pub fn apply_rules_grouped<G: AsRef<[usize]>>(
+    candidates: &[Candidate],
+    groups: &[G],
+    perc_region: f64,
+    perc_area: f64,
+    min_overlap_bp: i64,
+    rules: &[Area],
+    tie_break: TieBreak,
+    hard_thresholds: bool,
+) -> Vec<Candidate> {
+    let passes_region = |c: &Candidate| {
+        if min_overlap_bp > 0 {
+            c.overlap_bp >= min_overlap_bp
+        } else {
+            c.pctg_region >= perc_region
+        }
+    };
+    let mut to_report = Vec::new();
+
+    for positions in groups {
+        let positions = positions.as_ref();
         if positions.len() == 1 {
-            to_report.push(candidates[positions[0]].clone());
+            let candidate = &candidates[positions[0]];
+            if !hard_thresholds || passes_region(candidate) {
+                to_report.push(candidate.clone());
+            }
             continue;
         }
 
-        // Step 1: Filter by %Region threshold
+        // Step 1: Filter by %Region (or overlap bp) threshold
         let mut tmp_results_region: Vec<&Candidate> = positions
             .iter()
             .filter_map(|&pos| {
                 let c = &candidates[pos];
-                if c.pctg_region >= perc_region {
+                if passes_region(c) {
                     Some(c)
                 } else {
                     None
@@ -92,6 +246,9 @@ pub fn apply_rules(
 
         // If none pass, fallback to all candidates
         if tmp_results_region.is_empty() {
+            if hard_thresholds {
+                continue;
+            }
             tmp_results_region = positions.iter().map(|&pos| &candidates[pos]).collect();
         }
 
@@ -131,15 +288,18 @@ pub fn apply_rules(
                 } else {
                     // Step 4: Apply rules priority order for final selection
                     // Report all that match the first matching rule (ties allowed)
-                    let mut found = false;
                     for &area_rule in rules {
-                        for &candidate in &region_candidates {
-                            if candidate.area == area_rule {
-                                to_report.push(candidate.clone());
-                                found = true;
+                        let mut tied: Vec<&Candidate> = region_candidates
+                            .iter()
+                            .filter(|c| c.area == area_rule)
+                            .copied()
+                            .collect();
+                        if !tied.is_empty() {
+                            tied.sort_by(|a, b| tie_break_order(tie_break, a, b));
+                            if tie_break != TieBreak::ReportAll {
+                                tied.truncate(1);
                             }
-                        }
-                        if found {
+                            to_report.extend(tied.into_iter().cloned());
                             break;
                         }
                     }
@@ -151,10 +311,145 @@ pub fn apply_rules(
     to_report
 }
 
+/// Pick the highest-scoring candidate among `positions`.
+///
+/// Equal scores go to the area listed first in `rules`, then to the lowest
+/// transcript ID and exon number.
+fn best_by_score<'a>(
+    candidates: &'a [Candidate],
+    positions: &[usize],
+    weights: &BTreeMap<Area, f64>,
+    combination: ScoreCombination,
+    rules: &[Area],
+) -> Option<&'a Candidate> {
+    let rank = |c: &Candidate| {
+        rules
+            .iter()
+            .position(|&area| area == c.area)
+            .unwrap_or(rules.len())
+    };
+    let score = |c: &Candidate| combination.score(weights.get(&c.area).copied().unwrap_or(1.0), c);
+    positions
+        .iter()
+        .map(|&pos| &candidates[pos])
+        .min_by(|a, b| {
+            score(b)
+                .total_cmp(&score(a))
+                .then_with(|| rank(a).cmp(&rank(b)))
+                .then_with(|| tie_order(a, b))
+        })
+}
+
+/// Select the best candidate per group by score instead of rules.
+///
+/// Each candidate scores `combination` of its area weight (1 for areas
+/// missing from `weights`), overlap percentages and distance; the highest
+/// score wins, so exactly one candidate is reported per group. Equal scores
+/// are broken by the priority of the area in `rules`, then by transcript ID
+/// and exon number.
+///
+/// # Ordering
+/// Groups are reported in order of their first candidate in `candidates`.
+pub fn apply_scores<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+    weights: &BTreeMap<Area, f64>,
+    combination: ScoreCombination,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    apply_scores_grouped(
+        candidates,
+        &groups_by_occurrence(candidates, grouped_by, |c| c.transcript.as_str()),
+        weights,
+        combination,
+        rules,
+    )
+}
+
+/// [`apply_scores`] on groups of candidate positions, reported in the order
+/// of `groups`.
+pub fn apply_scores_grouped<G: AsRef<[usize]>>(
+    candidates: &[Candidate],
+    groups: &[G],
+    weights: &BTreeMap<Area, f64>,
+    combination: ScoreCombination,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    groups
+        .iter()
+        .filter_map(|positions| {
+            best_by_score(candidates, positions.as_ref(), weights, combination, rules).cloned()
+        })
+        .collect()
+}
+
+/// Select the best transcript per gene by score instead of rules.
+///
+/// The score-based counterpart of [`select_transcript`]: the
+/// highest-scoring candidate of each gene is reported as is, without
+/// merging, with the same tie-breaking as [`apply_scores`].
+pub fn select_transcript_by_score<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+    weights: &BTreeMap<Area, f64>,
+    combination: ScoreCombination,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    apply_scores_grouped(
+        candidates,
+        &gene_groups(candidates, grouped_by),
+        weights,
+        combination,
+        rules,
+    )
+}
+
+/// Select the single best candidate among all of a region's candidates.
+///
+/// Runs `apply_rules` with every candidate in one group, so the usual
+/// thresholds and priority order decide. Remaining ties go to the candidate
+/// that appears first.
+pub fn select_best_candidate(
+    candidates: &[Candidate],
+    perc_region: f64,
+    perc_area: f64,
+    rules: &[Area],
+) -> Option<Candidate> {
+    if candidates.is_empty() {
+        return None;
+    }
+
+    let all: Vec<usize> = (0..candidates.len()).collect();
+    apply_rules_grouped(
+        candidates,
+        &[all],
+        perc_region,
+        perc_area,
+        0,
+        rules,
+        TieBreak::ReportAll,
+        false,
+    )
+    .into_iter()
+    .next()
+    .or_else(|| candidates.first().cloned())
+}
+
 /// Select best transcript from candidates grouped by gene.
 ///
 /// Applies priority rules and merges tied candidates into a single
 /// representative with combined transcript/exon information.
+/// Merged candidates keep the feature coordinates of the tied candidate
+/// with the lowest transcript ID, then exon number (the reference
+/// candidate), so at most one candidate is returned per gene. Candidates
+/// of different genes are never merged, even when `grouped_by` puts them in
+/// one group.
+///
+/// # Ordering
+/// Genes are reported in order of their first candidate in `candidates`,
+/// whatever the iteration order of `grouped_by`.
+///
+/// Merged lists are joined with `,`; see [`select_transcript_with`].
 ///
 /// # Arguments
 /// * `candidates` - List of Candidate objects to filter
@@ -163,92 +458,213 @@ pub fn apply_rules(
 ///
 /// # Returns
 /// Filtered list of Candidate objects with merged tie information.
-pub fn select_transcript(
+pub fn select_transcript<K: GroupKey>(
     candidates: &[Candidate],
-    grouped_by: &AHashMap<String, Vec<usize>>,
+    grouped_by: &AHashMap<K, Vec<usize>>,
     rules: &[Area],
 ) -> Vec<Candidate> {
-    let mut to_report = Vec::new();
+    select_transcript_with(
+        candidates,
+        grouped_by,
+        rules,
+        ",",
+        false,
+        TieBreak::ReportAll,
+    )
+}
 
-    // Iterate keys in order of first appearance in candidates (grouped by Gene ID)
-    let key_order = order_keys_by_occurrence(candidates, grouped_by, |c| &c.gene);
+/// Candidate positions of each gene, genes in order of first appearance in
+/// `candidates`. A group of `grouped_by` never mixes genes in the result.
+fn gene_groups<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+) -> Vec<Vec<usize>> {
+    groups_by_occurrence(candidates, grouped_by, |c| c.gene.as_str())
+        .into_iter()
+        .flat_map(|positions| split_by_gene(candidates, positions))
+        .collect()
+}
 
-    for key in key_order {
-        let positions = &grouped_by[key];
-        if positions.len() == 1 {
-            to_report.push(candidates[positions[0]].clone());
-            continue;
-        }
+/// Positions of the candidates in the area ranked first by `rules`, or in
+/// the area of the first candidate if the rules list none of them.
+fn area_winner_positions(
+    candidates: &[Candidate],
+    positions: &[usize],
+    rules: &[Area],
+) -> Option<Vec<usize>> {
+    // Group by area
+    let mut by_area: AHashMap<Area, Vec<usize>> = AHashMap::new();
+    for &pos in positions {
+        let candidate = &candidates[pos];
+        by_area.entry(candidate.area).or_default().push(pos);
+    }
 
-        // Group by area
-        let mut by_area: AHashMap<Area, Vec<usize>> = AHashMap::new();
-        for &pos in positions {
-            let candidate = &candidates[pos];
-            by_area.entry(candidate.area).or_default().push(pos);
+    // Apply rules to find winning area
+    let mut area_winner: Option<Area> = None;
+    for &area_rule in rules {
+        if by_area.contains_key(&area_rule) {
+            area_winner = Some(area_rule);
+            break;
         }
+    }
 
-        // Apply rules to find winning area
-        let mut area_winner: Option<Area> = None;
-        for &area_rule in rules {
-            if by_area.contains_key(&area_rule) {
-                area_winner = Some(area_rule);
-                break;
-            }
+    // Fallback to first available candidate's Area if no rules match
+    // "First" means the first one in the list of positions, which preserves order.
+    if area_winner.is_none() {
+        if let Some(&first_pos) = positions.first() {
+            area_winner = Some(candidates[first_pos].area);
         }
+    }
 
-        // Fallback to first available candidate's Area if no rules match
-        // "First" means the first one in the list of positions, which preserves order.
-        if area_winner.is_none() {
-            if let Some(&first_pos) = positions.first() {
-                area_winner = Some(candidates[first_pos].area);
-            }
+    area_winner.and_then(|area| by_area.remove(&area))
+}
+
+/// Select one transcript per gene, reported without merging.
+///
+/// The rules pick the winning area of each gene as in
+/// [`select_transcript`]; among its candidates, the one with the highest
+/// `pctg_region`, then the smallest absolute TSS distance, then the lowest
+/// transcript ID and exon number is reported unchanged.
+pub fn select_best_transcript<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+    rules: &[Area],
+) -> Vec<Candidate> {
+    select_best_transcript_grouped(candidates, &gene_groups(candidates, grouped_by), rules)
+}
+
+/// [`select_best_transcript`] on groups of candidate positions, each of one
+/// gene, reported in the order of `groups`.
+pub fn select_best_transcript_grouped<G: AsRef<[usize]>>(
+    candidates: &[Candidate],
+    groups: &[G],
+    rules: &[Area],
+) -> Vec<Candidate> {
+    groups
+        .iter()
+        .filter_map(|positions| {
+            area_winner_positions(candidates, positions.as_ref(), rules)?
+                .into_iter()
+                .map(|pos| &candidates[pos])
+                .min_by(|a, b| {
+                    b.pctg_region
+                        .total_cmp(&a.pctg_region)
+                        .then_with(|| {
+                            a.tss_distance
+                                .unsigned_abs()
+                                .cmp(&b.tss_distance.unsigned_abs())
+                        })
+                        .then_with(|| tie_order(a, b))
+                })
+                .cloned()
+        })
+        .collect()
+}
+
+/// Select the best transcript per gene, with explicit merge settings.
+///
+/// Tied transcripts are sorted by ID and joined with `separator`; their exon
+/// numbers follow the same order, with repeated numbers removed. With
+/// `legacy_merge`, tied candidates are instead listed in input order with one
+/// exon entry per transcript, like the Python implementation.
+///
+/// # Ordering
+/// Genes are reported in order of their first candidate in `candidates`,
+/// whatever the iteration order of `grouped_by`. The reference candidate of a
+/// merge is the first tied candidate by transcript ID, then exon number, or
+/// the first in input order with `legacy_merge`. Other than
+/// `TieBreak::ReportAll`, `tie_break` picks the reference candidate instead;
+/// the merged lists are unchanged.
+pub fn select_transcript_with<K: GroupKey>(
+    candidates: &[Candidate],
+    grouped_by: &AHashMap<K, Vec<usize>>,
+    rules: &[Area],
+    separator: &str,
+    legacy_merge: bool,
+    tie_break: TieBreak,
+) -> Vec<Candidate> {
+    select_transcript_grouped(
+        candidates,
+        &gene_groups(candidates, grouped_by),
+        rules,
+        separator,
+        legacy_merge,
+        tie_break,
+    )
+}
+
+/// [`select_transcript_with`] on groups of candidate positions, each of one
+/// gene, reported in the order of `groups`.
+pub fn select_transcript_grouped<G: AsRef<[usize]>>(
+    candidates: &[Candidate],
+    groups: &[G],
+    rules: &[Area],
+    separator: &str,
+    legacy_merge: bool,
+    tie_break: TieBreak,
+) -> Vec<Candidate> {
+    let mut to_report = Vec::new();
+
+    for positions in groups {
+        let positions = positions.as_ref();
+        if positions.len() == 1 {
+            to_report.push(candidates[positions[0]].clone());
+            continue;
         }
 
-        let area_winner = match area_winner {
-            Some(a) => a,
+        let winner_positions = match area_winner_positions(candidates, positions, rules) {
+            Some(winners) => winners,
             None => continue,
         };
 
-        let winner_positions = &by_area[&area_winner];
-
         if winner_positions.len() == 1 {
             to_report.push(candidates[winner_positions[0]].clone());
         } else {
             // Merge all tied candidates
-            let mut transcripts = String::new();
-            let mut exons = String::new();
+            let mut merge_order = winner_positions.clone();
+            if !legacy_merge {
+                merge_order.sort_by(|&a, &b| tie_order(&candidates[a], &candidates[b]));
+            }
+
+            let mut transcripts: Vec<&str> = Vec::with_capacity(merge_order.len());
+            let mut exons: Vec<ExonNumber> = Vec::new();
             let mut max_parea = 0.0_f64;
             let mut max_pregion = 0.0_f64;
 
-            for &pos in winner_positions {
+            for &pos in &merge_order {
                 let c = &candidates[pos];
-                transcripts.push_str(&c.transcript);
-                transcripts.push(',');
-                exons.push_str(&c.exon_number);
-                exons.push(',');
+                transcripts.push(&c.transcript);
+                if legacy_merge {
+                    exons.extend(&c.exon_number);
+                } else {
+                    for &exon in &c.exon_number {
+                        if !exons.contains(&exon) {
+                            exons.push(exon);
+                        }
+                    }
+                }
                 max_parea = max_parea.max(c.pctg_area);
                 max_pregion = max_pregion.max(c.pctg_region);
             }
 
-            // Remove trailing comma
-            transcripts.pop();
-            exons.pop();
-
-            // Use first candidate as reference for other fields
-            let ref_candidate = &candidates[winner_positions[0]];
-            let merged = Candidate::new(
-                ref_candidate.start,
-                ref_candidate.end,
-                ref_candidate.strand,
-                exons,
-                ref_candidate.area,
-                transcripts,
-                ref_candidate.gene.clone(),
-                ref_candidate.distance,
-                max_pregion,
-                max_parea,
-                ref_candidate.tss_distance,
-            );
+            let transcripts = transcripts.join(separator);
+
+            // Use first merged candidate as reference for other fields
+            let ref_pos = match tie_break {
+                TieBreak::ReportAll => merge_order[0],
+                _ => *merge_order
+                    .iter()
+                    .min_by(|&&a, &&b| tie_break_order(tie_break, &candidates[a], &candidates[b]))
+                    .unwrap(),
+            };
+            let ref_candidate = &candidates[ref_pos];
+            let merged = Candidate {
+                exon_number: exons.into(),
+                transcript: transcripts.into(),
+                pctg_region: max_pregion,
+                pctg_area: max_parea,
+                ..ref_candidate.clone()
+            };
             to_report.push(merged);
         }
     }
@@ -259,14 +675,14 @@ pub fn select_transcript(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::Strand;
+    use crate::types::{Name, Strand};
 
     fn make_candidate(area: Area, pctg_region: f64, pctg_area: f64, transcript: &str) -> Candidate {
         Candidate::new(
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             area,
             transcript.to_string(),
             "G1".to_string(),
@@ -334,6 +750,25 @@ mod tests {
         let result = apply_rules(&candidates, &grouped_by, 50.0, 90.0, &rules);
 
         assert_eq!(result.len(), 1);
+
+        // Kept below both thresholds, unless they are hard filters
+        let result = apply_rules(&candidates, &grouped_by, 100.1, 100.1, &rules);
+        assert_eq!(result.len(), 1);
+        let hard = |perc_region| {
+            apply_rules_with(
+                &candidates,
+                &grouped_by,
+                perc_region,
+                100.1,
+                0,
+                &rules,
+                TieBreak::ReportAll,
+                true,
+            )
+        };
+        assert!(hard(100.1).is_empty());
+        // Only the region threshold is a hard filter
+        assert_eq!(hard(50.0).len(), 1);
     }
 
     #[test]
@@ -369,6 +804,18 @@ mod tests {
         // Should still pick one based on rules priority
         assert_eq!(result.len(), 1);
         assert_eq!(result[0].area, Area::Tss);
+
+        let hard = apply_rules_with(
+            &candidates,
+            &grouped_by,
+            90.0,
+            90.0,
+            0,
+            &rules,
+            TieBreak::ReportAll,
+            true,
+        );
+        assert!(hard.is_empty());
     }
 
     #[test]
@@ -407,9 +854,9 @@ mod tests {
         let rules = vec![Area::Tss];
 
         let mut c1 = make_candidate(Area::Tss, 80.0, 70.0, "T1");
-        c1.exon_number = "1".to_string();
+        c1.exon_number = 1.into();
         let mut c2 = make_candidate(Area::Tss, 90.0, 60.0, "T2");
-        c2.exon_number = "2".to_string();
+        c2.exon_number = 2.into();
 
         let candidates = vec![c1, c2];
         let mut grouped_by = AHashMap::new();
@@ -420,8 +867,7 @@ mod tests {
         assert_eq!(result.len(), 1);
         assert!(result[0].transcript.contains("T1"));
         assert!(result[0].transcript.contains("T2"));
-        assert!(result[0].exon_number.contains("1"));
-        assert!(result[0].exon_number.contains("2"));
+        assert_eq!(result[0].exon_number, "1,2");
         assert_eq!(result[0].pctg_region, 90.0); // max of 80, 90
         assert_eq!(result[0].pctg_area, 70.0); // max of 70, 60
     }
@@ -459,4 +905,335 @@ mod tests {
         // Both should be reported (tie)
         assert_eq!(result.len(), 2);
     }
+
+    fn three_way_tie() -> (Vec<Candidate>, AHashMap<String, Vec<usize>>) {
+        let exons = [("T3", "2"), ("T1", "4,2"), ("T2", "2")];
+        let candidates: Vec<Candidate> = exons
+            .iter()
+            .map(|(transcript, exon)| {
+                let mut c = make_candidate(Area::Intron, 100.0, 50.0, transcript);
+                c.exon_number = exon.parse().unwrap();
+                c
+            })
+            .collect();
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1, 2]);
+        (candidates, grouped_by)
+    }
+
+    #[test]
+    fn test_select_transcript_merge_sorted_and_deduplicated() {
+        let (candidates, grouped_by) = three_way_tie();
+
+        let result = select_transcript_with(
+            &candidates,
+            &grouped_by,
+            &[Area::Intron],
+            "|",
+            false,
+            TieBreak::ReportAll,
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].transcript, "T1|T2|T3");
+        assert_eq!(result[0].exon_number.join("|"), "4|2");
+
+        // Input order does not change the merged strings
+        let mut reversed = candidates.clone();
+        reversed.reverse();
+        let result = select_transcript_with(
+            &reversed,
+            &grouped_by,
+            &[Area::Intron],
+            "|",
+            false,
+            TieBreak::ReportAll,
+        );
+        assert_eq!(result[0].transcript, "T1|T2|T3");
+        assert_eq!(result[0].exon_number.join("|"), "4|2");
+    }
+
+    #[test]
+    fn test_select_transcript_merge_intron_labels() {
+        // Full-span gene bodies with labelled introns mix both kinds
+        let exons = [("T1", "3,intron_2"), ("T2", "intron_2"), ("T3", "3")];
+        let candidates: Vec<Candidate> = exons
+            .iter()
+            .map(|(transcript, exon)| {
+                let mut c = make_candidate(Area::GeneBody, 100.0, 5.0, transcript);
+                c.exon_number = exon.parse().unwrap();
+                c
+            })
+            .collect();
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1, 2]);
+
+        let result = select_transcript_with(
+            &candidates,
+            &grouped_by,
+            &[Area::GeneBody],
+            ",",
+            false,
+            TieBreak::ReportAll,
+        );
+        assert_eq!(result[0].transcript, "T1,T2,T3");
+        assert_eq!(result[0].exon_number, "3,intron_2");
+    }
+
+    #[test]
+    fn test_select_transcript_legacy_merge() {
+        let (candidates, grouped_by) = three_way_tie();
+
+        let result = select_transcript_with(
+            &candidates,
+            &grouped_by,
+            &[Area::Intron],
+            ",",
+            true,
+            TieBreak::ReportAll,
+        );
+        assert_eq!(result[0].transcript, "T3,T1,T2");
+        assert_eq!(result[0].exon_number, "2,4,2,2");
+    }
+
+    /// Candidates of three genes with ties at the transcript and gene level,
+    /// in an order that is neither sorted by gene nor by transcript.
+    fn tied_genes() -> Vec<Candidate> {
+        let rows = [
+            ("G2", "T5", "3", 100.0),
+            ("G1", "T2", "2", 100.0),
+            ("G3", "T7", "1", 60.0),
+            ("G2", "T4", "1", 100.0),
+            ("G1", "T2", "1", 100.0),
+            ("G1", "T1", "5", 100.0),
+            ("G3", "T6", "2", 60.0),
+        ];
+        rows.iter()
+            .map(|&(gene, transcript, exon, pctg_region)| {
+                let mut c = make_candidate(Area::Tss, pctg_region, 100.0, transcript);
+                c.gene = gene.into();
+                c.exon_number = exon.parse().unwrap();
+                c.start = 100 * exon.parse::<i64>().unwrap();
+                c
+            })
+            .collect()
+    }
+
+    /// Run both rule steps with maps using `state` and format the rows.
+    fn rows_with_hasher(candidates: &[Candidate], state: &ahash::RandomState) -> Vec<String> {
+        let mut by_transcript: AHashMap<Name, Vec<usize>> =
+            std::collections::HashMap::with_hasher(state.clone()).into();
+        for (i, c) in candidates.iter().enumerate() {
+            by_transcript
+                .entry(c.transcript.clone())
+                .or_default()
+                .push(i);
+        }
+        let per_transcript = apply_rules(candidates, &by_transcript, 50.0, 90.0, &[Area::Tss]);
+
+        let mut by_gene: AHashMap<Name, Vec<usize>> =
+            std::collections::HashMap::with_hasher(state.clone()).into();
+        for (i, c) in per_transcript.iter().enumerate() {
+            by_gene.entry(c.gene.clone()).or_default().push(i);
+        }
+        let per_gene = select_transcript(&per_transcript, &by_gene, &[Area::Tss]);
+
+        per_transcript
+            .iter()
+            .chain(&per_gene)
+            .map(|c| {
+                format!(
+                    "{}\t{}\t{}\t{}",
+                    c.gene, c.transcript, c.exon_number, c.start
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tie_order_independent_of_hasher() {
+        let candidates = tied_genes();
+        let expected = rows_with_hasher(&candidates, &ahash::RandomState::with_seeds(1, 2, 3, 4));
+
+        assert_eq!(
+            expected,
+            vec![
+                "G2\tT5\t3\t300",
+                "G1\tT2\t1\t100",
+                "G1\tT2\t2\t200",
+                "G3\tT7\t1\t100",
+                "G2\tT4\t1\t100",
+                "G1\tT1\t5\t500",
+                "G3\tT6\t2\t200",
+                "G2\tT4,T5\t1,3\t100",
+                "G1\tT1,T2,T2\t5,1,2\t500",
+                "G3\tT6,T7\t2,1\t200",
+            ]
+        );
+        let other_seed = ahash::RandomState::with_seeds(u64::MAX, 7, 0, 42);
+        assert_eq!(rows_with_hasher(&candidates, &other_seed), expected);
+        for _ in 0..100 {
+            assert_eq!(
+                rows_with_hasher(&candidates, &ahash::RandomState::new()),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_grouped_selection_matches_keyed_maps() {
+        let candidates = tied_genes();
+        let by_transcript = group_positions(&candidates, |c| c.transcript.as_str());
+        // Groups in order of first appearance
+        let first: Vec<&str> = by_transcript
+            .iter()
+            .map(|positions| candidates[positions[0]].transcript.as_str())
+            .collect();
+        assert_eq!(first, ["T5", "T2", "T7", "T4", "T1", "T6"]);
+
+        let per_transcript = apply_rules_grouped(
+            &candidates,
+            &by_transcript,
+            50.0,
+            90.0,
+            0,
+            &[Area::Tss],
+            TieBreak::ReportAll,
+            false,
+        );
+        let by_gene = group_positions(&per_transcript, |c| c.gene.as_str());
+        let per_gene = select_transcript_grouped(
+            &per_transcript,
+            &by_gene,
+            &[Area::Tss],
+            ",",
+            false,
+            TieBreak::ReportAll,
+        );
+        let rows: Vec<String> = per_transcript
+            .iter()
+            .chain(&per_gene)
+            .map(|c| {
+                format!(
+                    "{}\t{}\t{}\t{}",
+                    c.gene, c.transcript, c.exon_number, c.start
+                )
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            rows_with_hasher(&candidates, &ahash::RandomState::new())
+        );
+    }
+
+    /// TSS candidates of one group: G2 and G1 tie on the smallest absolute
+    /// TSS distance.
+    fn tss_ties() -> Vec<Candidate> {
+        [("T3", "G2", 50), ("T2", "G1", -50), ("T1", "G3", 200)]
+            .iter()
+            .map(|&(transcript, gene, tss_distance)| Candidate {
+                gene: gene.into(),
+                tss_distance,
+                ..make_candidate(Area::Tss, 100.0, 100.0, transcript)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_apply_rules_tie_break() {
+        let candidates = tss_ties();
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert(String::new(), vec![0, 1, 2]);
+        let rules = [Area::Tss];
+        let genes = |tie_break| -> Vec<String> {
+            apply_rules_with(
+                &candidates,
+                &grouped_by,
+                50.0,
+                90.0,
+                0,
+                &rules,
+                tie_break,
+                false,
+            )
+            .into_iter()
+            .map(|c| c.gene.into())
+            .collect()
+        };
+
+        // Report-all is apply_rules: every tie, by transcript ID
+        assert_eq!(genes(TieBreak::ReportAll), vec!["G3", "G1", "G2"]);
+        let all: Vec<String> = apply_rules(&candidates, &grouped_by, 50.0, 90.0, &rules)
+            .into_iter()
+            .map(|c| c.gene.into())
+            .collect();
+        assert_eq!(genes(TieBreak::ReportAll), all);
+        // Equal distances fall through to the gene ID
+        assert_eq!(genes(TieBreak::TssDistance), vec!["G1"]);
+        assert_eq!(genes(TieBreak::GeneId), vec!["G1"]);
+    }
+
+    #[test]
+    fn test_select_transcript_tie_break_reference() {
+        let candidates: Vec<Candidate> = tss_ties()
+            .into_iter()
+            .map(|c| Candidate {
+                gene: "G1".into(),
+                ..c
+            })
+            .collect();
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1, 2]);
+        let select = |tie_break| {
+            select_transcript_with(
+                &candidates,
+                &grouped_by,
+                &[Area::Tss],
+                ",",
+                false,
+                tie_break,
+            )
+        };
+
+        let report_all = select(TieBreak::ReportAll);
+        let default = select_transcript(&candidates, &grouped_by, &[Area::Tss]);
+        assert_eq!(report_all[0].transcript, default[0].transcript);
+        assert_eq!(report_all[0].tss_distance, default[0].tss_distance);
+        assert_eq!(report_all[0].tss_distance, 200);
+
+        // T2 and T3 are equally close; T2 comes first by transcript ID
+        let closest = select(TieBreak::TssDistance);
+        assert_eq!(closest.len(), 1);
+        assert_eq!(closest[0].transcript, "T1,T2,T3");
+        assert_eq!(closest[0].tss_distance, -50);
+    }
+
+    #[test]
+    fn test_select_best_transcript() {
+        let candidate = |transcript, pctg_region, tss_distance| Candidate {
+            tss_distance,
+            ..make_candidate(Area::Tss, pctg_region, 100.0, transcript)
+        };
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1, 2, 3]);
+        let best = |candidates: &[Candidate]| {
+            let result = select_best_transcript(candidates, &grouped_by, &[Area::Tss]);
+            assert_eq!(result.len(), 1);
+            result[0].transcript.clone()
+        };
+
+        // Highest region percentage first, then nearest TSS, then lowest ID
+        let mut candidates = vec![
+            candidate("T1", 50.0, 10),
+            candidate("T4", 80.0, 300),
+            candidate("T3", 80.0, -20),
+            candidate("T2", 80.0, 20),
+        ];
+        assert_eq!(best(&candidates), "T2");
+        candidates[3].tss_distance = 30;
+        assert_eq!(best(&candidates), "T3");
+        // Losing areas are never chosen
+        candidates[1].area = Area::Intron;
+        candidates[1].pctg_region = 100.0;
+        assert_eq!(best(&candidates), "T3");
+    }
 }
@@ -3,10 +3,11 @@
 //! This module implements the checkTSS logic with coordinate mirroring
 //! for negative strand genes.
 
-use crate::types::Strand;
+use crate::matcher::{area_percentage, in_window, mirror, window_bases, AreaOverlap};
+use crate::types::{Area, Strand};
 
-/// Result of a TSS check: (area_tag, pctg_dhs, pctg_area).
-pub type TssResult = (String, f64, f64);
+/// Result of a TSS check: one TSS, PROMOTER or UPSTREAM overlap.
+pub type TssResult = AreaOverlap;
 
 /// Helper struct to pass exon-like data to checkTSS.
 pub struct TssExonInfo {
@@ -31,16 +32,76 @@ pub struct TssExonInfo {
 /// * `tss_distance` - TSS region distance (default 200bp)
 /// * `promoter_distance` - Promoter region distance (default 1300bp)
 ///
+/// A zero `tss_distance` or `promoter_distance` leaves out that area: the
+/// region then falls in the next one upstream.
+///
 /// # Returns
-/// A vector of (area_tag, pctg_dhs, pctg_area) tuples for each overlapping region type.
+/// One [`AreaOverlap`] for each overlapping region type.
 pub fn check_tss(
     dhs_start: i64,
     dhs_end: i64,
     exon_info: &TssExonInfo,
     tss_distance: f64,
     promoter_distance: f64,
+) -> Vec<TssResult> {
+    check_tss_window(
+        dhs_start,
+        dhs_end,
+        exon_info,
+        tss_distance,
+        promoter_distance,
+        0.0,
+    )
+}
+
+/// [`check_tss`] with a TSS region extending `tss_downstream` bp past the
+/// TSS into the first exon (at most the whole exon).
+///
+/// The TSS overlap then includes the bases of the region within the first
+/// `tss_downstream` bases of the exon, and its area percentage is computed
+/// over the combined `tss_distance + tss_downstream` window.
+pub fn check_tss_window(
+    dhs_start: i64,
+    dhs_end: i64,
+    exon_info: &TssExonInfo,
+    tss_distance: f64,
+    promoter_distance: f64,
+    tss_downstream: f64,
+) -> Vec<TssResult> {
+    check_tss_with_boundary(
+        dhs_start,
+        dhs_end,
+        exon_info,
+        tss_distance,
+        promoter_distance,
+        tss_downstream,
+        true,
+    )
+}
+
+/// [`check_tss_window`] with the base exactly on the TSS/PROMOTER and
+/// PROMOTER/UPSTREAM boundaries in the inner area when `boundary_inclusive`,
+/// and in the outer one otherwise.
+///
+/// When exclusive, the TSS area is one base shorter, and so is the PROMOTER
+/// area if the TSS area is empty; the area percentages are computed over
+/// these shorter widths.
+pub fn check_tss_with_boundary(
+    dhs_start: i64,
+    dhs_end: i64,
+    exon_info: &TssExonInfo,
+    tss_distance: f64,
+    promoter_distance: f64,
+    tss_downstream: f64,
+    boundary_inclusive: bool,
 ) -> Vec<TssResult> {
     let mut exon_start = exon_info.start;
+    let downstream = (tss_downstream as i64).clamp(0, exon_info.end - exon_info.start + 1);
+    let promoter_limit = tss_distance + promoter_distance;
+    let tss_bases = window_bases(tss_distance, boundary_inclusive);
+    let promoter_bases = window_bases(promoter_limit, boundary_inclusive) - tss_bases;
+    let tss_window = tss_bases + downstream as f64;
+    let inside = |distance: i64, width: f64| in_window(distance, width, boundary_inclusive);
     let distance_val = exon_info.distance;
     let mut actual_dhs_start = dhs_start;
     let mut actual_dhs_end = dhs_end;
@@ -49,12 +110,14 @@ pub fn check_tss(
     // For negative strand, we flip the coordinates to make the code strand-invariant
     if exon_info.strand == Strand::Negative {
         let aux = actual_dhs_end;
-        actual_dhs_end = 2 * exon_info.end - actual_dhs_start;
-        actual_dhs_start = 2 * exon_info.end - aux;
+        actual_dhs_end = mirror(actual_dhs_start, exon_info.end);
+        actual_dhs_start = mirror(aux, exon_info.end);
         exon_start = exon_info.end; // TSS is at exon END for negative strand
     }
 
-    let dhs_length = actual_dhs_end - actual_dhs_start + 1;
+    let dhs_length = actual_dhs_end
+        .saturating_sub(actual_dhs_start)
+        .saturating_add(1);
 
     // Zero-length region check - must be <= 0, not < 0
     if dhs_length <= 0 {
@@ -64,24 +127,24 @@ pub fn check_tss(
     let mut results = Vec::new();
     let dhs_length_f = dhs_length as f64;
 
-    if distance_val as f64 <= tss_distance {
+    if tss_window > 0.0 && inside(distance_val, tss_distance) {
         // Region is within TSS distance
 
         // UPSTREAM       PROMOTER        TSS          1st exon
         // ..........|................|..............|----------->
 
-        if (exon_start - actual_dhs_start) as f64 <= tss_distance {
+        if inside(exon_start - actual_dhs_start, tss_distance) {
             // Region is entirely within TSS zone
             // UPSTREAM       PROMOTER        TSS          1st exon
             // ..........|................|..............|----------->
             //                      DHS
             //                               |-------------
 
-            let overlap_end = std::cmp::min(exon_start - 1, actual_dhs_end);
+            let overlap_end = std::cmp::min(exon_start - 1 + downstream, actual_dhs_end);
             let overlap = overlap_end - actual_dhs_start + 1;
             let pctg_dhs = (overlap as f64 / dhs_length_f) * 100.0;
-            let pctg_tss = (overlap as f64 / tss_distance) * 100.0;
-            results.push(("TSS".to_string(), pctg_dhs, pctg_tss));
+            let pctg_tss = area_percentage(overlap as f64, tss_window);
+            results.push(AreaOverlap::new(Area::Tss, pctg_dhs, pctg_tss));
         } else {
             // Region spans TSS and extends into PROMOTER
             // UPSTREAM       PROMOTER        TSS          1st exon
@@ -90,56 +153,69 @@ pub fn check_tss(
             //                        --------------
 
             // TSS portion
-            let tss_start = exon_start - tss_distance as i64;
-            let overlap_end = std::cmp::min(exon_start - 1, actual_dhs_end);
+            let tss_start = exon_start - tss_bases as i64;
+            let overlap_end = std::cmp::min(exon_start - 1 + downstream, actual_dhs_end);
             let tss_overlap = overlap_end - tss_start + 1;
             let pctg_dhs_tss = (tss_overlap as f64 / dhs_length_f) * 100.0;
-            let pctg_tss = (tss_overlap as f64 / tss_distance) * 100.0;
-            results.push(("TSS".to_string(), pctg_dhs_tss, pctg_tss));
+            let pctg_tss = area_percentage(tss_overlap as f64, tss_window);
+            results.push(AreaOverlap::new(Area::Tss, pctg_dhs_tss, pctg_tss));
 
             // Check if region extends into PROMOTER
-            if (exon_start - actual_dhs_start) as f64 <= tss_distance + promoter_distance {
+            if inside(exon_start - actual_dhs_start, promoter_limit) {
                 // Region is within TSS + PROMOTER zone
-                let promoter_overlap = (exon_start - tss_distance as i64) - actual_dhs_start;
+                let promoter_overlap = (exon_start - tss_bases as i64) - actual_dhs_start;
                 let pctg_dhs_promoter = (promoter_overlap as f64 / dhs_length_f) * 100.0;
-                let pctg_promoter = (promoter_overlap as f64 / promoter_distance) * 100.0;
-                results.push(("PROMOTER".to_string(), pctg_dhs_promoter, pctg_promoter));
+                let pctg_promoter = area_percentage(promoter_overlap as f64, promoter_bases);
+                results.push(AreaOverlap::new(
+                    Area::Promoter,
+                    pctg_dhs_promoter,
+                    pctg_promoter,
+                ));
             } else {
-                // Region extends into UPSTREAM
-                let pctg_dhs_promoter = (promoter_distance / dhs_length_f) * 100.0;
-                let pctg_promoter = 100.0;
-                results.push(("PROMOTER".to_string(), pctg_dhs_promoter, pctg_promoter));
+                // Region extends into UPSTREAM, across the whole PROMOTER
+                // unless it has zero width
+                if promoter_bases > 0.0 {
+                    let pctg_dhs_promoter = (promoter_bases / dhs_length_f) * 100.0;
+                    results.push(AreaOverlap::new(
+                        Area::Promoter,
+                        pctg_dhs_promoter,
+                        Some(100.0),
+                    ));
+                }
 
                 let upstream_overlap =
-                    (exon_start - tss_distance as i64 - promoter_distance as i64)
-                        - actual_dhs_start;
+                    (exon_start - tss_bases as i64 - promoter_bases as i64) - actual_dhs_start;
                 let pctg_dhs_upstream = (upstream_overlap as f64 / dhs_length_f) * 100.0;
-                results.push(("UPSTREAM".to_string(), pctg_dhs_upstream, -1.0));
+                results.push(AreaOverlap::new(Area::Upstream, pctg_dhs_upstream, None));
             }
         }
-    } else if distance_val as f64 <= tss_distance + promoter_distance {
+    } else if promoter_bases > 0.0 && inside(distance_val, promoter_limit) {
         // Region is within PROMOTER zone (beyond TSS)
 
-        if (exon_start - actual_dhs_start) as f64 <= tss_distance + promoter_distance {
+        if inside(exon_start - actual_dhs_start, promoter_limit) {
             // Region is entirely within PROMOTER zone
             let pctg_dhs = 100.0;
-            let pctg_promoter = (dhs_length_f / promoter_distance) * 100.0;
-            results.push(("PROMOTER".to_string(), pctg_dhs, pctg_promoter));
+            let pctg_promoter = area_percentage(dhs_length_f, promoter_bases);
+            results.push(AreaOverlap::new(Area::Promoter, pctg_dhs, pctg_promoter));
         } else {
             // Region spans PROMOTER and extends into UPSTREAM
-            let promoter_start = exon_start - tss_distance as i64 - promoter_distance as i64;
+            let promoter_start = exon_start - tss_bases as i64 - promoter_bases as i64;
             let promoter_overlap = actual_dhs_end - promoter_start + 1;
             let pctg_dhs_promoter = (promoter_overlap as f64 / dhs_length_f) * 100.0;
-            let pctg_promoter = (promoter_overlap as f64 / promoter_distance) * 100.0;
-            results.push(("PROMOTER".to_string(), pctg_dhs_promoter, pctg_promoter));
+            let pctg_promoter = area_percentage(promoter_overlap as f64, promoter_bases);
+            results.push(AreaOverlap::new(
+                Area::Promoter,
+                pctg_dhs_promoter,
+                pctg_promoter,
+            ));
 
             let upstream_overlap = promoter_start - actual_dhs_start;
             let pctg_dhs_upstream = (upstream_overlap as f64 / dhs_length_f) * 100.0;
-            results.push(("UPSTREAM".to_string(), pctg_dhs_upstream, -1.0));
+            results.push(AreaOverlap::new(Area::Upstream, pctg_dhs_upstream, None));
         }
     } else {
         // Region is entirely in UPSTREAM zone
-        results.push(("UPSTREAM".to_string(), 100.0, -1.0));
+        results.push(AreaOverlap::new(Area::Upstream, 100.0, None));
     }
 
     results
@@ -165,16 +241,16 @@ mod tests {
 
         let res = check_tss(1800, 1810, &exon, 200.0, 1300.0);
         assert!(
-            res.iter().any(|(tag, _, _)| tag == "TSS"),
+            res.iter().any(|o| o.area == Area::Tss),
             "1800 should be TSS: {:?}",
             res
         );
 
         // Case 2: Just outside TSS boundary -> [1799, 1810]
         let res = check_tss(1799, 1810, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
-        assert!(tags.contains(&"TSS"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
+        assert!(tags.contains(&Area::Tss));
 
         // Case 3: Far upstream (UPSTREAM tag)
         let exon_far = TssExonInfo {
@@ -184,10 +260,67 @@ mod tests {
             distance: 1800,
         };
         let res = check_tss(100, 200, &exon_far, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"UPSTREAM"));
-        assert!(!tags.contains(&"TSS"));
-        assert!(!tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Upstream));
+        assert!(!tags.contains(&Area::Tss));
+        assert!(!tags.contains(&Area::Promoter));
+    }
+
+    #[test]
+    fn test_tss_window_into_exon() {
+        // TSS region of 200 bp upstream and 100 bp downstream of the TSS
+        let plus = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+        let res = check_tss_window(1950, 2150, &plus, 200.0, 1300.0, 100.0);
+        assert_eq!(
+            res,
+            vec![AreaOverlap::new(
+                Area::Tss,
+                150.0 / 201.0 * 100.0,
+                Some(50.0)
+            )]
+        );
+        // Without the extension, only the upstream bases count
+        let res = check_tss(1950, 2150, &plus, 200.0, 1300.0);
+        assert_eq!(
+            res,
+            vec![AreaOverlap::new(
+                Area::Tss,
+                50.0 / 201.0 * 100.0,
+                Some(25.0)
+            )]
+        );
+
+        // Mirrored on the - strand: TSS @ 3000, window [2901, 3200]
+        let minus = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand: Strand::Negative,
+            distance: 0,
+        };
+        let res = check_tss_window(2850, 3050, &minus, 200.0, 1300.0, 100.0);
+        assert_eq!(
+            res,
+            vec![AreaOverlap::new(
+                Area::Tss,
+                150.0 / 201.0 * 100.0,
+                Some(50.0)
+            )]
+        );
+
+        // The extension stops at the end of the exon
+        let short = TssExonInfo {
+            start: 2000,
+            end: 2009,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+        let res = check_tss_window(1990, 2050, &short, 200.0, 1300.0, 100.0);
+        assert_eq!(res[0].pctg_area, Some(20.0 / 210.0 * 100.0));
     }
 
     #[test]
@@ -210,14 +343,14 @@ mod tests {
         // exon_start' = 3000
         // 3000 - 2790 = 210 > 200, so PROMOTER
         let res = check_tss(3200, 3210, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
 
         // Case 2: TSS Zone Inside [3100, 3150]
         // Flipped: 2*3000 - 3150 = 2850 (Start).
         // 3000 - 2850 = 150 <= 200, so TSS.
         let res = check_tss(3100, 3150, &exon, 200.0, 1300.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TSS"));
+        assert!(res.iter().any(|o| o.area == Area::Tss));
     }
 
     #[test]
@@ -259,8 +392,48 @@ mod tests {
             distance: 500,
         };
         let res = check_tss(1500, 1600, &exon, 0.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
+    }
+
+    #[test]
+    fn test_zero_width_areas() {
+        // Regions abutting the first exon and reaching up to 2 kb upstream
+        let exon = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+        for (tss, promoter) in [(0.0, 1300.0), (200.0, 0.0), (0.0, 0.0)] {
+            for start in [1999, 1900, 1500, 1000] {
+                let res = check_tss(start, 1999, &exon, tss, promoter);
+                assert!(!res.is_empty());
+                for overlap in &res {
+                    assert!(overlap.pctg_region.is_finite());
+                    assert!(overlap.pctg_area.map_or(true, f64::is_finite));
+                    assert!(tss > 0.0 || overlap.area != Area::Tss);
+                    assert!(promoter > 0.0 || overlap.area != Area::Promoter);
+                }
+            }
+        }
+
+        // With neither, the region is all UPSTREAM
+        let res = check_tss(1900, 1999, &exon, 0.0, 0.0);
+        assert_eq!(res, vec![AreaOverlap::new(Area::Upstream, 100.0, None)]);
+    }
+
+    #[test]
+    fn test_extreme_coordinates_do_not_overflow() {
+        let exon = TssExonInfo {
+            start: i64::MAX - 1000,
+            end: i64::MAX - 10,
+            strand: Strand::Negative,
+            distance: 0,
+        };
+        for overlap in check_tss(i64::MAX - 5, i64::MAX, &exon, 200.0, 1300.0) {
+            assert!(overlap.pctg_region.is_finite());
+        }
     }
 
     #[test]
@@ -272,6 +445,6 @@ mod tests {
             distance: 5000,
         };
         let res = check_tss(15000, 15100, &exon, 10000.0, 1300.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TSS"));
+        assert!(res.iter().any(|o| o.area == Area::Tss));
     }
 }
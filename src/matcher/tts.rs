@@ -3,10 +3,11 @@
 //! This module implements the checkTTS logic with coordinate mirroring
 //! for positive strand genes (opposite of TSS!).
 
-use crate::types::Strand;
+use crate::matcher::{area_percentage, in_window, mirror, window_bases, AreaOverlap};
+use crate::types::{Area, Strand};
 
-/// Result of a TTS check: (area_tag, pctg_dhs, pctg_area).
-pub type TtsResult = (String, f64, f64);
+/// Result of a TTS check: one TTS or DOWNSTREAM overlap.
+pub type TtsResult = AreaOverlap;
 
 /// Helper struct to pass exon-like data to checkTTS.
 pub struct TtsExonInfo {
@@ -28,17 +29,34 @@ pub struct TtsExonInfo {
 /// * `dhs_start` - Start coordinate of the DHS region
 /// * `dhs_end` - End coordinate of the DHS region
 /// * `exon_info` - Exon information including position, strand, and distance
-/// * `tts_distance` - TTS region distance (default 0bp)
+/// * `tts_distance` - TTS region distance (default 0bp); with 0 there is no
+///   TTS area and the region is all DOWNSTREAM
 ///
 /// # Returns
-/// A vector of (area_tag, pctg_dhs, pctg_area) tuples for each overlapping region type.
+/// One [`AreaOverlap`] for each overlapping region type.
 pub fn check_tts(
     dhs_start: i64,
     dhs_end: i64,
     exon_info: &TtsExonInfo,
     tts_distance: f64,
+) -> Vec<TtsResult> {
+    check_tts_with_boundary(dhs_start, dhs_end, exon_info, tts_distance, true)
+}
+
+/// [`check_tts`] with the base exactly on the TTS/DOWNSTREAM boundary in the
+/// TTS area when `boundary_inclusive`, and in DOWNSTREAM otherwise.
+///
+/// When exclusive, the TTS area is one base shorter and its area percentage
+/// is computed over that shorter width.
+pub fn check_tts_with_boundary(
+    dhs_start: i64,
+    dhs_end: i64,
+    exon_info: &TtsExonInfo,
+    tts_distance: f64,
+    boundary_inclusive: bool,
 ) -> Vec<TtsResult> {
     let mut exon_start = exon_info.start;
+    let tts_bases = window_bases(tts_distance, boundary_inclusive);
     let distance_val = exon_info.distance;
     let mut actual_dhs_start = dhs_start;
     let mut actual_dhs_end = dhs_end;
@@ -47,12 +65,14 @@ pub fn check_tts(
     // For positive strand, we flip the coordinates to make the code strand-invariant
     if exon_info.strand == Strand::Positive {
         let aux = actual_dhs_end;
-        actual_dhs_end = 2 * exon_info.end - actual_dhs_start;
-        actual_dhs_start = 2 * exon_info.end - aux;
+        actual_dhs_end = mirror(actual_dhs_start, exon_info.end);
+        actual_dhs_start = mirror(aux, exon_info.end);
         exon_start = exon_info.end; // TTS is at exon END for positive strand
     }
 
-    let dhs_length = actual_dhs_end - actual_dhs_start + 1;
+    let dhs_length = actual_dhs_end
+        .saturating_sub(actual_dhs_start)
+        .saturating_add(1);
 
     // Zero-length region check - must be <= 0, not < 0
     if dhs_length <= 0 {
@@ -62,13 +82,17 @@ pub fn check_tts(
     let mut results = Vec::new();
     let dhs_length_f = dhs_length as f64;
 
-    if distance_val as f64 <= tts_distance {
+    if tts_bases > 0.0 && in_window(distance_val, tts_distance, boundary_inclusive) {
         // Region is within TTS distance
 
         // DOWNSTREAM       TTS        last exon
         // ..........|...............|----------->
 
-        if (exon_start - actual_dhs_start) as f64 <= tts_distance {
+        if in_window(
+            exon_start - actual_dhs_start,
+            tts_distance,
+            boundary_inclusive,
+        ) {
             // Region is entirely within TTS zone
             // DOWNSTREAM        TTS          last exon
             // ..........|................|----------->
@@ -78,8 +102,8 @@ pub fn check_tts(
             let overlap_end = std::cmp::min(exon_start - 1, actual_dhs_end);
             let overlap = overlap_end - actual_dhs_start + 1;
             let pctg_dhs = (overlap as f64 / dhs_length_f) * 100.0;
-            let pctg_tts = (overlap as f64 / tts_distance) * 100.0;
-            results.push(("TTS".to_string(), pctg_dhs, pctg_tts));
+            let pctg_tts = area_percentage(overlap as f64, tts_bases);
+            results.push(AreaOverlap::new(Area::Tts, pctg_dhs, pctg_tts));
         } else {
             // Region spans TTS and extends into DOWNSTREAM
             // DOWNSTREAM         TTS          last exon
@@ -88,21 +112,25 @@ pub fn check_tts(
             //       --------------
 
             // TTS portion
-            let tts_start = exon_start - tts_distance as i64;
+            let tts_start = exon_start - tts_bases as i64;
             let overlap_end = std::cmp::min(exon_start - 1, actual_dhs_end);
             let tts_overlap = overlap_end - tts_start + 1;
             let pctg_dhs_tts = (tts_overlap as f64 / dhs_length_f) * 100.0;
-            let pctg_tts = (tts_overlap as f64 / tts_distance) * 100.0;
-            results.push(("TTS".to_string(), pctg_dhs_tts, pctg_tts));
+            let pctg_tts = area_percentage(tts_overlap as f64, tts_bases);
+            results.push(AreaOverlap::new(Area::Tts, pctg_dhs_tts, pctg_tts));
 
             // DOWNSTREAM portion
             let downstream_overlap = tts_start - actual_dhs_start;
             let pctg_dhs_downstream = (downstream_overlap as f64 / dhs_length_f) * 100.0;
-            results.push(("DOWNSTREAM".to_string(), pctg_dhs_downstream, -1.0));
+            results.push(AreaOverlap::new(
+                Area::Downstream,
+                pctg_dhs_downstream,
+                None,
+            ));
         }
     } else {
         // Region is entirely in DOWNSTREAM zone
-        results.push(("DOWNSTREAM".to_string(), 100.0, -1.0));
+        results.push(AreaOverlap::new(Area::Downstream, 100.0, None));
     }
 
     results
@@ -132,7 +160,7 @@ mod tests {
         // Check: exon_start' - dhs_start' = 2000 - 1850 = 150 <= 200
         // Returns TTS
         let res = check_tts(2100, 2150, &exon, 200.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -154,7 +182,7 @@ mod tests {
         // exon_start - dhs_start = 1000 - 850 = 150 <= 200
         // Returns TTS
         let res = check_tts(850, 900, &exon, 200.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -167,8 +195,8 @@ mod tests {
             distance: 100,
         };
         let res = check_tts(2100, 2150, &exon, 0.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"DOWNSTREAM"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Downstream));
     }
 
     #[test]
@@ -192,7 +220,7 @@ mod tests {
             distance: 3000,
         };
         let res = check_tts(5000, 5100, &exon, 5000.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -205,9 +233,9 @@ mod tests {
         };
         // Region from 2100 to 2300 (spans TTS at 200bp)
         let res = check_tts(2100, 2300, &exon, 200.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"TTS"));
-        assert!(tags.contains(&"DOWNSTREAM"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Tts));
+        assert!(tags.contains(&Area::Downstream));
     }
 
     #[test]
@@ -219,11 +247,40 @@ mod tests {
             distance: 0,
         };
         let res = check_tts(850, 950, &exon, 200.0);
-        for (tag, pctg_dhs, pctg_tts) in &res {
-            if tag == "TTS" {
-                assert!(*pctg_dhs >= 0.0 && *pctg_dhs <= 100.0);
-                assert!(*pctg_tts >= 0.0 && *pctg_tts <= 100.0);
+        for overlap in &res {
+            if overlap.area == Area::Tts {
+                assert!(overlap.pctg_region >= 0.0 && overlap.pctg_region <= 100.0);
+                let pctg_tts = overlap.pctg_area.unwrap();
+                assert!((0.0..=100.0).contains(&pctg_tts));
             }
         }
     }
+
+    #[test]
+    fn test_extreme_coordinates_do_not_overflow() {
+        let exon = TtsExonInfo {
+            start: i64::MAX - 1000,
+            end: i64::MAX - 10,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+        for overlap in check_tts(i64::MAX - 5, i64::MAX, &exon, 200.0) {
+            assert!(overlap.pctg_region.is_finite());
+        }
+    }
+
+    #[test]
+    fn test_zero_tts_abutting_exon() {
+        // Without a TTS area, a region abutting the last exon is DOWNSTREAM
+        let exon = TtsExonInfo {
+            start: 1000,
+            end: 2000,
+            strand: Strand::Positive,
+            distance: 0,
+        };
+        for (start, end) in [(2001, 2050), (1990, 2050), (2001, 2001)] {
+            let res = check_tts(start, end, &exon, 0.0);
+            assert_eq!(res, vec![AreaOverlap::new(Area::Downstream, 100.0, None)]);
+        }
+    }
 }
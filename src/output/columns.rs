@@ -0,0 +1,613 @@
+//! Named output columns and per-association records.
+//!
+//! Every TSV line is built from an `OutputRecord` by looking up one field per
+//! `OutputColumn`. The default layout is the ten standard columns, then any
+//! extra columns, then the BED metadata columns; `--columns` replaces it with
+//! an arbitrary subset in any order.
+
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserialize, Deserializer};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+use std::borrow::Cow;
+use std::fmt::{self, Write as _};
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::parser::bed::get_bed_headers;
+use crate::types::{Candidate, CoordinateConvention, ExonNumbers, ExtraColumn, Region};
+
+/// Maximum number of BED metadata columns kept from the input.
+pub const MAX_META_COLUMNS: usize = 9;
+
+/// The ten standard columns, in default order.
+pub const BASE_COLUMNS: [OutputColumn; 10] = [
+    OutputColumn::Region,
+    OutputColumn::Midpoint,
+    OutputColumn::Gene,
+    OutputColumn::Transcript,
+    OutputColumn::ExonIntron,
+    OutputColumn::Area,
+    OutputColumn::Distance,
+    OutputColumn::TssDistance,
+    OutputColumn::PercRegion,
+    OutputColumn::PercArea,
+];
+
+/// A named column of the TSV output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputColumn {
+    Region,
+    Midpoint,
+    Gene,
+    Transcript,
+    ExonIntron,
+    Area,
+    Distance,
+    TssDistance,
+    PercRegion,
+    PercArea,
+    FeatureStart,
+    FeatureEnd,
+    RelativeStrand,
+    TtsDistance,
+    GeneStart,
+    GeneEnd,
+    GeneLength,
+    /// Percentage of the gene span covered by the region.
+    PercGene,
+    /// Genes with any candidate before report-level filtering
+    NumCandidateGenes,
+    /// Sense class of the gene relative to the region's strand.
+    Sense,
+    /// Rank of the gene among the region's nearest genes (`--n-closest`).
+    Rank,
+    /// Whether the report-level selection keeps the row (`--no-rules`).
+    Selected,
+    /// Whether the region is at a bidirectional promoter of the gene.
+    Bidirectional,
+    /// Signed distance to the nearest exon-intron junction of the transcript.
+    JunctionDistance,
+    /// 1-based line of the region in the BED file.
+    BedLine,
+    /// Other genes of the region in wide output, as `gene:area:distance;...`.
+    OtherGenes,
+    /// BED metadata column by 0-based index (0 is `name`, BED column 4).
+    Metadata(usize),
+}
+
+impl OutputColumn {
+    /// Header name of the column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputColumn::Region => "Region",
+            OutputColumn::Midpoint => "Midpoint",
+            OutputColumn::Gene => "Gene",
+            OutputColumn::Transcript => "Transcript",
+            OutputColumn::ExonIntron => "Exon/Intron",
+            OutputColumn::Area => "Area",
+            OutputColumn::Distance => "Distance",
+            OutputColumn::TssDistance => "TSSDistance",
+            OutputColumn::PercRegion => "PercRegion",
+            OutputColumn::PercArea => "PercArea",
+            OutputColumn::FeatureStart => "FeatureStart",
+            OutputColumn::FeatureEnd => "FeatureEnd",
+            OutputColumn::RelativeStrand => "RelativeStrand",
+            OutputColumn::TtsDistance => "TTSDistance",
+            OutputColumn::GeneStart => "GeneStart",
+            OutputColumn::GeneEnd => "GeneEnd",
+            OutputColumn::GeneLength => "GeneLength",
+            OutputColumn::PercGene => "PercGene",
+            OutputColumn::NumCandidateGenes => "NumCandidateGenes",
+            OutputColumn::Sense => "Sense",
+            OutputColumn::Rank => "Rank",
+            OutputColumn::Selected => "Selected",
+            OutputColumn::Bidirectional => "Bidirectional",
+            OutputColumn::JunctionDistance => "JunctionDistance",
+            OutputColumn::BedLine => "BedLine",
+            OutputColumn::OtherGenes => "OtherGenes",
+            OutputColumn::Metadata(index) => get_bed_headers(MAX_META_COLUMNS)[*index],
+        }
+    }
+
+    /// Whether the column describes the region rather than the association.
+    pub fn is_region_column(&self) -> bool {
+        matches!(
+            self,
+            OutputColumn::Region
+                | OutputColumn::Midpoint
+                | OutputColumn::BedLine
+                | OutputColumn::Metadata(_)
+        )
+    }
+
+    /// Whether an intergenic pseudo-candidate has a value in this column;
+    /// its gene, transcript and feature columns are missing values.
+    pub fn applies_to_intergenic(&self) -> bool {
+        matches!(
+            self,
+            OutputColumn::Area
+                | OutputColumn::Distance
+                | OutputColumn::PercRegion
+                | OutputColumn::PercArea
+        ) || self.is_region_column()
+    }
+
+    /// Every valid column, in default layout order.
+    pub fn all() -> Vec<OutputColumn> {
+        let mut columns = BASE_COLUMNS.to_vec();
+        columns.extend([
+            OutputColumn::FeatureStart,
+            OutputColumn::FeatureEnd,
+            OutputColumn::RelativeStrand,
+            OutputColumn::TtsDistance,
+            OutputColumn::GeneStart,
+            OutputColumn::GeneEnd,
+            OutputColumn::GeneLength,
+            OutputColumn::PercGene,
+            OutputColumn::NumCandidateGenes,
+            OutputColumn::Sense,
+            OutputColumn::Rank,
+            OutputColumn::Selected,
+            OutputColumn::Bidirectional,
+            OutputColumn::JunctionDistance,
+            OutputColumn::BedLine,
+            OutputColumn::OtherGenes,
+        ]);
+        columns.extend((0..MAX_META_COLUMNS).map(OutputColumn::Metadata));
+        columns
+    }
+}
+
+impl fmt::Display for OutputColumn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// Error type for parsing an output column name from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOutputColumnError(pub String);
+
+impl fmt::Display for ParseOutputColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let valid: Vec<&str> = OutputColumn::all().iter().map(|c| c.name()).collect();
+        write!(
+            f,
+            "unknown column '{}': expected one of {}",
+            self.0,
+            valid.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for ParseOutputColumnError {}
+
+impl FromStr for OutputColumn {
+    type Err = ParseOutputColumnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        OutputColumn::all()
+            .into_iter()
+            .find(|column| column.name() == s)
+            .ok_or_else(|| ParseOutputColumnError(s.to_string()))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for OutputColumn {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for OutputColumn {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Output columns added by an extra column selector.
+pub fn extra_output_columns(extra: ExtraColumn) -> &'static [OutputColumn] {
+    match extra {
+        ExtraColumn::Coords => &[OutputColumn::FeatureStart, OutputColumn::FeatureEnd],
+        ExtraColumn::RelativeStrand => &[OutputColumn::RelativeStrand],
+        ExtraColumn::TtsDistance => &[OutputColumn::TtsDistance],
+        ExtraColumn::GeneSpan => &[
+            OutputColumn::GeneStart,
+            OutputColumn::GeneEnd,
+            OutputColumn::GeneLength,
+        ],
+        ExtraColumn::NumCandidateGenes => &[OutputColumn::NumCandidateGenes],
+        ExtraColumn::Sense => &[OutputColumn::Sense],
+        ExtraColumn::PercGene => &[OutputColumn::PercGene],
+        ExtraColumn::Bidirectional => &[OutputColumn::Bidirectional],
+        ExtraColumn::JunctionDistance => &[OutputColumn::JunctionDistance],
+        ExtraColumn::BedLine => &[OutputColumn::BedLine],
+    }
+}
+
+/// Default column layout: standard columns, extra columns, then metadata.
+pub fn default_layout(num_meta_columns: usize, extra_columns: &[ExtraColumn]) -> Vec<OutputColumn> {
+    let mut columns = BASE_COLUMNS.to_vec();
+    for &extra in extra_columns {
+        columns.extend_from_slice(extra_output_columns(extra));
+    }
+    columns.extend((0..num_meta_columns.min(MAX_META_COLUMNS)).map(OutputColumn::Metadata));
+    columns
+}
+
+/// One region-association pair viewed as named output fields.
+///
+/// Regions without an association have no candidate; their association
+/// fields are missing values, written as `na_string`.
+pub struct OutputRecord<'a> {
+    pub region: &'a Region,
+    pub candidate: Option<&'a Candidate>,
+    /// Number of decimals for percentage fields.
+    pub precision: usize,
+    /// Text written for missing values.
+    pub na_string: &'a str,
+    /// Separator of merged exon or intron numbers.
+    pub merge_separator: &'a str,
+    /// Write negative percentage sentinels as numbers instead of missing values.
+    pub legacy_sentinel: bool,
+    /// Value of the `OtherGenes` column in wide output.
+    pub other_genes: Option<String>,
+    /// Value of the `NumCandidateGenes` column, if known.
+    pub num_candidate_genes: Option<usize>,
+    /// Convention of reported start positions.
+    pub coordinates: CoordinateConvention,
+}
+
+impl<'a> OutputRecord<'a> {
+    /// Create a record using the formatting options of `config`.
+    pub fn new(region: &'a Region, candidate: Option<&'a Candidate>, config: &'a Config) -> Self {
+        OutputRecord {
+            region,
+            candidate,
+            precision: config.output_precision,
+            na_string: &config.na_string,
+            merge_separator: &config.merge_separator,
+            legacy_sentinel: config.legacy_sentinel,
+            other_genes: None,
+            num_candidate_genes: None,
+            coordinates: config.output_coordinate_convention,
+        }
+    }
+
+    /// Value of a column for this record.
+    ///
+    /// Returns `None` for metadata columns the region does not have.
+    pub fn field(&self, column: OutputColumn) -> Option<Cow<'a, str>> {
+        self.value(column).map(FieldValue::into_text)
+    }
+
+    /// Unformatted value of a column, `None` for metadata columns the region
+    /// does not have.
+    fn value(&self, column: OutputColumn) -> Option<FieldValue<'a>> {
+        let value = match column {
+            OutputColumn::Region => FieldValue::RegionId(
+                &self.region.chrom,
+                self.coordinates.from_bed_start(self.region.start),
+                self.region.end,
+            ),
+            OutputColumn::Midpoint => {
+                FieldValue::Integer(self.region.midpoint_in(self.coordinates))
+            }
+            OutputColumn::BedLine => match self.region.line {
+                Some(line) => FieldValue::Integer(line as i64),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::Metadata(index) => {
+                let value = self.region.metadata.get(index)?.as_str();
+                // Drop any trailing whitespace left on the last input column
+                if index + 1 == self.region.metadata.len() {
+                    FieldValue::Text(value.trim_end())
+                } else {
+                    FieldValue::Text(value)
+                }
+            }
+            OutputColumn::OtherGenes => match &self.other_genes {
+                Some(other_genes) => FieldValue::Owned(other_genes.clone()),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::NumCandidateGenes => match self.num_candidate_genes {
+                Some(count) => FieldValue::Integer(count as i64),
+                None => FieldValue::Text(self.na_string),
+            },
+            _ => match self.candidate {
+                Some(candidate) => self.candidate_field(candidate, column),
+                None => FieldValue::Text(self.na_string),
+            },
+        };
+        Some(value)
+    }
+
+    fn candidate_field(&self, candidate: &'a Candidate, column: OutputColumn) -> FieldValue<'a> {
+        // Only the area, distance and percentages describe an intergenic region
+        if candidate.is_intergenic() && !column.applies_to_intergenic() {
+            return FieldValue::Text(self.na_string);
+        }
+        match column {
+            OutputColumn::Gene => FieldValue::Text(&candidate.gene),
+            OutputColumn::Transcript => FieldValue::Text(&candidate.transcript),
+            OutputColumn::ExonIntron => {
+                FieldValue::ExonNumbers(&candidate.exon_number, self.merge_separator)
+            }
+            OutputColumn::Area => FieldValue::Text(candidate.area.as_str()),
+            OutputColumn::Distance => match candidate.feature_distance() {
+                Some(distance) => FieldValue::Integer(distance),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::TssDistance => FieldValue::Integer(candidate.tss_distance),
+            OutputColumn::PercRegion => {
+                self.percentage_field(candidate.region_percentage(), candidate.pctg_region)
+            }
+            OutputColumn::PercArea => {
+                self.percentage_field(candidate.area_percentage(), candidate.pctg_area)
+            }
+            OutputColumn::FeatureStart => {
+                FieldValue::Integer(self.coordinates.from_one_based_start(candidate.start))
+            }
+            OutputColumn::FeatureEnd => FieldValue::Integer(candidate.end),
+            OutputColumn::RelativeStrand => match relative_strand(self.region, candidate) {
+                Some(orientation) => FieldValue::Text(orientation),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::TtsDistance => FieldValue::Integer(candidate.tts_distance),
+            OutputColumn::GeneStart => {
+                FieldValue::Integer(self.coordinates.from_one_based_start(candidate.gene_start))
+            }
+            OutputColumn::GeneEnd => FieldValue::Integer(candidate.gene_end),
+            OutputColumn::GeneLength => FieldValue::Integer(candidate.gene_length()),
+            OutputColumn::PercGene => {
+                self.percentage_field(candidate.gene_percentage(self.region), -1.0)
+            }
+            OutputColumn::Sense => FieldValue::Text(candidate.sense.as_str()),
+            OutputColumn::Rank => match candidate.rank {
+                Some(rank) => FieldValue::Integer(rank as i64),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::Selected => match candidate.selected {
+                Some(true) => FieldValue::Text("1"),
+                Some(false) => FieldValue::Text("0"),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::Bidirectional => {
+                FieldValue::Text(if candidate.bidirectional { "yes" } else { "no" })
+            }
+            OutputColumn::JunctionDistance => match candidate.junction_distance {
+                Some(distance) => FieldValue::Integer(distance),
+                None => FieldValue::Text(self.na_string),
+            },
+            OutputColumn::Region
+            | OutputColumn::Midpoint
+            | OutputColumn::OtherGenes
+            | OutputColumn::NumCandidateGenes
+            | OutputColumn::BedLine
+            | OutputColumn::Metadata(_) => {
+                unreachable!("column does not depend on the candidate")
+            }
+        }
+    }
+
+    /// Format a percentage; `value` is `None` for a sentinel, which is written
+    /// as a missing value unless `legacy_sentinel` is set.
+    fn percentage_field(&self, value: Option<f64>, raw: f64) -> FieldValue<'a> {
+        match value {
+            Some(value) => FieldValue::Percentage(value, self.precision),
+            None if self.legacy_sentinel => FieldValue::Percentage(raw, self.precision),
+            None => FieldValue::Text(self.na_string),
+        }
+    }
+
+    /// Join the fields of `columns` into a tab-separated line.
+    ///
+    /// Metadata columns the region does not have are written as
+    /// `na_string`, so every line has one field per column.
+    pub fn project(&self, columns: &[OutputColumn]) -> String {
+        let mut line = String::new();
+        self.project_into(&mut line, columns);
+        line
+    }
+
+    /// [`OutputRecord::project`] appending to `line`, without allocating a
+    /// string per field.
+    pub fn project_into(&self, line: &mut String, columns: &[OutputColumn]) {
+        for (i, &column) in columns.iter().enumerate() {
+            if i > 0 {
+                line.push('\t');
+            }
+            match self.value(column) {
+                Some(value) => value.write_to(line),
+                None => line.push_str(self.na_string),
+            }
+        }
+    }
+}
+
+/// A field value, formatted only when written.
+enum FieldValue<'a> {
+    Text(&'a str),
+    Owned(String),
+    /// Exon or intron numbers and their separator.
+    ExonNumbers(&'a ExonNumbers, &'a str),
+    Integer(i64),
+    /// Percentage with its number of decimals.
+    Percentage(f64, usize),
+    /// `chrom_start_end` region ID.
+    RegionId(&'a str, i64, i64),
+}
+
+impl<'a> FieldValue<'a> {
+    fn write_to(&self, line: &mut String) {
+        // Writing to a String cannot fail
+        let _ = match self {
+            FieldValue::Text(text) => {
+                line.push_str(text);
+                Ok(())
+            }
+            FieldValue::Owned(text) => {
+                line.push_str(text);
+                Ok(())
+            }
+            FieldValue::ExonNumbers(numbers, separator) => {
+                numbers.write_joined(line, separator);
+                Ok(())
+            }
+            FieldValue::Integer(value) => write!(line, "{}", value),
+            FieldValue::Percentage(value, precision) => write!(line, "{:.*}", precision, value),
+            FieldValue::RegionId(chrom, start, end) => write!(line, "{}_{}_{}", chrom, start, end),
+        };
+    }
+
+    fn into_text(self) -> Cow<'a, str> {
+        match self {
+            FieldValue::Text(text) => Cow::Borrowed(text),
+            FieldValue::Owned(text) => Cow::Owned(text),
+            value => {
+                let mut text = String::new();
+                value.write_to(&mut text);
+                Cow::Owned(text)
+            }
+        }
+    }
+}
+
+/// Format a percentage with a fixed number of decimals.
+///
+/// Values are rounded to nearest and never use scientific notation; integers
+/// still get the full number of decimals so columns stay aligned.
+pub fn format_percentage(value: f64, precision: usize) -> String {
+    format!("{:.*}", precision, value)
+}
+
+/// Orientation of a stranded region relative to the candidate's gene.
+///
+/// Returns `same`, `opposite`, or `None` for unstranded regions.
+fn relative_strand(region: &Region, candidate: &Candidate) -> Option<&'static str> {
+    match region.strand? {
+        strand if strand == candidate.strand => Some("same"),
+        _ => Some("opposite"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Area, Strand};
+
+    fn make_record_parts() -> (Region, Candidate) {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "7".to_string()],
+        );
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            2,
+            Area::Intron,
+            "T1".to_string(),
+            "G1".to_string(),
+            800,
+            100.0,
+            3.5,
+            -850,
+        );
+        (region, candidate)
+    }
+
+    #[test]
+    fn test_output_column_names_round_trip() {
+        for column in OutputColumn::all() {
+            assert_eq!(column.name().parse::<OutputColumn>(), Ok(column));
+        }
+        assert_eq!(
+            "Exon/Intron".parse::<OutputColumn>(),
+            Ok(OutputColumn::ExonIntron)
+        );
+        assert_eq!(
+            "score".parse::<OutputColumn>(),
+            Ok(OutputColumn::Metadata(1))
+        );
+
+        let err = "Bogus".parse::<OutputColumn>().unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("'Bogus'"));
+        assert!(message.contains("PercRegion"));
+        assert!(message.contains("blockStarts"));
+    }
+
+    #[test]
+    fn test_default_layout() {
+        let layout = default_layout(2, &[ExtraColumn::Coords]);
+        let names: Vec<&str> = layout.iter().map(|c| c.name()).collect();
+        assert_eq!(names.len(), 14);
+        assert_eq!(names[9], "PercArea");
+        assert_eq!(
+            &names[10..],
+            ["FeatureStart", "FeatureEnd", "name", "score"]
+        );
+    }
+
+    #[test]
+    fn test_project_reorders_and_subsets() {
+        let (region, candidate) = make_record_parts();
+        let record = OutputRecord {
+            region: &region,
+            candidate: Some(&candidate),
+            precision: 2,
+            na_string: "NA",
+            merge_separator: ",",
+            legacy_sentinel: false,
+            other_genes: None,
+            num_candidate_genes: None,
+            coordinates: CoordinateConvention::Bed0,
+        };
+
+        let columns = [
+            OutputColumn::Area,
+            OutputColumn::Metadata(0),
+            OutputColumn::Region,
+            OutputColumn::PercArea,
+            OutputColumn::Area,
+        ];
+        assert_eq!(
+            record.project(&columns),
+            "INTRON\tpeak1\tchr1_100_200\t3.50\tINTRON"
+        );
+
+        // Metadata columns beyond those present are padded
+        assert_eq!(
+            record.project(&[OutputColumn::Gene, OutputColumn::Metadata(5)]),
+            "G1\tNA"
+        );
+    }
+
+    #[test]
+    fn test_project_without_candidate() {
+        let (region, _) = make_record_parts();
+        let record = OutputRecord {
+            region: &region,
+            candidate: None,
+            precision: 2,
+            na_string: "NA",
+            merge_separator: ",",
+            legacy_sentinel: false,
+            other_genes: None,
+            num_candidate_genes: None,
+            coordinates: CoordinateConvention::Bed0,
+        };
+
+        assert_eq!(
+            record.project(&default_layout(2, &[ExtraColumn::RelativeStrand])),
+            "chr1_100_200\t150\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tpeak1\t7"
+        );
+    }
+}
@@ -0,0 +1,528 @@
+//! Comparison of two rgmatch association tables.
+//!
+//! [`diff_results`] matches the rows of two tables on their Region, Gene,
+//! Transcript and Area, then compares the other columns they share: numbers
+//! within a tolerance, so that `1.5` and `1.50` are equal, and any other
+//! value as text. The [`ResultsDiff`] lists the rows found in one table only,
+//! the rows whose values differ and, unless the order is ignored, the rows
+//! found in a different order.
+
+use ahash::AHashMap;
+use std::collections::VecDeque;
+use std::fmt;
+#[cfg(not(target_arch = "wasm32"))]
+use std::fs::File;
+use std::io::BufRead;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+
+use crate::error::{Error, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parser::util::create_buffered_reader;
+
+/// Columns a row is matched on; Transcript may be missing.
+const KEY_COLUMNS: [&str; 4] = ["Region", "Gene", "Transcript", "Area"];
+
+/// Rows listed by name in each section of the report.
+const LISTED_ROWS: usize = 10;
+
+/// Slack added to the tolerance, so that a difference of exactly the
+/// tolerance between two decimal values is not lost to float rounding.
+const FLOAT_SLACK: f64 = 1e-9;
+
+/// How two tables are compared.
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    /// Largest absolute difference between two equal numeric values.
+    pub tolerance: f64,
+    /// Compare the rows regardless of their order.
+    pub ignore_order: bool,
+}
+
+/// Region, Gene, Transcript and Area of a row.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RowKey {
+    pub region: String,
+    pub gene: String,
+    /// Empty if the table has no Transcript column.
+    pub transcript: String,
+    pub area: String,
+}
+
+impl fmt::Display for RowKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.region, self.gene)?;
+        if !self.transcript.is_empty() {
+            write!(f, " {}", self.transcript)?;
+        }
+        write!(f, " {}", self.area)
+    }
+}
+
+/// A row found in one table only.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffRow {
+    pub key: RowKey,
+    /// 1-based line of the row in its file.
+    pub line: usize,
+}
+
+/// A column whose values differ between two matched rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueDiff {
+    pub column: String,
+    pub a: String,
+    pub b: String,
+}
+
+/// Two matched rows with different values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RowChange {
+    pub key: RowKey,
+    /// 1-based lines of the row in the first and second file.
+    pub line_a: usize,
+    pub line_b: usize,
+    pub values: Vec<ValueDiff>,
+}
+
+/// Differences between two association tables, A and B.
+#[derive(Debug, Clone, Default)]
+pub struct ResultsDiff {
+    /// Number of rows of each table.
+    pub rows_a: usize,
+    pub rows_b: usize,
+    /// Columns of one header only, which are not compared.
+    pub columns_only_in_a: Vec<String>,
+    pub columns_only_in_b: Vec<String>,
+    /// Rows of one table without a matching row in the other, in file order.
+    pub only_in_a: Vec<DiffRow>,
+    pub only_in_b: Vec<DiffRow>,
+    /// Matched rows with a value outside the tolerance, in the order of A.
+    pub changed: Vec<RowChange>,
+    /// Matched rows of A found in B before a row that precedes them in A;
+    /// always zero when the order is ignored.
+    pub out_of_order: usize,
+}
+
+impl ResultsDiff {
+    /// Whether the tables hold the same rows and values.
+    pub fn is_identical(&self) -> bool {
+        self.columns_only_in_a.is_empty()
+            && self.columns_only_in_b.is_empty()
+            && self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && self.changed.is_empty()
+            && self.out_of_order == 0
+    }
+
+    /// Number of rows matched between the tables.
+    pub fn num_matched(&self) -> usize {
+        self.rows_a - self.only_in_a.len()
+    }
+}
+
+impl fmt::Display for ResultsDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Rows: {} in A, {} in B, {} matched",
+            self.rows_a,
+            self.rows_b,
+            self.num_matched()
+        )?;
+        if !self.columns_only_in_a.is_empty() {
+            writeln!(
+                f,
+                "Columns only in A: {}",
+                self.columns_only_in_a.join(", ")
+            )?;
+        }
+        if !self.columns_only_in_b.is_empty() {
+            writeln!(
+                f,
+                "Columns only in B: {}",
+                self.columns_only_in_b.join(", ")
+            )?;
+        }
+        for (name, rows) in [("A", &self.only_in_a), ("B", &self.only_in_b)] {
+            writeln!(f, "Rows only in {}: {}", name, rows.len())?;
+            for row in rows.iter().take(LISTED_ROWS) {
+                writeln!(f, "  line {}: {}", row.line, row.key)?;
+            }
+            write_more(f, rows.len())?;
+        }
+        writeln!(f, "Rows with different values: {}", self.changed.len())?;
+        for change in self.changed.iter().take(LISTED_ROWS) {
+            let values: Vec<String> = change
+                .values
+                .iter()
+                .map(|value| format!("{} {} != {}", value.column, value.a, value.b))
+                .collect();
+            writeln!(
+                f,
+                "  lines {}/{}: {}: {}",
+                change.line_a,
+                change.line_b,
+                change.key,
+                values.join(", ")
+            )?;
+        }
+        write_more(f, self.changed.len())?;
+        if self.out_of_order > 0 {
+            writeln!(f, "Rows in a different order: {}", self.out_of_order)?;
+        }
+        Ok(())
+    }
+}
+
+/// Note the rows of a section beyond those listed.
+fn write_more(f: &mut fmt::Formatter<'_>, total: usize) -> fmt::Result {
+    if total > LISTED_ROWS {
+        writeln!(f, "  ... and {} more", total - LISTED_ROWS)?;
+    }
+    Ok(())
+}
+
+/// Header and rows of a table.
+struct Table {
+    columns: Vec<String>,
+    /// Line number and fields of each row.
+    rows: Vec<(usize, Vec<String>)>,
+    /// Positions of `KEY_COLUMNS`; `None` for a missing Transcript column.
+    key: [Option<usize>; 4],
+}
+
+impl Table {
+    /// Read a table, skipping the `#` provenance lines before its header.
+    fn read<R: BufRead>(reader: R) -> Result<Self> {
+        let mut lines = reader.lines().enumerate();
+        let (header_line, header) = loop {
+            match lines.next() {
+                Some((i, line)) => {
+                    let line = line?;
+                    if !line.starts_with('#') {
+                        break (i + 1, line);
+                    }
+                }
+                None => {
+                    return Err(Error::ResultsParse {
+                        line: 0,
+                        reason: "no header line".to_string(),
+                    })
+                }
+            }
+        };
+        let columns: Vec<String> = header.split('\t').map(str::to_string).collect();
+        let key = KEY_COLUMNS.map(|name| columns.iter().position(|column| column == name));
+        let missing: Vec<&str> = KEY_COLUMNS
+            .iter()
+            .zip(&key)
+            .filter(|(name, index)| index.is_none() && **name != "Transcript")
+            .map(|(name, _)| *name)
+            .collect();
+        if !missing.is_empty() {
+            return Err(Error::ResultsParse {
+                line: header_line,
+                reason: format!(
+                    "not an rgmatch association table, missing column(s) {}",
+                    missing.join(", ")
+                ),
+            });
+        }
+
+        let mut rows = Vec::new();
+        for (i, line) in lines {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<String> = line.split('\t').map(str::to_string).collect();
+            if fields.len() != columns.len() {
+                return Err(Error::ResultsParse {
+                    line: i + 1,
+                    reason: format!("expected {} columns, found {}", columns.len(), fields.len()),
+                });
+            }
+            rows.push((i + 1, fields));
+        }
+        Ok(Table { columns, rows, key })
+    }
+
+    fn row_key(&self, fields: &[String]) -> RowKey {
+        let field = |index: Option<usize>| index.map_or_else(String::new, |i| fields[i].clone());
+        RowKey {
+            region: field(self.key[0]),
+            gene: field(self.key[1]),
+            transcript: field(self.key[2]),
+            area: field(self.key[3]),
+        }
+    }
+}
+
+/// Whether two values are equal: as numbers within `tolerance` when both
+/// are numbers, as text otherwise.
+fn values_equal(a: &str, b: &str, tolerance: f64) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => (x - y).abs() <= tolerance + FLOAT_SLACK,
+        _ => false,
+    }
+}
+
+/// Compare two association tables, as written by rgmatch.
+///
+/// Rows are matched on Region, Gene, Transcript and Area. A row whose key
+/// is shared by several rows of B, such as two introns of a transcript, is
+/// matched to the first of them with equal values, or else the first. Fails
+/// with [`Error::ResultsParse`] if a table has no Region, Gene or Area
+/// column, or a row with a different number of fields than its header.
+pub fn diff_results<A: BufRead, B: BufRead>(
+    a: A,
+    b: B,
+    options: &DiffOptions,
+) -> Result<ResultsDiff> {
+    Ok(diff_tables(&Table::read(a)?, &Table::read(b)?, options))
+}
+
+/// [`diff_results`] on two files, which may be gzipped.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn diff_files(a: &Path, b: &Path, options: &DiffOptions) -> Result<ResultsDiff> {
+    let read = |path: &Path| -> Result<Table> {
+        let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+        Table::read(create_buffered_reader(file, path)).map_err(|e| e.in_file(path))
+    };
+    Ok(diff_tables(&read(a)?, &read(b)?, options))
+}
+
+fn diff_tables(a: &Table, b: &Table, options: &DiffOptions) -> ResultsDiff {
+    let mut diff = ResultsDiff {
+        rows_a: a.rows.len(),
+        rows_b: b.rows.len(),
+        columns_only_in_a: only_in(&a.columns, &b.columns),
+        columns_only_in_b: only_in(&b.columns, &a.columns),
+        ..ResultsDiff::default()
+    };
+
+    // Columns compared by value: those of both headers, except the key
+    let compared: Vec<(&str, usize, usize)> = a
+        .columns
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| !KEY_COLUMNS.contains(&name.as_str()))
+        .filter_map(|(i, name)| {
+            let j = b.columns.iter().position(|column| column == name)?;
+            Some((name.as_str(), i, j))
+        })
+        .collect();
+
+    let mut b_rows: AHashMap<RowKey, VecDeque<usize>> = AHashMap::new();
+    for (index, (_, fields)) in b.rows.iter().enumerate() {
+        b_rows
+            .entry(b.row_key(fields))
+            .or_default()
+            .push_back(index);
+    }
+
+    let differences = |fields_a: &[String], fields_b: &[String]| -> Vec<ValueDiff> {
+        compared
+            .iter()
+            .filter(|(_, i, j)| !values_equal(&fields_a[*i], &fields_b[*j], options.tolerance))
+            .map(|(column, i, j)| ValueDiff {
+                column: column.to_string(),
+                a: fields_a[*i].clone(),
+                b: fields_b[*j].clone(),
+            })
+            .collect()
+    };
+
+    let mut matched_b = vec![false; b.rows.len()];
+    let mut last_b: Option<usize> = None;
+    for (line_a, fields_a) in &a.rows {
+        let key = a.row_key(fields_a);
+        // Of the rows of B with the same key, the first with equal values,
+        // else the first
+        let index = b_rows.get_mut(&key).and_then(|indexes| {
+            let equal = indexes
+                .iter()
+                .position(|&index| differences(fields_a, &b.rows[index].1).is_empty());
+            indexes.remove(equal.unwrap_or(0))
+        });
+        let Some(index) = index else {
+            diff.only_in_a.push(DiffRow { key, line: *line_a });
+            continue;
+        };
+        matched_b[index] = true;
+        if !options.ignore_order {
+            match last_b {
+                Some(last) if index < last => diff.out_of_order += 1,
+                _ => last_b = Some(index),
+            }
+        }
+
+        let (line_b, fields_b) = &b.rows[index];
+        let values = differences(fields_a, fields_b);
+        if !values.is_empty() {
+            diff.changed.push(RowChange {
+                key,
+                line_a: *line_a,
+                line_b: *line_b,
+                values,
+            });
+        }
+    }
+
+    for ((line, fields), matched) in b.rows.iter().zip(matched_b) {
+        if !matched {
+            diff.only_in_b.push(DiffRow {
+                key: b.row_key(fields),
+                line: *line,
+            });
+        }
+    }
+    diff
+}
+
+/// Columns of `columns` missing from `other`.
+fn only_in(columns: &[String], other: &[String]) -> Vec<String> {
+    columns
+        .iter()
+        .filter(|column| !other.contains(column))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "# rgmatch_version: 0.1.0\nRegion\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea\n";
+
+    fn table(rows: &[&str]) -> String {
+        let mut table = HEADER.to_string();
+        for row in rows {
+            table.push_str(row);
+            table.push('\n');
+        }
+        table
+    }
+
+    fn diff(a: &str, b: &str, tolerance: f64, ignore_order: bool) -> ResultsDiff {
+        let options = DiffOptions {
+            tolerance,
+            ignore_order,
+        };
+        diff_results(a.as_bytes(), b.as_bytes(), &options).unwrap()
+    }
+
+    const ROW_1: &str = "chr1_100_200\t150\tG1\tT1\t1\tTSS\t0\t-20\t100.00\t50.00";
+    const ROW_2: &str = "chr1_100_200\t150\tG1\tT1\t2\tINTRON\t0\t300\t40.00\t5.00";
+    const ROW_3: &str = "chr1_900_950\t925\tG2\tT2\t1\tPROMOTER\t80\t-80\t100.00\t3.85";
+
+    #[test]
+    fn test_reordered_tables() {
+        let a = table(&[ROW_1, ROW_2, ROW_3]);
+        let b = table(&[ROW_3, ROW_1, ROW_2]);
+
+        let ordered = diff(&a, &b, 0.0, false);
+        assert_eq!(ordered.num_matched(), 3);
+        assert_eq!(ordered.out_of_order, 1);
+        assert!(!ordered.is_identical());
+        assert!(ordered
+            .to_string()
+            .contains("Rows in a different order: 1\n"));
+
+        let unordered = diff(&a, &b, 0.0, true);
+        assert!(unordered.is_identical(), "{}", unordered);
+        assert_eq!(
+            unordered.to_string(),
+            "Rows: 3 in A, 3 in B, 3 matched\nRows only in A: 0\nRows only in B: 0\nRows with different values: 0\n"
+        );
+    }
+
+    #[test]
+    fn test_rounded_percentage() {
+        let a = table(&[ROW_1, ROW_3]);
+        let rounded = ROW_3.replace("3.85", "3.86");
+        let b = table(&[ROW_1, &rounded]);
+
+        let strict = diff(&a, &b, 0.0, false);
+        assert_eq!(
+            strict.changed,
+            vec![RowChange {
+                key: RowKey {
+                    region: "chr1_900_950".to_string(),
+                    gene: "G2".to_string(),
+                    transcript: "T2".to_string(),
+                    area: "PROMOTER".to_string(),
+                },
+                line_a: 4,
+                line_b: 4,
+                values: vec![ValueDiff {
+                    column: "PercArea".to_string(),
+                    a: "3.85".to_string(),
+                    b: "3.86".to_string(),
+                }],
+            }]
+        );
+        assert!(strict
+            .to_string()
+            .contains("  lines 4/4: chr1_900_950 G2 T2 PROMOTER: PercArea 3.85 != 3.86\n"));
+
+        assert!(diff(&a, &b, 0.01, false).is_identical());
+
+        // Formatting alone is not a difference
+        let reformatted = ROW_1.replace("50.00", "50");
+        assert!(diff(&a, &table(&[&reformatted, ROW_3]), 0.0, false).is_identical());
+    }
+
+    #[test]
+    fn test_unmatched_rows_and_columns() {
+        let a = table(&[ROW_1, ROW_2, ROW_2]);
+        let b = table(&[ROW_1, ROW_2, ROW_3]);
+
+        // Rows with the same key are matched on their values, whatever the
+        // order
+        let intron_3 = ROW_2.replace("\t2\tINTRON\t0\t300", "\t3\tINTRON\t0\t700");
+        let two_introns = diff(
+            &table(&[ROW_2, &intron_3]),
+            &table(&[&intron_3, ROW_2]),
+            0.0,
+            true,
+        );
+        assert!(two_introns.is_identical(), "{}", two_introns);
+
+        // Rows with the same key are matched one to one
+        let result = diff(&a, &b, 0.0, false);
+        assert_eq!(result.only_in_a.len(), 1);
+        assert_eq!(result.only_in_a[0].line, 5);
+        assert_eq!(result.only_in_b.len(), 1);
+        assert_eq!(result.only_in_b[0].key.gene, "G2");
+        assert!(result
+            .to_string()
+            .contains("Rows only in B: 1\n  line 5: chr1_900_950 G2 T2 PROMOTER\n"));
+
+        let without_midpoint = "Region\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea\nchr1_100_200\tG1\tT1\t1\tTSS\t0\t-20\t100.00\t50.00\n";
+        let result = diff(&table(&[ROW_1]), without_midpoint, 0.0, false);
+        assert_eq!(result.columns_only_in_a, vec!["Midpoint".to_string()]);
+        assert!(result.changed.is_empty());
+        assert!(!result.is_identical());
+    }
+
+    #[test]
+    fn test_invalid_tables() {
+        let options = DiffOptions::default();
+        let no_area = "Region\tGene\nchr1_1_2\tG1\n";
+        assert!(matches!(
+            diff_results(no_area.as_bytes(), no_area.as_bytes(), &options),
+            Err(Error::ResultsParse { line: 1, ref reason }) if reason.ends_with("column(s) Area")
+        ));
+
+        let short_row = table(&["chr1_100_200\t150\tG1"]);
+        assert!(matches!(
+            diff_results(short_row.as_bytes(), short_row.as_bytes(), &options),
+            Err(Error::ResultsParse { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,1188 @@
+//! Output formatting for rgmatch results.
+//!
+//! This module handles writing formatted output to files with proper
+//! column ordering and number formatting.
+
+pub mod columns;
+pub mod diff;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod provenance;
+pub mod reader;
+pub mod summary;
+
+use indexmap::IndexMap;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::Write;
+
+use crate::config::{Config, DEFAULT_MERGE_SEPARATOR, DEFAULT_NA_STRING, DEFAULT_OUTPUT_PRECISION};
+use crate::error::{Error, Result};
+use crate::matcher::overlap::RegionMatches;
+use crate::matcher::rules::select_best_candidate;
+use crate::types::{
+    Association, Candidate, CoordinateConvention, OutputFormat, Region, ReportLevel, Strand,
+};
+
+pub use columns::{default_layout, format_percentage, OutputColumn, OutputRecord};
+
+use columns::MAX_META_COLUMNS;
+
+/// Buffer size for output files.
+pub const OUTPUT_BUFFER_SIZE: usize = 1 << 20;
+
+/// Write the output header.
+pub fn write_header<W: Write>(writer: &mut W, num_meta_columns: usize) -> Result<()> {
+    write_layout_header(writer, &default_layout(num_meta_columns, &[]))
+}
+
+/// Write a header line naming the given columns.
+fn write_layout_header<W: Write>(writer: &mut W, columns: &[OutputColumn]) -> Result<()> {
+    let names: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    writeln!(writer, "{}", names.join("\t"))?;
+    Ok(())
+}
+
+/// Get the TSV column layout for the configured columns.
+///
+/// This is `config.columns` if set, otherwise the standard columns followed
+/// by the extra columns, `Rank` with `n_closest`, `Selected` with
+/// `report_all_candidates` or `reports_all_overlapped_genes`, `OtherGenes`
+/// in wide mode, and `num_meta_columns` BED metadata columns.
+pub fn output_layout(num_meta_columns: usize, config: &Config) -> Vec<OutputColumn> {
+    match &config.columns {
+        Some(columns) => columns.clone(),
+        None => {
+            let mut columns = default_layout(0, &config.extra_columns);
+            if config.n_closest > 0 {
+                columns.push(OutputColumn::Rank);
+            }
+            if config.report_all_candidates || config.reports_all_overlapped_genes() {
+                columns.push(OutputColumn::Selected);
+            }
+            if config.wide {
+                columns.push(OutputColumn::OtherGenes);
+            }
+            columns.extend((0..num_meta_columns.min(MAX_META_COLUMNS)).map(OutputColumn::Metadata));
+            columns
+        }
+    }
+}
+
+/// Write the header appropriate for the configured output format.
+///
+/// The provenance comment lines, if configured, come first. BED and closest
+/// output have no column header line.
+pub fn write_output_header<W: Write>(
+    writer: &mut W,
+    num_meta_columns: usize,
+    config: &Config,
+) -> Result<()> {
+    if let Some(provenance) = &config.provenance {
+        provenance.write(writer, config)?;
+    }
+
+    match config.output_format {
+        OutputFormat::Tsv => write_layout_header(writer, &output_layout(num_meta_columns, config)),
+        OutputFormat::Bed | OutputFormat::Parquet | OutputFormat::Closest => Ok(()),
+    }
+}
+
+/// Get the candidates of a region that should be written.
+///
+/// With `best_only` or `wide` set, this is the single best candidate according
+/// to the configured thresholds and rules; otherwise all candidates are returned.
+/// At gene level, `best_only` keeps the other gene of a bidirectional promoter
+/// too, and every overlapped gene with `reports_all_overlapped_genes` (the
+/// matcher has already dropped the others).
+pub fn candidates_to_write<'a>(
+    candidates: &'a [Candidate],
+    config: &Config,
+) -> Cow<'a, [Candidate]> {
+    if !config.wide && (!config.best_only || config.reports_all_overlapped_genes()) {
+        return Cow::Borrowed(candidates);
+    }
+
+    match select_best_candidate(
+        candidates,
+        config.perc_region,
+        config.perc_area,
+        &config.rules,
+    ) {
+        Some(best) if best.bidirectional && config.level == ReportLevel::Gene && !config.wide => {
+            let partners: Vec<Candidate> = candidates
+                .iter()
+                .filter(|c| c.bidirectional && c.gene != best.gene)
+                .cloned()
+                .collect();
+            Cow::Owned(std::iter::once(best).chain(partners).collect())
+        }
+        Some(best) => Cow::Owned(vec![best]),
+        None => Cow::Borrowed(&[]),
+    }
+}
+
+/// Summarize the genes of a region other than the one of `best` (wide output).
+///
+/// Each other gene contributes its best candidate according to the configured
+/// rules, as `gene:area:distance`, in order of first appearance. Entries are
+/// joined by `;`, and `None` is returned if there are no other genes.
+pub fn format_other_genes(
+    candidates: &[Candidate],
+    best: &Candidate,
+    config: &Config,
+) -> Option<String> {
+    let mut by_gene: IndexMap<&str, Vec<Candidate>> = IndexMap::new();
+    for candidate in candidates {
+        if candidate.gene != best.gene {
+            by_gene
+                .entry(candidate.gene.as_str())
+                .or_default()
+                .push(candidate.clone());
+        }
+    }
+
+    let entries: Vec<String> = by_gene
+        .values()
+        .filter_map(|group| {
+            select_best_candidate(group, config.perc_region, config.perc_area, &config.rules)
+        })
+        .map(|c| format!("{}:{}:{}", c.gene, c.area, c.distance))
+        .collect();
+
+    if entries.is_empty() {
+        None
+    } else {
+        Some(entries.join(";"))
+    }
+}
+
+/// Build the output record of one candidate of `association`.
+///
+/// In wide mode all candidates of the association fill the `OtherGenes`
+/// column.
+pub fn association_record<'a>(
+    association: &'a Association,
+    candidate: &'a Candidate,
+    config: &'a Config,
+) -> OutputRecord<'a> {
+    let mut record = OutputRecord::new(&association.region, Some(candidate), config);
+    record.num_candidate_genes = Some(association.num_candidate_genes);
+    if config.wide {
+        record.other_genes = format_other_genes(&association.candidates, candidate, config);
+    }
+    record
+}
+
+/// Build the output record of a region without associations.
+pub fn unassigned_record<'a>(association: &'a Association, config: &'a Config) -> OutputRecord<'a> {
+    let mut record = OutputRecord::new(&association.region, None, config);
+    record.num_candidate_genes = Some(association.num_candidate_genes);
+    record
+}
+
+/// Format a record as a line in the configured output format.
+///
+/// Parquet is not line-based; its records use the TSV line.
+pub fn format_record(record: &OutputRecord, config: &Config) -> String {
+    format_record_with_meta_columns(record, record.region.metadata.len(), config)
+}
+
+/// [`format_record`] with exactly `num_meta_columns` BED metadata columns in
+/// TSV output, as in the header: regions with fewer are padded with the NA
+/// string and extra ones are dropped.
+pub fn format_record_with_meta_columns(
+    record: &OutputRecord,
+    num_meta_columns: usize,
+    config: &Config,
+) -> String {
+    let mut line = String::new();
+    format_record_into(
+        &mut line,
+        record,
+        &output_layout(num_meta_columns, config),
+        config,
+    );
+    line
+}
+
+/// Append the line of `record` in the configured output format to `line`,
+/// with the TSV columns of `layout`.
+pub fn format_record_into(
+    line: &mut String,
+    record: &OutputRecord,
+    layout: &[OutputColumn],
+    config: &Config,
+) {
+    match (config.output_format, record.candidate) {
+        (OutputFormat::Tsv | OutputFormat::Parquet, _) => record.project_into(line, layout),
+        (OutputFormat::Bed, Some(candidate)) => {
+            format_bed_line_into(line, record.region, candidate)
+        }
+        (OutputFormat::Bed, None) => {
+            let region = record.region;
+            let score = region.metadata.get(1).map(|s| s.trim_end()).unwrap_or("0");
+            let _ = write!(
+                line,
+                "{}\t{}\t{}\tNA\t{}\t.",
+                region.chrom, region.start, region.end, score
+            );
+        }
+        (OutputFormat::Closest, Some(candidate)) if !candidate.is_intergenic() => {
+            format_closest_line_into(line, record.region, candidate)
+        }
+        (OutputFormat::Closest, _) => {
+            // bedtools writes the same placeholder when B has no feature
+            format_region_bed_line_into(line, record.region);
+            line.push_str("\t.\t-1\t-1\t.\t-1\t.\t-1");
+        }
+    }
+}
+
+/// Format a single output line in the configured output format.
+pub fn format_line(region: &Region, candidate: &Candidate, config: &Config) -> String {
+    format_record(&OutputRecord::new(region, Some(candidate), config), config)
+}
+
+/// Format a region without associations for `keep_unassigned` output.
+///
+/// Association columns are filled with `NA`; the region columns and metadata
+/// are kept as usual.
+pub fn format_unassigned_line(region: &Region, config: &Config) -> String {
+    format_record(&OutputRecord::new(region, None, config), config)
+}
+
+/// Format a region as a BED line with its original coordinates and metadata.
+pub fn format_region_bed_line(region: &Region) -> String {
+    let mut line = String::new();
+    format_region_bed_line_into(&mut line, region);
+    line
+}
+
+/// [`format_region_bed_line`] appending to `line`.
+pub fn format_region_bed_line_into(line: &mut String, region: &Region) {
+    let _ = write!(line, "{}\t{}\t{}", region.chrom, region.start, region.end);
+    if !region.metadata.is_empty() {
+        line.push('\t');
+        let start = line.len();
+        for (i, value) in region.metadata.iter().enumerate() {
+            if i > 0 {
+                line.push('\t');
+            }
+            line.push_str(value);
+        }
+        let kept = line[start..].trim_end().len();
+        line.truncate(start + kept);
+    }
+}
+
+/// Destination for results in the configured output format.
+///
+/// TSV and BED are written line by line. Parquet needs the number of metadata
+/// columns for its schema, so its writer is opened by `write_header`.
+pub struct OutputSink<W: Write + Send> {
+    format: OutputFormat,
+    /// Text destination, or the Parquet destination until the header is written.
+    writer: Option<W>,
+    /// Number of BED metadata columns in the header, which every row has.
+    num_meta_columns: usize,
+    /// TSV columns, set by the first record after the header.
+    layout: Option<Vec<OutputColumn>>,
+    /// Line buffer reused by every record.
+    line: String,
+    #[cfg(feature = "parquet")]
+    parquet: Option<Box<parquet::ParquetSink<W>>>,
+}
+
+impl<W: Write + Send> OutputSink<W> {
+    /// Create a sink writing the configured output format to `writer`.
+    ///
+    /// Fails for Parquet output when built without the `parquet` feature.
+    pub fn new(writer: W, config: &Config) -> Result<Self> {
+        if config.output_format == OutputFormat::Parquet && !cfg!(feature = "parquet") {
+            return Err(Error::Output(
+                "Parquet output requires rgmatch built with the `parquet` feature".to_string(),
+            ));
+        }
+
+        Ok(OutputSink {
+            format: config.output_format,
+            writer: Some(writer),
+            num_meta_columns: 0,
+            layout: None,
+            line: String::new(),
+            #[cfg(feature = "parquet")]
+            parquet: None,
+        })
+    }
+
+    /// Get the underlying writer of line-based (TSV or BED) output.
+    pub fn text_writer(&mut self) -> Option<&mut W> {
+        match self.format {
+            OutputFormat::Parquet => None,
+            _ => self.writer.as_mut(),
+        }
+    }
+
+    fn writer(&mut self) -> Result<&mut W> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| Error::Output("output writer is closed".to_string()))
+    }
+
+    #[cfg(feature = "parquet")]
+    fn parquet(&mut self) -> Result<&mut parquet::ParquetSink<W>> {
+        self.parquet
+            .as_deref_mut()
+            .ok_or_else(|| Error::Output("Parquet header must be written first".to_string()))
+    }
+
+    /// Write the header (or open the Parquet writer).
+    ///
+    /// Every row written afterwards has `num_meta_columns` BED metadata
+    /// columns, padded or trimmed as needed.
+    pub fn write_header(&mut self, num_meta_columns: usize, config: &Config) -> Result<()> {
+        self.num_meta_columns = num_meta_columns;
+        self.layout = None;
+        #[cfg(feature = "parquet")]
+        if self.format == OutputFormat::Parquet {
+            let writer = self.writer.take().expect("Parquet writer opened twice");
+            let sink = parquet::ParquetSink::new(writer, num_meta_columns, config)?;
+            self.parquet = Some(Box::new(sink));
+            return Ok(());
+        }
+
+        write_output_header(self.writer()?, num_meta_columns, config)
+    }
+
+    /// Write one output record.
+    pub fn write_record(&mut self, record: &OutputRecord, config: &Config) -> Result<()> {
+        #[cfg(feature = "parquet")]
+        if self.format == OutputFormat::Parquet {
+            return self.parquet()?.write_record(record);
+        }
+
+        let num_meta_columns = self.num_meta_columns;
+        let layout = self
+            .layout
+            .get_or_insert_with(|| output_layout(num_meta_columns, config));
+        self.line.clear();
+        format_record_into(&mut self.line, record, layout, config);
+        self.line.push('\n');
+
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| Error::Output("output writer is closed".to_string()))?;
+        writer.write_all(self.line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Write one region-candidate association.
+    pub fn write_association(
+        &mut self,
+        region: &Region,
+        candidate: &Candidate,
+        config: &Config,
+    ) -> Result<()> {
+        self.write_record(&OutputRecord::new(region, Some(candidate), config), config)
+    }
+
+    /// Write a region without associations (`keep_unassigned` output).
+    pub fn write_unassigned(&mut self, region: &Region, config: &Config) -> Result<()> {
+        self.write_record(&OutputRecord::new(region, None, config), config)
+    }
+
+    /// Write the rows of one region: a record per candidate in `to_write`
+    /// (see [`candidates_to_write`]), or an unassigned record if there are
+    /// none and `keep_unassigned` or `wide` is set.
+    ///
+    /// Returns the number of rows written.
+    pub fn write_region(
+        &mut self,
+        association: &Association,
+        to_write: &[Candidate],
+        config: &Config,
+    ) -> Result<usize> {
+        for candidate in to_write {
+            self.write_record(&association_record(association, candidate, config), config)?;
+        }
+        if !to_write.is_empty() {
+            Ok(to_write.len())
+        } else if config.keep_unassigned || config.wide {
+            self.write_record(&unassigned_record(association, config), config)?;
+            Ok(1)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Flush all output and return the underlying writer.
+    pub fn finish(mut self) -> Result<W> {
+        #[cfg(feature = "parquet")]
+        if let Some(sink) = self.parquet.take() {
+            let mut writer = sink.finish()?;
+            writer.flush()?;
+            return Ok(writer);
+        }
+
+        let mut writer = self
+            .writer
+            .take()
+            .ok_or_else(|| Error::Output("output writer is closed".to_string()))?;
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+/// Write the header followed by the records of each association.
+///
+/// Accepts any `Write` implementation, so results can go to a file, stdout,
+/// or an in-memory buffer. Callers are responsible for buffering.
+pub fn write_associations<W: Write + Send>(
+    writer: &mut W,
+    associations: &[Association],
+    num_meta_columns: usize,
+    config: &Config,
+) -> Result<()> {
+    let mut sink = OutputSink::new(writer, config)?;
+    sink.write_header(num_meta_columns, config)?;
+
+    for association in associations {
+        let to_write = candidates_to_write(&association.candidates, config);
+        sink.write_region(association, &to_write, config)?;
+    }
+
+    sink.finish()?;
+    Ok(())
+}
+
+/// Write the header followed by one record per region-candidate association.
+///
+/// `NumCandidateGenes` counts the genes of the given candidates.
+#[deprecated(note = "use `write_associations`, which takes `Association` values")]
+pub fn write_results<W: Write + Send>(
+    writer: &mut W,
+    results: &[(Region, Vec<Candidate>)],
+    num_meta_columns: usize,
+    config: &Config,
+) -> Result<()> {
+    let associations: Vec<Association> = results
+        .iter()
+        .map(|(region, candidates)| {
+            RegionMatches::new(candidates.clone()).into_association(region.clone())
+        })
+        .collect();
+    write_associations(writer, &associations, num_meta_columns, config)
+}
+
+/// Format a region-candidate pair as a BED6 line.
+///
+/// The region keeps its original BED coordinates (0-based start, end as read)
+/// on the candidate's chromosome. The name is `GENE|AREA|DISTANCE`, the
+/// score is the region's original score column (or `0` if absent), and the
+/// strand is the gene's strand. Intergenic regions have no strand (`.`), and
+/// `NA` as distance on a chromosome without genes.
+pub fn format_bed_line(region: &Region, candidate: &Candidate) -> String {
+    let mut line = String::new();
+    format_bed_line_into(&mut line, region, candidate);
+    line
+}
+
+/// [`format_bed_line`] appending to `line`.
+pub fn format_bed_line_into(line: &mut String, region: &Region, candidate: &Candidate) {
+    let score = region.metadata.get(1).map(|s| s.trim_end()).unwrap_or("0");
+    let strand = if candidate.is_intergenic() {
+        "."
+    } else {
+        candidate.strand.as_str()
+    };
+
+    let _ = write!(
+        line,
+        "{}\t{}\t{}\t{}|{}|",
+        candidate.chrom, region.start, region.end, candidate.gene, candidate.area
+    );
+    let _ = match candidate.feature_distance() {
+        Some(distance) => write!(line, "{}", distance),
+        None => write!(line, "{}", DEFAULT_NA_STRING),
+    };
+    let _ = write!(line, "\t{}\t{}", score, strand);
+}
+
+/// Signed distance between a region and its candidate's gene, as reported by
+/// `bedtools closest -D b`.
+///
+/// The region is the half-open BED interval as read and the gene is its
+/// 1-based annotation span. Overlapping features are 0 apart and book-ended
+/// features 1. The distance is negative when the region is upstream of the
+/// gene with respect to the gene's strand.
+pub fn closest_distance(region: &Region, candidate: &Candidate) -> i64 {
+    // Gene as a half-open BED interval
+    let gene_start = candidate.gene_start - 1;
+    let gene_end = candidate.gene_end;
+
+    let (distance, region_before_gene) = if region.end <= gene_start {
+        (gene_start - region.end + 1, true)
+    } else if gene_end <= region.start {
+        (region.start - gene_end + 1, false)
+    } else {
+        return 0;
+    };
+
+    let upstream = match candidate.strand {
+        Strand::Positive => region_before_gene,
+        Strand::Negative => !region_before_gene,
+    };
+    if upstream {
+        -distance
+    } else {
+        distance
+    }
+}
+
+/// Format a region-candidate pair in the `bedtools closest` layout.
+///
+/// The region's BED columns are followed by the gene as BED6 (the candidate's
+/// chromosome, 0-based start, gene ID as name, `.` as score) and [`closest_distance`].
+pub fn format_closest_line(region: &Region, candidate: &Candidate) -> String {
+    let mut line = String::new();
+    format_closest_line_into(&mut line, region, candidate);
+    line
+}
+
+/// [`format_closest_line`] appending to `line`.
+pub fn format_closest_line_into(line: &mut String, region: &Region, candidate: &Candidate) {
+    format_region_bed_line_into(line, region);
+    let _ = write!(
+        line,
+        "\t{}\t{}\t{}\t{}\t.\t{}\t{}",
+        candidate.chrom,
+        candidate.gene_start - 1,
+        candidate.gene_end,
+        candidate.gene,
+        candidate.strand,
+        closest_distance(region, candidate)
+    );
+}
+
+/// Format a single output line for a region-candidate pair.
+///
+/// Uses the default layout: no extra columns and two decimals for percentages.
+pub fn format_output_line(region: &Region, candidate: &Candidate) -> String {
+    let mut line = String::new();
+    format_output_line_into(&mut line, region, candidate);
+    line
+}
+
+/// [`format_output_line`] appending to `line`, which can be reused across
+/// rows to avoid allocating one string per row.
+pub fn format_output_line_into(line: &mut String, region: &Region, candidate: &Candidate) {
+    let record = OutputRecord {
+        region,
+        candidate: Some(candidate),
+        precision: DEFAULT_OUTPUT_PRECISION,
+        na_string: DEFAULT_NA_STRING,
+        merge_separator: DEFAULT_MERGE_SEPARATOR,
+        legacy_sentinel: false,
+        other_genes: None,
+        num_candidate_genes: None,
+        coordinates: CoordinateConvention::Bed0,
+    };
+    record.project_into(line, &default_layout(region.metadata.len(), &[]));
+}
+
+/// Format a single output line using the column layout and precision of `config`.
+pub fn format_output_line_with_config(
+    region: &Region,
+    candidate: &Candidate,
+    config: &Config,
+) -> String {
+    OutputRecord::new(region, Some(candidate), config)
+        .project(&output_layout(region.metadata.len(), config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Area, ExtraColumn, Strand};
+
+    #[test]
+    fn test_format_output_line() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["name1".to_string()]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            50,
+            80.123,
+            90.456,
+            500,
+        );
+
+        let line = format_output_line(&region, &candidate);
+
+        assert!(line.contains("chr1_100_200"));
+        assert!(line.contains("150")); // midpoint
+        assert!(line.contains("G1"));
+        assert!(line.contains("T1"));
+        assert!(line.contains("TSS"));
+        assert!(line.contains("80.12")); // 2 decimal places
+        assert!(line.contains("90.46")); // 2 decimal places
+        assert!(line.contains("name1"));
+    }
+
+    #[test]
+    fn test_format_into_appends_to_reused_buffer() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["name1 ".to_string()]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Negative,
+            2,
+            Area::Intron,
+            "T1",
+            "G1",
+            -25,
+            12.5,
+            -1.0,
+            -300,
+        );
+
+        let mut line = String::from("previous\t");
+        format_output_line_into(&mut line, &region, &candidate);
+        assert_eq!(
+            line,
+            format!("previous\t{}", format_output_line(&region, &candidate))
+        );
+
+        line.clear();
+        format_bed_line_into(&mut line, &region, &candidate);
+        assert_eq!(line, format_bed_line(&region, &candidate));
+        line.clear();
+        format_closest_line_into(&mut line, &region, &candidate);
+        assert_eq!(line, format_closest_line(&region, &candidate));
+        assert!(line.starts_with("chr1\t100\t200\tname1\t"));
+    }
+
+    #[test]
+    fn test_midpoint_is_integer() {
+        // Test that midpoint uses integer division
+        let region = Region::new("chr1".to_string(), 100, 201, vec![]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            100.0,
+            0,
+        );
+
+        let line = format_output_line(&region, &candidate);
+
+        // (100 + 201) / 2 = 150 (integer division)
+        assert!(line.contains("\t150\t"));
+        assert!(!line.contains("150.5"));
+    }
+
+    #[test]
+    fn test_negative_pctg_area() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::Upstream,
+            "T1".to_string(),
+            "G1".to_string(),
+            1000,
+            100.0,
+            -1.0,
+            500,
+        );
+
+        // The -1.0 sentinel is a missing value
+        let line = format_output_line(&region, &candidate);
+        assert!(line.ends_with("\t100.00\tNA"));
+
+        let mut config = Config::new();
+        config.na_string = ".".to_string();
+        let line = format_output_line_with_config(&region, &candidate, &config);
+        assert!(line.ends_with("\t100.00\t."));
+
+        // Python-compatible output keeps the sentinel
+        config.legacy_sentinel = true;
+        let line = format_output_line_with_config(&region, &candidate, &config);
+        assert!(line.ends_with("\t100.00\t-1.00"));
+    }
+
+    /// Association of `candidates` with `region`.
+    fn association(region: Region, candidates: Vec<Candidate>) -> Association {
+        RegionMatches::new(candidates).into_association(region)
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_write_results_to_buffer() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::Tss,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            100.0,
+            0,
+        );
+        let results = vec![(region, vec![candidate])];
+
+        let mut output = Vec::new();
+        write_results(&mut output, &results, 0, &Config::default()).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("Region\tMidpoint"));
+        assert!(lines[1].starts_with("chr1_100_200\t150\tG1\tT1"));
+    }
+
+    #[test]
+    fn test_format_bed_line() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "850".to_string(), "+".to_string()],
+        );
+        let candidate = Candidate::new(
+            300,
+            400,
+            Strand::Negative,
+            1,
+            Area::Promoter,
+            "T1".to_string(),
+            "G1".to_string(),
+            250,
+            100.0,
+            20.0,
+            -250,
+        )
+        .with_region(&region);
+
+        assert_eq!(
+            format_bed_line(&region, &candidate),
+            "chr1\t100\t200\tG1|PROMOTER|250\t850\t-"
+        );
+
+        // Missing score column falls back to 0
+        let bare = Region::new("chr1".to_string(), 100, 200, vec![]);
+        assert_eq!(
+            format_bed_line(&bare, &candidate),
+            "chr1\t100\t200\tG1|PROMOTER|250\t0\t-"
+        );
+    }
+
+    #[test]
+    fn test_closest_distance() {
+        // Gene at 10000-12000 (1-based), i.e. BED 9999-12000
+        let gene = |strand| {
+            Candidate::new(
+                10000,
+                12000,
+                strand,
+                1,
+                Area::Upstream,
+                "T1".to_string(),
+                "G1".to_string(),
+                0,
+                100.0,
+                -1.0,
+                0,
+            )
+            .with_gene_span(10000, 12000)
+            .with_region(&Region::new("chr1".to_string(), 0, 1, vec![]))
+        };
+        let region = |start, end| Region::new("chr1".to_string(), start, end, vec![]);
+
+        let plus = gene(Strand::Positive);
+        assert_eq!(closest_distance(&region(8000, 8100), &plus), -1900);
+        assert_eq!(closest_distance(&region(9000, 9999), &plus), -1);
+        assert_eq!(closest_distance(&region(9000, 10000), &plus), 0);
+        assert_eq!(closest_distance(&region(11000, 11100), &plus), 0);
+        assert_eq!(closest_distance(&region(11999, 12100), &plus), 0);
+        assert_eq!(closest_distance(&region(12000, 12100), &plus), 1);
+        assert_eq!(closest_distance(&region(12100, 12200), &plus), 101);
+
+        let minus = gene(Strand::Negative);
+        assert_eq!(closest_distance(&region(8000, 8100), &minus), 1900);
+        assert_eq!(closest_distance(&region(12100, 12200), &minus), -101);
+
+        assert_eq!(
+            format_closest_line(&region(8000, 8100), &minus),
+            "chr1\t8000\t8100\tchr1\t9999\t12000\tG1\t.\t-\t1900"
+        );
+    }
+
+    #[test]
+    fn test_write_associations_bed_best_only() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let make = |area, gene: &str| {
+            Candidate::new(
+                100,
+                200,
+                Strand::Positive,
+                1,
+                area,
+                "T1".to_string(),
+                gene.to_string(),
+                0,
+                100.0,
+                100.0,
+                0,
+            )
+            .with_region(&region)
+        };
+        let candidates = vec![make(Area::Intron, "G1"), make(Area::Tss, "G2")];
+        let results = vec![association(region, candidates)];
+
+        let mut config = Config {
+            output_format: OutputFormat::Bed,
+            ..Config::default()
+        };
+
+        let mut output = Vec::new();
+        write_associations(&mut output, &results, 0, &config).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text.lines().count(), 2);
+        assert!(!text.contains("Region"));
+
+        config.best_only = true;
+        let mut output = Vec::new();
+        write_associations(&mut output, &results, 0, &config).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(text, "chr1\t100\t200\tG2|TSS|0\t0\t+\n");
+    }
+
+    #[test]
+    fn test_unassigned_lines() {
+        let region = Region::new(
+            "chr2".to_string(),
+            100,
+            201,
+            vec!["peak1".to_string(), "7".to_string()],
+        );
+
+        let mut config = Config::default();
+        assert_eq!(
+            format_unassigned_line(&region, &config),
+            "chr2_100_201\t150\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tpeak1\t7"
+        );
+
+        config.output_format = OutputFormat::Bed;
+        assert_eq!(
+            format_unassigned_line(&region, &config),
+            "chr2\t100\t201\tNA\t7\t."
+        );
+
+        assert_eq!(format_region_bed_line(&region), "chr2\t100\t201\tpeak1\t7");
+    }
+
+    #[test]
+    fn test_write_associations_keep_unassigned() {
+        let results = vec![association(
+            Region::new("chr1".to_string(), 100, 200, vec![]),
+            vec![],
+        )];
+
+        let mut config = Config::default();
+        let mut output = Vec::new();
+        write_associations(&mut output, &results, 0, &config).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap().lines().count(), 1);
+
+        config.keep_unassigned = true;
+        let mut output = Vec::new();
+        write_associations(&mut output, &results, 0, &config).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some("chr1_100_200\t150\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA")
+        );
+    }
+
+    #[test]
+    fn test_write_associations_wide() {
+        let make = |area, gene: &str, transcript: &str, distance| {
+            Candidate::new(
+                1000,
+                1500,
+                Strand::Positive,
+                1,
+                area,
+                transcript.to_string(),
+                gene.to_string(),
+                distance,
+                100.0,
+                50.0,
+                0,
+            )
+        };
+        let results = vec![
+            association(
+                Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]),
+                vec![
+                    make(Area::Intron, "G1", "T1", 0),
+                    make(Area::Upstream, "G2", "T2", 350),
+                    make(Area::Tss, "G1", "T3", 0),
+                    make(Area::Downstream, "G2", "T4", 900),
+                ],
+            ),
+            association(
+                Region::new("chr1".to_string(), 5000, 5100, vec!["peak2".to_string()]),
+                vec![],
+            ),
+        ];
+
+        let config = Config {
+            wide: true,
+            ..Config::default()
+        };
+        let mut output = Vec::new();
+        write_associations(&mut output, &results, 1, &config).unwrap();
+        let text = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+
+        // Header plus exactly one row per region
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with("\tPercArea\tOtherGenes\tname"));
+        assert_eq!(
+            lines[1],
+            "chr1_100_200\t150\tG1\tT3\t1\tTSS\t0\t0\t100.00\t50.00\tG2:UPSTREAM:350\tpeak1"
+        );
+        assert_eq!(
+            lines[2],
+            "chr1_5000_5100\t5050\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tpeak2"
+        );
+    }
+
+    #[test]
+    fn test_format_other_genes() {
+        let make = |gene: &str, area| {
+            Candidate::new(
+                0,
+                0,
+                Strand::Negative,
+                1,
+                area,
+                "T".to_string(),
+                gene.to_string(),
+                -20,
+                100.0,
+                100.0,
+                0,
+            )
+        };
+        let config = Config::default();
+        let best = make("G1", Area::Tss);
+
+        assert_eq!(
+            format_other_genes(std::slice::from_ref(&best), &best, &config),
+            None
+        );
+        assert_eq!(
+            format_other_genes(
+                &[
+                    best.clone(),
+                    make("G3", Area::Intron),
+                    make("G2", Area::GeneBody)
+                ],
+                &best,
+                &config
+            ),
+            Some("G3:INTRON:-20;G2:GENE_BODY:-20".to_string())
+        );
+    }
+
+    #[test]
+    fn test_format_output_line_coords_column() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]);
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            1,
+            Area::Promoter,
+            "T1".to_string(),
+            "G1".to_string(),
+            800,
+            100.0,
+            7.69,
+            -850,
+        );
+
+        let mut config = Config::new();
+        let plain = format_output_line_with_config(&region, &candidate, &config);
+        assert_eq!(plain, format_output_line(&region, &candidate));
+        assert!(!plain.contains("1500"));
+
+        config.extra_columns = vec![ExtraColumn::Coords];
+        let with_coords = format_output_line_with_config(&region, &candidate, &config);
+        // The 1-based feature start is reported 0-based by default
+        assert!(with_coords.ends_with("\t7.69\t999\t1500\tpeak1"));
+        assert!(with_coords.starts_with("chr1_100_200\t150\t"));
+
+        config.output_coordinate_convention = CoordinateConvention::OneBased;
+        let one_based = format_output_line_with_config(&region, &candidate, &config);
+        assert!(one_based.ends_with("\t7.69\t1000\t1500\tpeak1"));
+        assert!(one_based.starts_with("chr1_101_200\t151\t"));
+
+        let mut header = Vec::new();
+        let mut config = Config::new();
+        config.extra_columns = vec![ExtraColumn::Coords];
+        write_output_header(&mut header, 1, &config).unwrap();
+        let header = String::from_utf8(header).unwrap();
+        assert!(header.ends_with("PercArea\tFeatureStart\tFeatureEnd\tname\n"));
+        assert_eq!(header.split('\t').count(), with_coords.split('\t').count());
+    }
+
+    #[test]
+    fn test_format_output_line_relative_strand() {
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            1,
+            Area::FirstExon,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0,
+            20.0,
+            0,
+        );
+        let mut config = Config::new();
+        config.extra_columns = vec![ExtraColumn::RelativeStrand];
+        let region = |strand| {
+            Region::new(
+                "chr1".to_string(),
+                1100,
+                1200,
+                vec!["r1".to_string(), "0".to_string(), "+".to_string()],
+            )
+            .with_strand(strand)
+        };
+
+        let same =
+            format_output_line_with_config(&region(Some(Strand::Positive)), &candidate, &config);
+        assert!(same.ends_with("\tsame\tr1\t0\t+"));
+
+        let opposite =
+            format_output_line_with_config(&region(Some(Strand::Negative)), &candidate, &config);
+        assert!(opposite.ends_with("\topposite\tr1\t0\t+"));
+
+        let unstranded = format_output_line_with_config(&region(None), &candidate, &config);
+        assert!(unstranded.ends_with("\tNA\tr1\t0\t+"));
+
+        // Default output is unaffected by the region strand
+        assert_eq!(
+            format_output_line(&region(Some(Strand::Negative)), &candidate),
+            format_output_line(&region(None), &candidate)
+        );
+    }
+
+    #[test]
+    fn test_format_output_line_tts_distance() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]);
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            1,
+            Area::Upstream,
+            "T1".to_string(),
+            "G1".to_string(),
+            800,
+            100.0,
+            -1.0,
+            -850,
+        )
+        .with_tts_distance(-4850);
+
+        let mut config = Config::new();
+        config.extra_columns = vec![ExtraColumn::TtsDistance];
+        let line = format_output_line_with_config(&region, &candidate, &config);
+        assert!(line.ends_with("\tNA\t-4850\tpeak1"));
+
+        let mut header = Vec::new();
+        write_output_header(&mut header, 1, &config).unwrap();
+        let header = String::from_utf8(header).unwrap();
+        assert!(header.ends_with("PercArea\tTTSDistance\tname\n"));
+    }
+
+    #[test]
+    fn test_format_percentage_precision() {
+        assert_eq!(format_percentage(99.999, 0), "100");
+        assert_eq!(format_percentage(99.999, 2), "100.00");
+        assert_eq!(format_percentage(99.999, 5), "99.99900");
+        assert_eq!(format_percentage(0.009, 0), "0");
+        assert_eq!(format_percentage(0.009, 2), "0.01");
+        assert_eq!(format_percentage(0.009, 5), "0.00900");
+        assert_eq!(format_percentage(100.0, 5), "100.00000");
+        assert_eq!(format_percentage(1e-7, 5), "0.00000");
+    }
+
+    #[test]
+    fn test_format_output_line_precision() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            1,
+            Area::FirstExon,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            99.999,
+            0.009,
+            0,
+        );
+
+        let mut config = Config::new();
+        config.output_precision = 0;
+        let line = format_output_line_with_config(&region, &candidate, &config);
+        assert!(line.ends_with("\t100\t0"));
+
+        config.output_precision = 5;
+        let line = format_output_line_with_config(&region, &candidate, &config);
+        assert!(line.ends_with("\t99.99900\t0.00900"));
+    }
+
+    #[test]
+    fn test_write_header() {
+        let mut output = Vec::new();
+
+        write_header(&mut output, 0).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.starts_with("Region\tMidpoint\tGene"));
+        assert!(!header.contains("name"));
+
+        let mut output = Vec::new();
+        write_header(&mut output, 3).unwrap();
+        let header = String::from_utf8(output).unwrap();
+        assert!(header.contains("name\tscore\tstrand"));
+    }
+}
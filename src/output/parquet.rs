@@ -0,0 +1,412 @@
+//! Parquet output (`--output-format parquet`, behind the `parquet` feature).
+//!
+//! Associations are collected into typed Arrow arrays and written as one
+//! Parquet row group every [`ROWS_PER_BATCH`] rows, so memory stays bounded
+//! while results are streamed.
+//!
+//! # Schema
+//!
+//! Columns follow the default TSV layout, with the same names:
+//!
+//! | Column | Type | Nullable |
+//! |--------|------|----------|
+//! | `Region` | Utf8 | no |
+//! | `Midpoint` | Int64 | no |
+//! | `Gene`, `Transcript`, `Exon/Intron`, `Area` | Utf8 | yes |
+//! | `Distance`, `TSSDistance` | Int64 | yes |
+//! | `PercRegion`, `PercArea` | Float64 | yes |
+//! | `FeatureStart`, `FeatureEnd` (with `coords`) | Int64 | yes |
+//! | `RelativeStrand` (with `relative-strand`) | Utf8 | yes |
+//! | `TTSDistance` (with `tts-distance`) | Int64 | yes |
+//! | `GeneStart`, `GeneEnd`, `GeneLength` (with `gene-span`) | Int64 | yes |
+//! | `PercGene` (with `perc-gene`) | Float64 | yes |
+//! | `NumCandidateGenes` (with `num-candidate-genes`) | Int64 | yes |
+//! | `Sense` (with `sense`) | Utf8 | yes |
+//! | `Rank` (with `--n-closest`) | Int64 | yes |
+//! | `Selected` (with `--no-rules`) | Int64 | yes |
+//! | `Bidirectional` (with `bidirectional`) | Utf8 | yes |
+//! | `JunctionDistance` (with `junction-distance`) | Int64 | yes |
+//! | `BedLine` (with `bed-line`) | Int64 | yes |
+//! | `OtherGenes` (with `--wide`) | Utf8 | yes |
+//! | BED metadata (`name`, `score`, ...) | Utf8 | yes |
+//!
+//! Association columns are null for unassigned regions, and all but `Area`,
+//! `Distance` and the percentages for intergenic ones. Percentages are
+//! stored unrounded; the `-1` sentinel of UPSTREAM and DOWNSTREAM hits is
+//! null unless `legacy_sentinel` is set. The provenance text, if configured, is stored in the
+//! file key-value metadata under `rgmatch.provenance`.
+
+use arrow::array::{ArrayRef, Float64Builder, Int64Builder, StringBuilder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use std::io::Write;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::Result;
+use crate::output::columns::{OutputColumn, OutputRecord};
+use crate::output::output_layout;
+use crate::types::{Candidate, CoordinateConvention, Region};
+
+/// Number of rows buffered before a row group is written.
+pub const ROWS_PER_BATCH: usize = 65_536;
+
+/// Arrow type of an output column.
+fn column_type(column: OutputColumn) -> DataType {
+    match column {
+        OutputColumn::Midpoint
+        | OutputColumn::Distance
+        | OutputColumn::TssDistance
+        | OutputColumn::FeatureStart
+        | OutputColumn::FeatureEnd
+        | OutputColumn::TtsDistance
+        | OutputColumn::GeneStart
+        | OutputColumn::GeneEnd
+        | OutputColumn::GeneLength
+        | OutputColumn::NumCandidateGenes
+        | OutputColumn::Rank
+        | OutputColumn::Selected
+        | OutputColumn::JunctionDistance
+        | OutputColumn::BedLine => DataType::Int64,
+        OutputColumn::PercRegion | OutputColumn::PercArea | OutputColumn::PercGene => {
+            DataType::Float64
+        }
+        _ => DataType::Utf8,
+    }
+}
+
+/// Build the Arrow schema for a column layout.
+pub fn parquet_schema(columns: &[OutputColumn]) -> Schema {
+    let fields: Vec<Field> = columns
+        .iter()
+        .map(|&column| {
+            let nullable = !matches!(column, OutputColumn::Region | OutputColumn::Midpoint);
+            Field::new(column.name(), column_type(column), nullable)
+        })
+        .collect();
+    Schema::new(fields)
+}
+
+enum ColumnBuilder {
+    Utf8(StringBuilder),
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int64 => ColumnBuilder::Int64(Int64Builder::new()),
+            DataType::Float64 => ColumnBuilder::Float64(Float64Builder::new()),
+            _ => ColumnBuilder::Utf8(StringBuilder::new()),
+        }
+    }
+
+    fn finish(&mut self) -> ArrayRef {
+        match self {
+            ColumnBuilder::Utf8(b) => Arc::new(b.finish()),
+            ColumnBuilder::Int64(b) => Arc::new(b.finish()),
+            ColumnBuilder::Float64(b) => Arc::new(b.finish()),
+        }
+    }
+}
+
+/// Integer value of a column, if it has one for this record.
+fn int_value(
+    column: OutputColumn,
+    region: &Region,
+    candidate: Option<&Candidate>,
+    coordinates: CoordinateConvention,
+) -> Option<i64> {
+    match column {
+        OutputColumn::Midpoint => return Some(region.midpoint_in(coordinates)),
+        OutputColumn::BedLine => return region.line.map(|line| line as i64),
+        _ => {}
+    }
+    let candidate = candidate?;
+    if candidate.is_intergenic() && !column.applies_to_intergenic() {
+        return None;
+    }
+    match column {
+        OutputColumn::Distance => candidate.feature_distance(),
+        OutputColumn::TssDistance => Some(candidate.tss_distance),
+        OutputColumn::FeatureStart => Some(coordinates.from_one_based_start(candidate.start)),
+        OutputColumn::FeatureEnd => Some(candidate.end),
+        OutputColumn::TtsDistance => Some(candidate.tts_distance),
+        OutputColumn::GeneStart => Some(coordinates.from_one_based_start(candidate.gene_start)),
+        OutputColumn::GeneEnd => Some(candidate.gene_end),
+        OutputColumn::GeneLength => Some(candidate.gene_length()),
+        OutputColumn::Rank => candidate.rank.map(|rank| rank as i64),
+        OutputColumn::Selected => candidate.selected.map(i64::from),
+        OutputColumn::JunctionDistance => candidate.junction_distance,
+        _ => None,
+    }
+}
+
+/// Float value of a column, if it has one for this record.
+///
+/// Percentage sentinels are null unless `legacy_sentinel` is set.
+fn float_value(
+    column: OutputColumn,
+    candidate: Option<&Candidate>,
+    legacy_sentinel: bool,
+) -> Option<f64> {
+    let candidate = candidate?;
+    match column {
+        OutputColumn::PercRegion if legacy_sentinel => Some(candidate.pctg_region),
+        OutputColumn::PercRegion => candidate.region_percentage(),
+        OutputColumn::PercArea if legacy_sentinel => Some(candidate.pctg_area),
+        OutputColumn::PercArea => candidate.area_percentage(),
+        _ => None,
+    }
+}
+
+/// Streaming Parquet writer for association records.
+pub struct ParquetSink<W: Write + Send> {
+    writer: ArrowWriter<W>,
+    schema: SchemaRef,
+    columns: Vec<OutputColumn>,
+    builders: Vec<ColumnBuilder>,
+    buffered_rows: usize,
+}
+
+impl<W: Write + Send> ParquetSink<W> {
+    /// Create a sink using the default layout for `num_meta_columns`.
+    pub fn new(writer: W, num_meta_columns: usize, config: &Config) -> Result<Self> {
+        let columns = output_layout(num_meta_columns, config);
+        let schema: SchemaRef = Arc::new(parquet_schema(&columns));
+        let builders = schema
+            .fields()
+            .iter()
+            .map(|f| ColumnBuilder::new(f.data_type()))
+            .collect();
+
+        let metadata = config.provenance.as_ref().map(|provenance| {
+            let mut text = Vec::new();
+            // Writing to a Vec cannot fail
+            let _ = provenance.write(&mut text, config);
+            vec![KeyValue::new(
+                "rgmatch.provenance".to_string(),
+                String::from_utf8_lossy(&text).into_owned(),
+            )]
+        });
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROWS_PER_BATCH)
+            .set_key_value_metadata(metadata)
+            .build();
+
+        let writer = ArrowWriter::try_new(writer, schema.clone(), Some(properties))?;
+
+        Ok(ParquetSink {
+            writer,
+            schema,
+            columns,
+            builders,
+            buffered_rows: 0,
+        })
+    }
+
+    /// Append a record. Regions without a candidate get null association columns.
+    pub fn write_record(&mut self, record: &OutputRecord) -> Result<()> {
+        let region = record.region;
+        let candidate = record.candidate;
+
+        for (&column, builder) in self.columns.iter().zip(&mut self.builders) {
+            match builder {
+                ColumnBuilder::Int64(b) if column == OutputColumn::NumCandidateGenes => {
+                    b.append_option(record.num_candidate_genes.map(|n| n as i64))
+                }
+                ColumnBuilder::Int64(b) => {
+                    b.append_option(int_value(column, region, candidate, record.coordinates))
+                }
+                ColumnBuilder::Float64(b) if column == OutputColumn::PercGene => {
+                    b.append_option(candidate.and_then(|c| c.gene_percentage(region)))
+                }
+                ColumnBuilder::Float64(b) => {
+                    b.append_option(float_value(column, candidate, record.legacy_sentinel))
+                }
+                ColumnBuilder::Utf8(b) => {
+                    let missing = match candidate {
+                        Some(c) => c.is_intergenic() && !column.applies_to_intergenic(),
+                        None => !column.is_region_column(),
+                    };
+                    if column == OutputColumn::OtherGenes {
+                        b.append_option(record.other_genes.as_deref());
+                    } else if missing {
+                        b.append_null();
+                    } else {
+                        b.append_option(record.field(column));
+                    }
+                }
+            }
+        }
+
+        self.buffered_rows += 1;
+        if self.buffered_rows >= ROWS_PER_BATCH {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush_batch(&mut self) -> Result<()> {
+        if self.buffered_rows == 0 {
+            return Ok(());
+        }
+
+        let arrays: Vec<ArrayRef> = self.builders.iter_mut().map(|b| b.finish()).collect();
+        let batch =
+            RecordBatch::try_new(self.schema.clone(), arrays).map_err(ParquetError::from)?;
+        self.writer.write(&batch)?;
+        self.buffered_rows = 0;
+        Ok(())
+    }
+
+    /// Write any buffered rows and the file footer, returning the inner writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.flush_batch()?;
+        Ok(self.writer.into_inner()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::format_output_line_with_config;
+    use crate::types::{Area, Strand};
+    use arrow::array::{Array, Float64Array, Int64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn test_parquet_round_trip_matches_tsv() {
+        let region = Region::new(
+            "chr1".to_string(),
+            100,
+            200,
+            vec!["peak1".to_string(), "7".to_string()],
+        );
+        let unassigned = Region::new("chr2".to_string(), 5, 50, vec!["peak2".to_string()]);
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Negative,
+            2,
+            Area::Intron,
+            "T1".to_string(),
+            "G1".to_string(),
+            800,
+            99.999,
+            3.5,
+            -850,
+        );
+
+        let config = Config::new();
+        let file = tempfile::tempfile().unwrap();
+        let mut sink = ParquetSink::new(file, 2, &config).unwrap();
+        sink.write_record(&OutputRecord::new(&region, Some(&candidate), &config))
+            .unwrap();
+        sink.write_record(&OutputRecord::new(&unassigned, None, &config))
+            .unwrap();
+        let file = sink.finish().unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batches: Vec<RecordBatch> = reader.map(|b| b.unwrap()).collect();
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch.schema().as_ref(),
+            &parquet_schema(&crate::output::default_layout(2, &[]))
+        );
+
+        let strings = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .clone()
+        };
+        let ints = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .clone()
+        };
+        let floats = |name: &str| {
+            batch
+                .column_by_name(name)
+                .unwrap()
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .clone()
+        };
+
+        // First row carries the same values as the TSV line
+        let tsv = format_output_line_with_config(&region, &candidate, &config);
+        let fields: Vec<&str> = tsv.split('\t').collect();
+        assert_eq!(strings("Region").value(0), fields[0]);
+        assert_eq!(ints("Midpoint").value(0).to_string(), fields[1]);
+        assert_eq!(strings("Gene").value(0), fields[2]);
+        assert_eq!(strings("Transcript").value(0), fields[3]);
+        assert_eq!(strings("Exon/Intron").value(0), fields[4]);
+        assert_eq!(strings("Area").value(0), fields[5]);
+        assert_eq!(ints("Distance").value(0).to_string(), fields[6]);
+        assert_eq!(ints("TSSDistance").value(0).to_string(), fields[7]);
+        assert_eq!(format!("{:.2}", floats("PercRegion").value(0)), fields[8]);
+        assert_eq!(floats("PercArea").value(0), 3.5);
+        assert_eq!(strings("name").value(0), fields[10]);
+        assert_eq!(strings("score").value(0), fields[11]);
+
+        // Unassigned row: association columns and missing metadata are null
+        assert_eq!(strings("Region").value(1), "chr2_5_50");
+        assert!(strings("Gene").is_null(1));
+        assert!(ints("Distance").is_null(1));
+        assert!(floats("PercArea").is_null(1));
+        assert_eq!(strings("name").value(1), "peak2");
+        assert!(strings("score").is_null(1));
+    }
+
+    #[test]
+    fn test_percentage_sentinel_is_null() {
+        let candidate = Candidate::new(
+            1000,
+            1500,
+            Strand::Positive,
+            1,
+            Area::Upstream,
+            "T1".to_string(),
+            "G1".to_string(),
+            900,
+            100.0,
+            -1.0,
+            -900,
+        );
+
+        assert_eq!(
+            float_value(OutputColumn::PercRegion, Some(&candidate), false),
+            Some(100.0)
+        );
+        assert_eq!(
+            float_value(OutputColumn::PercArea, Some(&candidate), false),
+            None
+        );
+        assert_eq!(
+            float_value(OutputColumn::PercArea, Some(&candidate), true),
+            Some(-1.0)
+        );
+    }
+}
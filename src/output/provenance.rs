@@ -0,0 +1,160 @@
+//! Run provenance written as `#` comment lines before the output header.
+//!
+//! Records the rgmatch version, the command line, the input files and every
+//! configuration value, so an association table can be traced back to the
+//! exact run that produced it.
+
+use std::io::Write;
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::Path;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::error::Result;
+
+/// An input file recorded in the provenance header.
+#[derive(Debug, Clone)]
+pub struct ProvenanceInput {
+    /// Role of the file (e.g. `gtf`, `bed`).
+    pub label: String,
+    pub path: PathBuf,
+    /// File size in bytes, if it could be read.
+    pub size: Option<u64>,
+}
+
+/// Description of the run that produced an output file.
+#[derive(Debug, Clone)]
+pub struct Provenance {
+    /// Command line that started the run.
+    pub command_line: String,
+    /// Input files, in the order they were added.
+    pub inputs: Vec<ProvenanceInput>,
+}
+
+impl Provenance {
+    /// Create provenance for the given command line.
+    pub fn new(command_line: String) -> Self {
+        Provenance {
+            command_line,
+            inputs: Vec::new(),
+        }
+    }
+
+    /// Record an input file and its current size.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn add_input(&mut self, label: &str, path: &Path) {
+        self.inputs.push(ProvenanceInput {
+            label: label.to_string(),
+            path: path.to_path_buf(),
+            size: std::fs::metadata(path).ok().map(|m| m.len()),
+        });
+    }
+
+    /// Write the provenance as `# key: value` lines.
+    pub fn write<W: Write>(&self, writer: &mut W, config: &Config) -> Result<()> {
+        writeln!(writer, "# rgmatch_version: {}", env!("CARGO_PKG_VERSION"))?;
+        writeln!(writer, "# command: {}", self.command_line)?;
+
+        for input in &self.inputs {
+            let size = input
+                .size
+                .map_or_else(|| "NA".to_string(), |s| s.to_string());
+            writeln!(
+                writer,
+                "# input_{}: {} ({} bytes)",
+                input.label,
+                input.path.display(),
+                size
+            )?;
+        }
+
+        for (key, value) in config.parameters() {
+            writeln!(writer, "# {}: {}", key, value)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Join command-line arguments, quoting those that need it for a shell.
+pub fn format_command_line<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let quoted: Vec<String> = args
+        .into_iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            let plain = !arg.is_empty()
+                && arg
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_./=,:+@%".contains(c));
+            if plain {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            }
+        })
+        .collect();
+    quoted.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Area;
+
+    fn write_to_string(provenance: &Provenance, config: &Config) -> String {
+        let mut buffer = Vec::new();
+        provenance.write(&mut buffer, config).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_provenance_lines() {
+        let mut provenance = Provenance::new("rgmatch -g a.gtf -b b.bed".to_string());
+        provenance.add_input("bed", Path::new("/nonexistent/b.bed"));
+
+        let text = write_to_string(&provenance, &Config::new());
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert!(lines.iter().all(|l| l.starts_with("# ")));
+        assert_eq!(
+            lines[0],
+            format!("# rgmatch_version: {}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(lines[1], "# command: rgmatch -g a.gtf -b b.bed");
+        assert_eq!(lines[2], "# input_bed: /nonexistent/b.bed (NA bytes)");
+        assert!(lines.contains(&"# distance: 10000"));
+        assert!(lines.contains(&"# report_level: exon"));
+    }
+
+    #[test]
+    fn test_rules_round_trip() {
+        let mut config = Config::new();
+        let rules = "UPSTREAM,DOWNSTREAM,TTS,TSS,PROMOTER,INTRON,GENE_BODY,1st_EXON";
+        assert!(config.parse_rules(rules));
+
+        let text = write_to_string(&Provenance::new(String::new()), &config);
+        let recorded = text
+            .lines()
+            .find_map(|l| l.strip_prefix("# rules: "))
+            .unwrap();
+        assert_eq!(recorded, rules);
+
+        let mut reparsed = Config::new();
+        assert!(reparsed.parse_rules(recorded));
+        assert_eq!(reparsed.rules, config.rules);
+        assert_eq!(reparsed.rules[0], Area::Upstream);
+    }
+
+    #[test]
+    fn test_format_command_line() {
+        assert_eq!(
+            format_command_line(["rgmatch", "-g", "my genes.gtf", "-s", "it's"]),
+            r"rgmatch -g 'my genes.gtf' -s 'it'\''s'"
+        );
+        assert_eq!(format_command_line(["-o", ""]), "-o ''");
+    }
+}
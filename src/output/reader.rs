@@ -0,0 +1,397 @@
+//! Reader of rgmatch association tables.
+//!
+//! Reads back the TSV files written by [`OutputSink`](super::OutputSink): the
+//! optional `#` provenance lines, the header, and the association rows grouped
+//! by region, so tables of earlier runs can be summarized without matching
+//! again.
+
+use std::io::BufRead;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::types::{Area, Candidate, ExonNumbers, SenseClass, Strand};
+
+/// Columns a table needs to be read back.
+const REQUIRED_COLUMNS: [&str; 6] = [
+    "Region",
+    "Gene",
+    "Area",
+    "TSSDistance",
+    "PercRegion",
+    "PercArea",
+];
+
+/// Positions of the columns read from each row.
+struct ColumnIndexes {
+    region: usize,
+    midpoint: Option<usize>,
+    gene: usize,
+    transcript: Option<usize>,
+    area: usize,
+    distance: Option<usize>,
+    tss_distance: usize,
+    perc_region: usize,
+    perc_area: usize,
+    sense: Option<usize>,
+}
+
+/// The associations of one region of a table.
+#[derive(Debug, Clone)]
+pub struct ResultRegion {
+    /// Region ID, as in the `Region` column.
+    pub region: String,
+    /// One candidate per row, with the values of the table's columns; the
+    /// rest keep their defaults. Empty for a region kept without
+    /// associations (`--keep-unassigned`).
+    pub candidates: Vec<Candidate>,
+}
+
+/// Reads an association table region by region.
+///
+/// Rows of a region are consecutive in the files rgmatch writes; two regions
+/// with the same ID and midpoint next to each other are read as one. The
+/// report level, rules, thresholds, TSS histogram bins and missing-value
+/// text are taken from the provenance lines, when the table has them.
+pub struct ResultsReader<R> {
+    reader: R,
+    line: String,
+    line_number: usize,
+    columns: ColumnIndexes,
+    config: Config,
+    /// First row of the next region, already split.
+    pending: Option<(usize, Vec<String>)>,
+}
+
+impl<R: BufRead> ResultsReader<R> {
+    /// Read the provenance lines and the header of a table.
+    pub fn new(reader: R) -> Result<Self> {
+        let mut results = ResultsReader {
+            reader,
+            line: String::new(),
+            line_number: 0,
+            columns: ColumnIndexes {
+                region: 0,
+                midpoint: None,
+                gene: 0,
+                transcript: None,
+                area: 0,
+                distance: None,
+                tss_distance: 0,
+                perc_region: 0,
+                perc_area: 0,
+                sense: None,
+            },
+            config: Config::default(),
+            pending: None,
+        };
+
+        loop {
+            if !results.read_line()? {
+                return Err(results.error("no header line"));
+            }
+            match results.line.strip_prefix('#') {
+                Some(comment) => {
+                    if let Some((key, value)) = comment.trim_start().split_once(": ") {
+                        let (key, value) = (key.to_string(), value.to_string());
+                        results.apply_parameter(&key, &value)?;
+                    }
+                }
+                None => break,
+            }
+        }
+
+        let header: Vec<&str> = results.line.split('\t').collect();
+        let find = |name: &str| header.iter().position(|column| *column == name);
+        let missing: Vec<&str> = REQUIRED_COLUMNS
+            .iter()
+            .copied()
+            .filter(|name| find(name).is_none())
+            .collect();
+        if !missing.is_empty() {
+            return Err(results.error(&format!(
+                "not an rgmatch association table, missing column(s) {}",
+                missing.join(", ")
+            )));
+        }
+        results.columns = ColumnIndexes {
+            region: find("Region").unwrap_or_default(),
+            midpoint: find("Midpoint"),
+            gene: find("Gene").unwrap_or_default(),
+            transcript: find("Transcript"),
+            area: find("Area").unwrap_or_default(),
+            distance: find("Distance"),
+            tss_distance: find("TSSDistance").unwrap_or_default(),
+            perc_region: find("PercRegion").unwrap_or_default(),
+            perc_area: find("PercArea").unwrap_or_default(),
+            sense: find("Sense"),
+        };
+        Ok(results)
+    }
+
+    /// Configuration of the run that wrote the table: the defaults, with the
+    /// values of its provenance lines.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Read the rows of the next region; `None` at the end of the table.
+    pub fn next_region(&mut self) -> Result<Option<ResultRegion>> {
+        let (line_number, first) = match self.pending.take() {
+            Some(row) => row,
+            None => match self.next_row()? {
+                Some(row) => row,
+                None => return Ok(None),
+            },
+        };
+        let key = self.region_key(&first);
+        let mut region = ResultRegion {
+            region: first[self.columns.region].clone(),
+            candidates: Vec::new(),
+        };
+        self.add_row(&mut region, line_number, &first)?;
+
+        while let Some((line_number, row)) = self.next_row()? {
+            if self.region_key(&row) != key {
+                self.pending = Some((line_number, row));
+                break;
+            }
+            self.add_row(&mut region, line_number, &row)?;
+        }
+        Ok(Some(region))
+    }
+
+    /// Read the next line into `self.line`, without its line ending.
+    /// Returns false at the end of the input.
+    fn read_line(&mut self) -> Result<bool> {
+        self.line.clear();
+        if self.reader.read_line(&mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        let trimmed = self.line.trim_end_matches(['\n', '\r']).len();
+        self.line.truncate(trimmed);
+        Ok(true)
+    }
+
+    /// The fields of the next non-empty row, and its line number.
+    fn next_row(&mut self) -> Result<Option<(usize, Vec<String>)>> {
+        while self.read_line()? {
+            if self.line.is_empty() {
+                continue;
+            }
+            let row: Vec<String> = self.line.split('\t').map(String::from).collect();
+            if row.len() <= self.columns.max_index() {
+                return Err(self.error(&format!("{} columns, fewer than the header", row.len())));
+            }
+            return Ok(Some((self.line_number, row)));
+        }
+        Ok(None)
+    }
+
+    /// Region ID and midpoint, which tell the rows of a region apart.
+    fn region_key<'a>(&self, row: &'a [String]) -> (&'a str, Option<&'a str>) {
+        (
+            &row[self.columns.region],
+            self.columns.midpoint.map(|i| row[i].as_str()),
+        )
+    }
+
+    /// Add the candidate of a row to `region`, unless the row is an
+    /// unassigned region.
+    fn add_row(&self, region: &mut ResultRegion, line_number: usize, row: &[String]) -> Result<()> {
+        let columns = &self.columns;
+        let na = self.config.na_string.as_str();
+        let invalid = |column: &str, value: &str| Error::ResultsParse {
+            line: line_number,
+            reason: format!("invalid {} '{}'", column, value),
+        };
+
+        let area = &row[columns.area];
+        if area == na {
+            return Ok(());
+        }
+        let area: Area = area.parse().map_err(|_| invalid("Area", area))?;
+        let integer = |column: &str, index: usize| -> Result<i64> {
+            match row[index].as_str() {
+                value if value == na => Ok(0),
+                value => value.parse().map_err(|_| invalid(column, value)),
+            }
+        };
+        // Missing percentages stand for the -1 sentinel of unbounded areas
+        let percentage = |column: &str, index: usize| -> Result<f64> {
+            match row[index].as_str() {
+                value if value == na => Ok(-1.0),
+                value => value.parse().map_err(|_| invalid(column, value)),
+            }
+        };
+        let text = |index: Option<usize>| -> &str {
+            match index.map(|i| row[i].as_str()) {
+                Some(value) if value != na => value,
+                _ => "",
+            }
+        };
+
+        let mut candidate = Candidate::new(
+            0,
+            0,
+            Strand::Positive,
+            ExonNumbers::default(),
+            area,
+            text(columns.transcript),
+            text(Some(columns.gene)),
+            match columns.distance {
+                Some(index) => integer("Distance", index)?,
+                None => 0,
+            },
+            percentage("PercRegion", columns.perc_region)?,
+            percentage("PercArea", columns.perc_area)?,
+            integer("TSSDistance", columns.tss_distance)?,
+        );
+        if let Some(sense) = columns.sense.map(|i| row[i].as_str()) {
+            if sense != na {
+                candidate.sense = sense
+                    .parse::<SenseClass>()
+                    .map_err(|_| invalid("Sense", sense))?;
+            }
+        }
+        region.candidates.push(candidate);
+        Ok(())
+    }
+
+    /// Take a provenance value the summary depends on.
+    fn apply_parameter(&mut self, key: &str, value: &str) -> Result<()> {
+        let config = &mut self.config;
+        let valid = match key {
+            "report_level" => value.parse().map(|level| config.level = level).is_ok(),
+            "rules" => config.parse_rules(value),
+            "perc_area" => value.parse().map(|perc| config.perc_area = perc).is_ok(),
+            "perc_region" => value.parse().map(|perc| config.perc_region = perc).is_ok(),
+            "tss_bins" => {
+                let edges: std::result::Result<Vec<i64>, _> =
+                    value.split(',').map(str::parse).collect();
+                match edges {
+                    Ok(edges) if Config::valid_tss_bins(&edges) => {
+                        config.tss_bins = edges;
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            "na_string" => {
+                config.na_string = value.to_string();
+                true
+            }
+            _ => true,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(self.error(&format!("invalid {} '{}'", key, value)))
+        }
+    }
+
+    fn error(&self, reason: &str) -> Error {
+        Error::ResultsParse {
+            line: self.line_number,
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ResultsReader<R> {
+    type Item = Result<ResultRegion>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_region().transpose()
+    }
+}
+
+impl ColumnIndexes {
+    /// Position of the last column read.
+    fn max_index(&self) -> usize {
+        [
+            Some(self.region),
+            self.midpoint,
+            Some(self.gene),
+            self.transcript,
+            Some(self.area),
+            self.distance,
+            Some(self.tss_distance),
+            Some(self.perc_region),
+            Some(self.perc_area),
+            self.sense,
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ReportLevel;
+
+    const TABLE: &str = "# rgmatch_version: 0.1.0\n\
+        # perc_region: 40\n\
+        # rules: TSS,PROMOTER,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM\n\
+        # report_level: gene\n\
+        Region\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea\tname\n\
+        chr1_100_200\t150\tG1\tT1,T2\t1,2\tTSS\t0\t-50\t100.00\t25.00\tpeak1\n\
+        chr1_100_200\t150\tG2\tT3\t1\tDOWNSTREAM\t120\t900\t80.00\tNA\tpeak1\n\
+        chr1_500_600\t550\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tpeak2\n\
+        \n\
+        chr2_10_20\t15\tG3\tT4\t2\tINTRON\t0\t3000\t100.00\t1.50\tpeak3\n";
+
+    #[test]
+    fn test_read_regions() {
+        let mut reader = ResultsReader::new(TABLE.as_bytes()).unwrap();
+        assert_eq!(reader.config().level, ReportLevel::Gene);
+        assert_eq!(reader.config().perc_region, 40.0);
+        assert_eq!(reader.config().rules[1], Area::Promoter);
+
+        let first = reader.next_region().unwrap().unwrap();
+        assert_eq!(first.region, "chr1_100_200");
+        assert_eq!(first.candidates.len(), 2);
+        assert_eq!(first.candidates[0].area, Area::Tss);
+        assert_eq!(first.candidates[0].transcript, "T1,T2");
+        assert_eq!(first.candidates[0].tss_distance, -50);
+        assert_eq!(first.candidates[1].gene, "G2");
+        assert_eq!(first.candidates[1].distance, 120);
+        assert_eq!(first.candidates[1].pctg_area, -1.0);
+
+        // A region kept without associations
+        let second = reader.next_region().unwrap().unwrap();
+        assert_eq!(second.region, "chr1_500_600");
+        assert!(second.candidates.is_empty());
+
+        let third = reader.next_region().unwrap().unwrap();
+        assert_eq!(third.candidates[0].area, Area::Intron);
+        assert!(reader.next_region().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_errors() {
+        let err = ResultsReader::new("Region\tGene\tArea\n".as_bytes())
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Invalid results line 1: not an rgmatch association table, missing column(s) TSSDistance, PercRegion, PercArea"
+        );
+
+        let table = "Region\tGene\tArea\tTSSDistance\tPercRegion\tPercArea\n\
+                     r1\tG1\tNEARBY\t0\t100\t100\n";
+        let mut reader = ResultsReader::new(table.as_bytes()).unwrap();
+        let err = reader.next_region().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid results line 2: invalid Area 'NEARBY'"
+        );
+
+        let table = "Region\tGene\tArea\tTSSDistance\tPercRegion\tPercArea\nr1\tG1\n";
+        let mut reader = ResultsReader::new(table.as_bytes()).unwrap();
+        assert!(reader.next().unwrap().is_err());
+    }
+}
@@ -0,0 +1,1298 @@
+//! Per-run summary statistics.
+//!
+//! A `Summary` is accumulated region by region while results are written,
+//! and can be saved as TSV or JSON alongside the association table.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+use log::warn;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::matcher::select_best_candidate;
+use crate::output::reader::ResultsReader;
+use crate::parser::TranscriptFilterCounts;
+use crate::source::AnnotationSource;
+use crate::types::{Area, Association, Candidate, Region, ReportLevel, SenseClass};
+
+/// Number of missing chromosomes named in the warning and the summary file.
+const MISSING_CHROMOSOMES_SHOWN: usize = 5;
+
+/// Number of slowest chromosomes named in the timings.
+const SLOWEST_CHROMOSOMES_SHOWN: usize = 5;
+
+/// Counts and TSS-distance statistics for a single area.
+#[derive(Debug, Clone, Default)]
+pub struct AreaStats {
+    /// Number of reported associations with this area.
+    pub count: u64,
+    /// Number of those associations per sense class.
+    pub by_sense: BTreeMap<SenseClass, u64>,
+    /// Histogram of |TSS distance| values, used for exact medians.
+    abs_tss_distances: BTreeMap<u64, u64>,
+}
+
+impl AreaStats {
+    /// Median |TSS distance| over the associations of this area.
+    pub fn median_abs_tss_distance(&self) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // Positions (0-based) of the two middle values; equal for odd counts
+        let lower = (self.count - 1) / 2;
+        let upper = self.count / 2;
+
+        let mut seen = 0u64;
+        let mut lower_value = None;
+        for (&value, &n) in &self.abs_tss_distances {
+            seen += n;
+            if lower_value.is_none() && seen > lower {
+                lower_value = Some(value);
+            }
+            if seen > upper {
+                let lower_value = lower_value.unwrap_or(value);
+                return Some((lower_value + value) as f64 / 2.0);
+            }
+        }
+
+        None
+    }
+}
+
+/// Histogram of the signed TSS distance of each region's best association.
+///
+/// Bins are half-open ranges of |TSS distance|: `[0, edges[0])`,
+/// `[edges[0], edges[1])`, ..., and `[last edge, ∞)`. Negative distances
+/// (upstream of the TSS) and the rest (downstream, including 0) are counted
+/// separately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TssHistogram {
+    /// Increasing upper bin edges in bp.
+    pub edges: Vec<i64>,
+    /// Counts of upstream distances, one per bin.
+    pub upstream: Vec<u64>,
+    /// Counts of downstream distances, one per bin.
+    pub downstream: Vec<u64>,
+}
+
+impl TssHistogram {
+    /// Create an empty histogram with the given bin edges.
+    pub fn new(edges: Vec<i64>) -> Self {
+        let bins = edges.len() + 1;
+        TssHistogram {
+            edges,
+            upstream: vec![0; bins],
+            downstream: vec![0; bins],
+        }
+    }
+
+    /// Count one TSS distance.
+    pub fn add(&mut self, tss_distance: i64) {
+        let abs = tss_distance.unsigned_abs();
+        let bin = self.edges.partition_point(|&edge| edge as u64 <= abs);
+        if tss_distance < 0 {
+            self.upstream[bin] += 1;
+        } else {
+            self.downstream[bin] += 1;
+        }
+    }
+
+    /// Add the counts of another histogram with the same edges.
+    pub fn merge(&mut self, other: &TssHistogram) {
+        assert_eq!(self.edges, other.edges, "histogram bin edges differ");
+        for (count, n) in self.upstream.iter_mut().zip(&other.upstream) {
+            *count += n;
+        }
+        for (count, n) in self.downstream.iter_mut().zip(&other.downstream) {
+            *count += n;
+        }
+    }
+
+    /// Total number of counted distances.
+    pub fn total(&self) -> u64 {
+        self.upstream.iter().chain(&self.downstream).sum()
+    }
+
+    /// Label of a bin as `low-high` (bp), or `low+` for the last one.
+    pub fn bin_label(&self, bin: usize) -> String {
+        let low = if bin == 0 { 0 } else { self.edges[bin - 1] };
+        match self.edges.get(bin) {
+            Some(high) => format!("{}-{}", low, high),
+            None => format!("{}+", low),
+        }
+    }
+}
+
+/// Counts the regions on chromosomes without genes, so that they are
+/// reported once at the end of the run instead of once per chromosome.
+#[derive(Debug, Clone, Default)]
+pub struct MissingChromosomes {
+    counts: BTreeMap<String, u64>,
+}
+
+impl MissingChromosomes {
+    /// Create an empty counter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count `region` if `source` has no genes on its chromosome.
+    pub fn add(&mut self, region: &Region, source: &dyn AnnotationSource) {
+        if !source.genes(&region.chrom).is_empty() {
+            return;
+        }
+        match self.counts.get_mut(&region.chrom) {
+            Some(count) => *count += 1,
+            None => {
+                self.counts.insert(region.chrom.clone(), 1);
+            }
+        }
+    }
+
+    /// Add the counts of another counter.
+    pub fn merge(&mut self, other: MissingChromosomes) {
+        for (chrom, count) in other.counts {
+            *self.counts.entry(chrom).or_default() += count;
+        }
+    }
+
+    /// Number of regions counted on `chrom`.
+    pub fn count(&self, chrom: &str) -> u64 {
+        self.counts.get(chrom).copied().unwrap_or(0)
+    }
+
+    /// Whether no region was counted.
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Number of chromosomes without genes.
+    pub fn num_chromosomes(&self) -> usize {
+        self.counts.len()
+    }
+
+    /// Number of regions on chromosomes without genes.
+    pub fn num_regions(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// The `n` chromosomes with the most regions and their region counts,
+    /// by decreasing count then name.
+    pub fn top(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut top: Vec<(&str, u64)> = self
+            .counts
+            .iter()
+            .map(|(chrom, &count)| (chrom.as_str(), count))
+            .collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(n);
+        top
+    }
+
+    /// Describe the missing chromosomes in one sentence, naming the ones
+    /// with the most regions, or `None` if there are none.
+    pub fn report(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+        let top = self.top(MISSING_CHROMOSOMES_SHOWN);
+        let names: Vec<String> = top
+            .iter()
+            .map(|(chrom, count)| format!("{} ({})", chrom, count))
+            .collect();
+        let more = match self.num_chromosomes() - top.len() {
+            0 => String::new(),
+            n => format!(" and {} more", n),
+        };
+        Some(format!(
+            "{} chromosome(s) not found in the annotation, with {} region(s) without genes: {}{}",
+            self.num_chromosomes(),
+            self.num_regions(),
+            names.join(", "),
+            more
+        ))
+    }
+
+    /// Log [`MissingChromosomes::report`] as a single warning.
+    pub fn warn(&self) {
+        if let Some(report) = self.report() {
+            warn!("{}", report);
+        }
+    }
+
+    /// Fail with [`Error::NoAnnotatedRegions`] if all of the `total_regions`
+    /// regions are on chromosomes without genes, which means the BED and
+    /// GTF name their chromosomes differently.
+    pub fn check(&self, total_regions: u64) -> Result<()> {
+        if total_regions > 0 && self.num_regions() == total_regions {
+            let examples: Vec<&str> = self
+                .top(MISSING_CHROMOSOMES_SHOWN)
+                .into_iter()
+                .map(|(chrom, _)| chrom)
+                .collect();
+            return Err(Error::NoAnnotatedRegions {
+                regions: total_regions,
+                examples: examples.join(", "),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Time spent in each step of a run, and its throughput.
+///
+/// Steps are timed separately, so they do not add up to `total_secs`. With
+/// several threads, matching adds up the time of every worker and can exceed
+/// the run time.
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    /// Seconds spent parsing the GTF annotation.
+    pub gtf_parse_secs: f64,
+    /// Seconds spent reading the BED regions.
+    pub bed_parse_secs: f64,
+    /// Seconds spent writing the associations; zero if nothing was written.
+    pub write_secs: f64,
+    /// Seconds of the whole run.
+    pub total_secs: f64,
+    /// Number of regions matched.
+    pub regions: u64,
+    /// Most candidates found for a single region.
+    pub peak_candidates: usize,
+    /// Matching seconds per chromosome.
+    chromosome_secs: BTreeMap<String, f64>,
+}
+
+impl Timings {
+    /// Create timings with every step at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a matched region and its number of candidates.
+    pub fn add_region(&mut self, num_candidates: usize) {
+        self.regions += 1;
+        self.peak_candidates = self.peak_candidates.max(num_candidates);
+    }
+
+    /// Add `secs` of matching on `chrom`.
+    pub fn add_matching(&mut self, chrom: &str, secs: f64) {
+        match self.chromosome_secs.get_mut(chrom) {
+            Some(total) => *total += secs,
+            None => {
+                self.chromosome_secs.insert(chrom.to_string(), secs);
+            }
+        }
+    }
+
+    /// Seconds spent matching, over all chromosomes.
+    pub fn matching_secs(&self) -> f64 {
+        self.chromosome_secs.values().sum()
+    }
+
+    /// Seconds spent matching the regions of `chrom`.
+    pub fn chromosome_secs(&self, chrom: &str) -> f64 {
+        self.chromosome_secs.get(chrom).copied().unwrap_or(0.0)
+    }
+
+    /// The `n` chromosomes that took longest to match and their seconds,
+    /// slowest first.
+    pub fn slowest_chromosomes(&self, n: usize) -> Vec<(&str, f64)> {
+        let mut slowest: Vec<(&str, f64)> = self
+            .chromosome_secs
+            .iter()
+            .map(|(chrom, &secs)| (chrom.as_str(), secs))
+            .collect();
+        slowest.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        slowest.truncate(n);
+        slowest
+    }
+
+    /// Regions matched per second of the whole run, or zero if the run took
+    /// no measurable time.
+    pub fn regions_per_sec(&self) -> f64 {
+        if self.total_secs > 0.0 {
+            self.regions as f64 / self.total_secs
+        } else {
+            0.0
+        }
+    }
+
+    /// Add the times of another run, keeping the larger peak.
+    pub fn merge(&mut self, other: Timings) {
+        self.gtf_parse_secs += other.gtf_parse_secs;
+        self.bed_parse_secs += other.bed_parse_secs;
+        self.write_secs += other.write_secs;
+        self.total_secs += other.total_secs;
+        self.regions += other.regions;
+        self.peak_candidates = self.peak_candidates.max(other.peak_candidates);
+        for (chrom, secs) in other.chromosome_secs {
+            self.add_matching(&chrom, secs);
+        }
+    }
+
+    fn slowest_json(&self) -> String {
+        let slowest: Vec<String> = self
+            .slowest_chromosomes(SLOWEST_CHROMOSOMES_SHOWN)
+            .iter()
+            .map(|(chrom, secs)| format!("{{\"chrom\": {:?}, \"secs\": {:.3}}}", chrom, secs))
+            .collect();
+        slowest.join(", ")
+    }
+}
+
+/// One line per step, as logged at the end of a run.
+impl fmt::Display for Timings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "GTF parsing      {:>9.3}s", self.gtf_parse_secs)?;
+        writeln!(f, "BED parsing      {:>9.3}s", self.bed_parse_secs)?;
+        writeln!(f, "Matching         {:>9.3}s", self.matching_secs())?;
+        writeln!(f, "Writing          {:>9.3}s", self.write_secs)?;
+        writeln!(
+            f,
+            "Total            {:>9.3}s ({:.0} regions/s)",
+            self.total_secs,
+            self.regions_per_sec()
+        )?;
+        write!(f, "Peak candidates  {:>9}", self.peak_candidates)?;
+        let slowest = self.slowest_chromosomes(SLOWEST_CHROMOSOMES_SHOWN);
+        if !slowest.is_empty() {
+            let names: Vec<String> = slowest
+                .iter()
+                .map(|(chrom, secs)| format!("{} {:.3}s", chrom, secs))
+                .collect();
+            write!(f, "\nSlowest chromosomes: {}", names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Summary statistics of one rgmatch run.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    /// Report level the association counts refer to.
+    pub level: ReportLevel,
+    /// Number of input regions.
+    pub total_regions: u64,
+    /// Regions with at least one reported association.
+    pub associated_regions: u64,
+    /// Regions with no gene within range (or on a chromosome without genes),
+    /// including those reported as intergenic.
+    pub unassigned_regions: u64,
+    /// Associated regions whose associations come from the closest-gene
+    /// fallback.
+    pub closest_fallback_regions: u64,
+    /// Candidates dropped by the per-area distance limits
+    /// (`Config::area_max_distance`).
+    pub distance_filtered_candidates: u64,
+    /// Candidates with a percentage outside [0, 100], clamped into it.
+    pub clamped_candidates: u64,
+    /// Per-area statistics, ordered by area.
+    pub areas: BTreeMap<Area, AreaStats>,
+    /// TSS distances of the best association of each associated region.
+    pub tss_histogram: TssHistogram,
+    /// Transcripts and genes removed from the annotation by
+    /// `Config::transcript_filters`, when set.
+    pub transcript_filtering: Option<TranscriptFilterCounts>,
+    /// Regions on chromosomes without genes, per chromosome.
+    pub missing_chromosomes: MissingChromosomes,
+    /// Number of associations of each gene, for summaries of association
+    /// tables ([`Summary::from_results`]).
+    pub gene_associations: Option<BTreeMap<String, u64>>,
+    /// Time spent in each step of the run, when it was timed.
+    pub timings: Option<Timings>,
+    /// Whether regions without associations are known, which they are not
+    /// in an association table written without `--keep-unassigned`.
+    unassigned_known: bool,
+    /// Thresholds and rules used to pick each region's best association.
+    perc_region: f64,
+    perc_area: f64,
+    rules: Vec<Area>,
+    /// Whether the closest-gene fallback was enabled, to report its count.
+    always_closest: bool,
+    /// Whether per-area distance limits were set, to report their count.
+    area_distance_limits: bool,
+}
+
+impl Summary {
+    /// Create an empty summary for the given report level, with the default
+    /// histogram bins and best-association rules.
+    pub fn new(level: ReportLevel) -> Self {
+        Summary::from_config(&Config {
+            level,
+            ..Config::default()
+        })
+    }
+
+    /// Create an empty summary using the report level, TSS histogram bins,
+    /// thresholds and rules of `config`.
+    pub fn from_config(config: &Config) -> Self {
+        Summary {
+            level: config.level,
+            total_regions: 0,
+            associated_regions: 0,
+            unassigned_regions: 0,
+            closest_fallback_regions: 0,
+            distance_filtered_candidates: 0,
+            clamped_candidates: 0,
+            areas: BTreeMap::new(),
+            tss_histogram: TssHistogram::new(config.tss_bins.clone()),
+            transcript_filtering: None,
+            missing_chromosomes: MissingChromosomes::new(),
+            gene_associations: None,
+            timings: None,
+            unassigned_known: true,
+            perc_region: config.perc_region,
+            perc_area: config.perc_area,
+            rules: config.rules.clone(),
+            always_closest: config.always_closest,
+            area_distance_limits: !config.area_max_distance.is_empty(),
+        }
+    }
+
+    /// Summarize an association table written by an earlier run, with the
+    /// level, rules, thresholds and TSS bins of its provenance lines.
+    ///
+    /// Regions not in the table are unknown, so the unassigned count is left
+    /// out of the written summary. Associations are also counted per gene.
+    /// The best association of a region is picked from the table's columns
+    /// only, so ties the rules break on dropped columns (e.g. `Transcript`
+    /// at exon level) may pick a different TSS distance than the run did.
+    pub fn from_results<R: BufRead>(mut results: ResultsReader<R>) -> Result<Self> {
+        let mut summary = Summary::from_config(results.config());
+        summary.unassigned_known = false;
+        let mut genes: BTreeMap<String, u64> = BTreeMap::new();
+        while let Some(region) = results.next_region()? {
+            for candidate in &region.candidates {
+                if !candidate.is_intergenic() {
+                    *genes.entry(candidate.gene.to_string()).or_default() += 1;
+                }
+            }
+            summary.add_region(&region.candidates);
+        }
+        summary.gene_associations = Some(genes);
+        Ok(summary)
+    }
+
+    /// Record one region and the associations reported for it.
+    ///
+    /// An intergenic pseudo-candidate counts the region as unassigned and
+    /// adds to the `INTERGENIC` area, without TSS distance.
+    pub fn add_region(&mut self, candidates: &[Candidate]) {
+        self.total_regions += 1;
+        if candidates.iter().all(Candidate::is_intergenic) {
+            self.unassigned_regions += 1;
+            if !candidates.is_empty() {
+                self.areas.entry(Area::Intergenic).or_default().count += 1;
+            }
+            return;
+        }
+        self.associated_regions += 1;
+        if candidates.iter().any(|c| c.closest_fallback) {
+            self.closest_fallback_regions += 1;
+        }
+
+        if let Some(best) =
+            select_best_candidate(candidates, self.perc_region, self.perc_area, &self.rules)
+        {
+            self.tss_histogram.add(best.tss_distance);
+        }
+
+        for candidate in candidates {
+            let stats = self.areas.entry(candidate.area).or_default();
+            stats.count += 1;
+            *stats.by_sense.entry(candidate.sense).or_default() += 1;
+            *stats
+                .abs_tss_distances
+                .entry(candidate.tss_distance.unsigned_abs())
+                .or_default() += 1;
+        }
+    }
+
+    /// Record a matched region: the `written` candidates reported for it (see
+    /// [`candidates_to_write`](crate::output::candidates_to_write)), its
+    /// filtered and clamped candidates, and whether `source` has genes on its
+    /// chromosome.
+    ///
+    /// Every run, library or command line, counts its regions with it.
+    pub fn add_association(
+        &mut self,
+        association: &Association,
+        written: &[Candidate],
+        source: &dyn AnnotationSource,
+    ) {
+        self.add_region(written);
+        self.add_distance_filtered(association.num_distance_filtered);
+        self.add_clamped_percentages(association.num_clamped_percentages);
+        self.missing_chromosomes.add(&association.region, source);
+    }
+
+    /// Record candidates of a region dropped by the per-area distance limits.
+    pub fn add_distance_filtered(&mut self, count: usize) {
+        self.distance_filtered_candidates += count as u64;
+    }
+
+    /// Record candidates of a region with a clamped percentage.
+    pub fn add_clamped_percentages(&mut self, count: usize) {
+        self.clamped_candidates += count as u64;
+    }
+
+    /// Log a warning if any candidate had a percentage clamped into
+    /// [0, 100], which points to a bug in the overlap geometry.
+    pub fn warn_clamped_percentages(&self) {
+        if self.clamped_candidates > 0 {
+            warn!(
+                "{} associations had a PercRegion or PercArea outside 0-100, reported as 0 or 100; please report this with the regions and annotation used",
+                self.clamped_candidates
+            );
+        }
+    }
+
+    /// Combine the counts of another summary into this one.
+    pub fn merge(&mut self, other: Summary) {
+        self.total_regions += other.total_regions;
+        self.associated_regions += other.associated_regions;
+        self.unassigned_regions += other.unassigned_regions;
+        self.closest_fallback_regions += other.closest_fallback_regions;
+        self.distance_filtered_candidates += other.distance_filtered_candidates;
+        self.clamped_candidates += other.clamped_candidates;
+        self.tss_histogram.merge(&other.tss_histogram);
+        self.transcript_filtering = self.transcript_filtering.or(other.transcript_filtering);
+        self.missing_chromosomes.merge(other.missing_chromosomes);
+        self.unassigned_known &= other.unassigned_known;
+        if let Some(other_genes) = other.gene_associations {
+            let genes = self.gene_associations.get_or_insert_with(BTreeMap::new);
+            for (gene, n) in other_genes {
+                *genes.entry(gene).or_default() += n;
+            }
+        }
+        match (&mut self.timings, other.timings) {
+            (Some(timings), Some(other_timings)) => timings.merge(other_timings),
+            (timings @ None, other_timings) => *timings = other_timings,
+            (Some(_), None) => {}
+        }
+
+        for (area, other_stats) in other.areas {
+            let stats = self.areas.entry(area).or_default();
+            stats.count += other_stats.count;
+            for (class, n) in other_stats.by_sense {
+                *stats.by_sense.entry(class).or_default() += n;
+            }
+            for (value, n) in other_stats.abs_tss_distances {
+                *stats.abs_tss_distances.entry(value).or_default() += n;
+            }
+        }
+    }
+
+    /// Total number of reported associations across all areas.
+    pub fn total_associations(&self) -> u64 {
+        self.areas.values().map(|s| s.count).sum()
+    }
+
+    /// Whether any association has a stranded region, i.e. a sense class
+    /// other than `Unstranded`.
+    pub fn is_stranded(&self) -> bool {
+        self.areas
+            .values()
+            .flat_map(|s| s.by_sense.keys())
+            .any(|&class| class != SenseClass::Unstranded)
+    }
+
+    /// Percentage of associations with the given area.
+    pub fn area_percentage(&self, area: Area) -> f64 {
+        let count = self.areas.get(&area).map_or(0, |s| s.count);
+        percentage(count, self.total_associations())
+    }
+
+    /// Write the summary as a TSV table.
+    ///
+    /// Region counts are percentages of all regions, with a
+    /// `closest_fallback` row when the fallback is enabled. With per-area
+    /// distance limits, a `candidates` row counts the candidates they
+    /// dropped, and a `clamped_percentages` one those with a percentage
+    /// clamped into [0, 100], if any. With transcript filters, `transcripts` and `genes` rows count
+    /// what they removed from the annotation. With regions on chromosomes
+    /// without genes, an `on_missing_chromosomes` region row counts them and
+    /// `missing_chromosome` rows name the chromosomes with the most regions,
+    /// after a `missing_chromosomes` row with the number of such chromosomes.
+    /// Area counts are percentages of
+    /// all reported associations. TSS histogram counts, in
+    /// `tss_upstream` and `tss_downstream` rows, are percentages of the
+    /// associated regions. For stranded regions, `sense` rows (keyed
+    /// `AREA:class`) split each area count by sense class, as percentages of
+    /// the area count. Summaries of association tables have no `unassigned`
+    /// row, and a `gene` row per gene with its number of associations.
+    pub fn write_tsv<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "# report_level: {}", self.level.as_str())?;
+        writeln!(
+            writer,
+            "section\tkey\tcount\tpercentage\tmedian_abs_tss_distance"
+        )?;
+
+        let mut region_rows = vec![
+            ("total", self.total_regions),
+            ("associated", self.associated_regions),
+        ];
+        if self.unassigned_known {
+            region_rows.push(("unassigned", self.unassigned_regions));
+        }
+        if self.always_closest {
+            region_rows.push(("closest_fallback", self.closest_fallback_regions));
+        }
+        for (key, count) in region_rows {
+            writeln!(
+                writer,
+                "regions\t{}\t{}\t{:.2}\tNA",
+                key,
+                count,
+                percentage(count, self.total_regions)
+            )?;
+        }
+        if self.area_distance_limits {
+            writeln!(
+                writer,
+                "candidates\tdistance_filtered\t{}\tNA\tNA",
+                self.distance_filtered_candidates
+            )?;
+        }
+        if self.clamped_candidates > 0 {
+            writeln!(
+                writer,
+                "candidates\tclamped_percentages\t{}\tNA\tNA",
+                self.clamped_candidates
+            )?;
+        }
+        if let Some(counts) = &self.transcript_filtering {
+            for (key, count) in [
+                ("untagged", counts.untagged),
+                ("above_max_tsl", counts.above_max_tsl),
+                ("excluded", counts.transcripts),
+            ] {
+                writeln!(writer, "transcripts\t{}\t{}\tNA\tNA", key, count)?;
+            }
+            writeln!(writer, "genes\tdropped\t{}\tNA\tNA", counts.genes)?;
+        }
+        let missing = &self.missing_chromosomes;
+        if !missing.is_empty() {
+            writeln!(
+                writer,
+                "regions\ton_missing_chromosomes\t{}\t{:.2}\tNA",
+                missing.num_regions(),
+                percentage(missing.num_regions(), self.total_regions)
+            )?;
+            writeln!(
+                writer,
+                "missing_chromosomes\ttotal\t{}\tNA\tNA",
+                missing.num_chromosomes()
+            )?;
+            for (chrom, count) in missing.top(MISSING_CHROMOSOMES_SHOWN) {
+                writeln!(
+                    writer,
+                    "missing_chromosome\t{}\t{}\t{:.2}\tNA",
+                    chrom,
+                    count,
+                    percentage(count, self.total_regions)
+                )?;
+            }
+        }
+
+        for (area, stats) in &self.areas {
+            let median = stats
+                .median_abs_tss_distance()
+                .map_or_else(|| "NA".to_string(), |m| format!("{:.1}", m));
+            writeln!(
+                writer,
+                "area\t{}\t{}\t{:.2}\t{}",
+                area,
+                stats.count,
+                self.area_percentage(*area),
+                median
+            )?;
+        }
+
+        if self.is_stranded() {
+            for (area, stats) in &self.areas {
+                for (class, &count) in &stats.by_sense {
+                    writeln!(
+                        writer,
+                        "sense\t{}:{}\t{}\t{:.2}\tNA",
+                        area,
+                        class.as_str(),
+                        count,
+                        percentage(count, stats.count)
+                    )?;
+                }
+            }
+        }
+
+        if let Some(genes) = &self.gene_associations {
+            for (gene, &count) in genes {
+                writeln!(
+                    writer,
+                    "gene\t{}\t{}\t{:.2}\tNA",
+                    gene,
+                    count,
+                    percentage(count, self.total_associations())
+                )?;
+            }
+        }
+
+        let histogram = &self.tss_histogram;
+        for (section, counts) in [
+            ("tss_upstream", &histogram.upstream),
+            ("tss_downstream", &histogram.downstream),
+        ] {
+            for (bin, &count) in counts.iter().enumerate() {
+                writeln!(
+                    writer,
+                    "{}\t{}\t{}\t{:.2}\tNA",
+                    section,
+                    histogram.bin_label(bin),
+                    count,
+                    percentage(count, histogram.total())
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the summary as a JSON object.
+    ///
+    /// For stranded regions, each area also has a `sense` object with its
+    /// count per sense class. Regions on chromosomes without genes are
+    /// described by a `missing_chromosomes` object, when there are any.
+    /// Summaries of association tables have no `unassigned_regions`, and a
+    /// `gene_associations` object with the number of associations per gene.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "{{")?;
+        writeln!(writer, "  \"report_level\": \"{}\",", self.level.as_str())?;
+        writeln!(writer, "  \"total_regions\": {},", self.total_regions)?;
+        writeln!(
+            writer,
+            "  \"associated_regions\": {},",
+            self.associated_regions
+        )?;
+        if self.unassigned_known {
+            writeln!(
+                writer,
+                "  \"unassigned_regions\": {},",
+                self.unassigned_regions
+            )?;
+        }
+        if self.always_closest {
+            writeln!(
+                writer,
+                "  \"closest_fallback_regions\": {},",
+                self.closest_fallback_regions
+            )?;
+        }
+        if self.area_distance_limits {
+            writeln!(
+                writer,
+                "  \"distance_filtered_candidates\": {},",
+                self.distance_filtered_candidates
+            )?;
+        }
+        if self.clamped_candidates > 0 {
+            writeln!(
+                writer,
+                "  \"clamped_percentage_candidates\": {},",
+                self.clamped_candidates
+            )?;
+        }
+        if let Some(counts) = &self.transcript_filtering {
+            writeln!(
+                writer,
+                "  \"transcript_filtering\": {{\"untagged\": {}, \"above_max_tsl\": {}, \"excluded_transcripts\": {}, \"dropped_genes\": {}}},",
+                counts.untagged, counts.above_max_tsl, counts.transcripts, counts.genes
+            )?;
+        }
+        let missing = &self.missing_chromosomes;
+        if !missing.is_empty() {
+            let top: Vec<String> = missing
+                .top(MISSING_CHROMOSOMES_SHOWN)
+                .iter()
+                .map(|(chrom, count)| format!("{{\"chrom\": {:?}, \"regions\": {}}}", chrom, count))
+                .collect();
+            writeln!(
+                writer,
+                "  \"missing_chromosomes\": {{\"chromosomes\": {}, \"regions\": {}, \"top\": [{}]}},",
+                missing.num_chromosomes(),
+                missing.num_regions(),
+                top.join(", ")
+            )?;
+        }
+        writeln!(
+            writer,
+            "  \"total_associations\": {},",
+            self.total_associations()
+        )?;
+        if let Some(genes) = &self.gene_associations {
+            let counts: Vec<String> = genes
+                .iter()
+                .map(|(gene, count)| format!("{:?}: {}", gene, count))
+                .collect();
+            writeln!(
+                writer,
+                "  \"gene_associations\": {{{}}},",
+                counts.join(", ")
+            )?;
+        }
+        if let Some(timings) = &self.timings {
+            writeln!(
+                writer,
+                "  \"timings\": {{\"gtf_parse_secs\": {:.3}, \"bed_parse_secs\": {:.3}, \"matching_secs\": {:.3}, \"write_secs\": {:.3}, \"total_secs\": {:.3}, \"regions_per_sec\": {:.1}, \"peak_candidates\": {}, \"slowest_chromosomes\": [{}]}},",
+                timings.gtf_parse_secs,
+                timings.bed_parse_secs,
+                timings.matching_secs(),
+                timings.write_secs,
+                timings.total_secs,
+                timings.regions_per_sec(),
+                timings.peak_candidates,
+                timings.slowest_json()
+            )?;
+        }
+        writeln!(writer, "  \"areas\": [")?;
+
+        for (i, (area, stats)) in self.areas.iter().enumerate() {
+            let median = stats
+                .median_abs_tss_distance()
+                .map_or_else(|| "null".to_string(), |m| format!("{:.1}", m));
+            let separator = if i + 1 < self.areas.len() { "," } else { "" };
+            let sense = if self.is_stranded() {
+                let counts: Vec<String> = SenseClass::ALL
+                    .iter()
+                    .map(|class| {
+                        let count = stats.by_sense.get(class).copied().unwrap_or(0);
+                        format!("\"{}\": {}", class.as_str(), count)
+                    })
+                    .collect();
+                format!(", \"sense\": {{{}}}", counts.join(", "))
+            } else {
+                String::new()
+            };
+            writeln!(
+                writer,
+                "    {{\"area\": \"{}\", \"count\": {}, \"percentage\": {:.2}, \"median_abs_tss_distance\": {}{}}}{}",
+                area,
+                stats.count,
+                self.area_percentage(*area),
+                median,
+                sense,
+                separator
+            )?;
+        }
+
+        writeln!(writer, "  ],")?;
+
+        let join = |values: &[u64]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let histogram = &self.tss_histogram;
+        let edges: Vec<u64> = histogram.edges.iter().map(|&e| e as u64).collect();
+        writeln!(writer, "  \"tss_histogram\": {{")?;
+        writeln!(writer, "    \"edges\": [{}],", join(&edges))?;
+        writeln!(writer, "    \"upstream\": [{}],", join(&histogram.upstream))?;
+        writeln!(
+            writer,
+            "    \"downstream\": [{}]",
+            join(&histogram.downstream)
+        )?;
+        writeln!(writer, "  }}")?;
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+}
+
+/// Compute a summary over complete results.
+pub fn summarize(results: &[(Region, Vec<Candidate>)], level: ReportLevel) -> Summary {
+    let mut summary = Summary::new(level);
+    for (_, candidates) in results {
+        summary.add_region(candidates);
+    }
+    summary
+}
+
+fn percentage(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 / total as f64 * 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn make_candidate(area: Area, gene: &str, tss_distance: i64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            area,
+            "T1".to_string(),
+            gene.to_string(),
+            0,
+            100.0,
+            100.0,
+            tss_distance,
+        )
+    }
+
+    fn fixture() -> Vec<(Region, Vec<Candidate>)> {
+        let region = |start| Region::new("chr1".to_string(), start, start + 100, vec![]);
+        vec![
+            (
+                region(100),
+                vec![
+                    make_candidate(Area::Tss, "G1", -50),
+                    make_candidate(Area::Promoter, "G1", -50),
+                ],
+            ),
+            (region(1000), vec![make_candidate(Area::Tss, "G2", 150)]),
+            (region(2000), vec![make_candidate(Area::Intron, "G2", 900)]),
+            (region(5000), vec![]),
+        ]
+    }
+
+    #[test]
+    fn test_summarize_counts() {
+        let summary = summarize(&fixture(), ReportLevel::Gene);
+
+        assert_eq!(summary.total_regions, 4);
+        assert_eq!(summary.associated_regions, 3);
+        assert_eq!(summary.unassigned_regions, 1);
+        assert_eq!(summary.total_associations(), 4);
+        assert_eq!(summary.areas[&Area::Tss].count, 2);
+        assert_eq!(summary.areas[&Area::Promoter].count, 1);
+        assert_eq!(summary.areas[&Area::Intron].count, 1);
+        assert!(!summary.areas.contains_key(&Area::Upstream));
+        assert_eq!(summary.area_percentage(Area::Tss), 50.0);
+    }
+
+    #[test]
+    fn test_median_abs_tss_distance() {
+        let summary = summarize(&fixture(), ReportLevel::Gene);
+
+        // |-50| and |150| -> (50 + 150) / 2
+        assert_eq!(
+            summary.areas[&Area::Tss].median_abs_tss_distance(),
+            Some(100.0)
+        );
+        assert_eq!(
+            summary.areas[&Area::Intron].median_abs_tss_distance(),
+            Some(900.0)
+        );
+
+        let mut stats = AreaStats::default();
+        assert_eq!(stats.median_abs_tss_distance(), None);
+        for value in [5, 1, 9] {
+            stats.count += 1;
+            *stats.abs_tss_distances.entry(value).or_default() += 1;
+        }
+        assert_eq!(stats.median_abs_tss_distance(), Some(5.0));
+    }
+
+    #[test]
+    fn test_tss_histogram_bin_edges() {
+        let mut histogram = TssHistogram::new(vec![1000, 5000]);
+        for distance in [0, 999, 1000, -1, -999, -1000, 4999, -5000, 5000, 80000] {
+            histogram.add(distance);
+        }
+
+        // [0, 1000), [1000, 5000), [5000, inf)
+        assert_eq!(histogram.downstream, vec![2, 2, 2]);
+        assert_eq!(histogram.upstream, vec![2, 1, 1]);
+        assert_eq!(histogram.total(), 10);
+        assert_eq!(histogram.bin_label(0), "0-1000");
+        assert_eq!(histogram.bin_label(1), "1000-5000");
+        assert_eq!(histogram.bin_label(2), "5000+");
+    }
+
+    #[test]
+    fn test_tss_histogram_uses_best_association() {
+        let summary = summarize(&fixture(), ReportLevel::Gene);
+        let histogram = &summary.tss_histogram;
+
+        // One entry per associated region; the TSS hit wins over the promoter
+        assert_eq!(histogram.total(), 3);
+        assert_eq!(histogram.upstream[0], 1);
+        assert_eq!(histogram.downstream[0], 2);
+
+        let config = Config {
+            tss_bins: vec![100, 500],
+            ..Config::default()
+        };
+        let mut summary = Summary::from_config(&config);
+        for (_, candidates) in fixture() {
+            summary.add_region(&candidates);
+        }
+        assert_eq!(summary.tss_histogram.upstream, vec![1, 0, 0]);
+        assert_eq!(summary.tss_histogram.downstream, vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn test_merge_matches_single_pass() {
+        let results = fixture();
+        let whole = summarize(&results, ReportLevel::Exon);
+
+        let mut merged = summarize(&results[..2], ReportLevel::Exon);
+        merged.merge(summarize(&results[2..], ReportLevel::Exon));
+
+        assert_eq!(merged.total_regions, whole.total_regions);
+        assert_eq!(merged.unassigned_regions, whole.unassigned_regions);
+        assert_eq!(merged.total_associations(), whole.total_associations());
+        assert_eq!(
+            merged.areas[&Area::Tss].median_abs_tss_distance(),
+            whole.areas[&Area::Tss].median_abs_tss_distance()
+        );
+        assert_eq!(merged.tss_histogram, whole.tss_histogram);
+    }
+
+    #[test]
+    fn test_write_tsv_and_json() {
+        let summary = summarize(&fixture(), ReportLevel::Gene);
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.starts_with("# report_level: gene\n"));
+        assert!(tsv.contains("regions\tunassigned\t1\t25.00\tNA\n"));
+        assert!(tsv.contains("area\tTSS\t2\t50.00\t100.0\n"));
+        assert!(tsv.contains("tss_upstream\t0-1000\t1\t33.33\tNA\n"));
+        assert!(tsv.contains("tss_downstream\t0-1000\t2\t66.67\tNA\n"));
+        assert!(tsv.ends_with("tss_downstream\t50000+\t0\t0.00\tNA\n"));
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"unassigned_regions\": 1,"));
+        assert!(json.contains(
+            "{\"area\": \"INTRON\", \"count\": 1, \"percentage\": 25.00, \"median_abs_tss_distance\": 900.0}"
+        ));
+        assert!(json.contains("\"edges\": [1000, 5000, 10000, 50000],"));
+        assert!(json.contains("\"upstream\": [1, 0, 0, 0, 0],"));
+        assert!(json.contains("\"downstream\": [2, 0, 0, 0, 0]\n"));
+    }
+
+    #[test]
+    fn test_missing_chromosomes() {
+        use crate::source::VecSource;
+
+        let source = VecSource::default();
+        let region = |chrom: &str| Region::new(chrom.to_string(), 100, 200, vec![]);
+        let mut summary = summarize(&fixture(), ReportLevel::Exon);
+        for chrom in ["chrX", "chrUn", "chrX"] {
+            summary.missing_chromosomes.add(&region(chrom), &source);
+        }
+        let mut other = MissingChromosomes::new();
+        other.add(&region("chrY"), &source);
+        summary.missing_chromosomes.merge(other);
+
+        let missing = &summary.missing_chromosomes;
+        assert_eq!(missing.num_regions(), 4);
+        assert_eq!(
+            missing.top(2),
+            vec![("chrX", 2), ("chrUn", 1)],
+            "ties are broken by name"
+        );
+        assert_eq!(
+            missing.report().unwrap(),
+            "3 chromosome(s) not found in the annotation, with 4 region(s) without genes: chrX (2), chrUn (1), chrY (1)"
+        );
+        assert!(missing.check(5).is_ok());
+        assert!(matches!(
+            missing.check(4),
+            Err(Error::NoAnnotatedRegions { regions: 4, .. })
+        ));
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("regions\ton_missing_chromosomes\t4\t100.00\tNA\n"));
+        assert!(tsv.contains("missing_chromosomes\ttotal\t3\tNA\tNA\n"));
+        assert!(tsv.contains("missing_chromosome\tchrX\t2\t50.00\tNA\n"));
+    }
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings::new();
+        timings.add_matching("chr1", 0.5);
+        timings.add_matching("chr2", 0.25);
+        timings.add_matching("chr1", 0.5);
+        timings.add_region(3);
+        timings.add_region(7);
+        timings.total_secs = 2.0;
+
+        let mut other = Timings::new();
+        other.add_matching("chr3", 0.25);
+        other.add_region(5);
+        other.gtf_parse_secs = 1.0;
+        other.total_secs = 2.0;
+        timings.merge(other);
+
+        assert_eq!(timings.matching_secs(), 1.5);
+        assert_eq!(timings.chromosome_secs("chr1"), 1.0);
+        assert_eq!(
+            timings.slowest_chromosomes(2),
+            vec![("chr1", 1.0), ("chr2", 0.25)],
+            "ties are broken by name"
+        );
+        assert_eq!(timings.regions, 3);
+        assert_eq!(timings.peak_candidates, 7);
+        assert_eq!(timings.regions_per_sec(), 0.75);
+        assert_eq!(Timings::new().regions_per_sec(), 0.0);
+
+        let mut summary = summarize(&fixture(), ReportLevel::Exon);
+        summary.timings = Some(timings);
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(
+            "  \"timings\": {\"gtf_parse_secs\": 1.000, \"bed_parse_secs\": 0.000, \"matching_secs\": 1.500, \"write_secs\": 0.000, \"total_secs\": 4.000, \"regions_per_sec\": 0.8, \"peak_candidates\": 7, \"slowest_chromosomes\": [{\"chrom\": \"chr1\", \"secs\": 1.000}, {\"chrom\": \"chr2\", \"secs\": 0.250}, {\"chrom\": \"chr3\", \"secs\": 0.250}]},\n"
+        ));
+    }
+
+    #[test]
+    fn test_distance_filtered_count() {
+        let mut config = Config::default();
+        config.area_max_distance.insert(Area::Upstream, 5000);
+        let mut summary = Summary::from_config(&config);
+        summary.add_region(&[make_candidate(Area::Tss, "G1", 10)]);
+        summary.add_distance_filtered(2);
+        let mut other = Summary::from_config(&config);
+        other.add_distance_filtered(1);
+        summary.merge(other);
+        assert_eq!(summary.distance_filtered_candidates, 3);
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(
+            tsv.contains("candidates\tdistance_filtered\t3\tNA\tNA\n"),
+            "{}",
+            tsv
+        );
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains("\"distance_filtered_candidates\": 3,"));
+
+        // Without limits, the count is not written
+        let mut tsv = Vec::new();
+        Summary::new(ReportLevel::Gene).write_tsv(&mut tsv).unwrap();
+        assert!(!String::from_utf8(tsv)
+            .unwrap()
+            .contains("distance_filtered"));
+    }
+
+    #[test]
+    fn test_clamped_percentages_count() {
+        let mut summary = Summary::new(ReportLevel::Exon);
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        assert!(!String::from_utf8(tsv).unwrap().contains("clamped"));
+
+        summary.add_clamped_percentages(1);
+        let mut other = Summary::new(ReportLevel::Exon);
+        other.add_clamped_percentages(2);
+        summary.merge(other);
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(
+            tsv.contains("candidates\tclamped_percentages\t3\tNA\tNA\n"),
+            "{}",
+            tsv
+        );
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(
+            json.contains("\"clamped_percentage_candidates\": 3,"),
+            "{}",
+            json
+        );
+    }
+
+    #[test]
+    fn test_intergenic_region() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut summary = Summary::new(ReportLevel::Gene);
+        summary.add_region(&[make_candidate(Area::Tss, "G1", 10)]);
+        summary.add_region(&[Candidate::intergenic(&region, Some(5000))]);
+        summary.add_region(&[Candidate::intergenic(&region, None)]);
+
+        assert_eq!(summary.associated_regions, 1);
+        assert_eq!(summary.unassigned_regions, 2);
+        assert_eq!(summary.areas[&Area::Intergenic].count, 2);
+        assert_eq!(summary.tss_histogram.total(), 1);
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("area\tINTERGENIC\t2\t66.67\tNA\n"), "{}", tsv);
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(
+            "{\"area\": \"INTERGENIC\", \"count\": 2, \"percentage\": 66.67, \"median_abs_tss_distance\": null}"
+        ));
+    }
+
+    #[test]
+    fn test_sense_breakdown() {
+        let with_sense = |area, sense| Candidate {
+            sense,
+            ..make_candidate(area, "G1", 10)
+        };
+        let mut summary = Summary::new(ReportLevel::Gene);
+        summary.add_region(&[
+            with_sense(Area::Tss, SenseClass::Sense),
+            with_sense(Area::Intron, SenseClass::Antisense),
+        ]);
+        summary.add_region(&[with_sense(Area::Tss, SenseClass::Antisense)]);
+        summary.add_region(&[with_sense(Area::Tss, SenseClass::Sense)]);
+        assert!(summary.is_stranded());
+        assert_eq!(summary.areas[&Area::Tss].by_sense[&SenseClass::Sense], 2);
+        assert_eq!(
+            summary.areas[&Area::Tss].by_sense[&SenseClass::Antisense],
+            1
+        );
+
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        let tsv = String::from_utf8(tsv).unwrap();
+        assert!(tsv.contains("sense\tTSS:sense\t2\t66.67\tNA\n"));
+        assert!(tsv.contains("sense\tTSS:antisense\t1\t33.33\tNA\n"));
+        assert!(tsv.contains("sense\tINTRON:antisense\t1\t100.00\tNA\n"));
+        assert!(!tsv.contains("unstranded"));
+
+        let mut json = Vec::new();
+        summary.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(
+            "\"median_abs_tss_distance\": 10.0, \"sense\": {\"sense\": 2, \"antisense\": 1, \"unstranded\": 0}}"
+        ));
+
+        // Unstranded runs keep the plain layout
+        let summary = summarize(&fixture(), ReportLevel::Gene);
+        assert!(!summary.is_stranded());
+        let mut tsv = Vec::new();
+        summary.write_tsv(&mut tsv).unwrap();
+        assert!(!String::from_utf8(tsv).unwrap().contains("sense"));
+    }
+}
@@ -2,14 +2,19 @@
 //!
 //! Parses BED (Browser Extensible Data) files containing genomic regions.
 
-use ahash::AHashMap;
-use anyhow::{Context, Result};
-use std::fs::File;
+use ahash::{AHashMap, AHashSet};
+use log::{debug, trace, warn};
 use std::io::BufRead;
-use std::path::Path;
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::Path};
 
+use crate::config::Config;
+use crate::error::{Error, Result};
+#[cfg(not(target_arch = "wasm32"))]
 use crate::parser::util::create_buffered_reader;
-use crate::types::Region;
+use crate::parser::{check_coordinate, split_fields};
+use crate::types::{InvalidRegions, Region, Strand};
 
 /// Streaming BED file reader for chunked processing.
 ///
@@ -17,19 +22,114 @@ use crate::types::Region;
 /// in chunks, enabling memory-efficient processing of large files.
 pub struct BedReader {
     reader: Box<dyn BufRead + Send>,
+    path: Option<PathBuf>,
+    line_number: usize,
     num_meta_columns: usize,
+    allow_negative: bool,
+    invalid_regions: InvalidRegions,
+    num_swapped: usize,
+    num_dropped: usize,
+    header_meta_columns: Option<usize>,
+    num_trimmed: usize,
+    num_skipped: usize,
 }
 
 impl BedReader {
     /// Create a new BedReader from a file path (supports .gz).
+    #[cfg(not(target_arch = "wasm32"))]
     pub fn new(path: &Path) -> Result<Self> {
-        let file = File::open(path).context("Failed to open BED file")?;
-        let reader = create_buffered_reader(file, path);
+        let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+        let mut reader = Self::from_reader(create_buffered_reader(file, path));
+        reader.path = Some(path.to_path_buf());
+        Ok(reader)
+    }
 
-        Ok(BedReader {
-            reader,
+    /// Create a BedReader over any buffered reader, such as an in-memory
+    /// `Cursor` or a stream decompressed by the caller.
+    pub fn from_reader<R: BufRead + Send + 'static>(reader: R) -> Self {
+        BedReader {
+            reader: Box::new(reader),
+            path: None,
+            line_number: 0,
             num_meta_columns: 0,
-        })
+            allow_negative: false,
+            invalid_regions: InvalidRegions::default(),
+            num_swapped: 0,
+            num_dropped: 0,
+            header_meta_columns: None,
+            num_trimmed: 0,
+            num_skipped: 0,
+        }
+    }
+
+    /// Apply the BED options of `config`: whether negative coordinates are
+    /// accepted and what to do with regions whose start is after their end.
+    pub fn with_config(self, config: &Config) -> Self {
+        self.allow_negative_coordinates(config.allow_negative_coordinates)
+            .invalid_regions(config.invalid_regions)
+    }
+
+    /// Accept negative coordinates, which are otherwise a parse error.
+    pub fn allow_negative_coordinates(mut self, allow: bool) -> Self {
+        self.allow_negative = allow;
+        self
+    }
+
+    /// What to do with regions whose start is greater than their end.
+    pub fn invalid_regions(mut self, policy: InvalidRegions) -> Self {
+        self.invalid_regions = policy;
+        self
+    }
+
+    /// Number of regions read so far whose start and end were swapped.
+    pub fn num_swapped(&self) -> usize {
+        self.num_swapped
+    }
+
+    /// Number of regions skipped so far because their start was greater
+    /// than their end.
+    pub fn num_dropped(&self) -> usize {
+        self.num_dropped
+    }
+
+    /// Number of non-empty lines read so far that are not regions: headers,
+    /// `track` and `browser` lines, and lines with fewer than 3 columns.
+    pub fn num_skipped(&self) -> usize {
+        self.num_skipped
+    }
+
+    /// Fix the number of metadata columns of the output header to the most
+    /// found so far, and return it.
+    ///
+    /// Streaming output writes the header after the first chunk, before the
+    /// rest of the file is read. Its rows are padded or trimmed to this
+    /// count; lines read afterwards with more metadata columns are counted
+    /// and reported by [`BedReader::warn_irregular_lines`].
+    pub fn header_meta_columns(&mut self) -> usize {
+        *self
+            .header_meta_columns
+            .get_or_insert(self.num_meta_columns)
+    }
+
+    /// Log a warning with the number of swapped and dropped regions, and of
+    /// lines with more metadata columns than the header, if any.
+    pub fn warn_irregular_lines(&self) {
+        if self.num_trimmed > 0 {
+            warn!(
+                "{} BED lines after the first chunk have more than the {} metadata columns of the output header; their extra columns were dropped",
+                self.num_trimmed,
+                self.header_meta_columns.unwrap_or_default()
+            );
+        }
+        if self.num_swapped > 0 {
+            warn!(
+                "Swapped the start and end of {} BED regions with start > end",
+                self.num_swapped
+            );
+        }
+        if self.num_dropped > 0 {
+            warn!("Skipped {} BED regions with start > end", self.num_dropped);
+        }
     }
 
     /// Get the number of metadata columns found so far.
@@ -50,12 +150,16 @@ impl BedReader {
             let bytes_read = self
                 .reader
                 .read_line(&mut line)
-                .context("Failed to read BED line")?;
+                .map_err(|e| match &self.path {
+                    Some(path) => Error::from(e).in_file(path),
+                    None => Error::from(e),
+                })?;
 
             if bytes_read == 0 {
                 // EOF reached
                 break;
             }
+            self.line_number += 1;
 
             // Skip empty lines
             let trimmed = line.trim_end();
@@ -63,7 +167,7 @@ impl BedReader {
                 continue;
             }
 
-            if let Some(region) = self.parse_line(trimmed) {
+            if let Some(region) = self.parse_line(trimmed)? {
                 regions.push(region);
             }
         }
@@ -75,21 +179,49 @@ impl BedReader {
         }
     }
 
-    /// Parse a single BED line into a Region.
-    fn parse_line(&mut self, line: &str) -> Option<Region> {
-        let fields: Vec<&str> = line.split('\t').collect();
+    /// Parse a single BED line into a Region; `None` for skipped lines.
+    fn parse_line(&mut self, line: &str) -> Result<Option<Region>> {
+        let fields = split_fields(line);
 
         // Need at least 3 columns: chrom, start, end
         if fields.len() < 3 {
-            return None;
+            trace!(
+                "BED line {}: skipped, fewer than 3 columns",
+                self.line_number
+            );
+            self.num_skipped += 1;
+            return Ok(None);
         }
 
         let chrom = fields[0].to_string();
 
         // Try to parse start and end as integers
         // If they fail (e.g., header line), skip this line
-        let start: i64 = fields[1].parse().ok()?;
-        let end: i64 = fields[2].parse().ok()?;
+        let Some((start, end)) = parse_coordinates(&fields, self.line_number, self.allow_negative)?
+        else {
+            self.num_skipped += 1;
+            return Ok(None);
+        };
+        let (start, end) = if start > end {
+            match self.invalid_regions {
+                InvalidRegions::Swap => {
+                    self.num_swapped += 1;
+                    (end, start)
+                }
+                InvalidRegions::Drop => {
+                    self.num_dropped += 1;
+                    return Ok(None);
+                }
+                InvalidRegions::Error => {
+                    return Err(Error::BedParse {
+                        line: self.line_number,
+                        reason: format!("start {} is greater than end {}", start, end),
+                    })
+                }
+            }
+        } else {
+            (start, end)
+        };
 
         // Extract up to 9 additional BED columns as metadata
         let metadata: Vec<String> = fields
@@ -103,46 +235,94 @@ impl BedReader {
         if metadata.len() > self.num_meta_columns {
             self.num_meta_columns = metadata.len();
         }
+        if self
+            .header_meta_columns
+            .is_some_and(|header| metadata.len() > header)
+        {
+            self.num_trimmed += 1;
+        }
 
-        Some(Region::new(chrom, start, end, metadata))
+        let strand = parse_strand_column(&fields);
+        Ok(Some(
+            Region::new(chrom, start, end, metadata)
+                .with_strand(strand)
+                .with_line(self.line_number),
+        ))
     }
 }
 
 /// Result of parsing a BED file.
 pub struct BedData {
-    /// Regions organized by chromosome.
+    /// Regions organized by chromosome, in file order.
     pub regions_by_chrom: AHashMap<String, Vec<Region>>,
     /// Number of metadata columns found.
     pub num_meta_columns: usize,
+    /// Chromosomes whose regions were not in coordinate order in the file.
+    pub unsorted_chroms: AHashSet<String>,
+}
+
+impl BedData {
+    /// Sort the regions of each chromosome by start, then end.
+    ///
+    /// Only the chromosomes in [`unsorted_chroms`](Self::unsorted_chroms) are
+    /// sorted, in place; regions of a coordinate-sorted file are not touched.
+    /// Returns the number of chromosomes that were sorted.
+    pub fn sort_all(&mut self) -> usize {
+        let mut num_sorted = 0;
+        for chrom in self.unsorted_chroms.drain() {
+            if let Some(regions) = self.regions_by_chrom.get_mut(&chrom) {
+                regions.sort_by_key(|r| (r.start, r.end));
+                num_sorted += 1;
+            }
+        }
+        debug!(
+            "BED: sorted {} of {} chromosomes",
+            num_sorted,
+            self.regions_by_chrom.len()
+        );
+        num_sorted
+    }
+
+    /// Take the regions of each chromosome, sorted by start, then end.
+    pub fn into_sorted_regions(mut self) -> AHashMap<String, Vec<Region>> {
+        self.sort_all();
+        self.regions_by_chrom
+    }
 }
 
 /// Parse a BED file and return organized region data.
 ///
 /// Supports both plain text and gzip-compressed BED files.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn parse_bed(path: &Path) -> Result<BedData> {
-    let file = File::open(path).context("Failed to open BED file")?;
+    let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
     let reader = create_buffered_reader(file, path);
 
-    parse_bed_reader(reader)
+    parse_bed_from_reader(reader).map_err(|e| e.in_file(path))
 }
 
-/// Parse BED data from a reader.
-fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
+/// Parse BED data from any buffered reader.
+///
+/// The data must already be decompressed; [`parse_bed`] handles gzip files.
+/// Negative coordinates are an error and regions with start > end are
+/// swapped; [`BedReader`] can be configured otherwise.
+pub fn parse_bed_from_reader<R: BufRead>(reader: R) -> Result<BedData> {
     let mut regions_by_chrom: AHashMap<String, Vec<Region>> = AHashMap::new();
+    let mut unsorted_chroms = AHashSet::new();
     let mut num_meta_columns = 0;
 
-    for line_result in reader.lines() {
-        let line = line_result.context("Failed to read BED line")?;
+    for (index, line_result) in reader.lines().enumerate() {
+        let line = line_result?;
 
         // Skip empty lines
-        if line.is_empty() {
+        let fields = split_fields(&line);
+        if fields.is_empty() {
             continue;
         }
 
-        let fields: Vec<&str> = line.split('\t').collect();
-
         // Need at least 3 columns: chrom, start, end
         if fields.len() < 3 {
+            trace!("BED line {}: skipped, fewer than 3 columns", index + 1);
             continue;
         }
 
@@ -150,13 +330,14 @@ fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
 
         // Try to parse start and end as integers
         // If they fail (e.g., header line), skip this line
-        let start: i64 = match fields[1].parse() {
-            Ok(v) => v,
-            Err(_) => continue, // Skip header lines
+        let Some((start, end)) = parse_coordinates(&fields, index + 1, false)? else {
+            continue;
         };
-        let end: i64 = match fields[2].parse() {
-            Ok(v) => v,
-            Err(_) => continue,
+        let (start, end) = if start > end {
+            trace!("BED line {}: start > end, swapped", index + 1);
+            (end, start)
+        } else {
+            (start, end)
         };
 
         // Extract up to 9 additional BED columns as metadata
@@ -172,16 +353,62 @@ fn parse_bed_reader<R: BufRead>(reader: R) -> Result<BedData> {
             num_meta_columns = metadata.len();
         }
 
-        let region = Region::new(chrom.clone(), start, end, metadata);
-        regions_by_chrom.entry(chrom).or_default().push(region);
+        let strand = parse_strand_column(&fields);
+        let region = Region::new(chrom.clone(), start, end, metadata).with_strand(strand);
+        let regions = regions_by_chrom.entry(chrom).or_default();
+        if regions
+            .last()
+            .is_some_and(|last| (start, end) < (last.start, last.end))
+            && !unsorted_chroms.contains(&region.chrom)
+        {
+            unsorted_chroms.insert(region.chrom.clone());
+        }
+        regions.push(region);
     }
 
     Ok(BedData {
         regions_by_chrom,
         num_meta_columns,
+        unsorted_chroms,
     })
 }
 
+/// Parse the start and end columns of a BED line.
+///
+/// Lines whose start is not an integer (headers, `track` and `browser` lines)
+/// are skipped with `None`; a data line whose end is not an integer, or with
+/// a coordinate outside the supported range, is an error.
+fn parse_coordinates(
+    fields: &[&str],
+    line: usize,
+    allow_negative: bool,
+) -> Result<Option<(i64, i64)>> {
+    let Ok(start) = fields[1].parse() else {
+        trace!(
+            "BED line {}: skipped, start '{}' is not an integer",
+            line,
+            fields[1]
+        );
+        return Ok(None);
+    };
+    let end = fields[2].parse().map_err(|_| Error::BedParse {
+        line,
+        reason: format!("end '{}' is not an integer", fields[2]),
+    })?;
+    let check = |column, value| {
+        check_coordinate(column, value, allow_negative)
+            .map_err(|reason| Error::BedParse { line, reason })
+    };
+    Ok(Some((check("start", start)?, check("end", end)?)))
+}
+
+/// Parse the strand column (BED column 6), if present and stranded.
+///
+/// Values other than `+` and `-` (such as `.`) mean the region is unstranded.
+fn parse_strand_column(fields: &[&str]) -> Option<Strand> {
+    fields.get(5).and_then(|s| s.parse().ok())
+}
+
 /// Get standard BED column headers for metadata columns.
 pub fn get_bed_headers(num_columns: usize) -> Vec<&'static str> {
     let all_headers = [
@@ -209,7 +436,7 @@ mod tests {
         let bed_content = "chr1\t100\t200\nchrom2\t300\t400\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_from_reader(reader).unwrap();
 
         assert!(result.regions_by_chrom.contains_key("chr1"));
         assert!(result.regions_by_chrom.contains_key("chrom2"));
@@ -221,39 +448,137 @@ mod tests {
         assert!(chr1_regions[0].metadata.is_empty());
     }
 
+    #[test]
+    fn test_sort_all_skips_sorted_chromosomes() {
+        let bed_content = "chr1\t100\t200\nchr2\t500\t600\nchr1\t100\t150\nchr1\t50\t80\nchr2\t700\t800\nchr1\t300\t400\n";
+
+        let reader = BufReader::new(bed_content.as_bytes());
+        let mut result = parse_bed_from_reader(reader).unwrap();
+        assert!(result.unsorted_chroms.contains("chr1"));
+        assert!(!result.unsorted_chroms.contains("chr2"));
+
+        assert_eq!(result.sort_all(), 1);
+        assert!(result.unsorted_chroms.is_empty());
+        let starts: Vec<(i64, i64)> = result.regions_by_chrom["chr1"]
+            .iter()
+            .map(|r| (r.start, r.end))
+            .collect();
+        assert_eq!(starts, vec![(50, 80), (100, 150), (100, 200), (300, 400)]);
+
+        // Nothing is left to sort the second time.
+        assert_eq!(result.sort_all(), 0);
+    }
+
+    #[test]
+    fn test_into_sorted_regions_matches_sorted_input() {
+        let sorted = "chr1\t50\t80\tb\nchr1\t100\t200\ta\nchr2\t10\t20\tc\n";
+        let unsorted = "chr2\t10\t20\tc\nchr1\t100\t200\ta\nchr1\t50\t80\tb\n";
+
+        let sorted = parse_bed_from_reader(BufReader::new(sorted.as_bytes())).unwrap();
+        assert!(sorted.unsorted_chroms.is_empty());
+        let unsorted = parse_bed_from_reader(BufReader::new(unsorted.as_bytes())).unwrap();
+
+        let sorted = sorted.into_sorted_regions();
+        let unsorted = unsorted.into_sorted_regions();
+        for chrom in ["chr1", "chr2"] {
+            let a: Vec<_> = sorted[chrom]
+                .iter()
+                .map(|r| (r.start, r.end, r.metadata.clone()))
+                .collect();
+            let b: Vec<_> = unsorted[chrom]
+                .iter()
+                .map(|r| (r.start, r.end, r.metadata.clone()))
+                .collect();
+            assert_eq!(a, b);
+        }
+    }
+
     #[test]
     fn test_parse_bed_with_metadata() {
         let bed_content = "chr1\t100\t200\tregion1\t500\t+\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_from_reader(reader).unwrap();
 
         let regions = &result.regions_by_chrom["chr1"];
         assert_eq!(regions[0].metadata.len(), 3);
         assert_eq!(regions[0].metadata[0], "region1");
         assert_eq!(regions[0].metadata[1], "500");
         assert_eq!(regions[0].metadata[2], "+");
+        assert_eq!(regions[0].strand, Some(Strand::Positive));
         assert_eq!(result.num_meta_columns, 3);
     }
 
+    #[test]
+    fn test_crlf_and_trailing_tabs() {
+        // Windows line endings, trailing tabs and a blank score column
+        let bed_content =
+            "chr1\t100\t200\tr1\t \t+\r\nchr1\t300\t400\tr2\t\t\r\nchr1\t500\t600\t\t\n\r\n";
+
+        let result = parse_bed_from_reader(BufReader::new(bed_content.as_bytes())).unwrap();
+        let regions = &result.regions_by_chrom["chr1"];
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].metadata, vec!["r1", "", "+"]);
+        assert_eq!(regions[0].strand, Some(Strand::Positive));
+        assert_eq!(regions[1].metadata, vec!["r2"]);
+        assert!(regions[2].metadata.is_empty());
+        assert_eq!(result.num_meta_columns, 3);
+
+        let mut reader = BedReader::from_reader(std::io::Cursor::new(bed_content));
+        let regions = reader.read_chunk(10).unwrap().unwrap();
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].metadata, vec!["r1", "", "+"]);
+        assert_eq!(regions[1].metadata, vec!["r2"]);
+        assert!(regions[2].metadata.is_empty());
+        assert_eq!(reader.num_meta_columns(), 3);
+    }
+
+    #[test]
+    fn test_parse_bed_strand() {
+        let bed_content =
+            "chr1\t100\t200\tr1\t0\t-\nchr1\t300\t400\tr2\t0\t.\nchr1\t500\t600\tr3\n";
+
+        let reader = BufReader::new(bed_content.as_bytes());
+        let result = parse_bed_from_reader(reader).unwrap();
+
+        let regions = &result.regions_by_chrom["chr1"];
+        assert_eq!(regions[0].strand, Some(Strand::Negative));
+        assert_eq!(regions[1].strand, None);
+        assert_eq!(regions[2].strand, None);
+    }
+
     #[test]
     fn test_parse_bed_skip_header() {
         let bed_content = "chrom\tstart\tend\tname\nchr1\t100\t200\tregion1\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_from_reader(reader).unwrap();
 
         // Should skip header line (can't parse 'start' as int)
         assert!(result.regions_by_chrom.contains_key("chr1"));
         assert!(!result.regions_by_chrom.contains_key("chrom"));
     }
 
+    #[test]
+    fn test_parse_bed_invalid_end() {
+        let bed_content = "chrom\tstart\tend\nchr1\t100\t2e3\n";
+
+        let reader = BufReader::new(bed_content.as_bytes());
+        match parse_bed_from_reader(reader) {
+            Err(Error::BedParse { line, reason }) => {
+                assert_eq!(line, 2);
+                assert_eq!(reason, "end '2e3' is not an integer");
+            }
+            other => panic!("expected a BedParse error, got {:?}", other.is_ok()),
+        }
+    }
+
     #[test]
     fn test_parse_bed_empty_lines() {
         let bed_content = "\nchr1\t100\t200\n\nchr1\t300\t400\n\n";
 
         let reader = BufReader::new(bed_content.as_bytes());
-        let result = parse_bed_reader(reader).unwrap();
+        let result = parse_bed_from_reader(reader).unwrap();
 
         let regions = &result.regions_by_chrom["chr1"];
         assert_eq!(regions.len(), 2);
@@ -349,7 +674,7 @@ mod tests {
         writeln!(temp_file).unwrap(); // empty line
         writeln!(temp_file, "chr1\t100\t200\tregion1").unwrap();
         writeln!(temp_file).unwrap(); // empty line
-        writeln!(temp_file, "chr1\t300\t400\tregion2").unwrap();
+        writeln!(temp_file, "chr1\t300\t400\tregion2\t0\t+").unwrap();
         temp_file.flush().unwrap();
 
         let mut reader = BedReader::new(temp_file.path()).unwrap();
@@ -358,6 +683,8 @@ mod tests {
         // Should only get 2 valid regions
         assert_eq!(chunk.len(), 2);
         assert_eq!(chunk[0].start, 100);
+        assert_eq!(chunk[0].strand, None);
         assert_eq!(chunk[1].start, 300);
+        assert_eq!(chunk[1].strand, Some(Strand::Positive));
     }
 }
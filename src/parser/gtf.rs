@@ -4,167 +4,518 @@
 //! structure of genes, transcripts, and exons organized by chromosome.
 
 use ahash::AHashMap;
-use anyhow::{Context, Result};
-use std::fs::File;
+use log::trace;
+use rayon::prelude::*;
+use std::collections::hash_map::Entry;
 use std::io::BufRead;
-use std::path::Path;
-
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::Path};
+
+use crate::config::TranscriptFilters;
+use crate::error::{Error, Result};
+use crate::parser::interval::IntervalIndex;
+use crate::parser::tss_bed::TssPositions;
+#[cfg(not(target_arch = "wasm32"))]
 use crate::parser::util::create_buffered_reader;
-use crate::types::{Exon, Gene, Strand, Transcript};
+use crate::parser::{check_coordinate, leading_fields};
+use crate::source::{first_gene_ending_after, running_max_ends, sort_genes, AnnotationSource};
+use crate::types::{Exon, Gene, Name, NamePool, Strand, Transcript};
 
 /// Result of parsing a GTF file.
+///
+/// The parsers return the genes sorted (see [`GtfData::sort_genes`]); call
+/// `sort_genes` again after changing `genes_by_chrom`.
 #[derive(Clone)]
 pub struct GtfData {
     /// Genes organized by chromosome.
     pub genes_by_chrom: AHashMap<String, Vec<Gene>>,
     /// Maximum gene length per chromosome.
     pub max_lengths: AHashMap<String, i64>,
+    /// Chromosome and position of every gene, by gene ID.
+    gene_index: AHashMap<Name, (Name, usize)>,
+    /// Overlap index over the sorted genes of every chromosome.
+    interval_index: AHashMap<String, IntervalIndex>,
+    /// Running maximum of the gene ends of every chromosome.
+    max_ends: AHashMap<String, Vec<i64>>,
+    /// Transcripts and genes removed by the transcript filters, if
+    /// `load_annotation` applied any.
+    pub transcript_filtering: Option<TranscriptFilterCounts>,
+    /// Transcript IDs found under more than one gene, in file order. Each
+    /// gene keeps its own transcript with that ID.
+    pub shared_transcript_ids: Vec<SharedTranscriptId>,
+}
+
+/// A transcript ID found under a second gene, as in some malformed GTFs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedTranscriptId {
+    /// The transcript ID.
+    pub transcript_id: String,
+    /// Gene the transcript ID was first found under.
+    pub first_gene_id: String,
+    /// Other gene it was found under.
+    pub gene_id: String,
+    /// Line (1-based) of its first record under `gene_id`.
+    pub line: usize,
+}
+
+/// Transcripts and genes removed by [`GtfData::filter_transcripts`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TranscriptFilterCounts {
+    /// Transcripts without any of the required tags.
+    pub untagged: usize,
+    /// Transcripts above the maximum support level, or without one.
+    pub above_max_tsl: usize,
+    /// Transcripts removed by either filter.
+    pub transcripts: usize,
+    /// Genes left without any transcript, and removed.
+    pub genes: usize,
+}
+
+impl GtfData {
+    /// Sort the genes of every chromosome by start position, then gene ID,
+    /// and rebuild the gene ID and interval indexes and the running maximum
+    /// of gene ends.
+    ///
+    /// Matching requires sorted genes; the gene ID keeps the order of genes
+    /// with the same start deterministic.
+    pub fn sort_genes(&mut self) {
+        self.genes_by_chrom
+            .par_iter_mut()
+            .for_each(|(_, genes)| sort_genes(genes));
+
+        self.gene_index.clear();
+        for (chrom, genes) in &self.genes_by_chrom {
+            let chrom = Name::from(chrom);
+            for (i, gene) in genes.iter().enumerate() {
+                self.gene_index
+                    .insert(gene.gene_id.clone(), (chrom.clone(), i));
+            }
+        }
+
+        self.interval_index = self
+            .genes_by_chrom
+            .par_iter()
+            .map(|(chrom, genes)| (chrom.clone(), IntervalIndex::new(genes)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .collect();
+
+        self.max_ends = self
+            .genes_by_chrom
+            .iter()
+            .map(|(chrom, genes)| (chrom.clone(), running_max_ends(genes)))
+            .collect();
+    }
+
+    /// Look up a gene by its ID.
+    pub fn gene_by_id(&self, gene_id: &str) -> Option<&Gene> {
+        let (chrom, index) = self.gene_index.get(gene_id)?;
+        self.genes_by_chrom
+            .get(&**chrom)?
+            .get(*index)
+            .filter(|gene| gene.gene_id == gene_id)
+    }
+
+    /// Genes of `chrom` overlapping `start..=end` (1-based, inclusive, like
+    /// GTF coordinates), in start order.
+    pub fn genes_in_range(&self, chrom: &str, start: i64, end: i64) -> Vec<&Gene> {
+        let (Some(genes), Some(index)) = (
+            self.genes_by_chrom.get(chrom),
+            self.interval_index.get(chrom),
+        ) else {
+            return Vec::new();
+        };
+
+        index
+            .query(genes, start, end)
+            .into_iter()
+            .map(|i| &genes[i])
+            .collect()
+    }
+
+    /// Length (end - start) of the longest gene on `chrom`, 0 if it has none.
+    pub fn max_length(&self, chrom: &str) -> i64 {
+        self.max_lengths.get(chrom).copied().unwrap_or(0)
+    }
+
+    /// Chromosomes with at least one gene, sorted by name.
+    pub fn chromosomes(&self) -> Vec<&str> {
+        let mut chroms: Vec<&str> = self
+            .genes_by_chrom
+            .iter()
+            .filter(|(_, genes)| !genes.is_empty())
+            .map(|(chrom, _)| chrom.as_str())
+            .collect();
+        chroms.sort_unstable();
+        chroms
+    }
+
+    /// Total number of genes.
+    pub fn num_genes(&self) -> usize {
+        self.genes_by_chrom.values().map(Vec::len).sum()
+    }
+
+    /// Number of genes annotated without a strand.
+    pub fn num_unstranded_genes(&self) -> usize {
+        self.genes_by_chrom
+            .values()
+            .flatten()
+            .filter(|gene| gene.unstranded)
+            .count()
+    }
+
+    /// Fail with a parse error on the first transcript ID found under more
+    /// than one gene, if any.
+    pub fn check_shared_transcript_ids(&self) -> Result<()> {
+        match self.shared_transcript_ids.first() {
+            Some(shared) => Err(Error::GtfParse {
+                line: shared.line,
+                reason: format!(
+                    "transcript {} belongs to gene {} but was first found under gene {}",
+                    shared.transcript_id, shared.gene_id, shared.first_gene_id
+                ),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of distinct transcript IDs found under more than one gene.
+    pub fn num_shared_transcript_ids(&self) -> usize {
+        let mut ids: Vec<&str> = self
+            .shared_transcript_ids
+            .iter()
+            .map(|shared| shared.transcript_id.as_str())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        ids.len()
+    }
+
+    /// Remove the transcripts `filters` exclude, and the genes left without
+    /// any transcript. Gene spans are kept.
+    ///
+    /// A transcript failing both filters counts for each of them, once in
+    /// the total.
+    pub fn filter_transcripts(&mut self, filters: &TranscriptFilters) -> TranscriptFilterCounts {
+        let mut counts = TranscriptFilterCounts::default();
+        for (chrom, genes) in &mut self.genes_by_chrom {
+            genes.retain_mut(|gene| {
+                let num_transcripts = gene.transcripts.len();
+                gene.transcripts.retain(|transcript| {
+                    let tagged = filters.keeps_tags(transcript);
+                    let supported = filters.keeps_support_level(transcript);
+                    counts.untagged += usize::from(!tagged);
+                    counts.above_max_tsl += usize::from(!supported);
+                    counts.transcripts += usize::from(!(tagged && supported));
+                    tagged && supported
+                });
+                let emptied = num_transcripts > 0 && gene.transcripts.is_empty();
+                counts.genes += usize::from(emptied);
+                !emptied
+            });
+            let max_len = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
+            self.max_lengths.insert(chrom.clone(), max_len);
+        }
+        self.genes_by_chrom.retain(|_, genes| !genes.is_empty());
+        self.sort_genes();
+        counts
+    }
+
+    /// Merge the overlapping, abutting or duplicated exons of each
+    /// transcript, as found in collapsed annotations, and renumber them.
+    ///
+    /// Returns the number of exon records merged into another.
+    pub fn merge_overlapping_exons(&mut self) -> usize {
+        let mut num_merged = 0;
+        for gene in self.genes_by_chrom.values_mut().flatten() {
+            let strand = gene.strand;
+            for transcript in &mut gene.transcripts {
+                let merged = transcript.merge_overlapping_exons();
+                if merged > 0 {
+                    transcript.renumber_exons(strand);
+                    num_merged += merged;
+                }
+            }
+        }
+        num_merged
+    }
+
+    /// Move the TSS of transcripts to their refined positions.
+    ///
+    /// A transcript takes the position of its own ID, or else of its gene's
+    /// ID, if it is on the gene's chromosome. The 5' end of its first exon
+    /// moves there, so the TSS, PROMOTER and 1st_EXON areas and the TSS
+    /// distance follow; gene spans grow to include it. Positions past the
+    /// 3' end of the first exon are ignored and the annotation TSS is kept.
+    ///
+    /// Returns the number of transcripts moved.
+    pub fn refine_tss(&mut self, tss: &TssPositions) -> usize {
+        let mut num_refined = 0;
+        for (chrom, genes) in &mut self.genes_by_chrom {
+            for gene in genes.iter_mut() {
+                for transcript in &mut gene.transcripts {
+                    let position = tss
+                        .get(&transcript.transcript_id)
+                        .or_else(|| tss.get(&gene.gene_id))
+                        .filter(|(tss_chrom, _)| tss_chrom == chrom)
+                        .map(|(_, pos)| pos);
+                    let (Some(pos), false) = (position, transcript.exons.is_empty()) else {
+                        continue;
+                    };
+                    let moved = match gene.strand {
+                        Strand::Positive => {
+                            let first = &mut transcript.exons[0];
+                            (pos <= first.end).then(|| first.start = pos)
+                        }
+                        Strand::Negative => {
+                            let first = transcript.exons.last_mut().unwrap();
+                            (pos >= first.start).then(|| first.end = pos)
+                        }
+                    };
+                    if moved.is_some() {
+                        transcript.build_index(gene.strand);
+                        transcript.start = i64::MAX;
+                        transcript.end = 0;
+                        transcript.calculate_size();
+                        gene.start = gene.start.min(transcript.start);
+                        gene.end = gene.end.max(transcript.end);
+                        num_refined += 1;
+                    }
+                }
+            }
+            let max_len = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
+            self.max_lengths.insert(chrom.clone(), max_len);
+        }
+        self.sort_genes();
+        num_refined
+    }
+}
+
+impl AnnotationSource for GtfData {
+    fn chromosomes(&self) -> Vec<&str> {
+        GtfData::chromosomes(self)
+    }
+
+    fn genes(&self, chrom: &str) -> &[Gene] {
+        self.genes_by_chrom.get(chrom).map_or(&[], Vec::as_slice)
+    }
+
+    fn max_gene_length(&self, chrom: &str) -> i64 {
+        self.max_length(chrom)
+    }
+
+    fn first_gene_reaching(&self, chrom: &str, pos: i64) -> usize {
+        self.max_ends
+            .get(chrom)
+            .map_or(0, |max_ends| first_gene_ending_after(max_ends, pos))
+    }
+
+    fn genes_in_range(&self, chrom: &str, start: i64, end: i64) -> Vec<&Gene> {
+        GtfData::genes_in_range(self, chrom, start, end)
+    }
 }
 
 /// Parse a GTF file and return organized gene data.
 ///
 /// Supports both plain text and gzip-compressed GTF files.
+#[cfg(not(target_arch = "wasm32"))]
 pub fn parse_gtf(path: &Path, gene_id_tag: &str, transcript_id_tag: &str) -> Result<GtfData> {
-    let file = File::open(path).context("Failed to open GTF file")?;
+    let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+    let mut size = file.metadata().map_or(0, |metadata| metadata.len());
+    if path.to_string_lossy().ends_with(".gz") {
+        size = size.saturating_mul(GZIP_RATIO);
+    }
     let reader = create_buffered_reader(file, path);
 
-    parse_gtf_reader(reader, gene_id_tag, transcript_id_tag)
+    parse_gtf_sized(reader, gene_id_tag, transcript_id_tag, size).map_err(|e| e.in_file(path))
 }
 
-/// Parse GTF data from a reader.
-fn parse_gtf_reader<R: BufRead>(
+/// Typical size ratio of a gzip-compressed GTF file to its text, kept on the
+/// low side since it only sizes the parser's maps.
+#[cfg(not(target_arch = "wasm32"))]
+const GZIP_RATIO: u64 = 10;
+
+/// Bytes of GTF text per gene and per transcript in GENCODE, rounded down,
+/// to size the parser's maps from the file size.
+const BYTES_PER_GENE: u64 = 16_384;
+const BYTES_PER_TRANSCRIPT: u64 = 4_096;
+
+/// Parse GTF data from any buffered reader.
+///
+/// The data must already be decompressed; [`parse_gtf`] handles gzip files.
+pub fn parse_gtf_from_reader<R: BufRead>(
     reader: R,
     gene_id_tag: &str,
     transcript_id_tag: &str,
 ) -> Result<GtfData> {
-    // Maps to track all genes and transcripts
-    let mut all_genes: AHashMap<String, Gene> = AHashMap::new();
-    let mut all_transcripts: AHashMap<String, usize> = AHashMap::new(); // transcript_id -> index in gene
-    let mut gene_to_transcripts: AHashMap<String, Vec<String>> = AHashMap::new(); // gene_id -> transcript_ids
+    parse_gtf_sized(reader, gene_id_tag, transcript_id_tag, 0)
+}
 
-    // Genes organized by chromosome
-    let mut genes_by_chrom: AHashMap<String, Vec<String>> = AHashMap::new(); // chrom -> gene_ids (in order added)
+/// [`parse_gtf_from_reader`] with the maps sized for `size` bytes of GTF
+/// text; 0 if unknown.
+fn parse_gtf_sized<R: BufRead>(
+    mut reader: R,
+    gene_id_tag: &str,
+    transcript_id_tag: &str,
+    size: u64,
+) -> Result<GtfData> {
+    let num_genes = (size / BYTES_PER_GENE) as usize;
+    let num_transcripts = (size / BYTES_PER_TRANSCRIPT) as usize;
 
-    // Flags to track if transcript and gene entries exist in GTF
-    let mut gene_flag = false;
-    let mut trans_flag = false;
+    // Gene and transcript IDs and chromosome names are interned, so every
+    // map key, gene, transcript and candidate shares one copy of each
+    let mut names = NamePool::new();
 
-    for line_result in reader.lines() {
-        let line = line_result.context("Failed to read GTF line")?;
+    // Maps to track all genes and transcripts
+    let mut all_genes: AHashMap<Name, Gene> = AHashMap::with_capacity(num_genes);
+    // Transcripts are keyed by gene as well, so that a transcript ID reused
+    // by another gene does not add exons to the first gene's transcript
+    let mut all_transcripts: AHashMap<(Name, Name), usize> =
+        AHashMap::with_capacity(num_transcripts); // (gene_id, transcript_id) -> index in gene
+    let mut transcript_genes: AHashMap<Name, Name> = AHashMap::with_capacity(num_transcripts); // transcript_id -> first gene_id
+    let mut shared_transcript_ids: Vec<SharedTranscriptId> = Vec::new();
+
+    // Coding extents of transcripts: transcript_id -> (first, last) coding base
+    // from CDS lines, and the extents of their 5' and 3' UTR lines for
+    // annotations that give the UTRs only
+    let mut cds_extents: AHashMap<(Name, Name), (i64, i64)> =
+        AHashMap::with_capacity(num_transcripts);
+    let mut utr5_extents: AHashMap<(Name, Name), (i64, i64)> = AHashMap::new();
+    let mut utr3_extents: AHashMap<(Name, Name), (i64, i64)> = AHashMap::new();
 
-        // Skip empty lines and comments
-        if line.is_empty() || line.starts_with('#') {
-            continue;
+    // Genes organized by chromosome
+    let mut genes_by_chrom: AHashMap<Name, Vec<Name>> = AHashMap::new(); // chrom -> gene_ids (in order added)
+
+    // One buffer for every line, and only the 9 GTF columns split out
+    let mut line = String::new();
+    let mut index = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
         }
+        index += 1;
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let parse_error = |reason: String| Error::GtfParse {
+            line: index,
+            reason,
+        };
 
-        let fields: Vec<&str> = line.split('\t').collect();
-        if fields.len() < 9 {
+        // Skip comments, empty lines and short lines
+        if line.starts_with('#') {
             continue;
         }
+        let fields: [&str; 9] = match leading_fields(line) {
+            Ok(fields) => fields,
+            Err(0) => continue,
+            Err(count) => {
+                trace!(
+                    "GTF line {}: skipped, {} columns instead of 9",
+                    index,
+                    count
+                );
+                continue;
+            }
+        };
 
         let chrom = fields[0];
         let feature_type = fields[2];
         let start: i64 = fields[3]
             .parse()
-            .context("Failed to parse start coordinate")?;
+            .map_err(|_| parse_error(format!("start '{}' is not an integer", fields[3])))?;
         let end: i64 = fields[4]
             .parse()
-            .context("Failed to parse end coordinate")?;
+            .map_err(|_| parse_error(format!("end '{}' is not an integer", fields[4])))?;
+        check_coordinate("start", start, false).map_err(parse_error)?;
+        check_coordinate("end", end, false).map_err(parse_error)?;
         let strand_str = fields[6];
         let attributes = fields[8];
 
+        // Unstranded features are kept on the + strand and flagged
+        let unstranded = strand_str == ".";
         let strand = match strand_str.parse::<Strand>() {
             Ok(s) => s,
+            Err(_) if unstranded => Strand::Positive,
             Err(_) => continue, // Skip entries without valid strand
         };
 
         match feature_type {
-            "exon" => {
+            "exon" | "transcript" | "gene" => {
+                let missing = |tag: &str| {
+                    parse_error(format!("{} without a {} attribute", feature_type, tag))
+                };
                 let gene_id = extract_attribute(attributes, gene_id_tag)
-                    .context("Failed to extract gene_id from exon")?;
-                let transcript_id = extract_attribute(attributes, transcript_id_tag)
-                    .context("Failed to extract transcript_id from exon")?;
+                    .map(|id| names.intern(id))
+                    .ok_or_else(|| missing(gene_id_tag))?;
 
                 // Create or get gene
-                if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
+                let gene = all_genes.entry(gene_id.clone()).or_insert_with(|| {
                     genes_by_chrom
-                        .entry(chrom.to_string())
+                        .entry(names.intern(chrom))
                         .or_default()
                         .push(gene_id.clone());
+                    let mut gene = Gene::new(gene_id.clone(), strand);
+                    gene.unstranded = unstranded;
+                    gene
+                });
+                if feature_type == "gene" {
+                    // Set gene boundaries
+                    gene.set_length(start, end);
+                    continue;
                 }
 
-                // Create or get transcript
-                let is_new_transcript = !all_transcripts.contains_key(&transcript_id);
-                if is_new_transcript {
-                    let gene = all_genes.get_mut(&gene_id).unwrap();
-                    let transcript_idx = gene.transcripts.len();
-                    gene.add_transcript(Transcript::new(transcript_id.clone()));
-                    all_transcripts.insert(transcript_id.clone(), transcript_idx);
-                    gene_to_transcripts
-                        .entry(gene_id.clone())
-                        .or_default()
-                        .push(transcript_id.clone());
-                }
-
-                // Add exon to transcript
-                let exon = Exon::new(start, end);
-                let transcript_idx = all_transcripts[&transcript_id];
-                let gene = all_genes.get_mut(&gene_id).unwrap();
-                gene.transcripts[transcript_idx].add_exon(exon);
-            }
-            "transcript" => {
-                trans_flag = true;
-
-                let gene_id = extract_attribute(attributes, gene_id_tag)
-                    .context("Failed to extract gene_id from transcript")?;
                 let transcript_id = extract_attribute(attributes, transcript_id_tag)
-                    .context("Failed to extract transcript_id from transcript")?;
-
-                // Create or get gene
-                if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
-                    genes_by_chrom
-                        .entry(chrom.to_string())
-                        .or_default()
-                        .push(gene_id.clone());
-                }
+                    .map(|id| names.intern(id))
+                    .ok_or_else(|| missing(transcript_id_tag))?;
 
                 // Create or get transcript
-                let is_new_transcript = !all_transcripts.contains_key(&transcript_id);
-                if is_new_transcript {
-                    let gene = all_genes.get_mut(&gene_id).unwrap();
-                    let transcript_idx = gene.transcripts.len();
-                    gene.add_transcript(Transcript::new(transcript_id.clone()));
-                    all_transcripts.insert(transcript_id.clone(), transcript_idx);
-                    gene_to_transcripts
-                        .entry(gene_id.clone())
-                        .or_default()
-                        .push(transcript_id.clone());
+                let transcript_idx =
+                    match all_transcripts.entry((gene_id.clone(), transcript_id.clone())) {
+                        Entry::Occupied(entry) => *entry.get(),
+                        Entry::Vacant(entry) => {
+                            let first_gene_id = transcript_genes
+                                .entry(transcript_id.clone())
+                                .or_insert_with(|| gene_id.clone());
+                            if *first_gene_id != gene_id {
+                                shared_transcript_ids.push(SharedTranscriptId {
+                                    transcript_id: transcript_id.to_string(),
+                                    first_gene_id: first_gene_id.to_string(),
+                                    gene_id: gene_id.to_string(),
+                                    line: index,
+                                });
+                            }
+                            gene.add_transcript(Transcript::new(transcript_id));
+                            *entry.insert(gene.transcripts.len() - 1)
+                        }
+                    };
+
+                let transcript = &mut gene.transcripts[transcript_idx];
+                if feature_type == "exon" {
+                    transcript.add_exon(Exon::new(start, end));
+                } else {
+                    // Set transcript boundaries
+                    transcript.set_length(start, end);
                 }
-
-                // Set transcript boundaries
-                let transcript_idx = all_transcripts[&transcript_id];
-                let gene = all_genes.get_mut(&gene_id).unwrap();
-                gene.transcripts[transcript_idx].set_length(start, end);
+                record_transcript_attributes(transcript, attributes);
             }
-            "gene" => {
-                gene_flag = true;
-
-                let gene_id = extract_attribute(attributes, gene_id_tag)
-                    .context("Failed to extract gene_id from gene")?;
-
-                // Create or get gene
-                if !all_genes.contains_key(&gene_id) {
-                    all_genes.insert(gene_id.clone(), Gene::new(gene_id.clone(), strand));
-                    genes_by_chrom
-                        .entry(chrom.to_string())
-                        .or_default()
-                        .push(gene_id.clone());
-                }
-
-                // Set gene boundaries
-                all_genes.get_mut(&gene_id).unwrap().set_length(start, end);
+            "CDS" | "five_prime_utr" | "three_prime_utr" => {
+                let (Some(gene_id), Some(transcript_id)) = (
+                    extract_attribute(attributes, gene_id_tag),
+                    extract_attribute(attributes, transcript_id_tag),
+                ) else {
+                    continue;
+                };
+                let extents = match feature_type {
+                    "CDS" => &mut cds_extents,
+                    "five_prime_utr" => &mut utr5_extents,
+                    _ => &mut utr3_extents,
+                };
+                let extent = extents
+                    .entry((names.intern(gene_id), names.intern(transcript_id)))
+                    .or_insert((start, end));
+                extent.0 = extent.0.min(start);
+                extent.1 = extent.1.max(end);
             }
             _ => {
                 // Skip other feature types
@@ -175,20 +526,32 @@ fn parse_gtf_reader<R: BufRead>(
     // Post-processing: check exon numbers and calculate sizes
     for gene in all_genes.values_mut() {
         let strand = gene.strand;
+        let gene_id = &gene.gene_id;
         for transcript in &mut gene.transcripts {
             // Renumber exons based on strand
             transcript.renumber_exons(strand);
 
-            // Calculate transcript size if not set from transcript entry
-            if !trans_flag {
+            // Calculate transcript size if not set from a transcript entry;
+            // annotations may give them for some transcripts only
+            if transcript.start == i64::MAX {
                 transcript.calculate_size();
             }
+
+            let key = (gene_id.clone(), transcript.transcript_id.clone());
+            transcript.cds = cds_extents.get(&key).copied().or_else(|| {
+                coding_extent_from_utrs(
+                    transcript,
+                    strand,
+                    utr5_extents.get(&key).copied(),
+                    utr3_extents.get(&key).copied(),
+                )
+            });
         }
     }
 
-    // Calculate gene sizes if not set from gene entries
-    if !gene_flag {
-        for gene in all_genes.values_mut() {
+    // Calculate gene sizes if not set from a gene entry
+    for gene in all_genes.values_mut() {
+        if gene.start == i64::MAX {
             gene.calculate_size();
         }
     }
@@ -204,36 +567,93 @@ fn parse_gtf_reader<R: BufRead>(
             .collect();
 
         let max_len = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
-        max_lengths.insert(chrom.clone(), max_len);
+        max_lengths.insert(chrom.to_string(), max_len);
 
-        result_genes.insert(chrom, genes);
+        result_genes.insert(chrom.into(), genes);
     }
 
-    Ok(GtfData {
+    let mut data = GtfData {
         genes_by_chrom: result_genes,
         max_lengths,
-    })
+        gene_index: AHashMap::new(),
+        interval_index: AHashMap::new(),
+        max_ends: AHashMap::new(),
+        transcript_filtering: None,
+        shared_transcript_ids,
+    };
+    data.sort_genes();
+    Ok(data)
 }
 
-/// Extract an attribute value from the GTF attributes string.
+/// Coding extent of a transcript whose annotation gives its UTRs but no CDS:
+/// the exonic span between the 5' and the 3' UTR extents.
+///
+/// `None` without any UTR, or if the UTRs leave no base in between.
+fn coding_extent_from_utrs(
+    transcript: &Transcript,
+    strand: Strand,
+    utr5: Option<(i64, i64)>,
+    utr3: Option<(i64, i64)>,
+) -> Option<(i64, i64)> {
+    if utr5.is_none() && utr3.is_none() {
+        return None;
+    }
+    let (left, right) = match strand {
+        Strand::Positive => (utr5, utr3),
+        Strand::Negative => (utr3, utr5),
+    };
+    let exon_start = transcript.exons.iter().map(|e| e.start).min()?;
+    let exon_end = transcript.exons.iter().map(|e| e.end).max()?;
+    let first = left.map_or(exon_start, |(_, end)| end + 1);
+    let last = right.map_or(exon_end, |(start, _)| start - 1);
+    (first <= last).then_some((first, last))
+}
+
+/// Extract an attribute value from the GTF attributes string, borrowed
+/// from it.
 ///
 /// GTF attributes are in the format: key "value"; key "value"; ...
-fn extract_attribute(attributes: &str, key: &str) -> Option<String> {
-    // Find the key
-    let key_pattern = format!("{} ", key);
-    let start_idx = attributes.find(&key_pattern)?;
+/// The value is the text between the first two quotes after the first
+/// `key` followed by a space.
+fn extract_attribute<'a>(attributes: &'a str, key: &str) -> Option<&'a str> {
+    // Find the key, followed by a space
+    let mut from = 0;
+    let after_key = loop {
+        let start_idx = from + attributes[from..].find(key)?;
+        let after_key = &attributes[start_idx + key.len()..];
+        if let Some(after_space) = after_key.strip_prefix(' ') {
+            break after_space;
+        }
+        from = start_idx + attributes[start_idx..].chars().next()?.len_utf8();
+    };
 
     // Find the value between quotes
-    let after_key = &attributes[start_idx + key_pattern.len()..];
-
-    // Find first quote
     let first_quote = after_key.find('"')?;
     let after_first_quote = &after_key[first_quote + 1..];
-
-    // Find second quote
     let second_quote = after_first_quote.find('"')?;
 
-    Some(after_first_quote[..second_quote].to_string())
+    Some(&after_first_quote[..second_quote])
+}
+
+/// Add the `tag` values and `transcript_support_level` of the GTF
+/// attributes of a transcript or exon line to `transcript`.
+fn record_transcript_attributes(transcript: &mut Transcript, attributes: &str) {
+    if attributes.contains("tag ") {
+        for attribute in attributes.split(';') {
+            let Some(("tag", value)) = attribute.trim().split_once(' ') else {
+                continue;
+            };
+            let tag = value.trim().trim_matches('"');
+            if !transcript.tags.iter().any(|t| t == tag) {
+                transcript.tags.push(tag.to_string());
+            }
+        }
+    }
+    if transcript.support_level.is_none() && attributes.contains("transcript_support_level ") {
+        // Values such as "1 (assigned to previous version 5)" keep the level
+        transcript.support_level = extract_attribute(attributes, "transcript_support_level")
+            .and_then(|value| value.split_whitespace().next()?.parse().ok());
+    }
 }
 
 #[cfg(test)]
@@ -247,21 +667,43 @@ mod tests {
 
         assert_eq!(
             extract_attribute(attrs, "gene_id"),
-            Some("ENSG00000279493.1".to_string())
+            Some("ENSG00000279493.1")
         );
         assert_eq!(
             extract_attribute(attrs, "transcript_id"),
-            Some("ENST00000624081.1".to_string())
+            Some("ENST00000624081.1")
+        );
+        assert_eq!(extract_attribute(attrs, "gene_type"), Some("artifact"));
+        assert_eq!(extract_attribute(attrs, "nonexistent"), None);
+    }
+
+    #[test]
+    fn test_extract_attribute_malformed() {
+        // Key without a following space, or only as part of another key
+        assert_eq!(extract_attribute(r#"gene_id"G1";"#, "gene_id"), None);
+        assert_eq!(
+            extract_attribute(r#"gene_id_version "2"; gene_id "G1";"#, "gene_id"),
+            Some("G1")
         );
+        // Missing or unterminated quotes
+        assert_eq!(extract_attribute("gene_id G1;", "gene_id"), None);
+        assert_eq!(extract_attribute(r#"gene_id "G1;"#, "gene_id"), None);
+        // Empty value, and values with spaces or semicolons
+        assert_eq!(extract_attribute(r#"gene_id "";"#, "gene_id"), Some(""));
         assert_eq!(
-            extract_attribute(attrs, "gene_type"),
-            Some("artifact".to_string())
+            extract_attribute(r#"gene_id  "G 1;2" ;"#, "gene_id"),
+            Some("G 1;2")
         );
-        assert_eq!(extract_attribute(attrs, "nonexistent"), None);
+        // No trailing semicolon, non-ASCII text before the key
+        assert_eq!(
+            extract_attribute(r#"note "é"; gene_id "G1""#, "gene_id"),
+            Some("G1")
+        );
+        assert_eq!(extract_attribute("", "gene_id"), None);
     }
 
     #[test]
-    fn test_parse_gtf_reader() {
+    fn test_parse_gtf_from_reader() {
         let gtf_content = r#"##description: test
 chr1	TEST	gene	1000	2000	.	+	.	gene_id "G1"; gene_name "Gene1";
 chr1	TEST	transcript	1000	2000	.	+	.	gene_id "G1"; transcript_id "T1";
@@ -270,7 +712,7 @@ chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1"; exon_number 2;
 "#;
 
         let reader = BufReader::new(gtf_content.as_bytes());
-        let result = parse_gtf_reader(reader, "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_from_reader(reader, "gene_id", "transcript_id").unwrap();
 
         assert!(result.genes_by_chrom.contains_key("chr1"));
         let genes = &result.genes_by_chrom["chr1"];
@@ -287,9 +729,68 @@ chr1	TEST	exon	1500	2000	.	+	.	gene_id "G1"; transcript_id "T1"; exon_number 2;
 
         // Check exon numbering for positive strand
         assert_eq!(transcript.exons[0].start, 1000);
-        assert_eq!(transcript.exons[0].exon_number, Some("1".to_string()));
+        assert_eq!(transcript.exons[0].exon_number, Some(1));
         assert_eq!(transcript.exons[1].start, 1500);
-        assert_eq!(transcript.exons[1].exon_number, Some("2".to_string()));
+        assert_eq!(transcript.exons[1].exon_number, Some(2));
+    }
+
+    #[test]
+    fn test_parse_gtf_interns_ids() {
+        let gtf_content =
+            "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1500\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\tCDS\t1100\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";
+";
+        let data =
+            parse_gtf_from_reader(gtf_content.as_bytes(), "gene_id", "transcript_id").unwrap();
+        let gene = data.gene_by_id("G1").unwrap();
+        assert_eq!(gene.transcripts[0].cds, Some((1100, 1200)));
+
+        // Candidates share the gene's IDs instead of copying them
+        let config = crate::Config::default();
+        let region = crate::Region::new("chr1".into(), 1100, 1150, vec![]);
+        let candidates =
+            crate::matcher::match_region(&region, &data.genes_by_chrom["chr1"], &config, 0)
+                .candidates;
+        assert!(!candidates.is_empty());
+        for candidate in &candidates {
+            assert_eq!(candidate.gene.as_ptr(), gene.gene_id.as_ptr());
+            let transcript = gene
+                .transcripts
+                .iter()
+                .find(|t| t.transcript_id == candidate.transcript)
+                .unwrap();
+            assert_eq!(
+                candidate.transcript.as_ptr(),
+                transcript.transcript_id.as_ptr()
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_gtf_coding_extents() {
+        let gtf_content = "\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\tCDS\t1100\t1200\t.\t+\t0\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1500\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\tCDS\t1500\t1800\t.\t+\t1\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";
+chr1\tTEST\texon\t5000\t6000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T3\";
+chr1\tTEST\tfive_prime_utr\t5900\t6000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T3\";
+chr1\tTEST\tthree_prime_utr\t5000\t5099\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T3\";
+";
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        let result = parse_gtf_from_reader(reader, "gene_id", "transcript_id").unwrap();
+
+        let g1 = result.gene_by_id("G1").unwrap();
+        assert_eq!(g1.transcripts[0].cds, Some((1100, 1800)));
+        // Non-coding transcript
+        assert_eq!(g1.transcripts[1].cds, None);
+        // UTRs only, on the - strand
+        let g2 = result.gene_by_id("G2").unwrap();
+        assert_eq!(g2.transcripts[0].cds, Some((5100, 5899)));
     }
 
     #[test]
@@ -299,15 +800,213 @@ chr1	TEST	exon	1500	2000	.	-	.	gene_id "G1"; transcript_id "T1";
 "#;
 
         let reader = BufReader::new(gtf_content.as_bytes());
-        let result = parse_gtf_reader(reader, "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_from_reader(reader, "gene_id", "transcript_id").unwrap();
 
         let gene = &result.genes_by_chrom["chr1"][0];
         let transcript = &gene.transcripts[0];
 
         // For negative strand: first (lowest) gets N, last (highest) gets 1
         assert_eq!(transcript.exons[0].start, 1000);
-        assert_eq!(transcript.exons[0].exon_number, Some("2".to_string()));
+        assert_eq!(transcript.exons[0].exon_number, Some(2));
         assert_eq!(transcript.exons[1].start, 1500);
-        assert_eq!(transcript.exons[1].exon_number, Some("1".to_string()));
+        assert_eq!(transcript.exons[1].exon_number, Some(1));
+    }
+
+    #[test]
+    fn test_parse_gtf_reader_invalid_coordinate() {
+        let gtf_content = "# comment\nchr1\tTEST\texon\t1000\tx\t.\t+\t.\tgene_id \"G1\";\n";
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        match parse_gtf_from_reader(reader, "gene_id", "transcript_id") {
+            Err(Error::GtfParse { line, reason }) => {
+                assert_eq!(line, 2);
+                assert_eq!(reason, "end 'x' is not an integer");
+            }
+            other => panic!("expected a GtfParse error, got {:?}", other.is_ok()),
+        }
+
+        for (start, end) in [("-5", "100"), ("1000", "9223372036854775807")] {
+            let gtf_content = format!(
+                "chr1\tTEST\texon\t{}\t{}\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n",
+                start, end
+            );
+            let reader = BufReader::new(gtf_content.as_bytes());
+            match parse_gtf_from_reader(reader, "gene_id", "transcript_id") {
+                Err(Error::GtfParse { line, reason }) => {
+                    assert_eq!(line, 1);
+                    assert!(reason.contains("out of the supported coordinate range"));
+                }
+                other => panic!("expected a GtfParse error, got {:?}", other.is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_gtf_data_queries() {
+        // G_LONG starts far left of the query window but reaches into it
+        let gtf_content = "\
+chr1\tTEST\tgene\t1000\t900000\t.\t+\t.\tgene_id \"G_LONG\";
+chr1\tTEST\tgene\t500000\t501000\t.\t-\t.\tgene_id \"G_INSIDE\";
+chr1\tTEST\tgene\t600000\t601000\t.\t+\t.\tgene_id \"G_RIGHT\";
+chr1\tTEST\tgene\t5000\t6000\t.\t+\t.\tgene_id \"G_LEFT\";
+chr2\tTEST\tgene\t100\t200\t.\t+\t.\tgene_id \"G_CHR2\";
+";
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        let data = parse_gtf_from_reader(reader, "gene_id", "transcript_id").unwrap();
+
+        assert_eq!(data.num_genes(), 5);
+        assert_eq!(data.chromosomes(), vec!["chr1", "chr2"]);
+        assert_eq!(data.max_length("chr1"), 899000);
+        assert_eq!(data.max_length("chrM"), 0);
+
+        let ids = |genes: Vec<&Gene>| -> Vec<String> {
+            genes.into_iter().map(|g| g.gene_id.to_string()).collect()
+        };
+        assert_eq!(
+            ids(data.genes_in_range("chr1", 500500, 550000)),
+            vec!["G_LONG", "G_INSIDE"]
+        );
+        // Both bounds are inclusive
+        assert_eq!(
+            ids(data.genes_in_range("chr1", 6000, 6000)),
+            vec!["G_LONG", "G_LEFT"]
+        );
+        assert_eq!(
+            ids(data.genes_in_range("chr1", 1, 999)),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            ids(data.genes_in_range("chr1", 900001, 2000000)),
+            Vec::<String>::new()
+        );
+        assert!(data.genes_in_range("chrM", 1, 1000).is_empty());
+
+        assert_eq!(data.gene_by_id("G_RIGHT").unwrap().start, 600000);
+        assert_eq!(data.gene_by_id("G_CHR2").unwrap().strand, Strand::Positive);
+        assert!(data.gene_by_id("G_MISSING").is_none());
+    }
+
+    #[test]
+    fn test_gene_by_id_after_resort() {
+        let gtf_content = "\
+chr1\tTEST\tgene\t1000\t2000\t.\t+\t.\tgene_id \"G1\";
+chr1\tTEST\tgene\t3000\t4000\t.\t+\t.\tgene_id \"G2\";
+";
+
+        let reader = BufReader::new(gtf_content.as_bytes());
+        let mut data = parse_gtf_from_reader(reader, "gene_id", "transcript_id").unwrap();
+
+        // Move G2 before G1; the index is stale until the genes are sorted again
+        let genes = data.genes_by_chrom.get_mut("chr1").unwrap();
+        genes[1].start = 500;
+        genes[1].end = 800;
+        data.sort_genes();
+
+        assert_eq!(data.gene_by_id("G2").unwrap().start, 500);
+        assert_eq!(data.gene_by_id("G1").unwrap().start, 1000);
+    }
+
+    #[test]
+    fn test_refine_tss() {
+        let gtf_content = "\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1500\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t3000\t3200\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";
+chr1\tTEST\texon\t3500\t4000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";
+chr1\tTEST\texon\t5000\t5200\t.\t+\t.\tgene_id \"G3\"; transcript_id \"T3\";
+";
+        let mut data = parse_gtf_from_reader(
+            BufReader::new(gtf_content.as_bytes()),
+            "gene_id",
+            "transcript_id",
+        )
+        .unwrap();
+        // By transcript ID, by gene ID, and on another chromosome
+        let tss_bed = "chr1\t899\t900\tT1\nchr1\t3899\t3900\tG2\nchr2\t4999\t5000\tT3\n";
+        let tss = crate::parser::parse_tss_bed_from_reader(tss_bed.as_bytes()).unwrap();
+
+        assert_eq!(data.refine_tss(&tss), 2);
+        let g1 = data.gene_by_id("G1").unwrap();
+        assert_eq!((g1.start, g1.transcripts[0].start), (900, 900));
+        assert_eq!(g1.transcripts[0].exons[0].start, 900);
+        let g2 = data.gene_by_id("G2").unwrap();
+        assert_eq!(g2.transcripts[0].end, 3900);
+        assert_eq!(g2.transcripts[0].exons[1].end, 3900);
+        assert_eq!(data.gene_by_id("G3").unwrap().start, 5000);
+        assert_eq!(data.max_length("chr1"), 1100);
+    }
+
+    #[test]
+    fn test_transcript_tags() {
+        let gtf_content = "\
+chr1\tTEST\ttranscript\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; tag \"basic\"; tag \"Ensembl_canonical\"; transcript_support_level \"1\";
+chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; tag \"basic\"; tag \"Ensembl_canonical\"; transcript_support_level \"1\";
+chr1\tTEST\texon\t1500\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\"; transcript_support_level \"3 (assigned to previous version 2)\";
+chr1\tTEST\texon\t3000\t4000\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T3\"; tag \"MANE_Select\"; transcript_support_level \"NA\";
+";
+        let mut data = parse_gtf_from_reader(
+            BufReader::new(gtf_content.as_bytes()),
+            "gene_id",
+            "transcript_id",
+        )
+        .unwrap();
+        let g1 = data.gene_by_id("G1").unwrap();
+        assert_eq!(g1.transcripts[0].tags, ["basic", "Ensembl_canonical"]);
+        assert!(g1.transcripts[0].is_canonical());
+        assert_eq!(g1.transcripts[0].support_level, Some(1));
+        assert!(g1.transcripts[1].tags.is_empty());
+        assert!(!g1.transcripts[1].is_canonical());
+        assert_eq!(g1.transcripts[1].support_level, Some(3));
+        let g2 = data.gene_by_id("G2").unwrap();
+        assert!(g2.transcripts[0].is_canonical());
+        assert_eq!(g2.transcripts[0].support_level, None);
+
+        let filters = TranscriptFilters {
+            only_tagged: vec!["basic".to_string()],
+            max_tsl: Some(2),
+        };
+        let counts = data.filter_transcripts(&filters);
+        assert_eq!(
+            counts,
+            TranscriptFilterCounts {
+                untagged: 2,
+                above_max_tsl: 2,
+                transcripts: 2,
+                genes: 1,
+            }
+        );
+        assert_eq!(data.num_genes(), 1);
+        assert_eq!(data.gene_by_id("G1").unwrap().transcripts.len(), 1);
+        assert!(data.gene_by_id("G2").is_none());
+    }
+
+    #[test]
+    fn test_merge_overlapping_exons() {
+        // A duplicated exon line, an overlapping and an abutting exon
+        let gtf_content = "\
+chr1\tTEST\texon\t1000\t1200\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1000\t1200\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1100\t1300\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1500\t1600\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t1601\t1700\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t2000\t2100\t.\t-\t.\tgene_id \"G1\"; transcript_id \"T1\";
+";
+        let mut data = parse_gtf_from_reader(
+            BufReader::new(gtf_content.as_bytes()),
+            "gene_id",
+            "transcript_id",
+        )
+        .unwrap();
+        assert_eq!(data.gene_by_id("G1").unwrap().transcripts[0].exons.len(), 6);
+
+        assert_eq!(data.merge_overlapping_exons(), 3);
+        let exons: Vec<_> = data.gene_by_id("G1").unwrap().transcripts[0]
+            .exons
+            .iter()
+            .map(|e| (e.start, e.end, e.exon_number.unwrap()))
+            .collect();
+        assert_eq!(exons, [(1000, 1300, 3), (1500, 1700, 2), (2000, 2100, 1),]);
+        assert_eq!(data.merge_overlapping_exons(), 0);
     }
 }
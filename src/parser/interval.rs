@@ -0,0 +1,137 @@
+//! Interval index over the genes of a chromosome.
+//!
+//! Genes are kept sorted by start, so an overlap query only needs the gene
+//! ends: the index is an implicit segment tree holding the maximum end of
+//! every node's genes. Subtrees whose maximum end falls before the query are
+//! skipped, so a few very long genes no longer force a scan over all the
+//! genes they span.
+
+use crate::types::Gene;
+
+/// Maximum gene end per node of a segment tree over start-sorted genes.
+#[derive(Debug, Clone, Default)]
+pub struct IntervalIndex {
+    max_end: Vec<i64>,
+    len: usize,
+}
+
+impl IntervalIndex {
+    /// Build the index over `genes`, which must be sorted by start.
+    pub fn new(genes: &[Gene]) -> Self {
+        let mut index = IntervalIndex {
+            max_end: vec![i64::MIN; 4 * genes.len().max(1)],
+            len: genes.len(),
+        };
+        if !genes.is_empty() {
+            index.build(genes, 1, 0, genes.len());
+        }
+        index
+    }
+
+    fn build(&mut self, genes: &[Gene], node: usize, lo: usize, hi: usize) -> i64 {
+        let max_end = if hi - lo == 1 {
+            genes[lo].end
+        } else {
+            let mid = (lo + hi) / 2;
+            let left = self.build(genes, 2 * node, lo, mid);
+            let right = self.build(genes, 2 * node + 1, mid, hi);
+            left.max(right)
+        };
+        self.max_end[node] = max_end;
+        max_end
+    }
+
+    /// Indices, in start order, of the genes overlapping `start..=end`.
+    ///
+    /// `genes` must be the slice the index was built from.
+    pub fn query(&self, genes: &[Gene], start: i64, end: i64) -> Vec<usize> {
+        debug_assert_eq!(genes.len(), self.len, "index built over other genes");
+
+        // Genes from `limit` on start after the query
+        let limit = genes.partition_point(|g| g.start <= end);
+        let mut hits = Vec::new();
+        if limit > 0 {
+            self.collect(1, 0, self.len, limit, start, &mut hits);
+        }
+        hits
+    }
+
+    fn collect(
+        &self,
+        node: usize,
+        lo: usize,
+        hi: usize,
+        limit: usize,
+        start: i64,
+        hits: &mut Vec<usize>,
+    ) {
+        if lo >= limit || self.max_end[node] < start {
+            return;
+        }
+        if hi - lo == 1 {
+            hits.push(lo);
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        self.collect(2 * node, lo, mid, limit, start, hits);
+        self.collect(2 * node + 1, mid, hi, limit, start, hits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn gene(start: i64, end: i64) -> Gene {
+        let mut gene = Gene::new(format!("G{}", start), Strand::Positive);
+        gene.set_length(start, end);
+        gene
+    }
+
+    #[test]
+    fn test_query_matches_linear_scan() {
+        let genes: Vec<Gene> = [
+            (100, 200),
+            (150, 5_000_000),
+            (300, 400),
+            (1000, 1100),
+            (1000, 1500),
+            (2000, 2100),
+            (9000, 9100),
+        ]
+        .iter()
+        .map(|&(s, e)| gene(s, e))
+        .collect();
+        let index = IntervalIndex::new(&genes);
+
+        for (start, end) in [
+            (0, 50),
+            (0, 100),
+            (250, 260),
+            (1050, 2050),
+            (9100, 9100),
+            (10_000, 20_000),
+        ] {
+            let expected: Vec<usize> = genes
+                .iter()
+                .enumerate()
+                .filter(|(_, g)| g.start <= end && g.end >= start)
+                .map(|(i, _)| i)
+                .collect();
+            assert_eq!(
+                index.query(&genes, start, end),
+                expected,
+                "{}-{}",
+                start,
+                end
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_index() {
+        let index = IntervalIndex::new(&[]);
+        assert!(index.query(&[], 0, 100).is_empty());
+    }
+}
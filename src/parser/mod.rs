@@ -2,7 +2,112 @@
 
 pub mod bed;
 pub mod gtf;
+pub mod interval;
+pub mod tss_bed;
+#[cfg(not(target_arch = "wasm32"))]
 pub mod util;
 
-pub use bed::{parse_bed, BedReader};
-pub use gtf::{parse_gtf, GtfData};
+/// Largest coordinate accepted in input files (2^40 bp, far beyond any
+/// genome), which keeps coordinate arithmetic clear of overflow.
+pub const MAX_COORDINATE: i64 = 1 << 40;
+
+/// Check that a coordinate of the named column is within the supported
+/// range, returning the reason for the parse error otherwise.
+fn check_coordinate(
+    column: &str,
+    value: i64,
+    allow_negative: bool,
+) -> std::result::Result<i64, String> {
+    let min = if allow_negative { -MAX_COORDINATE } else { 0 };
+    if (min..=MAX_COORDINATE).contains(&value) {
+        Ok(value)
+    } else {
+        Err(format!(
+            "{} {} is out of the supported coordinate range ({} to {})",
+            column, value, min, MAX_COORDINATE
+        ))
+    }
+}
+
+/// Split a BED or GTF line into its tab-separated fields.
+///
+/// A trailing `\r` (CRLF line endings) is ignored, and so are the empty
+/// fields left by trailing tabs; a field of only whitespace is empty. Other
+/// fields, such as the GTF attributes, are returned untouched. A blank line
+/// has no fields.
+fn split_fields(line: &str) -> Vec<&str> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut fields: Vec<&str> = line
+        .split('\t')
+        .map(|field| if field.trim().is_empty() { "" } else { field })
+        .collect();
+    while fields.last() == Some(&"") {
+        fields.pop();
+    }
+    fields
+}
+
+/// The first `N` fields of a BED or GTF line, as [`split_fields`] would
+/// return them, without collecting the rest of the line.
+///
+/// Fails with the number of fields `split_fields` would find if there are
+/// fewer than `N`.
+fn leading_fields<const N: usize>(line: &str) -> std::result::Result<[&str; N], usize> {
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut fields = [""; N];
+    // Fields up to the last non-empty one
+    let mut count = 0;
+    let mut parts = line.splitn(N + 1, '\t');
+    for (i, field) in parts.by_ref().take(N).enumerate() {
+        if !field.trim().is_empty() {
+            fields[i] = field;
+            count = i + 1;
+        }
+    }
+    let more = parts
+        .next()
+        .is_some_and(|rest| rest.split('\t').any(|field| !field.trim().is_empty()));
+    if count == N || more {
+        Ok(fields)
+    } else {
+        Err(count)
+    }
+}
+
+pub use bed::{parse_bed_from_reader, BedReader};
+pub use gtf::{parse_gtf_from_reader, GtfData, SharedTranscriptId, TranscriptFilterCounts};
+pub use tss_bed::{parse_tss_bed_from_reader, TssPositions};
+
+// Reading from file paths is not available on wasm32, which has no file system
+#[cfg(not(target_arch = "wasm32"))]
+pub use bed::parse_bed;
+#[cfg(not(target_arch = "wasm32"))]
+pub use gtf::parse_gtf;
+#[cfg(not(target_arch = "wasm32"))]
+pub use tss_bed::parse_tss_bed;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_leading_fields_match_split_fields() {
+        let lines = [
+            "",
+            "  \t \r",
+            "chr1\t100\t200",
+            "chr1\t100\t200\r",
+            "chr1\t\t200\t \t",
+            "chr1\t100\t200\tname",
+            "chr1\t100\t200\t\t\tscore",
+            "chr1\t100\t200\tname\textra\tcolumns\r",
+        ];
+        for line in lines {
+            let fields = split_fields(line);
+            match leading_fields::<4>(line) {
+                Ok(leading) => assert_eq!(leading[..], fields[..4], "{:?}", line),
+                Err(count) => assert_eq!(count, fields.len(), "{:?}", line),
+            }
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Parser for refined TSS positions, such as CAGE peaks, in BED format.
+//!
+//! Each line gives one TSS: chrom, position (0-based), position + 1, the
+//! transcript or gene ID, a score and the strand.
+
+use ahash::AHashMap;
+use log::trace;
+use std::io::BufRead;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, path::Path};
+
+use crate::error::{Error, Result};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parser::util::create_buffered_reader;
+use crate::parser::{check_coordinate, split_fields};
+
+/// Refined TSS positions by transcript or gene ID.
+#[derive(Debug, Clone, Default)]
+pub struct TssPositions {
+    /// Chromosome, 1-based position and score of each ID's TSS.
+    positions: AHashMap<String, (String, i64, f64)>,
+}
+
+impl TssPositions {
+    /// Chromosome and 1-based position of the TSS of `id`.
+    pub fn get(&self, id: &str) -> Option<(&str, i64)> {
+        self.positions
+            .get(id)
+            .map(|(chrom, pos, _)| (chrom.as_str(), *pos))
+    }
+
+    /// Number of IDs with a TSS.
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Whether no ID has a TSS.
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    /// Record a TSS, keeping the highest-scoring one of each ID (the first
+    /// on equal scores).
+    fn insert(&mut self, id: String, chrom: String, pos: i64, score: f64) {
+        match self.positions.get(&id) {
+            Some(&(_, _, best)) if best >= score => {}
+            _ => {
+                self.positions.insert(id, (chrom, pos, score));
+            }
+        }
+    }
+}
+
+/// Parse a TSS BED file (plain text or gzip-compressed).
+#[cfg(not(target_arch = "wasm32"))]
+pub fn parse_tss_bed(path: &Path) -> Result<TssPositions> {
+    let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+    let reader = create_buffered_reader(file, path);
+
+    parse_tss_bed_from_reader(reader).map_err(|e| e.in_file(path))
+}
+
+/// Parse TSS BED data from any buffered reader.
+///
+/// Header, `track` and `browser` lines are skipped, as in region BED files.
+/// A missing or non-numeric score counts as 0.
+pub fn parse_tss_bed_from_reader<R: BufRead>(reader: R) -> Result<TssPositions> {
+    let mut positions = TssPositions::default();
+
+    for (index, line_result) in reader.lines().enumerate() {
+        let line = line_result?;
+        let fields = split_fields(&line);
+        if fields.is_empty() {
+            continue;
+        }
+
+        if fields.len() < 2 || fields[1].parse::<i64>().is_err() {
+            trace!("TSS BED line {}: skipped, not a data line", index + 1);
+            continue;
+        }
+        let Some(id) = fields.get(3).filter(|id| !id.is_empty()) else {
+            return Err(Error::BedParse {
+                line: index + 1,
+                reason: "missing transcript or gene ID (column 4)".to_string(),
+            });
+        };
+
+        // 1-based position of the TSS base
+        let pos = check_coordinate("position", fields[1].parse().unwrap_or_default(), false)
+            .map_err(|reason| Error::BedParse {
+                line: index + 1,
+                reason,
+            })?
+            + 1;
+        let score = fields.get(4).and_then(|s| s.parse().ok()).unwrap_or(0.0);
+        positions.insert(id.to_string(), fields[0].to_string(), pos, score);
+    }
+
+    Ok(positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_tss_bed() {
+        let text = "track name=cage\n\
+                    chr1\t999\t1000\tT1\t5\t+\n\
+                    chr1\t1199\t1200\tT1\t9\t+\n\
+                    chr1\t1299\t1300\tT1\t9\t+\n\
+                    chr2\t499\t500\tG2\n";
+        let positions = parse_tss_bed_from_reader(Cursor::new(text)).unwrap();
+
+        assert_eq!(positions.len(), 2);
+        // The highest score wins, the first one on ties
+        assert_eq!(positions.get("T1"), Some(("chr1", 1200)));
+        assert_eq!(positions.get("G2"), Some(("chr2", 500)));
+        assert_eq!(positions.get("T2"), None);
+
+        let missing_id = parse_tss_bed_from_reader(Cursor::new("chr1\t10\t11\n"));
+        assert!(matches!(missing_id, Err(Error::BedParse { line: 1, .. })));
+    }
+}
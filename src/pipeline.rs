@@ -0,0 +1,750 @@
+//! One-call annotation pipeline.
+//!
+//! [`annotate`] runs the same steps as the command-line tool: load and sort
+//! the GTF annotation, stream the BED regions through a [`RegionMatcher`]
+//! and accumulate the run [`Summary`]. The results are kept in memory
+//! instead of being written, so they can be inspected or written later.
+//!
+//! [`annotate_with_progress`] and [`AnnotationResult::write_tsv_with_progress`]
+//! report their [`Progress`] to a callback, for long runs.
+//!
+//! [`RegionAnnotator`] annotates regions one at a time instead, for callers
+//! that receive regions on demand rather than as a BED file.
+//!
+//! [`annotate_chunks`] is the matching loop of [`annotate`] and of the
+//! command-line tool: it hands the associations of each chunk of regions to a callback, in
+//! input order, and can match the chunks on several threads.
+//!
+//! Phases are also logged at info level through the `log` crate, and regions
+//! on chromosomes without genes are counted by [`MissingChromosomes`] and
+//! reported in a single warning. The time spent in each phase is kept in
+//! [`AnnotationResult::timings`].
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::Write;
+use std::sync::Arc;
+use std::thread;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crossbeam_channel::{bounded, TrySendError};
+use log::{info, warn};
+
+use crate::config::Config;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::error::Error;
+use crate::error::Result;
+use crate::matcher::overlap::{position_region, SearchCursor};
+use crate::matcher::{RegionMatcher, RegionMatches};
+use crate::output::summary::Summary;
+pub use crate::output::summary::{MissingChromosomes, Timings};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::output::OUTPUT_BUFFER_SIZE;
+use crate::output::{candidates_to_write, OutputSink};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::parser::parse_gtf;
+use crate::parser::{BedReader, GtfData};
+use crate::source::AnnotationSource;
+use crate::types::{Association, Candidate, OutputFormat, Region};
+
+/// Number of BED regions read at a time.
+const BED_CHUNK_SIZE: usize = 5000;
+
+/// Regions matched or written between two progress updates.
+const PROGRESS_INTERVAL: usize = 10_000;
+
+/// Start of a timed step. wasm32 has no clock, so steps take no time there.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+}
+
+impl Stopwatch {
+    /// Start timing a step.
+    pub(crate) fn start() -> Self {
+        Stopwatch {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    /// Seconds since the step started.
+    pub(crate) fn secs(&self) -> f64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed().as_secs_f64();
+        #[cfg(target_arch = "wasm32")]
+        return 0.0;
+    }
+}
+
+/// Step of the pipeline a [`Progress`] update refers to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Phase {
+    /// Parsing the GTF annotation.
+    ParsingGtf,
+    /// Reading the BED regions.
+    ParsingBed,
+    /// Matching the regions of a chromosome.
+    Matching { chrom: String },
+    /// Writing the associations.
+    Writing,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Phase::ParsingGtf => write!(f, "Parsing GTF"),
+            Phase::ParsingBed => write!(f, "Parsing BED"),
+            Phase::Matching { chrom } => write!(f, "Matching {}", chrom),
+            Phase::Writing => write!(f, "Writing"),
+        }
+    }
+}
+
+/// Progress update passed to a progress callback.
+///
+/// Within a phase, `done` never decreases. Matching counts the regions of
+/// all chromosomes, so it keeps growing when the chromosome changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    /// Current step of the pipeline.
+    pub phase: Phase,
+    /// Genes parsed while parsing the GTF, regions in the other phases.
+    pub done: u64,
+    /// Number of items of the phase, when known.
+    pub total: Option<u64>,
+}
+
+impl Progress {
+    /// Create a progress update.
+    pub fn new(phase: Phase, done: u64, total: Option<u64>) -> Self {
+        Progress { phase, done, total }
+    }
+}
+
+/// Associations of every input region, with the data needed to write them.
+#[derive(Debug, Clone)]
+pub struct AnnotationResult {
+    /// Regions in input order with their candidates for the report level.
+    ///
+    /// `best_only` and `wide` are applied when writing, so all candidates of
+    /// the report level are kept here.
+    pub associations: Vec<Association>,
+    /// Number of BED columns after chrom, start and end.
+    pub num_meta_columns: usize,
+    /// Run statistics over the associations that would be written.
+    pub summary: Summary,
+    /// Configuration the regions were annotated with.
+    pub config: Config,
+    /// Time spent parsing and matching. Nothing is written by the pipeline,
+    /// so `write_secs` is zero. Also set as the summary's timings.
+    pub timings: Timings,
+}
+
+impl AnnotationResult {
+    /// Number of regions without an association to write.
+    pub fn num_unassigned(&self) -> u64 {
+        self.summary.unassigned_regions
+    }
+
+    /// Write the associations as a TSV table to `path`.
+    ///
+    /// Uses the column layout and formatting options of the configuration;
+    /// `output_format` is ignored.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_tsv(&self, path: &Path) -> Result<()> {
+        self.write_tsv_with_progress(path, |_| {})
+    }
+
+    /// Write the associations as a TSV table to `path`, reporting the
+    /// regions written to `on_progress`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn write_tsv_with_progress(
+        &self,
+        path: &Path,
+        mut on_progress: impl FnMut(Progress),
+    ) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::from(e).in_file(path))?;
+        self.write_tsv_inner(
+            BufWriter::with_capacity(OUTPUT_BUFFER_SIZE, file),
+            &mut on_progress,
+        )
+    }
+
+    /// Write the associations as a TSV table to `writer`.
+    pub fn write_tsv_to<W: Write + Send>(&self, writer: W) -> Result<()> {
+        self.write_tsv_inner(writer, &mut |_| {})
+    }
+
+    fn write_tsv_inner<W: Write + Send>(
+        &self,
+        writer: W,
+        on_progress: &mut dyn FnMut(Progress),
+    ) -> Result<()> {
+        let total = self.associations.len() as u64;
+        on_progress(Progress::new(Phase::Writing, 0, Some(total)));
+
+        let config = Config {
+            output_format: OutputFormat::Tsv,
+            ..self.config.clone()
+        };
+
+        let mut sink = OutputSink::new(writer, &config)?;
+        sink.write_header(self.num_meta_columns, &config)?;
+        for (i, association) in self.associations.iter().enumerate() {
+            let to_write = candidates_to_write(&association.candidates, &config);
+            sink.write_region(association, &to_write, &config)?;
+            if (i + 1) % PROGRESS_INTERVAL == 0 {
+                on_progress(Progress::new(Phase::Writing, i as u64 + 1, Some(total)));
+            }
+        }
+        sink.finish()?;
+        on_progress(Progress::new(Phase::Writing, total, Some(total)));
+        Ok(())
+    }
+}
+
+/// Size rayon's global thread pool by [`Config::num_threads`].
+///
+/// Call it before any parallel work: GTF parsing, [`associate_regions_par`]
+/// and the command-line workers all run on this one pool, so nested parallel
+/// sections share its threads instead of adding their own. The global pool
+/// can only be built once; later calls leave it as it is. Returns the number
+/// of threads of the pool.
+///
+/// [`associate_regions_par`]: crate::matcher::overlap::associate_regions_par
+#[cfg(not(target_arch = "wasm32"))]
+pub fn init_thread_pool(config: &Config) -> usize {
+    let num_threads = config.num_threads();
+    if let Err(e) = rayon::ThreadPoolBuilder::new()
+        .num_threads(num_threads)
+        .build_global()
+    {
+        log::debug!("Thread pool already initialized: {}", e);
+    }
+    rayon::current_num_threads()
+}
+
+/// Parse a GTF file with the ID tags of `config`.
+///
+/// Fails with [`Error::NoGenes`] if the file has no usable gene, which
+/// usually means the ID tags do not match the annotation.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn load_annotation(path: &Path, config: &Config) -> Result<GtfData> {
+    info!("Parsing GTF file: {}", path.display());
+    let mut gtf_data = parse_gtf(path, &config.gene_id_tag, &config.transcript_id_tag)?;
+    if gtf_data.num_genes() == 0 {
+        return Err(Error::NoGenes {
+            path: path.to_path_buf(),
+        });
+    }
+    info!(
+        "Loaded {} genes on {} chromosomes",
+        gtf_data.num_genes(),
+        gtf_data.chromosomes().len()
+    );
+    if config.strict_transcript_ids {
+        gtf_data.check_shared_transcript_ids()?;
+    } else if let Some(shared) = gtf_data.shared_transcript_ids.first() {
+        warn!(
+            "{} transcript IDs are used by more than one gene and were kept as separate transcripts (e.g. {} in {} and {})",
+            gtf_data.num_shared_transcript_ids(),
+            shared.transcript_id,
+            shared.first_gene_id,
+            shared.gene_id
+        );
+    }
+    if config.merge_overlapping_exons {
+        let num_merged = gtf_data.merge_overlapping_exons();
+        if num_merged > 0 {
+            info!(
+                "Merged {} overlapping or duplicated exon records",
+                num_merged
+            );
+        }
+    }
+    if !config.transcript_filters.is_empty() {
+        let counts = gtf_data.filter_transcripts(&config.transcript_filters);
+        info!(
+            "Transcript filters removed {} transcripts ({} untagged, {} above the maximum support level) and {} genes",
+            counts.transcripts, counts.untagged, counts.above_max_tsl, counts.genes
+        );
+        gtf_data.transcript_filtering = Some(counts);
+    }
+    let unstranded = gtf_data.num_unstranded_genes();
+    if unstranded > 0 {
+        info!(
+            "{} genes have no strand (--unstranded-genes {})",
+            unstranded,
+            config.unstranded_genes.as_str()
+        );
+    }
+    Ok(gtf_data)
+}
+
+/// Annotate the regions of a BED file with the genes of a GTF file.
+///
+/// Nothing is written; see [`AnnotationResult::write_tsv`].
+///
+/// # Example
+///
+/// ```no_run
+/// use rgmatch::{annotate, Config, ReportLevel};
+/// use std::path::Path;
+///
+/// # fn main() -> rgmatch::Result<()> {
+/// let config = Config {
+///     level: ReportLevel::Gene,
+///     ..Config::default()
+/// };
+/// let result = annotate(Path::new("genes.gtf"), Path::new("peaks.bed"), &config)?;
+///
+/// for association in &result.associations {
+///     for candidate in &association.candidates {
+///         println!("{}\t{}\t{}", association.region.id(), candidate.gene, candidate.area);
+///     }
+/// }
+/// result.write_tsv(Path::new("peaks.rgmatch.tsv"))?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub fn annotate(gtf: &Path, bed: &Path, config: &Config) -> Result<AnnotationResult> {
+    annotate_with_progress(gtf, bed, config, |_| {})
+}
+
+/// [`annotate`], reporting the progress of each phase to `on_progress`.
+///
+/// The GTF is parsed first, then all BED regions are read, so the matching
+/// phase knows the number of regions. Updates are sent at the start and end
+/// of each phase and every few thousand regions in between.
+///
+/// # Example
+///
+/// ```no_run
+/// use rgmatch::{annotate_with_progress, Config};
+/// use std::path::Path;
+///
+/// # fn main() -> rgmatch::Result<()> {
+/// let config = Config::default();
+/// let result = annotate_with_progress(
+///     Path::new("genes.gtf"),
+///     Path::new("peaks.bed"),
+///     &config,
+///     |progress| eprintln!("{}: {}", progress.phase, progress.done),
+/// )?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub fn annotate_with_progress(
+    gtf: &Path,
+    bed: &Path,
+    config: &Config,
+    mut on_progress: impl FnMut(Progress),
+) -> Result<AnnotationResult> {
+    on_progress(Progress::new(Phase::ParsingGtf, 0, None));
+    let gtf_start = Stopwatch::start();
+    let gtf_data = load_annotation(gtf, config)?;
+    let gtf_parse_secs = gtf_start.secs();
+    let num_genes = gtf_data.num_genes() as u64;
+    on_progress(Progress::new(Phase::ParsingGtf, num_genes, Some(num_genes)));
+
+    on_progress(Progress::new(Phase::ParsingBed, 0, None));
+    info!("Processing BED file: {}", bed.display());
+    let bed_reader = BedReader::new(bed)?.with_config(config);
+    let mut result = annotate_bed_reader(&gtf_data, bed_reader, config, &mut on_progress)?;
+    result.timings.gtf_parse_secs = gtf_parse_secs;
+    result.timings.total_secs = gtf_start.secs();
+    result.summary.timings = Some(result.timings.clone());
+    Ok(result)
+}
+
+/// [`annotate`] with the genes of any [`AnnotationSource`] instead of a GTF
+/// file, such as a [`VecSource`](crate::source::VecSource) built in memory.
+///
+/// # Example
+///
+/// ```no_run
+/// use rgmatch::pipeline::annotate_source;
+/// use rgmatch::source::VecSource;
+/// use rgmatch::types::Gene;
+/// use rgmatch::Config;
+/// use std::path::Path;
+///
+/// # fn main() -> rgmatch::Result<()> {
+/// # let genes_from_database: Vec<(String, Gene)> = Vec::new();
+/// let source: VecSource = genes_from_database.into_iter().collect();
+/// let result = annotate_source(&source, Path::new("peaks.bed"), &Config::default())?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub fn annotate_source(
+    source: &(dyn AnnotationSource + Sync),
+    bed: &Path,
+    config: &Config,
+) -> Result<AnnotationResult> {
+    info!("Processing BED file: {}", bed.display());
+    let bed_reader = BedReader::new(bed)?.with_config(config);
+    annotate_bed_reader(source, bed_reader, config, &mut |_| {})
+}
+
+/// Read all regions of `bed_reader` and match them against `source`,
+/// reporting the BED reading and matching phases.
+pub(crate) fn annotate_bed_reader(
+    source: &(dyn AnnotationSource + Sync),
+    mut bed_reader: BedReader,
+    config: &Config,
+    on_progress: &mut dyn FnMut(Progress),
+) -> Result<AnnotationResult> {
+    let start = Stopwatch::start();
+    let mut chunks = Vec::new();
+    let mut total = 0;
+    while let Some(chunk) = bed_reader.read_chunk(BED_CHUNK_SIZE)? {
+        total += chunk.len() as u64;
+        chunks.push(chunk);
+        on_progress(Progress::new(Phase::ParsingBed, total, None));
+    }
+    bed_reader.warn_irregular_lines();
+    let bed_parse_secs = start.secs();
+    on_progress(Progress::new(Phase::ParsingBed, total, Some(total)));
+
+    info!("Matching {} regions", total);
+    let mut associations: Vec<Association> = Vec::with_capacity(total as usize);
+    let mut summary = annotate_chunks(
+        source,
+        chunks.into_iter().map(Ok),
+        config,
+        None,
+        Some(total),
+        &mut |chunk| {
+            associations.extend(chunk);
+            Ok(())
+        },
+        on_progress,
+    )?;
+    summary.missing_chromosomes.warn();
+    summary.warn_clamped_percentages();
+    let timings = summary.timings.get_or_insert_with(Timings::new);
+    timings.bed_parse_secs = bed_parse_secs;
+    timings.total_secs = start.secs();
+    let timings = timings.clone();
+
+    Ok(AnnotationResult {
+        associations,
+        num_meta_columns: bed_reader.num_meta_columns(),
+        summary,
+        config: config.clone(),
+        timings,
+    })
+}
+
+/// Match chunks of regions against `source` and pass the associations of
+/// each chunk to `sink`, in input order.
+///
+/// This is the loop behind [`annotate`] and the command-line tool. `sink`
+/// receives one chunk at a time, so the associations need not be kept in
+/// memory. With `num_threads` above one, the chunks are matched by that many
+/// workers on rayon's global pool and still reach `sink` in input order, so
+/// the result does not depend on the number of threads. `total` is the number
+/// of regions, when known, for the matching updates sent to `on_progress`.
+///
+/// Returns the summary of the regions with their matching timings. Missing
+/// chromosomes and clamped percentages are not reported; that is left to the
+/// caller.
+pub fn annotate_chunks(
+    source: &(dyn AnnotationSource + Sync),
+    mut chunks: impl Iterator<Item = Result<Vec<Region>>>,
+    config: &Config,
+    num_threads: Option<usize>,
+    total: Option<u64>,
+    sink: &mut dyn FnMut(Vec<Association>) -> Result<()>,
+    on_progress: &mut dyn FnMut(Progress),
+) -> Result<Summary> {
+    let mut collector = Collector {
+        source,
+        config,
+        total,
+        summary: Summary::from_config(config),
+        timings: Timings::new(),
+        done: 0,
+        chrom: None,
+        sink,
+        on_progress,
+    };
+    match num_threads {
+        Some(num_threads) if num_threads > 1 => {
+            match_parallel(source, &mut chunks, config, num_threads, &mut collector)?
+        }
+        _ => {
+            let mut matcher = RegionMatcher::new(source, config);
+            for chunk in chunks {
+                collector.add(match_chunk(&mut matcher, chunk?))?;
+            }
+        }
+    }
+    Ok(collector.finish())
+}
+
+/// Associations of a chunk of regions, with the seconds spent matching them
+/// per run of regions on the same chromosome.
+struct MatchedChunk {
+    associations: Vec<Association>,
+    match_secs: Vec<(String, f64)>,
+}
+
+/// Match the regions of a chunk, in order.
+fn match_chunk(matcher: &mut RegionMatcher, regions: Vec<Region>) -> MatchedChunk {
+    let mut match_secs: Vec<(String, f64)> = Vec::new();
+    let associations = regions
+        .into_iter()
+        .map(|region| {
+            let start = Stopwatch::start();
+            let association = matcher.match_next(&region).into_association(region);
+            let secs = start.secs();
+            match match_secs.last_mut() {
+                Some((chrom, total)) if *chrom == association.region.chrom => *total += secs,
+                _ => match_secs.push((association.region.chrom.clone(), secs)),
+            }
+            association
+        })
+        .collect();
+    MatchedChunk {
+        associations,
+        match_secs,
+    }
+}
+
+/// Match the chunks on `num_threads` workers, passing the results to
+/// `collector` in input order.
+///
+/// The chunks are read and the results collected on the calling thread, so
+/// neither the input nor the sink has to be sent to the workers.
+fn match_parallel(
+    source: &(dyn AnnotationSource + Sync),
+    chunks: &mut dyn Iterator<Item = Result<Vec<Region>>>,
+    config: &Config,
+    num_threads: usize,
+    collector: &mut Collector,
+) -> Result<()> {
+    thread::scope(|scope| {
+        let (work_tx, work_rx) = bounded::<(usize, Vec<Region>)>(2 * num_threads);
+        let (result_tx, result_rx) = bounded::<(usize, MatchedChunk)>(2 * num_threads);
+        scope.spawn(move || {
+            rayon::scope(move |s| {
+                for _ in 0..num_threads {
+                    let work_rx = work_rx.clone();
+                    let result_tx = result_tx.clone();
+                    s.spawn(move |_| {
+                        // Each worker keeps its own search position across chunks
+                        let mut matcher = RegionMatcher::new(source, config);
+                        for (seq, regions) in work_rx {
+                            let matched = match_chunk(&mut matcher, regions);
+                            if result_tx.send((seq, matched)).is_err() {
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
+        });
+
+        // A worker only stops early by panicking, which the scope re-raises,
+        // so a closed channel just ends the loops here.
+        let mut ordered = InOrder::default();
+        for (seq, chunk) in chunks.enumerate() {
+            let mut item = (seq, chunk?);
+            loop {
+                match work_tx.try_send(item) {
+                    Ok(()) => break,
+                    Err(TrySendError::Full(back)) => {
+                        // Workers are busy: take a result while waiting
+                        item = back;
+                        match result_rx.recv() {
+                            Ok((seq, matched)) => ordered.push(seq, matched, collector)?,
+                            Err(_) => return Ok(()),
+                        }
+                    }
+                    Err(TrySendError::Disconnected(_)) => return Ok(()),
+                }
+            }
+        }
+        drop(work_tx);
+        for (seq, matched) in result_rx {
+            ordered.push(seq, matched, collector)?;
+        }
+        Ok(())
+    })
+}
+
+/// Matched chunks waiting for the chunks before them.
+#[derive(Default)]
+struct InOrder {
+    /// Chunks from number `next` on, `None` while not matched yet.
+    pending: VecDeque<Option<MatchedChunk>>,
+    next: usize,
+}
+
+impl InOrder {
+    /// Add chunk number `seq` and pass the chunks now in order to `collector`.
+    fn push(&mut self, seq: usize, chunk: MatchedChunk, collector: &mut Collector) -> Result<()> {
+        let index = seq - self.next;
+        if self.pending.len() <= index {
+            self.pending.resize_with(index + 1, || None);
+        }
+        self.pending[index] = Some(chunk);
+        while matches!(self.pending.front(), Some(Some(_))) {
+            if let Some(Some(chunk)) = self.pending.pop_front() {
+                collector.add(chunk)?;
+            }
+            self.next += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Counts the matched chunks, in input order, and passes them to the sink.
+struct Collector<'a> {
+    source: &'a dyn AnnotationSource,
+    config: &'a Config,
+    total: Option<u64>,
+    summary: Summary,
+    timings: Timings,
+    /// Regions counted so far, and the chromosome of the last one.
+    done: u64,
+    chrom: Option<String>,
+    sink: &'a mut dyn FnMut(Vec<Association>) -> Result<()>,
+    on_progress: &'a mut dyn FnMut(Progress),
+}
+
+impl Collector<'_> {
+    /// Count the regions of a chunk, reporting progress on a new chromosome
+    /// and every `PROGRESS_INTERVAL` regions, then pass them to the sink.
+    fn add(&mut self, chunk: MatchedChunk) -> Result<()> {
+        for (chrom, secs) in &chunk.match_secs {
+            self.timings.add_matching(chrom, *secs);
+        }
+        for association in &chunk.associations {
+            let chrom = &association.region.chrom;
+            let new_chrom = self.chrom.as_ref() != Some(chrom);
+            if new_chrom {
+                self.chrom = Some(chrom.clone());
+            }
+            if new_chrom || self.done % PROGRESS_INTERVAL as u64 == 0 {
+                let phase = Phase::Matching {
+                    chrom: chrom.clone(),
+                };
+                (self.on_progress)(Progress::new(phase, self.done, self.total));
+            }
+            self.timings.add_region(association.candidates.len());
+            let to_write = candidates_to_write(&association.candidates, self.config);
+            self.summary
+                .add_association(association, &to_write, self.source);
+            self.done += 1;
+        }
+        (self.sink)(chunk.associations)
+    }
+
+    /// Report the end of matching and return the summary with its timings.
+    fn finish(mut self) -> Summary {
+        if let Some(chrom) = self.chrom.take() {
+            let phase = Phase::Matching { chrom };
+            (self.on_progress)(Progress::new(phase, self.done, self.total));
+        }
+        self.summary.timings = Some(self.timings);
+        self.summary
+    }
+}
+
+/// Annotates regions one at a time against a loaded annotation.
+///
+/// Regions may arrive in any order and from any chromosome; the gene search
+/// continues from the previous region when the input is sorted and falls back
+/// to a binary search otherwise, so results do not depend on the order.
+///
+/// # Example
+///
+/// ```no_run
+/// use rgmatch::pipeline::load_annotation;
+/// use rgmatch::{Config, Region, RegionAnnotator};
+/// use std::path::Path;
+///
+/// # fn main() -> rgmatch::Result<()> {
+/// let config = Config::default();
+/// let gtf = load_annotation(Path::new("genes.gtf"), &config)?;
+/// let mut annotator = RegionAnnotator::new(gtf, config);
+///
+/// let region = Region::new("chr1".to_string(), 11_000, 11_200, vec![]);
+/// for candidate in annotator.annotate_region(&region) {
+///     println!("{}\t{}", candidate.gene, candidate.area);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct RegionAnnotator {
+    gtf: Arc<GtfData>,
+    config: Config,
+    cursor: SearchCursor,
+}
+
+impl RegionAnnotator {
+    /// Create an annotator over `gtf` using `config`.
+    ///
+    /// The annotation is not copied: pass an `Arc<GtfData>` to share one
+    /// annotation between annotators, e.g. one per thread or per request.
+    /// Its genes must be sorted, as the parsers return them; call
+    /// [`GtfData::sort_genes`] after changing them.
+    pub fn new(gtf: impl Into<Arc<GtfData>>, config: Config) -> Self {
+        RegionAnnotator {
+            gtf: gtf.into(),
+            config,
+            cursor: SearchCursor::default(),
+        }
+    }
+
+    /// Configuration the regions are annotated with.
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Annotation the regions are matched against.
+    pub fn gtf(&self) -> &GtfData {
+        &self.gtf
+    }
+
+    /// Match a region, keeping the number of candidate genes seen before
+    /// report-level filtering.
+    pub fn match_region(&mut self, region: &Region) -> RegionMatches {
+        self.cursor
+            .match_region(region, self.gtf.as_ref(), &self.config)
+    }
+
+    /// Candidates of a region for the report level, as [`annotate`] reports
+    /// them. `best_only` and `wide` are not applied.
+    pub fn annotate_region(&mut self, region: &Region) -> Vec<Candidate> {
+        self.match_region(region).candidates
+    }
+
+    /// Candidates of a single position, as [`classify_position`] finds them.
+    ///
+    /// [`classify_position`]: crate::matcher::classify_position
+    pub fn classify_position(&mut self, chrom: &str, pos: i64) -> Vec<Candidate> {
+        let region = position_region(chrom, pos, &self.config);
+        self.annotate_region(&region)
+    }
+
+    /// Annotate a stream of regions lazily, yielding the association of
+    /// each region.
+    pub fn annotate_iter<'s, I>(&'s mut self, regions: I) -> impl Iterator<Item = Association> + 's
+    where
+        I: IntoIterator<Item = Region>,
+        I::IntoIter: 's,
+    {
+        regions
+            .into_iter()
+            .map(move |region| self.match_region(&region).into_association(region))
+    }
+}
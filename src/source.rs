@@ -0,0 +1,290 @@
+//! Gene models the matcher reads.
+//!
+//! The matcher only needs the sorted genes of a chromosome and the length of
+//! its longest gene, described by [`AnnotationSource`]. [`GtfData`] implements
+//! it for parsed GTF files and [`VecSource`] for genes built in memory, so an
+//! annotation kept elsewhere can be matched without writing it as a GTF file
+//! first. [`Gene`], [`Transcript`](crate::types::Transcript) and
+//! [`Exon`](crate::types::Exon) are the interchange types.
+//!
+//! [`GtfData`]: crate::parser::GtfData
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::types::Gene;
+
+/// Genes by chromosome, as the matcher reads them.
+///
+/// The genes of a chromosome must have their start and end set (see
+/// [`Gene::calculate_size`]) and be sorted by [`sort_genes`].
+///
+/// # Example
+///
+/// ```
+/// use rgmatch::matcher::RegionMatcher;
+/// use rgmatch::source::{AnnotationSource, VecSource};
+/// use rgmatch::types::{Exon, Gene, Strand, Transcript};
+/// use rgmatch::{Config, Region};
+///
+/// let mut transcript = Transcript::new("T1".to_string());
+/// transcript.add_exon(Exon::new(1000, 2000));
+/// transcript.renumber_exons(Strand::Positive);
+/// transcript.calculate_size();
+/// let mut gene = Gene::new("G1".to_string(), Strand::Positive);
+/// gene.add_transcript(transcript);
+/// gene.calculate_size();
+///
+/// let source: VecSource = [("chr1".to_string(), gene)].into_iter().collect();
+/// assert_eq!(source.chromosomes(), vec!["chr1"]);
+///
+/// let config = Config::default();
+/// let mut matcher = RegionMatcher::new(&source, &config);
+/// let region = Region::new("chr1".to_string(), 1500, 1600, vec![]);
+/// assert_eq!(matcher.match_next(&region).candidates[0].gene, "G1");
+/// ```
+pub trait AnnotationSource {
+    /// Chromosomes with at least one gene, sorted by name.
+    fn chromosomes(&self) -> Vec<&str>;
+
+    /// Genes of `chrom` in [`sort_genes`] order; empty for a chromosome
+    /// without genes.
+    fn genes(&self, chrom: &str) -> &[Gene];
+
+    /// Length (end - start) of the longest gene on `chrom`, 0 if it has none.
+    fn max_gene_length(&self, chrom: &str) -> i64;
+
+    /// Index in [`AnnotationSource::genes`] from which to scan for the genes
+    /// of `chrom` ending at or after `pos`: no gene before it does.
+    ///
+    /// The default binary-searches the genes that start within the longest
+    /// gene length before `pos`, which may return an earlier index than
+    /// needed. Sources should override it with [`first_gene_ending_after`]
+    /// over the running maximum of their gene ends, so that one very long
+    /// gene does not widen the scan of every region.
+    fn first_gene_reaching(&self, chrom: &str, pos: i64) -> usize {
+        let earliest = pos.saturating_sub(self.max_gene_length(chrom));
+        self.genes(chrom).partition_point(|g| g.start < earliest)
+    }
+
+    /// Genes of `chrom` overlapping `start..=end` (1-based, inclusive), in
+    /// start order.
+    ///
+    /// The default scans from [`AnnotationSource::first_gene_reaching`];
+    /// sources with an interval index should override it.
+    fn genes_in_range(&self, chrom: &str, start: i64, end: i64) -> Vec<&Gene> {
+        let genes = self.genes(chrom);
+        let first = self.first_gene_reaching(chrom, start);
+        let last = genes.partition_point(|g| g.start <= end);
+        genes[first..last.max(first)]
+            .iter()
+            .filter(|g| g.end >= start)
+            .collect()
+    }
+}
+
+/// A shared annotation, e.g. one `Arc<GtfData>` matched by several threads.
+impl<S: AnnotationSource + ?Sized> AnnotationSource for Arc<S> {
+    fn chromosomes(&self) -> Vec<&str> {
+        (**self).chromosomes()
+    }
+
+    fn genes(&self, chrom: &str) -> &[Gene] {
+        (**self).genes(chrom)
+    }
+
+    fn max_gene_length(&self, chrom: &str) -> i64 {
+        (**self).max_gene_length(chrom)
+    }
+
+    fn first_gene_reaching(&self, chrom: &str, pos: i64) -> usize {
+        (**self).first_gene_reaching(chrom, pos)
+    }
+
+    fn genes_in_range(&self, chrom: &str, start: i64, end: i64) -> Vec<&Gene> {
+        (**self).genes_in_range(chrom, start, end)
+    }
+}
+
+/// Sort genes by start position, then gene ID, the order every
+/// [`AnnotationSource`] returns them in.
+///
+/// The gene ID keeps the order of genes with the same start deterministic.
+pub fn sort_genes(genes: &mut [Gene]) {
+    genes.sort_by(|a, b| a.start.cmp(&b.start).then(a.gene_id.cmp(&b.gene_id)));
+}
+
+/// Running maximum of the gene ends of sorted `genes`: element `i` is the
+/// largest end among `genes[..=i]`.
+pub fn running_max_ends(genes: &[Gene]) -> Vec<i64> {
+    genes
+        .iter()
+        .scan(i64::MIN, |max_end, gene| {
+            *max_end = (*max_end).max(gene.end);
+            Some(*max_end)
+        })
+        .collect()
+}
+
+/// Index of the first gene whose end is at least `pos`, given the
+/// [`running_max_ends`] of the genes. No earlier gene reaches `pos`.
+pub fn first_gene_ending_after(max_ends: &[i64], pos: i64) -> usize {
+    max_ends.partition_point(|&max_end| max_end < pos)
+}
+
+/// Genes built in memory, for annotations that do not come from a GTF file.
+///
+/// Collect it from `(chromosome, gene)` pairs; the genes are sorted on
+/// construction.
+#[derive(Debug, Clone, Default)]
+pub struct VecSource {
+    genes_by_chrom: BTreeMap<String, Vec<Gene>>,
+    max_lengths: BTreeMap<String, i64>,
+    max_ends: BTreeMap<String, Vec<i64>>,
+}
+
+impl VecSource {
+    /// Create a source from the genes of each chromosome.
+    pub fn new(genes_by_chrom: impl IntoIterator<Item = (String, Vec<Gene>)>) -> Self {
+        let mut source = VecSource::default();
+        for (chrom, genes) in genes_by_chrom {
+            source
+                .genes_by_chrom
+                .entry(chrom)
+                .or_default()
+                .extend(genes);
+        }
+        source.genes_by_chrom.retain(|_, genes| !genes.is_empty());
+
+        for (chrom, genes) in &mut source.genes_by_chrom {
+            sort_genes(genes);
+            let max_length = genes.iter().map(|g| g.end - g.start).max().unwrap_or(0);
+            source.max_lengths.insert(chrom.clone(), max_length);
+            source
+                .max_ends
+                .insert(chrom.clone(), running_max_ends(genes));
+        }
+        source
+    }
+
+    /// Total number of genes.
+    pub fn num_genes(&self) -> usize {
+        self.genes_by_chrom.values().map(Vec::len).sum()
+    }
+}
+
+impl FromIterator<(String, Gene)> for VecSource {
+    fn from_iter<I: IntoIterator<Item = (String, Gene)>>(iter: I) -> Self {
+        let mut genes_by_chrom: BTreeMap<String, Vec<Gene>> = BTreeMap::new();
+        for (chrom, gene) in iter {
+            genes_by_chrom.entry(chrom).or_default().push(gene);
+        }
+        VecSource::new(genes_by_chrom)
+    }
+}
+
+impl AnnotationSource for VecSource {
+    fn chromosomes(&self) -> Vec<&str> {
+        self.genes_by_chrom.keys().map(String::as_str).collect()
+    }
+
+    fn genes(&self, chrom: &str) -> &[Gene] {
+        self.genes_by_chrom.get(chrom).map_or(&[], Vec::as_slice)
+    }
+
+    fn max_gene_length(&self, chrom: &str) -> i64 {
+        self.max_lengths.get(chrom).copied().unwrap_or(0)
+    }
+
+    fn first_gene_reaching(&self, chrom: &str, pos: i64) -> usize {
+        self.max_ends
+            .get(chrom)
+            .map_or(0, |max_ends| first_gene_ending_after(max_ends, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Strand;
+
+    fn gene(id: &str, start: i64, end: i64) -> Gene {
+        let mut gene = Gene::new(id.to_string(), Strand::Positive);
+        gene.set_length(start, end);
+        gene
+    }
+
+    #[test]
+    fn test_vec_source_sorts_and_measures() {
+        let source: VecSource = [
+            ("chr2".to_string(), gene("B", 500, 600)),
+            ("chr1".to_string(), gene("C", 300, 5000)),
+            ("chr1".to_string(), gene("B", 100, 200)),
+            ("chr1".to_string(), gene("A", 100, 150)),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(source.chromosomes(), vec!["chr1", "chr2"]);
+        assert_eq!(source.num_genes(), 4);
+        let ids: Vec<&str> = source
+            .genes("chr1")
+            .iter()
+            .map(|g| g.gene_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["A", "B", "C"]);
+        assert_eq!(source.max_gene_length("chr1"), 4700);
+        assert_eq!(source.max_gene_length("chrX"), 0);
+        assert!(source.genes("chrX").is_empty());
+    }
+
+    #[test]
+    fn test_first_gene_reaching() {
+        let source: VecSource = [
+            ("chr1".to_string(), gene("long", 100, 10_000)),
+            ("chr1".to_string(), gene("a", 200, 300)),
+            ("chr1".to_string(), gene("b", 2000, 2100)),
+            ("chr1".to_string(), gene("c", 20_000, 20_100)),
+            ("chr1".to_string(), gene("d", 20_050, 20_060)),
+        ]
+        .into_iter()
+        .collect();
+
+        let genes = source.genes("chr1");
+        assert_eq!(
+            running_max_ends(genes),
+            vec![10_000, 10_000, 10_000, 20_100, 20_100]
+        );
+        // The long gene keeps the scan at the start until past its end
+        assert_eq!(source.first_gene_reaching("chr1", 0), 0);
+        assert_eq!(source.first_gene_reaching("chr1", 10_000), 0);
+        assert_eq!(source.first_gene_reaching("chr1", 10_001), 3);
+        assert_eq!(source.first_gene_reaching("chr1", 20_101), 5);
+        assert_eq!(source.first_gene_reaching("chrX", 100), 0);
+    }
+
+    #[test]
+    fn test_default_genes_in_range() {
+        let source: VecSource = [
+            ("chr1".to_string(), gene("long", 100, 10_000)),
+            ("chr1".to_string(), gene("a", 200, 300)),
+            ("chr1".to_string(), gene("b", 2000, 2100)),
+            ("chr1".to_string(), gene("c", 20_000, 20_100)),
+        ]
+        .into_iter()
+        .collect();
+
+        let ids = |start, end| -> Vec<String> {
+            source
+                .genes_in_range("chr1", start, end)
+                .iter()
+                .map(|g| g.gene_id.to_string())
+                .collect()
+        };
+        assert_eq!(ids(5000, 5100), vec!["long"]);
+        assert_eq!(ids(250, 2050), vec!["long", "a", "b"]);
+        assert_eq!(ids(10_001, 19_999), Vec::<String>::new());
+        assert_eq!(ids(20_100, 30_000), vec!["c"]);
+        assert!(source.genes_in_range("chrX", 0, 100).is_empty());
+    }
+}
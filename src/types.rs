@@ -3,8 +3,64 @@
 //! This module contains the fundamental types used throughout the genomic
 //! region-to-gene matching process.
 
+use ahash::AHashSet;
+#[cfg(feature = "serde")]
+use serde::de::{self, Deserializer};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::fmt;
+use std::ops::{Deref, Range};
 use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+
+/// Implement serde for enums through their `as_str` and `FromStr` string forms,
+/// so configuration files and serialized annotations use the same names as
+/// the command line and the output.
+#[cfg(feature = "serde")]
+macro_rules! serde_as_str {
+    ($($ty:ty),* $(,)?) => {$(
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(de::Error::custom)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "serde")]
+serde_as_str!(
+    Strand,
+    Area,
+    ReportLevel,
+    OutputFormat,
+    ExtraColumn,
+    CoordinateConvention,
+    Strandedness,
+    SenseClass,
+    ClosestBy,
+    AssignmentMode,
+    SelectionMode,
+    ScoreCombination,
+    TieBreak,
+    GeneLevelStyle,
+    DistanceAnchor,
+    TssDistanceSign,
+    GeneBodyDefinition,
+    ContainedGeneBehavior,
+    UnstrandedGenes,
+    SpanMultipleGenes,
+    GeneTssMode,
+    InvalidRegions,
+);
 
 /// Strand orientation for genomic features.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -15,11 +71,11 @@ pub enum Strand {
 
 /// Error type for parsing strand from string.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ParseStrandError;
+pub struct ParseStrandError(pub String);
 
 impl fmt::Display for ParseStrandError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "invalid strand: expected '+' or '-'")
+        write!(f, "invalid strand '{}': expected '+' or '-'", self.0)
     }
 }
 
@@ -32,7 +88,7 @@ impl FromStr for Strand {
         match s {
             "+" => Ok(Strand::Positive),
             "-" => Ok(Strand::Negative),
-            _ => Err(ParseStrandError),
+            _ => Err(ParseStrandError(s.to_string())),
         }
     }
 }
@@ -61,18 +117,31 @@ pub enum Area {
     Promoter,
     Tts,
     Intron,
+    /// Intron between exons 1 and 2 in transcription order
+    /// (`Config::distinguish_first_intron`).
+    FirstIntron,
     GeneBody,
+    /// Whole transcript within the region
+    /// (`Config::contained_gene_behavior`).
+    GeneContained,
+    /// Exonic part of a coding transcript 5' of its CDS (`Config::report_utr`).
+    Utr5,
+    /// Exonic part of a coding transcript 3' of its CDS (`Config::report_utr`).
+    Utr3,
     Upstream,
     Downstream,
+    /// No gene within range: the pseudo-candidate of an unassigned region
+    /// (`Config::report_intergenic`).
+    Intergenic,
 }
 
 /// Error type for parsing area from string.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ParseAreaError;
+pub struct ParseAreaError(pub String);
 
 impl fmt::Display for ParseAreaError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "invalid area type")
+        write!(f, "invalid area type '{}'", self.0)
     }
 }
 
@@ -88,10 +157,15 @@ impl FromStr for Area {
             "PROMOTER" => Ok(Area::Promoter),
             "TTS" => Ok(Area::Tts),
             "INTRON" => Ok(Area::Intron),
+            "1st_INTRON" => Ok(Area::FirstIntron),
             "GENE_BODY" => Ok(Area::GeneBody),
+            "GENE_CONTAINED" => Ok(Area::GeneContained),
+            "UTR5" => Ok(Area::Utr5),
+            "UTR3" => Ok(Area::Utr3),
             "UPSTREAM" => Ok(Area::Upstream),
             "DOWNSTREAM" => Ok(Area::Downstream),
-            _ => Err(ParseAreaError),
+            "INTERGENIC" => Ok(Area::Intergenic),
+            _ => Err(ParseAreaError(s.to_string())),
         }
     }
 }
@@ -105,9 +179,14 @@ impl Area {
             Area::Promoter => "PROMOTER",
             Area::Tts => "TTS",
             Area::Intron => "INTRON",
+            Area::FirstIntron => "1st_INTRON",
             Area::GeneBody => "GENE_BODY",
+            Area::GeneContained => "GENE_CONTAINED",
+            Area::Utr5 => "UTR5",
+            Area::Utr3 => "UTR3",
             Area::Upstream => "UPSTREAM",
             Area::Downstream => "DOWNSTREAM",
+            Area::Intergenic => "INTERGENIC",
         }
     }
 }
@@ -118,13 +197,462 @@ impl fmt::Display for Area {
     }
 }
 
+/// A shared, immutable identifier such as a gene or transcript ID.
+///
+/// Cloning shares the text instead of copying it, so the candidates of a
+/// gene point to the ID parsed once with the annotation. It reads like a
+/// `&str` and compares equal to string slices and `String`s.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Name(Arc<str>);
+
+impl Name {
+    /// The identifier as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Name {
+    /// The empty name, shared like any other.
+    fn default() -> Self {
+        static EMPTY: OnceLock<Name> = OnceLock::new();
+        EMPTY.get_or_init(|| Name(Arc::from(""))).clone()
+    }
+}
+
+impl Deref for Name {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Name {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Name {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl From<&str> for Name {
+    fn from(s: &str) -> Self {
+        Name(Arc::from(s))
+    }
+}
+
+impl From<&String> for Name {
+    fn from(s: &String) -> Self {
+        Name(Arc::from(s.as_str()))
+    }
+}
+
+impl From<String> for Name {
+    fn from(s: String) -> Self {
+        Name(Arc::from(s))
+    }
+}
+
+impl From<Name> for String {
+    fn from(name: Name) -> Self {
+        name.0.to_string()
+    }
+}
+
+impl PartialEq<str> for Name {
+    fn eq(&self, other: &str) -> bool {
+        &*self.0 == other
+    }
+}
+
+impl PartialEq<&str> for Name {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.0 == *other
+    }
+}
+
+impl PartialEq<String> for Name {
+    fn eq(&self, other: &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<Name> for str {
+    fn eq(&self, other: &Name) -> bool {
+        self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for &str {
+    fn eq(&self, other: &Name) -> bool {
+        *self == &*other.0
+    }
+}
+
+impl PartialEq<Name> for String {
+    fn eq(&self, other: &Name) -> bool {
+        **self == *other.0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Name {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Name {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Name::from)
+    }
+}
+
+/// Hands out one shared [`Name`] per distinct identifier.
+///
+/// The GTF parser interns gene and transcript IDs and chromosome names, so
+/// the genes, their transcripts and every candidate built from them share
+/// the same text.
+#[derive(Debug, Default)]
+pub struct NamePool {
+    names: AHashSet<Name>,
+}
+
+impl NamePool {
+    /// Create an empty pool.
+    pub fn new() -> Self {
+        NamePool::default()
+    }
+
+    /// The pooled name equal to `s`, added on first use.
+    pub fn intern(&mut self, s: &str) -> Name {
+        if let Some(name) = self.names.get(s) {
+            return name.clone();
+        }
+        let name = Name::from(s);
+        self.names.insert(name.clone());
+        name
+    }
+
+    /// Number of distinct names in the pool.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether the pool has no names.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// One exon or intron number of the Exon/Intron output column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExonNumber {
+    /// An exon, or an intron when introns are not labelled: shown as `N`.
+    Number(u32),
+    /// An intron labelled `intron_N` (`Config::label_introns`).
+    Intron(u32),
+}
+
+impl ExonNumber {
+    /// Compare as the text of the numbers, as the output sorts them.
+    fn cmp_text(self, other: ExonNumber) -> Ordering {
+        // Digits sort before the `intron_` label
+        match (self, other) {
+            (ExonNumber::Number(a), ExonNumber::Number(b))
+            | (ExonNumber::Intron(a), ExonNumber::Intron(b)) => {
+                let (mut a_digits, mut b_digits) = ([0; 10], [0; 10]);
+                decimal_digits(a, &mut a_digits).cmp(decimal_digits(b, &mut b_digits))
+            }
+            (ExonNumber::Number(_), ExonNumber::Intron(_)) => Ordering::Less,
+            (ExonNumber::Intron(_), ExonNumber::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+/// The decimal digits of `n`, written to the end of `buf`.
+fn decimal_digits(mut n: u32, buf: &mut [u8; 10]) -> &[u8] {
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            return &buf[start..];
+        }
+    }
+}
+
+impl fmt::Display for ExonNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExonNumber::Number(n) => write!(f, "{}", n),
+            ExonNumber::Intron(n) => write!(f, "intron_{}", n),
+        }
+    }
+}
+
+/// Error type for parsing exon numbers from their text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExonNumbersError(pub String);
+
+impl fmt::Display for ParseExonNumbersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid exon numbers '{}': expected numbers or intron_N labels separated by ','",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseExonNumbersError {}
+
+impl FromStr for ExonNumber {
+    type Err = ParseExonNumbersError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (number, make): (&str, fn(u32) -> ExonNumber) = match s.strip_prefix("intron_") {
+            Some(number) => (number, ExonNumber::Intron),
+            None => (s, ExonNumber::Number),
+        };
+        // Only plain digits, as the output writes them
+        match number.parse() {
+            Ok(n) if number.bytes().all(|b| b.is_ascii_digit()) => Ok(make(n)),
+            _ => Err(ParseExonNumbersError(s.to_string())),
+        }
+    }
+}
+
+/// The Exon/Intron column of a candidate: the numbers of the exons or
+/// introns it covers, none for intergenic candidates and several for rows
+/// merged from more than one.
+///
+/// A single number is stored inline and longer lists are shared between
+/// clones. The output joins the numbers with `Config::merge_separator`;
+/// `Display`, serde and the comparisons with strings use `,`. Numbers are
+/// ordered as their text, as in `"10" < "9"`.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct ExonNumbers(NumbersRepr);
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum NumbersRepr {
+    One(ExonNumber),
+    /// Any other number of them, never exactly one.
+    Many(Arc<[ExonNumber]>),
+}
+
+impl ExonNumbers {
+    /// The numbers, in column order.
+    pub fn as_slice(&self) -> &[ExonNumber] {
+        match &self.0 {
+            NumbersRepr::One(number) => std::slice::from_ref(number),
+            NumbersRepr::Many(numbers) => numbers,
+        }
+    }
+
+    /// Iterate over the numbers.
+    pub fn iter(&self) -> std::slice::Iter<'_, ExonNumber> {
+        self.as_slice().iter()
+    }
+
+    /// Number of exon and intron numbers.
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    /// Whether there is no number, as for intergenic candidates.
+    pub fn is_empty(&self) -> bool {
+        self.as_slice().is_empty()
+    }
+
+    /// Append the numbers to `out`, joined with `separator`.
+    pub fn write_joined(&self, out: &mut String, separator: &str) {
+        use fmt::Write;
+        for (i, number) in self.iter().enumerate() {
+            if i > 0 {
+                out.push_str(separator);
+            }
+            // Writing to a String cannot fail
+            let _ = write!(out, "{}", number);
+        }
+    }
+
+    /// The numbers joined with `separator`, as in the output.
+    pub fn join(&self, separator: &str) -> String {
+        let mut text = String::new();
+        self.write_joined(&mut text, separator);
+        text
+    }
+}
+
+impl Default for ExonNumbers {
+    /// No number, shared like any other list.
+    fn default() -> Self {
+        static EMPTY: OnceLock<ExonNumbers> = OnceLock::new();
+        EMPTY
+            .get_or_init(|| ExonNumbers(NumbersRepr::Many(Arc::from([]))))
+            .clone()
+    }
+}
+
+impl From<ExonNumber> for ExonNumbers {
+    fn from(number: ExonNumber) -> Self {
+        ExonNumbers(NumbersRepr::One(number))
+    }
+}
+
+impl From<u32> for ExonNumbers {
+    /// A single exon number.
+    fn from(number: u32) -> Self {
+        ExonNumber::Number(number).into()
+    }
+}
+
+impl From<Vec<ExonNumber>> for ExonNumbers {
+    fn from(numbers: Vec<ExonNumber>) -> Self {
+        match numbers.as_slice() {
+            [] => ExonNumbers::default(),
+            [number] => (*number).into(),
+            _ => ExonNumbers(NumbersRepr::Many(numbers.into())),
+        }
+    }
+}
+
+impl FromIterator<ExonNumber> for ExonNumbers {
+    fn from_iter<I: IntoIterator<Item = ExonNumber>>(iter: I) -> Self {
+        iter.into_iter().collect::<Vec<_>>().into()
+    }
+}
+
+impl<'a> IntoIterator for &'a ExonNumbers {
+    type Item = &'a ExonNumber;
+    type IntoIter = std::slice::Iter<'a, ExonNumber>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl PartialOrd for ExonNumbers {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ExonNumbers {
+    /// Compare as the numbers joined with `,`, which sorts before any digit.
+    fn cmp(&self, other: &Self) -> Ordering {
+        let mut others = other.iter();
+        for &number in self {
+            match others.next() {
+                Some(&other) => match number.cmp_text(other) {
+                    Ordering::Equal => {}
+                    unequal => return unequal,
+                },
+                None => return Ordering::Greater,
+            }
+        }
+        if others.next().is_some() {
+            Ordering::Less
+        } else {
+            Ordering::Equal
+        }
+    }
+}
+
+impl fmt::Display for ExonNumbers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, number) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}", number)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ExonNumbers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string(), f)
+    }
+}
+
+impl FromStr for ExonNumbers {
+    type Err = ParseExonNumbersError;
+
+    /// Parse numbers joined with `,`; the empty string has none.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Ok(ExonNumbers::default());
+        }
+        s.split(',')
+            .map(|number| {
+                number
+                    .parse()
+                    .map_err(|_| ParseExonNumbersError(s.to_string()))
+            })
+            .collect::<Result<Vec<ExonNumber>, _>>()
+            .map(ExonNumbers::from)
+    }
+}
+
+impl PartialEq<str> for ExonNumbers {
+    fn eq(&self, other: &str) -> bool {
+        other
+            .parse::<ExonNumbers>()
+            .is_ok_and(|numbers| *self == numbers)
+    }
+}
+
+impl PartialEq<&str> for ExonNumbers {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for ExonNumbers {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for ExonNumbers {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
 /// An exon within a transcript.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Exon {
     pub start: i64,
     pub end: i64,
     /// Exon number within the transcript (set by renumber_exons).
-    pub exon_number: Option<String>,
+    pub exon_number: Option<u32>,
 }
 
 impl Exon {
@@ -143,28 +671,238 @@ impl Exon {
     }
 }
 
+/// Total length of `exons`, sorted by start, counting overlaps once.
+fn exonic_length(exons: &[Exon]) -> i64 {
+    let mut length = 0;
+    let mut covered_to = i64::MIN;
+    for exon in exons {
+        let start = exon.start.max(covered_to.saturating_add(1));
+        if exon.end >= start {
+            length += exon.end - start + 1;
+        }
+        covered_to = covered_to.max(exon.end);
+    }
+    length
+}
+
+/// Exon layout of a transcript precomputed for the matcher by
+/// [`Transcript::build_index`].
+///
+/// The arrays follow the transcript's exons, sorted by start as
+/// `renumber_exons` leaves them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExonIndex {
+    /// Strand the first and last exons were taken for.
+    pub strand: Strand,
+    /// Exon starts.
+    pub starts: Vec<i64>,
+    /// Exon ends.
+    pub ends: Vec<i64>,
+    /// Largest end of the exons up to each one; annotations that were not
+    /// merged may have overlapping exons.
+    pub max_ends: Vec<i64>,
+    /// Start of the leftmost exon.
+    pub exons_start: i64,
+    /// Largest exon end.
+    pub exons_end: i64,
+    /// First and last base of the intron after each exon but the last.
+    pub introns: Vec<(i64, i64)>,
+    /// Index of the first exon in transcription order.
+    pub first_exon: usize,
+    /// Index of the last exon in transcription order.
+    pub last_exon: usize,
+    /// Whether the leftmost exon is numbered 1, which puts the TSS at its
+    /// start and the TTS at the end of the rightmost exon; otherwise the TSS
+    /// is at the end of the rightmost exon and the TTS at the start of the
+    /// leftmost one.
+    pub forward: bool,
+    /// TSS position.
+    pub tss: i64,
+    /// TTS position.
+    pub tts: i64,
+    /// Total length of the exons ([`Transcript::exonic_length`]).
+    pub exonic_length: i64,
+}
+
+impl ExonIndex {
+    /// Index `exons`, sorted by start, of a transcript on `strand`.
+    pub fn new(exons: &[Exon], strand: Strand) -> Self {
+        let starts: Vec<i64> = exons.iter().map(|e| e.start).collect();
+        let ends: Vec<i64> = exons.iter().map(|e| e.end).collect();
+        let max_ends: Vec<i64> = ends
+            .iter()
+            .scan(i64::MIN, |max, &end| {
+                *max = (*max).max(end);
+                Some(*max)
+            })
+            .collect();
+        let introns = exons
+            .windows(2)
+            .map(|pair| (pair[0].end + 1, pair[1].start - 1))
+            .collect();
+        let last = exons.len().saturating_sub(1);
+        let (first_exon, last_exon) = match strand {
+            Strand::Positive => (0, last),
+            Strand::Negative => (last, 0),
+        };
+        let forward = exons.first().is_some_and(|e| e.exon_number == Some(1));
+        let leftmost = starts.first().copied().unwrap_or(0);
+        let rightmost = ends.last().copied().unwrap_or(0);
+        let (tss, tts) = if forward {
+            (leftmost, rightmost)
+        } else {
+            (rightmost, leftmost)
+        };
+
+        ExonIndex {
+            strand,
+            exons_start: starts.first().copied().unwrap_or(i64::MAX),
+            exons_end: max_ends.last().copied().unwrap_or(i64::MIN),
+            starts,
+            ends,
+            max_ends,
+            introns,
+            first_exon,
+            last_exon,
+            forward,
+            tss,
+            tts,
+            exonic_length: exonic_length(exons),
+        }
+    }
+
+    /// Number of exons.
+    pub fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// Whether the transcript has no exons.
+    pub fn is_empty(&self) -> bool {
+        self.starts.is_empty()
+    }
+
+    /// Exons that can give a candidate for the region `start..=end`.
+    ///
+    /// Skips the exons left of the region except the last one before it,
+    /// which bounds the intron the region starts in, and the exons right of
+    /// it except the leftmost exon, whose start is the transcript's distance
+    /// from an upstream region.
+    pub fn exons_near(&self, start: i64, end: i64) -> Range<usize> {
+        // Most genes scanned lie wholly on one side of the region
+        let len = self.len();
+        if self.exons_end < start {
+            return len.saturating_sub(1)..len;
+        }
+        if self.exons_start > end {
+            return 0..len.min(1);
+        }
+        let ending_before = self.max_ends.partition_point(|&e| e < start);
+        let starting_at_or_before = self.starts.partition_point(|&s| s <= start);
+        let from = ending_before.min(starting_at_or_before.saturating_sub(1));
+        let to = self.starts.partition_point(|&s| s <= end).max(1);
+        from..to.min(self.len())
+    }
+
+    /// Signed distance from `pos` to the TSS, positive downstream of it.
+    pub fn tss_distance(&self, pos: i64) -> i64 {
+        if self.forward {
+            pos - self.tss
+        } else {
+            self.tss - pos
+        }
+    }
+
+    /// Signed distance from `pos` to the TTS, positive downstream of it.
+    pub fn tts_distance(&self, pos: i64) -> i64 {
+        if self.forward {
+            pos - self.tts
+        } else {
+            self.tts - pos
+        }
+    }
+
+    /// Exon-intron junctions, each as the last base before it.
+    pub fn junctions(&self) -> Vec<i64> {
+        self.introns
+            .iter()
+            .flat_map(|&(start, end)| [start - 1, end])
+            .collect()
+    }
+}
+
 /// A transcript containing exons.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Transcript {
-    pub transcript_id: String,
+    pub transcript_id: Name,
     pub exons: Vec<Exon>,
     /// Minimum start coordinate (initialized to i64::MAX).
     pub start: i64,
     /// Maximum end coordinate (initialized to 0).
     pub end: i64,
+    /// First and last coding base, from the CDS features; `None` for a
+    /// non-coding transcript or an annotation without CDS features.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cds: Option<(i64, i64)>,
+    /// Values of the GTF `tag` attributes, such as `basic` or `MANE_Select`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tags: Vec<String>,
+    /// GTF `transcript_support_level`, 1 for the best supported; `None`
+    /// when missing or `NA`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub support_level: Option<u8>,
+    /// Exon layout for the matcher, built by `build_index`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    index: Option<ExonIndex>,
+}
+
+// The exon index is derived from the exons, so it is left out
+impl fmt::Debug for Transcript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transcript")
+            .field("transcript_id", &self.transcript_id)
+            .field("exons", &self.exons)
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("cds", &self.cds)
+            .field("tags", &self.tags)
+            .field("support_level", &self.support_level)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Transcript {
     /// Create a new transcript with the given ID.
-    pub fn new(transcript_id: String) -> Self {
+    pub fn new(transcript_id: impl Into<Name>) -> Self {
         Transcript {
-            transcript_id,
+            transcript_id: transcript_id.into(),
             exons: Vec::new(),
             start: i64::MAX,
             end: 0,
+            cds: None,
+            tags: Vec::new(),
+            support_level: None,
+            index: None,
         }
     }
 
+    /// Precompute the exon layout the matcher reads, for a transcript on
+    /// `strand`.
+    ///
+    /// `renumber_exons` builds it; call it again after changing the exons
+    /// otherwise. The matcher indexes the exons of a transcript without one
+    /// on every match.
+    pub fn build_index(&mut self, strand: Strand) {
+        self.index = Some(ExonIndex::new(&self.exons, strand));
+    }
+
+    /// The exon layout built for `strand`, if it covers the current exons.
+    pub fn exon_index(&self, strand: Strand) -> Option<&ExonIndex> {
+        self.index
+            .as_ref()
+            .filter(|index| index.strand == strand && index.len() == self.exons.len())
+    }
+
     /// Add an exon to this transcript.
     pub fn add_exon(&mut self, exon: Exon) {
         self.exons.push(exon);
@@ -188,9 +926,53 @@ impl Transcript {
         }
     }
 
+    /// Merge overlapping or abutting exons, leaving them sorted by start.
+    ///
+    /// Returns the number of exon records merged into another; the exons
+    /// must then be numbered again with `renumber_exons`.
+    pub fn merge_overlapping_exons(&mut self) -> usize {
+        self.exons.sort_by_key(|e| e.start);
+        let num_exons = self.exons.len();
+        let mut merged: Vec<Exon> = Vec::with_capacity(num_exons);
+        for exon in self.exons.drain(..) {
+            match merged.last_mut() {
+                Some(last) if exon.start <= last.end.saturating_add(1) => {
+                    last.end = last.end.max(exon.end);
+                }
+                _ => merged.push(exon),
+            }
+        }
+        self.exons = merged;
+        num_exons - self.exons.len()
+    }
+
+    /// Whether the transcript is tagged `Ensembl_canonical` or `MANE_Select`.
+    pub fn is_canonical(&self) -> bool {
+        self.tags
+            .iter()
+            .any(|tag| tag == "Ensembl_canonical" || tag == "MANE_Select")
+    }
+
+    /// Position of the transcript's TSS: its first base on the + strand,
+    /// its last one on the - strand.
+    pub fn tss(&self, strand: Strand) -> i64 {
+        match strand {
+            Strand::Positive => self.start,
+            Strand::Negative => self.end,
+        }
+    }
+
+    /// Total length of the exons, counting overlapping exons once.
+    ///
+    /// Exons must be sorted by start, as `renumber_exons` leaves them.
+    pub fn exonic_length(&self) -> i64 {
+        exonic_length(&self.exons)
+    }
+
     /// Renumber exons based on strand orientation.
     ///
-    /// Sorts exons by position and assigns exon numbers.
+    /// Sorts exons by position, assigns exon numbers and builds the exon
+    /// index.
     /// For positive strand: ascending order (1, 2, 3...).
     /// For negative strand: descending order (N, N-1, ...).
     pub fn renumber_exons(&mut self, strand: Strand) {
@@ -203,43 +985,66 @@ impl Transcript {
             Strand::Positive => {
                 // Positive strand: 1, 2, 3, ...
                 for (i, exon) in self.exons.iter_mut().enumerate() {
-                    exon.exon_number = Some((i + 1).to_string());
+                    exon.exon_number = Some(i as u32 + 1);
                 }
             }
             Strand::Negative => {
                 // Negative strand: N, N-1, N-2, ... (reverse numbering)
                 for (i, exon) in self.exons.iter_mut().enumerate() {
-                    exon.exon_number = Some((n_exons - i).to_string());
+                    exon.exon_number = Some((n_exons - i) as u32);
                 }
             }
         }
+        self.build_index(strand);
     }
 }
 
 /// A gene containing transcripts.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Gene {
-    pub gene_id: String,
+    pub gene_id: Name,
     pub strand: Strand,
     pub transcripts: Vec<Transcript>,
     /// Minimum start coordinate (initialized to i64::MAX).
     pub start: i64,
     /// Maximum end coordinate (initialized to 0).
     pub end: i64,
+    /// The annotation gives no strand ('.'); the gene is kept on the
+    /// + strand and [`UnstrandedGenes`] decides how it is matched.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub unstranded: bool,
 }
 
 impl Gene {
     /// Create a new gene with the given ID and strand.
-    pub fn new(gene_id: String, strand: Strand) -> Self {
+    pub fn new(gene_id: impl Into<Name>, strand: Strand) -> Self {
         Gene {
-            gene_id,
+            gene_id: gene_id.into(),
             strand,
             transcripts: Vec::new(),
             start: i64::MAX,
             end: 0,
+            unstranded: false,
         }
     }
 
+    /// Copy of the gene on the opposite strand, with its exons renumbered.
+    pub fn mirrored(&self) -> Gene {
+        let strand = match self.strand {
+            Strand::Positive => Strand::Negative,
+            Strand::Negative => Strand::Positive,
+        };
+        let mut gene = Gene {
+            strand,
+            ..self.clone()
+        };
+        for transcript in &mut gene.transcripts {
+            transcript.renumber_exons(strand);
+        }
+        gene
+    }
+
     /// Add a transcript to this gene.
     pub fn add_transcript(&mut self, transcript: Transcript) {
         self.transcripts.push(transcript);
@@ -266,31 +1071,94 @@ impl Gene {
 
 /// A candidate match between a genomic region and a gene annotation.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Candidate {
+    /// Start of the matched feature. For TSS, PROMOTER and UPSTREAM this
+    /// is the first exon in transcription order.
     pub start: i64,
+    /// End of the matched feature.
     pub end: i64,
     pub strand: Strand,
-    pub exon_number: String,
+    pub exon_number: ExonNumbers,
     pub area: Area,
-    pub transcript: String,
-    pub gene: String,
+    pub transcript: Name,
+    pub gene: Name,
     pub distance: i64,
     pub pctg_region: f64,
     pub pctg_area: f64,
     pub tss_distance: i64,
+    /// Distance from the transcript's TTS to the region midpoint, positive
+    /// downstream of the TTS.
+    pub tts_distance: i64,
+    /// Start of the matched gene (not of the matched feature).
+    pub gene_start: i64,
+    /// End of the matched gene.
+    pub gene_end: i64,
+    /// Chromosome of the region and gene.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub chrom: String,
+    /// [`Region::id`] of the matched region.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub region_id: String,
+    /// Orientation of the gene relative to the region's strand.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub sense: SenseClass,
+    /// Found by the closest-gene fallback (`Config::always_closest`), beyond
+    /// the distance cutoff.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub closest_fallback: bool,
+    /// Rank of the gene among the region's nearest genes
+    /// (`Config::n_closest`), 1 for the nearest.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub rank: Option<usize>,
+    /// Whether the report-level selection keeps the candidate, set only when
+    /// every candidate is reported (`Config::report_all_candidates`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub selected: Option<bool>,
+    /// Whether the region is at the shared promoter of this gene and a
+    /// head-to-head neighbor (`Config::bidirectional_window`).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub bidirectional: bool,
+    /// Bases of the region within the matched area.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub overlap_bp: i64,
+    /// Signed distance to the nearest exon-intron junction of the
+    /// transcript, positive 3' of it, for exon, gene body and intron
+    /// candidates of multi-exon transcripts. Set only when the output has a
+    /// `JunctionDistance` column.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub junction_distance: Option<i64>,
 }
 
-impl Candidate {
-    /// Create a new candidate.
-    #[allow(clippy::too_many_arguments)]
+/// Excess over [0, 100] that [`Candidate::clamp_percentages`] puts down to
+/// float rounding.
+const PERCENTAGE_ROUNDING: f64 = 1e-6;
+
+/// Clamp one percentage of a candidate, returning whether it changed.
+fn clamp_percentage(pctg: &mut f64) -> bool {
+    if *pctg == -1.0 || (0.0..=100.0).contains(pctg) {
+        return false;
+    }
+    debug_assert!(
+        *pctg > -PERCENTAGE_ROUNDING && *pctg < 100.0 + PERCENTAGE_ROUNDING,
+        "percentage {} out of range",
+        pctg
+    );
+    *pctg = pctg.clamp(0.0, 100.0);
+    true
+}
+
+impl Candidate {
+    /// Create a new candidate.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         start: i64,
         end: i64,
         strand: Strand,
-        exon_number: String,
+        exon_number: impl Into<ExonNumbers>,
         area: Area,
-        transcript: String,
-        gene: String,
+        transcript: impl Into<Name>,
+        gene: impl Into<Name>,
         distance: i64,
         pctg_region: f64,
         pctg_area: f64,
@@ -300,38 +1168,170 @@ impl Candidate {
             start,
             end,
             strand,
-            exon_number,
+            exon_number: exon_number.into(),
             area,
-            transcript,
-            gene,
+            transcript: transcript.into(),
+            gene: gene.into(),
             distance,
             pctg_region,
             pctg_area,
             tss_distance,
+            tts_distance: 0,
+            gene_start: 0,
+            gene_end: 0,
+            chrom: String::new(),
+            region_id: String::new(),
+            sense: SenseClass::Unstranded,
+            closest_fallback: false,
+            rank: None,
+            selected: None,
+            bidirectional: false,
+            overlap_bp: 0,
+            junction_distance: None,
+        }
+    }
+
+    /// Set the distance to the transcript's TTS.
+    pub fn with_tts_distance(mut self, tts_distance: i64) -> Self {
+        self.tts_distance = tts_distance;
+        self
+    }
+
+    /// Set the boundaries of the candidate's gene.
+    pub fn with_gene_span(mut self, gene_start: i64, gene_end: i64) -> Self {
+        self.gene_start = gene_start;
+        self.gene_end = gene_end;
+        self
+    }
+
+    /// Clamp `pctg_region` and `pctg_area` into [0, 100], leaving the `-1`
+    /// sentinel of unbounded areas and intergenic candidates.
+    ///
+    /// Returns whether a value was out of range. Beyond float rounding that
+    /// points to a bug in the overlap geometry, so debug builds panic on it.
+    pub fn clamp_percentages(&mut self) -> bool {
+        let region = clamp_percentage(&mut self.pctg_region);
+        let area = clamp_percentage(&mut self.pctg_area);
+        region || area
+    }
+
+    /// Pseudo-candidate of a region without any candidate
+    /// (`Config::report_intergenic`).
+    ///
+    /// Gene and transcript are `NA`, there is no exon number and the
+    /// percentages are the `-1` sentinel. `distance` is the distance to the nearest gene, `None`
+    /// (stored as -1) if the chromosome has none.
+    pub fn intergenic(region: &Region, distance: Option<i64>) -> Self {
+        let na = || "NA".to_string();
+        Candidate {
+            sense: SenseClass::Unstranded,
+            ..Candidate::new(
+                0,
+                0,
+                Strand::Positive,
+                ExonNumbers::default(),
+                Area::Intergenic,
+                na(),
+                na(),
+                distance.unwrap_or(-1),
+                -1.0,
+                -1.0,
+                0,
+            )
+            .with_region(region)
+        }
+    }
+
+    /// Whether this is the pseudo-candidate of a region without genes in
+    /// range; only its area, distance and region fields apply.
+    pub fn is_intergenic(&self) -> bool {
+        self.area == Area::Intergenic
+    }
+
+    /// Distance to the feature, or `None` for an intergenic candidate on a
+    /// chromosome without genes.
+    pub fn feature_distance(&self) -> Option<i64> {
+        (!self.is_intergenic() || self.distance >= 0).then_some(self.distance)
+    }
+
+    /// Set the chromosome and ID of the matched region, and the sense class
+    /// of the gene relative to it.
+    pub fn with_region(mut self, region: &Region) -> Self {
+        self.chrom = region.chrom.clone();
+        self.region_id = region.id();
+        self.sense = SenseClass::of(region.strand, self.strand);
+        self
+    }
+
+    /// Percentage of the region overlapped, or `None` for the negative
+    /// sentinel used when it does not apply.
+    pub fn region_percentage(&self) -> Option<f64> {
+        (self.pctg_region >= 0.0).then_some(self.pctg_region)
+    }
+
+    /// Percentage of the area overlapped, or `None` for the `-1` sentinel
+    /// of UPSTREAM and DOWNSTREAM hits.
+    pub fn area_percentage(&self) -> Option<f64> {
+        (self.pctg_area >= 0.0).then_some(self.pctg_area)
+    }
+
+    /// Length of the candidate's gene (1-based, inclusive).
+    pub fn gene_length(&self) -> i64 {
+        self.gene_end - self.gene_start + 1
+    }
+
+    /// Percentage of the candidate's gene span covered by `region`, the same
+    /// for every candidate of the gene; `None` for an intergenic candidate.
+    pub fn gene_percentage(&self, region: &Region) -> Option<f64> {
+        if self.is_intergenic() {
+            return None;
         }
+        let overlap = region.end.min(self.gene_end) - region.start.max(self.gene_start) + 1;
+        Some(overlap.max(0) as f64 / self.gene_length() as f64 * 100.0)
     }
 }
 
 /// A genomic region from a BED file.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Region {
     pub chrom: String,
     pub start: i64,
     pub end: i64,
     pub metadata: Vec<String>,
+    /// Strand from BED column 6; `None` for unstranded regions.
+    pub strand: Option<Strand>,
+    /// 1-based line of the region in its BED file; `None` for regions not
+    /// read from a file.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub line: Option<usize>,
 }
 
 impl Region {
-    /// Create a new region.
+    /// Create a new unstranded region.
     pub fn new(chrom: String, start: i64, end: i64, metadata: Vec<String>) -> Self {
         Region {
             chrom,
             start,
             end,
             metadata,
+            strand: None,
+            line: None,
         }
     }
 
+    /// Set the region strand.
+    pub fn with_strand(mut self, strand: Option<Strand>) -> Self {
+        self.strand = strand;
+        self
+    }
+
+    /// Set the BED line the region was read from.
+    pub fn with_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+
     /// Get the region length (end - start + 1).
     pub fn length(&self) -> i64 {
         self.end - self.start + 1
@@ -346,25 +1346,99 @@ impl Region {
     pub fn id(&self) -> String {
         format!("{}_{}_{}", self.chrom, self.start, self.end)
     }
+
+    /// Get the region ID with the start reported in `convention`.
+    pub fn id_in(&self, convention: CoordinateConvention) -> String {
+        format!(
+            "{}_{}_{}",
+            self.chrom,
+            convention.from_bed_start(self.start),
+            self.end
+        )
+    }
+
+    /// Get the midpoint reported in `convention`.
+    pub fn midpoint_in(&self, convention: CoordinateConvention) -> i64 {
+        convention.from_bed_start(self.midpoint())
+    }
+}
+
+/// A region with its candidates for the report level.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Association {
+    pub region: Region,
+    /// Candidates for the report level, before `best_only` and `wide`.
+    pub candidates: Vec<Candidate>,
+    /// Number of distinct genes with any candidate before report-level filtering.
+    pub num_candidate_genes: usize,
+    /// Number of candidates dropped by `Config::area_max_distance`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub num_distance_filtered: usize,
+    /// Number of candidates with a percentage clamped into [0, 100].
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub num_clamped_percentages: usize,
+}
+
+impl Association {
+    /// Whether the region has no candidate.
+    pub fn is_unassigned(&self) -> bool {
+        self.candidates.is_empty()
+    }
+}
+
+/// The region ID, then `gene:area:distance` for each candidate, separated
+/// by `;`.
+impl fmt::Display for Association {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.region.id())?;
+        for (i, candidate) in self.candidates.iter().enumerate() {
+            let sep = if i == 0 { '\t' } else { ';' };
+            write!(
+                f,
+                "{}{}:{}:{}",
+                sep, candidate.gene, candidate.area, candidate.distance
+            )?;
+        }
+        Ok(())
+    }
 }
 
 /// Report level for output.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
 pub enum ReportLevel {
     Exon,
     Transcript,
     Gene,
 }
 
+impl ReportLevel {
+    /// Convert report level to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportLevel::Exon => "exon",
+            ReportLevel::Transcript => "transcript",
+            ReportLevel::Gene => "gene",
+        }
+    }
+}
+
+impl fmt::Display for ReportLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 /// Error type for parsing report level from string.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct ParseReportLevelError;
+pub struct ParseReportLevelError(pub String);
 
 impl fmt::Display for ParseReportLevelError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "invalid report level: expected 'exon', 'transcript', or 'gene'"
+            "invalid report level '{}': expected 'exon', 'transcript', or 'gene'",
+            self.0
         )
     }
 }
@@ -379,77 +1453,1567 @@ impl FromStr for ReportLevel {
             "exon" => Ok(ReportLevel::Exon),
             "transcript" => Ok(ReportLevel::Transcript),
             "gene" => Ok(ReportLevel::Gene),
-            _ => Err(ParseReportLevelError),
+            _ => Err(ParseReportLevelError(s.to_string())),
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Optional output columns enabled with `--extra-columns`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExtraColumn {
+    /// Coordinates of the matched feature (`FeatureStart`, `FeatureEnd`).
+    Coords,
+    /// Region strand relative to the gene (`RelativeStrand`).
+    RelativeStrand,
+    /// Distance to the transcript's TTS (`TTSDistance`).
+    TtsDistance,
+    /// Boundaries and length of the matched gene (`GeneStart`, `GeneEnd`, `GeneLength`).
+    GeneSpan,
+    /// Number of genes with any candidate for the region (`NumCandidateGenes`).
+    NumCandidateGenes,
+    /// Gene orientation relative to the region's strand (`Sense`).
+    Sense,
+    /// Percentage of the gene span covered by the region (`PercGene`).
+    PercGene,
+    /// Whether the region is at a bidirectional promoter (`Bidirectional`).
+    Bidirectional,
+    /// Distance to the nearest exon-intron junction (`JunctionDistance`).
+    JunctionDistance,
+    /// BED line of the region, to join the output to the input (`BedLine`).
+    BedLine,
+}
 
-    #[test]
-    fn test_strand_parsing() {
-        assert_eq!("+".parse::<Strand>(), Ok(Strand::Positive));
-        assert_eq!("-".parse::<Strand>(), Ok(Strand::Negative));
-        assert!(".".parse::<Strand>().is_err());
+/// Error type for parsing an extra column name from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseExtraColumnError(pub String);
+
+impl fmt::Display for ParseExtraColumnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = ExtraColumn::ALL.iter().map(|c| c.as_str()).collect();
+        write!(
+            f,
+            "invalid extra column '{}': expected one of {}",
+            self.0,
+            names.join(", ")
+        )
     }
+}
 
-    #[test]
-    fn test_area_parsing() {
-        assert_eq!("TSS".parse::<Area>(), Ok(Area::Tss));
-        assert_eq!("1st_EXON".parse::<Area>(), Ok(Area::FirstExon));
-        assert_eq!("PROMOTER".parse::<Area>(), Ok(Area::Promoter));
-        assert!("INVALID".parse::<Area>().is_err());
+impl std::error::Error for ParseExtraColumnError {}
+
+impl FromStr for ExtraColumn {
+    type Err = ParseExtraColumnError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ExtraColumn::ALL
+            .into_iter()
+            .find(|column| column.as_str() == s)
+            .ok_or_else(|| ParseExtraColumnError(s.to_string()))
     }
+}
 
-    #[test]
-    fn test_exon_length() {
-        let exon = Exon::new(100, 200);
-        assert_eq!(exon.length(), 101);
+impl ExtraColumn {
+    /// Every extra column selector.
+    pub const ALL: [ExtraColumn; 10] = [
+        ExtraColumn::Coords,
+        ExtraColumn::RelativeStrand,
+        ExtraColumn::TtsDistance,
+        ExtraColumn::GeneSpan,
+        ExtraColumn::NumCandidateGenes,
+        ExtraColumn::Sense,
+        ExtraColumn::PercGene,
+        ExtraColumn::Bidirectional,
+        ExtraColumn::JunctionDistance,
+        ExtraColumn::BedLine,
+    ];
+
+    /// Convert extra column selector to its CLI name.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExtraColumn::Coords => "coords",
+            ExtraColumn::RelativeStrand => "relative-strand",
+            ExtraColumn::TtsDistance => "tts-distance",
+            ExtraColumn::GeneSpan => "gene-span",
+            ExtraColumn::NumCandidateGenes => "num-candidate-genes",
+            ExtraColumn::Sense => "sense",
+            ExtraColumn::PercGene => "perc-gene",
+            ExtraColumn::Bidirectional => "bidirectional",
+            ExtraColumn::JunctionDistance => "junction-distance",
+            ExtraColumn::BedLine => "bed-line",
+        }
     }
+}
 
-    #[test]
-    fn test_region_midpoint() {
-        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
-        assert_eq!(region.midpoint(), 150);
+/// Output file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Tab-separated association table with a header line.
+    #[default]
+    Tsv,
+    /// BED6 track with one line per association, named GENE|AREA|DISTANCE.
+    Bed,
+    /// Apache Parquet table (requires the `parquet` cargo feature).
+    Parquet,
+    /// `bedtools closest -D b` layout: the region's BED columns, the gene as
+    /// BED6 and a signed distance.
+    Closest,
+}
 
-        // Test integer division
-        let region2 = Region::new("chr1".to_string(), 100, 201, vec![]);
-        assert_eq!(region2.midpoint(), 150); // (100 + 201) / 2 = 150 (integer division)
+/// Error type for parsing output format from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseOutputFormatError(pub String);
+
+impl fmt::Display for ParseOutputFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid output format '{}': expected 'tsv', 'bed', 'parquet' or 'closest'",
+            self.0
+        )
     }
+}
 
-    #[test]
-    fn test_transcript_renumber_positive() {
-        let mut transcript = Transcript::new("T1".to_string());
-        transcript.add_exon(Exon::new(500, 600));
-        transcript.add_exon(Exon::new(100, 200));
-        transcript.add_exon(Exon::new(300, 400));
+impl std::error::Error for ParseOutputFormatError {}
 
-        transcript.renumber_exons(Strand::Positive);
+impl FromStr for OutputFormat {
+    type Err = ParseOutputFormatError;
 
-        // After sorting and numbering: 100-200 is 1, 300-400 is 2, 500-600 is 3
-        assert_eq!(transcript.exons[0].start, 100);
-        assert_eq!(transcript.exons[0].exon_number, Some("1".to_string()));
-        assert_eq!(transcript.exons[1].start, 300);
-        assert_eq!(transcript.exons[1].exon_number, Some("2".to_string()));
-        assert_eq!(transcript.exons[2].start, 500);
-        assert_eq!(transcript.exons[2].exon_number, Some("3".to_string()));
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tsv" => Ok(OutputFormat::Tsv),
+            "bed" => Ok(OutputFormat::Bed),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "closest" => Ok(OutputFormat::Closest),
+            _ => Err(ParseOutputFormatError(s.to_string())),
+        }
     }
+}
 
-    #[test]
-    fn test_transcript_renumber_negative() {
-        let mut transcript = Transcript::new("T1".to_string());
-        transcript.add_exon(Exon::new(100, 200));
-        transcript.add_exon(Exon::new(300, 400));
+impl OutputFormat {
+    /// Convert output format to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Tsv => "tsv",
+            OutputFormat::Bed => "bed",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Closest => "closest",
+        }
+    }
+}
 
-        transcript.renumber_exons(Strand::Negative);
+/// Coordinate convention of reported positions.
+///
+/// Regions keep their BED coordinates and features their GTF coordinates
+/// internally; the convention only changes how start positions are written.
+/// End positions are the same in both conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoordinateConvention {
+    /// 0-based starts, as in BED files.
+    #[default]
+    Bed0,
+    /// 1-based starts, as in GTF files and genome browsers.
+    OneBased,
+}
 
-        // After sorting: 100-200 first, 300-400 second
-        // For negative strand: first (lowest) gets N, last (highest) gets 1
-        assert_eq!(transcript.exons[0].start, 100);
-        assert_eq!(transcript.exons[0].exon_number, Some("2".to_string()));
-        assert_eq!(transcript.exons[1].start, 300);
-        assert_eq!(transcript.exons[1].exon_number, Some("1".to_string()));
+/// Error type for parsing a coordinate convention from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseCoordinateConventionError(pub String);
+
+impl fmt::Display for ParseCoordinateConventionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid coordinate convention '{}': expected 'bed0' or 'one-based'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseCoordinateConventionError {}
+
+impl FromStr for CoordinateConvention {
+    type Err = ParseCoordinateConventionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bed0" => Ok(CoordinateConvention::Bed0),
+            "one-based" => Ok(CoordinateConvention::OneBased),
+            _ => Err(ParseCoordinateConventionError(s.to_string())),
+        }
+    }
+}
+
+impl CoordinateConvention {
+    /// Convert coordinate convention to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoordinateConvention::Bed0 => "bed0",
+            CoordinateConvention::OneBased => "one-based",
+        }
+    }
+
+    /// Report a 0-based (BED) start position in this convention.
+    pub fn from_bed_start(self, start: i64) -> i64 {
+        match self {
+            CoordinateConvention::Bed0 => start,
+            CoordinateConvention::OneBased => start + 1,
+        }
+    }
+
+    /// Read a start position given in this convention as 1-based (GTF).
+    pub fn to_one_based_start(self, start: i64) -> i64 {
+        match self {
+            CoordinateConvention::Bed0 => start + 1,
+            CoordinateConvention::OneBased => start,
+        }
+    }
+
+    /// Report a 1-based (GTF) start position in this convention.
+    pub fn from_one_based_start(self, start: i64) -> i64 {
+        match self {
+            CoordinateConvention::Bed0 => start - 1,
+            CoordinateConvention::OneBased => start,
+        }
+    }
+}
+
+/// Strands of the genes a stranded region may be matched to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Strandedness {
+    /// Genes on both strands.
+    #[default]
+    Ignore,
+    /// Genes on the region's strand.
+    Same,
+    /// Genes on the strand opposite to the region's.
+    Opposite,
+}
+
+/// Error type for parsing a strandedness from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseStrandednessError(pub String);
+
+impl fmt::Display for ParseStrandednessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid strandedness '{}': expected 'ignore', 'same' or 'opposite'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseStrandednessError {}
+
+impl FromStr for Strandedness {
+    type Err = ParseStrandednessError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ignore" => Ok(Strandedness::Ignore),
+            "same" => Ok(Strandedness::Same),
+            "opposite" => Ok(Strandedness::Opposite),
+            _ => Err(ParseStrandednessError(s.to_string())),
+        }
+    }
+}
+
+impl Strandedness {
+    /// Convert strandedness to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strandedness::Ignore => "ignore",
+            Strandedness::Same => "same",
+            Strandedness::Opposite => "opposite",
+        }
+    }
+
+    /// Whether a gene on `gene` may be matched to a region on `region`.
+    pub fn accepts(self, region: Strand, gene: Strand) -> bool {
+        match self {
+            Strandedness::Ignore => true,
+            Strandedness::Same => region == gene,
+            Strandedness::Opposite => region != gene,
+        }
+    }
+}
+
+/// Distance that ranks genes in the closest-gene fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ClosestBy {
+    /// Gap between the region and the nearest end of the gene, 0 if they
+    /// overlap, as in `bedtools closest`.
+    #[default]
+    Edge,
+    /// Distance from the region midpoint to the gene's TSS.
+    Tss,
+}
+
+/// Error type for parsing a closest-gene distance from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseClosestByError(pub String);
+
+impl fmt::Display for ParseClosestByError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid closest-gene distance '{}': expected 'edge' or 'tss'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseClosestByError {}
+
+impl FromStr for ClosestBy {
+    type Err = ParseClosestByError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "edge" => Ok(ClosestBy::Edge),
+            "tss" => Ok(ClosestBy::Tss),
+            _ => Err(ParseClosestByError(s.to_string())),
+        }
+    }
+}
+
+impl ClosestBy {
+    /// Convert closest-gene distance to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClosestBy::Edge => "edge",
+            ClosestBy::Tss => "tss",
+        }
+    }
+
+    /// Distance between `region` and `gene` in bp.
+    pub fn distance(self, region: &Region, gene: &Gene) -> i64 {
+        match self {
+            ClosestBy::Edge => (gene.start - region.end)
+                .max(region.start - gene.end)
+                .max(0),
+            ClosestBy::Tss => {
+                let tss = match gene.strand {
+                    Strand::Positive => gene.start,
+                    Strand::Negative => gene.end,
+                };
+                (tss - region.midpoint()).abs()
+            }
+        }
+    }
+}
+
+/// What part of a region is matched to the genes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AssignmentMode {
+    /// The whole region, with every area it overlaps.
+    #[default]
+    Overlap,
+    /// Only the base at the region midpoint.
+    Midpoint,
+}
+
+/// Error type for parsing an assignment mode from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseAssignmentModeError(pub String);
+
+impl fmt::Display for ParseAssignmentModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid assignment mode '{}': expected 'overlap' or 'midpoint'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseAssignmentModeError {}
+
+impl FromStr for AssignmentMode {
+    type Err = ParseAssignmentModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "overlap" => Ok(AssignmentMode::Overlap),
+            "midpoint" => Ok(AssignmentMode::Midpoint),
+            _ => Err(ParseAssignmentModeError(s.to_string())),
+        }
+    }
+}
+
+impl AssignmentMode {
+    /// Convert assignment mode to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AssignmentMode::Overlap => "overlap",
+            AssignmentMode::Midpoint => "midpoint",
+        }
+    }
+}
+
+/// How genes lying entirely within a region are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ContainedGeneBehavior {
+    /// Like any other overlap, as TSS, 1st_EXON, GENE_BODY, INTRON and TTS
+    /// candidates.
+    #[default]
+    Split,
+    /// As one GENE_CONTAINED candidate per transcript, with PercArea 100.
+    Consolidate,
+}
+
+/// Error type for parsing a contained gene behavior from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseContainedGeneBehaviorError(pub String);
+
+impl fmt::Display for ParseContainedGeneBehaviorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid contained gene behavior '{}': expected 'split' or 'consolidate'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseContainedGeneBehaviorError {}
+
+impl FromStr for ContainedGeneBehavior {
+    type Err = ParseContainedGeneBehaviorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "split" => Ok(ContainedGeneBehavior::Split),
+            "consolidate" => Ok(ContainedGeneBehavior::Consolidate),
+            _ => Err(ParseContainedGeneBehaviorError(s.to_string())),
+        }
+    }
+}
+
+impl ContainedGeneBehavior {
+    /// Convert contained gene behavior to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContainedGeneBehavior::Split => "split",
+            ContainedGeneBehavior::Consolidate => "consolidate",
+        }
+    }
+}
+
+/// Transcripts whose TSS a gene reports at gene level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum GeneTssMode {
+    /// Every transcript's.
+    #[default]
+    AnyTranscript,
+    /// Only the 5'-most transcript's, so internal isoforms give no TSS,
+    /// PROMOTER or UPSTREAM candidates.
+    FivePrimeMost,
+    /// Only the canonical transcript's (tagged `Ensembl_canonical` or
+    /// `MANE_Select`), or the 5'-most one's if the gene has none.
+    Canonical,
+}
+
+/// Error type for parsing a gene TSS mode from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGeneTssModeError(pub String);
+
+impl fmt::Display for ParseGeneTssModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid gene TSS mode '{}': expected 'any-transcript', 'five-prime-most' or 'canonical'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseGeneTssModeError {}
+
+impl FromStr for GeneTssMode {
+    type Err = ParseGeneTssModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "any-transcript" => Ok(GeneTssMode::AnyTranscript),
+            "five-prime-most" => Ok(GeneTssMode::FivePrimeMost),
+            "canonical" => Ok(GeneTssMode::Canonical),
+            _ => Err(ParseGeneTssModeError(s.to_string())),
+        }
+    }
+}
+
+impl GeneTssMode {
+    /// Convert gene TSS mode to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeneTssMode::AnyTranscript => "any-transcript",
+            GeneTssMode::FivePrimeMost => "five-prime-most",
+            GeneTssMode::Canonical => "canonical",
+        }
+    }
+}
+
+/// Handling of BED regions whose start is greater than their end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum InvalidRegions {
+    /// Swap the start and end.
+    #[default]
+    Swap,
+    /// Fail with an error naming the line.
+    Error,
+    /// Skip the region.
+    Drop,
+}
+
+/// Error type for parsing an invalid region policy from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseInvalidRegionsError(pub String);
+
+impl fmt::Display for ParseInvalidRegionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid region policy '{}': expected 'swap', 'error' or 'drop'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseInvalidRegionsError {}
+
+impl FromStr for InvalidRegions {
+    type Err = ParseInvalidRegionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "swap" => Ok(InvalidRegions::Swap),
+            "error" => Ok(InvalidRegions::Error),
+            "drop" => Ok(InvalidRegions::Drop),
+            _ => Err(ParseInvalidRegionsError(s.to_string())),
+        }
+    }
+}
+
+impl InvalidRegions {
+    /// Convert invalid region policy to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvalidRegions::Swap => "swap",
+            InvalidRegions::Error => "error",
+            InvalidRegions::Drop => "drop",
+        }
+    }
+}
+
+/// Which genes are reported at gene level when a region spans several.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SpanMultipleGenes {
+    /// Those kept by the rules and thresholds.
+    #[default]
+    RulesOnly,
+    /// Every gene whose span the region overlaps, with PercRegion the share
+    /// of the region within the gene, flagging the rules' choice in the
+    /// `Selected` column.
+    ReportAllOverlapped,
+}
+
+/// Error type for parsing a multiple gene span policy from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSpanMultipleGenesError(pub String);
+
+impl fmt::Display for ParseSpanMultipleGenesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid multiple gene span policy '{}': expected 'rules-only' or 'report-all-overlapped'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseSpanMultipleGenesError {}
+
+impl FromStr for SpanMultipleGenes {
+    type Err = ParseSpanMultipleGenesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rules-only" => Ok(SpanMultipleGenes::RulesOnly),
+            "report-all-overlapped" => Ok(SpanMultipleGenes::ReportAllOverlapped),
+            _ => Err(ParseSpanMultipleGenesError(s.to_string())),
+        }
+    }
+}
+
+impl SpanMultipleGenes {
+    /// Convert multiple gene span policy to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpanMultipleGenes::RulesOnly => "rules-only",
+            SpanMultipleGenes::ReportAllOverlapped => "report-all-overlapped",
+        }
+    }
+}
+
+/// How genes without a strand in the annotation are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum UnstrandedGenes {
+    /// Leave them out.
+    #[default]
+    Skip,
+    /// Match them as + strand genes.
+    TreatAsPlus,
+    /// Match them on both strands and report, per transcript, the
+    /// orientation whose best area ranks higher (the + strand on ties).
+    Both,
+}
+
+/// Error type for parsing an unstranded gene policy from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseUnstrandedGenesError(pub String);
+
+impl fmt::Display for ParseUnstrandedGenesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid unstranded gene policy '{}': expected 'skip', 'treat-as-plus' or 'both'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseUnstrandedGenesError {}
+
+impl FromStr for UnstrandedGenes {
+    type Err = ParseUnstrandedGenesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "skip" => Ok(UnstrandedGenes::Skip),
+            "treat-as-plus" => Ok(UnstrandedGenes::TreatAsPlus),
+            "both" => Ok(UnstrandedGenes::Both),
+            _ => Err(ParseUnstrandedGenesError(s.to_string())),
+        }
+    }
+}
+
+impl UnstrandedGenes {
+    /// Convert unstranded gene policy to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UnstrandedGenes::Skip => "skip",
+            UnstrandedGenes::TreatAsPlus => "treat-as-plus",
+            UnstrandedGenes::Both => "both",
+        }
+    }
+}
+
+/// What counts as a transcript's gene body for GENE_BODY candidates.
+///
+/// The first exon is always reported as 1st_EXON, and UTR overlaps as
+/// 5'UTR/3'UTR when they are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum GeneBodyDefinition {
+    /// Overlaps with the other exons; PercArea is over the overlapped exons.
+    #[default]
+    ExonOnly,
+    /// The whole transcript span: intron overlaps are GENE_BODY instead of
+    /// INTRON, and PercArea is over the transcript span.
+    FullSpan,
+    /// Overlaps with the other exons, as in exon-only, but PercArea is
+    /// over the union of all the transcript's exons (its mature length).
+    ExonUnion,
+}
+
+/// Error type for parsing a gene body definition from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGeneBodyDefinitionError(pub String);
+
+impl fmt::Display for ParseGeneBodyDefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid gene body definition '{}': expected 'exon-only', 'full-span' or 'exon-union'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseGeneBodyDefinitionError {}
+
+impl FromStr for GeneBodyDefinition {
+    type Err = ParseGeneBodyDefinitionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "exon-only" => Ok(GeneBodyDefinition::ExonOnly),
+            "full-span" => Ok(GeneBodyDefinition::FullSpan),
+            "exon-union" => Ok(GeneBodyDefinition::ExonUnion),
+            _ => Err(ParseGeneBodyDefinitionError(s.to_string())),
+        }
+    }
+}
+
+impl GeneBodyDefinition {
+    /// Convert gene body definition to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeneBodyDefinition::ExonOnly => "exon-only",
+            GeneBodyDefinition::FullSpan => "full-span",
+            GeneBodyDefinition::ExonUnion => "exon-union",
+        }
+    }
+}
+
+/// Point of the region that reported distances are measured from.
+///
+/// Only `Candidate::distance` and `Candidate::tss_distance` depend on it;
+/// areas and the distance cutoff are always decided from the midpoint.
+/// Candidates overlapping their feature have distance 0 with both anchors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DistanceAnchor {
+    /// The region midpoint. A region overlapping the TSS has a non-zero
+    /// TSS distance unless its midpoint is at the TSS, and TSS, PROMOTER
+    /// and UPSTREAM hits report the distance from the midpoint to the TSS.
+    #[default]
+    Midpoint,
+    /// The region edge nearest to the feature. Distances are the gap
+    /// between the region and the feature (the TSS for `tss_distance`), 0
+    /// when the region reaches it; the TSS distance keeps its sign.
+    Edge,
+}
+
+/// Error type for parsing a distance anchor from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDistanceAnchorError(pub String);
+
+impl fmt::Display for ParseDistanceAnchorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid distance anchor '{}': expected 'midpoint' or 'edge'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseDistanceAnchorError {}
+
+impl FromStr for DistanceAnchor {
+    type Err = ParseDistanceAnchorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "midpoint" => Ok(DistanceAnchor::Midpoint),
+            "edge" => Ok(DistanceAnchor::Edge),
+            _ => Err(ParseDistanceAnchorError(s.to_string())),
+        }
+    }
+}
+
+impl DistanceAnchor {
+    /// Convert distance anchor to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DistanceAnchor::Midpoint => "midpoint",
+            DistanceAnchor::Edge => "edge",
+        }
+    }
+}
+
+/// Sign convention of `Candidate::tss_distance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TssDistanceSign {
+    /// Negative when the region is upstream (5') of the TSS, on either
+    /// strand, as in HOMER.
+    #[default]
+    UpstreamNegative,
+    /// Negative when the region is left of the TSS in genome coordinates,
+    /// whatever the gene strand.
+    GenomicLeftNegative,
+}
+
+/// Error type for parsing a TSS distance sign from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTssDistanceSignError(pub String);
+
+impl fmt::Display for ParseTssDistanceSignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid TSS distance sign '{}': expected 'upstream-negative' or 'genomic-left-negative'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTssDistanceSignError {}
+
+impl FromStr for TssDistanceSign {
+    type Err = ParseTssDistanceSignError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "upstream-negative" => Ok(TssDistanceSign::UpstreamNegative),
+            "genomic-left-negative" => Ok(TssDistanceSign::GenomicLeftNegative),
+            _ => Err(ParseTssDistanceSignError(s.to_string())),
+        }
+    }
+}
+
+impl TssDistanceSign {
+    /// Convert TSS distance sign to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TssDistanceSign::UpstreamNegative => "upstream-negative",
+            TssDistanceSign::GenomicLeftNegative => "genomic-left-negative",
+        }
+    }
+}
+
+/// How the reported candidates are chosen among a region's candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum SelectionMode {
+    /// Percentage thresholds, then the priority order of the rules.
+    #[default]
+    Rules,
+    /// Highest score from the area weights and the candidate's overlap and
+    /// distance, combined as set by --score-combination.
+    ///
+    /// The combination is a [`ScoreCombination`].
+    Score,
+}
+
+/// Error type for parsing a selection mode from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSelectionModeError(pub String);
+
+impl fmt::Display for ParseSelectionModeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid selection mode '{}': expected 'rules' or 'score'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseSelectionModeError {}
+
+impl FromStr for SelectionMode {
+    type Err = ParseSelectionModeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "rules" => Ok(SelectionMode::Rules),
+            "score" => Ok(SelectionMode::Score),
+            _ => Err(ParseSelectionModeError(s.to_string())),
+        }
+    }
+}
+
+impl SelectionMode {
+    /// Convert selection mode to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SelectionMode::Rules => "rules",
+            SelectionMode::Score => "score",
+        }
+    }
+}
+
+/// How a candidate's score combines its area weight `w` with its region
+/// fraction `r` (`pctg_region / 100`), area fraction `a` (`pctg_area / 100`,
+/// 1 for areas without an extent) and proximity `p` (`1000 / (1000 +
+/// distance)`, 1 for overlaps).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScoreCombination {
+    /// `w * r * a * p`
+    #[default]
+    Product,
+    /// `w * (r + a + p) / 3`
+    Sum,
+}
+
+/// Error type for parsing a score combination from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseScoreCombinationError(pub String);
+
+impl fmt::Display for ParseScoreCombinationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid score combination '{}': expected 'product' or 'sum'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseScoreCombinationError {}
+
+impl FromStr for ScoreCombination {
+    type Err = ParseScoreCombinationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "product" => Ok(ScoreCombination::Product),
+            "sum" => Ok(ScoreCombination::Sum),
+            _ => Err(ParseScoreCombinationError(s.to_string())),
+        }
+    }
+}
+
+impl ScoreCombination {
+    /// Convert score combination to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScoreCombination::Product => "product",
+            ScoreCombination::Sum => "sum",
+        }
+    }
+
+    /// Combine the area `weight` with the overlap and distance of
+    /// `candidate`.
+    pub fn score(self, weight: f64, candidate: &Candidate) -> f64 {
+        let region = candidate.pctg_region / 100.0;
+        let area = if candidate.pctg_area < 0.0 {
+            1.0
+        } else {
+            candidate.pctg_area / 100.0
+        };
+        let proximity = 1000.0 / (1000.0 + candidate.distance.max(0) as f64);
+        match self {
+            ScoreCombination::Product => weight * region * area * proximity,
+            ScoreCombination::Sum => weight * (region + area + proximity) / 3.0,
+        }
+    }
+}
+
+/// How the rules settle candidates tied on area and region percentage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum TieBreak {
+    /// Report every tied candidate (merged into one row at gene level).
+    #[default]
+    ReportAll,
+    /// Keep the candidate with the smallest absolute TSS distance, then the
+    /// lowest gene ID.
+    TssDistance,
+    /// Keep the candidate with the lowest gene ID.
+    GeneId,
+}
+
+/// Error type for parsing a tie-break from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseTieBreakError(pub String);
+
+impl fmt::Display for ParseTieBreakError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid tie-break '{}': expected 'report-all', 'tss-distance' or 'gene-id'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseTieBreakError {}
+
+impl FromStr for TieBreak {
+    type Err = ParseTieBreakError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "report-all" => Ok(TieBreak::ReportAll),
+            "tss-distance" => Ok(TieBreak::TssDistance),
+            "gene-id" => Ok(TieBreak::GeneId),
+            _ => Err(ParseTieBreakError(s.to_string())),
+        }
+    }
+}
+
+impl TieBreak {
+    /// Convert tie-break to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TieBreak::ReportAll => "report-all",
+            TieBreak::TssDistance => "tss-distance",
+            TieBreak::GeneId => "gene-id",
+        }
+    }
+}
+
+/// How the gene level reports transcripts tied on the winning area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum GeneLevelStyle {
+    /// One row listing every tied transcript and exon number.
+    #[default]
+    Merge,
+    /// One row for a single representative transcript, as matched.
+    BestTranscript,
+}
+
+/// Error type for parsing a gene-level style from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseGeneLevelStyleError(pub String);
+
+impl fmt::Display for ParseGeneLevelStyleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid gene-level style '{}': expected 'merge' or 'best-transcript'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseGeneLevelStyleError {}
+
+impl FromStr for GeneLevelStyle {
+    type Err = ParseGeneLevelStyleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "merge" => Ok(GeneLevelStyle::Merge),
+            "best-transcript" => Ok(GeneLevelStyle::BestTranscript),
+            _ => Err(ParseGeneLevelStyleError(s.to_string())),
+        }
+    }
+}
+
+impl GeneLevelStyle {
+    /// Convert gene-level style to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GeneLevelStyle::Merge => "merge",
+            GeneLevelStyle::BestTranscript => "best-transcript",
+        }
+    }
+}
+
+/// Orientation of a candidate's gene relative to a stranded region.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum SenseClass {
+    /// The gene is on the region's strand.
+    Sense,
+    /// The gene is on the strand opposite to the region's.
+    Antisense,
+    /// The region has no strand.
+    #[default]
+    Unstranded,
+}
+
+/// Error type for parsing a sense class from string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseSenseClassError(pub String);
+
+impl fmt::Display for ParseSenseClassError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid sense class '{}': expected 'sense', 'antisense' or 'unstranded'",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ParseSenseClassError {}
+
+impl FromStr for SenseClass {
+    type Err = ParseSenseClassError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SenseClass::ALL
+            .into_iter()
+            .find(|class| class.as_str() == s)
+            .ok_or_else(|| ParseSenseClassError(s.to_string()))
+    }
+}
+
+impl SenseClass {
+    /// Every sense class, in order.
+    pub const ALL: [SenseClass; 3] = [
+        SenseClass::Sense,
+        SenseClass::Antisense,
+        SenseClass::Unstranded,
+    ];
+
+    /// Classify a gene on `gene` against a region on `region`.
+    pub fn of(region: Option<Strand>, gene: Strand) -> Self {
+        match region {
+            Some(strand) if strand == gene => SenseClass::Sense,
+            Some(_) => SenseClass::Antisense,
+            None => SenseClass::Unstranded,
+        }
+    }
+
+    /// Convert sense class to string representation.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SenseClass::Sense => "sense",
+            SenseClass::Antisense => "antisense",
+            SenseClass::Unstranded => "unstranded",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strand_parsing() {
+        assert_eq!("+".parse::<Strand>(), Ok(Strand::Positive));
+        assert_eq!("-".parse::<Strand>(), Ok(Strand::Negative));
+        assert!(".".parse::<Strand>().is_err());
+    }
+
+    #[test]
+    fn test_area_parsing() {
+        assert_eq!("TSS".parse::<Area>(), Ok(Area::Tss));
+        assert_eq!("1st_EXON".parse::<Area>(), Ok(Area::FirstExon));
+        assert_eq!("PROMOTER".parse::<Area>(), Ok(Area::Promoter));
+        assert!("INVALID".parse::<Area>().is_err());
+    }
+
+    #[test]
+    fn test_output_format_parsing() {
+        assert_eq!("tsv".parse::<OutputFormat>(), Ok(OutputFormat::Tsv));
+        assert_eq!("BED".parse::<OutputFormat>(), Ok(OutputFormat::Bed));
+        assert_eq!("parquet".parse::<OutputFormat>(), Ok(OutputFormat::Parquet));
+        assert_eq!("closest".parse::<OutputFormat>(), Ok(OutputFormat::Closest));
+        assert!("csv".parse::<OutputFormat>().is_err());
+        assert_eq!(OutputFormat::default(), OutputFormat::Tsv);
+        assert_eq!(OutputFormat::Bed.as_str(), "bed");
+    }
+
+    #[test]
+    fn test_extra_column_parsing() {
+        assert_eq!("coords".parse::<ExtraColumn>(), Ok(ExtraColumn::Coords));
+        assert_eq!(
+            "relative-strand".parse::<ExtraColumn>(),
+            Ok(ExtraColumn::RelativeStrand)
+        );
+        assert_eq!(
+            "tts-distance".parse::<ExtraColumn>(),
+            Ok(ExtraColumn::TtsDistance)
+        );
+        let err = "Coords".parse::<ExtraColumn>().unwrap_err();
+        assert_eq!(err, ParseExtraColumnError("Coords".to_string()));
+        assert!(err.to_string().contains("tts-distance"));
+        assert_eq!(ExtraColumn::Coords.as_str(), "coords");
+    }
+
+    #[test]
+    fn test_exon_length() {
+        let exon = Exon::new(100, 200);
+        assert_eq!(exon.length(), 101);
+    }
+
+    #[test]
+    fn test_clamp_percentages() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        let mut candidate = Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            1,
+            Area::GeneBody,
+            "T1".to_string(),
+            "G1".to_string(),
+            0,
+            100.0 + 1e-9,
+            50.0,
+            0,
+        );
+        assert!(candidate.clamp_percentages());
+        assert_eq!(candidate.pctg_region, 100.0);
+        assert_eq!(candidate.pctg_area, 50.0);
+        assert!(!candidate.clamp_percentages());
+
+        // The -1 sentinel is not a percentage
+        let mut intergenic = Candidate::intergenic(&region, Some(10));
+        assert!(!intergenic.clamp_percentages());
+        assert_eq!(intergenic.pctg_region, -1.0);
+        assert_eq!(intergenic.pctg_area, -1.0);
+    }
+
+    #[test]
+    fn test_region_midpoint() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        assert_eq!(region.midpoint(), 150);
+
+        // Test integer division
+        let region2 = Region::new("chr1".to_string(), 100, 201, vec![]);
+        assert_eq!(region2.midpoint(), 150); // (100 + 201) / 2 = 150 (integer division)
+    }
+
+    /// Candidate of a merged gene-level row, with transcripts `T1,T2`.
+    fn merged_candidate(gene: &str, area: Area, distance: i64) -> Candidate {
+        Candidate::new(
+            100,
+            200,
+            Strand::Positive,
+            vec![ExonNumber::Number(1), ExonNumber::Number(2)],
+            area,
+            "T1,T2".to_string(),
+            gene.to_string(),
+            distance,
+            100.0,
+            -1.0,
+            0,
+        )
+        .with_tts_distance(-900)
+        .with_gene_span(100, 1000)
+    }
+
+    /// Serialize `value` as TOML, check that it reads back unchanged and
+    /// return the text.
+    #[cfg(feature = "serde")]
+    fn assert_round_trip<T>(value: &T) -> String
+    where
+        T: Serialize + serde::de::DeserializeOwned + fmt::Debug,
+    {
+        let text = toml::to_string(value).unwrap();
+        let parsed: T = toml::from_str(&text).unwrap();
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", value));
+        text
+    }
+
+    #[test]
+    fn test_association_display() {
+        let candidate = merged_candidate;
+        let association = Association {
+            region: Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()]),
+            candidates: vec![
+                candidate("G1", Area::Tss, 0),
+                candidate("G2", Area::Upstream, 350),
+            ],
+            num_candidate_genes: 2,
+            num_distance_filtered: 0,
+            num_clamped_percentages: 0,
+        };
+        assert!(!association.is_unassigned());
+        assert_eq!(
+            association.to_string(),
+            "chr1_100_200\tG1:TSS:0;G2:UPSTREAM:350"
+        );
+
+        let unassigned = Association {
+            candidates: vec![],
+            num_candidate_genes: 0,
+            ..association
+        };
+        assert!(unassigned.is_unassigned());
+        assert_eq!(unassigned.to_string(), "chr1_100_200");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_candidate_with_merged_transcripts() {
+        let text = assert_round_trip(&merged_candidate("G1", Area::Tss, 0));
+        assert!(text.contains("transcript = \"T1,T2\""));
+        assert!(text.contains("exon_number = \"1,2\""));
+        assert!(text.contains("area = \"TSS\""));
+        assert!(text.contains("strand = \"+\""));
+        assert!(text.contains("pctg_region = 100.0"));
+        assert!(text.contains("pctg_area = -1.0"));
+        assert!(text.contains("tss_distance = 0"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_region() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec!["peak1".to_string()])
+            .with_strand(Some(Strand::Negative));
+        let text = assert_round_trip(&region);
+        assert!(text.contains("strand = \"-\""));
+
+        let unstranded = Region::new("chr2".to_string(), 5, 10, vec![]);
+        assert!(!assert_round_trip(&unstranded).contains("strand"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_gene_transcript_exon() {
+        let mut exon = Exon::new(100, 200);
+        exon.exon_number = Some(2);
+        assert!(assert_round_trip(&exon).contains("exon_number = 2"));
+
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(100, 200));
+        transcript.add_exon(Exon::new(500, 800));
+        transcript.calculate_size();
+        transcript.renumber_exons(Strand::Negative);
+        assert_round_trip(&transcript);
+
+        let mut gene = Gene::new("G1".to_string(), Strand::Negative);
+        gene.transcripts.push(transcript);
+        gene.calculate_size();
+        let text = assert_round_trip(&gene);
+        assert!(text.contains("gene_id = \"G1\""));
+        assert!(text.contains("strand = \"-\""));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_association() {
+        let association = Association {
+            region: Region::new("chr1".to_string(), 100, 200, vec![]),
+            candidates: vec![
+                merged_candidate("G1", Area::Tss, 0),
+                merged_candidate("G2", Area::Upstream, 350),
+            ],
+            num_candidate_genes: 2,
+            num_distance_filtered: 0,
+            num_clamped_percentages: 0,
+        };
+        assert_round_trip(&association);
+    }
+
+    #[test]
+    fn test_region_coordinate_convention() {
+        let region = Region::new("chr1".to_string(), 100, 200, vec![]);
+        assert_eq!(region.id_in(CoordinateConvention::Bed0), "chr1_100_200");
+        assert_eq!(region.id_in(CoordinateConvention::OneBased), "chr1_101_200");
+        assert_eq!(region.midpoint_in(CoordinateConvention::Bed0), 150);
+        assert_eq!(region.midpoint_in(CoordinateConvention::OneBased), 151);
+
+        // A single base: BED 99-100 is base 100
+        let base = Region::new("chr1".to_string(), 99, 100, vec![]);
+        assert_eq!(base.id_in(CoordinateConvention::Bed0), "chr1_99_100");
+        assert_eq!(base.id_in(CoordinateConvention::OneBased), "chr1_100_100");
+        assert_eq!(base.midpoint_in(CoordinateConvention::Bed0), 99);
+        assert_eq!(base.midpoint_in(CoordinateConvention::OneBased), 100);
+    }
+
+    #[test]
+    fn test_coordinate_convention_parsing() {
+        assert_eq!(
+            "bed0".parse::<CoordinateConvention>(),
+            Ok(CoordinateConvention::Bed0)
+        );
+        assert_eq!(
+            "one-based".parse::<CoordinateConvention>(),
+            Ok(CoordinateConvention::OneBased)
+        );
+        assert!("1".parse::<CoordinateConvention>().is_err());
+        assert_eq!(CoordinateConvention::default(), CoordinateConvention::Bed0);
+    }
+
+    #[test]
+    fn test_strandedness() {
+        assert_eq!("same".parse::<Strandedness>(), Ok(Strandedness::Same));
+        assert_eq!(
+            "Opposite".parse::<Strandedness>(),
+            Ok(Strandedness::Opposite)
+        );
+        assert!("stranded".parse::<Strandedness>().is_err());
+        assert_eq!(Strandedness::default(), Strandedness::Ignore);
+
+        use Strand::{Negative, Positive};
+        assert!(Strandedness::Ignore.accepts(Positive, Negative));
+        assert!(Strandedness::Same.accepts(Negative, Negative));
+        assert!(!Strandedness::Same.accepts(Positive, Negative));
+        assert!(Strandedness::Opposite.accepts(Positive, Negative));
+        assert!(!Strandedness::Opposite.accepts(Positive, Positive));
+    }
+
+    #[test]
+    fn test_closest_by_distance() {
+        let mut gene = Gene::new("G1".to_string(), Strand::Negative);
+        gene.set_length(1000, 2000);
+        let region = |start, end| Region::new("chr1".to_string(), start, end, vec![]);
+
+        assert_eq!(ClosestBy::Edge.distance(&region(2500, 2600), &gene), 500);
+        assert_eq!(ClosestBy::Edge.distance(&region(100, 400), &gene), 600);
+        assert_eq!(ClosestBy::Edge.distance(&region(1500, 1600), &gene), 0);
+        // Minus strand TSS is the gene end
+        assert_eq!(ClosestBy::Tss.distance(&region(2500, 2600), &gene), 550);
+        assert_eq!(ClosestBy::Tss.distance(&region(100, 400), &gene), 1750);
+        assert_eq!("TSS".parse::<ClosestBy>(), Ok(ClosestBy::Tss));
+        assert!("midpoint".parse::<ClosestBy>().is_err());
+    }
+
+    #[test]
+    fn test_sense_class() {
+        use Strand::{Negative, Positive};
+        assert_eq!(SenseClass::of(Some(Positive), Positive), SenseClass::Sense);
+        assert_eq!(
+            SenseClass::of(Some(Negative), Positive),
+            SenseClass::Antisense
+        );
+        assert_eq!(SenseClass::of(None, Negative), SenseClass::Unstranded);
+        assert_eq!("antisense".parse::<SenseClass>(), Ok(SenseClass::Antisense));
+        assert!("same".parse::<SenseClass>().is_err());
+        assert_eq!(CoordinateConvention::OneBased.as_str(), "one-based");
+
+        // GTF feature 101-200 (1-based) is BED 100-200
+        assert_eq!(CoordinateConvention::Bed0.from_one_based_start(101), 100);
+        assert_eq!(
+            CoordinateConvention::OneBased.from_one_based_start(101),
+            101
+        );
+    }
+
+    #[test]
+    fn test_name_reads_like_a_string() {
+        let name = Name::from("ENSG01");
+        assert_eq!(name, "ENSG01");
+        assert_eq!("ENSG01", name);
+        assert_eq!(name, "ENSG01".to_string());
+        assert_eq!(name.len(), 6);
+        assert_eq!(format!("{} {:?}", name, name), "ENSG01 \"ENSG01\"");
+        assert_eq!(String::from(name.clone()), "ENSG01");
+        assert!(Name::default().is_empty());
+
+        // A clone shares the text
+        assert_eq!(name.clone().as_ptr(), name.as_ptr());
+    }
+
+    #[test]
+    fn test_name_pool_interns() {
+        let mut pool = NamePool::new();
+        let first = pool.intern("T1");
+        let second = pool.intern("T1");
+        let other = pool.intern("T2");
+        assert_eq!(first.as_ptr(), second.as_ptr());
+        assert_ne!(first, other);
+        assert_eq!(pool.len(), 2);
+    }
+
+    #[test]
+    fn test_exon_numbers_join_and_parse() {
+        let merged: ExonNumbers = vec![
+            ExonNumber::Number(3),
+            ExonNumber::Intron(2),
+            ExonNumber::Number(10),
+        ]
+        .into();
+        assert_eq!(merged.len(), 3);
+        assert_eq!(merged.join(","), "3,intron_2,10");
+        assert_eq!(merged.join(" | "), "3 | intron_2 | 10");
+        assert_eq!(
+            format!("{} {:?}", merged, merged),
+            "3,intron_2,10 \"3,intron_2,10\""
+        );
+        assert_eq!("3,intron_2,10".parse::<ExonNumbers>().unwrap(), merged);
+        assert_eq!(merged, "3,intron_2,10");
+
+        let single = ExonNumbers::from(7);
+        assert_eq!(single.as_slice(), [ExonNumber::Number(7)]);
+        assert_eq!(single.join("|"), "7");
+        assert_eq!(ExonNumbers::from(vec![ExonNumber::Number(7)]), single);
+
+        let none = ExonNumbers::default();
+        assert!(none.is_empty());
+        assert_eq!(none.join(","), "");
+        assert_eq!("".parse::<ExonNumbers>().unwrap(), none);
+
+        for bad in ["NA", "1,,2", "intron_", "+1", "1|2"] {
+            assert!(bad.parse::<ExonNumbers>().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_exon_numbers_order_as_text() {
+        let parse = |text: &str| text.parse::<ExonNumbers>().unwrap();
+        let mut numbers = [
+            parse("9"),
+            parse("intron_1"),
+            parse("10"),
+            parse("1,2"),
+            parse("1"),
+            parse(""),
+        ];
+        numbers.sort();
+        let texts: Vec<String> = numbers.iter().map(ExonNumbers::to_string).collect();
+        let mut expected = texts.clone();
+        expected.sort();
+        assert_eq!(texts, expected);
+        assert_eq!(texts, ["", "1", "1,2", "10", "9", "intron_1"]);
+    }
+
+    #[test]
+    fn test_transcript_renumber_positive() {
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(500, 600));
+        transcript.add_exon(Exon::new(100, 200));
+        transcript.add_exon(Exon::new(300, 400));
+
+        transcript.renumber_exons(Strand::Positive);
+
+        // After sorting and numbering: 100-200 is 1, 300-400 is 2, 500-600 is 3
+        assert_eq!(transcript.exons[0].start, 100);
+        assert_eq!(transcript.exons[0].exon_number, Some(1));
+        assert_eq!(transcript.exons[1].start, 300);
+        assert_eq!(transcript.exons[1].exon_number, Some(2));
+        assert_eq!(transcript.exons[2].start, 500);
+        assert_eq!(transcript.exons[2].exon_number, Some(3));
+    }
+
+    #[test]
+    fn test_transcript_renumber_negative() {
+        let mut transcript = Transcript::new("T1".to_string());
+        transcript.add_exon(Exon::new(100, 200));
+        transcript.add_exon(Exon::new(300, 400));
+
+        transcript.renumber_exons(Strand::Negative);
+
+        // After sorting: 100-200 first, 300-400 second
+        // For negative strand: first (lowest) gets N, last (highest) gets 1
+        assert_eq!(transcript.exons[0].start, 100);
+        assert_eq!(transcript.exons[0].exon_number, Some(2));
+        assert_eq!(transcript.exons[1].start, 300);
+        assert_eq!(transcript.exons[1].exon_number, Some(1));
+    }
+
+    #[test]
+    fn test_renumber_builds_exon_index() {
+        let mut transcript = Transcript::new("T1");
+        transcript.add_exon(Exon::new(500, 600));
+        transcript.add_exon(Exon::new(100, 200));
+        transcript.add_exon(Exon::new(300, 400));
+        assert!(transcript.exon_index(Strand::Negative).is_none());
+
+        transcript.renumber_exons(Strand::Negative);
+        let index = transcript.exon_index(Strand::Negative).unwrap();
+        assert_eq!(index.starts, [100, 300, 500]);
+        assert_eq!(index.ends, [200, 400, 600]);
+        assert_eq!(index.introns, [(201, 299), (401, 499)]);
+        assert_eq!((index.first_exon, index.last_exon), (2, 0));
+        // Exon 1 is the rightmost: the TSS is at its end
+        assert!(!index.forward);
+        assert_eq!((index.tss, index.tts), (600, 100));
+        assert_eq!(index.tss_distance(550), 50);
+        assert_eq!(index.tts_distance(50), 50);
+        assert_eq!(index.exonic_length, transcript.exonic_length());
+        assert_eq!(index.junctions(), [200, 299, 400, 499]);
+
+        // An index for the other strand or for other exons is not used
+        assert!(transcript.exon_index(Strand::Positive).is_none());
+        transcript.add_exon(Exon::new(700, 800));
+        assert!(transcript.exon_index(Strand::Negative).is_none());
+    }
+
+    #[test]
+    fn test_exons_near_region() {
+        let mut transcript = Transcript::new("T1");
+        // The second exon overlaps the first, as unmerged annotations may
+        for (start, end) in [(100, 900), (200, 300), (1000, 1100), (2000, 2100)] {
+            transcript.add_exon(Exon::new(start, end));
+        }
+        transcript.renumber_exons(Strand::Positive);
+        let index = transcript.exon_index(Strand::Positive).unwrap();
+
+        // Within the long first exon, which still reaches the region
+        assert_eq!(index.exons_near(500, 600), 0..2);
+        // In the intron after the overlapping exons
+        assert_eq!(index.exons_near(950, 960), 1..2);
+        assert_eq!(index.exons_near(1050, 2050), 2..4);
+        // Downstream, only the last exon matters
+        assert_eq!(index.exons_near(5000, 5100), 3..4);
+        // Upstream, only the leftmost exon
+        assert_eq!(index.exons_near(10, 20), 0..1);
     }
 }
@@ -0,0 +1,403 @@
+//! Preflight checks of a GTF and BED file pair.
+//!
+//! [`validate`] parses both files, or the first bytes of each, and reports
+//! what a full run would match: record counts, the chromosomes the files
+//! share and the BED layout. It does no matching, so it takes seconds where
+//! the run may take hours.
+
+use ahash::AHashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, Cursor};
+use std::path::Path;
+
+use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::parser::gtf::{parse_gtf, parse_gtf_from_reader, GtfData};
+use crate::parser::util::create_buffered_reader;
+use crate::parser::BedReader;
+
+/// Regions read from the BED file at a time.
+const CHUNK_SIZE: usize = 10_000;
+
+/// BED-only chromosomes listed by name in the report.
+const LISTED_CHROMOSOMES: usize = 10;
+
+/// Counts of the annotation found in a GTF file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GtfStats {
+    /// Number of genes.
+    pub genes: usize,
+    /// Number of transcripts.
+    pub transcripts: usize,
+    /// Genes annotated without a strand.
+    pub unstranded_genes: usize,
+    /// Distinct transcript IDs found under more than one gene.
+    pub shared_transcript_ids: usize,
+    /// Chromosomes with at least one gene, sorted by name.
+    pub chromosomes: Vec<String>,
+    /// Whether only the first bytes of the file were read.
+    pub sampled: bool,
+}
+
+/// Counts and layout of the regions found in a BED file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BedStats {
+    /// Number of regions.
+    pub regions: usize,
+    /// Most columns found on a line, counting at most 9 beyond the
+    /// coordinates.
+    pub columns: usize,
+    /// Non-empty lines that are not regions: headers, `track` and `browser`
+    /// lines, and lines with fewer than 3 columns.
+    pub skipped_lines: usize,
+    /// Regions whose start and end were swapped.
+    pub swapped: usize,
+    /// Regions skipped because their start was greater than their end.
+    pub dropped: usize,
+    /// Number of regions on each chromosome, sorted by chromosome name.
+    pub chromosomes: Vec<(String, usize)>,
+    /// Whether only the first bytes of the file were read.
+    pub sampled: bool,
+}
+
+impl BedStats {
+    /// BED variant named by its column count, such as `BED6`.
+    pub fn format(&self) -> String {
+        format!("BED{}", self.columns)
+    }
+}
+
+/// What [`validate`] found in a GTF and BED file pair.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    /// The annotation.
+    pub gtf: GtfStats,
+    /// The regions.
+    pub bed: BedStats,
+}
+
+impl ValidationReport {
+    /// Chromosomes with both genes and regions, sorted by name.
+    pub fn shared_chromosomes(&self) -> Vec<&str> {
+        self.bed
+            .chromosomes
+            .iter()
+            .map(|(chrom, _)| chrom.as_str())
+            .filter(|chrom| self.gtf.chromosomes.iter().any(|c| c == chrom))
+            .collect()
+    }
+
+    /// Chromosomes with regions but no gene, and their number of regions.
+    pub fn bed_only_chromosomes(&self) -> Vec<(&str, usize)> {
+        self.bed
+            .chromosomes
+            .iter()
+            .filter(|(chrom, _)| !self.gtf.chromosomes.contains(chrom))
+            .map(|(chrom, regions)| (chrom.as_str(), *regions))
+            .collect()
+    }
+
+    /// Chromosomes with genes but no region.
+    pub fn gtf_only_chromosomes(&self) -> Vec<&str> {
+        self.gtf
+            .chromosomes
+            .iter()
+            .filter(|chrom| !self.bed.chromosomes.iter().any(|(c, _)| c == *chrom))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Issues a run would report as warnings.
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if self.gtf.shared_transcript_ids > 0 {
+            warnings.push(format!(
+                "{} transcript IDs are used by more than one gene",
+                self.gtf.shared_transcript_ids
+            ));
+        }
+        if self.gtf.unstranded_genes > 0 {
+            warnings.push(format!(
+                "{} genes have no strand",
+                self.gtf.unstranded_genes
+            ));
+        }
+        if self.bed.skipped_lines > 0 {
+            warnings.push(format!(
+                "{} BED lines are not regions (headers or fewer than 3 columns)",
+                self.bed.skipped_lines
+            ));
+        }
+        if self.bed.swapped > 0 {
+            warnings.push(format!(
+                "{} BED regions have start > end and were swapped",
+                self.bed.swapped
+            ));
+        }
+        if self.bed.dropped > 0 {
+            warnings.push(format!(
+                "{} BED regions have start > end and were skipped",
+                self.bed.dropped
+            ));
+        }
+        let bed_only = self.bed_only_chromosomes();
+        if !bed_only.is_empty() && !self.shared_chromosomes().is_empty() {
+            let regions: usize = bed_only.iter().map(|(_, regions)| regions).sum();
+            warnings.push(format!(
+                "{} region(s) on {} chromosome(s) not in the annotation",
+                regions,
+                bed_only.len()
+            ));
+        }
+        warnings
+    }
+
+    /// Issues that make a run pointless: a file without records, or no
+    /// chromosome in common.
+    pub fn errors(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+        if self.gtf.genes == 0 {
+            errors.push("The GTF file has no usable gene".to_string());
+        }
+        if self.bed.regions == 0 {
+            errors.push("The BED file has no region".to_string());
+        }
+        if self.gtf.genes > 0 && self.bed.regions > 0 && self.shared_chromosomes().is_empty() {
+            errors.push(
+                "No chromosome of the BED file is in the annotation; check the naming (e.g. chr1 vs 1)"
+                    .to_string(),
+            );
+        }
+        errors
+    }
+
+    /// Whether the files can be annotated: both have records and they
+    /// share a chromosome.
+    pub fn is_valid(&self) -> bool {
+        self.errors().is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sampled = |sampled: bool| if sampled { " (sampled)" } else { "" };
+        writeln!(
+            f,
+            "GTF: {} genes, {} transcripts on {} chromosomes{}",
+            self.gtf.genes,
+            self.gtf.transcripts,
+            self.gtf.chromosomes.len(),
+            sampled(self.gtf.sampled)
+        )?;
+        writeln!(
+            f,
+            "BED: {} regions on {} chromosomes, {} ({} columns){}",
+            self.bed.regions,
+            self.bed.chromosomes.len(),
+            self.bed.format(),
+            self.bed.columns,
+            sampled(self.bed.sampled)
+        )?;
+        writeln!(f, "Shared chromosomes: {}", self.shared_chromosomes().len())?;
+        let bed_only = self.bed_only_chromosomes();
+        if !bed_only.is_empty() {
+            let mut listed: Vec<String> = bed_only
+                .iter()
+                .take(LISTED_CHROMOSOMES)
+                .map(|(chrom, regions)| format!("{} ({})", chrom, regions))
+                .collect();
+            if bed_only.len() > LISTED_CHROMOSOMES {
+                listed.push(format!("{} more", bed_only.len() - LISTED_CHROMOSOMES));
+            }
+            writeln!(f, "BED chromosomes not in the GTF: {}", listed.join(", "))?;
+        }
+        writeln!(
+            f,
+            "GTF chromosomes without regions: {}",
+            self.gtf_only_chromosomes().len()
+        )?;
+        for warning in self.warnings() {
+            writeln!(f, "Warning: {}", warning)?;
+        }
+        for error in self.errors() {
+            writeln!(f, "Error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a GTF and a BED file and report what a run on them would match.
+///
+/// The ID tags and BED options come from `config`. With `sample_bytes`,
+/// only about that many bytes of each (decompressed) file are read, ending
+/// on a whole line. Files that cannot be read or parsed are an error; a file
+/// without records, or files without a chromosome in common, are reported
+/// by [`ValidationReport::errors`].
+pub fn validate(
+    gtf: &Path,
+    bed: &Path,
+    config: &Config,
+    sample_bytes: Option<u64>,
+) -> Result<ValidationReport> {
+    let (gtf_data, gtf_sampled) = match sample_bytes {
+        Some(limit) => {
+            let (sample, sampled) = read_sample(gtf, limit)?;
+            let data =
+                parse_gtf_from_reader(sample, &config.gene_id_tag, &config.transcript_id_tag)
+                    .map_err(|e| e.in_file(gtf))?;
+            (data, sampled)
+        }
+        None => (
+            parse_gtf(gtf, &config.gene_id_tag, &config.transcript_id_tag)?,
+            false,
+        ),
+    };
+
+    let (bed_reader, bed_sampled) = match sample_bytes {
+        Some(limit) => {
+            let (sample, sampled) = read_sample(bed, limit)?;
+            (BedReader::from_reader(sample), sampled)
+        }
+        None => (BedReader::new(bed)?, false),
+    };
+    let mut bed_stats = bed_stats(bed_reader.with_config(config)).map_err(|e| e.in_file(bed))?;
+    bed_stats.sampled = bed_sampled;
+
+    Ok(ValidationReport {
+        gtf: gtf_stats(&gtf_data, gtf_sampled),
+        bed: bed_stats,
+    })
+}
+
+/// Counts of a parsed annotation.
+fn gtf_stats(gtf: &GtfData, sampled: bool) -> GtfStats {
+    GtfStats {
+        genes: gtf.num_genes(),
+        transcripts: gtf
+            .genes_by_chrom
+            .values()
+            .flatten()
+            .map(|gene| gene.transcripts.len())
+            .sum(),
+        unstranded_genes: gtf.num_unstranded_genes(),
+        shared_transcript_ids: gtf.num_shared_transcript_ids(),
+        chromosomes: gtf.chromosomes().into_iter().map(String::from).collect(),
+        sampled,
+    }
+}
+
+/// Read every region of `reader`, counting them per chromosome.
+fn bed_stats(mut reader: BedReader) -> Result<BedStats> {
+    let mut per_chrom: AHashMap<String, usize> = AHashMap::new();
+    let mut regions = 0;
+    while let Some(chunk) = reader.read_chunk(CHUNK_SIZE)? {
+        regions += chunk.len();
+        for region in chunk {
+            *per_chrom.entry(region.chrom).or_default() += 1;
+        }
+    }
+    let mut chromosomes: Vec<(String, usize)> = per_chrom.into_iter().collect();
+    chromosomes.sort_unstable();
+
+    Ok(BedStats {
+        regions,
+        columns: if regions > 0 {
+            3 + reader.num_meta_columns()
+        } else {
+            0
+        },
+        skipped_lines: reader.num_skipped(),
+        swapped: reader.num_swapped(),
+        dropped: reader.num_dropped(),
+        chromosomes,
+        sampled: false,
+    })
+}
+
+/// Read the first lines of a file, decompressed, until at least `limit`
+/// bytes. Also returns whether the file goes on.
+fn read_sample(path: &Path, limit: u64) -> Result<(Cursor<Vec<u8>>, bool)> {
+    let file = File::open(path).map_err(|e| Error::from(e).in_file(path))?;
+    let mut reader = create_buffered_reader(file, path);
+    sample_lines(&mut reader, limit)
+        .map(|(sample, truncated)| (Cursor::new(sample), truncated))
+        .map_err(|e| Error::from(e).in_file(path))
+}
+
+/// Whole lines of `reader` up to at least `limit` bytes, and whether any
+/// input is left.
+fn sample_lines(reader: &mut dyn BufRead, limit: u64) -> io::Result<(Vec<u8>, bool)> {
+    let mut sample = Vec::new();
+    while (sample.len() as u64) < limit {
+        if reader.read_until(b'\n', &mut sample)? == 0 {
+            return Ok((sample, false));
+        }
+    }
+    let truncated = !reader.fill_buf()?.is_empty();
+    Ok((sample, truncated))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_lines_ends_on_whole_line() {
+        let mut input = Cursor::new(b"aaaa\nbbbb\ncccc\n".to_vec());
+        let (sample, truncated) = sample_lines(&mut input, 7).unwrap();
+        assert_eq!(sample, b"aaaa\nbbbb\n");
+        assert!(truncated);
+
+        let mut input = Cursor::new(b"aaaa\nbbbb".to_vec());
+        let (sample, truncated) = sample_lines(&mut input, 100).unwrap();
+        assert_eq!(sample, b"aaaa\nbbbb");
+        assert!(!truncated);
+
+        // Reaching the limit at the end of the input leaves nothing behind
+        let mut input = Cursor::new(b"aaaa\n".to_vec());
+        let (_, truncated) = sample_lines(&mut input, 5).unwrap();
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_report_chromosome_overlap() {
+        let report = ValidationReport {
+            gtf: GtfStats {
+                genes: 3,
+                transcripts: 4,
+                chromosomes: vec!["chr1".to_string(), "chr2".to_string()],
+                ..Default::default()
+            },
+            bed: BedStats {
+                regions: 5,
+                columns: 6,
+                chromosomes: vec![("chr1".to_string(), 4), ("chrUn".to_string(), 1)],
+                ..Default::default()
+            },
+        };
+        assert_eq!(report.shared_chromosomes(), vec!["chr1"]);
+        assert_eq!(report.bed_only_chromosomes(), vec![("chrUn", 1)]);
+        assert_eq!(report.gtf_only_chromosomes(), vec!["chr2"]);
+        assert_eq!(report.bed.format(), "BED6");
+        assert_eq!(
+            report.warnings(),
+            vec!["1 region(s) on 1 chromosome(s) not in the annotation"]
+        );
+        assert!(report.is_valid());
+    }
+
+    #[test]
+    fn test_report_errors() {
+        let mut report = ValidationReport::default();
+        assert_eq!(report.errors().len(), 2);
+
+        report.gtf.genes = 1;
+        report.gtf.chromosomes = vec!["chr1".to_string()];
+        report.bed.regions = 2;
+        report.bed.chromosomes = vec![("1".to_string(), 2)];
+        assert_eq!(report.errors().len(), 1);
+        assert!(report.errors()[0].starts_with("No chromosome of the BED file"));
+        assert!(!report.is_valid());
+    }
+}
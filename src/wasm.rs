@@ -0,0 +1,183 @@
+//! JavaScript entry point for WebAssembly builds (`wasm` feature).
+//!
+//! `wasm32-unknown-unknown` has no file system, so the GTF and BED data are
+//! passed as text and the associations come back as JSON lines, one object
+//! per BED region in input order.
+
+use std::io::Cursor;
+
+use serde_json::json;
+use wasm_bindgen::prelude::*;
+
+use crate::config::{Config, ConfigBuilder};
+use crate::error::{Error, Result};
+use crate::output::candidates_to_write;
+use crate::parser::{parse_gtf_from_reader, BedReader};
+use crate::pipeline::annotate_bed_reader;
+use crate::types::{Candidate, Region};
+
+/// Annotate BED text with the genes of GTF text and return JSON lines.
+///
+/// `config_json` holds `Config` fields as a JSON object, like the keys of a
+/// TOML configuration file; an empty string uses the defaults. Each line is
+/// `{"chrom", "start", "end", "line", "metadata", "candidates"}` with the
+/// BED coordinates and 1-based line of the region and the candidates that
+/// `best_only` keeps.
+/// Errors are thrown as JavaScript exceptions.
+#[wasm_bindgen]
+pub fn annotate_strings(
+    gtf: &str,
+    bed: &str,
+    config_json: &str,
+) -> std::result::Result<String, JsError> {
+    Ok(annotate_to_jsonl(gtf, bed, config_json)?)
+}
+
+/// [`annotate_strings`] with the library error type, usable outside
+/// JavaScript.
+pub fn annotate_to_jsonl(gtf: &str, bed: &str, config_json: &str) -> Result<String> {
+    let config = parse_config_json(config_json)?;
+    let mut gtf_data = parse_gtf_from_reader(
+        gtf.as_bytes(),
+        &config.gene_id_tag,
+        &config.transcript_id_tag,
+    )?;
+    if config.strict_transcript_ids {
+        gtf_data.check_shared_transcript_ids()?;
+    }
+    if config.merge_overlapping_exons {
+        gtf_data.merge_overlapping_exons();
+    }
+    if !config.transcript_filters.is_empty() {
+        gtf_data.filter_transcripts(&config.transcript_filters);
+    }
+    let bed_reader =
+        BedReader::from_reader(Cursor::new(bed.as_bytes().to_vec())).with_config(&config);
+    let result = annotate_bed_reader(&gtf_data, bed_reader, &config, &mut |_| {})?;
+
+    let mut jsonl = String::new();
+    for association in &result.associations {
+        let region = &association.region;
+        // Every line has the same number of metadata values, null if missing
+        let mut metadata: Vec<Option<&str>> =
+            region.metadata.iter().map(|m| Some(m.as_str())).collect();
+        metadata.resize(result.num_meta_columns, None);
+        let candidates: Vec<serde_json::Value> =
+            candidates_to_write(&association.candidates, &config)
+                .iter()
+                .map(|candidate| candidate_json(region, candidate, &config))
+                .collect();
+        let line = json!({
+            "chrom": region.chrom,
+            "start": region.start,
+            "end": region.end,
+            "line": region.line,
+            "metadata": metadata,
+            "candidates": candidates,
+        });
+        jsonl.push_str(&line.to_string());
+        jsonl.push('\n');
+    }
+    Ok(jsonl)
+}
+
+/// Parse and validate a JSON configuration; blank text gives the defaults.
+fn parse_config_json(text: &str) -> Result<Config> {
+    let config = if text.trim().is_empty() {
+        Config::default()
+    } else {
+        serde_json::from_str(text).map_err(Error::ConfigJson)?
+    };
+    Ok(ConfigBuilder::from_config(config).build()?)
+}
+
+/// JSON object of a candidate; inapplicable percentages are `null`, as are
+/// the gene fields of an intergenic region.
+fn candidate_json(region: &Region, candidate: &Candidate, config: &Config) -> serde_json::Value {
+    let genic = !candidate.is_intergenic();
+    json!({
+        "gene": genic.then_some(&candidate.gene),
+        "transcript": genic.then_some(&candidate.transcript),
+        "exon_number": genic.then(|| candidate.exon_number.join(&config.merge_separator)),
+        "area": candidate.area.as_str(),
+        "strand": genic.then_some(candidate.strand.as_str()),
+        "distance": candidate.feature_distance(),
+        "tss_distance": genic.then_some(candidate.tss_distance),
+        "pctg_region": candidate.region_percentage(),
+        "pctg_area": candidate.area_percentage(),
+        "pctg_gene": candidate.gene_percentage(region),
+        "junction_distance": candidate.junction_distance,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GTF: &str = "\
+chr1\tTEST\texon\t10000\t10500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t11500\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+";
+
+    #[test]
+    fn test_annotate_to_jsonl() {
+        let bed = "chr1\t9900\t9950\tr1\nchr9\t100\t200\tmissing\n";
+        let jsonl = annotate_to_jsonl(GTF, bed, "").unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["metadata"], json!(["r1"]));
+        assert_eq!(lines[0]["candidates"][0]["gene"], "G1");
+        assert_eq!(lines[0]["candidates"][0]["area"], "TSS");
+        assert_eq!(lines[1]["chrom"], "chr9");
+        assert_eq!(lines[1]["candidates"], json!([]));
+    }
+
+    #[test]
+    fn test_annotate_to_jsonl_intergenic() {
+        let bed = "chr1\t30000\t30100\tfar\nchr9\t100\t200\tmissing\n";
+        let jsonl = annotate_to_jsonl(GTF, bed, r#"{"report_intergenic": true}"#).unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let far = &lines[0]["candidates"][0];
+        assert_eq!(far["area"], "INTERGENIC");
+        assert_eq!(far["gene"], json!(null));
+        assert_eq!(far["distance"], 18000);
+        assert_eq!(far["pctg_region"], json!(null));
+        assert_eq!(lines[1]["candidates"][0]["distance"], json!(null));
+    }
+
+    #[test]
+    fn test_annotate_to_jsonl_ragged_metadata() {
+        let bed = "chr1\t9900\t9950\tr1\nchr1\t9800\t9850\tr2\t0\t+\n";
+        let jsonl = annotate_to_jsonl(GTF, bed, "").unwrap();
+        let lines: Vec<serde_json::Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines[0]["metadata"], json!(["r1", null, null]));
+        assert_eq!(lines[1]["metadata"], json!(["r2", "0", "+"]));
+    }
+
+    #[test]
+    fn test_config_json() {
+        let config = parse_config_json(r#"{"level": "gene", "distance": 5000}"#).unwrap();
+        assert_eq!(config.distance, 5000);
+
+        assert!(matches!(
+            parse_config_json(r#"{"bogus": 1}"#),
+            Err(Error::ConfigJson(_))
+        ));
+        assert!(matches!(
+            parse_config_json(r#"{"perc_area": 150}"#),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+}
@@ -1,4 +1,8 @@
+// Runs the binary and reads files, neither of which exists on wasm32
+#![cfg(not(target_arch = "wasm32"))]
+
 use assert_cmd::Command;
+use rgmatch::output::diff::{diff_files, DiffOptions};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -34,9 +38,21 @@ fn run_golden_test(
         .arg(output_path)
         .arg("-r")
         .arg(report_level)
+        .arg("--no-provenance")
+        .arg("--legacy-sentinel")
+        .arg("--legacy-merge")
         .assert()
         .success();
 
+    // Report the rows and values that differ, then check the exact text
+    let diff = diff_files(&golden_path, output_path, &DiffOptions::default())?;
+    assert!(
+        diff.is_identical(),
+        "Output differs from {} (A):\n{}",
+        golden_filename,
+        diff
+    );
+
     // Open files for streaming comparison
     let output_reader = BufReader::new(File::open(output_path)?);
     let golden_reader = BufReader::new(File::open(&golden_path)?);
@@ -84,3 +100,2840 @@ fn test_golden_output_transcript() -> Result<(), Box<dyn std::error::Error>> {
 fn test_golden_output_gene() -> Result<(), Box<dyn std::error::Error>> {
     run_golden_test("gene", "subset_golden_output_gene.txt")
 }
+
+/// Paths to the bundled GTF and BED fixtures.
+fn fixture_paths() -> (std::path::PathBuf, std::path::PathBuf) {
+    let data_dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data");
+    (
+        data_dir.join("subset_genome.gtf"),
+        data_dir.join("subset_peaks.bed"),
+    )
+}
+
+#[test]
+fn test_output_to_stdout() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let golden_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("data")
+        .join("subset_golden_output_gene.txt");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("-r")
+        .arg("gene")
+        .args(["--legacy-sentinel", "--legacy-merge"])
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let golden = std::fs::read_to_string(golden_path)?;
+    // Provenance comment lines come first and are skipped like any '#' line
+    assert!(stdout.starts_with("# rgmatch_version: "));
+    let mut stdout_lines = stdout.lines().filter(|l| !l.starts_with('#'));
+    let mut golden_lines = golden.lines();
+
+    // Header plus the first few association rows
+    for _ in 0..5 {
+        assert_eq!(stdout_lines.next(), golden_lines.next());
+    }
+
+    // Status messages stay on stderr
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Writing output to: stdout"));
+    assert!(!stdout.contains("Parsing GTF file"));
+
+    Ok(())
+}
+
+#[test]
+fn test_quiet_suppresses_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let output_file = NamedTempFile::new()?;
+
+    for threads in ["1", "4"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(&bed_path)
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["--quiet", "-j", threads])
+            .output()?;
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stderr)?, "", "threads={}", threads);
+        assert!(std::fs::metadata(output_file.path())?.len() > 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_chromosome_warned_once() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf_file = NamedTempFile::new()?;
+    std::fs::write(
+        gtf_file.path(),
+        "chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n",
+    )?;
+    let bed_file = NamedTempFile::new()?;
+    std::fs::write(
+        bed_file.path(),
+        "track name=peaks\nchr1\t900\t950\nchrX\t10\t20\nchrX\t30\t40\nchrX\t50\t60\nchrY\t10\t20\n",
+    )?;
+    let output_file = NamedTempFile::new()?;
+
+    for threads in ["1", "4"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf_file.path())
+            .arg("-b")
+            .arg(bed_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", threads])
+            .output()?;
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert_eq!(stderr.matches("[WARN]").count(), 1, "{}", stderr);
+        assert!(stderr.contains(
+            "[WARN] 2 chromosome(s) not found in the annotation, with 4 region(s) without genes: chrX (3), chrY (1)"
+        ));
+        // Skipped lines are only reported with --verbose given twice
+        assert!(!stderr.contains("BED line 1"));
+
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf_file.path())
+            .arg("-b")
+            .arg(bed_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", threads, "--verbose"])
+            .output()?;
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.contains("[DEBUG] chr1: 1 regions, "), "{}", stderr);
+        assert!(stderr.contains("[DEBUG] chrX: 3 regions, 0 candidates in "));
+        assert!(!stderr.contains("BED line 1"));
+
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf_file.path())
+            .arg("-b")
+            .arg(bed_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", threads, "--verbose", "--verbose"])
+            .output()?;
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.contains("[TRACE] BED line 1: skipped"), "{}", stderr);
+
+        // Nothing but errors with --quiet, warnings included
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf_file.path())
+            .arg("-b")
+            .arg(bed_file.path())
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-j", threads, "--quiet"])
+            .output()?;
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stderr)?, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_log_file_duplicates_stderr() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let output_file = NamedTempFile::new()?;
+    let log_file = NamedTempFile::new()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output_file.path())
+        .arg("--log-file")
+        .arg(log_file.path())
+        .output()?;
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("Done!"), "{}", stderr);
+    assert_eq!(std::fs::read_to_string(log_file.path())?, stderr);
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_chromosomes_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with(
+        "chr1\t900\t950\nchrX\t10\t20\nchrX\t30\t40\nchrUn_1\t10\t20\n",
+        ".bed",
+    )?;
+    // Every region on a chromosome the annotation lacks
+    let mismatched = temp_file_with("1\t900\t950\n2\t10\t20\n", ".bed")?;
+    let summary = temp_file_with("", ".json")?;
+
+    for threads in ["1", "4"] {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "-j", threads, "--summary"])
+            .arg(summary.path())
+            .output()?;
+        assert!(output.status.success());
+        let json = std::fs::read_to_string(summary.path())?;
+        assert!(
+            json.contains(
+                r#""missing_chromosomes": {"chromosomes": 2, "regions": 3, "top": [{"chrom": "chrX", "regions": 2}, {"chrom": "chrUn_1", "regions": 1}]}"#
+            ),
+            "{}",
+            json
+        );
+
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(mismatched.path())
+            .args(["-o", "-", "-j", threads])
+            .output()?;
+        assert!(!output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert_eq!(stderr.matches("[WARN]").count(), 1, "{}", stderr);
+        assert!(
+            stderr.contains("None of the 2 BED regions is on a chromosome of the annotation"),
+            "{}",
+            stderr
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_output_to_closed_pipe_exits_cleanly() -> Result<(), Box<dyn std::error::Error>> {
+    use std::process::Stdio;
+
+    let (gtf_path, bed_path) = fixture_paths();
+
+    for threads in ["1", "4"] {
+        let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(&bed_path)
+            .arg("-o")
+            .arg("-")
+            .arg("-j")
+            .arg(threads)
+            .arg("--no-provenance")
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Read only the header, then close the pipe like `head -n 1` would
+        let mut reader = BufReader::new(child.stdout.take().unwrap());
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        assert!(header.starts_with("Region\tMidpoint"));
+        drop(reader);
+
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(output.status.success(), "threads={}: {}", threads, stderr);
+        assert!(!stderr.contains("panicked"));
+    }
+
+    Ok(())
+}
+
+/// Run rgmatch on the bundled fixtures and return the output file contents.
+fn run_on_fixtures(extra_args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let output_file = NamedTempFile::new()?;
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output_file.path())
+        .arg("--no-provenance")
+        .args(extra_args)
+        .assert()
+        .success();
+
+    Ok(std::fs::read_to_string(output_file.path())?)
+}
+
+#[test]
+fn test_threads_produce_identical_output() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let mut outputs = Vec::new();
+
+    for threads in ["1", "4"] {
+        let output_file = NamedTempFile::new()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(&bed_path)
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["--no-provenance", "--threads", threads])
+            .output()?;
+        assert!(output.status.success());
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(
+            stderr.contains(&format!("Threads: {}\n", threads)),
+            "{}",
+            stderr
+        );
+        outputs.push(std::fs::read_to_string(output_file.path())?);
+    }
+    assert_eq!(outputs[0], outputs[1]);
+
+    Ok(())
+}
+
+#[test]
+fn test_provenance_header() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let rules = "GENE_BODY,TSS,1st_EXON,PROMOTER,TTS,INTRON,UPSTREAM,DOWNSTREAM";
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("-R")
+        .arg(rules)
+        .arg("-v")
+        .arg("75")
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let comments: Vec<&str> = stdout.lines().take_while(|l| l.starts_with('#')).collect();
+    let gtf_size = std::fs::metadata(&gtf_path)?.len();
+
+    assert!(comments[1].starts_with("# command: "));
+    assert!(comments[1].contains(rules));
+    assert!(comments
+        .contains(&format!("# input_gtf: {} ({} bytes)", gtf_path.display(), gtf_size).as_str()));
+    assert!(comments.contains(&format!("# rules: {}", rules).as_str()));
+    assert!(comments.contains(&"# perc_area: 75"));
+    assert!(stdout
+        .lines()
+        .nth(comments.len())
+        .unwrap()
+        .starts_with("Region\t"));
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_matches_cli() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::{annotate, Config, ReportLevel};
+
+    let (gtf_path, bed_path) = fixture_paths();
+
+    for (level, name) in [
+        (ReportLevel::Exon, "exon"),
+        (ReportLevel::Transcript, "transcript"),
+        (ReportLevel::Gene, "gene"),
+    ] {
+        let config = Config {
+            level,
+            ..Config::default()
+        };
+        let result = annotate(&gtf_path, &bed_path, &config)?;
+
+        let mut library = Vec::new();
+        result.write_tsv_to(&mut library)?;
+        let cli = run_on_fixtures(&["-r", name])?;
+        assert_eq!(String::from_utf8(library)?, cli, "report level {}", name);
+
+        let bed_lines = std::fs::read_to_string(&bed_path)?.lines().count();
+        assert_eq!(result.associations.len(), bed_lines);
+        assert_eq!(result.summary.total_regions, bed_lines as u64);
+        assert_eq!(result.num_meta_columns, 3);
+    }
+
+    // Both command-line modes, with the options that search every gene of
+    // the chromosome
+    let gene_level = Config {
+        level: ReportLevel::Gene,
+        ..Config::default()
+    };
+    let options: [(&[&str], Config); 3] = [
+        (
+            &["--closest"],
+            Config {
+                always_closest: true,
+                ..Config::default()
+            },
+        ),
+        (
+            &["--report-intergenic", "-r", "gene"],
+            Config {
+                report_intergenic: true,
+                ..gene_level.clone()
+            },
+        ),
+        (
+            &["--n-closest", "2", "-r", "gene"],
+            Config {
+                n_closest: 2,
+                ..gene_level
+            },
+        ),
+    ];
+    for (args, config) in options {
+        let mut library = Vec::new();
+        annotate(&gtf_path, &bed_path, &config)?.write_tsv_to(&mut library)?;
+        let library = String::from_utf8(library)?;
+        for threads in [&["-j", "1"][..], &["-j", "4", "--batch-size", "100"]] {
+            let cli = run_on_fixtures(&[args, threads].concat())?;
+            assert_eq!(library, cli, "{:?} {:?}", args, threads);
+        }
+    }
+
+    // The file writer produces the same table
+    let result = annotate(&gtf_path, &bed_path, &Config::default())?;
+    let output = NamedTempFile::new()?;
+    result.write_tsv(output.path())?;
+    assert_eq!(
+        std::fs::read_to_string(output.path())?,
+        run_on_fixtures(&[])?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_timings() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::{annotate, Config};
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let result = annotate(&gtf_path, &bed_path, &Config::default())?;
+    let timings = &result.timings;
+
+    assert!(timings.gtf_parse_secs > 0.0);
+    assert!(timings.bed_parse_secs >= 0.0);
+    assert!(timings.matching_secs() > 0.0);
+    assert_eq!(timings.write_secs, 0.0);
+    assert!(timings.total_secs >= timings.gtf_parse_secs + timings.bed_parse_secs);
+    assert_eq!(timings.regions, 2400);
+    assert!(timings.regions_per_sec() > 0.0);
+    let peak = result
+        .associations
+        .iter()
+        .map(|association| association.candidates.len())
+        .max();
+    assert_eq!(Some(timings.peak_candidates), peak);
+    let slowest = timings.slowest_chromosomes(5);
+    assert_eq!(slowest.len(), 5);
+    assert!(slowest.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+    assert_eq!(slowest[0].1, timings.chromosome_secs(slowest[0].0));
+    assert_eq!(result.summary.timings.as_ref().unwrap().regions, 2400);
+
+    Ok(())
+}
+
+#[test]
+fn test_timings_in_summary() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    for threads in ["1", "4"] {
+        let summary = tempfile::Builder::new().suffix(".json").tempfile()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(&bed_path)
+            .args(["-o", "-", "-j", threads, "--summary"])
+            .arg(summary.path())
+            .output()?;
+        assert!(output.status.success());
+
+        let stderr = String::from_utf8(output.stderr)?;
+        assert!(stderr.contains("Timings:\n"), "{}", stderr);
+        assert!(stderr.contains("  Peak candidates "));
+        assert!(stderr.contains("  Slowest chromosomes: chr"));
+
+        let json = std::fs::read_to_string(summary.path())?;
+        let line = json
+            .lines()
+            .find(|line| line.starts_with("  \"timings\": {"))
+            .expect("timings in the summary");
+        for key in [
+            "gtf_parse_secs",
+            "bed_parse_secs",
+            "matching_secs",
+            "write_secs",
+            "total_secs",
+            "regions_per_sec",
+        ] {
+            let value: f64 = line
+                .split(&format!("\"{}\": ", key))
+                .nth(1)
+                .and_then(|rest| rest.split([',', '}']).next())
+                .unwrap()
+                .parse()?;
+            assert!(value >= 0.0, "{} = {}", key, value);
+        }
+        assert!(line.contains("\"peak_candidates\": 858, "), "{}", line);
+        assert_eq!(line.matches("{\"chrom\": \"chr").count(), 5);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_source_matches_annotate() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::pipeline::load_annotation;
+    use rgmatch::{annotate, annotate_source, Config, ReportLevel, VecSource};
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let config = Config {
+        level: ReportLevel::Gene,
+        ..Config::default()
+    };
+
+    // The same genes, handed over as plain Gene values
+    let gtf = load_annotation(&gtf_path, &config)?;
+    let source = VecSource::new(gtf.genes_by_chrom.clone());
+
+    let mut expected = Vec::new();
+    annotate(&gtf_path, &bed_path, &config)?.write_tsv_to(&mut expected)?;
+    let mut actual = Vec::new();
+    annotate_source(&source, &bed_path, &config)?.write_tsv_to(&mut actual)?;
+    assert_eq!(String::from_utf8(actual)?, String::from_utf8(expected)?);
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_chunks_threads_keep_input_order() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::parser::BedReader;
+    use rgmatch::pipeline::{annotate_chunks, load_annotation};
+    use rgmatch::{annotate, Config};
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let config = Config::default();
+    let gtf = load_annotation(&gtf_path, &config)?;
+    let expected = annotate(&gtf_path, &bed_path, &config)?;
+
+    // Small chunks, so the workers finish them out of order
+    let mut bed_reader = BedReader::new(&bed_path)?.with_config(&config);
+    let chunks = std::iter::from_fn(|| bed_reader.read_chunk(7).transpose());
+    let mut associations = Vec::new();
+    let mut sizes = Vec::new();
+    let summary = annotate_chunks(
+        &gtf,
+        chunks,
+        &config,
+        Some(4),
+        None,
+        &mut |chunk| {
+            sizes.push(chunk.len());
+            associations.extend(chunk);
+            Ok(())
+        },
+        &mut |_| {},
+    )?;
+
+    assert!(sizes.len() > 1);
+    // Association has no PartialEq; compare the debug output
+    assert_eq!(
+        format!("{:?}", associations),
+        format!("{:?}", expected.associations)
+    );
+    assert_eq!(summary.total_regions, expected.summary.total_regions);
+    assert_eq!(
+        summary.associated_regions,
+        expected.summary.associated_regions
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_shared_annotation_across_threads() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::pipeline::load_annotation;
+    use rgmatch::{annotate, annotate_source, Config, RegionAnnotator, ReportLevel};
+    use std::sync::Arc;
+    use std::thread;
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let config = Config {
+        level: ReportLevel::Transcript,
+        ..Config::default()
+    };
+
+    // A second BED file: every other fixture region, in reverse order
+    let second_bed = NamedTempFile::new()?;
+    let lines: Vec<String> = std::fs::read_to_string(&bed_path)?
+        .lines()
+        .step_by(2)
+        .map(|line| format!("{}\n", line))
+        .collect();
+    std::fs::write(
+        second_bed.path(),
+        lines.into_iter().rev().collect::<String>(),
+    )?;
+    let beds = [bed_path.clone(), second_bed.path().to_path_buf()];
+
+    let single_threaded = beds
+        .iter()
+        .map(|bed| -> Result<String, Box<dyn std::error::Error>> {
+            let mut tsv = Vec::new();
+            annotate(&gtf_path, bed, &config)?.write_tsv_to(&mut tsv)?;
+            Ok(String::from_utf8(tsv)?)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // One annotation, loaded once and shared without copies
+    let gtf = Arc::new(load_annotation(&gtf_path, &config)?);
+    let handles: Vec<_> = beds
+        .iter()
+        .cloned()
+        .map(|bed| {
+            let gtf = Arc::clone(&gtf);
+            let config = config.clone();
+            thread::spawn(move || {
+                let mut tsv = Vec::new();
+                annotate_source(&gtf, &bed, &config)
+                    .unwrap()
+                    .write_tsv_to(&mut tsv)
+                    .unwrap();
+
+                // Region by region through an annotator over the same Arc
+                let mut annotator = RegionAnnotator::new(Arc::clone(&gtf), config);
+                let mut reader = rgmatch::BedReader::new(&bed).unwrap();
+                let mut num_candidates = 0;
+                while let Some(chunk) = reader.read_chunk(100).unwrap() {
+                    for region in chunk {
+                        num_candidates += annotator.annotate_region(&region).len();
+                    }
+                }
+                (String::from_utf8(tsv).unwrap(), num_candidates)
+            })
+        })
+        .collect();
+
+    for (handle, expected) in handles.into_iter().zip(&single_threaded) {
+        let (tsv, num_candidates) = handle.join().unwrap();
+        assert_eq!(&tsv, expected);
+        let rows = expected
+            .lines()
+            .filter(|l| !l.starts_with('#') && !l.starts_with("Region\t"))
+            .count();
+        assert_eq!(num_candidates, rows);
+    }
+    assert_eq!(Arc::strong_count(&gtf), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_region_annotator_shuffled_matches_batch() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::pipeline::load_annotation;
+    use rgmatch::{annotate, Config, RegionAnnotator, ReportLevel};
+
+    let (gtf_path, bed_path) = fixture_paths();
+
+    for level in [
+        ReportLevel::Exon,
+        ReportLevel::Transcript,
+        ReportLevel::Gene,
+    ] {
+        let config = Config {
+            level,
+            ..Config::default()
+        };
+        let batch = annotate(&gtf_path, &bed_path, &config)?;
+
+        // Deterministic Fisher-Yates shuffle mixing chromosomes and positions
+        let mut order: Vec<usize> = (0..batch.associations.len()).collect();
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        for i in (1..order.len()).rev() {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            order.swap(i, (state >> 33) as usize % (i + 1));
+        }
+
+        let mut annotator = RegionAnnotator::new(load_annotation(&gtf_path, &config)?, config);
+        let shuffled = order.iter().map(|&i| batch.associations[i].region.clone());
+        let streamed: Vec<_> = annotator.annotate_iter(shuffled).collect();
+
+        assert_eq!(streamed.len(), batch.associations.len());
+        for (&i, association) in order.iter().zip(&streamed) {
+            let expected = &batch.associations[i];
+            assert_eq!(association.region.id(), expected.region.id());
+            assert_eq!(
+                format!("{:?}", association.candidates),
+                format!("{:?}", expected.candidates),
+                "region {} at {:?} level",
+                association.region.id(),
+                level
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_progress_callback() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::{annotate_with_progress, Config, Phase, Progress};
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let mut updates: Vec<Progress> = Vec::new();
+    let result = annotate_with_progress(&gtf_path, &bed_path, &Config::default(), |p| {
+        updates.push(p)
+    })?;
+    let output = NamedTempFile::new()?;
+    result.write_tsv_with_progress(output.path(), |p| updates.push(p))?;
+
+    let step = |phase: &Phase| match phase {
+        Phase::ParsingGtf => 0,
+        Phase::ParsingBed => 1,
+        Phase::Matching { .. } => 2,
+        Phase::Writing => 3,
+    };
+
+    // Phases come in pipeline order and each counter only grows
+    for pair in updates.windows(2) {
+        let (a, b) = (&pair[0], &pair[1]);
+        assert!(step(&a.phase) <= step(&b.phase), "{:?} before {:?}", a, b);
+        if step(&a.phase) == step(&b.phase) {
+            assert!(a.done <= b.done, "{:?} before {:?}", a, b);
+        }
+    }
+    for update in &updates {
+        if let Some(total) = update.total {
+            assert!(update.done <= total, "{:?}", update);
+        }
+    }
+
+    // Every phase runs and ends complete
+    let num_regions = result.associations.len() as u64;
+    for phase in 0..4 {
+        let last = updates
+            .iter()
+            .rfind(|p| step(&p.phase) == phase)
+            .unwrap_or_else(|| panic!("no update for phase {}", phase));
+        assert_eq!(Some(last.done), last.total, "{:?}", last);
+        if phase > 0 {
+            assert_eq!(last.done, num_regions);
+        }
+    }
+
+    // Matching reports every chromosome of the input
+    let mut expected: Vec<&str> = result
+        .associations
+        .iter()
+        .map(|a| a.region.chrom.as_str())
+        .collect();
+    expected.dedup();
+    let mut reported: Vec<&str> = updates
+        .iter()
+        .filter_map(|p| match &p.phase {
+            Phase::Matching { chrom } => Some(chrom.as_str()),
+            _ => None,
+        })
+        .collect();
+    reported.dedup();
+    assert_eq!(reported, expected);
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_unassigned_regions() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::{annotate, Config};
+
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\nchr1\t62000\t62100\tfar\nchr9\t100\t200\tmissing\n",
+        ".bed",
+    )?;
+
+    let result = annotate(gtf.path(), bed.path(), &Config::default())?;
+    assert_eq!(result.num_unassigned(), 2);
+    let genes: Vec<Vec<&str>> = result
+        .associations
+        .iter()
+        .map(|a| a.candidates.iter().map(|c| c.gene.as_str()).collect())
+        .collect();
+    assert_eq!(genes, vec![vec!["G1"], vec![], vec![]]);
+
+    Ok(())
+}
+
+#[test]
+fn test_exclusive_boundaries() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // Regions 200 bp (the TSS width) and 1500 bp (TSS + PROMOTER) upstream
+    // of the TSS at 10000
+    let bed = temp_file_with(
+        "chr1\t9800\t9801\ttss\nchr1\t8500\t8501\tpromoter\n",
+        ".bed",
+    )?;
+
+    let areas = |extra: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance"])
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(|line| line.split('\t').nth(5).unwrap_or_default().to_string())
+            .collect())
+    };
+
+    assert_eq!(areas(&[])?, vec!["TSS", "PROMOTER"]);
+    assert_eq!(
+        areas(&["--exclusive-boundaries"])?,
+        vec!["PROMOTER", "UPSTREAM"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_crlf_input_files() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(&SMALL_GTF.replace('\n', "\t\r\n"), ".gtf")?;
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\t\r\nchr1\t10100\t10200\tr2\r\n",
+        ".bed",
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .args(["-o", "-", "--no-provenance"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(!stdout.contains('\r'), "{:?}", stdout);
+    let rows: Vec<Vec<&str>> = stdout.lines().map(|l| l.split('\t').collect()).collect();
+    // One metadata column, not a phantom empty one from the trailing tab
+    assert_eq!(rows[0].last(), Some(&"name"));
+    let names: Vec<&str> = rows[1..].iter().map(|row| *row.last().unwrap()).collect();
+    assert_eq!(names, vec!["r1", "r2"]);
+    assert!(rows.iter().all(|row| row.len() == rows[0].len()));
+
+    Ok(())
+}
+
+#[test]
+fn test_unlimited_distance() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t600000\t601000\t.\t+\t.\tgene_id \"FAR\"; transcript_id \"T1\";\n",
+        ".gtf",
+    )?;
+    // 500 kb upstream of the gene
+    let bed = temp_file_with("chr1\t100000\t100010\tr1\n", ".bed")?;
+
+    let rows = |distance: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-q", distance])
+            .output()?;
+        assert!(output.status.success(), "{:?}", output);
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(String::from)
+            .collect())
+    };
+
+    assert!(rows("10")?.is_empty());
+    let rows = rows("-1")?;
+    assert_eq!(rows.len(), 1);
+    let fields: Vec<&str> = rows[0].split('\t').collect();
+    assert_eq!(fields[2], "FAR");
+    assert_eq!(fields[5], "UPSTREAM");
+
+    Ok(())
+}
+
+#[test]
+fn test_hard_thresholds() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // The large region has under 50% in any area of the transcript
+    let bed = temp_file_with("chr1\t5000\t13000\tbig\nchr1\t9900\t9950\tsmall\n", ".bed")?;
+
+    let names = |extra: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-r", "transcript"])
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(|line| line.rsplit('\t').next().unwrap_or_default().to_string())
+            .collect())
+    };
+
+    assert_eq!(names(&[])?, vec!["big", "small"]);
+    assert_eq!(names(&["--hard-thresholds"])?, vec!["small"]);
+    assert_eq!(
+        names(&["--hard-thresholds", "--report-intergenic"])?,
+        vec!["big", "small"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_annotate_error_variants() -> Result<(), Box<dyn std::error::Error>> {
+    use rgmatch::config::{ConfigBuilder, ConfigViolation};
+    use rgmatch::{annotate, Config, Error};
+
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with("chr1\t9900\t9950\n", ".bed")?;
+    let missing = Path::new("does/not/exist.bed");
+
+    match annotate(gtf.path(), missing, &Config::default()) {
+        Err(Error::Io { path, .. }) => assert_eq!(path.as_deref(), Some(missing)),
+        other => panic!(
+            "expected an Io error, got {:?}",
+            other.map(|r| r.associations)
+        ),
+    }
+
+    // Genes are keyed by a tag the annotation does not have
+    let config = Config {
+        gene_id_tag: "gene_name".to_string(),
+        ..Config::default()
+    };
+    match annotate(gtf.path(), bed.path(), &config) {
+        Err(Error::GtfParse { line: 1, reason }) => assert!(reason.contains("gene_name")),
+        other => panic!(
+            "expected a GtfParse error, got {:?}",
+            other.map(|r| r.associations)
+        ),
+    }
+
+    let comments = temp_file_with("#!genome-build test\n", ".gtf")?;
+    match annotate(comments.path(), bed.path(), &Config::default()) {
+        Err(Error::NoGenes { path }) => assert_eq!(path, comments.path()),
+        other => panic!(
+            "expected a NoGenes error, got {:?}",
+            other.map(|r| r.associations)
+        ),
+    }
+
+    let bad_bed = temp_file_with("chr1\t100\t200\nchr1\t300\tend\n", ".bed")?;
+    match annotate(gtf.path(), bad_bed.path(), &Config::default()) {
+        Err(Error::BedParse { line: 2, .. }) => {}
+        other => panic!(
+            "expected a BedParse error, got {:?}",
+            other.map(|r| r.associations)
+        ),
+    }
+
+    let error: Error = ConfigBuilder::new().tss_bp(-1).build().unwrap_err().into();
+    match error {
+        Error::InvalidConfig(e) => {
+            assert_eq!(e.violations, vec![ConfigViolation::NegativeTss(-1.0)])
+        }
+        other => panic!("expected an InvalidConfig error, got {:?}", other),
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bed_output_format() -> Result<(), Box<dyn std::error::Error>> {
+    let tsv = run_on_fixtures(&["-r", "gene"])?;
+    let bed = run_on_fixtures(&["-r", "gene", "--output-format", "bed"])?;
+
+    let tsv_rows: Vec<Vec<&str>> = tsv
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').collect())
+        .collect();
+    let bed_rows: Vec<Vec<&str>> = bed.lines().map(|l| l.split('\t').collect()).collect();
+    assert_eq!(tsv_rows.len(), bed_rows.len());
+
+    for (tsv_row, bed_row) in tsv_rows.iter().zip(&bed_rows) {
+        // Valid BED6: chrom, start < end, name, score, strand
+        assert_eq!(bed_row.len(), 6);
+        let start: i64 = bed_row[1].parse()?;
+        let end: i64 = bed_row[2].parse()?;
+        assert!(start < end);
+        assert!(bed_row[5] == "+" || bed_row[5] == "-");
+
+        // Coordinates and name agree with the TSV row
+        assert_eq!(tsv_row[0], format!("{}_{}_{}", bed_row[0], start, end));
+        let name: Vec<&str> = bed_row[3].split('|').collect();
+        assert_eq!(name, vec![tsv_row[2], tsv_row[5], tsv_row[6]]);
+        assert_eq!(bed_row[4], tsv_row[11]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bed_output_best_only() -> Result<(), Box<dyn std::error::Error>> {
+    let bed = run_on_fixtures(&["--output-format", "bed", "--best-only"])?;
+
+    let mut seen = std::collections::HashSet::new();
+    for line in bed.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let key = (fields[0], fields[1], fields[2]);
+        assert!(seen.insert(key), "region reported twice: {:?}", key);
+    }
+    assert!(!seen.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_column_projection() -> Result<(), Box<dyn std::error::Error>> {
+    let full = run_on_fixtures(&["-r", "gene"])?;
+    let projected = run_on_fixtures(&["-r", "gene", "--columns", "Area,Gene,name,Region"])?;
+
+    let full_lines: Vec<&str> = full.lines().collect();
+    let projected_lines: Vec<&str> = projected.lines().collect();
+    assert_eq!(full_lines.len(), projected_lines.len());
+    assert_eq!(projected_lines[0], "Area\tGene\tname\tRegion");
+
+    for (full_line, projected_line) in full_lines.iter().zip(&projected_lines).skip(1) {
+        let f: Vec<&str> = full_line.split('\t').collect();
+        let p: Vec<&str> = projected_line.split('\t').collect();
+        assert_eq!(p, vec![f[5], f[2], f[10], f[0]]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_separator() -> Result<(), Box<dyn std::error::Error>> {
+    let output = run_on_fixtures(&["-r", "gene", "--merge-sep", ";"])?;
+
+    let mut merged = 0;
+    for line in output.lines().skip(1) {
+        let fields: Vec<&str> = line.split('\t').collect();
+        assert!(!fields[3].contains(','));
+        assert!(!fields[4].contains(','));
+        if fields[3].contains(';') {
+            merged += 1;
+            let transcripts: Vec<&str> = fields[3].split(';').collect();
+            assert!(transcripts.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+    assert!(merged > 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_column_fails_fast() {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("--columns")
+        .arg("Region,Bogus")
+        .assert()
+        .failure()
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("unknown column 'Bogus'"))
+        .stderr(predicates::str::contains("PercArea"));
+}
+
+#[test]
+fn test_invalid_parameters_all_reported() {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("--tss=-1")
+        .arg("--perc_area=120")
+        .arg("--rules=TSS")
+        .assert()
+        .failure()
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains(
+            "TSS distance cannot be lower than 0",
+        ))
+        .stderr(predicates::str::contains("percentage of area"))
+        .stderr(predicates::str::contains("Rules not properly passed"));
+}
+
+/// A tiny annotation with one positive-strand gene on chr1.
+const SMALL_GTF: &str = "chr1\tTEST\tgene\t10000\t12000\t.\t+\t.\tgene_id \"G1\";
+chr1\tTEST\ttranscript\t10000\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t10000\t10500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t11500\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+";
+
+/// Write `contents` to a named temporary file with the given suffix.
+fn temp_file_with(contents: &str, suffix: &str) -> Result<NamedTempFile, std::io::Error> {
+    use std::io::Write;
+
+    let mut file = tempfile::Builder::new().suffix(suffix).tempfile()?;
+    file.write_all(contents.as_bytes())?;
+    file.flush()?;
+    Ok(file)
+}
+
+#[test]
+fn test_config_file_with_cli_overrides() -> Result<(), Box<dyn std::error::Error>> {
+    let config = temp_file_with("level = \"gene\"\ndistance = 5000\ntss = 100.0\n", ".toml")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("--config")
+        .arg(config.path())
+        .arg("--tss=300")
+        .arg("--dump-config")
+        .output()?;
+    assert!(output.status.success());
+    let dumped = String::from_utf8(output.stdout)?;
+    assert!(dumped.contains("level = \"gene\"\n"));
+    assert!(dumped.contains("distance = 5000\n"));
+    assert!(dumped.contains("tss = 300.0\n"));
+
+    // The dumped configuration reads back unchanged
+    let round_trip = temp_file_with(&dumped, ".toml")?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("--config")
+        .arg(round_trip.path())
+        .arg("--dump-config")
+        .assert()
+        .success()
+        .stdout(dumped);
+    Ok(())
+}
+
+#[test]
+fn test_config_file_unknown_key() -> Result<(), Box<dyn std::error::Error>> {
+    let config = temp_file_with("distanse = 5000\n", ".toml")?;
+    let (gtf_path, bed_path) = fixture_paths();
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("--config")
+        .arg(config.path())
+        .assert()
+        .failure()
+        .stdout(predicates::str::is_empty())
+        .stderr(predicates::str::contains("distanse"));
+    Ok(())
+}
+
+#[test]
+fn test_summary_file() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // TSS hit, intron hit, 50 kb away, and a chromosome absent from the GTF
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\nchr1\t11000\t11100\tr2\nchr1\t62000\t62100\tr3\nchr9\t100\t200\tr4\n",
+        ".bed",
+    )?;
+
+    for threads in ["1", "2"] {
+        let output = NamedTempFile::new()?;
+        let summary_tsv = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+        let summary_json = tempfile::Builder::new().suffix(".json").tempfile()?;
+
+        for summary_path in [summary_tsv.path(), summary_json.path()] {
+            Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+                .arg("-g")
+                .arg(gtf.path())
+                .arg("-b")
+                .arg(bed.path())
+                .arg("-o")
+                .arg(output.path())
+                .arg("-r")
+                .arg("gene")
+                .arg("-j")
+                .arg(threads)
+                .arg("--summary")
+                .arg(summary_path)
+                .assert()
+                .success()
+                .stderr(predicates::str::contains(
+                    "Regions: 4 total, 2 associated, 2 unassigned",
+                ));
+        }
+
+        let tsv = std::fs::read_to_string(summary_tsv.path())?;
+        assert!(tsv.starts_with("# report_level: gene\n"));
+        assert!(tsv.contains("regions\ttotal\t4\t100.00\tNA\n"));
+        assert!(tsv.contains("regions\tassociated\t2\t50.00\tNA\n"));
+        assert!(tsv.contains("regions\tunassigned\t2\t50.00\tNA\n"));
+        assert!(tsv.contains("area\tTSS\t1\t50.00\t75.0\n"));
+        assert!(tsv.contains("area\tINTRON\t1\t50.00\t1050.0\n"));
+        assert!(tsv.contains("tss_upstream\t0-1000\t1\t50.00\tNA\n"));
+        assert!(tsv.contains("tss_downstream\t1000-5000\t1\t50.00\tNA\n"));
+
+        let json = std::fs::read_to_string(summary_json.path())?;
+        assert!(json.contains("\"total_regions\": 4,"));
+        assert!(json.contains("\"total_associations\": 2,"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_unassigned_regions() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\t5\t+\nchr1\t62000\t62100\tfar\t6\t+\nchr9\t100\t200\tmissing\t7\t-\n",
+        ".bed",
+    )?;
+
+    for threads in ["1", "2"] {
+        let output = NamedTempFile::new()?;
+        let unassigned = NamedTempFile::new()?;
+
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .arg("-o")
+            .arg(output.path())
+            .arg("-j")
+            .arg(threads)
+            .arg("--unassigned")
+            .arg(unassigned.path())
+            .arg("--keep-unassigned")
+            .assert()
+            .success()
+            .stderr(predicates::str::contains("2 unassigned"));
+
+        let unassigned = std::fs::read_to_string(unassigned.path())?;
+        assert_eq!(
+            unassigned,
+            "chr1\t62000\t62100\tfar\t6\t+\nchr9\t100\t200\tmissing\t7\t-\n"
+        );
+
+        let output = std::fs::read_to_string(output.path())?;
+        assert!(
+            output.contains("chr1_62000_62100\t62050\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tfar\t6\t+\n")
+        );
+        assert!(
+            output.contains("chr9_100_200\t150\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tNA\tmissing\t7\t-\n")
+        );
+        assert!(output.contains("chr1_9900_9950\t9925\tG1\tT1"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_gene_span_columns() -> Result<(), Box<dyn std::error::Error>> {
+    // No gene lines: each gene spans its transcripts, so T2 extends G1 to 12500
+    let gtf = temp_file_with(
+        "chr1\tTEST\ttranscript\t10000\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t10000\t10500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t11500\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\ttranscript\t11000\t12500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";
+chr1\tTEST\texon\t11000\t12500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";
+chr1\tTEST\ttranscript\t20000\t21000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T3\";
+chr1\tTEST\texon\t20000\t21000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T3\";
+",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t11000\t11100\tr1\nchr1\t20400\t20500\tr2\n", ".bed")?;
+
+    let run = |extra: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .arg("-o")
+            .arg("-")
+            .arg("-r")
+            .arg("gene")
+            .arg("--no-provenance")
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    let plain = run(&[])?;
+    assert!(!plain.contains("GeneStart"));
+
+    // GTF coordinates are reported as-is with one-based starts
+    let output = run(&[
+        "--extra-columns",
+        "gene-span",
+        "--report-coords",
+        "one-based",
+    ])?;
+    let mut lines = output.lines();
+    assert!(lines
+        .next()
+        .unwrap()
+        .ends_with("PercArea\tGeneStart\tGeneEnd\tGeneLength\tname"));
+
+    let spans: Vec<(String, Vec<String>)> = lines
+        .map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            (
+                fields[2].to_string(),
+                fields[10..13].iter().map(|f| f.to_string()).collect(),
+            )
+        })
+        .collect();
+    assert!(spans.contains(&(
+        "G1".to_string(),
+        vec!["10000".into(), "12500".into(), "2501".into()]
+    )));
+    assert!(spans.contains(&(
+        "G2".to_string(),
+        vec!["20000".into(), "21000".into(), "1001".into()]
+    )));
+
+    // The default bed0 convention only shifts the start
+    let output = run(&["--extra-columns", "gene-span"])?;
+    assert!(output.contains("\tG1\t"));
+    assert!(output.contains("\t9999\t12500\t2501\t"));
+
+    Ok(())
+}
+
+#[test]
+fn test_num_candidate_genes_column() -> Result<(), Box<dyn std::error::Error>> {
+    // G2 is nested inside the intron of G1
+    let gtf = temp_file_with(
+        &format!(
+            "{}{}",
+            SMALL_GTF,
+            "chr1\tTEST\tgene\t10800\t11200\t.\t+\t.\tgene_id \"G2\";
+chr1\tTEST\ttranscript\t10800\t11200\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T2\";
+chr1\tTEST\texon\t10800\t11200\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T2\";
+"
+        ),
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t11000\t11100\tr1\nchr1\t62000\t62100\tfar\n", ".bed")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .arg("-o")
+        .arg("-")
+        .arg("-r")
+        .arg("gene")
+        .arg("--best-only")
+        .arg("--keep-unassigned")
+        .arg("--no-provenance")
+        .arg("--columns")
+        .arg("Region,Gene,NumCandidateGenes")
+        .output()?;
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "Region\tGene\tNumCandidateGenes");
+    assert_eq!(lines.len(), 3);
+    assert!(lines[1].starts_with("chr1_11000_11100\tG"));
+    assert!(lines[1].ends_with("\t2"));
+    assert_eq!(lines[2], "chr1_62000_62100\tNA\t0");
+
+    Ok(())
+}
+
+#[test]
+fn test_closest_output_format() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with(
+        "chr1\t8000\t8100\tup\nchr1\t11000\t11100\tin\nchr1\t62000\t62100\tfar\n",
+        ".bed",
+    )?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .arg("-o")
+        .arg("-")
+        .arg("-r")
+        .arg("gene")
+        .arg("--output-format")
+        .arg("closest")
+        .arg("--keep-unassigned")
+        .arg("--no-provenance")
+        .output()?;
+    assert!(output.status.success());
+
+    // Distances follow `bedtools closest -D b`: negative upstream of the gene
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "chr1\t8000\t8100\tup\tchr1\t9999\t12000\tG1\t.\t+\t-1900\n\
+         chr1\t11000\t11100\tin\tchr1\t9999\t12000\tG1\t.\t+\t0\n\
+         chr1\t62000\t62100\tfar\t.\t-1\t-1\t.\t-1\t.\t-1\n"
+    );
+
+    // Only gene-level rows can be written one gene per line
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .arg("-o")
+        .arg("-")
+        .arg("--output-format")
+        .arg("closest")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("requires -r gene"));
+
+    Ok(())
+}
+
+#[test]
+fn test_upstream_percentage_is_na() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with("chr1\t8000\t8100\tup\n", ".bed")?;
+
+    let run = |extra: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .arg("-o")
+            .arg("-")
+            .arg("--no-provenance")
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    for level in ["exon", "transcript", "gene"] {
+        let output = run(&["-r", level])?;
+        let row = output.lines().nth(1).unwrap();
+        assert!(
+            row.ends_with("\tUPSTREAM\t1950\t-1950\t100.00\tNA\tup"),
+            "{}: {}",
+            level,
+            row
+        );
+
+        let output = run(&["-r", level, "--na-string", ""])?;
+        assert!(output.lines().nth(1).unwrap().ends_with("\t100.00\t\tup"));
+
+        let output = run(&["-r", level, "--legacy-sentinel"])?;
+        assert!(output
+            .lines()
+            .nth(1)
+            .unwrap()
+            .ends_with("\t100.00\t-1.00\tup"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_strandedness_option() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with(
+        "chr1\t8000\t8100\tplus\t0\t+\n\
+         chr1\t8000\t8100\tminus\t0\t-\n\
+         chr1\t8000\t8100\tnone\t0\t.\n",
+        ".bed",
+    )?;
+
+    let names = |extra: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .arg("-o")
+            .arg("-")
+            .args(["--no-provenance", "-r", "gene", "--columns", "name"])
+            .args(extra)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(str::to_string)
+            .collect())
+    };
+
+    assert_eq!(names(&[])?, vec!["plus", "minus", "none"]);
+    assert_eq!(names(&["--strandedness", "same"])?, vec!["plus", "none"]);
+    assert_eq!(
+        names(&["--strandedness", "opposite"])?,
+        vec!["minus", "none"]
+    );
+    assert_eq!(
+        names(&["--strandedness", "same", "--strict-strandedness"])?,
+        vec!["plus"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_sense_column_and_summary() -> Result<(), Box<dyn std::error::Error>> {
+    // G1 on + (10000-12000) and G2 on - (40000-41000)
+    let gtf = temp_file_with(
+        &format!(
+            "{}chr1\tTEST\tgene\t40000\t41000\t.\t-\t.\tgene_id \"G2\";\n\
+             chr1\tTEST\ttranscript\t40000\t41000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n\
+             chr1\tTEST\texon\t40000\t41000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n",
+            SMALL_GTF
+        ),
+        ".gtf",
+    )?;
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\t0\t+\n\
+         chr1\t11000\t11100\tr2\t0\t-\n\
+         chr1\t40950\t41050\tr3\t0\t-\n\
+         chr1\t40950\t41050\tr4\t0\t.\n",
+        ".bed",
+    )?;
+    let summary = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .arg("-o")
+        .arg("-")
+        .args(["--no-provenance", "-r", "gene"])
+        .args(["--columns", "name,Gene,Area,Sense"])
+        .arg("--summary")
+        .arg(summary.path())
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "name\tGene\tArea\tSense\n\
+         r1\tG1\tTSS\tsense\n\
+         r2\tG1\tINTRON\tantisense\n\
+         r3\tG2\t1st_EXON\tsense\n\
+         r4\tG2\t1st_EXON\tunstranded\n"
+    );
+
+    let summary = std::fs::read_to_string(summary.path())?;
+    assert!(summary.contains("area\t1st_EXON\t2\t50.00\t"));
+    assert!(summary.contains("sense\tTSS:sense\t1\t100.00\tNA\n"));
+    assert!(summary.contains("sense\t1st_EXON:sense\t1\t50.00\tNA\n"));
+    assert!(summary.contains("sense\t1st_EXON:unstranded\t1\t50.00\tNA\n"));
+    assert!(summary.contains("sense\tINTRON:antisense\t1\t100.00\tNA\n"));
+
+    Ok(())
+}
+
+#[test]
+fn test_closest_fallback() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // 250 kb downstream of the only gene, a TSS hit, and a chromosome without genes
+    let bed = temp_file_with(
+        "chr1\t262000\t262100\tfar\nchr1\t9900\t9950\tnear\nchr2\t100\t200\tnone\n",
+        ".bed",
+    )?;
+    let summary = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+
+    for level in ["exon", "transcript", "gene"] {
+        let run = |extra: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+            let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+                .arg("-g")
+                .arg(gtf.path())
+                .arg("-b")
+                .arg(bed.path())
+                .arg("-o")
+                .arg("-")
+                .args(["--no-provenance", "-r", level])
+                .args(["--columns", "name,Gene,Area,Distance,TSSDistance"])
+                .args(extra)
+                .output()?;
+            assert!(output.status.success());
+            Ok(String::from_utf8(output.stdout)?)
+        };
+
+        let plain = run(&[])?;
+        assert!(!plain.contains("far"), "{}", plain);
+
+        let closest = run(&["--closest", "--summary", summary.path().to_str().unwrap()])?;
+        let far: Vec<&str> = closest.lines().filter(|l| l.starts_with("far")).collect();
+        assert_eq!(
+            far,
+            vec!["far\tG1\tDOWNSTREAM\t250050\t252050"],
+            "{}",
+            level
+        );
+        assert!(closest.contains("near\tG1\tTSS\t"));
+        assert!(!closest.contains("none"));
+
+        let summary = std::fs::read_to_string(summary.path())?;
+        assert!(summary.contains("regions\tclosest_fallback\t1\t33.33\tNA\n"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_report_intergenic() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // 250 kb downstream of the only gene, a TSS hit, and a chromosome without genes
+    let bed = temp_file_with(
+        "chr1\t262000\t262100\tfar\nchr1\t9900\t9950\tnear\nchr2\t100\t200\tnone\n",
+        ".bed",
+    )?;
+    let unassigned = tempfile::Builder::new().suffix(".bed").tempfile()?;
+    let summary = tempfile::Builder::new().suffix(".json").tempfile()?;
+    let run = |args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "--report-intergenic"])
+            .args(args)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    for level in ["exon", "gene"] {
+        let tsv = run(&[
+            "-r",
+            level,
+            "--unassigned",
+            unassigned.path().to_str().unwrap(),
+            "--summary",
+            summary.path().to_str().unwrap(),
+        ])?;
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[1],
+            "chr1_262000_262100\t262050\tNA\tNA\tNA\tINTERGENIC\t250000\tNA\tNA\tNA\tfar"
+        );
+        assert!(lines[2].contains("\tG1\t"));
+        assert_eq!(
+            lines[3],
+            "chr2_100_200\t150\tNA\tNA\tNA\tINTERGENIC\tNA\tNA\tNA\tNA\tnone"
+        );
+
+        // Intergenic regions still count as unassigned
+        assert_eq!(
+            std::fs::read_to_string(unassigned.path())?,
+            "chr1\t262000\t262100\tfar\nchr2\t100\t200\tnone\n"
+        );
+        let summary = std::fs::read_to_string(summary.path())?;
+        assert!(summary.contains("\"unassigned_regions\": 2,"));
+        assert!(summary.contains("{\"area\": \"INTERGENIC\", \"count\": 2,"));
+    }
+
+    assert_eq!(
+        run(&["--output-format", "bed", "--na-string", "."])?,
+        "chr1\t262000\t262100\tNA|INTERGENIC|250000\t0\t.\n\
+         chr1\t9900\t9950\tG1|TSS|75\t0\t+\n\
+         chr2\t100\t200\tNA|INTERGENIC|NA\t0\t.\n"
+    );
+    // The na-string replaces the NA gene in TSV output
+    assert!(run(&["--na-string", "."])?
+        .contains("chr2_100_200\t150\t.\t.\t.\tINTERGENIC\t.\t.\t.\t.\tnone"));
+
+    Ok(())
+}
+
+#[test]
+fn test_n_closest() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(
+        &format!(
+            "{}{}",
+            SMALL_GTF,
+            "chr1\tTEST\tgene\t50000\t51000\t.\t+\t.\tgene_id \"G2\";
+chr1\tTEST\ttranscript\t50000\t51000\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T2\";
+chr1\tTEST\texon\t50000\t51000\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T2\";
+"
+        ),
+        ".gtf",
+    )?;
+    // A TSS hit of G1, and a region 20 kb from both TSSs, nearer to G2's
+    let bed = temp_file_with("chr1\t9900\t9950\tnear\nchr1\t30000\t30100\tmid\n", ".bed")?;
+    let run = |args: &[&str]| -> Result<std::process::Output, std::io::Error> {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance"])
+            .args(args)
+            .output()
+    };
+    let lines = |args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = run(args)?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                // name, Gene, Area, Rank
+                [fields[11], fields[2], fields[5], fields[10]].join(" ")
+            })
+            .collect())
+    };
+
+    let output = run(&["-r", "gene", "--n-closest", "1"])?;
+    let header = String::from_utf8(output.stdout)?;
+    assert!(header.starts_with(
+        "Region\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea\tRank\tname\n"
+    ));
+
+    // The nearest gene is added beyond the 10 kb cutoff
+    assert_eq!(
+        lines(&["-r", "gene", "--n-closest", "1"])?,
+        vec!["near G1 TSS 1", "mid G2 UPSTREAM 1"]
+    );
+    assert_eq!(
+        lines(&["-r", "gene", "--n-closest", "2"])?,
+        vec![
+            "near G1 TSS 1",
+            "near G2 UPSTREAM 2",
+            "mid G2 UPSTREAM 1",
+            "mid G1 DOWNSTREAM 2",
+        ]
+    );
+
+    // Within a 25 kb cutoff both genes are rule-selected for mid; the one
+    // outside the ranking keeps NA, or is dropped with --closest-only
+    assert_eq!(
+        lines(&["-r", "gene", "-q", "25", "--n-closest", "1"])?,
+        vec!["near G1 TSS 1", "mid G1 DOWNSTREAM NA", "mid G2 UPSTREAM 1"]
+    );
+    assert_eq!(
+        lines(&[
+            "-r",
+            "gene",
+            "-q",
+            "25",
+            "--n-closest",
+            "1",
+            "--closest-only"
+        ])?,
+        vec!["near G1 TSS 1", "mid G2 UPSTREAM 1"]
+    );
+
+    assert!(!run(&["--n-closest", "1"])?.status.success());
+    assert!(!run(&["-r", "gene", "--closest-only"])?.status.success());
+
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+#[test]
+fn test_parquet_requires_feature() {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg("-")
+        .arg("--output-format")
+        .arg("parquet")
+        .assert()
+        .failure()
+        .stderr(predicates::str::contains("`parquet` feature"));
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_output_matches_tsv() -> Result<(), Box<dyn std::error::Error>> {
+    use arrow::array::{Array, Float64Array, Int64Array, StringArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    let tsv = run_on_fixtures(&["-r", "gene", "-j", "2"])?;
+    let tsv_rows: Vec<Vec<&str>> = tsv
+        .lines()
+        .skip(1)
+        .map(|l| l.split('\t').collect())
+        .collect();
+
+    let (gtf_path, bed_path) = fixture_paths();
+    let output = tempfile::Builder::new().suffix(".parquet").tempfile()?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output.path())
+        .args(["-r", "gene", "--output-format", "parquet"])
+        .assert()
+        .success();
+
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(output.path())?)?.build()?;
+    let mut row = 0;
+    for batch in reader {
+        let batch = batch?;
+        let strings = |i: usize| {
+            batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<StringArray>()
+                .unwrap()
+                .clone()
+        };
+        let ints = |i: usize| {
+            batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<Int64Array>()
+                .unwrap()
+                .clone()
+        };
+        let floats = |i: usize| {
+            batch
+                .column(i)
+                .as_any()
+                .downcast_ref::<Float64Array>()
+                .unwrap()
+                .clone()
+        };
+
+        for i in 0..batch.num_rows() {
+            let expected = &tsv_rows[row];
+            assert_eq!(strings(0).value(i), expected[0]);
+            assert_eq!(ints(1).value(i).to_string(), expected[1]);
+            for (col, value) in expected.iter().enumerate().take(6).skip(2) {
+                assert_eq!(strings(col).value(i), *value);
+            }
+            assert_eq!(ints(6).value(i).to_string(), expected[6]);
+            assert_eq!(ints(7).value(i).to_string(), expected[7]);
+            for col in [8, 9] {
+                let value = if floats(col).is_null(i) {
+                    "NA".to_string()
+                } else {
+                    format!("{:.2}", floats(col).value(i))
+                };
+                assert_eq!(value, expected[col]);
+            }
+            for (col, value) in expected.iter().enumerate().skip(10) {
+                assert_eq!(strings(col).value(i), *value);
+            }
+            row += 1;
+        }
+    }
+    assert_eq!(row, tsv_rows.len());
+
+    Ok(())
+}
+
+#[test]
+fn test_no_rules_flags_selected_rows() -> Result<(), Box<dyn std::error::Error>> {
+    let exon_rows = sorted_rows(&run_on_fixtures(&[])?, |_| true);
+
+    for level in ["exon", "transcript", "gene"] {
+        let output = run_on_fixtures(&["-r", level, "--no-rules"])?;
+        assert!(output.starts_with(
+            "Region\tMidpoint\tGene\tTranscript\tExon/Intron\tArea\tDistance\tTSSDistance\tPercRegion\tPercArea\tSelected\t"
+        ));
+        // Every candidate is reported, as at exon level
+        assert_eq!(sorted_rows(&output, |_| true), exon_rows);
+        let selected = sorted_rows(&output, |flag| flag == "1");
+        match level {
+            "exon" => assert_eq!(selected, exon_rows),
+            "transcript" => assert_eq!(
+                selected,
+                sorted_rows(&run_on_fixtures(&["-r", level])?, |_| true)
+            ),
+            // Merged gene-level rows have no counterpart among the candidates
+            _ => assert!(!selected.is_empty() && selected.len() < exon_rows.len()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Distinct data rows, sorted, of the rows whose `Selected` column (the
+/// 11th, removed) passes `keep`; output without that column is taken whole.
+fn sorted_rows(output: &str, keep: impl Fn(&str) -> bool) -> Vec<String> {
+    let with_selected = output.lines().next().unwrap_or("").contains("\tSelected\t");
+    let mut rows: Vec<String> = output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let mut fields: Vec<&str> = line.split('\t').collect();
+            if with_selected && !keep(fields.remove(10)) {
+                return None;
+            }
+            Some(fields.join("\t"))
+        })
+        .collect();
+    rows.sort();
+    rows.dedup();
+    rows
+}
+
+#[test]
+fn test_gene_style_best_transcript() -> Result<(), Box<dyn std::error::Error>> {
+    let merged = run_on_fixtures(&["-r", "gene"])?;
+    let best = run_on_fixtures(&["-r", "gene", "--gene-style", "best-transcript"])?;
+    assert_eq!(merged.lines().count(), best.lines().count());
+
+    let mut num_merged = 0;
+    for (merged_row, best_row) in merged.lines().zip(best.lines()).skip(1) {
+        let merged_fields: Vec<&str> = merged_row.split('\t').collect();
+        let best_fields: Vec<&str> = best_row.split('\t').collect();
+        if !merged_fields[3].contains(',') {
+            assert_eq!(merged_row, best_row);
+            continue;
+        }
+        num_merged += 1;
+        // Region, Midpoint, Gene and Area; the distances and percentages
+        // are those of the chosen transcript
+        for i in [0, 1, 2, 5] {
+            assert_eq!(merged_fields[i], best_fields[i], "{}", merged_row);
+        }
+        assert!(!best_fields[3].contains(','), "{}", best_row);
+        assert!(merged_fields[3].split(',').any(|t| t == best_fields[3]));
+    }
+    assert!(num_merged > 0);
+
+    // Other levels are unchanged
+    assert_eq!(
+        run_on_fixtures(&["-r", "transcript", "--gene-style", "best-transcript"])?,
+        run_on_fixtures(&["-r", "transcript"])?
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_tss_bed_refines_tss() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with("chr1\t9000\t9050\tpeak\n", ".bed")?;
+    let cage = temp_file_with("chr1\t9099\t9100\tT1\t12\t+\n", ".bed")?;
+
+    let areas =
+        |extra_args: &[&std::ffi::OsStr]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+            let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+                .arg("-g")
+                .arg(gtf.path())
+                .arg("-b")
+                .arg(bed.path())
+                .args(["-o", "-", "--no-provenance"])
+                .args(extra_args)
+                .output()?;
+            assert!(output.status.success());
+            Ok(String::from_utf8(output.stdout)?
+                .lines()
+                .skip(1)
+                .map(|row| row.split('\t').nth(5).unwrap_or_default().to_string())
+                .collect())
+        };
+
+    // 975 bp upstream of the annotated TSS, 75 bp upstream of the CAGE peak
+    assert_eq!(areas(&[])?, ["PROMOTER"]);
+    assert_eq!(
+        areas(&["--tss-bed".as_ref(), cage.path().as_os_str()])?,
+        ["TSS"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_duplicated_exon_line() -> Result<(), Box<dyn std::error::Error>> {
+    let exon = "chr1\tTEST\texon\t1001\t1300\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n";
+    let gtf = temp_file_with(
+        &format!(
+            "{exon}{exon}chr1\tTEST\texon\t2001\t2300\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n"
+        ),
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t1100\t1150\tpeak\n", ".bed")?;
+
+    let rows = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance"])
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(|row| {
+                row.split('\t')
+                    .skip(4)
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect())
+    };
+
+    assert_eq!(rows(&[])?, ["1 1st_EXON"]);
+    assert_eq!(
+        rows(&["--keep-overlapping-exons"])?,
+        ["1 1st_EXON", "2 GENE_BODY"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_mixed_gene_and_exon_only_annotation() -> Result<(), Box<dyn std::error::Error>> {
+    let g2_exons = "chr1\tTEST\texon\t50000\t51000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";
+chr1\tTEST\texon\t52000\t53000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";
+";
+    let g2_lines = "chr1\tTEST\tgene\t50000\t53000\t.\t-\t.\tgene_id \"G2\";
+chr1\tTEST\ttranscript\t50000\t53000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";
+";
+    let exon_lines = |gtf: &str| -> String {
+        gtf.lines()
+            .filter(|line| line.split('\t').nth(2) == Some("exon"))
+            .map(|line| format!("{}\n", line))
+            .collect()
+    };
+    // G1 has gene and transcript lines, G2 only exons; the same annotation
+    // written fully and with exons only
+    let mixed = temp_file_with(&format!("{}{}", SMALL_GTF, g2_exons), ".gtf")?;
+    let complete = temp_file_with(&format!("{}{}{}", SMALL_GTF, g2_lines, g2_exons), ".gtf")?;
+    let exon_only = temp_file_with(&exon_lines(&format!("{}{}", SMALL_GTF, g2_exons)), ".gtf")?;
+    // A TSS and an UPSTREAM hit of G2, and a region closer to G2 than to G1
+    let bed = temp_file_with(
+        "chr1\t53100\t53150\ttss\nchr1\t60000\t60050\tupstream\nchr1\t300000\t300100\tfar\n",
+        ".bed",
+    )?;
+
+    let run = |gtf: &Path, extra_args: &[&str]| -> Result<String, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf)
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance"])
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?)
+    };
+
+    for extra_args in [
+        &["-r", "exon"][..],
+        &["-r", "gene"],
+        &["--closest"],
+        &["--report-intergenic"],
+        &["-q", "-1", "-r", "gene"],
+    ] {
+        let expected = run(complete.path(), extra_args)?;
+        assert_eq!(run(mixed.path(), extra_args)?, expected, "{:?}", extra_args);
+        assert_eq!(
+            run(exon_only.path(), extra_args)?,
+            expected,
+            "{:?}",
+            extra_args
+        );
+    }
+
+    let columns = ["--columns", "name,Gene,Transcript,Area,Distance"];
+    let output = run(mixed.path(), &[&["-r", "exon"][..], &columns].concat())?;
+    assert!(output.contains("\ntss\tG2\tT2\tTSS\t125\n"), "{}", output);
+    assert!(
+        output.contains("\nupstream\tG2\tT2\tUPSTREAM\t"),
+        "{}",
+        output
+    );
+    let output = run(mixed.path(), &[&["--closest"][..], &columns].concat())?;
+    assert!(output.contains("\nfar\tG2\tT2\tUPSTREAM\t"), "{}", output);
+
+    Ok(())
+}
+
+#[test]
+fn test_negative_bed_coordinates() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with("chr1\t100\t200\tr1\nchr1\t-50\t20\tr2\n", ".bed")?;
+
+    let run = |extra_args: &[&str]| -> Result<std::process::Output, std::io::Error> {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-j", "1"])
+            .args(extra_args)
+            .output()
+    };
+
+    let output = run(&[])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?
+        .contains("Invalid BED line 2: start -50 is out of the supported coordinate range"));
+
+    let output = run(&["--allow-negative-coordinates"])?;
+    assert!(output.status.success());
+    Ok(())
+}
+
+#[test]
+fn test_ragged_metadata_columns() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    // One metadata column on the first line, five on the last
+    let bed = temp_file_with(
+        "chr1\t9900\t9950\tr1\n\
+         chr1\t11000\t11100\tr2\t5\n\
+         chr1\t9800\t9850\tr3\t7\t+\t9800\t9850\n",
+        ".bed",
+    )?;
+
+    let run = |extra_args: &[&str]| -> Result<(String, String), Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "--na-string", "."])
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok((
+            String::from_utf8(output.stdout)?,
+            String::from_utf8(output.stderr)?,
+        ))
+    };
+    let split = |stdout: &str| -> Vec<Vec<String>> {
+        stdout
+            .lines()
+            .map(|row| row.split('\t').map(str::to_string).collect())
+            .collect()
+    };
+
+    for threads in ["1", "2"] {
+        // The whole file is one chunk: all five columns, padded
+        let rows = split(&run(&["-j", threads])?.0);
+        let header = &rows[0];
+        assert_eq!(
+            &header[header.len() - 5..],
+            ["name", "score", "strand", "thickStart", "thickEnd"]
+        );
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+        let first = &rows[1];
+        assert_eq!(&first[first.len() - 5..], ["r1", ".", ".", ".", "."]);
+
+        // One region per chunk: the header keeps the first line's column,
+        // and later rows are trimmed to it
+        let (stdout, stderr) = run(&["-j", threads, "--batch-size", "1"])?;
+        let rows = split(&stdout);
+        let header = &rows[0];
+        assert_eq!(header.last().map(String::as_str), Some("name"));
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+        assert!(stderr.contains(
+            "2 BED lines after the first chunk have more than the 1 metadata columns of the output header"
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_start_after_end() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(SMALL_GTF, ".gtf")?;
+    let bed = temp_file_with("chr1\t9950\t9900\tr1\n", ".bed")?;
+    let twin = temp_file_with("chr1\t9900\t9950\tr1\n", ".bed")?;
+
+    let run = |bed: &Path, extra_args: &[&str]| -> Result<std::process::Output, std::io::Error> {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed)
+            .args(["-o", "-", "--no-provenance"])
+            .args(extra_args)
+            .output()
+    };
+
+    // Swapped, the region annotates like its correctly ordered twin
+    let swapped = run(bed.path(), &[])?;
+    assert!(swapped.status.success());
+    assert!(String::from_utf8(swapped.stderr)?
+        .contains("Swapped the start and end of 1 BED regions with start > end"));
+    let expected = run(twin.path(), &[])?;
+    assert_eq!(swapped.stdout, expected.stdout);
+    assert_eq!(String::from_utf8(expected.stdout)?.lines().count(), 2);
+
+    let strict = run(bed.path(), &["--strict"])?;
+    assert!(!strict.status.success());
+    assert!(String::from_utf8(strict.stderr)?
+        .contains("Invalid BED line 1: start 9950 is greater than end 9900"));
+
+    let dropped = run(bed.path(), &["--drop-invalid", "-j", "1"])?;
+    assert!(dropped.status.success());
+    assert_eq!(String::from_utf8(dropped.stdout)?.lines().count(), 1);
+    assert!(String::from_utf8(dropped.stderr)?.contains("Skipped 1 BED regions with start > end"));
+    Ok(())
+}
+
+#[test]
+fn test_output_follows_input_order() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    // Deterministic shuffle mixing chromosomes and positions
+    let lines: Vec<String> = std::fs::read_to_string(&bed_path)?
+        .lines()
+        .map(str::to_string)
+        .collect();
+    let mut shuffled: Vec<String> = Vec::with_capacity(lines.len());
+    for offset in 0..7 {
+        shuffled.extend(lines.iter().skip(offset).step_by(7).rev().cloned());
+    }
+    let shuffled_bed = temp_file_with(&(shuffled.join("\n") + "\n"), ".bed")?;
+
+    let run = |bed: &Path, threads: &str| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(bed)
+            .args(["-o", "-", "--no-provenance", "--keep-unassigned"])
+            .args(["--extra-columns", "bed-line", "--batch-size", "100"])
+            .args(["-j", threads])
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(str::to_string)
+            .collect())
+    };
+    // Rows without their BedLine column (the 11th), and that column
+    let split = |rows: &[String]| -> (Vec<String>, Vec<usize>) {
+        let mut content = Vec::new();
+        let mut bed_lines = Vec::new();
+        for row in rows {
+            let mut fields: Vec<&str> = row.split('\t').collect();
+            bed_lines.push(fields.remove(10).parse().unwrap());
+            content.push(fields.join("\t"));
+        }
+        (content, bed_lines)
+    };
+
+    let (mut expected, _) = split(&run(&bed_path, "1")?);
+    expected.sort();
+    for threads in ["1", "4"] {
+        let (mut content, bed_lines) = split(&run(shuffled_bed.path(), threads)?);
+        // Rows come in the order of the shuffled file...
+        assert!(bed_lines.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(bed_lines.last(), Some(&shuffled.len()));
+        let first: Vec<&str> = shuffled[0].split('\t').collect();
+        assert!(content[0].starts_with(&format!("{}_{}_{}\t", first[0], first[1], first[2])));
+        // ...with the same content as for the sorted file
+        content.sort();
+        assert_eq!(content, expected, "threads={}", threads);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_transcript_id_shared_by_two_genes() -> Result<(), Box<dyn std::error::Error>> {
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+         chr1\tTEST\texon\t90000\t90500\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T1\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t1000\t1100\n", ".bed")?;
+
+    let run = |extra_args: &[&str]| -> Result<std::process::Output, std::io::Error> {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance"])
+            .args(extra_args)
+            .output()
+    };
+
+    let output = run(&[])?;
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains(
+        "1 transcript IDs are used by more than one gene and were kept as separate transcripts (e.g. T1 in G1 and G2)"
+    ));
+    // G2's exon does not stretch G1 into a 89 kb gene
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.lines().skip(1).all(|line| line.contains("\tG1\t")));
+    assert!(stdout.contains("\t1st_EXON\t"), "{}", stdout);
+
+    let strict = run(&["--strict"])?;
+    assert!(!strict.status.success());
+    assert!(String::from_utf8(strict.stderr)?.contains(
+        "Invalid GTF line 2: transcript T1 belongs to gene G2 but was first found under gene G1"
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_transcript_filters() -> Result<(), Box<dyn std::error::Error>> {
+    // A basic, TSL 1 transcript and an untagged fragment starting in its intron
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t1001\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; tag \"basic\"; transcript_support_level \"1\";\n\
+         chr1\tTEST\texon\t5001\t6000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"; tag \"basic\"; transcript_support_level \"1\";\n\
+         chr1\tTEST\texon\t4001\t6000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\"; transcript_support_level \"NA\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t3900\t3950\tpeak\n", ".bed")?;
+
+    let run = |extra_args: &[&str]| -> Result<(String, String), Box<dyn std::error::Error>> {
+        let summary = tempfile::Builder::new().suffix(".tsv").tempfile()?;
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-r", "gene", "--summary"])
+            .arg(summary.path())
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        let row = String::from_utf8(output.stdout)?
+            .lines()
+            .nth(1)
+            .map(|row| {
+                let fields: Vec<&str> = row.split('\t').collect();
+                format!("{} {}", fields[3], fields[5])
+            })
+            .unwrap_or_default();
+        Ok((row, std::fs::read_to_string(summary.path())?))
+    };
+
+    // The fragment gives the peak a TSS hit unless it is filtered out
+    let (row, summary) = run(&[])?;
+    assert_eq!(row, "T2 TSS");
+    assert!(!summary.contains("transcripts\t"));
+
+    let (row, summary) = run(&["--only-tagged", "basic,MANE_Select"])?;
+    assert_eq!(row, "T1 INTRON");
+    assert!(summary.contains("transcripts\tuntagged\t1\t"));
+    assert!(summary.contains("genes\tdropped\t0\t"));
+
+    let (row, summary) = run(&["--max-tsl", "2"])?;
+    assert_eq!(row, "T1 INTRON");
+    assert!(summary.contains("transcripts\tabove_max_tsl\t1\t"));
+    Ok(())
+}
+
+#[test]
+fn test_span_multiple_genes() -> Result<(), Box<dyn std::error::Error>> {
+    // Three adjacent 1 kb genes and a region across all of them
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t1001\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+         chr1\tTEST\texon\t2001\t3000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n\
+         chr1\tTEST\texon\t3001\t4000\t.\t+\t.\tgene_id \"G3\"; transcript_id \"T3\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t1500\t3800\tdomain\n", ".bed")?;
+
+    let rows = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-r", "gene", "--best-only"])
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .map(|row| {
+                let fields: Vec<&str> = row.split('\t').collect();
+                format!("{} {} {}", fields[2], fields[8], fields[10])
+            })
+            .collect())
+    };
+
+    // The rules keep only the promoter of G3
+    assert_eq!(rows(&[])?, ["Gene PercRegion name", "G3 56.50 domain"]);
+    assert_eq!(
+        rows(&["--span-multiple-genes", "report-all-overlapped"])?,
+        [
+            "Gene PercRegion Selected",
+            "G1 21.77 0",
+            "G2 43.46 0",
+            "G3 34.77 1"
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_perc_gene_column() -> Result<(), Box<dyn std::error::Error>> {
+    // A 1 kb gene whose last 400 bp are within the region
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t10001\t10300\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+         chr1\tTEST\texon\t10701\t11000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t10601\t11200\tdomain\n", ".bed")?;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(gtf.path())
+        .arg("-b")
+        .arg(bed.path())
+        .args(["-o", "-", "--no-provenance", "--extra-columns", "perc-gene"])
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+
+    let mut lines = stdout.lines();
+    let header: Vec<&str> = lines.next().unwrap().split('\t').collect();
+    let perc_gene = header.iter().position(|&name| name == "PercGene").unwrap();
+    let rows: Vec<Vec<&str>> = lines.map(|line| line.split('\t').collect()).collect();
+    assert!(rows.len() > 1);
+    for row in &rows {
+        assert_eq!(row[perc_gene], "40.00", "{:?}", row);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_bidirectional_promoter() -> Result<(), Box<dyn std::error::Error>> {
+    // Head-to-head pair with TSSs 600 bp apart: GN at 8000, GP at 8600
+    let gtf = temp_file_with(
+        "chr1\tTEST\texon\t5000\t5500\t.\t-\t.\tgene_id \"GN\"; transcript_id \"TN\";\n\
+         chr1\tTEST\texon\t6000\t8000\t.\t-\t.\tgene_id \"GN\"; transcript_id \"TN\";\n\
+         chr1\tTEST\texon\t8600\t9000\t.\t+\t.\tgene_id \"GP\"; transcript_id \"TP\";\n\
+         chr1\tTEST\texon\t9500\t11000\t.\t+\t.\tgene_id \"GP\"; transcript_id \"TP\";\n",
+        ".gtf",
+    )?;
+    let bed = temp_file_with("chr1\t8250\t8350\tpeak\n", ".bed")?;
+
+    let rows = |extra_args: &[&str]| -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(gtf.path())
+            .arg("-b")
+            .arg(bed.path())
+            .args(["-o", "-", "--no-provenance", "-r", "gene"])
+            .args(["--columns", "Gene,Area,Bidirectional"])
+            .args(extra_args)
+            .output()?;
+        assert!(output.status.success());
+        Ok(String::from_utf8(output.stdout)?
+            .lines()
+            .skip(1)
+            .map(str::to_string)
+            .collect())
+    };
+
+    let flagged = ["GN\tPROMOTER\tyes", "GP\tPROMOTER\tyes"];
+    assert_eq!(rows(&[])?, flagged);
+    // Both genes are kept with --best-only
+    assert_eq!(rows(&["--best-only"])?, flagged);
+
+    // TSSs farther apart than the window: only the first of the equally
+    // near upstream genes, as without the column
+    assert_eq!(
+        rows(&["--bidirectional-window", "500"])?,
+        ["GN\tPROMOTER\tno"]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_validate_report() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let config = rgmatch::Config::default();
+
+    let report = rgmatch::validate(&gtf_path, &bed_path, &config, None)?;
+    assert_eq!(report.gtf.genes, 443);
+    assert_eq!(report.bed.regions, 2400);
+    assert_eq!(report.bed.format(), "BED6");
+    assert_eq!(report.shared_chromosomes().len(), 24);
+    assert!(report.warnings().is_empty());
+    assert!(report.is_valid());
+
+    // A sample of the GTF holds fewer genes, on fewer chromosomes
+    let sampled = rgmatch::validate(&gtf_path, &bed_path, &config, Some(200_000))?;
+    assert!(sampled.gtf.sampled);
+    assert!(!sampled.bed.sampled);
+    assert!(sampled.gtf.genes < report.gtf.genes);
+    assert!(sampled.is_valid());
+
+    Ok(())
+}
+
+#[test]
+fn test_validate_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("validate")
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .output()?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("GTF: 443 genes, "), "{}", stdout);
+    assert!(stdout.contains("BED: 2400 regions on 24 chromosomes, BED6 (6 columns)\n"));
+    assert!(stdout.contains("Shared chromosomes: 24\n"));
+
+    // Chromosome names that do not match the annotation
+    let mismatched = temp_file_with("track name=peaks\n1\t900\t950\n2\t10\t20\n", ".bed")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("validate")
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(mismatched.path())
+        .output()?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("Shared chromosomes: 0\n"), "{}", stdout);
+    assert!(stdout.contains("BED chromosomes not in the GTF: 1 (1), 2 (1)\n"));
+    assert!(stdout.contains("Warning: 1 BED lines are not regions"));
+
+    // A BED file without regions
+    let empty = temp_file_with("track name=peaks\n", ".bed")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("validate")
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(empty.path())
+        .output()?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Error: The BED file has no region\n"));
+
+    // The bare invocation and the annotate subcommand are the same run
+    let bare = run_on_fixtures(&[])?;
+    let output_file = NamedTempFile::new()?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("annotate")
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output_file.path())
+        .arg("--no-provenance")
+        .assert()
+        .success();
+    assert_eq!(std::fs::read_to_string(output_file.path())?, bare);
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_manifest() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+    let dir = tempfile::tempdir()?;
+
+    // Three samples from thirds of the fixture regions
+    let lines: Vec<String> = std::fs::read_to_string(&bed_path)?
+        .lines()
+        .map(|line| format!("{}\n", line))
+        .collect();
+    let mut manifest = String::from("bed_path\toutput_path\tlabel\n");
+    for (i, part) in lines.chunks(lines.len() / 3).enumerate() {
+        let bed = dir.path().join(format!("sample{}.bed", i));
+        std::fs::write(&bed, part.concat())?;
+        manifest.push_str(&format!(
+            "{}\t{}\tS{}\n",
+            bed.display(),
+            dir.path().join(format!("sample{}.txt", i)).display(),
+            i
+        ));
+    }
+    let manifest_path = dir.path().join("samples.tsv");
+    std::fs::write(&manifest_path, &manifest)?;
+    let summary_path = dir.path().join("summary.tsv");
+
+    let batch = |manifest: &Path, extra_args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("batch")
+            .arg("--manifest")
+            .arg(manifest)
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("--summary")
+            .arg(&summary_path)
+            .args(["-r", "gene", "--no-provenance"])
+            .args(extra_args)
+            .output()
+    };
+    let output = batch(&manifest_path, &[])?;
+    assert!(output.status.success());
+
+    // Each output is that of a single run on the sample
+    for i in 0..3 {
+        let single = NamedTempFile::new()?;
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(dir.path().join(format!("sample{}.bed", i)))
+            .arg("-o")
+            .arg(single.path())
+            .args(["-r", "gene", "--no-provenance"])
+            .assert()
+            .success();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join(format!("sample{}.txt", i)))?,
+            std::fs::read_to_string(single.path())?,
+            "sample {}",
+            i
+        );
+    }
+
+    let summary = std::fs::read_to_string(&summary_path)?;
+    let rows: Vec<&str> = summary.lines().collect();
+    assert_eq!(rows.len(), 4, "{}", summary);
+    assert!(rows[0].starts_with("sample\tbed\toutput\tstatus\ttotal_regions\t"));
+    assert!(rows[0].ends_with("\terror"));
+    for (i, row) in rows[1..].iter().enumerate() {
+        let fields: Vec<&str> = row.split('\t').collect();
+        assert_eq!(fields[0], format!("S{}", i));
+        assert_eq!(fields[3], "ok");
+        assert_eq!(fields[4], "800");
+        assert_eq!(*fields.last().unwrap(), "NA");
+    }
+
+    // A failed sample does not stop the others, unless --fail-fast
+    let missing = dir.path().join("missing.bed");
+    let failing_manifest = dir.path().join("failing.tsv");
+    std::fs::write(
+        &failing_manifest,
+        format!(
+            "{}\t{}\n{}",
+            missing.display(),
+            dir.path().join("missing.txt").display(),
+            manifest.lines().skip(1).collect::<Vec<_>>().join("\n")
+        ),
+    )?;
+    let output = batch(&failing_manifest, &["-j", "1"])?;
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("1 of 4 samples failed"));
+    let summary = std::fs::read_to_string(&summary_path)?;
+    assert!(summary.contains("\tfailed\tNA\t"), "{}", summary);
+    assert_eq!(summary.matches("\tok\t").count(), 3);
+
+    let output = batch(&failing_manifest, &["-j", "1", "--fail-fast"])?;
+    assert!(!output.status.success());
+    let summary = std::fs::read_to_string(&summary_path)?;
+    assert_eq!(summary.matches("\tskipped\t").count(), 3, "{}", summary);
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let output = run_on_fixtures(&["-r", "gene"])?;
+    let (header, rows) = output.split_once('\n').unwrap();
+    let table = temp_file_with(&output, ".txt")?;
+    let diff = |b: &Path, extra_args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("diff")
+            .arg(table.path())
+            .arg(b)
+            .args(extra_args)
+            .output()
+    };
+
+    // The same rows in reverse order
+    let mut reversed_rows: Vec<&str> = rows.lines().collect();
+    reversed_rows.reverse();
+    let reversed = temp_file_with(
+        &format!("{}\n{}\n", header, reversed_rows.join("\n")),
+        ".txt",
+    )?;
+    let result = diff(reversed.path(), &["--ignore-order"])?;
+    assert!(result.status.success());
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(
+        stdout.contains("Rows with different values: 0\n"),
+        "{}",
+        stdout
+    );
+    let result = diff(reversed.path(), &[])?;
+    assert!(!result.status.success());
+    assert!(String::from_utf8(result.stdout)?.contains("Rows in a different order: "));
+
+    // One percentage rounded the other way
+    let first_row = rows.lines().next().unwrap();
+    let fields: Vec<&str> = first_row.split('\t').collect();
+    let perc_area: f64 = fields[9].parse()?;
+    let rounded = format!("{:.2}", perc_area - 0.01);
+    let changed_row = [&fields[..9], &[rounded.as_str()], &fields[10..]]
+        .concat()
+        .join("\t");
+    let changed = temp_file_with(&output.replacen(first_row, &changed_row, 1), ".txt")?;
+    let result = diff(changed.path(), &[])?;
+    assert!(!result.status.success());
+    let stdout = String::from_utf8(result.stdout)?;
+    assert!(
+        stdout.contains("Rows with different values: 1\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains(&format!("PercArea {} != {}", fields[9], rounded)));
+    assert!(diff(changed.path(), &["--tolerance", "0.01"])?
+        .status
+        .success());
+
+    // The library comparison gives the same answer
+    let options = DiffOptions {
+        tolerance: 0.01,
+        ignore_order: true,
+    };
+    assert!(diff_files(table.path(), changed.path(), &options)?.is_identical());
+
+    Ok(())
+}
+
+#[test]
+fn test_summarize_subcommand() -> Result<(), Box<dyn std::error::Error>> {
+    let (gtf_path, bed_path) = fixture_paths();
+
+    for level in ["exon", "transcript", "gene"] {
+        let output_file = NamedTempFile::new()?;
+        let summary = tempfile::Builder::new().suffix(".json").tempfile()?;
+        Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("-g")
+            .arg(&gtf_path)
+            .arg("-b")
+            .arg(&bed_path)
+            .arg("-o")
+            .arg(output_file.path())
+            .args(["-r", level, "--summary"])
+            .arg(summary.path())
+            .assert()
+            .success();
+
+        let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+            .arg("summarize")
+            .arg(output_file.path())
+            .arg("--json")
+            .output()?;
+        assert!(output.status.success());
+        let summarized = String::from_utf8(output.stdout)?;
+        assert!(!summarized.contains("\"unassigned_regions\""));
+        assert!(summarized.contains("\"gene_associations\": {"));
+
+        // Everything the table records matches the summary of the run
+        let comparable = |json: &str| -> Vec<String> {
+            json.lines()
+                .filter(|line| {
+                    !line.contains("\"total_regions\"")
+                        && !line.contains("\"unassigned_regions\"")
+                        && !line.contains("\"timings\"")
+                })
+                .map(str::to_string)
+                .collect()
+        };
+        let expected = comparable(&std::fs::read_to_string(summary.path())?);
+        let without_genes: Vec<String> = comparable(&summarized)
+            .into_iter()
+            .filter(|line| !line.contains("\"gene_associations\""))
+            .collect();
+        assert_eq!(without_genes, expected, "level {}", level);
+    }
+
+    // TSV form, with one row per gene
+    let output_file = NamedTempFile::new()?;
+    Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("-g")
+        .arg(&gtf_path)
+        .arg("-b")
+        .arg(&bed_path)
+        .arg("-o")
+        .arg(output_file.path())
+        .args(["-r", "gene"])
+        .assert()
+        .success();
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("summarize")
+        .arg(output_file.path())
+        .output()?;
+    assert!(output.status.success());
+    let tsv = String::from_utf8(output.stdout)?;
+    assert!(tsv.contains("gene\tENSG"), "{}", tsv);
+    assert!(!tsv.contains("unassigned"));
+
+    // A file that is not an association table
+    let table = temp_file_with("Region\tGene\n", ".tsv")?;
+    let output = Command::new(env!("CARGO_BIN_EXE_rgmatch"))
+        .arg("summarize")
+        .arg(table.path())
+        .output()?;
+    assert!(!output.status.success());
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+// Log records of the library, captured by a test logger. The logger is global
+// to the process, so this file holds a single test.
+#![cfg(not(target_arch = "wasm32"))]
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::sync::Mutex;
+use tempfile::NamedTempFile;
+
+use rgmatch::{annotate, Config};
+
+/// Keeps every record as (level, message).
+struct CapturingLogger {
+    records: Mutex<Vec<(Level, String)>>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        self.records
+            .lock()
+            .unwrap()
+            .push((record.level(), record.args().to_string()));
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CapturingLogger = CapturingLogger {
+    records: Mutex::new(Vec::new()),
+};
+
+#[test]
+fn test_annotate_log_records() -> Result<(), Box<dyn std::error::Error>> {
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let gtf = NamedTempFile::new()?;
+    std::fs::write(
+        gtf.path(),
+        "#!genome-build test\n\
+         chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+         chr1\tTEST\texon\n",
+    )?;
+    let bed = NamedTempFile::new()?;
+    std::fs::write(
+        bed.path(),
+        "chrom\tstart\tend\n\
+         chr1\t900\t950\n\
+         chrX\t10\t20\n\
+         chrX\t30\t40\n\
+         chrY\t10\t20\n\
+         chrX\t50\t60\n",
+    )?;
+
+    let result = annotate(gtf.path(), bed.path(), &Config::default())?;
+    assert_eq!(result.associations.len(), 5);
+
+    let records = LOGGER.records.lock().unwrap();
+    let at = |level: Level| -> Vec<&str> {
+        records
+            .iter()
+            .filter(|(l, _)| *l == level)
+            .map(|(_, message)| message.as_str())
+            .collect()
+    };
+
+    // One warning for all missing chromosomes, not one per region
+    assert_eq!(
+        at(Level::Warn),
+        vec![
+            "2 chromosome(s) not found in the annotation, with 4 region(s) without genes: chrX (3), chrY (1)",
+        ]
+    );
+    // Per-line parser diagnostics are trace records
+    assert_eq!(
+        at(Level::Trace),
+        vec![
+            "GTF line 3: skipped, 3 columns instead of 9",
+            "BED line 1: skipped, start 'start' is not an integer",
+        ]
+    );
+    let info = at(Level::Info);
+    assert!(
+        info.contains(&"Loaded 1 genes on 1 chromosomes"),
+        "{:?}",
+        info
+    );
+    assert!(info.contains(&"Matching 5 regions"), "{:?}", info);
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Synthetic annotations, regions and candidates shared by the tests and the
+//! benchmarks.
+//!
+//! Everything is generated from a seeded [`Lcg`], so a fixture is the same on
+//! every run. The benchmarks include this file with
+//! `#[path = "../tests/support/mod.rs"]`.
+
+// Each test or bench target uses only some of the generators
+#![allow(dead_code)]
+
+use ahash::AHashMap;
+use rgmatch::{Area, Candidate, Region, Strand};
+
+/// Small deterministic generator (64-bit LCG).
+pub struct Lcg(pub u64);
+
+impl Lcg {
+    pub fn below(&mut self, n: i64) -> i64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((self.0 >> 33) % n as u64) as i64
+    }
+}
+
+/// Random genes with 1-3 transcripts of 1-4 exons, a few of them very long.
+pub fn random_gtf(rng: &mut Lcg, chroms: &[&str], genes_per_chrom: usize) -> String {
+    let mut gtf = String::new();
+    for chrom in chroms {
+        for g in 0..genes_per_chrom {
+            let start = 1 + rng.below(2_000_000);
+            let length = if rng.below(20) == 0 {
+                500_000 + rng.below(1_500_000)
+            } else {
+                200 + rng.below(30_000)
+            };
+            let strand = if rng.below(2) == 0 { '+' } else { '-' };
+            let gene_id = format!("{}_G{}", chrom, g);
+
+            for t in 0..1 + rng.below(3) {
+                let num_exons = 1 + rng.below(4);
+                let step = length / num_exons;
+                for e in 0..num_exons {
+                    let exon_start = start + e * step + rng.below(step / 4 + 1);
+                    let exon_end = exon_start + 1 + rng.below(step / 2 + 1);
+                    gtf.push_str(&format!(
+                        "{}\tTEST\texon\t{}\t{}\t.\t{}\t.\tgene_id \"{}\"; transcript_id \"{}_T{}\";\n",
+                        chrom, exon_start, exon_end, strand, gene_id, gene_id, t
+                    ));
+                }
+            }
+        }
+    }
+    gtf
+}
+
+pub fn random_regions(rng: &mut Lcg, chroms: &[&str], count: usize) -> Vec<Region> {
+    (0..count)
+        .map(|_| {
+            let chrom = chroms[rng.below(chroms.len() as i64) as usize];
+            let start = rng.below(2_100_000);
+            let end = start + rng.below(5_000);
+            Region::new(chrom.to_string(), start, end, vec![])
+        })
+        .collect()
+}
+
+/// `genes` evenly spaced genes on `chrom`, each with two transcripts of three
+/// exons over `gene_length` bases.
+///
+/// A `spacing` below `gene_length` makes neighbouring genes overlap.
+pub fn tiled_gtf(chrom: &str, genes: usize, spacing: i64, gene_length: i64) -> String {
+    let mut gtf = String::new();
+    let exon_length = (gene_length / 5).max(1);
+    for g in 0..genes {
+        let start = 1 + g as i64 * spacing;
+        let strand = if g % 2 == 0 { '+' } else { '-' };
+        for t in 0..2 {
+            // The second transcript skips the middle exon
+            for e in (0..3).filter(|e| t == 0 || *e != 1) {
+                let exon_start = start + e * 2 * exon_length;
+                gtf.push_str(&format!(
+                    "{}\tBENCH\texon\t{}\t{}\t.\t{}\t.\tgene_id \"G{}\"; transcript_id \"G{}_T{}\";\n",
+                    chrom,
+                    exon_start,
+                    exon_start + exon_length - 1,
+                    strand,
+                    g,
+                    g,
+                    t
+                ));
+            }
+        }
+    }
+    gtf
+}
+
+/// [`tiled_gtf`] with one gene of `megabases` Mb spanning the first genes,
+/// so every region in its span looks back over it.
+pub fn megabase_gene_gtf(chrom: &str, genes: usize, spacing: i64, megabases: i64) -> String {
+    let mut gtf = format!(
+        "{}\tBENCH\texon\t5000\t{}\t.\t+\t.\tgene_id \"LONG\"; transcript_id \"LONG_T\";\n",
+        chrom,
+        5000 + megabases * 1_000_000
+    );
+    gtf.push_str(&tiled_gtf(chrom, genes, spacing, 300));
+    gtf
+}
+
+/// One gene per strand with `exons` exons of 100-399 bases, like TTN with
+/// its 363, in `transcripts` transcripts that each skip a few of them.
+pub fn many_exon_gtf(rng: &mut Lcg, chrom: &str, exons: usize, transcripts: usize) -> String {
+    let mut gtf = String::new();
+    for (g, strand) in ['+', '-'].into_iter().enumerate() {
+        let mut exon_start = 1 + g as i64 * 10_000_000;
+        let mut coordinates = Vec::with_capacity(exons);
+        for _ in 0..exons {
+            let exon_end = exon_start + 100 + rng.below(300);
+            coordinates.push((exon_start, exon_end));
+            exon_start = exon_end + 200 + rng.below(5_000);
+        }
+        for t in 0..transcripts {
+            for (e, (exon_start, exon_end)) in coordinates.iter().enumerate() {
+                // Keep the first and last exons so the transcripts share their ends
+                if e > 0 && e + 1 < exons && rng.below(10) == 0 {
+                    continue;
+                }
+                gtf.push_str(&format!(
+                    "{}\tBENCH\texon\t{}\t{}\t.\t{}\t.\tgene_id \"M{}\"; transcript_id \"M{}_T{}\";\n",
+                    chrom, exon_start, exon_end, strand, g, g, t
+                ));
+            }
+        }
+    }
+    gtf
+}
+
+/// `count` sorted regions of 100-1,099 bases on `chrom` within `span` bases.
+pub fn sorted_regions(rng: &mut Lcg, chrom: &str, count: usize, span: i64) -> Vec<Region> {
+    let mut regions: Vec<Region> = (0..count)
+        .map(|_| {
+            let start = rng.below(span);
+            Region::new(
+                chrom.to_string(),
+                start,
+                start + 100 + rng.below(1_000),
+                vec![],
+            )
+        })
+        .collect();
+    regions.sort_by_key(|region| region.start);
+    regions
+}
+
+/// BED text of `count` regions with a name and a score column.
+pub fn bed_text(rng: &mut Lcg, chroms: &[&str], count: usize) -> String {
+    let mut bed = String::with_capacity(count * 40);
+    for i in 0..count {
+        let chrom = chroms[rng.below(chroms.len() as i64) as usize];
+        let start = rng.below(50_000_000);
+        bed.push_str(&format!(
+            "{}\t{}\t{}\tpeak{}\t{}\n",
+            chrom,
+            start,
+            start + 100 + rng.below(2_000),
+            i,
+            rng.below(1_000)
+        ));
+    }
+    bed
+}
+
+/// `count` candidates spread at random over `genes` genes of
+/// `transcripts` transcripts each, in random areas, and their indices
+/// grouped by transcript.
+pub fn candidate_group(
+    rng: &mut Lcg,
+    count: usize,
+    genes: usize,
+    transcripts: usize,
+) -> (Vec<Candidate>, AHashMap<String, Vec<usize>>) {
+    const AREAS: [Area; 9] = [
+        Area::Tss,
+        Area::FirstExon,
+        Area::Promoter,
+        Area::Tts,
+        Area::Intron,
+        Area::GeneBody,
+        Area::Upstream,
+        Area::Downstream,
+        Area::Intron,
+    ];
+
+    let mut candidates = Vec::with_capacity(count);
+    let mut grouped_by: AHashMap<String, Vec<usize>> = AHashMap::new();
+    for i in 0..count {
+        let gene = rng.below(genes as i64);
+        let transcript = format!("G{}_T{}", gene, rng.below(transcripts as i64));
+        grouped_by.entry(transcript.clone()).or_default().push(i);
+        candidates.push(Candidate::new(
+            1_000,
+            20_000,
+            Strand::Positive,
+            1 + rng.below(20) as u32,
+            AREAS[rng.below(AREAS.len() as i64) as usize],
+            transcript,
+            format!("G{}", gene),
+            rng.below(5_000),
+            rng.below(101) as f64,
+            rng.below(101) as f64,
+            rng.below(2_000) - 1_000,
+        ));
+    }
+    (candidates, grouped_by)
+}
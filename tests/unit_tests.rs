@@ -3,16 +3,21 @@
 //! These tests verify the core logic of rgmatch, especially coordinate mirroring
 //! and priority rule application.
 
+// Uses temporary files, which do not exist on wasm32
+#![cfg(not(target_arch = "wasm32"))]
+
+mod support;
+
 use rgmatch::config::Config;
 use rgmatch::matcher::overlap::{
-    find_search_start_index, match_region_to_genes, match_regions_to_genes,
+    associate_regions, find_search_start_index, match_region, match_region_to_genes,
     process_candidates_for_output,
 };
 use rgmatch::matcher::rules::{apply_rules, select_transcript};
 use rgmatch::matcher::tss::{check_tss, TssExonInfo};
 use rgmatch::matcher::tts::{check_tts, TtsExonInfo};
 use rgmatch::output::{format_output_line, write_header};
-use rgmatch::types::{Area, Candidate, ReportLevel, Strand, Transcript};
+use rgmatch::types::{Area, Candidate, ExonNumber, ExonNumbers, ReportLevel, Strand, Transcript};
 
 // -------------------------------------------------------------------------
 // Helper functions
@@ -30,7 +35,7 @@ fn make_candidate(
         100,
         200,
         Strand::Positive,
-        exon_number.to_string(),
+        exon_number.parse::<ExonNumbers>().unwrap(),
         area,
         transcript.to_string(),
         gene.to_string(),
@@ -87,9 +92,11 @@ mod test_check_tss {
         // Region [1900, 1950] is 50-100bp upstream - entirely in TSS zone (200bp)
         let res = check_tss(1900, 1950, &exon, 200.0, 1300.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "TSS");
+        assert_eq!(res[0].area, Area::Tss);
         // pctg_dhs should be 100% since entire region is in TSS
-        assert!(res[0].1 > 99.0);
+        assert!(res[0].pctg_region > 99.0);
+        // 51 of the 200 TSS bases are covered
+        assert_eq!(res[0].pctg_area, Some(25.5));
     }
 
     #[test]
@@ -103,8 +110,8 @@ mod test_check_tss {
         };
         // Region [1400, 1500] is 500-600bp upstream (in promoter zone)
         let res = check_tss(1400, 1500, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
     }
 
     #[test]
@@ -119,9 +126,9 @@ mod test_check_tss {
         // With TSS=200, promoter=1300: TSS+promoter extends to 1500bp
         // Region at distance 1400 spans into upstream
         let res = check_tss(100, 700, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         assert!(
-            tags.contains(&"PROMOTER") || tags.contains(&"UPSTREAM"),
+            tags.contains(&Area::Promoter) || tags.contains(&Area::Upstream),
             "Should contain PROMOTER or UPSTREAM: {:?}",
             tags
         );
@@ -138,9 +145,9 @@ mod test_check_tss {
         };
         let res = check_tss(4000, 4500, &exon, 200.0, 1300.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "UPSTREAM");
-        assert_eq!(res[0].1, 100.0); // 100% of region is upstream
-        assert_eq!(res[0].2, -1.0); // pctg_area is -1 for UPSTREAM
+        assert_eq!(res[0].area, Area::Upstream);
+        assert_eq!(res[0].pctg_region, 100.0); // 100% of region is upstream
+        assert_eq!(res[0].pctg_area, None); // no pctg_area for UPSTREAM
     }
 
     #[test]
@@ -155,7 +162,7 @@ mod test_check_tss {
         // For negative strand, upstream is > 3000
         // Region [3050, 3100] should be in TSS zone (50-100bp from end)
         let res = check_tss(3050, 3100, &exon, 200.0, 1300.0);
-        assert!(res.iter().any(|(t, _, _)| t == "TSS"));
+        assert!(res.iter().any(|o| o.area == Area::Tss));
     }
 
     #[test]
@@ -168,7 +175,7 @@ mod test_check_tss {
             distance: 3000, // 3000bp upstream from end
         };
         let res = check_tss(6000, 6100, &exon, 200.0, 1300.0);
-        assert!(res.iter().any(|(t, _, _)| t == "UPSTREAM"));
+        assert!(res.iter().any(|o| o.area == Area::Upstream));
     }
 
     #[test]
@@ -187,7 +194,7 @@ mod test_check_tss {
         };
         let res = check_tss(1800, 1810, &exon, 200.0, 1300.0);
         assert!(
-            res.iter().any(|(tag, _, _)| tag == "TSS"),
+            res.iter().any(|o| o.area == Area::Tss),
             "1800 should be TSS: {:?}",
             res
         );
@@ -195,9 +202,9 @@ mod test_check_tss {
         // Case 2: Just outside TSS boundary -> [1799, 1810]
         // 2000 - 1799 = 201. > 200. Should be PROMOTER.
         let res = check_tss(1799, 1810, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
-        assert!(tags.contains(&"TSS"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
+        assert!(tags.contains(&Area::Tss));
 
         // Case 3: Far upstream (UPSTREAM tag)
         let exon_far = TssExonInfo {
@@ -207,10 +214,10 @@ mod test_check_tss {
             distance: 1800,
         };
         let res = check_tss(100, 200, &exon_far, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"UPSTREAM"));
-        assert!(!tags.contains(&"TSS"));
-        assert!(!tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Upstream));
+        assert!(!tags.contains(&Area::Tss));
+        assert!(!tags.contains(&Area::Promoter));
     }
 
     #[test]
@@ -227,12 +234,12 @@ mod test_check_tss {
 
         // Case 1: Region [3200, 3210] should be PROMOTER
         let res = check_tss(3200, 3210, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(tag, _, _)| tag.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
 
         // Case 2: TSS Zone Inside [3100, 3150]
         let res = check_tss(3100, 3150, &exon, 200.0, 1300.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TSS"));
+        assert!(res.iter().any(|o| o.area == Area::Tss));
     }
 
     #[test]
@@ -290,7 +297,7 @@ mod test_check_tts {
         // Region [2050, 2100] is 50-100bp downstream - should be in TTS zone
         let res = check_tts(2050, 2100, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "TTS");
+        assert_eq!(res[0].area, Area::Tts);
     }
 
     #[test]
@@ -304,9 +311,9 @@ mod test_check_tts {
         };
         let res = check_tts(2500, 2600, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "DOWNSTREAM");
-        assert_eq!(res[0].1, 100.0); // 100% downstream
-        assert_eq!(res[0].2, -1.0); // pctg_area is -1 for DOWNSTREAM
+        assert_eq!(res[0].area, Area::Downstream);
+        assert_eq!(res[0].pctg_region, 100.0); // 100% downstream
+        assert_eq!(res[0].pctg_area, None); // no pctg_area for DOWNSTREAM
     }
 
     #[test]
@@ -322,7 +329,7 @@ mod test_check_tts {
         // Region [900, 950] is 50-100bp "downstream" (before start)
         let res = check_tts(900, 950, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "TTS");
+        assert_eq!(res[0].area, Area::Tts);
     }
 
     #[test]
@@ -336,7 +343,7 @@ mod test_check_tts {
         };
         let res = check_tts(400, 500, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "DOWNSTREAM");
+        assert_eq!(res[0].area, Area::Downstream);
     }
 
     #[test]
@@ -350,7 +357,7 @@ mod test_check_tts {
         };
         let res = check_tts(2050, 2100, &exon, 0.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "DOWNSTREAM");
+        assert_eq!(res[0].area, Area::Downstream);
     }
 
     #[test]
@@ -365,9 +372,9 @@ mod test_check_tts {
         // Region [2000, 2100] - 100bp, half in TTS zone (if TTS=100)
         let res = check_tts(2050, 2150, &exon, 100.0);
         // Should span TTS and DOWNSTREAM
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         assert!(
-            tags.contains(&"TTS") && tags.contains(&"DOWNSTREAM"),
+            tags.contains(&Area::Tts) && tags.contains(&Area::Downstream),
             "Should span TTS and DOWNSTREAM: {:?}",
             tags
         );
@@ -386,7 +393,7 @@ mod test_check_tts {
 
         // Case 1: Downstream 100bp [2100, 2150]
         let res = check_tts(2100, 2150, &exon, 200.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -402,7 +409,7 @@ mod test_check_tts {
 
         // Case 1: Downstream 100bp [850, 900]
         let res = check_tts(850, 900, &exon, 200.0);
-        assert!(res.iter().any(|(tag, _, _)| tag == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -425,6 +432,63 @@ mod test_check_tts {
 mod test_apply_rules {
     use super::*;
     use ahash::AHashMap;
+    use rgmatch::matcher::rules::{apply_scores, select_transcript_by_score};
+    use rgmatch::types::ScoreCombination;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_apply_scores_tie_breaking() {
+        let rules = default_rules();
+        let weights = BTreeMap::new();
+
+        // Equal scores: the area first in the rules wins, then the lowest
+        // transcript ID
+        let candidates = vec![
+            make_candidate(Area::Intron, 100.0, 100.0, "T2", "G1", "1"),
+            make_candidate(Area::Tss, 100.0, 100.0, "T2", "G1", "1"),
+            make_candidate(Area::Tss, 100.0, 100.0, "T1", "G1", "1"),
+        ];
+        let mut by_transcript = AHashMap::new();
+        by_transcript.insert("T".to_string(), vec![0, 1, 2]);
+        let result = apply_scores(
+            &candidates,
+            &by_transcript,
+            &weights,
+            ScoreCombination::Product,
+            &rules,
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            (result[0].area, result[0].transcript.as_str()),
+            (Area::Tss, "T1")
+        );
+
+        let mut by_gene = AHashMap::new();
+        by_gene.insert("G1".to_string(), vec![2, 1, 0]);
+        let result = select_transcript_by_score(
+            &candidates,
+            &by_gene,
+            &weights,
+            ScoreCombination::Sum,
+            &rules,
+        );
+        assert_eq!(result.len(), 1);
+        assert_eq!(
+            (result[0].area, result[0].transcript.as_str()),
+            (Area::Tss, "T1")
+        );
+
+        // A weight outranks the rules
+        let weights = BTreeMap::from([(Area::Intron, 2.0)]);
+        let result = apply_scores(
+            &candidates,
+            &by_transcript,
+            &weights,
+            ScoreCombination::Product,
+            &rules,
+        );
+        assert_eq!(result[0].area, Area::Intron);
+    }
 
     #[test]
     fn test_priority_logic() {
@@ -535,7 +599,7 @@ mod test_apply_rules {
     fn test_empty_grouped_by() {
         let rules = default_rules();
         let candidates: Vec<Candidate> = vec![];
-        let grouped_by = AHashMap::new();
+        let grouped_by: AHashMap<String, Vec<usize>> = AHashMap::new();
 
         let result = apply_rules(&candidates, &grouped_by, 50.0, 90.0, &rules);
         assert!(result.is_empty());
@@ -603,7 +667,7 @@ mod test_apply_rules {
     fn test_select_transcript_empty() {
         let rules = default_rules();
         let candidates: Vec<Candidate> = vec![];
-        let grouped_by = AHashMap::new();
+        let grouped_by: AHashMap<String, Vec<usize>> = AHashMap::new();
 
         let result = select_transcript(&candidates, &grouped_by, &rules);
         assert!(result.is_empty());
@@ -668,11 +732,11 @@ mod test_transcript {
         // Should be sorted by start: e1, e2, e3
         // Numbering: 1, 2, 3
         assert_eq!(t.exons[0].start, 100);
-        assert_eq!(t.exons[0].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(1));
         assert_eq!(t.exons[1].start, 300);
-        assert_eq!(t.exons[1].exon_number, Some("2".to_string()));
+        assert_eq!(t.exons[1].exon_number, Some(2));
         assert_eq!(t.exons[2].start, 500);
-        assert_eq!(t.exons[2].exon_number, Some("3".to_string()));
+        assert_eq!(t.exons[2].exon_number, Some(3));
     }
 
     #[test]
@@ -686,9 +750,9 @@ mod test_transcript {
         // Sorted by start: [100-200, 300-400]
         // For negative strand: first (lowest) gets N, last (highest) gets 1
         assert_eq!(t.exons[0].start, 100);
-        assert_eq!(t.exons[0].exon_number, Some("2".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(2));
         assert_eq!(t.exons[1].start, 300);
-        assert_eq!(t.exons[1].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[1].exon_number, Some(1));
     }
 }
 
@@ -766,8 +830,46 @@ mod test_select_transcript {
         grouped_by.insert("G1".to_string(), vec![0, 1]);
 
         let result = select_transcript(&candidates, &grouped_by, &rules);
-        assert!(result[0].exon_number.contains("1"));
-        assert!(result[0].exon_number.contains("3"));
+        assert_eq!(result[0].exon_number, "1,3");
+    }
+
+    #[test]
+    fn test_merged_candidate_keeps_region() {
+        let rules = default_rules();
+        let region = rgmatch::Region::new("chr2".to_string(), 100, 200, vec![]);
+        let c1 = make_candidate(Area::Tss, 100.0, 100.0, "T1", "G1", "1").with_region(&region);
+        let c2 = make_candidate(Area::Tss, 100.0, 100.0, "T2", "G1", "3").with_region(&region);
+        let candidates = vec![c1, c2];
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1]);
+
+        let result = select_transcript(&candidates, &grouped_by, &rules);
+        assert_eq!(result[0].transcript, "T1,T2");
+        assert_eq!(result[0].chrom, "chr2");
+        assert_eq!(result[0].region_id, "chr2_100_200");
+    }
+
+    #[test]
+    fn test_never_merges_different_genes() {
+        let rules = default_rules();
+        let c1 = make_candidate(Area::Tss, 100.0, 100.0, "T1", "G1", "1");
+        let c2 = make_candidate(Area::Tss, 100.0, 100.0, "T2", "G2", "1");
+        let c3 = make_candidate(Area::Tss, 100.0, 100.0, "T3", "G1", "2");
+        let candidates = vec![c1, c2, c3];
+        // A group mixing genes, e.g. keyed by something other than the gene
+        let mut grouped_by = AHashMap::new();
+        grouped_by.insert("G1".to_string(), vec![0, 1, 2]);
+
+        let result = select_transcript(&candidates, &grouped_by, &rules);
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            (result[0].gene.as_str(), result[0].transcript.as_str()),
+            ("G1", "T1,T3")
+        );
+        assert_eq!(
+            (result[1].gene.as_str(), result[1].transcript.as_str()),
+            ("G2", "T2")
+        );
     }
 }
 
@@ -801,6 +903,22 @@ mod test_config {
         assert_eq!(config.rules[7], Area::Tss);
     }
 
+    #[test]
+    fn test_parse_rules_intergenic_goes_last() {
+        let mut config = Config::new();
+        assert!(config.parse_rules(
+            "INTERGENIC,TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"
+        ));
+        assert_eq!(config.rules.len(), 9);
+        assert_eq!(config.rules[0], Area::Tss);
+        assert_eq!(config.rules[8], Area::Intergenic);
+
+        // INTERGENIC does not stand in for a missing area
+        assert!(
+            !config.parse_rules("INTERGENIC,TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM")
+        );
+    }
+
     #[test]
     fn test_parse_rules_missing_tags() {
         let mut config = Config::new();
@@ -829,8 +947,9 @@ mod test_config {
         config.set_distance_kb(20);
         assert_eq!(config.distance, 20000);
 
+        // A negative distance removes the cutoff
         config.set_distance_kb(-1);
-        assert_eq!(config.distance, 20000); // Should not change
+        assert!(config.has_unlimited_distance());
     }
 }
 
@@ -857,7 +976,7 @@ mod test_bug_regression {
         let mut transcript = Transcript::new(format!("TRANS_{}", gene_id.replace("GENE", "")));
         for (i, (exon_start, exon_end)) in exons.iter().enumerate() {
             let mut exon = Exon::new(*exon_start, *exon_end);
-            exon.exon_number = Some((i + 1).to_string());
+            exon.exon_number = Some(i as u32 + 1);
             transcript.add_exon(exon);
         }
         transcript.calculate_size();
@@ -1082,6 +1201,24 @@ mod test_types_strand {
         assert!(set.contains(&Strand::Positive));
         assert!(set.contains(&Strand::Negative));
     }
+
+    #[test]
+    fn test_strand_round_trip() {
+        for strand in [Strand::Positive, Strand::Negative] {
+            assert_eq!(strand.to_string().parse::<Strand>().unwrap(), strand);
+
+            #[cfg(feature = "serde")]
+            {
+                let value = toml::Value::try_from(strand).unwrap();
+                assert_eq!(value.as_str(), Some(strand.as_str()));
+                assert_eq!(value.try_into::<Strand>().unwrap(), strand);
+            }
+        }
+        #[cfg(feature = "serde")]
+        {
+            assert!(toml::Value::from(".").try_into::<Strand>().is_err());
+        }
+    }
 }
 
 mod test_types_area {
@@ -1141,6 +1278,35 @@ mod test_types_area {
         areas.sort();
         assert_eq!(areas[0], Area::Tss);
     }
+
+    #[test]
+    fn test_area_round_trip() {
+        let areas = [
+            Area::Tss,
+            Area::FirstExon,
+            Area::Promoter,
+            Area::Tts,
+            Area::Intron,
+            Area::GeneBody,
+            Area::Upstream,
+            Area::Downstream,
+        ];
+        for area in areas {
+            assert_eq!(area.to_string().parse::<Area>().unwrap(), area);
+
+            #[cfg(feature = "serde")]
+            {
+                let value = toml::Value::try_from(area).unwrap();
+                assert_eq!(value.as_str(), Some(area.as_str()));
+                assert_eq!(value.try_into::<Area>().unwrap(), area);
+            }
+        }
+        #[cfg(feature = "serde")]
+        {
+            let err = toml::Value::from("EXON").try_into::<Area>().unwrap_err();
+            assert!(err.to_string().contains("invalid area type 'EXON'"));
+        }
+    }
 }
 
 mod test_types_exon {
@@ -1169,12 +1335,12 @@ mod test_types_exon {
     #[test]
     fn test_exon_clone() {
         let mut exon = Exon::new(100, 200);
-        exon.exon_number = Some("3".to_string());
+        exon.exon_number = Some(3);
 
         let cloned = exon.clone();
         assert_eq!(cloned.start, 100);
         assert_eq!(cloned.end, 200);
-        assert_eq!(cloned.exon_number, Some("3".to_string()));
+        assert_eq!(cloned.exon_number, Some(3));
     }
 }
 
@@ -1235,11 +1401,11 @@ mod test_types_transcript {
 
         // Sorted by start, numbered 1, 2, 3
         assert_eq!(t.exons[0].start, 100);
-        assert_eq!(t.exons[0].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(1));
         assert_eq!(t.exons[1].start, 300);
-        assert_eq!(t.exons[1].exon_number, Some("2".to_string()));
+        assert_eq!(t.exons[1].exon_number, Some(2));
         assert_eq!(t.exons[2].start, 500);
-        assert_eq!(t.exons[2].exon_number, Some("3".to_string()));
+        assert_eq!(t.exons[2].exon_number, Some(3));
     }
 
     #[test]
@@ -1252,9 +1418,9 @@ mod test_types_transcript {
 
         // Sorted by start, but numbered in reverse
         assert_eq!(t.exons[0].start, 100);
-        assert_eq!(t.exons[0].exon_number, Some("2".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(2));
         assert_eq!(t.exons[1].start, 500);
-        assert_eq!(t.exons[1].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[1].exon_number, Some(1));
     }
 
     #[test]
@@ -1348,7 +1514,7 @@ mod test_types_candidate {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -1377,7 +1543,7 @@ mod test_types_candidate {
             100,
             200,
             Strand::Negative,
-            "2".to_string(),
+            2,
             Area::Intron,
             "T2".to_string(),
             "G2".to_string(),
@@ -1494,6 +1660,37 @@ mod test_types_report_level {
         assert!("exons".parse::<ReportLevel>().is_err());
     }
 
+    #[test]
+    fn test_report_level_display() {
+        assert_eq!(ReportLevel::Exon.to_string(), "exon");
+        assert_eq!(ReportLevel::Transcript.to_string(), "transcript");
+        assert_eq!(ReportLevel::Gene.to_string(), "gene");
+    }
+
+    #[test]
+    fn test_report_level_round_trip() {
+        use clap::ValueEnum;
+
+        for &level in ReportLevel::value_variants() {
+            assert_eq!(level.to_string().parse::<ReportLevel>().unwrap(), level);
+            assert_eq!(ReportLevel::from_str(level.as_str(), false).unwrap(), level);
+
+            #[cfg(feature = "serde")]
+            {
+                let value = toml::Value::try_from(level).unwrap();
+                assert_eq!(value.as_str(), Some(level.as_str()));
+                assert_eq!(value.try_into::<ReportLevel>().unwrap(), level);
+            }
+        }
+        assert_eq!(ReportLevel::value_variants().len(), 3);
+        #[cfg(feature = "serde")]
+        {
+            assert!(toml::Value::from("chromosome")
+                .try_into::<ReportLevel>()
+                .is_err());
+        }
+    }
+
     #[test]
     fn test_report_level_clone_copy() {
         let r1 = ReportLevel::Gene;
@@ -1589,7 +1786,7 @@ mod test_overlap_functions {
         let mut transcript = Transcript::new(format!("TRANS_{}", gene_id.replace("GENE", "")));
         for (i, (exon_start, exon_end)) in exons.iter().enumerate() {
             let mut exon = Exon::new(*exon_start, *exon_end);
-            exon.exon_number = Some((i + 1).to_string());
+            exon.exon_number = Some(i as u32 + 1);
             transcript.add_exon(exon);
         }
         transcript.calculate_size();
@@ -1673,6 +1870,83 @@ mod test_overlap_functions {
         assert!(has_first_exon);
     }
 
+    #[test]
+    fn test_tts_distance_positive_strand() {
+        let config = Config::default();
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            2000,
+            Strand::Positive,
+            vec![(1000, 1200), (1800, 2000)],
+        )];
+
+        // Midpoint 2500 lies 500bp downstream of the TTS at 2000
+        let region = Region::new("chr1".into(), 2400, 2600, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.tts_distance == 500));
+
+        // Midpoint 1100 lies inside the gene, upstream of the TTS
+        let region = Region::new("chr1".into(), 1050, 1150, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.tts_distance == -900));
+    }
+
+    #[test]
+    fn test_tts_distance_negative_strand() {
+        let config = Config::default();
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            2000,
+            Strand::Negative,
+            vec![(1000, 1200), (1800, 2000)],
+        )];
+
+        // The TTS of a - strand gene is its start; 1000 - 500 = 500bp downstream
+        let region = Region::new("chr1".into(), 400, 600, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.tts_distance == 500));
+
+        let region = Region::new("chr1".into(), 1850, 1950, vec![]);
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.tts_distance == -900));
+    }
+
+    #[test]
+    fn test_match_region_counts_candidate_genes() {
+        let config = Config {
+            level: ReportLevel::Gene,
+            ..Config::default()
+        };
+        // G2 is nested inside the first intron of G1
+        let genes = vec![
+            make_test_gene(
+                "G1",
+                1000,
+                5000,
+                Strand::Positive,
+                vec![(1000, 1200), (4800, 5000)],
+            ),
+            make_test_gene("G2", 2000, 3000, Strand::Positive, vec![(2000, 3000)]),
+        ];
+        let region = Region::new("chr1".into(), 2400, 2500, vec![]);
+
+        let matches = match_region(&region, &genes, &config, 0);
+        assert_eq!(matches.num_candidate_genes, 2);
+        assert_eq!(matches.candidates.len(), 2);
+
+        // Regions without candidates count no genes
+        let far = Region::new("chr1".into(), 900_000, 900_100, vec![]);
+        let matches = match_region(&far, &genes, &config, 0);
+        assert_eq!(matches.num_candidate_genes, 0);
+        assert!(matches.candidates.is_empty());
+    }
+
     #[test]
     fn test_process_candidates_empty() {
         let config = Config::default();
@@ -1736,7 +2010,37 @@ mod test_overlap_functions {
     }
 
     #[test]
-    fn test_match_regions_to_genes_basic() {
+    fn test_process_candidates_identical_rows() {
+        // Two transcripts with the same structure, and a repeated exon in T1
+        let candidates = vec![
+            make_candidate(Area::Intron, 100.0, 10.11, "T1", "G1", "1"),
+            make_candidate(Area::Intron, 100.0, 10.11, "T1", "G1", "1"),
+            make_candidate(Area::Intron, 100.0, 10.11, "T2", "G1", "1"),
+        ];
+        let at_level = |level| {
+            let config = Config {
+                level,
+                ..Default::default()
+            };
+            process_candidates_for_output(candidates.clone(), &config)
+        };
+
+        let gene = at_level(ReportLevel::Gene);
+        assert_eq!(gene.len(), 1);
+        assert_eq!(gene[0].transcript, "T1,T2");
+        assert_eq!(gene[0].exon_number, "1");
+
+        let transcript = at_level(ReportLevel::Transcript);
+        assert_eq!(transcript.len(), 2);
+        assert_eq!(transcript[0].transcript, "T1");
+        assert_eq!(transcript[1].transcript, "T2");
+
+        // Exon level reports every candidate
+        assert_eq!(at_level(ReportLevel::Exon).len(), 3);
+    }
+
+    #[test]
+    fn test_associate_regions_basic() {
         let config = Config::default();
         let regions = vec![
             Region::new("chr1".into(), 1050, 1150, vec![]),
@@ -1751,17 +2055,17 @@ mod test_overlap_functions {
             vec![(1000, 1200), (1500, 1700)],
         )];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 0);
+        let results = associate_regions(&regions, &genes, &config, 0);
 
         assert_eq!(results.len(), 2);
 
         // First region
-        assert_eq!(results[0].0.start, 1050);
-        assert!(!results[0].1.is_empty());
+        assert_eq!(results[0].region.start, 1050);
+        assert!(!results[0].candidates.is_empty());
 
         // Second region
-        assert_eq!(results[1].0.start, 1500);
-        assert!(!results[1].1.is_empty());
+        assert_eq!(results[1].region.start, 1500);
+        assert!(!results[1].candidates.is_empty());
     }
 
     #[test]
@@ -1892,105 +2196,1532 @@ mod test_overlap_functions {
             candidates.iter().map(|c| c.area).collect::<Vec<_>>()
         );
     }
-}
-
-// -------------------------------------------------------------------------
-// 10. Output Module Tests
-// -------------------------------------------------------------------------
-
-// -------------------------------------------------------------------------
-// 10. Parser BED Module Tests
-// -------------------------------------------------------------------------
-
-mod test_parser_bed {
-    use rgmatch::parser::bed::get_bed_headers;
-    use rgmatch::BedReader;
-    use rgmatch::Region;
-    use std::io::Write;
-    use tempfile::NamedTempFile;
 
     #[test]
-    fn test_get_bed_headers_zero() {
-        let headers = get_bed_headers(0);
-        assert!(headers.is_empty());
-    }
+    fn test_candidates_carry_region_through_gene_level() {
+        // Region spans two introns and an exon, so entries are aggregated
+        // before the gene-level merge
+        let region = Region::new("chr7".into(), 1150, 2450, vec![]);
+        let mut gene = make_test_gene(
+            "G1",
+            1000,
+            3000,
+            Strand::Positive,
+            vec![(1000, 1100), (1800, 2000), (2900, 3000)],
+        );
+        let mut second = gene.transcripts[0].clone();
+        second.transcript_id = "TRANS_2".into();
+        gene.transcripts.push(second);
+        let genes = vec![gene];
 
-    #[test]
-    fn test_get_bed_headers_partial() {
-        let headers = get_bed_headers(3);
-        assert_eq!(headers.len(), 3);
-        assert_eq!(headers[0], "name");
-        assert_eq!(headers[1], "score");
-        assert_eq!(headers[2], "strand");
-    }
+        let candidates = match_region_to_genes(&region, &genes, &Config::default(), 0);
+        assert!(candidates.iter().any(|c| c.exon_number.len() > 1));
 
-    #[test]
-    fn test_get_bed_headers_full() {
-        let headers = get_bed_headers(9);
-        assert_eq!(headers.len(), 9);
-        assert_eq!(headers[8], "blockStarts");
-    }
+        let config = Config {
+            level: ReportLevel::Gene,
+            ..Config::default()
+        };
+        let reported = process_candidates_for_output(candidates.clone(), &config);
+        assert_eq!(reported.len(), 1);
+        assert!(reported[0].transcript.contains("TRANS_2"));
 
-    #[test]
-    fn test_get_bed_headers_exceeds_max() {
-        // Request more than available, should cap at 9
-        let headers = get_bed_headers(20);
-        assert_eq!(headers.len(), 9);
+        for c in candidates.iter().chain(&reported) {
+            assert_eq!(c.chrom, "chr7");
+            assert_eq!(c.region_id, "chr7_1150_2450");
+        }
     }
 
     #[test]
-    fn test_bed_reader_multiple_chroms() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, "chr1\t100\t200").unwrap();
-        writeln!(temp_file, "chr2\t300\t400").unwrap();
-        writeln!(temp_file, "chr3\t500\t600").unwrap();
-        writeln!(temp_file, "chr1\t700\t800").unwrap();
-        temp_file.flush().unwrap();
+    fn test_strandedness_filters_proximity_candidates() {
+        use rgmatch::types::Strandedness;
 
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
-        let chunk = reader.read_chunk(100).unwrap().unwrap();
+        // Region downstream of both genes, nearer to the minus strand one
+        let genes = vec![
+            make_test_gene("GP", 1000, 2000, Strand::Positive, vec![(1000, 2000)]),
+            make_test_gene("GM", 3500, 4500, Strand::Negative, vec![(3500, 4500)]),
+        ];
+        let plus =
+            Region::new("chr1".into(), 3000, 3100, vec![]).with_strand(Some(Strand::Positive));
+        let unstranded = Region::new("chr1".into(), 3000, 3100, vec![]);
+        let genes_of = |region: &Region, strandedness, strict| -> Vec<String> {
+            let config = Config {
+                strandedness,
+                strict_strandedness: strict,
+                ..Config::default()
+            };
+            let mut ids: Vec<String> = match_region_to_genes(region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| c.gene.into())
+                .collect();
+            ids.dedup();
+            ids
+        };
 
-        assert_eq!(chunk.len(), 4);
-        assert_eq!(chunk[0].chrom, "chr1");
-        assert_eq!(chunk[1].chrom, "chr2");
-        assert_eq!(chunk[2].chrom, "chr3");
-        assert_eq!(chunk[3].chrom, "chr1");
+        assert_eq!(genes_of(&plus, Strandedness::Ignore, false), vec!["GM"]);
+        assert_eq!(genes_of(&plus, Strandedness::Same, false), vec!["GP"]);
+        assert_eq!(genes_of(&plus, Strandedness::Opposite, false), vec!["GM"]);
+
+        // Unstranded regions ignore the strand unless strict
+        assert_eq!(genes_of(&unstranded, Strandedness::Same, false), vec!["GM"]);
+        assert!(genes_of(&unstranded, Strandedness::Same, true).is_empty());
+        assert_eq!(
+            genes_of(&unstranded, Strandedness::Ignore, true),
+            vec!["GM"]
+        );
     }
 
     #[test]
-    fn test_bed_reader_max_metadata_columns() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        // 12-column BED (9 metadata columns)
-        writeln!(
-            temp_file,
-            "chr1\t100\t200\tname\t500\t+\t100\t200\t0,0,0\t2\t50,50\t0,100"
-        )
-        .unwrap();
-        temp_file.flush().unwrap();
+    fn test_closest_fallback_ranking() {
+        use rgmatch::matcher::overlap::match_region;
+        use rgmatch::types::{ClosestBy, Strandedness};
 
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
-        let chunk = reader.read_chunk(100).unwrap().unwrap();
+        // GL has the nearest edge, GR the nearest TSS (its end, on - strand)
+        let genes = vec![
+            make_test_gene(
+                "GL",
+                10_000,
+                58_000,
+                Strand::Positive,
+                vec![(10_000, 58_000)],
+            ),
+            make_test_gene(
+                "GR",
+                84_000,
+                94_000,
+                Strand::Negative,
+                vec![(84_000, 94_000)],
+            ),
+        ];
+        let region =
+            Region::new("chr1".into(), 70_000, 70_100, vec![]).with_strand(Some(Strand::Positive));
+        let closest = |closest_by, strandedness| -> Vec<(String, Area, bool)> {
+            let config = Config {
+                always_closest: true,
+                closest_by,
+                strandedness,
+                level: ReportLevel::Gene,
+                ..Config::default()
+            };
+            match_region(&region, &genes, &config, 0)
+                .candidates
+                .into_iter()
+                .map(|c| (c.gene.into(), c.area, c.closest_fallback))
+                .collect()
+        };
 
-        assert_eq!(reader.num_meta_columns(), 9);
-        assert_eq!(chunk[0].metadata.len(), 9);
-        assert_eq!(chunk[0].metadata[0], "name");
-        assert_eq!(chunk[0].metadata[8], "0,100");
+        assert_eq!(
+            closest(ClosestBy::Edge, Strandedness::Ignore),
+            vec![("GL".to_string(), Area::Downstream, true)]
+        );
+        assert_eq!(
+            closest(ClosestBy::Tss, Strandedness::Ignore),
+            vec![("GR".to_string(), Area::Downstream, true)]
+        );
+        // The fallback respects the strand filter
+        assert_eq!(
+            closest(ClosestBy::Edge, Strandedness::Opposite),
+            vec![("GR".to_string(), Area::Downstream, true)]
+        );
+
+        // Regions with candidates within the cutoff do not use the fallback
+        let config = Config {
+            always_closest: true,
+            ..Config::default()
+        };
+        let near = Region::new("chr1".into(), 59_000, 59_100, vec![]);
+        let candidates = match_region(&near, &genes, &config, 0).candidates;
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| !c.closest_fallback));
     }
 
     #[test]
-    fn test_bed_reader_skip_malformed_lines() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, "chr1\t100").unwrap(); // Only 2 columns
-        writeln!(temp_file, "chr1\t100\t200").unwrap(); // Valid
-        temp_file.flush().unwrap();
-
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
-        let chunk = reader.read_chunk(100).unwrap().unwrap();
+    fn test_n_closest_ranking() {
+        use rgmatch::matcher::overlap::match_region;
 
-        assert_eq!(chunk.len(), 1);
-        assert_eq!(chunk[0].start, 100);
-        assert_eq!(chunk[0].end, 200);
-    }
+        // TSS distances from the midpoint 15050: GA 5050, GT 5050, GB 9950,
+        // GC 24950, GD 84950
+        let genes = vec![
+            make_test_gene(
+                "GA",
+                10_000,
+                12_000,
+                Strand::Positive,
+                vec![(10_000, 12_000)],
+            ),
+            make_test_gene(
+                "GT",
+                19_000,
+                20_100,
+                Strand::Negative,
+                vec![(19_000, 20_100)],
+            ),
+            make_test_gene(
+                "GB",
+                20_000,
+                25_000,
+                Strand::Negative,
+                vec![(20_000, 25_000)],
+            ),
+            make_test_gene(
+                "GC",
+                40_000,
+                41_000,
+                Strand::Positive,
+                vec![(40_000, 41_000)],
+            ),
+            make_test_gene(
+                "GD",
+                100_000,
+                101_000,
+                Strand::Positive,
+                vec![(100_000, 101_000)],
+            ),
+        ];
+        let region = Region::new("chr1".into(), 15_000, 15_100, vec![]);
+        let rows = |n_closest, closest_only, distance| -> Vec<(String, Option<usize>, i64)> {
+            let config = Config {
+                n_closest,
+                closest_only,
+                distance,
+                level: ReportLevel::Gene,
+                ..Config::default()
+            };
+            match_region(&region, &genes, &config, 0)
+                .candidates
+                .into_iter()
+                .map(|c| (c.gene.into(), c.rank, c.distance))
+                .collect()
+        };
+        let ranked = |rows: Vec<(String, Option<usize>, i64)>| -> Vec<(String, usize)> {
+            let mut ranked: Vec<(String, usize)> = rows
+                .into_iter()
+                .filter_map(|(gene, rank, _)| Some((gene, rank?)))
+                .collect();
+            ranked.sort_by_key(|(_, rank)| *rank);
+            ranked
+        };
+
+        // Ties in TSS distance go to the lowest gene ID; GC lies beyond the
+        // 10 kb cutoff but is still ranked
+        let all = rows(4, false, 10_000);
+        assert_eq!(
+            ranked(all.clone()),
+            vec![
+                ("GA".to_string(), 1),
+                ("GT".to_string(), 2),
+                ("GB".to_string(), 3),
+                ("GC".to_string(), 4),
+            ]
+        );
+        // Rule-selected rows of unranked genes stay, without a rank
+        let plain = rows(0, false, 10_000);
+        assert!(plain.iter().all(|(_, rank, _)| rank.is_none()));
+        for (gene, _, _) in &plain {
+            assert!(all.iter().any(|(g, _, _)| g == gene));
+        }
+        assert!(!plain.iter().any(|(gene, _, _)| gene == "GC"));
+
+        // With a 1 kb cutoff nothing is within reach, yet the nearest genes
+        // are reported with their true distances
+        assert!(rows(0, false, 1000).is_empty());
+        assert_eq!(
+            rows(2, false, 1000),
+            vec![
+                ("GA".to_string(), Some(1), 3050),
+                ("GT".to_string(), Some(2), 3950),
+            ]
+        );
+
+        // Only the ranked rows, in rank order
+        assert_eq!(
+            rows(1, true, 10_000)
+                .into_iter()
+                .map(|(gene, rank, _)| (gene, rank))
+                .collect::<Vec<_>>(),
+            vec![("GA".to_string(), Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_nearest_gene_search_matches_full_scan() {
+        use rgmatch::matcher::overlap::{associate_regions, match_region};
+        use rgmatch::matcher::RegionMatcher;
+        use rgmatch::source::{sort_genes, VecSource};
+        use rgmatch::types::ClosestBy;
+
+        // Deterministic pseudo-random genes, with one long gene spanning many
+        let mut seed: i64 = 7;
+        let mut next = |bound: i64| {
+            seed = (seed * 1_103_515_245 + 12_345) % 2_147_483_648;
+            seed % bound
+        };
+        let mut genes = vec![make_test_gene(
+            "G000",
+            1000,
+            400_000,
+            Strand::Negative,
+            vec![(1000, 400_000)],
+        )];
+        for i in 1..200 {
+            let start = next(2_000_000) + 1;
+            let end = start + next(50_000);
+            let strand = if next(2) == 0 {
+                Strand::Positive
+            } else {
+                Strand::Negative
+            };
+            genes.push(make_test_gene(
+                &format!("G{:03}", i),
+                start,
+                end,
+                strand,
+                vec![(start, end)],
+            ));
+        }
+        sort_genes(&mut genes);
+        let mut regions: Vec<Region> = (0..300)
+            .map(|_| {
+                let start = next(2_100_000) + 1;
+                Region::new("chr1".into(), start, start + 100, vec![])
+            })
+            .collect();
+        regions.sort_by_key(|r| r.start);
+        let source: VecSource = genes
+            .iter()
+            .map(|gene| ("chr1".to_string(), gene.clone()))
+            .collect();
+
+        let scan = |region: &Region, by: ClosestBy, n: usize| -> Vec<String> {
+            let mut all: Vec<(i64, &str)> = genes
+                .iter()
+                .map(|gene| (by.distance(region, gene), gene.gene_id.as_str()))
+                .collect();
+            all.sort();
+            all.into_iter()
+                .take(n)
+                .map(|(_, id)| id.to_string())
+                .collect()
+        };
+        // Genes of the rows of each region, by every matching path
+        let genes_by_path = |config: &Config| -> Vec<Vec<Vec<String>>> {
+            let ids = |candidates: &[Candidate]| -> Vec<String> {
+                let mut ids: Vec<String> = candidates.iter().map(|c| c.gene.to_string()).collect();
+                ids.dedup();
+                ids
+            };
+            let mut matcher = RegionMatcher::new(&source, config);
+            let by_region = regions
+                .iter()
+                .map(|region| ids(&match_region(region, &genes, config, 0).candidates))
+                .collect();
+            let by_batch = associate_regions(&regions, &genes, config, 0)
+                .iter()
+                .map(|association| ids(&association.candidates))
+                .collect();
+            let by_stream = regions
+                .iter()
+                .map(|region| ids(&matcher.match_next(region).candidates))
+                .collect();
+            vec![by_region, by_batch, by_stream]
+        };
+
+        // The three nearest TSSs of every region
+        let config = Config {
+            n_closest: 3,
+            closest_only: true,
+            level: ReportLevel::Gene,
+            ..Config::default()
+        };
+        let expected: Vec<Vec<String>> = regions
+            .iter()
+            .map(|region| scan(region, ClosestBy::Tss, 3))
+            .collect();
+        for found in genes_by_path(&config) {
+            assert_eq!(found, expected);
+        }
+
+        // The nearest gene edge of every region outside all genes
+        for closest_by in [ClosestBy::Edge, ClosestBy::Tss] {
+            let config = Config {
+                always_closest: true,
+                closest_by,
+                distance: 0,
+                tss: 0.0,
+                promoter: 0.0,
+                level: ReportLevel::Gene,
+                ..Config::default()
+            };
+            let expected: Vec<Vec<String>> = regions
+                .iter()
+                .map(|region| {
+                    let overlapped: Vec<String> = genes
+                        .iter()
+                        .filter(|g| g.start <= region.end && region.start <= g.end)
+                        .map(|g| g.gene_id.to_string())
+                        .collect();
+                    if overlapped.is_empty() {
+                        scan(region, closest_by, 1)
+                    } else {
+                        overlapped
+                    }
+                })
+                .collect();
+            for found in genes_by_path(&config) {
+                assert_eq!(found, expected, "{:?}", closest_by);
+            }
+        }
+    }
+
+    #[test]
+    fn test_first_intron_both_strands() {
+        use rgmatch::config::ConfigBuilder;
+
+        let exons = vec![(1000, 1100), (2000, 2100), (3000, 3100)];
+        let plus = vec![make_test_gene(
+            "GP",
+            1000,
+            3100,
+            Strand::Positive,
+            exons.clone(),
+        )];
+        let minus = vec![make_test_gene("GM", 1000, 3100, Strand::Negative, exons)];
+        // Within the intron between the first two and the last two exons
+        let left = Region::new("chr1".into(), 1500, 1550, vec![]);
+        let right = Region::new("chr1".into(), 2500, 2550, vec![]);
+        let intron_of = |region: &Region, genes: &[Gene], config: &Config| -> (Area, String) {
+            let candidates = match_region_to_genes(region, genes, config, 0);
+            let intron = candidates
+                .into_iter()
+                .find(|c| matches!(c.area, Area::Intron | Area::FirstIntron))
+                .unwrap();
+            (intron.area, intron.exon_number.to_string())
+        };
+
+        let distinguish = ConfigBuilder::new()
+            .distinguish_first_intron(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            distinguish.rules[4..7],
+            [Area::Intron, Area::FirstIntron, Area::GeneBody]
+        );
+        assert_eq!(
+            intron_of(&left, &plus, &distinguish),
+            (Area::FirstIntron, "1".to_string())
+        );
+        assert_eq!(
+            intron_of(&right, &plus, &distinguish),
+            (Area::Intron, "2".to_string())
+        );
+        // On the minus strand, exon 1 is the rightmost one
+        assert_eq!(
+            intron_of(&left, &minus, &distinguish),
+            (Area::Intron, "2".to_string())
+        );
+        assert_eq!(
+            intron_of(&right, &minus, &distinguish),
+            (Area::FirstIntron, "1".to_string())
+        );
+
+        // Off by default
+        let config = Config::default();
+        assert_eq!(intron_of(&left, &plus, &config).0, Area::Intron);
+    }
+
+    #[test]
+    fn test_first_intron_rules_fallback() {
+        use rgmatch::config::ConfigBuilder;
+
+        let genes = vec![make_test_gene(
+            "GP",
+            1000,
+            3100,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2100), (3000, 3100)],
+        )];
+        let region = Region::new("chr1".into(), 1500, 1550, vec![]);
+        let area_with = |rules: &str| -> Area {
+            let config = ConfigBuilder::new()
+                .rules_str(rules)
+                .distinguish_first_intron(true)
+                .level(ReportLevel::Gene)
+                .build()
+                .unwrap();
+            match_region(&region, &genes, &config, 0).candidates[0].area
+        };
+
+        // Custom rule strings without the tag stay valid and keep INTRON
+        assert_eq!(
+            area_with("PROMOTER,TSS,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"),
+            Area::Intron
+        );
+        assert_eq!(
+            area_with("1st_INTRON,TSS,1st_EXON,PROMOTER,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM"),
+            Area::FirstIntron
+        );
+
+        let mut config = Config::default();
+        assert!(!config
+            .parse_rules("1st_INTRON,TSS,1st_EXON,PROMOTER,TTS,GENE_BODY,UPSTREAM,DOWNSTREAM"));
+    }
+
+    #[test]
+    fn test_midpoint_assignment_mode() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::AssignmentMode;
+
+        let genes = vec![make_test_gene(
+            "GP",
+            1000,
+            2100,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2100)],
+        )];
+        // Straddles the TSS, with its midpoint (1005) in the first exon
+        let region = Region::new("chr1".into(), 950, 1060, vec![]);
+        let areas = |config: &Config| -> Vec<Area> {
+            match_region_to_genes(&region, &genes, config, 0)
+                .into_iter()
+                .map(|c| c.area)
+                .collect()
+        };
+
+        let overlap = Config::default();
+        assert_eq!(areas(&overlap), vec![Area::Tss, Area::FirstExon]);
+
+        let midpoint = ConfigBuilder::new()
+            .assignment_mode(AssignmentMode::Midpoint)
+            .build()
+            .unwrap();
+        let candidates = match_region_to_genes(&region, &genes, &midpoint, 0);
+        assert_eq!(candidates.len(), 1);
+        let candidate = &candidates[0];
+        assert_eq!(candidate.area, Area::FirstExon);
+        assert_eq!(candidate.pctg_region, 100.0);
+        assert_eq!(candidate.pctg_area, 100.0 / 101.0);
+        assert_eq!(candidate.region_id, "chr1_950_1060");
+    }
+
+    #[test]
+    fn test_distance_anchor() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::DistanceAnchor;
+
+        let genes = vec![
+            make_test_gene("GP", 10000, 12000, Strand::Positive, vec![(10000, 12000)]),
+            make_test_gene(
+                "GN",
+                18000,
+                20000,
+                Strand::Negative,
+                vec![(18000, 18500), (19500, 20000)],
+            ),
+        ];
+        let distances = |region: &Region, anchor| -> Vec<(&'static str, Area, i64, i64)> {
+            let config = ConfigBuilder::new()
+                .distance_anchor(anchor)
+                .build()
+                .unwrap();
+            match_region_to_genes(region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| {
+                    let gene = if c.gene == "GP" { "GP" } else { "GN" };
+                    (gene, c.area, c.distance, c.tss_distance)
+                })
+                .collect()
+        };
+
+        // 1 kb upstream of GP: the near edge 100 bp and the midpoint 600 bp
+        // from the TSS
+        let region = Region::new("chr1".into(), 8901, 9900, vec![]);
+        assert_eq!(
+            distances(&region, DistanceAnchor::Midpoint),
+            vec![("GP", Area::Promoter, 600, -600)]
+        );
+        assert_eq!(
+            distances(&region, DistanceAnchor::Edge),
+            vec![("GP", Area::Promoter, 100, -100)]
+        );
+
+        // Upstream of GN, on the other side of its TSS
+        let region = Region::new("chr1".into(), 20100, 21099, vec![]);
+        assert_eq!(
+            distances(&region, DistanceAnchor::Midpoint),
+            vec![("GN", Area::Promoter, 599, -599)]
+        );
+        assert_eq!(
+            distances(&region, DistanceAnchor::Edge),
+            vec![("GN", Area::Promoter, 100, -100)]
+        );
+
+        // A region reaching the TSS is 0 bp from it at the edge
+        let region = Region::new("chr1".into(), 9900, 10097, vec![]);
+        let midpoint = distances(&region, DistanceAnchor::Midpoint);
+        let edge = distances(&region, DistanceAnchor::Edge);
+        assert_eq!(midpoint.len(), edge.len());
+        for (m, e) in midpoint.iter().zip(&edge) {
+            assert_eq!((m.0, m.1), (e.0, e.1));
+            assert_eq!(m.3, -2);
+            assert_eq!((e.2, e.3), (0, 0));
+        }
+    }
+
+    #[test]
+    fn test_tss_distance_sign() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::TssDistanceSign;
+
+        // GP's TSS is at 10000 and GN's at 20000
+        let genes = [
+            make_test_gene(
+                "GP",
+                10000,
+                12000,
+                Strand::Positive,
+                vec![(10000, 10500), (11500, 12000)],
+            ),
+            make_test_gene(
+                "GN",
+                18000,
+                20000,
+                Strand::Negative,
+                vec![(18000, 18500), (19500, 20000)],
+            ),
+        ];
+        let tss_distance = |gene: &Gene, start: i64, sign| -> i64 {
+            let config = ConfigBuilder::new()
+                .tss_distance_sign(sign)
+                .build()
+                .unwrap();
+            let region = Region::new("chr1".into(), start, start + 100, vec![]);
+            let candidates = match_region_to_genes(&region, std::slice::from_ref(gene), &config, 0);
+            candidates[0].tss_distance
+        };
+        let (gp, gn) = (&genes[0], &genes[1]);
+
+        // (gene, region start, upstream-negative, genomic-left-negative)
+        let cases = [
+            (gp, 9450, -500, -500), // + strand, upstream (left)
+            (gp, 10150, 200, 200),  // + strand, downstream (right)
+            (gn, 20450, -500, 500), // - strand, upstream (right)
+            (gn, 19650, 300, -300), // - strand, downstream (left)
+        ];
+        for (gene, start, upstream_negative, left_negative) in cases {
+            assert_eq!(
+                tss_distance(gene, start, TssDistanceSign::UpstreamNegative),
+                upstream_negative
+            );
+            assert_eq!(
+                tss_distance(gene, start, TssDistanceSign::GenomicLeftNegative),
+                left_negative
+            );
+        }
+    }
+
+    #[test]
+    fn test_intron_labels() {
+        use rgmatch::config::ConfigBuilder;
+
+        let exons = vec![(1000, 1099), (2000, 2099), (3000, 3099), (4000, 4099)];
+        let labels = |strand, start, end, distinguish| -> Vec<(Area, String)> {
+            let genes = vec![make_test_gene("G1", 1000, 4099, strand, exons.clone())];
+            let config = ConfigBuilder::new()
+                .label_introns(true)
+                .distinguish_first_intron(distinguish)
+                .build()
+                .unwrap();
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, &genes, &config, 0)
+                .into_iter()
+                .filter(|c| c.area != Area::GeneBody)
+                .map(|c| (c.area, c.exon_number.to_string()))
+                .collect()
+        };
+        let intron = |label: &str| vec![(Area::Intron, label.to_string())];
+
+        // Introns from left to right, numbered in transcription order
+        for (start, plus, minus) in [
+            (1500, "intron_1", "intron_3"),
+            (2500, "intron_2", "intron_2"),
+            (3500, "intron_3", "intron_1"),
+        ] {
+            assert_eq!(
+                labels(Strand::Positive, start, start + 99, false),
+                intron(plus)
+            );
+            assert_eq!(
+                labels(Strand::Negative, start, start + 99, false),
+                intron(minus)
+            );
+        }
+
+        // Across the 2nd exon into the introns on both sides
+        assert_eq!(
+            labels(Strand::Positive, 1500, 2599, false),
+            intron("intron_1,intron_2")
+        );
+        assert_eq!(
+            labels(Strand::Negative, 1500, 2599, false),
+            intron("intron_3,intron_2")
+        );
+
+        // The first intron is still recognised
+        assert_eq!(
+            labels(Strand::Negative, 3500, 3599, true),
+            vec![(Area::FirstIntron, "intron_1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_contained_gene_behavior() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::ContainedGeneBehavior;
+
+        let region = Region::new("chr1".into(), 1001, 11000, vec![]);
+        let exons = vec![(5000, 5099), (5150, 5199), (5250, 5299)];
+        for (strand, tss_distance) in [(Strand::Positive, 1000), (Strand::Negative, -701)] {
+            let genes = vec![make_test_gene("G1", 5000, 5299, strand, exons.clone())];
+            let candidates = |behavior| {
+                let config = ConfigBuilder::new()
+                    .contained_gene_behavior(behavior)
+                    .build()
+                    .unwrap();
+                match_region_to_genes(&region, &genes, &config, 0)
+            };
+
+            let split = candidates(ContainedGeneBehavior::Split);
+            assert!(split.len() > 1);
+            assert!(split.iter().all(|c| c.area != Area::GeneContained));
+
+            let consolidated = candidates(ContainedGeneBehavior::Consolidate);
+            assert_eq!(consolidated.len(), 1, "{:?}", strand);
+            let c = &consolidated[0];
+            assert_eq!(c.area, Area::GeneContained);
+            assert_eq!((c.start, c.end), (5000, 5299));
+            assert_eq!(c.exon_number, "1,2,3");
+            assert_eq!((c.distance, c.tss_distance), (0, tss_distance));
+            assert_eq!((c.pctg_region, c.pctg_area), (3.0, 100.0));
+        }
+    }
+
+    #[test]
+    fn test_negative_strand_exon_numbers_in_output() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::output::format_output_line_with_config;
+        use rgmatch::types::ContainedGeneBehavior;
+
+        // Exons numbered 3, 2, 1 from left to right
+        let exons = vec![(1000, 1099), (2000, 2099), (3000, 3099)];
+        let genes = vec![make_test_gene("G1", 1000, 3099, Strand::Negative, exons)];
+        let numbers: Vec<Option<u32>> = genes[0].transcripts[0]
+            .exons
+            .iter()
+            .map(|e| e.exon_number)
+            .collect();
+        assert_eq!(numbers, vec![Some(3), Some(2), Some(1)]);
+
+        let mut config = ConfigBuilder::new()
+            .label_introns(true)
+            .contained_gene_behavior(ContainedGeneBehavior::Consolidate)
+            .build()
+            .unwrap();
+        config.merge_separator = "|".to_string();
+        let exon_intron = |start, end| -> Vec<(Area, String)> {
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, &genes, &config, 0)
+                .iter()
+                .map(|c| {
+                    let line = format_output_line_with_config(&region, c, &config);
+                    (c.area, line.split('\t').nth(4).unwrap().to_string())
+                })
+                .collect()
+        };
+
+        assert_eq!(
+            exon_intron(2050, 2060),
+            vec![(Area::GeneBody, "2".to_string())]
+        );
+        // The intron between exons 3 and 2 is the second one
+        assert_eq!(
+            exon_intron(1500, 1510),
+            vec![(Area::Intron, "intron_2".to_string())]
+        );
+        // A contained gene lists its exons in transcription order
+        assert_eq!(
+            exon_intron(500, 3500),
+            vec![(Area::GeneContained, "1|2|3".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_junction_distance() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::{DistanceAnchor, ExtraColumn};
+
+        let exons = vec![(1000, 1099), (2000, 2099), (3000, 3099)];
+        let distances = |strand, start, end, anchor| -> Vec<(Area, Option<i64>)> {
+            let genes = vec![make_test_gene("G1", 1000, 3099, strand, exons.clone())];
+            let mut config = ConfigBuilder::new()
+                .distance_anchor(anchor)
+                .build()
+                .unwrap();
+            config.extra_columns.push(ExtraColumn::JunctionDistance);
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| (c.area, c.junction_distance))
+                .collect()
+        };
+        let midpoint = DistanceAnchor::Midpoint;
+
+        // 37 bp into the first intron, past the donor site, on either strand
+        assert_eq!(
+            distances(Strand::Positive, 1131, 1141, midpoint),
+            [(Area::Intron, Some(37))]
+        );
+        assert_eq!(
+            distances(Strand::Negative, 2958, 2968, midpoint),
+            [(Area::Intron, Some(37))]
+        );
+        // 37 bp before the acceptor site, and 10 bp before the end of an exon
+        assert_eq!(
+            distances(Strand::Positive, 1958, 1968, midpoint),
+            [(Area::Intron, Some(-37))]
+        );
+        assert_eq!(
+            distances(Strand::Positive, 1085, 1095, midpoint),
+            [(Area::FirstExon, Some(-10))]
+        );
+        // From the nearest edge, 0 across the junction
+        assert_eq!(
+            distances(Strand::Positive, 1131, 1141, DistanceAnchor::Edge),
+            [(Area::Intron, Some(32))]
+        );
+        assert!(
+            distances(Strand::Positive, 1090, 1110, DistanceAnchor::Edge)
+                .iter()
+                .all(|&(_, distance)| distance == Some(0))
+        );
+
+        // Not reported without the column
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            3099,
+            Strand::Positive,
+            exons.clone(),
+        )];
+        let config = ConfigBuilder::new().build().unwrap();
+        let region = Region::new("chr1".into(), 1131, 1141, vec![]);
+        assert_eq!(
+            match_region_to_genes(&region, &genes, &config, 0)[0].junction_distance,
+            None
+        );
+    }
+
+    #[test]
+    fn test_zero_width_areas_are_finite() {
+        use rgmatch::config::Config;
+
+        // Regions abutting or near both ends of single-exon genes
+        let genes = vec![
+            make_test_gene("GPOS", 2000, 3000, Strand::Positive, vec![(2000, 3000)]),
+            make_test_gene("GNEG", 12000, 13000, Strand::Negative, vec![(12000, 13000)]),
+        ];
+        let configs = [
+            Config::default(),
+            Config {
+                tss: 0.0,
+                ..Config::default()
+            },
+            Config {
+                promoter: 0.0,
+                ..Config::default()
+            },
+            Config {
+                tss: 0.0,
+                promoter: 0.0,
+                tts: 0.0,
+                ..Config::default()
+            },
+        ];
+        for config in &configs {
+            for (start, end) in [
+                (1990, 1999),
+                (1500, 1999),
+                (3001, 3010),
+                (2990, 3010),
+                (11000, 11999),
+                (13001, 13100),
+                (12900, 13100),
+            ] {
+                let region = Region::new("chr1".into(), start, end, vec![]);
+                for candidate in match_region_to_genes(&region, &genes, config, 0) {
+                    assert!(
+                        candidate.pctg_region.is_finite() && candidate.pctg_area.is_finite(),
+                        "{:?} at {}-{}: {:?}",
+                        candidate.area,
+                        start,
+                        end,
+                        candidate
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_gene_tss_mode() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::matcher::process_candidates_for_output;
+        use rgmatch::types::{GeneTssMode, ReportLevel};
+
+        // A long transcript and an internal isoform starting in its first
+        // intron, with a peak 100-150 bp upstream of the isoform's TSS
+        let cases = [
+            (
+                Strand::Positive,
+                vec![(5000, 5099), (9000, 9099)],
+                Region::new("chr1".into(), 4850, 4900, vec![]),
+            ),
+            (
+                Strand::Negative,
+                vec![(1000, 1099), (5000, 5099)],
+                Region::new("chr1".into(), 5200, 5250, vec![]),
+            ),
+        ];
+        for (strand, isoform_exons, region) in cases {
+            let mut gene = make_test_gene(
+                "G1",
+                1000,
+                9099,
+                strand,
+                vec![(1000, 1099), (5000, 5099), (9000, 9099)],
+            );
+            let mut isoform = make_test_gene("G1", 1000, 9099, strand, isoform_exons.clone())
+                .transcripts
+                .remove(0);
+            isoform.transcript_id = "ISOFORM".into();
+            gene.transcripts.push(isoform);
+
+            let areas = |gene: &Gene, mode| -> Vec<(Area, String)> {
+                let config = ConfigBuilder::new()
+                    .level(ReportLevel::Gene)
+                    .gene_tss_mode(mode)
+                    .build()
+                    .unwrap();
+                let genes = [gene.clone()];
+                let candidates = match_region_to_genes(&region, &genes, &config, 0);
+                process_candidates_for_output(candidates, &config)
+                    .into_iter()
+                    .map(|c| (c.area, c.transcript.into()))
+                    .collect()
+            };
+            let tss = vec![(Area::Tss, "ISOFORM".to_string())];
+            let intron = vec![(Area::Intron, "TRANS_G1".to_string())];
+
+            assert_eq!(
+                areas(&gene, GeneTssMode::AnyTranscript),
+                tss,
+                "{:?}",
+                strand
+            );
+            assert_eq!(
+                areas(&gene, GeneTssMode::FivePrimeMost),
+                intron,
+                "{:?}",
+                strand
+            );
+            // Without a canonical transcript, the 5'-most one
+            assert_eq!(areas(&gene, GeneTssMode::Canonical), intron, "{:?}", strand);
+            gene.transcripts[1].tags.push("MANE_Select".to_string());
+            assert_eq!(areas(&gene, GeneTssMode::Canonical), tss, "{:?}", strand);
+        }
+    }
+
+    #[test]
+    fn test_unstranded_genes() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::UnstrandedGenes;
+
+        let mut gene = make_test_gene(
+            "G1",
+            5000,
+            7999,
+            Strand::Positive,
+            vec![(5000, 5999), (7000, 7999)],
+        );
+        gene.unstranded = true;
+        let genes = vec![gene];
+        // Peaks 450 bp beyond the left and the right end of the gene
+        let left = Region::new("chr1".into(), 4500, 4600, vec![]);
+        let right = Region::new("chr1".into(), 8400, 8500, vec![]);
+        let candidates = |region: &Region, policy| -> Vec<(Area, Strand, String)> {
+            let config = ConfigBuilder::new()
+                .unstranded_genes(policy)
+                .build()
+                .unwrap();
+            match_region_to_genes(region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| (c.area, c.strand, c.exon_number.to_string()))
+                .collect()
+        };
+
+        assert!(candidates(&left, UnstrandedGenes::Skip).is_empty());
+        assert!(candidates(&right, UnstrandedGenes::Skip).is_empty());
+
+        assert_eq!(
+            candidates(&left, UnstrandedGenes::TreatAsPlus),
+            [(Area::Promoter, Strand::Positive, "1".to_string())]
+        );
+        assert_eq!(
+            candidates(&right, UnstrandedGenes::TreatAsPlus),
+            [(Area::Downstream, Strand::Positive, "2".to_string())]
+        );
+
+        // The promoter wins over the downstream area of the other orientation
+        assert_eq!(
+            candidates(&left, UnstrandedGenes::Both),
+            [(Area::Promoter, Strand::Positive, "1".to_string())]
+        );
+        assert_eq!(
+            candidates(&right, UnstrandedGenes::Both),
+            [(Area::Promoter, Strand::Negative, "1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_gene_body_definition() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::GeneBodyDefinition;
+
+        // Span of 3100 bp, 400 bp of exons, 900 bp introns
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            4099,
+            Strand::Positive,
+            vec![(1000, 1099), (2000, 2099), (3000, 3099), (4000, 4099)],
+        )];
+        let candidates = |region: &Region, definition| -> Vec<(Area, String, f64, f64)> {
+            let config = ConfigBuilder::new()
+                .gene_body_definition(definition)
+                .build()
+                .unwrap();
+            let mut candidates: Vec<_> = match_region_to_genes(region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| {
+                    (
+                        c.area,
+                        c.exon_number.to_string(),
+                        c.pctg_region,
+                        c.pctg_area,
+                    )
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            candidates
+        };
+
+        // Within the 2nd intron: GENE_BODY only over the full span
+        let region = Region::new("chr1".into(), 2500, 2599, vec![]);
+        let intron = vec![(Area::Intron, "2".to_string(), 100.0, 100.0 / 9.0)];
+        assert_eq!(candidates(&region, GeneBodyDefinition::ExonOnly), intron);
+        assert_eq!(candidates(&region, GeneBodyDefinition::ExonUnion), intron);
+        assert_eq!(
+            candidates(&region, GeneBodyDefinition::FullSpan),
+            vec![(Area::GeneBody, "2".to_string(), 100.0, 100.0 / 31.0)]
+        );
+
+        // Half in the 2nd intron, half in the 3rd exon
+        let region = Region::new("chr1".into(), 2950, 3049, vec![]);
+        let intron = (Area::Intron, "2".to_string(), 50.0, 50.0 / 9.0);
+        assert_eq!(
+            candidates(&region, GeneBodyDefinition::ExonOnly),
+            vec![
+                (Area::GeneBody, "3".to_string(), 50.0, 50.0),
+                intron.clone()
+            ]
+        );
+        assert_eq!(
+            candidates(&region, GeneBodyDefinition::ExonUnion),
+            vec![(Area::GeneBody, "3".to_string(), 50.0, 12.5), intron]
+        );
+        assert_eq!(
+            candidates(&region, GeneBodyDefinition::FullSpan),
+            vec![(Area::GeneBody, "3".to_string(), 100.0, 100.0 / 31.0)]
+        );
+    }
+
+    #[test]
+    fn test_min_overlap_bp_selection() {
+        use rgmatch::config::ConfigBuilder;
+
+        let areas = |region: &Region, genes: &[Gene], config: &Config| -> Vec<(Area, i64)> {
+            match_region(region, genes, config, 0)
+                .candidates
+                .into_iter()
+                .map(|c| (c.area, c.overlap_bp))
+                .collect()
+        };
+
+        // A 50 kb domain covering the 400 bp last exon and running downstream
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            5399,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2100), (5000, 5399)],
+        )];
+        let domain = Region::new("chr1".into(), 5000, 54999, vec![]);
+        let by_percentage = ConfigBuilder::new()
+            .level(ReportLevel::Gene)
+            .build()
+            .unwrap();
+        assert_eq!(
+            areas(&domain, &genes, &by_percentage),
+            vec![(Area::Downstream, 49600)]
+        );
+        let by_overlap = ConfigBuilder::new()
+            .level(ReportLevel::Gene)
+            .min_overlap_bp(200)
+            .build()
+            .unwrap();
+        assert_eq!(
+            areas(&domain, &genes, &by_overlap),
+            vec![(Area::GeneBody, 400)]
+        );
+
+        // A 1 kb region with a 30 bp sliver over a 30 bp exon
+        let genes = vec![make_test_gene(
+            "G2",
+            1000,
+            5000,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2029), (4000, 5000)],
+        )];
+        let region = Region::new("chr1".into(), 2000, 2999, vec![]);
+        let by_percentage = ConfigBuilder::new()
+            .level(ReportLevel::Gene)
+            .perc_region(2.0)
+            .build()
+            .unwrap();
+        assert_eq!(
+            areas(&region, &genes, &by_percentage),
+            vec![(Area::GeneBody, 30)]
+        );
+        let by_overlap = ConfigBuilder::new()
+            .level(ReportLevel::Gene)
+            .perc_region(2.0)
+            .min_overlap_bp(100)
+            .build()
+            .unwrap();
+        assert_eq!(
+            areas(&region, &genes, &by_overlap),
+            vec![(Area::Intron, 970)]
+        );
+    }
+
+    #[test]
+    fn test_score_selection_disagrees_with_rules() {
+        use rgmatch::config::ConfigBuilder;
+        use rgmatch::types::{ScoreCombination, SelectionMode};
+
+        let genes = vec![make_test_gene(
+            "G1",
+            1000,
+            5000,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2100), (3000, 5000)],
+        )];
+        // 10 bp of TSS, the whole 101 bp first exon and 889 bp of intron
+        let region = Region::new("chr1".into(), 990, 1989, vec![]);
+        let areas = |builder: ConfigBuilder| -> Vec<Area> {
+            let config = builder.level(ReportLevel::Gene).build().unwrap();
+            match_region(&region, &genes, &config, 0)
+                .candidates
+                .into_iter()
+                .map(|c| c.area)
+                .collect()
+        };
+
+        assert_eq!(areas(ConfigBuilder::new()), vec![Area::Intron]);
+        // Unweighted, the intron also has the highest score
+        assert_eq!(
+            areas(ConfigBuilder::new().selection(SelectionMode::Score)),
+            vec![Area::Intron]
+        );
+        // A strong first exon preference outweighs its smaller overlap
+        for combination in [ScoreCombination::Product, ScoreCombination::Sum] {
+            let builder = ConfigBuilder::new()
+                .selection(SelectionMode::Score)
+                .area_weight(Area::FirstExon, 10.0)
+                .score_combination(combination);
+            assert_eq!(areas(builder), vec![Area::FirstExon]);
+        }
+        // ... which the rules never do
+        let rules = ConfigBuilder::new().area_weight(Area::FirstExon, 10.0);
+        assert_eq!(areas(rules), vec![Area::Intron]);
+    }
+
+    #[test]
+    fn test_area_max_distance_flips_assignment() {
+        use rgmatch::config::ConfigBuilder;
+
+        // The region midpoint (2800) is 800 bp downstream of GB and 3 kb
+        // upstream of GA
+        let genes = vec![
+            make_test_gene("GB", 1000, 2000, Strand::Positive, vec![(1000, 2000)]),
+            make_test_gene("GA", 5800, 7000, Strand::Positive, vec![(5800, 7000)]),
+        ];
+        let region = Region::new("chr1".into(), 2795, 2805, vec![]);
+        let assigned = |limits: &[(Area, i64)]| -> (Vec<(String, Area)>, usize) {
+            let mut builder = ConfigBuilder::new().level(ReportLevel::Gene);
+            for &(area, bp) in limits {
+                builder = builder.area_max_distance(area, bp);
+            }
+            let matches = match_region(&region, &genes, &builder.build().unwrap(), 0);
+            let assigned = matches
+                .candidates
+                .into_iter()
+                .map(|c| (c.gene.into(), c.area))
+                .collect();
+            (assigned, matches.num_distance_filtered)
+        };
+
+        let gb = ("GB".to_string(), Area::Downstream);
+        let ga = ("GA".to_string(), Area::Upstream);
+        assert_eq!(assigned(&[]), (vec![gb.clone()], 0));
+        assert_eq!(
+            assigned(&[(Area::Upstream, 5000), (Area::Downstream, 1000)]),
+            (vec![gb.clone()], 0)
+        );
+        // GB is too far for the DOWNSTREAM limit, so GA takes over
+        assert_eq!(
+            assigned(&[(Area::Upstream, 5000), (Area::Downstream, 500)]),
+            (vec![ga], 1)
+        );
+        assert_eq!(
+            assigned(&[(Area::Upstream, 2000), (Area::Downstream, 500)]),
+            (vec![], 2)
+        );
+        assert_eq!(assigned(&[(Area::Upstream, 2000)]), (vec![gb], 0));
+    }
+
+    #[test]
+    fn test_tss_downstream_boundaries() {
+        use rgmatch::config::ConfigBuilder;
+
+        let exons = vec![(1000, 1100), (2000, 2100)];
+        let plus = vec![make_test_gene(
+            "GP",
+            1000,
+            2100,
+            Strand::Positive,
+            exons.clone(),
+        )];
+        let minus = vec![make_test_gene("GM", 1000, 2100, Strand::Negative, exons)];
+        let config = ConfigBuilder::new().tss_downstream_bp(50).build().unwrap();
+        let areas = |start: i64, end: i64, genes: &[Gene], config: &Config| {
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, genes, config, 0)
+                .into_iter()
+                .filter(|c| matches!(c.area, Area::Tss | Area::FirstExon))
+                .map(|c| (c.area, c.pctg_region, c.pctg_area))
+                .collect::<Vec<_>>()
+        };
+
+        // + strand: TSS @ 1000, the TSS region reaches 1049
+        assert_eq!(
+            areas(1049, 1049, &plus, &config),
+            vec![(Area::Tss, 100.0, 0.4)]
+        );
+        assert_eq!(
+            areas(1050, 1050, &plus, &config),
+            vec![(Area::FirstExon, 100.0, 100.0 / 51.0)]
+        );
+        // Across the boundary, over the combined 250 bp window
+        assert_eq!(
+            areas(1040, 1059, &plus, &config),
+            vec![
+                (Area::Tss, 50.0, 4.0),
+                (Area::FirstExon, 50.0, 1000.0 / 51.0)
+            ]
+        );
+        // From upstream into the exon, a single TSS overlap
+        assert_eq!(
+            areas(975, 1024, &plus, &config),
+            vec![(Area::Tss, 100.0, 20.0)]
+        );
+
+        // - strand: TSS @ 2100, the TSS region reaches 2051
+        assert_eq!(
+            areas(2051, 2051, &minus, &config),
+            vec![(Area::Tss, 100.0, 0.4)]
+        );
+        assert_eq!(
+            areas(2050, 2050, &minus, &config),
+            vec![(Area::FirstExon, 100.0, 100.0 / 51.0)]
+        );
+        assert_eq!(
+            areas(2076, 2125, &minus, &config),
+            vec![(Area::Tss, 100.0, 20.0)]
+        );
+
+        // Off by default
+        assert_eq!(
+            areas(1049, 1049, &plus, &Config::default()),
+            vec![(Area::FirstExon, 100.0, 100.0 / 101.0)]
+        );
+    }
+
+    #[test]
+    fn test_utr_areas_both_strands() {
+        use rgmatch::config::ConfigBuilder;
+
+        let exons = vec![(1000, 1100), (2000, 2100), (3000, 3100)];
+        let coding = |mut gene: Gene| {
+            gene.transcripts[0].cds = Some((1050, 3050));
+            gene
+        };
+        let plus = vec![coding(make_test_gene(
+            "GP",
+            1000,
+            3100,
+            Strand::Positive,
+            exons.clone(),
+        ))];
+        let minus = vec![coding(make_test_gene(
+            "GM",
+            1000,
+            3100,
+            Strand::Negative,
+            exons.clone(),
+        ))];
+        let non_coding = vec![make_test_gene("GN", 1000, 3100, Strand::Positive, exons)];
+
+        let config = ConfigBuilder::new().report_utr(true).build().unwrap();
+        let exonic = |start: i64, end: i64, genes: &[Gene]| -> Vec<(Area, i64, i64, f64)> {
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, genes, &config, 0)
+                .into_iter()
+                .filter(|c| {
+                    matches!(
+                        c.area,
+                        Area::FirstExon | Area::GeneBody | Area::Utr5 | Area::Utr3
+                    )
+                })
+                .map(|c| (c.area, c.start, c.end, c.pctg_region))
+                .collect()
+        };
+
+        // + strand: the 5' UTR is left of the CDS
+        assert_eq!(
+            exonic(1010, 1019, &plus),
+            vec![(Area::Utr5, 1000, 1049, 100.0)]
+        );
+        assert_eq!(
+            exonic(3060, 3069, &plus),
+            vec![(Area::Utr3, 3051, 3100, 100.0)]
+        );
+        assert_eq!(
+            exonic(2010, 2019, &plus),
+            vec![(Area::GeneBody, 2000, 2100, 100.0)]
+        );
+        // Across the CDS start, the overlap is split
+        assert_eq!(
+            exonic(1040, 1059, &plus),
+            vec![
+                (Area::Utr5, 1000, 1049, 50.0),
+                (Area::FirstExon, 1050, 1100, 50.0)
+            ]
+        );
+
+        // - strand: the 5' UTR is right of the CDS, in exon 1
+        assert_eq!(
+            exonic(3060, 3069, &minus),
+            vec![(Area::Utr5, 3051, 3100, 100.0)]
+        );
+        assert_eq!(
+            exonic(1010, 1019, &minus),
+            vec![(Area::Utr3, 1000, 1049, 100.0)]
+        );
+
+        // Without a CDS, the exon areas stay
+        assert_eq!(
+            exonic(1010, 1019, &non_coding),
+            vec![(Area::FirstExon, 1000, 1100, 100.0)]
+        );
+        assert_eq!(
+            exonic(3060, 3069, &non_coding),
+            vec![(Area::GeneBody, 3000, 3100, 100.0)]
+        );
+    }
+
+    #[test]
+    fn test_utr_rules_fallback() {
+        use rgmatch::config::ConfigBuilder;
+
+        let mut gene = make_test_gene(
+            "GP",
+            1000,
+            3100,
+            Strand::Positive,
+            vec![(1000, 1100), (2000, 2100), (3000, 3100)],
+        );
+        gene.transcripts[0].cds = Some((1050, 3050));
+        let genes = vec![gene];
+        let areas_with = |rules: &str, start: i64, end: i64| -> Vec<Area> {
+            let config = ConfigBuilder::new()
+                .rules_str(rules)
+                .report_utr(true)
+                .build()
+                .unwrap();
+            let region = Region::new("chr1".into(), start, end, vec![]);
+            match_region_to_genes(&region, &genes, &config, 0)
+                .into_iter()
+                .map(|c| c.area)
+                .filter(|a| {
+                    matches!(
+                        a,
+                        Area::FirstExon | Area::GeneBody | Area::Utr5 | Area::Utr3
+                    )
+                })
+                .collect()
+        };
+
+        // Custom rule strings without the tags stay valid and keep the exon areas
+        let legacy = "PROMOTER,TSS,1st_EXON,TTS,INTRON,GENE_BODY,UPSTREAM,DOWNSTREAM";
+        assert_eq!(areas_with(legacy, 1040, 1059), vec![Area::FirstExon]);
+        assert_eq!(areas_with(legacy, 3060, 3069), vec![Area::GeneBody]);
+
+        // Only the listed UTR is reported
+        let utr3 = "PROMOTER,TSS,1st_EXON,TTS,INTRON,GENE_BODY,UTR3,UPSTREAM,DOWNSTREAM";
+        assert_eq!(areas_with(utr3, 1040, 1059), vec![Area::FirstExon]);
+        assert_eq!(areas_with(utr3, 3060, 3069), vec![Area::Utr3]);
+    }
+
+    #[test]
+    fn test_strandedness_same_between_genes() {
+        use rgmatch::types::Strandedness;
+
+        // A plus strand region overlapping a plus and a minus gene
+        let genes = vec![
+            make_test_gene("GP", 1000, 2000, Strand::Positive, vec![(1000, 2000)]),
+            make_test_gene("GM", 1900, 3000, Strand::Negative, vec![(1900, 3000)]),
+        ];
+        let region =
+            Region::new("chr1".into(), 1950, 1990, vec![]).with_strand(Some(Strand::Positive));
+
+        let candidates = match_region_to_genes(&region, &genes, &Config::default(), 0);
+        assert!(candidates.iter().any(|c| c.gene == "GM"));
+
+        let config = Config {
+            strandedness: Strandedness::Same,
+            ..Config::default()
+        };
+        let candidates = match_region_to_genes(&region, &genes, &config, 0);
+        assert!(!candidates.is_empty());
+        assert!(candidates.iter().all(|c| c.gene == "GP"));
+    }
+}
+
+// -------------------------------------------------------------------------
+// 10. Output Module Tests
+// -------------------------------------------------------------------------
+
+// -------------------------------------------------------------------------
+// 10. Parser BED Module Tests
+// -------------------------------------------------------------------------
+
+mod test_parser_bed {
+    use rgmatch::parser::bed::get_bed_headers;
+    use rgmatch::BedReader;
+    use rgmatch::Region;
+    use std::io::{Cursor, Write};
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_get_bed_headers_zero() {
+        let headers = get_bed_headers(0);
+        assert!(headers.is_empty());
+    }
+
+    #[test]
+    fn test_get_bed_headers_partial() {
+        let headers = get_bed_headers(3);
+        assert_eq!(headers.len(), 3);
+        assert_eq!(headers[0], "name");
+        assert_eq!(headers[1], "score");
+        assert_eq!(headers[2], "strand");
+    }
+
+    #[test]
+    fn test_get_bed_headers_full() {
+        let headers = get_bed_headers(9);
+        assert_eq!(headers.len(), 9);
+        assert_eq!(headers[8], "blockStarts");
+    }
+
+    #[test]
+    fn test_get_bed_headers_exceeds_max() {
+        // Request more than available, should cap at 9
+        let headers = get_bed_headers(20);
+        assert_eq!(headers.len(), 9);
+    }
+
+    #[test]
+    fn test_bed_reader_multiple_chroms() {
+        const DATA: &[u8] = b"\
+chr1\t100\t200\n\
+chr2\t300\t400\n\
+chr3\t500\t600\n\
+chr1\t700\t800\n";
+
+        let mut reader = BedReader::from_reader(Cursor::new(DATA));
+        let chunk = reader.read_chunk(100).unwrap().unwrap();
+
+        assert_eq!(chunk.len(), 4);
+        assert_eq!(chunk[0].chrom, "chr1");
+        assert_eq!(chunk[1].chrom, "chr2");
+        assert_eq!(chunk[2].chrom, "chr3");
+        assert_eq!(chunk[3].chrom, "chr1");
+    }
+
+    #[test]
+    fn test_bed_reader_max_metadata_columns() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // 12-column BED (9 metadata columns)
+        writeln!(
+            temp_file,
+            "chr1\t100\t200\tname\t500\t+\t100\t200\t0,0,0\t2\t50,50\t0,100"
+        )
+        .unwrap();
+        temp_file.flush().unwrap();
+
+        let mut reader = BedReader::new(temp_file.path()).unwrap();
+        let chunk = reader.read_chunk(100).unwrap().unwrap();
+
+        assert_eq!(reader.num_meta_columns(), 9);
+        assert_eq!(chunk[0].metadata.len(), 9);
+        assert_eq!(chunk[0].metadata[0], "name");
+        assert_eq!(chunk[0].metadata[8], "0,100");
+    }
+
+    #[test]
+    fn test_bed_reader_skip_malformed_lines() {
+        // The first line has only 2 columns
+        const DATA: &[u8] = b"\
+chr1\t100\n\
+chr1\t100\t200\n";
+
+        let mut reader = BedReader::from_reader(Cursor::new(DATA));
+        let chunk = reader.read_chunk(100).unwrap().unwrap();
+
+        assert_eq!(chunk.len(), 1);
+        assert_eq!(chunk[0].start, 100);
+        assert_eq!(chunk[0].end, 200);
+    }
 
     #[test]
     fn test_bed_reader_empty_file() {
@@ -2019,13 +3750,12 @@ mod test_parser_bed {
 
     #[test]
     fn test_bed_reader_varying_metadata() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, "chr1\t100\t200\tname1").unwrap();
-        writeln!(temp_file, "chr1\t300\t400\tname2\t500").unwrap();
-        writeln!(temp_file, "chr1\t500\t600\tname3\t600\t+").unwrap();
-        temp_file.flush().unwrap();
+        const DATA: &[u8] = b"\
+chr1\t100\t200\tname1\n\
+chr1\t300\t400\tname2\t500\n\
+chr1\t500\t600\tname3\t600\t+\n";
 
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
+        let mut reader = BedReader::from_reader(Cursor::new(DATA));
         let _chunk = reader.read_chunk(100).unwrap().unwrap();
 
         // num_meta_columns should track the maximum (3)
@@ -2046,24 +3776,62 @@ mod test_parser_bed {
     }
 
     #[test]
-    fn test_bed_reader_negative_coords_accepted() {
+    fn test_bed_reader_negative_coords() {
         let mut temp_file = NamedTempFile::new().unwrap();
         writeln!(temp_file, "chr1\t-100\t200").unwrap();
         temp_file.flush().unwrap();
 
+        // Rejected unless explicitly allowed
         let mut reader = BedReader::new(temp_file.path()).unwrap();
+        match reader.read_chunk(100) {
+            Err(rgmatch::Error::BedParse { line, reason }) => {
+                assert_eq!(line, 1);
+                assert!(reason.starts_with("start -100 is out of the supported coordinate range"));
+            }
+            other => panic!("expected a BedParse error, got {:?}", other.is_ok()),
+        }
+
+        let mut reader = BedReader::new(temp_file.path())
+            .unwrap()
+            .allow_negative_coordinates(true);
         let chunk = reader.read_chunk(100).unwrap().unwrap();
 
         assert_eq!(chunk[0].start, -100);
     }
 
+    #[test]
+    fn test_bed_reader_extreme_coords_rejected() {
+        use rgmatch::parser::MAX_COORDINATE;
+
+        for line in [
+            format!("chr1\t100\t{}\n", i64::MAX),
+            format!("chr1\t{}\t{}\n", MAX_COORDINATE + 1, MAX_COORDINATE + 2),
+            format!("chr1\t{}\t100\n", i64::MIN),
+        ] {
+            let mut reader = BedReader::from_reader(Cursor::new(line.clone().into_bytes()))
+                .allow_negative_coordinates(true);
+            assert!(
+                matches!(
+                    reader.read_chunk(100),
+                    Err(rgmatch::Error::BedParse { line: 1, .. })
+                ),
+                "{}",
+                line
+            );
+        }
+
+        // The bound itself is accepted
+        let line = format!("chr1\t0\t{}\n", MAX_COORDINATE);
+        let mut reader = BedReader::from_reader(Cursor::new(line.into_bytes()));
+        let chunk = reader.read_chunk(100).unwrap().unwrap();
+        assert_eq!(chunk[0].end, MAX_COORDINATE);
+    }
+
     #[test]
     fn test_bed_reader_large_coordinates() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(temp_file, "chr1\t100000000\t200000000").unwrap();
-        temp_file.flush().unwrap();
+        const DATA: &[u8] = b"chr1\t100000000\t200000000\n";
 
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
+        let mut reader = BedReader::from_reader(Cursor::new(DATA));
         let chunk = reader.read_chunk(100).unwrap().unwrap();
 
         assert_eq!(chunk[0].start, 100000000);
@@ -2112,7 +3880,7 @@ mod test_parser_bed {
 // -------------------------------------------------------------------------
 
 mod test_parser_gtf {
-    use rgmatch::parser::gtf::parse_gtf;
+    use rgmatch::parser::gtf::{parse_gtf, parse_gtf_from_reader};
     use rgmatch::types::Strand;
     use std::io::Write;
     use tempfile::NamedTempFile;
@@ -2135,27 +3903,32 @@ mod test_parser_gtf {
         assert_eq!(result.genes_by_chrom["chr1"].len(), 1);
     }
 
+    #[test]
+    fn test_parse_gtf_crlf_and_trailing_tabs() {
+        // Windows line endings, and a trailing tab after the attributes
+        const DATA: &[u8] = b"\
+# exported on Windows\r\n\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\t\r\n\
+\r\n\
+chr1\tTEST\texon\t1500\t1700\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\"\r\n";
+
+        let result = parse_gtf_from_reader(DATA, "gene_id", "transcript_id").unwrap();
+        let genes = &result.genes_by_chrom["chr1"];
+        assert_eq!(genes.len(), 1);
+        assert_eq!(genes[0].gene_id, "G1");
+        assert_eq!(genes[0].transcripts.len(), 1);
+        assert_eq!(genes[0].transcripts[0].transcript_id, "T1");
+        assert_eq!(genes[0].transcripts[0].exons.len(), 2);
+    }
+
     #[test]
     fn test_parse_gtf_multiple_chromosomes() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(
-            temp_file,
-            "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";"
-        )
-        .unwrap();
-        writeln!(
-            temp_file,
-            "chr2\tTEST\texon\t2000\t2200\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";"
-        )
-        .unwrap();
-        writeln!(
-            temp_file,
-            "chrX\tTEST\texon\t3000\t3200\t.\t+\t.\tgene_id \"G3\"; transcript_id \"T3\";"
-        )
-        .unwrap();
-        temp_file.flush().unwrap();
+        const DATA: &[u8] = b"\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+chr2\tTEST\texon\t2000\t2200\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n\
+chrX\tTEST\texon\t3000\t3200\t.\t+\t.\tgene_id \"G3\"; transcript_id \"T3\";\n";
 
-        let result = parse_gtf(temp_file.path(), "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_from_reader(DATA, "gene_id", "transcript_id").unwrap();
 
         assert_eq!(result.genes_by_chrom.len(), 3);
         assert!(result.genes_by_chrom.contains_key("chr1"));
@@ -2165,15 +3938,10 @@ mod test_parser_gtf {
 
     #[test]
     fn test_parse_gtf_custom_id_tags() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(
-            temp_file,
-            "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tmy_gene \"G1\"; my_trans \"T1\";"
-        )
-        .unwrap();
-        temp_file.flush().unwrap();
+        const DATA: &[u8] = b"\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tmy_gene \"G1\"; my_trans \"T1\";\n";
 
-        let result = parse_gtf(temp_file.path(), "my_gene", "my_trans").unwrap();
+        let result = parse_gtf_from_reader(DATA, "my_gene", "my_trans").unwrap();
 
         assert!(result.genes_by_chrom.contains_key("chr1"));
         assert_eq!(result.genes_by_chrom["chr1"][0].gene_id, "G1");
@@ -2185,20 +3953,11 @@ mod test_parser_gtf {
 
     #[test]
     fn test_parse_gtf_exon_only_no_gene_entry() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        writeln!(
-            temp_file,
-            "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";"
-        )
-        .unwrap();
-        writeln!(
-            temp_file,
-            "chr1\tTEST\texon\t1500\t1700\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";"
-        )
-        .unwrap();
-        temp_file.flush().unwrap();
+        const DATA: &[u8] = b"\
+chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+chr1\tTEST\texon\t1500\t1700\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n";
 
-        let result = parse_gtf(temp_file.path(), "gene_id", "transcript_id").unwrap();
+        let result = parse_gtf_from_reader(DATA, "gene_id", "transcript_id").unwrap();
 
         let gene = &result.genes_by_chrom["chr1"][0];
         // Gene size should be calculated from exons
@@ -2211,6 +3970,26 @@ mod test_parser_gtf {
         assert_eq!(transcript.end, 1700);
     }
 
+    #[test]
+    fn test_parse_gtf_gene_entries_for_some_genes() {
+        const DATA: &[u8] = b"\
+chr1\tTEST\tgene\t1000\t2000\t.\t+\t.\tgene_id \"G1\";\n\
+chr1\tTEST\ttranscript\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+chr1\tTEST\texon\t1000\t2000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+chr1\tTEST\texon\t50000\t51000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n\
+chr1\tTEST\texon\t52000\t53000\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T2\";\n";
+
+        let result = parse_gtf_from_reader(DATA, "gene_id", "transcript_id").unwrap();
+
+        // Sizes are calculated from exons for the gene without entries
+        let genes = &result.genes_by_chrom["chr1"];
+        assert_eq!((genes[0].start, genes[0].end), (1000, 2000));
+        assert_eq!((genes[1].start, genes[1].end), (50000, 53000));
+        let transcript = &genes[1].transcripts[0];
+        assert_eq!((transcript.start, transcript.end), (50000, 53000));
+        assert_eq!(result.max_lengths["chr1"], 3000);
+    }
+
     #[test]
     fn test_parse_gtf_with_gene_and_transcript_entries() {
         let mut temp_file = NamedTempFile::new().unwrap();
@@ -2264,26 +4043,49 @@ mod test_parser_gtf {
         .unwrap();
         writeln!(
             temp_file,
-            "chr1\tTEST\texon\t1100\t1300\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";"
+            "chr1\tTEST\texon\t1100\t1300\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";"
+        )
+        .unwrap();
+        writeln!(
+            temp_file,
+            "chr1\tTEST\texon\t1600\t1800\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";"
+        )
+        .unwrap();
+        temp_file.flush().unwrap();
+
+        let result = parse_gtf(temp_file.path(), "gene_id", "transcript_id").unwrap();
+
+        let gene = &result.genes_by_chrom["chr1"][0];
+        assert_eq!(gene.transcripts.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_gtf_skip_invalid_strand() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        // Invalid strand '?' should be skipped
+        writeln!(
+            temp_file,
+            "chr1\tTEST\texon\t1000\t1200\t.\t?\t.\tgene_id \"G1\"; transcript_id \"T1\";"
         )
         .unwrap();
         writeln!(
             temp_file,
-            "chr1\tTEST\texon\t1600\t1800\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T2\";"
+            "chr1\tTEST\texon\t1500\t1700\t.\t+\t.\tgene_id \"G2\"; transcript_id \"T2\";"
         )
         .unwrap();
         temp_file.flush().unwrap();
 
         let result = parse_gtf(temp_file.path(), "gene_id", "transcript_id").unwrap();
 
-        let gene = &result.genes_by_chrom["chr1"][0];
-        assert_eq!(gene.transcripts.len(), 2);
+        // Only G2 should be parsed (G1 has invalid strand)
+        assert_eq!(result.genes_by_chrom["chr1"].len(), 1);
+        assert_eq!(result.genes_by_chrom["chr1"][0].gene_id, "G2");
     }
 
     #[test]
-    fn test_parse_gtf_skip_invalid_strand() {
+    fn test_parse_gtf_unstranded_gene() {
         let mut temp_file = NamedTempFile::new().unwrap();
-        // Invalid strand '.' should be skipped
+        // Strand '.' is kept on the + strand and flagged
         writeln!(
             temp_file,
             "chr1\tTEST\texon\t1000\t1200\t.\t.\t.\tgene_id \"G1\"; transcript_id \"T1\";"
@@ -2298,9 +4100,13 @@ mod test_parser_gtf {
 
         let result = parse_gtf(temp_file.path(), "gene_id", "transcript_id").unwrap();
 
-        // Only G2 should be parsed (G1 has invalid strand)
-        assert_eq!(result.genes_by_chrom["chr1"].len(), 1);
-        assert_eq!(result.genes_by_chrom["chr1"][0].gene_id, "G2");
+        let genes = &result.genes_by_chrom["chr1"];
+        assert_eq!(genes.len(), 2);
+        assert_eq!(genes[0].gene_id, "G1");
+        assert_eq!(genes[0].strand, Strand::Positive);
+        assert!(genes[0].unstranded);
+        assert!(!genes[1].unstranded);
+        assert_eq!(result.num_unstranded_genes(), 1);
     }
 
     #[test]
@@ -2327,9 +4133,9 @@ mod test_parser_gtf {
 
         let transcript = &result.genes_by_chrom["chr1"][0].transcripts[0];
         // For negative strand: first exon (genomically) gets highest number
-        assert_eq!(transcript.exons[0].exon_number, Some("3".to_string())); // 1000-1200
-        assert_eq!(transcript.exons[1].exon_number, Some("2".to_string())); // 1500-1700
-        assert_eq!(transcript.exons[2].exon_number, Some("1".to_string())); // 2000-2200
+        assert_eq!(transcript.exons[0].exon_number, Some(3)); // 1000-1200
+        assert_eq!(transcript.exons[1].exon_number, Some(2)); // 1500-1700
+        assert_eq!(transcript.exons[2].exon_number, Some(1)); // 2000-2200
     }
 
     #[test]
@@ -2414,6 +4220,48 @@ mod test_parser_gtf {
 
         assert_eq!(result.genes_by_chrom["chr1"][0].strand, Strand::Negative);
     }
+
+    #[test]
+    fn test_parse_gtf_transcript_id_shared_by_two_genes() {
+        // T1 is reused by G2, with CDS lines for both
+        let gtf = "chr1\tTEST\texon\t1000\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+                   chr1\tTEST\tCDS\t1100\t1200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+                   chr1\tTEST\texon\t90000\t90500\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T1\";\n\
+                   chr1\tTEST\texon\t1500\t1600\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";\n\
+                   chr1\tTEST\texon\t91000\t91200\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T1\";\n\
+                   chr1\tTEST\tCDS\t90100\t91100\t.\t-\t.\tgene_id \"G2\"; transcript_id \"T1\";\n";
+        let result = parse_gtf_from_reader(gtf.as_bytes(), "gene_id", "transcript_id").unwrap();
+
+        let g1 = result.gene_by_id("G1").unwrap();
+        let g2 = result.gene_by_id("G2").unwrap();
+        assert_eq!((g1.start, g1.end), (1000, 1600));
+        assert_eq!((g2.start, g2.end), (90000, 91200));
+        assert_eq!(g1.transcripts.len(), 1);
+        assert_eq!(g2.transcripts.len(), 1);
+        let exons = |gene: &rgmatch::types::Gene| -> Vec<(i64, i64)> {
+            gene.transcripts[0]
+                .exons
+                .iter()
+                .map(|e| (e.start, e.end))
+                .collect()
+        };
+        assert_eq!(exons(g1), vec![(1000, 1200), (1500, 1600)]);
+        assert_eq!(exons(g2), vec![(90000, 90500), (91000, 91200)]);
+        assert_eq!(g1.transcripts[0].cds, Some((1100, 1200)));
+        assert_eq!(g2.transcripts[0].cds, Some((90100, 91100)));
+
+        assert_eq!(result.num_shared_transcript_ids(), 1);
+        let shared = &result.shared_transcript_ids[0];
+        assert_eq!(
+            (shared.first_gene_id.as_str(), shared.gene_id.as_str()),
+            ("G1", "G2")
+        );
+        let err = result.check_shared_transcript_ids().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Invalid GTF line 3: transcript T1 belongs to gene G2 but was first found under gene G1"
+        );
+    }
 }
 
 // -------------------------------------------------------------------------
@@ -2428,7 +4276,10 @@ mod test_error_types {
     #[test]
     fn test_parse_strand_error_display() {
         let err = Strand::from_str("invalid").unwrap_err();
-        assert_eq!(format!("{}", err), "invalid strand: expected '+' or '-'");
+        assert_eq!(
+            format!("{}", err),
+            "invalid strand 'invalid': expected '+' or '-'"
+        );
     }
 
     #[test]
@@ -2441,7 +4292,7 @@ mod test_error_types {
     #[test]
     fn test_parse_area_error_display() {
         let err = Area::from_str("invalid").unwrap_err();
-        assert_eq!(format!("{}", err), "invalid area type");
+        assert_eq!(format!("{}", err), "invalid area type 'invalid'");
     }
 
     #[test]
@@ -2455,7 +4306,7 @@ mod test_error_types {
         let err = ReportLevel::from_str("invalid").unwrap_err();
         assert_eq!(
             format!("{}", err),
-            "invalid report level: expected 'exon', 'transcript', or 'gene'"
+            "invalid report level 'invalid': expected 'exon', 'transcript', or 'gene'"
         );
     }
 
@@ -2468,19 +4319,19 @@ mod test_error_types {
     #[test]
     fn test_parse_strand_error_debug() {
         let err = Strand::from_str("invalid").unwrap_err();
-        assert_eq!(format!("{:?}", err), "ParseStrandError");
+        assert_eq!(format!("{:?}", err), "ParseStrandError(\"invalid\")");
     }
 
     #[test]
     fn test_parse_area_error_debug() {
         let err = Area::from_str("invalid").unwrap_err();
-        assert_eq!(format!("{:?}", err), "ParseAreaError");
+        assert_eq!(format!("{:?}", err), "ParseAreaError(\"invalid\")");
     }
 
     #[test]
     fn test_parse_report_level_error_debug() {
         let err = ReportLevel::from_str("invalid").unwrap_err();
-        assert_eq!(format!("{:?}", err), "ParseReportLevelError");
+        assert_eq!(format!("{:?}", err), "ParseReportLevelError(\"invalid\")");
     }
 }
 
@@ -2647,7 +4498,7 @@ mod test_output {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -2680,7 +4531,7 @@ mod test_output {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Intron,
             "T1".to_string(),
             "G1".to_string(),
@@ -2704,7 +4555,7 @@ mod test_output {
             100,
             200,
             Strand::Negative,
-            "2".to_string(),
+            2,
             Area::Downstream,
             "T2".to_string(),
             "G2".to_string(),
@@ -2718,7 +4569,7 @@ mod test_output {
 
         // Should not have trailing tab
         assert!(!line.ends_with('\t'));
-        assert!(line.contains("-1.00"));
+        assert!(line.ends_with("\t50.00\tNA"));
         assert!(line.contains("DOWNSTREAM"));
     }
 
@@ -2742,7 +4593,7 @@ mod test_output {
                 100,
                 200,
                 Strand::Positive,
-                "1".to_string(),
+                1,
                 area,
                 "T1".to_string(),
                 "G1".to_string(),
@@ -2813,7 +4664,7 @@ mod test_output {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -2836,7 +4687,7 @@ mod test_output {
             0,
             0,
             Strand::Positive,
-            "0".to_string(),
+            0,
             Area::Tss,
             "T0".to_string(),
             "G0".to_string(),
@@ -2859,7 +4710,7 @@ mod test_output {
             100000000,
             200000000,
             Strand::Negative,
-            "999".to_string(),
+            999,
             Area::GeneBody,
             "TRANSCRIPT_VERY_LONG_NAME".to_string(),
             "GENE_VERY_LONG_NAME".to_string(),
@@ -2902,16 +4753,16 @@ mod test_tss_extended {
         // Promoter zone: 3500-4800 (1300bp)
         // Upstream zone: < 3500
         let res = check_tss(3000, 4950, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
 
-        assert!(tags.contains(&"TSS"), "Should contain TSS: {:?}", tags);
+        assert!(tags.contains(&Area::Tss), "Should contain TSS: {:?}", tags);
         assert!(
-            tags.contains(&"PROMOTER"),
+            tags.contains(&Area::Promoter),
             "Should contain PROMOTER: {:?}",
             tags
         );
         assert!(
-            tags.contains(&"UPSTREAM"),
+            tags.contains(&Area::Upstream),
             "Should contain UPSTREAM: {:?}",
             tags
         );
@@ -2928,8 +4779,8 @@ mod test_tss_extended {
         };
         let res = check_tss(800, 810, &exon, 200.0, 1300.0);
         // At exactly 200bp, should still be TSS
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"TSS") || tags.contains(&"PROMOTER"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Tss) || tags.contains(&Area::Promoter));
     }
 
     #[test]
@@ -2942,9 +4793,9 @@ mod test_tss_extended {
             distance: 201, // Just beyond TSS
         };
         let res = check_tss(799, 810, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         assert!(
-            tags.contains(&"PROMOTER"),
+            tags.contains(&Area::Promoter),
             "Should contain PROMOTER at 201bp"
         );
     }
@@ -2963,9 +4814,9 @@ mod test_tss_extended {
         // Promoter zone: 2200-3500
         // Upstream: > 3500
         let res = check_tss(3600, 3700, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         assert!(
-            tags.contains(&"UPSTREAM"),
+            tags.contains(&Area::Upstream),
             "Should be UPSTREAM for neg strand far from end"
         );
     }
@@ -2980,10 +4831,13 @@ mod test_tss_extended {
             distance: 500, // Within promoter (200 < 500 < 1500)
         };
         let res = check_tss(1400, 1500, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"PROMOTER"));
-        assert!(!tags.contains(&"TSS"), "Should not contain TSS");
-        assert!(!tags.contains(&"UPSTREAM"), "Should not contain UPSTREAM");
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Promoter));
+        assert!(!tags.contains(&Area::Tss), "Should not contain TSS");
+        assert!(
+            !tags.contains(&Area::Upstream),
+            "Should not contain UPSTREAM"
+        );
     }
 
     #[test]
@@ -2997,9 +4851,9 @@ mod test_tss_extended {
         };
         let res = check_tss(900, 900, &exon, 200.0, 1300.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "TSS");
+        assert_eq!(res[0].area, Area::Tss);
         // Percentage should be 100% for 1bp region in zone
-        assert!(res[0].1 > 99.9 && res[0].1 <= 100.0);
+        assert!(res[0].pctg_region > 99.9 && res[0].pctg_region <= 100.0);
     }
 
     #[test]
@@ -3012,9 +4866,9 @@ mod test_tss_extended {
             distance: 100,
         };
         let res = check_tss(890, 900, &exon, 0.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // With tss=0, promoter starts at exon start
-        assert!(tags.contains(&"PROMOTER") || tags.contains(&"UPSTREAM"));
+        assert!(tags.contains(&Area::Promoter) || tags.contains(&Area::Upstream));
     }
 
     #[test]
@@ -3027,9 +4881,9 @@ mod test_tss_extended {
             distance: 300,
         };
         let res = check_tss(600, 700, &exon, 200.0, 0.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // Beyond TSS (200bp), should be UPSTREAM
-        assert!(tags.contains(&"UPSTREAM"));
+        assert!(tags.contains(&Area::Upstream));
     }
 
     #[test]
@@ -3043,11 +4897,11 @@ mod test_tss_extended {
         };
         // Region of 100bp entirely in TSS zone (200bp)
         let res = check_tss(900, 999, &exon, 200.0, 1300.0);
-        assert_eq!(res[0].0, "TSS");
+        assert_eq!(res[0].area, Area::Tss);
         // 100bp / 100bp region = 100% pctg_dhs
-        assert!((res[0].1 - 100.0).abs() < 0.01);
+        assert!((res[0].pctg_region - 100.0).abs() < 0.01);
         // 100bp / 200bp TSS = 50% pctg_area
-        assert!((res[0].2 - 50.0).abs() < 0.01);
+        assert!((res[0].pctg_area.unwrap() - 50.0).abs() < 0.01);
     }
 }
 
@@ -3070,7 +4924,7 @@ mod test_tts_extended {
         // Region 50bp downstream, entirely in TTS zone (200bp)
         let res = check_tts(2050, 2100, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "TTS");
+        assert_eq!(res[0].area, Area::Tts);
     }
 
     #[test]
@@ -3083,9 +4937,12 @@ mod test_tts_extended {
         };
         // Region from 2150 to 2300 spans TTS boundary (200bp from end)
         let res = check_tts(2150, 2300, &exon, 200.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"TTS"), "Should contain TTS");
-        assert!(tags.contains(&"DOWNSTREAM"), "Should contain DOWNSTREAM");
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Tts), "Should contain TTS");
+        assert!(
+            tags.contains(&Area::Downstream),
+            "Should contain DOWNSTREAM"
+        );
     }
 
     #[test]
@@ -3099,7 +4956,7 @@ mod test_tts_extended {
         };
         let res = check_tts(400, 500, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "DOWNSTREAM");
+        assert_eq!(res[0].area, Area::Downstream);
     }
 
     #[test]
@@ -3112,8 +4969,8 @@ mod test_tts_extended {
         };
         let res = check_tts(2200, 2210, &exon, 200.0);
         // At exactly 200bp, should still be in TTS zone due to <= check
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"TTS") || tags.contains(&"DOWNSTREAM"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Tts) || tags.contains(&Area::Downstream));
     }
 
     #[test]
@@ -3126,9 +4983,9 @@ mod test_tts_extended {
         };
         let res = check_tts(2500, 2600, &exon, 200.0);
         assert_eq!(res.len(), 1);
-        assert_eq!(res[0].0, "DOWNSTREAM");
-        assert_eq!(res[0].1, 100.0); // 100% in DOWNSTREAM
-        assert_eq!(res[0].2, -1.0); // -1 for DOWNSTREAM
+        assert_eq!(res[0].area, Area::Downstream);
+        assert_eq!(res[0].pctg_region, 100.0); // 100% in DOWNSTREAM
+        assert_eq!(res[0].pctg_area, None); // no pctg_area for DOWNSTREAM
     }
 
     #[test]
@@ -3141,7 +4998,7 @@ mod test_tts_extended {
         };
         // Even 5000bp downstream, with TTS of 10000, should be in TTS
         let res = check_tts(7000, 7100, &exon, 10000.0);
-        assert!(res.iter().any(|(t, _, _)| t == "TTS"));
+        assert!(res.iter().any(|o| o.area == Area::Tts));
     }
 
     #[test]
@@ -3154,11 +5011,11 @@ mod test_tts_extended {
         };
         // 50bp region entirely in TTS zone (200bp)
         let res = check_tts(2050, 2099, &exon, 200.0);
-        assert_eq!(res[0].0, "TTS");
+        assert_eq!(res[0].area, Area::Tts);
         // 50bp / 50bp = 100% pctg_dhs
-        assert!((res[0].1 - 100.0).abs() < 0.01);
+        assert!((res[0].pctg_region - 100.0).abs() < 0.01);
         // 50bp / 200bp TTS = 25% pctg_area
-        assert!((res[0].2 - 25.0).abs() < 0.01);
+        assert!((res[0].pctg_area.unwrap() - 25.0).abs() < 0.01);
     }
 
     #[test]
@@ -3171,10 +5028,10 @@ mod test_tts_extended {
         };
         // For negative strand, 100bp before start in TTS zone
         let res = check_tts(900, 999, &exon, 200.0);
-        assert!(res.iter().any(|(t, _, _)| t == "TTS"));
-        for (tag, pctg_dhs, _) in &res {
-            if tag == "TTS" {
-                assert!(*pctg_dhs >= 0.0 && *pctg_dhs <= 100.0);
+        assert!(res.iter().any(|o| o.area == Area::Tts));
+        for overlap in &res {
+            if overlap.area == Area::Tts {
+                assert!(overlap.pctg_region >= 0.0 && overlap.pctg_region <= 100.0);
             }
         }
     }
@@ -3201,7 +5058,7 @@ mod test_rules_extended {
             100,
             200,
             Strand::Positive,
-            exon.to_string(),
+            exon.parse::<ExonNumbers>().unwrap(),
             area,
             transcript.to_string(),
             gene.to_string(),
@@ -3371,7 +5228,7 @@ mod test_overlap_extended {
         let mut transcript = Transcript::new(format!("TRANS_{}", gene_id.replace("GENE", "")));
         for (i, (exon_start, exon_end)) in exons.iter().enumerate() {
             let mut exon = Exon::new(*exon_start, *exon_end);
-            exon.exon_number = Some((i + 1).to_string());
+            exon.exon_number = Some(i as u32 + 1);
             transcript.add_exon(exon);
         }
         transcript.calculate_size();
@@ -3575,7 +5432,7 @@ mod test_output_extended {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -3603,7 +5460,7 @@ mod test_output_extended {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Intron,
             "T1".to_string(),
             "G1".to_string(),
@@ -3636,7 +5493,7 @@ mod test_output_extended {
             -100,
             100,
             Strand::Negative,
-            "1".to_string(),
+            1,
             Area::GeneBody,
             "T1".to_string(),
             "G1".to_string(),
@@ -3658,7 +5515,10 @@ mod test_output_extended {
             100,
             200,
             Strand::Positive,
-            "1,2,3".to_string(),
+            vec![1, 2, 3]
+                .into_iter()
+                .map(ExonNumber::Number)
+                .collect::<ExonNumbers>(),
             Area::Tss,
             "T1,T2,T3".to_string(),
             "G1".to_string(),
@@ -3684,7 +5544,7 @@ mod test_output_extended {
                 100,
                 200,
                 strand,
-                "1".to_string(),
+                1,
                 Area::Tss,
                 "T1".to_string(),
                 "G1".to_string(),
@@ -3771,18 +5631,36 @@ mod test_parser_bed_extended {
 
     #[test]
     fn test_bed_reader_coordinates_ordering() {
-        let mut temp_file = NamedTempFile::new().unwrap();
-        // BED allows start > end in some edge cases (though unusual)
-        writeln!(temp_file, "chr1\t200\t100").unwrap();
-        temp_file.flush().unwrap();
+        use rgmatch::types::InvalidRegions;
+        use std::io::Cursor;
 
-        let mut reader = BedReader::new(temp_file.path()).unwrap();
+        const DATA: &[u8] = b"chr1\t200\t100\nchr1\t300\t400\n";
+
+        // Swapped by default
+        let mut reader = BedReader::from_reader(Cursor::new(DATA));
         let chunk = reader.read_chunk(100).unwrap().unwrap();
+        assert_eq!(chunk.len(), 2);
+        assert_eq!((chunk[0].start, chunk[0].end), (100, 200));
+        assert_eq!(reader.num_swapped(), 1);
+        assert_eq!(reader.num_dropped(), 0);
 
-        // Parser should still read the values as given
+        let mut reader =
+            BedReader::from_reader(Cursor::new(DATA)).invalid_regions(InvalidRegions::Drop);
+        let chunk = reader.read_chunk(100).unwrap().unwrap();
         assert_eq!(chunk.len(), 1);
-        assert_eq!(chunk[0].start, 200);
-        assert_eq!(chunk[0].end, 100);
+        assert_eq!((chunk[0].start, chunk[0].end), (300, 400));
+        assert_eq!(reader.num_swapped(), 0);
+        assert_eq!(reader.num_dropped(), 1);
+
+        let mut reader =
+            BedReader::from_reader(Cursor::new(DATA)).invalid_regions(InvalidRegions::Error);
+        match reader.read_chunk(100) {
+            Err(rgmatch::Error::BedParse { line, reason }) => {
+                assert_eq!(line, 1);
+                assert_eq!(reason, "start 200 is greater than end 100");
+            }
+            other => panic!("expected a BedParse error, got {:?}", other.is_ok()),
+        }
     }
 }
 
@@ -4264,8 +6142,8 @@ mod test_exon_extended {
     #[test]
     fn test_exon_with_exon_number() {
         let mut exon = Exon::new(100, 200);
-        exon.exon_number = Some("5".to_string());
-        assert_eq!(exon.exon_number, Some("5".to_string()));
+        exon.exon_number = Some(5);
+        assert_eq!(exon.exon_number, Some(5));
     }
 
     #[test]
@@ -4513,7 +6391,7 @@ mod test_output_special_chars {
             0,
             0,
             Strand::Positive,
-            String::new(),
+            ExonNumbers::default(),
             Area::Tss,
             String::new(),
             String::new(),
@@ -4535,7 +6413,7 @@ mod test_output_special_chars {
             100,
             200,
             Strand::Negative,
-            "1".to_string(),
+            1,
             Area::Upstream,
             "T1".to_string(),
             "G1".to_string(),
@@ -4847,7 +6725,7 @@ mod test_candidate_extended {
                 100,
                 200,
                 Strand::Positive,
-                "1".to_string(),
+                1,
                 area,
                 "T1".to_string(),
                 "G1".to_string(),
@@ -4867,7 +6745,7 @@ mod test_candidate_extended {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Upstream,
             "T1".to_string(),
             "G1".to_string(),
@@ -4885,7 +6763,7 @@ mod test_candidate_extended {
             0,
             0,
             Strand::Positive,
-            "0".to_string(),
+            0,
             Area::Tss,
             "".to_string(),
             "".to_string(),
@@ -4904,7 +6782,7 @@ mod test_candidate_extended {
             i64::MAX - 1000,
             i64::MAX - 500,
             Strand::Negative,
-            "999".to_string(),
+            999,
             Area::GeneBody,
             "VERY_LONG_TRANSCRIPT_ID".to_string(),
             "VERY_LONG_GENE_ID".to_string(),
@@ -4928,11 +6806,11 @@ mod test_candidate_extended {
 }
 
 // -------------------------------------------------------------------------
-// 31. match_regions_to_genes Integration Tests
+// 31. associate_regions Integration Tests
 // -------------------------------------------------------------------------
 
-mod test_match_regions_to_genes {
-    use rgmatch::matcher::overlap::match_regions_to_genes;
+mod test_associate_regions {
+    use rgmatch::matcher::overlap::associate_regions;
     use rgmatch::types::{Exon, Strand, Transcript};
     use rgmatch::{Config, Gene, Region, ReportLevel};
 
@@ -4950,7 +6828,7 @@ mod test_match_regions_to_genes {
     }
 
     #[test]
-    fn test_match_regions_to_genes_empty_regions() {
+    fn test_associate_regions_empty_regions() {
         let config = Config::default();
         let regions: Vec<Region> = vec![];
         let genes = vec![make_multi_exon_gene(
@@ -4959,23 +6837,23 @@ mod test_match_regions_to_genes {
             vec![(1000, 1200)],
         )];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         assert!(results.is_empty());
     }
 
     #[test]
-    fn test_match_regions_to_genes_empty_genes() {
+    fn test_associate_regions_empty_genes() {
         let config = Config::default();
         let regions = vec![Region::new("chr1".into(), 100, 200, vec![])];
         let genes: Vec<Gene> = vec![];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 0);
+        let results = associate_regions(&regions, &genes, &config, 0);
         assert_eq!(results.len(), 1);
-        assert!(results[0].1.is_empty());
+        assert!(results[0].candidates.is_empty());
     }
 
     #[test]
-    fn test_match_regions_to_genes_sorted_regions() {
+    fn test_associate_regions_sorted_regions() {
         let config = Config::default();
         let regions = vec![
             Region::new("chr1".into(), 1050, 1100, vec![]),
@@ -4987,17 +6865,17 @@ mod test_match_regions_to_genes {
             make_multi_exon_gene("G2", Strand::Positive, vec![(2000, 2200)]),
         ];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         assert_eq!(results.len(), 3);
         // First two regions should match G1
-        assert!(!results[0].1.is_empty());
-        assert!(!results[1].1.is_empty());
+        assert!(!results[0].candidates.is_empty());
+        assert!(!results[1].candidates.is_empty());
         // Third region should match G2
-        assert!(!results[2].1.is_empty());
+        assert!(!results[2].candidates.is_empty());
     }
 
     #[test]
-    fn test_match_regions_to_genes_max_gene_length() {
+    fn test_associate_regions_max_gene_length() {
         let config = Config::default();
         let regions = vec![Region::new("chr1".into(), 500, 600, vec![])];
         let genes = vec![make_multi_exon_gene(
@@ -5007,13 +6885,13 @@ mod test_match_regions_to_genes {
         )];
 
         // With max_gene_length=4000, lookback covers the region
-        let results = match_regions_to_genes(&regions, &genes, &config, 4000);
+        let results = associate_regions(&regions, &genes, &config, 4000);
         // Region is upstream of gene - should find upstream/TSS/PROMOTER candidate
-        assert!(!results[0].1.is_empty());
+        assert!(!results[0].candidates.is_empty());
     }
 
     #[test]
-    fn test_match_regions_to_genes_multiple_chromosomes() {
+    fn test_associate_regions_multiple_chromosomes() {
         let config = Config::default();
         // Regions on different chromosomes (but all genes on chr1 for simplicity)
         let regions = vec![
@@ -5026,15 +6904,15 @@ mod test_match_regions_to_genes {
             vec![(1000, 1200)],
         )];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         // Both processed, but only chr1 region matches
         assert_eq!(results.len(), 2);
-        assert!(!results[0].1.is_empty()); // chr1 matches
-                                           // chr2 doesn't match (different chrom logic is in main matching)
+        assert!(!results[0].candidates.is_empty()); // chr1 matches
+                                                    // chr2 doesn't match (different chrom logic is in main matching)
     }
 
     #[test]
-    fn test_match_regions_to_genes_large_gap_between_genes() {
+    fn test_associate_regions_large_gap_between_genes() {
         let config = Config::default();
         let regions = vec![
             Region::new("chr1".into(), 1050, 1100, vec![]),
@@ -5045,16 +6923,16 @@ mod test_match_regions_to_genes {
             make_multi_exon_gene("G2", Strand::Positive, vec![(50000, 50200)]),
         ];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         assert_eq!(results.len(), 2);
         // First region matches G1
-        assert!(results[0].1.iter().any(|c| c.gene == "G1"));
+        assert!(results[0].candidates.iter().any(|c| c.gene == "G1"));
         // Second region matches G2
-        assert!(results[1].1.iter().any(|c| c.gene == "G2"));
+        assert!(results[1].candidates.iter().any(|c| c.gene == "G2"));
     }
 
     #[test]
-    fn test_match_regions_to_genes_with_gene_level_reporting() {
+    fn test_associate_regions_with_gene_level_reporting() {
         let config = Config {
             level: ReportLevel::Gene,
             ..Default::default()
@@ -5078,11 +6956,11 @@ mod test_match_regions_to_genes {
         let regions = vec![Region::new("chr1".into(), 1150, 1250, vec![])];
         let genes = vec![gene];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         // Gene level - should merge transcripts
         assert_eq!(results.len(), 1);
         // Should have consolidated output
-        for candidate in &results[0].1 {
+        for candidate in &results[0].candidates {
             // At gene level, transcripts should be merged
             if candidate.gene == "G1" {
                 // Candidate should exist
@@ -5092,7 +6970,7 @@ mod test_match_regions_to_genes {
     }
 
     #[test]
-    fn test_match_regions_to_genes_preserves_region_order() {
+    fn test_associate_regions_preserves_region_order() {
         let config = Config::default();
         let regions = vec![
             Region::new("chr1".into(), 5000, 5100, vec!["region_5".into()]),
@@ -5105,12 +6983,41 @@ mod test_match_regions_to_genes {
             make_multi_exon_gene("G3", Strand::Positive, vec![(4900, 5200)]),
         ];
 
-        let results = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let results = associate_regions(&regions, &genes, &config, 1000);
         assert_eq!(results.len(), 3);
         // Order should be preserved
-        assert_eq!(results[0].0.metadata[0], "region_5");
-        assert_eq!(results[1].0.metadata[0], "region_1");
-        assert_eq!(results[2].0.metadata[0], "region_3");
+        assert_eq!(results[0].region.metadata[0], "region_5");
+        assert_eq!(results[1].region.metadata[0], "region_1");
+        assert_eq!(results[2].region.metadata[0], "region_3");
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_match_regions_to_genes_matches_associate_regions() {
+        use rgmatch::matcher::overlap::match_regions_to_genes;
+
+        let config = Config::default();
+        let regions = vec![
+            Region::new("chr1".into(), 1000, 1100, vec![]),
+            Region::new("chr1".into(), 80000, 80100, vec![]),
+        ];
+        let genes = vec![make_multi_exon_gene(
+            "G1",
+            Strand::Positive,
+            vec![(900, 1200)],
+        )];
+
+        let tuples = match_regions_to_genes(&regions, &genes, &config, 1000);
+        let associations = associate_regions(&regions, &genes, &config, 1000);
+        assert_eq!(tuples.len(), associations.len());
+        for ((region, candidates), association) in tuples.iter().zip(&associations) {
+            assert_eq!(region.id(), association.region.id());
+            assert_eq!(
+                format!("{:?}", candidates),
+                format!("{:?}", association.candidates)
+            );
+        }
+        assert!(associations[1].is_unassigned());
     }
 }
 
@@ -5166,7 +7073,7 @@ mod test_transcript_advanced {
         t.renumber_exons(Strand::Positive);
 
         for (i, exon) in t.exons.iter().enumerate() {
-            assert_eq!(exon.exon_number, Some((i + 1).to_string()));
+            assert_eq!(exon.exon_number, Some(i as u32 + 1));
         }
     }
 
@@ -5180,9 +7087,9 @@ mod test_transcript_advanced {
         t.renumber_exons(Strand::Negative);
 
         // First exon (lowest position) should have highest number
-        assert_eq!(t.exons[0].exon_number, Some("5".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(5));
         // Last exon (highest position) should have number 1
-        assert_eq!(t.exons[4].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[4].exon_number, Some(1));
     }
 
     #[test]
@@ -5208,7 +7115,7 @@ mod test_transcript_advanced {
         t.calculate_size();
         t.renumber_exons(Strand::Positive);
 
-        assert_eq!(t.exons[0].exon_number, Some("1".to_string()));
+        assert_eq!(t.exons[0].exon_number, Some(1));
         assert_eq!(t.start, 1000);
         assert_eq!(t.end, 2000);
     }
@@ -5222,8 +7129,8 @@ mod test_transcript_advanced {
 
         let cloned = t.clone();
 
-        assert_eq!(cloned.exons[0].exon_number, Some("1".to_string()));
-        assert_eq!(cloned.exons[1].exon_number, Some("2".to_string()));
+        assert_eq!(cloned.exons[0].exon_number, Some(1));
+        assert_eq!(cloned.exons[1].exon_number, Some(2));
     }
 }
 
@@ -5455,7 +7362,7 @@ mod test_config_validation {
 
 mod test_tss_boundary_conditions {
     use rgmatch::matcher::tss::{check_tss, TssExonInfo};
-    use rgmatch::types::Strand;
+    use rgmatch::types::{Area, Strand};
 
     #[test]
     fn test_tss_region_at_exact_tss_position() {
@@ -5482,8 +7389,8 @@ mod test_tss_boundary_conditions {
         };
         let res = check_tss(0, 100, &exon, 200.0, 1300.0);
         // Very far upstream
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"UPSTREAM"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Upstream));
     }
 
     #[test]
@@ -5510,9 +7417,9 @@ mod test_tss_boundary_conditions {
         };
         // TSS distance is 0 - everything should go to promoter or upstream
         let res = check_tss(900, 950, &exon, 0.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // With TSS=0, should be PROMOTER or UPSTREAM
-        assert!(tags.contains(&"PROMOTER") || tags.contains(&"UPSTREAM"));
+        assert!(tags.contains(&Area::Promoter) || tags.contains(&Area::Upstream));
     }
 
     #[test]
@@ -5525,11 +7432,15 @@ mod test_tss_boundary_conditions {
         };
         // Region from 0 to 5000 spans everything upstream
         let res = check_tss(0, 4999, &exon, 200.0, 1300.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // Should contain multiple zones
         assert!(!res.is_empty());
         // Should have TSS, PROMOTER, and UPSTREAM
-        assert!(tags.contains(&"TSS") || tags.contains(&"PROMOTER") || tags.contains(&"UPSTREAM"));
+        assert!(
+            tags.contains(&Area::Tss)
+                || tags.contains(&Area::Promoter)
+                || tags.contains(&Area::Upstream)
+        );
     }
 }
 
@@ -5539,7 +7450,7 @@ mod test_tss_boundary_conditions {
 
 mod test_tts_boundary_conditions {
     use rgmatch::matcher::tts::{check_tts, TtsExonInfo};
-    use rgmatch::types::Strand;
+    use rgmatch::types::{Area, Strand};
 
     #[test]
     fn test_tts_region_at_exact_tts_position() {
@@ -5564,8 +7475,8 @@ mod test_tts_boundary_conditions {
         };
         let res = check_tts(1_002_000, 1_002_100, &exon, 200.0);
         // Very far downstream
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
-        assert!(tags.contains(&"DOWNSTREAM"));
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
+        assert!(tags.contains(&Area::Downstream));
     }
 
     #[test]
@@ -5592,9 +7503,9 @@ mod test_tts_boundary_conditions {
         };
         // TTS distance is 0
         let res = check_tts(2050, 2100, &exon, 0.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // Everything downstream of TTS zone should be DOWNSTREAM
-        assert!(tags.contains(&"DOWNSTREAM"));
+        assert!(tags.contains(&Area::Downstream));
     }
 
     #[test]
@@ -5607,9 +7518,9 @@ mod test_tts_boundary_conditions {
         };
         // Large region spanning TTS and downstream
         let res = check_tts(2000, 3000, &exon, 200.0);
-        let tags: Vec<&str> = res.iter().map(|(t, _, _)| t.as_str()).collect();
+        let tags: Vec<Area> = res.iter().map(|o| o.area).collect();
         // Should have both TTS and DOWNSTREAM
-        assert!(tags.contains(&"TTS") || tags.contains(&"DOWNSTREAM"));
+        assert!(tags.contains(&Area::Tts) || tags.contains(&Area::Downstream));
     }
 }
 
@@ -5734,10 +7645,7 @@ mod test_gtf_parser_edge_cases {
         let gene = &result.genes_by_chrom["chr1"][0];
 
         assert_eq!(gene.transcripts[0].exons.len(), 1);
-        assert_eq!(
-            gene.transcripts[0].exons[0].exon_number,
-            Some("1".to_string())
-        );
+        assert_eq!(gene.transcripts[0].exons[0].exon_number, Some(1));
     }
 
     #[test]
@@ -5790,9 +7698,9 @@ mod test_gtf_parser_edge_cases {
         assert_eq!(transcript.exons[2].start, 3000);
 
         // Exon numbers should be correct
-        assert_eq!(transcript.exons[0].exon_number, Some("1".to_string()));
-        assert_eq!(transcript.exons[1].exon_number, Some("2".to_string()));
-        assert_eq!(transcript.exons[2].exon_number, Some("3".to_string()));
+        assert_eq!(transcript.exons[0].exon_number, Some(1));
+        assert_eq!(transcript.exons[1].exon_number, Some(2));
+        assert_eq!(transcript.exons[2].exon_number, Some(3));
     }
 
     #[test]
@@ -6013,7 +7921,7 @@ mod test_output_format_validation {
             100,
             200,
             Strand::Positive,
-            "5".to_string(),
+            5,
             Area::Intron,
             "TRANSCRIPT_A".to_string(),
             "GENE_X".to_string(),
@@ -6045,7 +7953,7 @@ mod test_output_format_validation {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -6068,7 +7976,7 @@ mod test_output_format_validation {
             100,
             200,
             Strand::Positive,
-            "1".to_string(),
+            1,
             Area::Tss,
             "T1".to_string(),
             "G1".to_string(),
@@ -6170,3 +8078,917 @@ mod test_result_ordering {
         assert_eq!(output, vec![0, 1, 2, 3, 4, 5, 6, 7]);
     }
 }
+
+// -------------------------------------------------------------------------
+// Interval Index Differential Tests
+// -------------------------------------------------------------------------
+// The interval index must find exactly the associations of the lookback scan,
+// and the scan exactly those of a brute-force match over every gene.
+
+mod test_interval_index_differential {
+    use rgmatch::config::UNLIMITED_DISTANCE;
+    use rgmatch::matcher::overlap::{associate_regions, associate_regions_par, match_region};
+    use rgmatch::matcher::RegionMatcher;
+    use rgmatch::parser::parse_gtf_from_reader;
+    use rgmatch::source::AnnotationSource;
+    use rgmatch::{Config, Region, ReportLevel};
+
+    use crate::support::{megabase_gene_gtf, random_gtf, random_regions, sorted_regions, Lcg};
+
+    #[test]
+    fn test_interval_index_matches_scan_under_megabase_gene() {
+        let gtf_text = megabase_gene_gtf("chr1", 500, 2_000, 1);
+        let gtf = parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap();
+        let regions = sorted_regions(&mut Lcg(0x10e6), "chr1", 300, 1_000_000);
+
+        let scan = Config {
+            use_interval_index: false,
+            ..Config::default()
+        };
+        let indexed = Config {
+            use_interval_index: true,
+            ..Config::default()
+        };
+        let mut scan_matcher = RegionMatcher::new(&gtf, &scan);
+        let mut indexed_matcher = RegionMatcher::new(&gtf, &indexed);
+        for region in &regions {
+            let expected = scan_matcher.match_next(region);
+            let actual = indexed_matcher.match_next(region);
+            assert_eq!(
+                format!("{:?}", actual.candidates),
+                format!("{:?}", expected.candidates),
+                "{}",
+                region.id()
+            );
+            // Every region lies within the long gene
+            assert!(expected.candidates.iter().any(|c| c.gene == "LONG"));
+        }
+    }
+
+    #[test]
+    fn test_interval_index_matches_scan() {
+        let mut rng = Lcg(0x5eed);
+        let chroms = ["chr1", "chr2", "chr3"];
+        let gtf_text = random_gtf(&mut rng, &chroms, 150);
+        let gtf = parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap();
+
+        // Sorted regions use the forward scan, shuffled ones the binary search
+        let mut regions = random_regions(&mut rng, &["chr1", "chr2", "chr3", "chrM"], 600);
+        let mut sorted = regions.clone();
+        sorted.sort_by(|a, b| a.chrom.cmp(&b.chrom).then(a.start.cmp(&b.start)));
+        regions.extend(sorted);
+
+        let settings = [
+            (200.0, 0.0, 1300.0, 10_000),
+            (0.0, 0.0, 0.0, 0),
+            (1000.0, 2500.0, 5000.0, 100_000),
+            (200.0, 0.0, 1300.0, UNLIMITED_DISTANCE),
+        ];
+        for level in [
+            ReportLevel::Exon,
+            ReportLevel::Transcript,
+            ReportLevel::Gene,
+        ] {
+            for &(tss, tts, promoter, distance) in &settings {
+                let scan = Config {
+                    level,
+                    tss,
+                    tts,
+                    promoter,
+                    distance,
+                    use_interval_index: false,
+                    ..Config::default()
+                };
+                let indexed = Config {
+                    use_interval_index: true,
+                    ..scan.clone()
+                };
+
+                let mut scan_matcher = RegionMatcher::new(&gtf, &scan);
+                let mut indexed_matcher = RegionMatcher::new(&gtf, &indexed);
+                let mut associated = 0;
+                for region in &regions {
+                    let expected = scan_matcher.match_next(region);
+                    let actual = indexed_matcher.match_next(region);
+                    assert_eq!(
+                        format!("{:?}", actual.candidates),
+                        format!("{:?}", expected.candidates),
+                        "{} at {:?} level, tss {} tts {} promoter {} distance {}",
+                        region.id(),
+                        level,
+                        tss,
+                        tts,
+                        promoter,
+                        distance
+                    );
+                    assert_eq!(actual.num_candidate_genes, expected.num_candidate_genes);
+                    associated += usize::from(!expected.candidates.is_empty());
+                }
+                // Most regions should have associations, or the comparison proves little
+                assert!(associated > regions.len() / 2, "{} associated", associated);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shared_annotation_reused_across_parallel_runs() {
+        use rgmatch::pipeline::RegionAnnotator;
+        use std::sync::Arc;
+
+        let mut rng = Lcg(0xa11e);
+        let chroms = ["chr1", "chr2"];
+        let gtf_text = random_gtf(&mut rng, &chroms, 100);
+        let gtf = Arc::new(
+            parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap(),
+        );
+        // Addresses of the genes of each chromosome
+        let gene_ptrs: Vec<usize> = chroms
+            .iter()
+            .map(|chrom| gtf.genes(chrom).as_ptr() as usize)
+            .collect();
+        let mut regions = random_regions(&mut rng, &chroms, 400);
+        regions.sort_by(|a, b| a.chrom.cmp(&b.chrom).then(a.start.cmp(&b.start)));
+        let on_chrom = |chrom: &str| -> Vec<Region> {
+            regions
+                .iter()
+                .filter(|r| r.chrom == chrom)
+                .cloned()
+                .collect()
+        };
+        let config = Config::default();
+        let expected: Vec<String> = chroms
+            .iter()
+            .map(|chrom| {
+                let associations =
+                    associate_regions(&on_chrom(chrom), gtf.genes(chrom), &config, 0);
+                format!("{:?}", associations)
+            })
+            .collect();
+
+        // Every thread matches the one annotation, twice, without a copy of
+        // its genes and with the same results
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let gtf = Arc::clone(&gtf);
+                let (chroms, gene_ptrs, expected) = (&chroms, &gene_ptrs, &expected);
+                let (config, on_chrom) = (&config, &on_chrom);
+                scope.spawn(move || {
+                    let mut annotator = RegionAnnotator::new(Arc::clone(&gtf), config.clone());
+                    for _ in 0..2 {
+                        for (i, chrom) in chroms.iter().enumerate() {
+                            let genes = annotator.gtf().genes(chrom);
+                            assert_eq!(genes.as_ptr() as usize, gene_ptrs[i]);
+                            let regions = on_chrom(chrom);
+                            let par = associate_regions_par(&regions, genes, config, 16);
+                            assert_eq!(format!("{:?}", par), expected[i]);
+                            let streamed: Vec<_> = annotator.annotate_iter(regions).collect();
+                            assert_eq!(format!("{:?}", streamed), expected[i]);
+                        }
+                    }
+                });
+            }
+        });
+
+        // No thread or annotator kept a reference, and the genes were
+        // neither moved nor changed
+        let gtf = Arc::try_unwrap(gtf).unwrap_or_else(|_| panic!("annotation still shared"));
+        for (i, chrom) in chroms.iter().enumerate() {
+            assert_eq!(gtf.genes(chrom).as_ptr() as usize, gene_ptrs[i]);
+            let associations = associate_regions(&on_chrom(chrom), gtf.genes(chrom), &config, 0);
+            assert_eq!(format!("{:?}", associations), expected[i]);
+        }
+    }
+
+    #[test]
+    fn test_parallel_chunks_match_sequential() {
+        let mut rng = Lcg(0xc4a2);
+        let gtf_text = random_gtf(&mut rng, &["chr1"], 200);
+        let gtf = parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap();
+        let genes = gtf.genes("chr1");
+
+        let mut regions = random_regions(&mut rng, &["chr1"], 800);
+        regions.sort_by_key(|r| r.start);
+
+        for distance in [0, 10_000, UNLIMITED_DISTANCE] {
+            let config = Config {
+                distance,
+                ..Config::default()
+            };
+            let expected = format!("{:?}", associate_regions(&regions, genes, &config, 0));
+            // Chunk sizes that split the regions unevenly, down to one region per chunk
+            for chunk_size in [1, 7, 256, 5_000] {
+                let actual = associate_regions_par(&regions, genes, &config, chunk_size);
+                assert_eq!(
+                    format!("{:?}", actual),
+                    expected,
+                    "distance {} chunk size {}",
+                    distance,
+                    chunk_size
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scan_matches_brute_force_with_giant_gene() {
+        let mut rng = Lcg(0xb16);
+        let chroms = ["chr1", "chr2"];
+        let mut gtf_text = random_gtf(&mut rng, &chroms, 200);
+        // A 2.3 Mb gene early on chr1, spanning most short genes
+        gtf_text.push_str(
+            "chr1\tTEST\texon\t5000\t6000\t.\t+\t.\tgene_id \"GIANT\"; transcript_id \"GIANT_T\";\n\
+             chr1\tTEST\texon\t2300000\t2305000\t.\t+\t.\tgene_id \"GIANT\"; transcript_id \"GIANT_T\";\n",
+        );
+        let gtf = parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap();
+        assert!(gtf.max_length("chr1") > 2_000_000);
+
+        let mut regions = random_regions(&mut rng, &["chr1", "chr2"], 500);
+        let mut sorted = regions.clone();
+        sorted.sort_by(|a, b| a.chrom.cmp(&b.chrom).then(a.start.cmp(&b.start)));
+        regions.extend(sorted);
+
+        for level in [ReportLevel::Exon, ReportLevel::Gene] {
+            for distance in [0, 10_000] {
+                let config = Config {
+                    level,
+                    distance,
+                    use_interval_index: false,
+                    ..Config::default()
+                };
+                let mut matcher = RegionMatcher::new(&gtf, &config);
+                for region in &regions {
+                    let expected = match_region(region, gtf.genes(&region.chrom), &config, 0);
+                    let actual = matcher.match_next(region);
+                    assert_eq!(
+                        format!("{:?}", actual.candidates),
+                        format!("{:?}", expected.candidates),
+                        "{} at {:?} level, distance {}",
+                        region.id(),
+                        level,
+                        distance
+                    );
+                    assert_eq!(actual.num_candidate_genes, expected.num_candidate_genes);
+                }
+            }
+        }
+
+        // Regions far past the giant gene's start skip every gene ending
+        // before their window, instead of scanning back 2.3 Mb
+        let genes = gtf.genes("chr1");
+        let first = gtf.first_gene_reaching("chr1", 1_500_000);
+        assert!(genes[..first].iter().all(|g| g.end < 1_500_000));
+        assert!(genes[first].end >= 1_500_000);
+    }
+}
+
+// -------------------------------------------------------------------------
+// Single-Position Classification Tests
+// -------------------------------------------------------------------------
+
+mod test_classify_position {
+    use rgmatch::parser::{parse_gtf_from_reader, GtfData};
+    use rgmatch::types::CoordinateConvention;
+    use rgmatch::{classify_position, Area, Config, RegionAnnotator};
+
+    /// One positive-strand gene with exons 10000-10200, 10500-10800 and
+    /// 11100-11400 on chr1.
+    const GTF: &[u8] = b"\
+chr1\tTEST\texon\t10000\t10200\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t10500\t10800\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t11100\t11400\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+";
+
+    fn gtf() -> GtfData {
+        parse_gtf_from_reader(GTF, "gene_id", "transcript_id").unwrap()
+    }
+
+    fn one_based() -> Config {
+        Config {
+            output_coordinate_convention: CoordinateConvention::OneBased,
+            ..Config::default()
+        }
+    }
+
+    fn areas(pos: i64, config: &Config) -> Vec<Area> {
+        classify_position("chr1", pos, &gtf(), config)
+            .iter()
+            .map(|c| c.area)
+            .collect()
+    }
+
+    #[test]
+    fn test_walk_across_gene() {
+        let config = one_based();
+        for (pos, expected) in [
+            (5000, Area::Upstream),
+            (9000, Area::Promoter),
+            (9900, Area::Tss),
+            (10000, Area::FirstExon),
+            (10300, Area::Intron),
+            (10600, Area::GeneBody),
+            (10900, Area::Intron),
+            (11400, Area::GeneBody),
+            (12000, Area::Downstream),
+        ] {
+            assert_eq!(areas(pos, &config), vec![expected], "position {}", pos);
+        }
+
+        // Beyond the 10 kb distance limit
+        assert!(areas(25_000, &config).is_empty());
+    }
+
+    #[test]
+    fn test_tss_boundary() {
+        let config = one_based();
+        // The TSS area is the 200 bp before the first exon
+        assert_eq!(areas(9800, &config), vec![Area::Tss]);
+        assert_eq!(areas(9799, &config), vec![Area::Promoter]);
+    }
+
+    #[test]
+    fn test_bed0_position_is_next_base() {
+        // BED position 9799 is the interval 9799..9800, base 9800 in GTF terms
+        let candidates = classify_position("chr1", 9799, &gtf(), &Config::default());
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].area, Area::Tss);
+        assert_eq!(candidates[0].pctg_region, 100.0);
+    }
+
+    #[test]
+    fn test_missing_chromosome() {
+        assert!(classify_position("chrX", 10_000, &gtf(), &Config::default()).is_empty());
+    }
+
+    #[test]
+    fn test_annotator_matches_function() {
+        let config = one_based();
+        let mut annotator = RegionAnnotator::new(gtf(), config.clone());
+        for pos in [12000, 9900, 10600, 5000] {
+            assert_eq!(
+                format!("{:?}", annotator.classify_position("chr1", pos)),
+                format!("{:?}", classify_position("chr1", pos, &gtf(), &config)),
+            );
+        }
+        assert!(annotator.classify_position("chrX", 10_000).is_empty());
+    }
+}
+
+// -------------------------------------------------------------------------
+// Annotation Source Tests
+// -------------------------------------------------------------------------
+// Genes built in memory must match exactly like the same genes read from GTF.
+
+mod test_annotation_source {
+    use rgmatch::matcher::RegionMatcher;
+    use rgmatch::parser::parse_gtf_from_reader;
+    use rgmatch::types::{Exon, Gene, Strand, Transcript};
+    use rgmatch::{AnnotationSource, Config, Region, ReportLevel, VecSource};
+
+    /// (chrom, gene, strand, transcripts with their exons)
+    type Model = (
+        &'static str,
+        &'static str,
+        Strand,
+        Vec<(&'static str, Vec<(i64, i64)>)>,
+    );
+
+    fn models() -> Vec<Model> {
+        vec![
+            (
+                "chr1",
+                "G1",
+                Strand::Positive,
+                vec![
+                    ("G1.1", vec![(1000, 1200), (1500, 1800), (2100, 2400)]),
+                    ("G1.2", vec![(1000, 1200), (2100, 2600)]),
+                ],
+            ),
+            (
+                "chr1",
+                "G2",
+                Strand::Negative,
+                vec![("G2.1", vec![(3000, 3300), (3800, 4200)])],
+            ),
+            (
+                "chr1",
+                "G3",
+                Strand::Positive,
+                vec![("G3.1", vec![(2000, 90_000)])],
+            ),
+            (
+                "chr2",
+                "G4",
+                Strand::Negative,
+                vec![("G4.1", vec![(500, 700), (900, 1100), (1300, 1600)])],
+            ),
+        ]
+    }
+
+    fn gtf_text(models: &[Model]) -> String {
+        let mut text = String::new();
+        for (chrom, gene_id, strand, transcripts) in models {
+            for (transcript_id, exons) in transcripts {
+                for (start, end) in exons {
+                    text.push_str(&format!(
+                        "{}\tTEST\texon\t{}\t{}\t.\t{}\t.\tgene_id \"{}\"; transcript_id \"{}\";\n",
+                        chrom, start, end, strand, gene_id, transcript_id
+                    ));
+                }
+            }
+        }
+        text
+    }
+
+    fn vec_source(models: &[Model]) -> VecSource {
+        models
+            .iter()
+            .map(|(chrom, gene_id, strand, transcripts)| {
+                let mut gene = Gene::new(gene_id.to_string(), *strand);
+                for (transcript_id, exons) in transcripts {
+                    let mut transcript = Transcript::new(transcript_id.to_string());
+                    for &(start, end) in exons {
+                        transcript.add_exon(Exon::new(start, end));
+                    }
+                    transcript.renumber_exons(*strand);
+                    transcript.calculate_size();
+                    gene.add_transcript(transcript);
+                }
+                gene.calculate_size();
+                (chrom.to_string(), gene)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_vec_source_matches_gtf() {
+        let models = models();
+        let gtf = parse_gtf_from_reader(gtf_text(&models).as_bytes(), "gene_id", "transcript_id")
+            .unwrap();
+        let source = vec_source(&models);
+        assert_eq!(source.chromosomes(), AnnotationSource::chromosomes(&gtf));
+
+        let mut regions = Vec::new();
+        for chrom in ["chr1", "chr2", "chr3"] {
+            for start in (0..100_000).step_by(350) {
+                regions.push(Region::new(chrom.to_string(), start, start + 120, vec![]));
+            }
+        }
+
+        for level in [
+            ReportLevel::Exon,
+            ReportLevel::Transcript,
+            ReportLevel::Gene,
+        ] {
+            for use_interval_index in [false, true] {
+                let config = Config {
+                    level,
+                    tts: 300.0,
+                    use_interval_index,
+                    ..Config::default()
+                };
+                let mut from_gtf = RegionMatcher::new(&gtf, &config);
+                let mut from_source = RegionMatcher::new(&source, &config);
+                let mut associated = 0;
+                for region in &regions {
+                    let expected = from_gtf.match_next(region);
+                    let actual = from_source.match_next(region);
+                    assert_eq!(
+                        format!("{:?}", actual.candidates),
+                        format!("{:?}", expected.candidates),
+                        "{} at {:?} level",
+                        region.id(),
+                        level
+                    );
+                    assert_eq!(actual.num_candidate_genes, expected.num_candidate_genes);
+                    if !actual.candidates.is_empty() {
+                        associated += 1;
+                    }
+                }
+                assert!(associated > 100, "{} associated", associated);
+            }
+        }
+    }
+}
+
+mod test_area_boundaries {
+    use rgmatch::matcher::tss::{check_tss_with_boundary, TssExonInfo};
+    use rgmatch::matcher::tts::{check_tts_with_boundary, TtsExonInfo};
+    use rgmatch::types::{Area, Strand};
+
+    const TSS: f64 = 200.0;
+    const PROMOTER: f64 = 1300.0;
+    const TTS: f64 = 100.0;
+
+    /// Areas of the single base `d` bp upstream of the TSS of an exon
+    /// [2000, 3000] on `strand`.
+    fn tss_areas(strand: Strand, d: i64, inclusive: bool) -> Vec<Area> {
+        let exon = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand,
+            distance: d,
+        };
+        let pos = match strand {
+            Strand::Positive => 2000 - d,
+            Strand::Negative => 3000 + d,
+        };
+        check_tss_with_boundary(pos, pos, &exon, TSS, PROMOTER, 0.0, inclusive)
+            .iter()
+            .map(|o| o.area)
+            .collect()
+    }
+
+    /// Areas of the single base `d` bp downstream of the TTS of an exon
+    /// [2000, 3000] on `strand`.
+    fn tts_areas(strand: Strand, d: i64, inclusive: bool) -> Vec<Area> {
+        let exon = TtsExonInfo {
+            start: 2000,
+            end: 3000,
+            strand,
+            distance: d,
+        };
+        let pos = match strand {
+            Strand::Positive => 3000 + d,
+            Strand::Negative => 2000 - d,
+        };
+        check_tts_with_boundary(pos, pos, &exon, TTS, inclusive)
+            .iter()
+            .map(|o| o.area)
+            .collect()
+    }
+
+    #[test]
+    fn test_tss_promoter_boundary() {
+        let w = TSS as i64;
+        for strand in [Strand::Positive, Strand::Negative] {
+            assert_eq!(tss_areas(strand, w - 1, true), vec![Area::Tss]);
+            assert_eq!(tss_areas(strand, w, true), vec![Area::Tss]);
+            assert_eq!(tss_areas(strand, w + 1, true), vec![Area::Promoter]);
+
+            assert_eq!(tss_areas(strand, w - 1, false), vec![Area::Tss]);
+            assert_eq!(tss_areas(strand, w, false), vec![Area::Promoter]);
+            assert_eq!(tss_areas(strand, w + 1, false), vec![Area::Promoter]);
+        }
+    }
+
+    #[test]
+    fn test_promoter_upstream_boundary() {
+        let w = (TSS + PROMOTER) as i64;
+        for strand in [Strand::Positive, Strand::Negative] {
+            assert_eq!(tss_areas(strand, w - 1, true), vec![Area::Promoter]);
+            assert_eq!(tss_areas(strand, w, true), vec![Area::Promoter]);
+            assert_eq!(tss_areas(strand, w + 1, true), vec![Area::Upstream]);
+
+            assert_eq!(tss_areas(strand, w - 1, false), vec![Area::Promoter]);
+            assert_eq!(tss_areas(strand, w, false), vec![Area::Upstream]);
+            assert_eq!(tss_areas(strand, w + 1, false), vec![Area::Upstream]);
+        }
+    }
+
+    #[test]
+    fn test_tts_downstream_boundary() {
+        let w = TTS as i64;
+        for strand in [Strand::Positive, Strand::Negative] {
+            assert_eq!(tts_areas(strand, w - 1, true), vec![Area::Tts]);
+            assert_eq!(tts_areas(strand, w, true), vec![Area::Tts]);
+            assert_eq!(tts_areas(strand, w + 1, true), vec![Area::Downstream]);
+
+            assert_eq!(tts_areas(strand, w - 1, false), vec![Area::Tts]);
+            assert_eq!(tts_areas(strand, w, false), vec![Area::Downstream]);
+            assert_eq!(tts_areas(strand, w + 1, false), vec![Area::Downstream]);
+        }
+    }
+
+    #[test]
+    fn test_exclusive_area_widths() {
+        let exon = TssExonInfo {
+            start: 2000,
+            end: 3000,
+            strand: Strand::Positive,
+            distance: 1,
+        };
+        // The whole TSS area: 200 bases when inclusive, 199 when exclusive
+        let res = check_tss_with_boundary(1800, 1999, &exon, TSS, PROMOTER, 0.0, true);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].pctg_area, Some(100.0));
+        let res = check_tss_with_boundary(1801, 1999, &exon, TSS, PROMOTER, 0.0, false);
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].pctg_area, Some(100.0));
+
+        // Spanning TSS and PROMOTER, the boundary base counts as PROMOTER
+        let res = check_tss_with_boundary(1700, 1999, &exon, TSS, PROMOTER, 0.0, false);
+        let areas: Vec<(Area, f64)> = res.iter().map(|o| (o.area, o.pctg_region)).collect();
+        assert_eq!(
+            areas,
+            vec![
+                (Area::Tss, 199.0 / 300.0 * 100.0),
+                (Area::Promoter, 101.0 / 300.0 * 100.0)
+            ]
+        );
+
+        // Across the whole PROMOTER, which keeps its 1300 bases
+        let res = check_tss_with_boundary(0, 1999, &exon, TSS, PROMOTER, 0.0, false);
+        assert_eq!(res[1].area, Area::Promoter);
+        assert_eq!(res[1].pctg_region, 1300.0 / 2000.0 * 100.0);
+        assert_eq!(res[2].area, Area::Upstream);
+        assert_eq!(res[2].pctg_region, 501.0 / 2000.0 * 100.0);
+
+        // A 1 bp TSS area is empty when exclusive
+        let res = check_tss_with_boundary(1999, 1999, &exon, 1.0, PROMOTER, 0.0, false);
+        assert_eq!(res[0].area, Area::Promoter);
+    }
+}
+
+mod test_percentage_range {
+    use rgmatch::matcher::tss::{check_tss_with_boundary, TssExonInfo};
+    use rgmatch::matcher::tts::{check_tts_with_boundary, TtsExonInfo};
+    use rgmatch::matcher::RegionMatcher;
+    use rgmatch::parser::parse_gtf_from_reader;
+    use rgmatch::types::{
+        AssignmentMode, DistanceAnchor, GeneBodyDefinition, SpanMultipleGenes, Strand,
+    };
+    use rgmatch::{Config, Region, ReportLevel};
+
+    /// Small deterministic generator (64-bit LCG).
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: i64) -> i64 {
+            self.0 = self
+                .0
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            ((self.0 >> 33) % n as u64) as i64
+        }
+    }
+
+    fn in_range(pctg: f64) -> bool {
+        (0.0..=100.0).contains(&pctg)
+    }
+
+    #[test]
+    fn test_tss_tts_percentages_in_range() {
+        let mut rng = Lcg(0x9c7);
+        for _ in 0..5_000 {
+            let start = 10_000 + rng.below(1_000);
+            let end = start + rng.below(2_000);
+            let strand = if rng.below(2) == 0 {
+                Strand::Positive
+            } else {
+                Strand::Negative
+            };
+            // A region up to 3 kb on the TSS or TTS side of the exon
+            let (region_start, distance) = match strand {
+                Strand::Positive => (start - 1 - rng.below(3_000), 0),
+                Strand::Negative => (end + 1 + rng.below(3_000), 0),
+            };
+            let region_end = region_start + rng.below(4_000);
+            let tss = rng.below(500) as f64;
+            let promoter = rng.below(2_000) as f64;
+            let downstream = rng.below(300) as f64;
+            let inclusive = rng.below(2) == 0;
+            let anchor = match strand {
+                Strand::Positive => start,
+                Strand::Negative => end,
+            };
+            let distance = distance + (anchor - (region_start + region_end) / 2).abs();
+
+            let exon = TssExonInfo {
+                start,
+                end,
+                strand,
+                distance,
+            };
+            for o in check_tss_with_boundary(
+                region_start,
+                region_end,
+                &exon,
+                tss,
+                promoter,
+                downstream,
+                inclusive,
+            ) {
+                assert!(
+                    in_range(o.pctg_region) && o.pctg_area.map_or(true, in_range),
+                    "{:?} for [{}, {}] and exon [{}, {}] {:?}, tss {} promoter {} down {}",
+                    o,
+                    region_start,
+                    region_end,
+                    start,
+                    end,
+                    strand,
+                    tss,
+                    promoter,
+                    downstream
+                );
+            }
+
+            let exon = TtsExonInfo {
+                start,
+                end,
+                // The TTS of a - strand exon is at its start, like the TSS of a +
+                // strand one
+                strand: match strand {
+                    Strand::Positive => Strand::Negative,
+                    Strand::Negative => Strand::Positive,
+                },
+                distance,
+            };
+            for o in check_tts_with_boundary(region_start, region_end, &exon, tss, inclusive) {
+                assert!(
+                    in_range(o.pctg_region) && o.pctg_area.map_or(true, in_range),
+                    "{:?} for [{}, {}] and exon [{}, {}], tts {}",
+                    o,
+                    region_start,
+                    region_end,
+                    start,
+                    end,
+                    tss
+                );
+            }
+        }
+    }
+
+    /// Random coding genes with 1-3 transcripts of 1-5 exons.
+    fn random_gtf(rng: &mut Lcg, genes: usize) -> String {
+        let mut gtf = String::new();
+        for g in 0..genes {
+            let start = 1 + rng.below(500_000);
+            let length = 200 + rng.below(20_000);
+            let strand = if rng.below(2) == 0 { '+' } else { '-' };
+            for t in 0..1 + rng.below(3) {
+                let num_exons = 1 + rng.below(5);
+                let step = length / num_exons;
+                for e in 0..num_exons {
+                    let exon_start = start + e * step + rng.below(step / 4 + 1);
+                    let exon_end = exon_start + 1 + rng.below(step / 2 + 1);
+                    let attributes =
+                        format!("gene_id \"G{}\"; transcript_id \"G{}_T{}\";", g, g, t);
+                    gtf.push_str(&format!(
+                        "chr1\tTEST\texon\t{}\t{}\t.\t{}\t.\t{}\n",
+                        exon_start, exon_end, strand, attributes
+                    ));
+                    if e == 0 && rng.below(2) == 0 {
+                        let cds_start = exon_start + rng.below(exon_end - exon_start + 1);
+                        gtf.push_str(&format!(
+                            "chr1\tTEST\tCDS\t{}\t{}\t.\t{}\t0\t{}\n",
+                            cds_start, exon_end, strand, attributes
+                        ));
+                    }
+                }
+            }
+        }
+        gtf
+    }
+
+    #[test]
+    fn test_candidate_percentages_in_range() {
+        let mut rng = Lcg(0x7ac);
+        let gtf_text = random_gtf(&mut rng, 200);
+        let gtf = parse_gtf_from_reader(gtf_text.as_bytes(), "gene_id", "transcript_id").unwrap();
+        let regions: Vec<Region> = (0..800)
+            .map(|_| {
+                let start = rng.below(520_000);
+                let end = start + rng.below(8_000);
+                Region::new("chr1".to_string(), start, end, vec![])
+            })
+            .collect();
+
+        for round in 0..12 {
+            let config = Config {
+                level: [
+                    ReportLevel::Exon,
+                    ReportLevel::Transcript,
+                    ReportLevel::Gene,
+                ][round % 3],
+                tss: rng.below(600) as f64,
+                tts: rng.below(600) as f64,
+                promoter: rng.below(3_000) as f64,
+                tss_downstream: rng.below(400) as f64,
+                boundary_inclusive: round % 2 == 0,
+                report_utr: round % 4 < 2,
+                gene_body_definition: [
+                    GeneBodyDefinition::ExonOnly,
+                    GeneBodyDefinition::FullSpan,
+                    GeneBodyDefinition::ExonUnion,
+                ][round / 3 % 3],
+                assignment_mode: if round % 5 == 0 {
+                    AssignmentMode::Midpoint
+                } else {
+                    AssignmentMode::Overlap
+                },
+                distance_anchor: if round % 7 < 3 {
+                    DistanceAnchor::Edge
+                } else {
+                    DistanceAnchor::Midpoint
+                },
+                span_multiple_genes: if round % 6 == 5 {
+                    SpanMultipleGenes::ReportAllOverlapped
+                } else {
+                    SpanMultipleGenes::RulesOnly
+                },
+                report_all_candidates: round % 8 == 3,
+                ..Config::default()
+            };
+            let mut matcher = RegionMatcher::new(&gtf, &config);
+            for region in &regions {
+                for c in matcher.match_next(region).candidates {
+                    assert!(
+                        in_range(c.pctg_region) && (c.pctg_area == -1.0 || in_range(c.pctg_area)),
+                        "{:?} for {} with {:?}",
+                        c,
+                        region.id(),
+                        config
+                    );
+                }
+            }
+        }
+    }
+}
+
+mod test_exon_index_differential {
+    use rgmatch::types::{Exon, Strand, Transcript};
+
+    use crate::support::Lcg;
+
+    /// Transcript of 1-400 exons, overlapping ones included.
+    fn random_transcript(rng: &mut Lcg, strand: Strand) -> Transcript {
+        let mut transcript = Transcript::new("T");
+        let mut start = 1 + rng.below(1_000);
+        let max_exons = if rng.below(4) == 0 { 400 } else { 6 };
+        for _ in 0..1 + rng.below(max_exons) {
+            let end = start + rng.below(2_000);
+            transcript.add_exon(Exon::new(start, end));
+            start = (start + rng.below(4_000) - 500).max(start + 1);
+        }
+        transcript.renumber_exons(strand);
+        transcript
+    }
+
+    /// Whether exon `j` gives no candidate for the region `start..=end`
+    /// in the exon loop of the matcher.
+    fn exon_gives_nothing(exons: &[Exon], j: usize, start: i64, end: i64) -> bool {
+        let exon = &exons[j];
+        let is_last = j + 1 == exons.len();
+        if exon.end < start {
+            return !is_last && exons[j + 1].start <= start;
+        }
+        let overlaps = exon.start <= end;
+        !overlaps && j > 0
+    }
+
+    #[test]
+    fn test_exon_index_matches_exons() {
+        let mut rng = Lcg(0xe10);
+        for case in 0..400 {
+            let strand = if case % 2 == 0 {
+                Strand::Positive
+            } else {
+                Strand::Negative
+            };
+            let transcript = random_transcript(&mut rng, strand);
+            let exons = &transcript.exons;
+            let index = transcript.exon_index(strand).unwrap();
+
+            let first_is_one = exons[0].exon_number == Some(1);
+            let last = exons.last().unwrap();
+            for _ in 0..20 {
+                let start = rng.below(last.end + 5_000);
+                let end = start + rng.below(3_000);
+                let pm = start + (end - start) / 2;
+
+                let near = index.exons_near(start, end);
+                for j in (0..exons.len()).filter(|j| !near.contains(j)) {
+                    assert!(
+                        exon_gives_nothing(exons, j, start, end),
+                        "exon {} of {:?} skipped for {}-{}",
+                        j,
+                        exons,
+                        start,
+                        end
+                    );
+                }
+
+                let (tss_distance, tts_distance) = if first_is_one {
+                    (pm - exons[0].start, pm - last.end)
+                } else {
+                    (last.end - pm, exons[0].start - pm)
+                };
+                assert_eq!(index.tss_distance(pm), tss_distance);
+                assert_eq!(index.tts_distance(pm), tts_distance);
+            }
+
+            let first_exon = match strand {
+                Strand::Positive => 0,
+                Strand::Negative => exons.len() - 1,
+            };
+            assert_eq!(index.first_exon, first_exon);
+            assert_eq!(index.exonic_length, transcript.exonic_length());
+            let junctions: Vec<i64> = exons
+                .windows(2)
+                .flat_map(|pair| [pair[0].end, pair[1].start - 1])
+                .collect();
+            assert_eq!(index.junctions(), junctions);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+//! Headless tests of the JavaScript entry point.
+//!
+//! Run with `wasm-pack test --node -- --features wasm`.
+
+#![cfg(all(target_arch = "wasm32", feature = "wasm"))]
+
+use rgmatch::wasm::annotate_strings;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+const GTF: &str = "\
+chr1\tTEST\tgene\t10000\t12000\t.\t+\t.\tgene_id \"G1\";
+chr1\tTEST\ttranscript\t10000\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t10000\t10500\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+chr1\tTEST\texon\t11500\t12000\t.\t+\t.\tgene_id \"G1\"; transcript_id \"T1\";
+";
+
+const BED: &str = "chr1\t9900\t9950\tr1\nchr1\t62000\t62100\tfar\nchr9\t100\t200\tmissing\n";
+
+#[wasm_bindgen_test]
+fn annotate_strings_returns_one_line_per_region() {
+    let jsonl = annotate_strings(GTF, BED, r#"{"level": "gene"}"#).unwrap();
+    let lines: Vec<&str> = jsonl.lines().collect();
+
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].contains(r#""gene":"G1""#));
+    assert!(lines[1].contains(r#""candidates":[]"#));
+    assert!(lines[2].contains(r#""chrom":"chr9""#));
+}
+
+#[wasm_bindgen_test]
+fn annotate_strings_rejects_unknown_config_keys() {
+    assert!(annotate_strings(GTF, BED, r#"{"bogus": 1}"#).is_err());
+}